@@ -0,0 +1,104 @@
+#[macro_use]
+extern crate criterion;
+extern crate crowbook;
+extern crate mustache;
+
+use crowbook::{Book, Number, Parser, InfoLevel};
+use criterion::Criterion;
+use std::io;
+
+/// Returns the Markdown source of a chapter, with most usual elements
+fn chapter(n: usize) -> String {
+    let mut content = format!("# Chapter {} #\n\n", n);
+    for i in 0..20 {
+        content.push_str(&format!("This is paragraph {}, with *emphasis*, **strong emphasis**, \
+                                   a [link](https://example.com) and \"quotes\"... It's \
+                                   supposed -- more or less -- to look like text.[^{}]\n\n\
+                                   [^{}]: And a footnote.\n\n",
+                                  i,
+                                  i,
+                                  i));
+    }
+    content.push_str("## A section ##\n\n> A quote\n\n* A list\n* of items\n\n```\nsome code\n```\n");
+    content
+}
+
+/// Returns a book containing `n` chapters
+fn book(n: usize) -> Book {
+    let mut book = Book::new();
+    book.set_verbosity(InfoLevel::Error)
+        .set_options(&[("author", "Crowbook"), ("title", "Benchmark")]);
+    for i in 0..n {
+        book.add_chapter_from_source(Number::Default, chapter(i).as_bytes()).unwrap();
+    }
+    book
+}
+
+fn parse(c: &mut Criterion) {
+    let content = chapter(1);
+    c.bench_function("parse", move |b| b.iter(|| Parser::new().parse(&content).unwrap()));
+}
+
+fn clean(c: &mut Criterion) {
+    let book = book(0);
+    let content = chapter(1);
+    c.bench_function("clean", move |b| b.iter(|| book.clean(content.as_str(), false).len()));
+}
+
+fn header(c: &mut Criterion) {
+    let compiled = book(0);
+    let uncompiled = book(0);
+    c.bench_function("chapter header (compiled template)",
+                     move |b| b.iter(|| {
+                         compiled.get_chapter_header(1, String::from("Title"), |s| {
+                                 Ok(s.to_owned())
+                             })
+                             .unwrap()
+                     }));
+    // What rendering a chapter header used to cost: compiling the template and
+    // rendering all the metadata each time
+    c.bench_function("chapter header (template compiled per call)",
+                     move |b| b.iter(|| {
+                         let template = uncompiled.options
+                             .get_str("rendering.chapter.template")
+                             .unwrap();
+                         let template = mustache::compile_str(template).unwrap();
+                         let data = uncompiled.get_metadata(|s| Ok(s.to_owned()))
+                             .unwrap()
+                             .insert_str("chapter_title", "Title")
+                             .insert_str("number", "1")
+                             .build();
+                         let mut res: Vec<u8> = vec![];
+                         template.render_data(&mut res, &data).unwrap();
+                         res
+                     }));
+}
+
+fn load(c: &mut Criterion) {
+    c.bench_function("load (100 chapters)", |b| b.iter(|| book(100)));
+}
+
+fn render(c: &mut Criterion) {
+    let book = book(100);
+    c.bench_function_over_inputs("render (100 chapters)",
+                                 move |b, format: &&str| {
+                                     b.iter(|| {
+                                         book.render_format_to(format, &mut io::sink()).unwrap()
+                                     })
+                                 },
+                                 vec!["html", "tex", "html.if"]);
+}
+
+fn scalability(c: &mut Criterion) {
+    let book = book(5000);
+    c.bench_function("render html (5000 chapters)",
+                     move |b| b.iter(|| book.render_format_to("html", &mut io::sink()).unwrap()));
+}
+
+criterion_group!(benches, parse, clean, header, load, render);
+criterion_group!{
+    name = large;
+    config = Criterion::default().sample_size(2);
+    targets = scalability
+}
+criterion_main!(benches, large);
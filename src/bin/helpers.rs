@@ -1,9 +1,10 @@
-use crowbook::Book;
+use crowbook::{Book, Split};
 use clap::{App, Arg, Format, ArgMatches, AppSettings};
 
 use std::io::{self, Write};
 use std::process::exit;
 use std::fs;
+use std::path::Path;
 use std::env;
 
 
@@ -49,6 +50,18 @@ pub fn get_book_options<'a>(matches: &'a ArgMatches) -> Vec<(&'a str, &'a str)>
     if matches.is_present("proofread") {
         output.push(("proofread", "true"));
     }
+
+    if matches.is_present("strict") {
+        output.push(("strict", "true"));
+    }
+
+    if let Some(n) = matches.value_of("sample") {
+        output.push(("sample", n));
+    }
+
+    if let Some(text) = matches.value_of("watermark") {
+        output.push(("watermark", text));
+    }
     output
 }
 
@@ -68,9 +81,91 @@ pub fn set_book_options(book: &mut Book, matches: &ArgMatches) -> String {
         }
         output.push_str(&format!("{}: {}\n", key, value));
     }
+    for warning in book.options.take_warnings() {
+        book.logger.warning(warning);
+    }
     output
 }
 
+/// Returns the options written at the beginning of a new book configuration file
+fn default_header() -> String {
+    lformat!("author: Your name
+title: Your title
+lang: en
+
+## Output formats
+
+# Uncomment and fill to generate files
+# output.html: some_file.html
+# output.epub: some_file.epub
+# output.pdf: some_file.pdf
+
+# Or uncomment the following to generate PDF, HTML and EPUB files based on this file's name
+# output: [pdf, epub, html]
+
+# Uncomment and fill to set cover image (for EPUB)
+# cover: some_cover.png\n")
+}
+
+/// Splits a Markdown file into chapter files, and creates a book file
+/// listing them, then exits the process
+pub fn split_book(matches: &ArgMatches) -> ! {
+    let file = match matches.value_of("split") {
+        Some(file) => file,
+        None => print_error(&lformat!("--split needs the Markdown file to split")),
+    };
+    let mut content = String::new();
+    if let Err(err) = fs::File::open(file).and_then(|mut f| io::Read::read_to_string(&mut f, &mut content)) {
+        print_error(&lformat!("Could not read {file}: {error}", file = file, error = err));
+    }
+    let split = Split::new(&content);
+    if split.chapters.is_empty() {
+        print_error(&lformat!("{file} is empty", file = file));
+    }
+    let dir = Path::new(file).parent().unwrap_or(Path::new(""));
+    let paths: Vec<_> = split.chapters.iter().map(|c| dir.join(&c.file)).collect();
+    for path in &paths {
+        if fs::metadata(path).is_ok() {
+            print_error(&lformat!("Could not create file {}: it already exists!", path.display()));
+        }
+    }
+    let mut f: Box<Write> = if let Some(book) = matches.value_of("BOOK") {
+        if fs::metadata(book).is_ok() {
+            print_error(&lformat!("Could not create file {}: it already exists!", book));
+        }
+        Box::new(fs::File::create(book).unwrap())
+    } else {
+        Box::new(io::stdout())
+    };
+
+    for (chapter, path) in split.chapters.iter().zip(&paths) {
+        if let Err(err) = fs::File::create(path).and_then(|mut f| f.write_all(chapter.content.as_bytes())) {
+            print_error(&lformat!("Could not write {file}: {error}",
+                                  file = path.display(),
+                                  error = err));
+        }
+    }
+    match split.yaml {
+        Some(ref yaml) => f.write_all(format!("{}\n", yaml).as_bytes()).unwrap(),
+        None => f.write_all(default_header().as_bytes()).unwrap(),
+    }
+    f.write_all(lformat!("\n## List of chapters\n").as_bytes()).unwrap();
+    for (chapter, path) in split.chapters.iter().zip(&paths) {
+        f.write_all(format!("{} {}\n",
+                            if chapter.numbered { '+' } else { '-' },
+                            path.display())
+                .as_bytes())
+            .unwrap();
+    }
+    if let Some(s) = matches.value_of("BOOK") {
+        println!("{}",
+                 lformat!("Created {book} and {n} chapter files, now you'll have to complete it!",
+                          book = s,
+                          n = paths.len()));
+    }
+    exit(0);
+}
+
 /// create a book file with the command line arguments
 /// and exit the process at the end
 pub fn create_book(matches: &ArgMatches) -> ! {
@@ -89,23 +184,7 @@ pub fn create_book(matches: &ArgMatches) -> ! {
             let s = set_book_options(&mut book, matches);
             f.write_all(s.as_bytes()).unwrap();
         } else {
-            f.write_all(lformat!("author: Your name
-title: Your title
-lang: en
-
-## Output formats
-
-# Uncomment and fill to generate files
-# output.html: some_file.html
-# output.epub: some_file.epub
-# output.pdf: some_file.pdf
-
-# Or uncomment the following to generate PDF, HTML and EPUB files based on this file's name
-# output: [pdf, epub, html]
-
-# Uncomment and fill to set cover image (for EPUB)
-# cover: some_cover.png\n").as_bytes())
-                .unwrap();
+            f.write_all(default_header().as_bytes()).unwrap();
         }
         f.write_all(lformat!("\n## List of chapters\n").as_bytes()).unwrap();
         for file in values {
@@ -130,7 +209,9 @@ pub fn create_matches<'a>() -> (ArgMatches<'a>, String, String) {
         static ref VERBOSE: String = lformat!("Print warnings in parsing/rendering");
         static ref QUIET: String = lformat!("Don't print info/error messages");
         static ref PROOFREAD: String = lformat!("Enable proofreading");
+        static ref STRICT: String = lformat!("Fail on warnings, broken links, unknown options, ... (sets the strict option)");
         static ref CREATE: String = lformat!("Create a new book with existing Markdown files");
+        static ref NEW: String = lformat!("Interactively create a new book, with its configuration file and stub chapters");
         static ref OUTPUT: String = lformat!("Specify output file");
         static ref LANG: String = lformat!("Set the runtime language used by Crowbook");
         static ref TO: String = lformat!("Generate specific format");
@@ -138,8 +219,19 @@ pub fn create_matches<'a>() -> (ArgMatches<'a>, String, String) {
         static ref LIST_OPTIONS: String = lformat!("List all possible options");
         static ref LIST_OPTIONS_MD: String = lformat!("List all possible options, formatted in Markdown");
         static ref PRINT_TEMPLATE: String = lformat!("Prints the default content of a template");
-        static ref BOOK: String = lformat!("File containing the book configuration file, or a Markdown file when called with --single");
+        static ref BOOK: String = lformat!("File containing the book configuration file, or a Markdown file when called with --single. If several files are given, the books are rendered in parallel");
         static ref STATS: String = lformat!("Print some project statistics");
+        static ref TIMELINE: String = lformat!("Print a table of the chapters with their point of view character, in-story date and location, sorted by SORT: order (the default), date, pov or location");
+        static ref NBSP: String = lformat!("Print the places where French typographic rules require a non-breaking space but the source has a regular one, or the opposite");
+        static ref NAMES: String = lformat!("Print the names of characters and places that are inconsistent with the ones set by names.* options");
+        static ref PUBLISH: String = lformat!("Render the book, then publish its output files to the targets set by publish.* options");
+        static ref SAMPLE: String = lformat!("Only render a sample of the book: its first N chapters, or N% of its words (sets the sample option)");
+        static ref WATERMARK: String = lformat!("Stamp TEXT on each chapter of EPUB files and each page of PDF files, e.g. for review copies (sets the watermark option)");
+        static ref REORDER: String = lformat!("Reorder the chapters of the book, given their current positions or files (e.g. 2,1,3), renumbering chapter files and updating the links between them. Asks for the new order if ORDER is not set");
+        static ref SPLIT: String = lformat!("Create a new book by splitting a Markdown FILE into chapter files at its level-1 headings");
+        static ref DUMP_AST: String = lformat!("Print the parsed book (its metadata and the tokens of its chapters) as JSON, to process it with external scripts");
+        static ref OPDS: String = lformat!("Print an OPDS catalog of the book's output files");
+        static ref RETAIL: String = lformat!("Print the book's metadata for retailers, as an ONIX 3.0 record (onix, default) or a CSV file with the fields of Kindle Direct Publishing (kdp)");
         static ref TEMPLATE: String = lformat!("\
 {{bin}} {{version}} by {{author}}
 {{about}}
@@ -173,7 +265,16 @@ ARGS:
         .arg(Arg::from_usage("-h, --help").help(HELP.as_str()))
         .arg(Arg::from_usage("-V, --version").help(VERSION.as_str()))
         .arg(Arg::from_usage("-p, --proofread").help(PROOFREAD.as_str()))
+        .arg(Arg::from_usage("--strict").help(STRICT.as_str()))
+        .arg(Arg::from_usage("--sample [N]").help(SAMPLE.as_str()))
+        .arg(Arg::from_usage("--watermark [TEXT]").help(WATERMARK.as_str()))
         .arg(Arg::from_usage("-c, --create [FILES]...").help(CREATE.as_str()))
+        .arg(Arg::from_usage("--split [FILE]")
+            .help(SPLIT.as_str())
+            .conflicts_with("create"))
+        .arg(Arg::from_usage("--new")
+            .help(NEW.as_str())
+            .conflicts_with("create"))
         .arg(Arg::from_usage("-o, --output [FILE]")
             .help(OUTPUT.as_str())
             .requires("to"))
@@ -181,9 +282,16 @@ ARGS:
             .help(TO.as_str())
             .possible_values(&["epub",
                                "pdf",
+                               "pdf.cover",
                                "html",
                                "tex",
                                "odt",
+                               "mobi",
+                               "docbook",
+                               "txt",
+                               "md",
+                               "ast",
+                               "slides",
                                "html.dir",
                                "proofread.html",
                                "proofread.html.dir",
@@ -200,8 +308,19 @@ ARGS:
              .help(LANG.as_str()))
         .arg(Arg::from_usage("--print-template [TEMPLATE]").help(PRINT_TEMPLATE.as_str()))
         .arg(Arg::from_usage("--stats -S").help(STATS.as_str()))
+        .arg(Arg::from_usage("--names").help(NAMES.as_str()))
+        .arg(Arg::from_usage("--timeline [SORT]").help(TIMELINE.as_str()))
+        .arg(Arg::from_usage("--reorder [ORDER]").help(REORDER.as_str()))
+        .arg(Arg::from_usage("--nbsp").help(NBSP.as_str()))
+        .arg(Arg::from_usage("--dump-ast").help(DUMP_AST.as_str()))
+        .arg(Arg::from_usage("--opds").help(OPDS.as_str()))
+        .arg(Arg::from_usage("--retail-metadata [FORMAT]").help(RETAIL.as_str()))
+        .arg(Arg::from_usage("--publish")
+            .help(PUBLISH.as_str())
+            .conflicts_with("to"))
         .arg(Arg::with_name("BOOK")
             .index(1)
+            .multiple(true)
             .help(BOOK.as_str()))
         .template(TEMPLATE.as_str());
 
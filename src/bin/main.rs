@@ -11,6 +11,8 @@ mod real_main;
 #[cfg(feature = "clap")]
 mod helpers;
 #[cfg(feature = "clap")]
+mod wizard;
+#[cfg(feature = "clap")]
 
 
 
@@ -19,9 +19,14 @@ extern crate clap;
 
 use helpers::*;
 
-use crowbook::{Result, Book, BookOptions, InfoLevel};
+use crowbook::{Result, Book, BookOptions, InfoLevel, RenderResult, RenderOutput, Error, Source};
 use crowbook_intl_runtime::set_lang;
 use crowbook::Stats;
+use crowbook::NameReport;
+use crowbook::Timeline;
+use crowbook::Reorder;
+use crowbook::NbspReport;
+use crowbook;
 use clap::ArgMatches;
 use std::process::exit;
 use std::io;
@@ -29,7 +34,7 @@ use std::env;
 
 
 /// Render a book to specific format
-fn render_format(book: &mut Book, matches: &ArgMatches, format: &str) -> ! {
+fn render_format(book: &mut Book, matches: &ArgMatches, format: &str) -> Result<()> {
     let mut key = String::from("output.");
     key.push_str(format);
 
@@ -55,12 +60,100 @@ fn render_format(book: &mut Book, matches: &ArgMatches, format: &str) -> ! {
         => book.render_format_to(format, &mut io::stdout()),
     };
     
-    match result {
-        Err(err) => print_error(&format!("{}", err)),
-        Ok(_) => {
-            exit(0);
+    let result = result?;
+    log_result(book, &result);
+    Ok(())
+}
+
+/// Logs the successful rendering of a format
+fn log_result(book: &Book, result: &RenderResult) {
+    let description = book.format_description(&result.format).unwrap_or(&result.format);
+    match result.output {
+        RenderOutput::File(ref path) => {
+            book.logger.info(lformat!("Succesfully generated {format}: {path}",
+                                      format = description,
+                                      path = path.display()))
+        }
+        RenderOutput::Bytes(_) => {
+            book.logger.info(lformat!("Succesfully generated {format}", format = description))
+        }
+    }
+    if !result.degraded.is_empty() {
+        book.logger.info(lformat!("{format} can't express some features of the book: {features}",
+                                  format = description,
+                                  features = result.degraded.join(", ")));
+    }
+}
+
+/// Returns the verbosity set by command line arguments
+fn get_verbosity(matches: &ArgMatches) -> InfoLevel {
+    if matches.is_present("verbose") {
+        InfoLevel::Debug
+    } else if matches.is_present("quiet") {
+        InfoLevel::Quiet
+    } else {
+        InfoLevel::Warning
+    }
+}
+
+/// Renders several books in parallel, and reports all the failures at the end
+fn render_books(matches: &ArgMatches, files: &[&str]) -> Result<()> {
+    for arg in &["single", "stats", "names", "timeline", "reorder", "nbsp", "dump-ast",
+                 "opds", "retail-metadata", "output", "publish"] {
+        if matches.is_present(arg) {
+            return Err(Error::default(Source::empty(),
+                                      lformat!("--{arg} can't be used with several books",
+                                               arg = arg)));
         }
     }
+    let verbosity = get_verbosity(matches);
+    let failures = crowbook::render_books(files,
+                                          |file| load_book(matches, verbosity, file),
+                                          |book| render_book(matches, book));
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::default(Source::empty(),
+                           lformat!("{n} book(s) or format(s) could not be rendered:\n{failures}",
+                                    n = failures.len(),
+                                    failures = failures.join("\n"))))
+    }
+}
+
+/// Loads a book of a batch
+fn load_book(matches: &ArgMatches, verbosity: InfoLevel, file: &str) -> Result<Book> {
+    let mut book = Book::new();
+    book.set_verbosity(verbosity)
+        .set_options(&get_book_options(matches));
+    book.load_file(file)?;
+    set_book_options(&mut book, matches);
+    Ok(book)
+}
+
+/// Renders a book of a batch to all its formats (or the one set by `--to`)
+fn render_book(matches: &ArgMatches, book: &Book) -> Vec<(String, Result<RenderResult>)> {
+    let results = match matches.value_of("to") {
+        Some(format) => {
+            match book.render_format(format) {
+                Ok(Some(result)) => vec![(format.to_string(), Ok(result))],
+                Ok(None) => {
+                    book.logger.warning(lformat!("{file}: output.{format} is not set, \
+                                                  nothing to render",
+                                                 file = book.source,
+                                                 format = format));
+                    vec![]
+                }
+                Err(err) => vec![(format.to_string(), Err(err))],
+            }
+        }
+        None => book.render_all(),
+    };
+    for &(_, ref result) in &results {
+        if let Ok(ref result) = *result {
+            log_result(book, result);
+        }
+    }
+    results
 }
 
 pub fn try_main() -> Result<()> {
@@ -121,6 +214,14 @@ pub fn try_main() -> Result<()> {
         create_book(&matches);
     }
 
+    if matches.is_present("split") {
+        split_book(&matches);
+    }
+
+    if matches.is_present("new") {
+        wizard::new_book(&matches);
+    }
+
     if !matches.is_present("BOOK") {
         print_error(&lformat!("You must pass the file of a book configuration \
                                file.\n\n{}\n\nFor more information try --help.",
@@ -130,14 +231,12 @@ pub fn try_main() -> Result<()> {
 
 
     // ok to unwrap since clap checks it's there
-    let s = matches.value_of("BOOK").unwrap();
-    let verbosity = if matches.is_present("verbose") {
-        InfoLevel::Debug
-    } else if matches.is_present("quiet") {
-        InfoLevel::Quiet
-    } else {
-        InfoLevel::Warning
-    };
+    let files: Vec<&str> = matches.values_of("BOOK").unwrap().collect();
+    if files.len() > 1 {
+        return render_books(&matches, &files);
+    }
+    let s = files[0];
+    let verbosity = get_verbosity(&matches);
 
     let mut book = Book::new();
     book.set_verbosity(verbosity)
@@ -163,10 +262,76 @@ pub fn try_main() -> Result<()> {
         exit(0);
     }
 
+    if matches.is_present("names") {
+        let report = NameReport::new(&book);
+        print!("{}", report);
+        exit(if report.is_empty() { 0 } else { 1 });
+    }
+
+    if matches.is_present("timeline") {
+        let mut timeline = Timeline::new(&book);
+        timeline.sort_by(matches.value_of("timeline").unwrap_or("order"))?;
+        print!("{}", timeline);
+        exit(0);
+    }
+
+    if matches.is_present("reorder") {
+        if matches.is_present("single") || s == "-" {
+            print_error(&lformat!("--reorder needs a book configuration file"));
+        }
+        let mut reorder = Reorder::new(s)?;
+        let order = match matches.value_of("reorder") {
+            Some(order) => reorder.parse_order(order)?,
+            None => wizard::ask_order(&reorder),
+        };
+        for (old, new) in reorder.apply(&order)? {
+            println!("{} -> {}", old, new);
+        }
+        exit(0);
+    }
+
+    if matches.is_present("nbsp") {
+        let report = NbspReport::new(&book);
+        print!("{}", report);
+        exit(if report.is_empty() { 0 } else { 1 });
+    }
+
+    if matches.is_present("dump-ast") {
+        book.render_format_to("ast", &mut io::stdout())?;
+        exit(0);
+    }
+
+    if matches.is_present("opds") {
+        println!("{}", book.opds_catalog()?);
+        exit(0);
+    }
+
+    if matches.is_present("retail-metadata") {
+        print!("{}", book.retail_metadata(matches.value_of("retail-metadata").unwrap_or("onix"))?);
+        exit(0);
+    }
+
     if let Some(format) = matches.value_of("to") {
-        render_format(&mut book, &matches, format);
+        render_format(&mut book, &matches, format)?;
     } else {
-        book.render_all();
+        // Render all formats, and report all the failures at the end
+        let mut failures = vec![];
+        for (format, result) in book.render_all() {
+            match result {
+                Ok(result) => log_result(&book, &result),
+                Err(err) => failures.push(format!("* {}: {}", format, err)),
+            }
+        }
+        if !failures.is_empty() {
+            return Err(Error::default(Source::empty(),
+                                      lformat!("{n} format(s) could not be rendered:\n{failures}",
+                                               n = failures.len(),
+                                               failures = failures.join("\n"))));
+        }
+    }
+
+    if matches.is_present("publish") {
+        book.publish()?;
     }
 
     Ok(())
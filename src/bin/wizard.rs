@@ -0,0 +1,192 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Interactive creation of a new book (`crowbook --new`) and reordering of
+//! its chapters (`crowbook --reorder`)
+
+use helpers::print_error;
+
+use crowbook::Reorder;
+use clap::ArgMatches;
+
+use std::io::{self, BufRead, Write};
+use std::process::exit;
+use std::path::Path;
+use std::fs;
+
+/// Formats that can be chosen when creating a new book
+const FORMATS: &'static [&'static str] = &["epub", "pdf", "html", "html.dir", "odt"];
+
+/// Returns `s` as a YAML double-quoted scalar
+fn yaml_str(s: &str) -> String {
+    let mut res = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '\\' => res.push_str("\\\\"),
+            '"' => res.push_str("\\\""),
+            c if c.is_control() => res.push_str(&format!("\\u{:04X}", c as u32)),
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
+
+/// Asks a question on stdout and returns the answer, or `default` if it is empty
+fn ask(question: &str, default: &str) -> String {
+    if default.is_empty() {
+        print!("{}: ", question);
+    } else {
+        print!("{} [{}]: ", question, default);
+    }
+    io::stdout().flush().unwrap();
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer).unwrap_or(0) == 0 {
+        // End of input: use default values for all remaining questions
+        println!("");
+    }
+    let answer = answer.trim();
+    if answer.is_empty() {
+        default.to_owned()
+    } else {
+        answer.to_owned()
+    }
+}
+
+/// Asks a question until the answer is valid according to `check`, which
+/// returns an error message otherwise
+fn ask_valid<F>(question: &str, default: &str, check: F) -> String
+    where F: Fn(&str) -> Result<(), String>
+{
+    loop {
+        let answer = ask(question, default);
+        match check(&answer) {
+            Ok(()) => return answer,
+            Err(msg) => println!("{}", msg),
+        }
+    }
+}
+
+/// Displays the chapters of a book and asks for their new order
+pub fn ask_order(reorder: &Reorder) -> Vec<usize> {
+    let files = reorder.files();
+    for (i, file) in files.iter().enumerate() {
+        println!("{:>3}. {}", i + 1, file);
+    }
+    let current: Vec<String> = (1..files.len() + 1).map(|i| i.to_string()).collect();
+    let answer = ask_valid(&lformat!("New order of the chapters"),
+                           &current.join(","),
+                           |s| reorder.parse_order(s).map(|_| ()).map_err(|e| format!("{}", e)));
+    reorder.parse_order(&answer).unwrap()
+}
+
+/// Returns the file name of chapter `n`, according to `scheme`
+fn chapter_file(scheme: &str, n: usize) -> String {
+    scheme.replace("{n}", &format!("{:02}", n))
+}
+
+/// Interactively asks for the title, author, language, output formats and
+/// chapters of a new book, then writes its configuration file and stub
+/// chapters
+pub fn new_book(matches: &ArgMatches) -> ! {
+    let book = match matches.value_of("BOOK") {
+        Some(book) => book,
+        None => print_error(&lformat!("You must pass the name of the book configuration file to create")),
+    };
+    if fs::metadata(book).is_ok() {
+        print_error(&lformat!("Could not create file {}: it already exists!", book));
+    }
+    let dir = Path::new(book).parent().unwrap_or_else(|| Path::new(""));
+
+    let title = ask(&lformat!("Title of the book"), "");
+    let author = ask(&lformat!("Author"), "");
+    let lang = ask_valid(&lformat!("Language (e.g. en, fr, es)"), "en", |lang| {
+        if lang.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+            Ok(())
+        } else {
+            Err(lformat!("'{}' is not a valid language code", lang))
+        }
+    });
+    let formats = ask_valid(&lformat!("Output formats ({}), separated by commas",
+                                      FORMATS.join(", ")),
+                            "epub, pdf, html",
+                            |answer| {
+        for format in answer.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+            if !FORMATS.contains(&format) {
+                return Err(lformat!("'{}' is not a valid output format", format));
+            }
+        }
+        Ok(())
+    });
+    let formats: Vec<_> = formats.split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .collect();
+    let n = ask_valid(&lformat!("Number of chapters"), "3", |n| {
+        n.parse::<usize>()
+            .map(|_| ())
+            .map_err(|_| lformat!("'{}' is not a valid number", n))
+    });
+    let n = n.parse::<usize>().unwrap();
+    let scheme = ask_valid(&lformat!("Chapter file names ({{n}} is replaced by the chapter number)"),
+                           "chapter_{n}.md",
+                           |scheme| {
+        if scheme.contains("{n}") {
+            Ok(())
+        } else {
+            Err(lformat!("file names must contain {{n}}"))
+        }
+    });
+
+    // Write the stub chapters, unless they already exist
+    for i in 1..n + 1 {
+        let file = chapter_file(&scheme, i);
+        let path = dir.join(&file);
+        if fs::metadata(&path).is_ok() {
+            println!("{}", lformat!("{} already exists, keeping it", file));
+            continue;
+        }
+        let content = format!("# {} #\n\n{}\n",
+                              lformat!("Chapter {}", i),
+                              lformat!("Your text here."));
+        if let Err(err) = fs::File::create(&path).and_then(|mut f| f.write_all(content.as_bytes())) {
+            print_error(&lformat!("Could not create file {}: {}", path.display(), err));
+        }
+    }
+
+    // Write the configuration file
+    let mut config = String::new();
+    config.push_str(&format!("author: {}\ntitle: {}\nlang: {}\n",
+                             yaml_str(&author),
+                             yaml_str(&title),
+                             yaml_str(&lang)));
+    config.push_str(&lformat!("\n## Output formats\n"));
+    config.push_str(&format!("output: [{}]\n", formats.join(", ")));
+    config.push_str(&lformat!("\n## List of chapters\n"));
+    for i in 1..n + 1 {
+        config.push_str(&format!("+ {}\n", chapter_file(&scheme, i)));
+    }
+    if let Err(err) = fs::File::create(book).and_then(|mut f| f.write_all(config.as_bytes())) {
+        print_error(&lformat!("Could not create file {}: {}", book, err));
+    }
+    println!("{}",
+             lformat!("Created {} and {} chapter(s), you can now render it with `crowbook {}`",
+                      book,
+                      n,
+                      book));
+    exit(0);
+}
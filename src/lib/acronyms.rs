@@ -0,0 +1,128 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Handling of the acronyms defined in the book configuration with
+//! `acronyms.*` keys.
+
+use book::Book;
+use token::Token;
+
+use std::collections::HashSet;
+
+/// Marks the acronyms used in a chapter
+///
+/// If `rendering.acronyms.expand` is set, the first use of each acronym in
+/// the chapter is expanded, e.g. "HTTP (Hypertext Transfer Protocol)". Other
+/// uses become `Abbreviation`s. Acronyms in code and headers are left untouched.
+pub fn mark_acronyms(book: &Book, tokens: &mut Vec<Token>) {
+    let acronyms = book.options.get_acronyms();
+    if acronyms.is_empty() {
+        return;
+    }
+    let mut marker = Marker {
+        acronyms: acronyms,
+        expand: book.options.get_bool("rendering.acronyms.expand").unwrap(),
+        seen: HashSet::new(),
+    };
+    marker.visit(tokens);
+}
+
+struct Marker<'a> {
+    acronyms: Vec<(&'a str, &'a str)>,
+    expand: bool,
+    /// Acronyms that were already expanded in this chapter
+    seen: HashSet<&'a str>,
+}
+
+impl<'a> Marker<'a> {
+    fn visit(&mut self, tokens: &mut Vec<Token>) {
+        let mut i = 0;
+        while i < tokens.len() {
+            let replacement = if let Token::Str(ref text) = tokens[i] {
+                self.split(text)
+            } else {
+                None
+            };
+            if let Some(replacement) = replacement {
+                tokens.remove(i);
+                for token in replacement {
+                    tokens.insert(i, token);
+                    i += 1;
+                }
+                continue;
+            }
+
+            match tokens[i] {
+                Token::Code(..) | Token::CodeBlock(..) | Token::Header(..)
+                    | Token::Abbreviation(..) => {}
+                ref mut token => {
+                    if let Some(inner) = token.inner_mut() {
+                        self.visit(inner);
+                    }
+                }
+            }
+            i += 1;
+        }
+    }
+
+    /// Splits a string around the acronyms it contains, or returns `None`
+    /// if it doesn't contain any
+    fn split(&mut self, text: &str) -> Option<Vec<Token>> {
+        let mut result = vec![];
+        let mut start = 0;
+        let mut pos = 0;
+        let mut previous_alnum = false;
+        while pos < text.len() {
+            if !previous_alnum {
+                if let Some(&(acronym, expansion)) = self.acronyms
+                    .iter()
+                    .find(|&&(acronym, _)| is_word_at(text, pos, acronym)) {
+                    if start < pos {
+                        result.push(Token::Str(text[start..pos].to_owned()));
+                    }
+                    if self.expand && self.seen.insert(acronym) {
+                        result.push(Token::Str(format!("{} ({})", acronym, expansion)));
+                    } else {
+                        result.push(Token::Abbreviation(expansion.to_owned(),
+                                                        vec![Token::Str(acronym.to_owned())]));
+                    }
+                    pos += acronym.len();
+                    start = pos;
+                    previous_alnum = true;
+                    continue;
+                }
+            }
+            let c = text[pos..].chars().next().unwrap();
+            previous_alnum = c.is_alphanumeric();
+            pos += c.len_utf8();
+        }
+        if result.is_empty() {
+            return None;
+        }
+        if start < text.len() {
+            result.push(Token::Str(text[start..].to_owned()));
+        }
+        Some(result)
+    }
+}
+
+/// Returns true if `word` is at position `pos` of `text` and is not
+/// followed by an alphanumeric character
+fn is_word_at(text: &str, pos: usize, word: &str) -> bool {
+    text[pos..].starts_with(word) &&
+        text[pos + word.len()..].chars().next().map_or(true, |c| !c.is_alphanumeric())
+}
@@ -0,0 +1,226 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Dump of the parsed book (its metadata and the tokens of its chapters) as
+//! JSON, to process it with external scripts.
+
+use book::Book;
+use chapter::Chapter;
+use number::Number;
+use token::{Token, Data};
+use error::{Error, Result, Source};
+use book_renderer::BookRenderer;
+
+use rustc_serialize::json::{Json, Object};
+
+use std::io::Write;
+
+/// Metadata options included in the dump, if they are set
+const METADATA: &'static [&'static str] = &["title", "subtitle", "author", "lang", "date",
+                                           "version", "license", "description", "subject"];
+
+/// Returns the parsed book as JSON
+///
+/// The result is an object with a `metadata` object and a `chapters` array.
+/// Each chapter has a `number` (e.g. `{"kind": "specified", "part": false,
+/// "value": 3}`) and a `content` array of tokens, which are objects with a
+/// `type` (e.g. `paragraph`), their attributes (e.g. `level` for headers) and,
+/// for tokens that contain other tokens, a `content` array.
+pub fn to_json(book: &Book) -> Json {
+    let mut metadata = Object::new();
+    for key in METADATA {
+        if let Ok(value) = book.options.get_str(key) {
+            metadata.insert((*key).to_owned(), Json::String(value.to_owned()));
+        }
+    }
+    let mut res = Object::new();
+    res.insert(String::from("metadata"), Json::Object(metadata));
+    res.insert(String::from("chapters"),
+               Json::Array(book.chapters.iter().map(chapter_json).collect()));
+    Json::Object(res)
+}
+
+fn chapter_json(chapter: &Chapter) -> Json {
+    let mut res = Object::new();
+    res.insert(String::from("number"), number_json(chapter.number));
+    res.insert(String::from("file"), Json::String(chapter.filename.clone()));
+    res.insert(String::from("front_matter"), Json::Boolean(chapter.front_matter));
+    res.insert(String::from("toc"), Json::Boolean(chapter.toc));
+    res.insert(String::from("hide_title"), Json::Boolean(chapter.hide_title));
+    for &(key, value) in &[("pov", &chapter.pov),
+                           ("story_date", &chapter.story_date),
+                           ("location", &chapter.location),
+                           ("published", &chapter.published)] {
+        if let Some(ref value) = *value {
+            res.insert(key.to_owned(), Json::String(value.clone()));
+        }
+    }
+    res.insert(String::from("content"), tokens_json(&chapter.content));
+    Json::Object(res)
+}
+
+fn number_json(number: Number) -> Json {
+    let (kind, value) = match number {
+        Number::Hidden => ("hidden", None),
+        Number::Unnumbered | Number::UnnumberedPart => ("unnumbered", None),
+        Number::Default | Number::DefaultPart => ("default", None),
+        Number::Specified(n) | Number::SpecifiedPart(n) => ("specified", Some(n)),
+    };
+    let mut res = Object::new();
+    res.insert(String::from("kind"), Json::String(kind.to_owned()));
+    res.insert(String::from("part"), Json::Boolean(number.is_part()));
+    if let Some(n) = value {
+        res.insert(String::from("value"), Json::I64(n as i64));
+    }
+    Json::Object(res)
+}
+
+fn tokens_json(tokens: &[Token]) -> Json {
+    Json::Array(tokens.iter().map(token_json).collect())
+}
+
+fn token_json(token: &Token) -> Json {
+    let mut res = Object::new();
+    let string = |s: &str| Json::String(s.to_owned());
+    let kind = match *token {
+        Token::Str(ref text) => {
+            res.insert(String::from("text"), string(text));
+            "str"
+        }
+        Token::Paragraph(_) => "paragraph",
+        Token::Header(level, _) => {
+            res.insert(String::from("level"), Json::I64(level as i64));
+            "header"
+        }
+        Token::Emphasis(_) => "emphasis",
+        Token::Strong(_) => "strong",
+        Token::Code(_) => "code",
+        Token::BlockQuote(_) => "blockquote",
+        Token::Attribution(_) => "attribution",
+        Token::CodeBlock(ref language, _) => {
+            res.insert(String::from("language"), string(language));
+            "code_block"
+        }
+        Token::Verse(_) => "verse",
+        Token::Epigraph(ref author, _) => {
+            res.insert(String::from("author"), string(author));
+            "epigraph"
+        }
+        Token::Spoiler(ref summary, _) => {
+            res.insert(String::from("summary"), string(summary));
+            "spoiler"
+        }
+        Token::Admonition(ref kind, ref title, _) => {
+            res.insert(String::from("kind"), string(kind));
+            res.insert(String::from("title"), string(title));
+            "admonition"
+        }
+        Token::Columns(count, _) => {
+            res.insert(String::from("count"), Json::U64(count as u64));
+            "columns"
+        }
+        Token::KeepTogether(_) => "keep_together",
+        Token::Exercise(ref id, _) => {
+            res.insert(String::from("id"), string(id));
+            "exercise"
+        }
+        Token::Answer(_) => "answer",
+        Token::Superscript(_) => "superscript",
+        Token::Subscript(_) => "subscript",
+        Token::Abbreviation(ref expansion, _) => {
+            res.insert(String::from("expansion"), string(expansion));
+            "abbreviation"
+        }
+        Token::List(_) => "list",
+        Token::OrderedList(start, _) => {
+            res.insert(String::from("start"), Json::U64(start as u64));
+            "ordered_list"
+        }
+        Token::Item(_) => "item",
+        Token::Table(columns, _) => {
+            res.insert(String::from("columns"), Json::I64(columns as i64));
+            "table"
+        }
+        Token::TableCaption(_) => "table_caption",
+        Token::TableHead(_) => "table_head",
+        Token::TableRow(_) => "table_row",
+        Token::TableCell(_) => "table_cell",
+        Token::Footnote(_) => "footnote",
+        Token::MarginNote(_) => "margin_note",
+        Token::Rule => "rule",
+        Token::NewPage => "new_page",
+        Token::SoftBreak => "soft_break",
+        Token::HardBreak => "hard_break",
+        Token::PageBreak(ref page) => {
+            res.insert(String::from("page"), string(page));
+            "page_break"
+        }
+        Token::Link(ref url, ref title, _) => {
+            res.insert(String::from("url"), string(url));
+            res.insert(String::from("title"), string(title));
+            "link"
+        }
+        Token::Image(ref url, ref title, ref attributes, _) |
+        Token::StandaloneImage(ref url, ref title, ref attributes, _) => {
+            res.insert(String::from("url"), string(url));
+            res.insert(String::from("title"), string(title));
+            res.insert(String::from("attributes"), string(attributes));
+            if let Token::Image(..) = *token {
+                "image"
+            } else {
+                "standalone_image"
+            }
+        }
+        Token::Annotation(ref data, _) => {
+            match *data {
+                Data::GrammarError(ref s) => {
+                    res.insert(String::from("grammar_error"), string(s));
+                }
+                Data::Repetition(ref s) => {
+                    res.insert(String::from("repetition"), string(s));
+                }
+                Data::__NonExhaustive => unreachable!(),
+            }
+            "annotation"
+        }
+        Token::__NonExhaustive => unreachable!(),
+    };
+    res.insert(String::from("type"), string(kind));
+    if let Some(inner) = token.inner() {
+        res.insert(String::from("content"), tokens_json(inner));
+    }
+    Json::Object(res)
+}
+
+/// JSON dump of the parsed book
+pub struct Ast {}
+
+impl BookRenderer for Ast {
+    fn auto_path(&self, book_name: &str) -> Result<String> {
+        Ok(format!("{}.ast.json", book_name))
+    }
+
+    fn render(&self, book: &Book, to: &mut Write) -> Result<()> {
+        let content = format!("{}\n", to_json(book).pretty());
+        to.write_all(content.as_bytes())
+            .map_err(|e| {
+                Error::render(Source::empty(),
+                              lformat!("problem when writing the AST: {error}", error = e))
+            })?;
+        Ok(())
+    }
+}
@@ -0,0 +1,55 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Rendering of several books in one invocation.
+
+use book::Book;
+use book_renderer::RenderResult;
+use error::Result;
+
+use rayon::prelude::*;
+
+/// Loads and renders several books in parallel, and returns the failures,
+/// one line per book that couldn't be loaded or format that couldn't be
+/// rendered
+///
+/// `load` loads the book of a file, and `render` renders it to its formats,
+/// returning the result of each one (like `Book::render_all`). A failure
+/// doesn't prevent other books from being rendered.
+///
+/// The only cache shared between these books is the one of diagrams, in
+/// `crowbook.temp_dir` (if they don't set a different one).
+pub fn render_books<L, R>(files: &[&str], load: L, render: R) -> Vec<String>
+    where L: Fn(&str) -> Result<Book> + Sync,
+          R: Fn(&Book) -> Vec<(String, Result<RenderResult>)> + Sync
+{
+    let failures: Vec<Vec<String>> = files.par_iter()
+        .map(|file| {
+            let book = match load(file) {
+                Ok(book) => book,
+                Err(err) => return vec![format!("* {}: {}", file, err)],
+            };
+            render(&book)
+                .into_iter()
+                .filter_map(|(format, result)| {
+                    result.err().map(|err| format!("* {} ({}): {}", file, format, err))
+                })
+                .collect()
+        })
+        .collect();
+    failures.into_iter().flat_map(|v| v).collect()
+}
@@ -16,26 +16,55 @@
 // along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
 
 use error::{Error, Result, Source};
-use cleaner::{Cleaner, CleanerParams, French, Off, Default};
+use cleaner::{self, Cleaner, CleanerParams, NumberFormat, French, Off, Default};
 use bookoptions::BookOptions;
 use parser::Parser;
 use parser::Features;
-use epub::{Epub};
+use rst::RstParser;
+use html_import::HtmlImporter;
+use epub::{Epub, Mobi};
 use html_single::{HtmlSingle, ProofHtmlSingle};
 use html_dir::{HtmlDir, ProofHtmlDir};
 use html_if::{HtmlIf};
 use latex::{Latex, ProofLatex, Pdf, ProofPdf};
+use cover::PdfCover;
 use odt::{Odt};
-use templates::{epub, html, epub3, latex, html_dir, highlight, html_single, html_if};
+use docbook::DocBook;
+use text::Text;
+use markdown::Markdown;
+use ast::Ast;
+use slides::Slides;
+use templates::{self, epub, html, epub3, latex, html_dir, highlight, html_single, html_if, colophon,
+                revisions, back_matter, slides, version};
 use number::Number;
 use resource_handler::ResourceHandler;
 use logger::{Logger, InfoLevel};
 use lang;
 use misc;
-use book_renderer::BookRenderer;
+use book_renderer::{BookRenderer, RenderResult, RenderOutput};
+use degradation;
 use chapter::Chapter;
 use token::Token;
 use text_view::view_as_text;
+use captions;
+use captions::CaptionKind;
+use syntax::CodeOptions;
+use diagrams;
+use page_breaks;
+use acronyms;
+use links;
+use editions;
+use exercises;
+use dialogue;
+use quotes;
+use headings;
+use sample::{self, SampleSize};
+use opds;
+use publish;
+use retail;
+use git::{self, GitInfo};
+use manifest;
+use calibre;
 
 #[cfg(feature = "proofread")]
 use repetition_check::RepetitionDetector;
@@ -75,13 +104,16 @@ impl RepetitionDetector {
 
 
 use std::fs::File;
-use std::io::{Write, Read};
+use std::io::{self, Write, Read};
 use std::path::{Path, PathBuf};
 use std::borrow::Cow;
 use std::iter::IntoIterator;
 use std::collections::HashMap;
 use std::cmp::Ordering;
 use std::fmt;
+use std::time::Instant;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
 
 use rayon::prelude::*;
 use mustache;
@@ -89,6 +121,12 @@ use mustache::{MapBuilder, Template};
 use yaml_rust::{YamlLoader, Yaml};
 use numerals::roman::Roman;
 
+/// Keys of inline YAML blocks that are specific to a chapter instead of setting book options
+static CHAPTER_METADATA: &'static [&'static str] = &["epigraph", "epigraph.author", "front_matter",
+                                                     "toc", "numbered", "hide_title", "edition",
+                                                     "pov", "story_date", "location", "published",
+                                                     "chapter_title", "chapter_title.keep_heading"];
+
 /// Type of header (part or chapter)
 #[derive(Copy, Clone, Debug)]
 pub enum Header {
@@ -168,13 +206,26 @@ pub struct Book {
     #[doc(hidden)]
     pub features: Features,
 
+    /// State of the git repository containing the book, if any
+    #[doc(hidden)]
+    pub git: Option<GitInfo>,
+
     cleaner: Box<Cleaner>,
+    /// Whether `input.clean` is set
+    autoclean: bool,
     chapter_template: Option<Template>,
     part_template: Option<Template>,
+    /// Whether chapter_template and part_template refer to metadata that
+    /// must be rendered (e.g. `author`), or only to numbering and localization
+    chapter_template_metadata: bool,
+    part_template_metadata: bool,
     checker: Option<GrammarChecker>,
     grammalecte: Option<GrammalecteChecker>,
     detector: Option<RepetitionDetector>,
     formats: HashMap<&'static str, (String, Box<BookRenderer>)>,
+    /// Configuration file of this book, and the ones that inherit from it
+    /// (to detect loops in `inherits`)
+    inheritance: Vec<PathBuf>,
 }
 
 impl Book {
@@ -184,16 +235,21 @@ impl Book {
             source: Source::empty(),
             chapters: vec![],
             cleaner: Box::new(Off),
+            autoclean: false,
             root: PathBuf::new(),
             options: BookOptions::new(),
             logger: Logger::new(),
             chapter_template: None,
             part_template: None,
+            chapter_template_metadata: true,
+            part_template_metadata: true,
             checker: None,
             grammalecte: None,
             detector: None,
             formats: HashMap::new(),
             features: Features::new(),
+            git: None,
+            inheritance: vec![],
         };
         book.add_format("html", lformat!("HTML (standalone page)"), Box::new(HtmlSingle{}))
             .add_format("proofread.html", lformat!("HTML (standalone page/proofreading)"), Box::new(ProofHtmlSingle{}))
@@ -203,8 +259,15 @@ impl Book {
             .add_format("proofread.tex", lformat!("LaTeX (proofreading)"), Box::new(ProofLatex{}))
             .add_format("pdf", lformat!("PDF"), Box::new(Pdf{}))
             .add_format("proofread.pdf", lformat!("PDF (proofreading)"), Box::new(ProofPdf{}))
+            .add_format("pdf.cover", lformat!("PDF (print cover)"), Box::new(PdfCover{}))
             .add_format("epub", lformat!("EPUB"), Box::new(Epub{}))
+            .add_format("mobi", lformat!("Mobi (Kindle)"), Box::new(Mobi{}))
             .add_format("odt", lformat!("ODT"), Box::new(Odt{}))
+            .add_format("docbook", lformat!("DocBook"), Box::new(DocBook{}))
+            .add_format("txt", lformat!("Plain text"), Box::new(Text{}))
+            .add_format("md", lformat!("Markdown"), Box::new(Markdown{}))
+            .add_format("ast", lformat!("AST (JSON)"), Box::new(Ast{}))
+            .add_format("slides", lformat!("Slides (reveal.js)"), Box::new(Slides{}))
             .add_format("html.if", lformat!("HTML (interactive fiction)"), Box::new(HtmlIf{}));
         book
     }
@@ -212,6 +275,11 @@ impl Book {
     /// Register a format that can be rendered.
     ///
     /// The renderer for this format must implement the `BookRenderer` trait.
+    /// Its output file can then be set with the `output.{format}` option
+    /// (which, like the ones of built-in formats, is relative to
+    /// `output.base_path` and can be set to `auto` if the renderer
+    /// implements `auto_path`), and `render_all` renders it along with
+    /// built-in formats.
     ///
     /// # Example
     ///
@@ -230,11 +298,14 @@ impl Book {
     /// book.add_format("foo",
     ///                 "Some dummy implementation",
     ///                 Box::new(Dummy{}));
+    /// book.set_options(&[("output.foo", "foo.txt")]);
+    /// assert!(book.options.get_path("output.foo").unwrap().ends_with("foo.txt"));
     /// ```
     pub fn add_format<S: Into<String>>(&mut self,
                                        format: &'static str,
                                        description: S,
                                        renderer: Box<BookRenderer>) -> &mut Self {
+        self.options.add_output(format);
         self.formats.insert(format, (description.into(), renderer));
         self
     }
@@ -266,8 +337,13 @@ impl Book {
                                     error = err));
             }
         }
+        self.log_option_warnings();
         // set cleaner according to lang and autoclean settings
         self.update_cleaner();
+        // compile the numbering templates, which might have changed
+        if let Err(err) = self.set_chapter_template() {
+            self.logger.error(lformat!("Error initializing book: {error}", error = err));
+        }
         self
     }
 
@@ -315,6 +391,10 @@ impl Book {
             self.root = parent.to_owned();
             self.options.root = self.root.clone();
         }
+        if let Ok(path) = path.as_ref().canonicalize() {
+            self.inheritance.push(path);
+        }
+        self.git = GitInfo::new(&self.root);
 
         let result = self.read_config(&f);
         match result {
@@ -358,6 +438,7 @@ impl Book {
             self.root = parent.to_owned();
             self.options.root = self.root.clone();
         }
+        self.git = GitInfo::new(&self.root);
         self.options.set("tex.class", "article").unwrap();
         self.options.set("input.yaml_blocks", "true").unwrap();
 
@@ -404,6 +485,16 @@ impl Book {
 
         // Update grammar checker according to options
         self.add_chapter_from_source(Number::Hidden, source)?;
+        page_breaks::import_page_breaks(self)?;
+        self.make_sample()?;
+        self.add_lists()?;
+        self.add_list_of_acronyms();
+        self.add_answers()?;
+        self.add_back_matter()?;
+        self.add_revision_history()?;
+        self.add_colophon()?;
+        links::check_links(self)?;
+        self.check_strict()?;
 
         Ok(self)
     }
@@ -419,12 +510,21 @@ impl Book {
             }
             Ok(mut docs) => {
                 if docs.len() == 1 && docs[0].as_hash().is_some() {
-                    if let Yaml::Hash(hash) = docs.pop().unwrap() {
+                    if let Yaml::Hash(mut hash) = docs.pop().unwrap() {
+                        // Base configurations are applied first, so the options
+                        // of this file override them wherever they are set
+                        if let Some(value) = hash.remove(&Yaml::String(String::from("inherits"))) {
+                            self.inherit(value)?;
+                        }
                         for (key, value) in hash {
                             if let Err(err) = self.options.set_yaml(key, value) {
+                                if self.options.get_bool("strict").unwrap() {
+                                    return Err(err);
+                                }
                                 self.logger.error(format!("{}", err));
                             };
                         }
+                        self.log_option_warnings();
                     } else {
                         unreachable!();
                     }
@@ -438,6 +538,47 @@ impl Book {
         Ok(self)
     }
         
+    /// Sets the options of the base configuration file(s) set by `inherits`
+    fn inherit(&mut self, value: Yaml) -> Result<()> {
+        let files = match value {
+            Yaml::String(file) => vec![Yaml::String(file)],
+            Yaml::Array(files) => files,
+            value => vec![value],
+        };
+        // `merge` doesn't override options that are already set, so when
+        // several files are listed, the last one is applied first
+        for file in files.into_iter().rev() {
+            let file = match file {
+                Yaml::String(file) => file,
+                value => {
+                    return Err(Error::book_option(&self.source,
+                                                  lformat!("expected a file name or a list of \
+                                                            file names for 'inherits', found \
+                                                            {value:?}",
+                                                           value = value)))
+                }
+            };
+            let path = self.root.join(&file);
+            let canonical = path.canonicalize()
+                .map_err(|_| {
+                    Error::file_not_found(&self.source,
+                                          lformat!("base configuration"),
+                                          format!("{}", path.display()))
+                })?;
+            if self.inheritance.contains(&canonical) {
+                return Err(Error::config_parser(&self.source,
+                                                lformat!("'inherits' loops: {file} inherits \
+                                                          from this book",
+                                                         file = misc::normalize(&path))));
+            }
+            let mut base = Book::new();
+            base.inheritance = self.inheritance.clone();
+            base.load_file(&path)?;
+            self.options.merge(base.options)?;
+        }
+        Ok(())
+    }
+
     /// Reads a book configuration from a `Read`able source.
     ///
     /// # Book configuration
@@ -657,10 +798,345 @@ impl Book {
         }
 
         self.source.unset_line();
+        page_breaks::import_page_breaks(self)?;
+        self.make_sample()?;
+        self.add_lists()?;
+        self.add_list_of_acronyms();
+        self.add_answers()?;
+        self.add_back_matter()?;
+        self.add_revision_history()?;
+        self.add_colophon()?;
+        links::check_links(self)?;
+        self.check_strict()?;
         self.set_chapter_template()?;
         Ok(self)
     }
 
+    /// Parses Markdown content (e.g. from an option) with the book's parser
+    /// options, logging the parser's warnings
+    #[doc(hidden)]
+    pub fn parse_markdown(&self, content: &str) -> Result<Vec<Token>> {
+        let mut parser = Parser::from(self);
+        let tokens = parser.parse(content)?;
+        for warning in parser.take_warnings() {
+            self.logger.warning(warning);
+        }
+        Ok(tokens)
+    }
+
+    /// Logs the warnings emitted while setting options (e.g. for deprecated keys)
+    fn log_option_warnings(&mut self) {
+        for warning in self.options.take_warnings() {
+            self.logger.warning(warning);
+        }
+    }
+
+    /// Returns an error if `strict` is set and warnings were logged while
+    /// loading the book, or if there is no specific typographic cleaner for
+    /// its language
+    fn check_strict(&self) -> Result<()> {
+        if !self.options.get_bool("strict").unwrap() {
+            return Ok(());
+        }
+        let lang = self.options.get_str("lang").unwrap().to_lowercase();
+        if self.options.get_bool("input.clean").unwrap() &&
+           !(lang.starts_with("fr") || lang.starts_with("en")) {
+            return Err(Error::default(&self.source,
+                                      lformat!("strict mode: there is no typographic cleaner \
+                                                specific to lang '{lang}', set input.clean to \
+                                                false to use none",
+                                               lang = lang)));
+        }
+        let warnings = self.logger.warnings();
+        if warnings > 0 {
+            Err(Error::default(&self.source,
+                               lformat!("strict mode: {n} warning(s) were emitted while loading \
+                                         the book",
+                                        n = warnings)))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Keeps only the beginning of the book if `sample` is set, and adds a
+    /// chapter inviting readers to buy the full book after it
+    fn make_sample(&mut self) -> Result<()> {
+        let size = match self.options.get_str("sample") {
+            Ok(size) => {
+                match SampleSize::parse(size) {
+                    Some(size) => size,
+                    None => {
+                        return Err(Error::config_parser(&self.source,
+                                                        lformat!("invalid value for sample: \
+                                                                  '{value}', expected a number \
+                                                                  of chapters or a percentage \
+                                                                  (e.g. 10%)",
+                                                                 value = size)))
+                    }
+                }
+            }
+            Err(_) => return Ok(()),
+        };
+        sample::truncate(&mut self.chapters, size);
+
+        let template = compile_str(self.get_template("sample.template")?.as_ref(),
+                                   &self.source,
+                                   "sample.template")?;
+        let mut data = self.get_metadata(|s| Ok(s.to_owned()))?;
+        if let Ok(url) = self.options.get_str("sample.url") {
+            data = data
+                .insert_bool("has_sample_url", true)
+                .insert_str("sample_url", url);
+        }
+        let data = data.build();
+        let mut res: Vec<u8> = vec![];
+        template.render_data(&mut res, &data)?;
+        self.add_chapter_from_source(Number::Unnumbered, res.as_slice())?;
+        Ok(())
+    }
+
+    /// Displays the answers of exercises after them, collects them in an
+    /// "Answers" chapter or removes them, according to `rendering.exercises.answers`
+    fn add_answers(&mut self) -> Result<()> {
+        let lang = self.options.get_str("lang").unwrap();
+        match self.options.get_str("rendering.exercises.answers").unwrap() {
+            "inline" => {
+                exercises::answers_as_spoilers(&mut self.chapters, &lang::get_str(lang, "answer"));
+            }
+            "none" => {
+                exercises::take_answers(&mut self.chapters);
+            }
+            "end" => {
+                let answers = exercises::take_answers(&mut self.chapters);
+                if answers.iter().all(|answer| answer.is_none()) {
+                    return Ok(());
+                }
+                let exercises = captions::collect(self, Some(CaptionKind::Exercise));
+                let content = exercises::answers_chapter(&lang::get_str(lang, "answers"),
+                                                         lang,
+                                                         &exercises,
+                                                         answers);
+                self.chapters.push(Chapter::new(Number::Unnumbered, String::new(), content));
+            }
+            value => {
+                return Err(Error::config_parser(&self.source,
+                                                lformat!("rendering.exercises.answers set to \
+                                                          '{value}', expected 'end', 'inline' \
+                                                          or 'none'",
+                                                         value = value)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the "About the author" and "Also by this author" chapters at the
+    /// end of the book, if `rendering.about_author` or
+    /// `rendering.also_by.page` are set
+    fn add_back_matter(&mut self) -> Result<()> {
+        if self.options.get_bool("rendering.about_author").unwrap() {
+            let template = compile_str(self.get_template("rendering.about_author.template")?
+                                           .as_ref(),
+                                       &self.source,
+                                       "rendering.about_author.template")?;
+            let mut data = self.get_metadata(|s| Ok(s.to_owned()))?;
+            if let Ok(photo) = self.options.get_str("author.photo") {
+                data = data
+                    .insert_bool("has_author_photo", true)
+                    .insert_str("author_photo", photo);
+            }
+            let data = data.build();
+            let mut res: Vec<u8> = vec![];
+            template.render_data(&mut res, &data)?;
+            self.add_chapter_from_source(Number::Unnumbered, res.as_slice())?;
+        }
+        if self.options.get_bool("rendering.also_by.page").unwrap() {
+            if self.options.get_str_vec("rendering.also_by").map(|v| v.is_empty()).unwrap_or(true) {
+                self.logger.warning(lformat!("rendering.also_by.page is set to true but \
+                                              rendering.also_by is empty"));
+                return Ok(());
+            }
+            let template = compile_str(self.get_template("rendering.also_by.template")?.as_ref(),
+                                       &self.source,
+                                       "rendering.also_by.template")?;
+            let data = self.get_metadata(|s| Ok(s.to_owned()))?.build();
+            let mut res: Vec<u8> = vec![];
+            template.render_data(&mut res, &data)?;
+            self.add_chapter_from_source(Number::Unnumbered, res.as_slice())?;
+        }
+        Ok(())
+    }
+
+    /// Adds a colophon at the end of the book if `rendering.colophon` is set
+    fn add_colophon(&mut self) -> Result<()> {
+        if !self.options.get_bool("rendering.colophon").unwrap() {
+            return Ok(());
+        }
+        let template = compile_str(self.get_template("rendering.colophon.template")?.as_ref(),
+                                   &self.source,
+                                   "rendering.colophon.template")?;
+        let mut data = self.get_metadata(|s| Ok(s.to_owned()))?
+            .insert_str("build_date", misc::today());
+        if let Ok(fonts) = self.options.get_str("rendering.colophon.fonts") {
+            data = data
+                .insert_bool("has_fonts", true)
+                .insert_str("fonts", fonts);
+        }
+        let data = data.build();
+        let mut res: Vec<u8> = vec![];
+        template.render_data(&mut res, &data)?;
+        self.add_chapter_from_source(Number::Unnumbered, res.as_slice())?;
+        Ok(())
+    }
+
+    /// Adds a chapter listing the commits that modified the chapters'
+    /// files at the end of the book if `rendering.revision_history` is set
+    fn add_revision_history(&mut self) -> Result<()> {
+        if !self.options.get_bool("rendering.revision_history").unwrap() {
+            return Ok(());
+        }
+        let commits = {
+            let files: Vec<&str> = self.chapters
+                .iter()
+                .map(|c| c.filename.as_str())
+                .filter(|f| !f.is_empty() && self.root.join(f).is_file())
+                .collect();
+            let max = self.options.get_i32("rendering.revision_history.max").unwrap();
+            match git::log(&self.root, &files, max) {
+                Some(commits) => commits,
+                None => {
+                    self.logger.warning(lformat!("rendering.revision_history is set but the \
+                                                  book is not in a git repository"));
+                    return Ok(());
+                }
+            }
+        };
+        let template = compile_str(self.get_template("rendering.revision_history.template")?
+                                       .as_ref(),
+                                   &self.source,
+                                   "rendering.revision_history.template")?;
+        let data = self.get_metadata(|s| Ok(s.to_owned()))?
+            .insert_vec("commits", |mut builder| {
+                for commit in &commits {
+                    builder = builder.push_map(|map| {
+                        map.insert_str("short_commit", commit.short_commit.as_str())
+                            .insert_str("date", commit.date.as_str())
+                            .insert_str("author", commit.author.as_str())
+                            .insert_str("subject", commit.subject.as_str())
+                    });
+                }
+                builder
+            })
+            .build();
+        let mut res: Vec<u8> = vec![];
+        template.render_data(&mut res, &data)?;
+        self.add_chapter_from_source(Number::Unnumbered, res.as_slice())?;
+        Ok(())
+    }
+
+    /// Replaces the content of code blocks with an `include` option by
+    /// (a part of) the content of the file, relative to `dir`
+    fn include_code(&self, tokens: &mut [Token], dir: &Path, source: &Source) -> Result<()> {
+        for token in tokens {
+            if let Token::CodeBlock(ref info, ref mut content) = *token {
+                let options = CodeOptions::parse(info).unwrap_or_default();
+                if let Some(ref file) = options.include {
+                    let path = dir.join(file);
+                    let mut code = String::new();
+                    File::open(&path)
+                        .and_then(|mut f| f.read_to_string(&mut code))
+                        .map_err(|_| {
+                            Error::file_not_found(source,
+                                                  lformat!("included code"),
+                                                  format!("{}", path.display()))
+                        })?;
+                    let code = options.extract(&code)
+                        .ok_or_else(|| {
+                            Error::parser(source,
+                                          lformat!("could not find the requested region or \
+                                                    lines in included file {file}",
+                                                   file = misc::normalize(file)))
+                        })?;
+                    *content = vec![Token::Str(code)];
+                }
+                continue;
+            }
+            if let Some(inner) = token.inner_mut() {
+                self.include_code(inner, dir, source)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds lists of listings, figures and tables at the beginning of the book, according
+    /// to `rendering.list_of_listings`, `rendering.list_of_figures` and
+    /// `rendering.list_of_tables`
+    fn add_lists(&mut self) -> Result<()> {
+        for &(option, kind) in &[("rendering.list_of_listings", CaptionKind::Listing),
+                                 ("rendering.list_of_figures", CaptionKind::Figure),
+                                 ("rendering.list_of_tables", CaptionKind::Table)] {
+            if self.options.get_bool(option).unwrap() {
+                self.add_list_of(kind)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a list of the numbered elements of this kind, after the front matter chapters
+    fn add_list_of(&mut self, kind: CaptionKind) -> Result<()> {
+        let captions = captions::collect(self, Some(kind));
+        if captions.is_empty() {
+            return Ok(());
+        }
+        let lang = self.options.get_str("lang").unwrap().to_owned();
+        let title = format!("list_of_{}s", kind.loc_key());
+        let mut content = format!("# {}\n\n", lang::get_str(&lang, &title));
+        for caption in &captions {
+            content.push_str(&format!("* [{label}{caption}](#{id})\n",
+                                      label = caption.label(&lang),
+                                      caption = if caption.caption.is_empty() {
+                                          String::new()
+                                      } else {
+                                          format!(": {}", caption.caption)
+                                      },
+                                      id = caption.id));
+        }
+        self.add_chapter_from_source(Number::Unnumbered, content.as_bytes())?;
+
+        // Move it after the other front matter chapters
+        let mut chapter = self.chapters.pop().unwrap();
+        chapter.front_matter = true;
+        chapter.list_of = Some(kind);
+        let position = self.chapters.iter().take_while(|c| c.front_matter).count();
+        self.chapters.insert(position, chapter);
+        Ok(())
+    }
+
+    /// Adds a list of the acronyms at the end of the book if `rendering.list_of_acronyms`
+    /// is set
+    ///
+    /// The tokens are built directly, so acronyms in this list are not expanded.
+    fn add_list_of_acronyms(&mut self) {
+        if !self.options.get_bool("rendering.list_of_acronyms").unwrap() {
+            return;
+        }
+        let content = {
+            let acronyms = self.options.get_acronyms();
+            if acronyms.is_empty() {
+                return;
+            }
+            let lang = self.options.get_str("lang").unwrap();
+            let items = acronyms.iter()
+                .map(|&(acronym, expansion)| {
+                    Token::Item(vec![Token::Strong(vec![Token::Str(acronym.to_owned())]),
+                                     Token::Str(format!(": {}", expansion))])
+                })
+                .collect();
+            vec![Token::Header(1, vec![Token::Str(lang::get_str(lang, "list_of_acronyms"))]),
+                 Token::List(items)]
+        };
+        self.chapters.push(Chapter::new(Number::Unnumbered, String::new(), content));
+    }
+
     /// Determine whether proofreading is activated or not
     fn is_proofread(&self) -> bool {
         self.options.get_bool("proofread").unwrap() &&
@@ -707,7 +1183,7 @@ impl Book {
     fn init_checker(&mut self) {}
 
     /// Renders the book to the given format if output.{format} is set;
-    /// do nothing otherwise.
+    /// do nothing (and return `None`) otherwise.
     ///
     /// # Example
     ///
@@ -715,17 +1191,12 @@ impl Book {
     /// use crowbook::Book;
     /// let mut book = Book::new();
     /// /* Will do nothing as book is empty and has no output format specified */
-    /// book.render_format("pdf");
+    /// assert!(book.render_format("pdf").unwrap().is_none());
     /// ```
-    pub fn render_format(&self, format: &str) -> () {
-        let mut key = String::from("output.");
-        key.push_str(format);
-        if let Ok(path) = self.options.get_path(&key) {
-            let result = self.render_format_to_file(format, path);
-            if let Err(err) = result {
-                self.logger
-                    .error(lformat!("Error rendering {name}: {error}", name = format, error = err));
-            }
+    pub fn render_format(&self, format: &str) -> Result<Option<RenderResult>> {
+        match self.output_path(format)? {
+            Some(path) => self.render_format_to_file(format, path).map(Some),
+            None => Ok(None),
         }
     }
 
@@ -750,7 +1221,14 @@ impl Book {
     ///       .unwrap()
     ///       .render_all(); // renders foo.tex in /tmp
     /// ```
-    pub fn render_all(&self) -> () {
+    ///
+    /// Returns the result of the rendering of each format whose output file
+    /// is specified, sorted by format name. A format failing to render (or
+    /// its renderer panicking) doesn't prevent other formats from being
+    /// rendered. Errors writing the manifest (`output.manifest`) or the
+    /// Calibre metadata (`output.calibre`) are returned last, for the
+    /// `manifest` and `calibre` keys.
+    pub fn render_all(&self) -> Vec<(String, Result<RenderResult>)> {
         let mut keys: Vec<_> = self.formats
             .keys()
             .filter(|fmt| {
@@ -772,13 +1250,66 @@ impl Book {
             }
         });
 
-        keys.par_iter()
-            .for_each(|fmt| self.render_format(fmt));
+        let mut results: Vec<_> = keys.par_iter()
+            .filter_map(|fmt| {
+                let result = panic::catch_unwind(AssertUnwindSafe(|| self.render_format(fmt)))
+                    .unwrap_or_else(|_| {
+                        Err(Error::render(Source::empty(),
+                                          lformat!("renderer panicked")))
+                    });
+                match result {
+                    Ok(Some(result)) => Some((fmt.to_string(), Ok(result))),
+                    Ok(None) => None,
+                    Err(err) => Some((fmt.to_string(), Err(err))),
+                }
+            })
+            .collect();
+        results.sort_by(|a, b| a.0.cmp(&b.0));
+
+        if let Err(err) = manifest::write_manifest(self, &results) {
+            results.push((String::from("manifest"), Err(err)));
+        }
+        if let Err(err) = calibre::write_metadata(self) {
+            results.push((String::from("calibre"), Err(err)));
+        }
+
+        results
+    }
 
-        // if handles.is_empty() {
-        //     Logger::display_warning(lformat!("Crowbook generated no file because no output file was \
-        //                              specified. Add output.{{format}} to your config file."));
-        // }
+    /// Renders the book with a renderer, recording the time spent and the
+    /// warnings logged
+    fn render_with<F>(&self, format: &str, f: F) -> Result<RenderResult>
+        where F: FnOnce(&BookRenderer) -> Result<RenderOutput>
+    {
+        self.logger.debug(lformat!("Attempting to generate {format}...",
+                                   format = format));
+        let renderer = match self.formats.get(format) {
+            Some(&(_, ref renderer)) => renderer,
+            None => {
+                return Err(Error::default(Source::empty(),
+                                          lformat!("unknown format {format}",
+                                                   format = format)))
+            }
+        };
+        let start = Instant::now();
+        self.logger.start_recording();
+        let result = f(&**renderer);
+        let warnings = self.logger.stop_recording();
+        let output = result?;
+        if !warnings.is_empty() && self.options.get_bool("strict").unwrap() {
+            return Err(Error::render(&self.source,
+                                     lformat!("strict mode: {n} warning(s) were emitted while \
+                                               rendering {format}",
+                                              n = warnings.len(),
+                                              format = format)));
+        }
+        Ok(RenderResult {
+            format: format.to_owned(),
+            output: output,
+            duration: start.elapsed(),
+            warnings: warnings,
+            degraded: degradation::report(self, format),
+        })
     }
 
 
@@ -793,22 +1324,15 @@ impl Book {
     /// * `render_format_to_file`, which creates a new file (that *can* be a directory).
     /// * `render_format`, which won't do anything if `output.{format}` isn't specified
     ///   in the book configuration file.
-    pub fn render_format_to<T: Write>(&self, format: &str, f: &mut T) -> Result<()> {
-        self.logger.debug(lformat!("Attempting to generate {format}...",
-                                   format = format));
-        match self.formats.get(format) {
-            Some(&(ref description, ref renderer)) => {
-                renderer.render(self, f)?;
-                self.logger.info(lformat!("Succesfully generated {format}",
-                                          format = description));
-                Ok(())
-            },
-            None => {
-                Err(Error::default(Source::empty(),
-                                   lformat!("unknown format {format}",
-                                            format = format)))
-            }
-        }
+    pub fn render_format_to<T: Write>(&self, format: &str, f: &mut T) -> Result<RenderResult> {
+        self.render_with(format, |renderer| {
+            let mut writer = CountingWriter {
+                inner: f,
+                count: 0,
+            };
+            renderer.render(self, &mut writer)?;
+            Ok(RenderOutput::Bytes(writer.count))
+        })
     }
 
     /// Render book to specified format according to book options. Creates a new file
@@ -821,35 +1345,105 @@ impl Book {
     /// * `render_format_to`, which writes in any `Write`able object.
     /// * `render_format`, which won't do anything if `output.{format}` isn't specified
     ///   in the book configuration file.
-    pub fn render_format_to_file<P:Into<PathBuf>>(&self, format: &str, path: P) -> Result<()> {
-        self.logger.debug(lformat!("Attempting to generate {format}...",
-                                   format = format));
-        let path = path.into();
+    pub fn render_format_to_file<P:Into<PathBuf>>(&self, format: &str, path: P)
+                                                  -> Result<RenderResult> {
+        let path = self.resolve_path(format, path.into())?;
+        self.render_with(format, |renderer| {
+            renderer.render_to_file(self, &path)?;
+            Ok(RenderOutput::File(path.clone()))
+        })
+    }
+
+    /// Returns the description of a format (e.g. "HTML (standalone page)"),
+    /// or `None` if it isn't handled by the book
+    pub fn format_description(&self, format: &str) -> Option<&str> {
+        self.formats.get(format).map(|&(ref description, _)| description.as_str())
+    }
+
+    /// Returns the names of the formats handled by the book, sorted
+    pub fn formats(&self) -> Vec<&'static str> {
+        let mut formats: Vec<_> = self.formats.keys().cloned().collect();
+        formats.sort();
+        formats
+    }
+
+    /// Returns the path of the file generated for a format according to book
+    /// options, or `None` if `output.{format}` isn't specified
+    ///
+    /// If `output.versioned` is set, the version of the book is appended to
+    /// the file name, and if `sample` is set, `-sample` is.
+    pub fn output_path(&self, format: &str) -> Result<Option<PathBuf>> {
+        let path = match self.options.get_path(&format!("output.{}", format)) {
+            Ok(path) => self.resolve_path(format, PathBuf::from(path))?,
+            Err(_) => return Ok(None),
+        };
+        let mut suffix = String::new();
+        if self.options.get_bool("output.versioned").unwrap() {
+            let version = self.options
+                .get_str("version")
+                .map_err(|_| Error::default(&self.source,
+                                            lformat!("output.versioned is set but version is not")))?;
+            suffix.push_str(&format!("-{}", version));
+        }
+        if self.options.get_str("sample").is_ok() {
+            suffix.push_str("-sample");
+        }
+        if suffix.is_empty() {
+            return Ok(Some(path));
+        }
+        let stem = path.file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let file = match path.extension() {
+            Some(ext) => format!("{}{}.{}", stem, suffix, ext.to_string_lossy()),
+            None => format!("{}{}", stem, suffix),
+        };
+        Ok(Some(path.with_file_name(file)))
+    }
+
+    /// Returns an OPDS catalog containing this book, with links to its
+    /// EPUB, PDF, ODT and HTML files
+    pub fn opds_catalog(&self) -> Result<String> {
+        opds::catalog(self)
+    }
+
+    /// Returns the metadata of the book for retailers, as an ONIX 3.0 record
+    /// (`onix`) or as a CSV file with the fields of Kindle Direct Publishing
+    /// (`kdp`)
+    pub fn retail_metadata(&self, format: &str) -> Result<String> {
+        retail::export(self, format)
+    }
+
+    /// Publishes the output files of the book to the targets set by `publish.*`
+    /// options
+    ///
+    /// The book must already have been rendered.
+    pub fn publish(&self) -> Result<()> {
+        publish::publish(self)
+    }
+
+    /// Infers the file name of an output path set to `auto` from the book
+    /// file name
+    fn resolve_path(&self, format: &str, path: PathBuf) -> Result<PathBuf> {
+        if !path.ends_with("auto") {
+            return Ok(path);
+        }
         match self.formats.get(format) {
             Some(&(ref description, ref renderer)) => {
-                let path = if path.ends_with("auto") {
-                    let file = if let Some(s) = self.source
-                        .file
-                        .as_ref()
-                        .and_then(|f| Path::new(f).file_stem()) {
-                        s.to_string_lossy().into_owned()
-                    } else {
-                        return Err(Error::default(&self.source, lformat!("output to {format} set to auto but can't find book file name to infer it",
-                                                                     format = description)));
-                        };
-                    let file = renderer.auto_path(&file)
-                        .map_err(|_| Error::default(&self.source,
-                                                    lformat!("the {format} renderer does not support auto for output path",
-                                                             format = description)))?;
-                    path.with_file_name(file)
+                let file = if let Some(s) = self.source
+                    .file
+                    .as_ref()
+                    .and_then(|f| Path::new(f).file_stem()) {
+                    s.to_string_lossy().into_owned()
                 } else {
-                    path
+                    return Err(Error::default(&self.source, lformat!("output to {format} set to auto but can't find book file name to infer it",
+                                                                 format = description)));
                 };
-                renderer.render_to_file(self, &path)?;
-                self.logger.info(lformat!("Succesfully generated {format}: {path}",
-                                          format = description,
-                                          path = misc::normalize(path)));
-                Ok(())
+                let file = renderer.auto_path(&file)
+                    .map_err(|_| Error::default(&self.source,
+                                                lformat!("the {format} renderer does not support auto for output path",
+                                                         format = description)))?;
+                Ok(path.with_file_name(file))
             },
             None => {
                 Err(Error::default(Source::empty(),
@@ -875,14 +1469,90 @@ impl Book {
                                        file = misc::normalize(file)))
             })?;
 
-        // Ignore YAML blocks (or not)
-        self.parse_yaml(&mut content);
+        // Ignore YAML blocks (or not); in reStructuredText and HTML, `---`
+        // lines can be title adornments or text, so only front matter is read
+        let front_matter_only = file.ends_with(".rst") || file.ends_with(".html") ||
+                                file.ends_with(".htm");
+        let metadata = self.parse_yaml(&mut content, front_matter_only);
+
+        // Skip the chapter, or some of its blocks, if they are tagged with
+        // editions that aren't built
+        if let Some(tags) = metadata.get("edition") {
+            if !editions::is_included(self, tags) {
+                self.logger.debug(lformat!("skipping {file}, which is not part of the editions \
+                                            that are built",
+                                           file = misc::normalize(file)));
+                return Ok(self);
+            }
+        }
+        let content = editions::filter_blocks(self, &content, &Source::new(file))?;
 
-        // parse the file
+        // parse the file, as reStructuredText or HTML according to its
+        // extension
         let mut parser = Parser::from(self);
         parser.set_source_file(file);
-        let mut tokens = parser.parse(&content)?;
+        let mut tokens = if file.ends_with(".rst") {
+            let mut rst = RstParser::new();
+            rst.set_source_file(file);
+            let tokens = rst.parse(&content)?;
+            self.features = self.features | rst.features();
+            for warning in rst.take_warnings() {
+                self.logger.warning(warning);
+            }
+            tokens
+        } else if file.ends_with(".html") || file.ends_with(".htm") {
+            let mut importer = HtmlImporter::new();
+            importer.set_source_file(file);
+            let tokens = importer.parse(&content)?;
+            self.features = self.features | importer.features();
+            for warning in importer.take_warnings() {
+                self.logger.warning(warning);
+            }
+            tokens
+        } else {
+            parser.parse(&content)?
+        };
+        let summary_len = parser.summary_len();
+
+        // Include code from external files
+        let dir = self.root.join(Path::new(file).parent().unwrap_or(Path::new("")));
+        self.include_code(&mut tokens, &dir, &Source::new(file))?;
+        diagrams::render_diagrams(self, &mut tokens, &Source::new(file))?;
+        acronyms::mark_acronyms(self, &mut tokens);
+        headings::normalize(self, &mut tokens, &Source::new(file))?;
+
+        // Replace the heading of the file by the title set in the YAML block,
+        // if any, or keep it as a section
+        if let Some(title) = metadata.get("chapter_title") {
+            let title = Token::Header(1, parser.parse_inline(title)?);
+            let keep = metadata.get("chapter_title.keep_heading").map_or(false, |s| s == "true");
+            match tokens.iter().position(|t| if let Token::Header(1, _) = *t { true } else { false }) {
+                Some(pos) => {
+                    let heading = mem::replace(&mut tokens[pos], title);
+                    if keep {
+                        if let Token::Header(_, inner) = heading {
+                            tokens.insert(pos + 1, Token::Header(2, inner));
+                        }
+                    }
+                }
+                None => tokens.insert(0, title),
+            }
+        }
+
+        // Insert the epigraph, if any, right after the chapter title
+        if let Some(epigraph) = metadata.get("epigraph") {
+            let quote = parser.parse(epigraph)?;
+            let author = metadata.get("epigraph.author").cloned().unwrap_or_default();
+            let pos = tokens.iter()
+                .position(|t| if let Token::Header(1, _) = *t { true } else { false })
+                .map(|i| i + 1)
+                .unwrap_or(0);
+            tokens.insert(pos, Token::Epigraph(author, quote));
+        }
         self.features = self.features | parser.features();
+        for warning in parser.take_warnings() {
+            self.logger.warning(warning);
+        }
 
         // transform the AST to make local links and images relative to `book` directory
         let offset = if let Some(f) = Path::new(file).parent() {
@@ -960,25 +1630,65 @@ impl Book {
             }
         }
 
-        self.chapters.push(Chapter::new(number, file, tokens));
+        // Split the file into one chapter per level-1 heading, if asked to
+        let contents = match self.options.get_str("input.chapters").unwrap() {
+            "file" => vec![tokens],
+            "heading" if !number.is_part() => misc::split_at_headings(tokens),
+            "heading" => vec![tokens],
+            value => {
+                return Err(Error::config_parser(&self.source,
+                                                lformat!("input.chapters set to '{value}', \
+                                                          expected 'file' or 'heading'",
+                                                         value = value)));
+            }
+        };
+        for (i, tokens) in contents.into_iter().enumerate() {
+            let summary = misc::summary(&tokens, if i == 0 { summary_len } else { None });
+            // Chapters following the first one of a file continue its numbering
+            let number = match number {
+                Number::Specified(_) if i > 0 => Number::Default,
+                number => number,
+            };
+            let mut chapter = Chapter::new(number, file, tokens);
+            chapter.summary = summary;
+            chapter.front_matter = metadata.get("front_matter").map_or(false, |s| s == "true");
+            chapter.toc = metadata.get("toc").map_or(true, |s| s != "false");
+            // Numbering and title visibility can be set independently of the
+            // way the chapter is included
+            if let Some(numbered) = metadata.get("numbered") {
+                chapter.number = number.with_numbering(numbered == "true");
+            }
+            if let Some(hide_title) = metadata.get("hide_title") {
+                chapter.hide_title = hide_title == "true";
+            }
+            chapter.pov = metadata.get("pov").cloned();
+            chapter.story_date = metadata.get("story_date").cloned();
+            chapter.location = metadata.get("location").cloned();
+            chapter.published = metadata.get("published").cloned();
+            self.chapters.push(chapter);
+        }
 
         Ok(self)
     }
 
     /// Adds a chapter, as a file name, to the book
     pub fn add_subchapter(&mut self, level: i32, file: &str) -> Result<&mut Self> {
-        let number = {
+        let (number, front_matter, toc) = {
             if let Some(chapter) = self.chapters.last() {
-                chapter.number
+                (chapter.number, chapter.front_matter, chapter.toc)
             } else {
-                Number::Hidden
+                (Number::Hidden, false, true)
             }
         };
+        let first = self.chapters.len();
         self.add_chapter(number, file)?;
 
-        // Adjust header levels
-        {
-            let last = self.chapters.last_mut().unwrap();
+        // Adjust header levels (of each chapter, if the file was split)
+        for last in &mut self.chapters[first..] {
+            // A subchapter belongs to the front matter if its parent chapter does
+            last.front_matter = last.front_matter || front_matter;
+            // Nor is it listed in the table of contents if its parent isn't
+            last.toc = last.toc && toc;
             for mut token in &mut last.content {
                 match *token {
                     Token::Header(ref mut n, _) => {
@@ -1047,10 +1757,74 @@ impl Book {
     /// according to book `lang` and `autoclean` options
     #[doc(hidden)]
     pub fn clean<'s, S: Into<Cow<'s, str>>>(&self, text: S, tex: bool) -> Cow<'s, str> {
-        self.cleaner.clean(text.into(), tex)
+        self.clean_if(self.autoclean, text, tex)
+    }
+
+    /// Cleans a string according to book `lang` if `clean` is true, or does
+    /// nothing, e.g. with the result of `cleans`
+    #[doc(hidden)]
+    pub fn clean_if<'s, S: Into<Cow<'s, str>>>(&self, clean: bool, text: S, tex: bool)
+                                               -> Cow<'s, str> {
+        if clean {
+            self.cleaner.clean(text.into(), tex)
+        } else {
+            text.into()
+        }
     }
 
+    /// Returns whether text is typographically cleaned for a format (`html`,
+    /// `epub` or `tex`), that is, `{format}.clean` if it is set, and
+    /// `input.clean` else.
+    #[doc(hidden)]
+    pub fn cleans(&self, format: &str) -> bool {
+        self.options.get_bool(&format!("{}.clean", format))
+            .unwrap_or(self.autoclean)
+    }
 
+    /// Returns a chapter as it is rendered by a format
+    ///
+    /// Chapters are kept as they were parsed, so if `clean` is true (e.g.
+    /// with the result of `cleans`), this returns a copy of it where
+    /// dialogue is converted and quotes are nested according to
+    /// `input.clean.*` options (unless both are disabled). Other cleaning is
+    /// done by the cleaner when rendering each string.
+    ///
+    /// Renderers call it in their chapter loop, so only one cleaned chapter
+    /// is kept in memory at a time.
+    pub fn clean_chapter<'c>(&self, chapter: &'c Chapter, clean: bool) -> Result<Cow<'c, Chapter>> {
+        if !clean || !self.transforms_chapters() {
+            return Ok(Cow::Borrowed(chapter));
+        }
+        let mut chapter = chapter.clone();
+        let source = Source::new(chapter.filename.as_str());
+        dialogue::convert(self, &mut chapter.content, &source)?;
+        quotes::nest_quotes(self, &mut chapter.content);
+        dialogue::convert(self, &mut chapter.summary, &source)?;
+        quotes::nest_quotes(self, &mut chapter.summary);
+        Ok(Cow::Owned(chapter))
+    }
+
+    /// Returns the chapters of the book as they are rendered by a format
+    ///
+    /// See `clean_chapter`, which renderers should use instead to avoid
+    /// copying the whole book.
+    pub fn clean_chapters(&self, clean: bool) -> Result<Cow<[Chapter]>> {
+        if !clean || !self.transforms_chapters() {
+            return Ok(Cow::Borrowed(&self.chapters));
+        }
+        let mut chapters = Vec::with_capacity(self.chapters.len());
+        for chapter in &self.chapters {
+            chapters.push(self.clean_chapter(chapter, clean)?.into_owned());
+        }
+        Ok(Cow::Owned(chapters))
+    }
+
+    /// Returns true if cleaning modifies the tokens of chapters, i.e. if
+    /// dialogue is converted or quotes are nested
+    fn transforms_chapters(&self) -> bool {
+        self.options.get_str("input.clean.dialogue").is_ok() ||
+            self.options.get_bool("input.clean.smart_quotes") == Ok(true)
+    }
 
     /// Returns a template
     ///
@@ -1080,7 +1854,21 @@ impl Book {
             "html.highlight.css" => highlight::CSS,
             "html.if.js" => html_if::JS,
             "html.if.new_game" => html_if::NEW_GAME,
-            "tex.template" => latex::TEMPLATE,
+            "slides.template" => slides::TEMPLATE,
+            "tex.template" => {
+                if self.options.get_str("tex.class")? == "beamer" {
+                    latex::BEAMER
+                } else {
+                    latex::TEMPLATE
+                }
+            }
+            "tex.cover.template" => latex::COVER,
+            "tex.front_matter.template" => latex::FRONT_MATTER,
+            "rendering.colophon.template" => colophon::TEMPLATE,
+            "rendering.also_by.template" => back_matter::ALSO_BY,
+            "rendering.about_author.template" => back_matter::ABOUT_AUTHOR,
+            "sample.template" => back_matter::SAMPLE,
+            "rendering.revision_history.template" => revisions::TEMPLATE,
             _ => {
                 return Err(Error::config_parser(&self.source,
                                                 lformat!("invalid template '{template}'",
@@ -1100,6 +1888,19 @@ impl Book {
                     Error::config_parser(&self.source,
                                          lformat!("file '{file}' could not be read", file = s))
                 })?;
+            let missing = version::missing(template, &res);
+            if !missing.is_empty() {
+                self.logger.warning(lformat!("template '{template}' ({file}) was written for an \
+                                              older version of Crowbook and doesn't use \
+                                              {variables}: some content might not be \
+                                              rendered. Add them to the template, or stamp it \
+                                              with a 'crowbook-template: {version}' comment if \
+                                              they are not needed.",
+                                             template = template,
+                                             file = s,
+                                             variables = missing.join(", "),
+                                             version = version::VERSION));
+            }
             Ok(Cow::Owned(res))
         } else {
             Ok(Cow::Borrowed(fallback))
@@ -1107,16 +1908,103 @@ impl Book {
     }
 
 
-    /// Sets the chapter_template once and for all
+    /// Returns the footnote numbering style (`numbers` or `symbols`) for a format,
+    /// that is, `{format}.footnotes.style` if it is set, and `rendering.footnotes.style`
+    /// else.
+    #[doc(hidden)]
+    pub fn footnote_style(&self, format: &str) -> Result<&str> {
+        let style = self.options.get_str(&format!("{}.footnotes.style", format))
+            .or_else(|_| self.options.get_str("rendering.footnotes.style"))?;
+        match style {
+            "numbers" | "symbols" => Ok(style),
+            _ => Err(Error::config_parser(&self.source,
+                                          lformat!("footnotes style must be either 'numbers' \
+                                                    or 'symbols', found '{style}'",
+                                                   style = style))),
+        }
+    }
+
+    /// Returns where notes must be displayed (`footnotes`, `end_of_chapter`
+    /// or `end_of_book`), according to `rendering.notes`.
+    #[doc(hidden)]
+    pub fn notes_mode(&self) -> Result<&str> {
+        let mode = self.options.get_str("rendering.notes").unwrap();
+        match mode {
+            "footnotes" | "end_of_chapter" | "end_of_book" => Ok(mode),
+            _ => Err(Error::config_parser(&self.source,
+                                          lformat!("rendering.notes must be either 'footnotes', \
+                                                    'end_of_chapter' or 'end_of_book', \
+                                                    found '{mode}'",
+                                                   mode = mode))),
+        }
+    }
+
+    /// Returns where the inline table of contents must be displayed (`start`
+    /// or `end`), or `None` if `rendering.inline_toc` is not set or if
+    /// `rendering.inline_toc.location` is `none`.
+    #[doc(hidden)]
+    pub fn inline_toc_location(&self) -> Result<Option<&str>> {
+        if !self.options.get_bool("rendering.inline_toc").unwrap() {
+            return Ok(None);
+        }
+        let location = self.options.get_str("rendering.inline_toc.location").unwrap();
+        match location {
+            "start" | "end" => Ok(Some(location)),
+            "none" => Ok(None),
+            _ => Err(Error::config_parser(&self.source,
+                                          lformat!("rendering.inline_toc.location must be either \
+                                                    'start', 'end' or 'none', found '{location}'",
+                                                   location = location))),
+        }
+    }
+
+    /// Returns the maximum level of headings listed in tables of contents,
+    /// according to `rendering.toc.depth` (or `rendering.num_depth` if it is
+    /// not set).
+    #[doc(hidden)]
+    pub fn toc_depth(&self) -> i32 {
+        self.options
+            .get_i32("rendering.toc.depth")
+            .unwrap_or_else(|_| self.options.get_i32("rendering.num_depth").unwrap())
+    }
+
+    /// Sets the chapter_template and part_template once and for all
     fn set_chapter_template(&mut self) -> Result<()> {
         let template =
             compile_str(self.options.get_str("rendering.chapter.template").unwrap(),
                         &self.source,
                         "rendering.chapter.template")?;
+        self.chapter_template_metadata = self.uses_metadata(&template);
         self.chapter_template = Some(template);
+        let template =
+            compile_str(self.options.get_str("rendering.part.template").unwrap(),
+                        &self.source,
+                        "rendering.part.template")?;
+        self.part_template_metadata = self.uses_metadata(&template);
+        self.part_template = Some(template);
         Ok(())
     }
 
+    /// Returns true if the output of a compiled template depends on the
+    /// metadata rendered by `get_metadata`'s closure
+    fn uses_metadata(&self, template: &Template) -> bool {
+        let mut variables = vec![String::from("has_also_by")];
+        for key in self.options.get_metadata() {
+            let key = key.replace(".", "_");
+            variables.push(format!("{}_raw", key));
+            variables.push(format!("has_{}", key));
+            variables.push(key);
+        }
+        let variables: Vec<&str> = variables.iter().map(|s| s.as_str()).collect();
+        // `also_by` is a list, so it can't be a placeholder
+        let with_metadata =
+            templates::with_placeholders(self.add_common_metadata(MapBuilder::new()), &variables)
+                .insert_vec("also_by", |builder| builder.push_str("\u{1}"));
+        templates::renders_differently(template,
+                                       self.add_common_metadata(MapBuilder::new()),
+                                       with_metadata)
+    }
+
     /// Returns the formatted (roman or arabic) number of chapter
     #[doc(hidden)]
     pub fn get_header_number(&self, header: Header, n: i32) -> Result<String> {
@@ -1147,7 +2035,15 @@ impl Book {
             Header::Part => "part",
             Header::Chapter => "chapter",
         };
-        let mut data = self.get_metadata(&mut f)?;
+        let uses_metadata = match header {
+            Header::Part => self.part_template.is_none() || self.part_template_metadata,
+            Header::Chapter => self.chapter_template.is_none() || self.chapter_template_metadata,
+        };
+        let mut data = if uses_metadata {
+            self.get_metadata(&mut f)?
+        } else {
+            self.add_common_metadata(MapBuilder::new())
+        };
         if !title.is_empty() {
             data = data.insert_bool(&format!("has_{}_title", header_type),
                                     true);
@@ -1161,9 +2057,9 @@ impl Book {
                                               header_type));
 
         data = data.insert_str(format!("{}_title", header_type),
-                               title.clone())
-            .insert_str(header_type, header_name.clone())
-            .insert_str("number", number.clone());
+                               title.as_str())
+            .insert_str(header_type, header_name.as_str())
+            .insert_str("number", number.as_str());
         let data = data.build();
         let mut res: Vec<u8> = vec![];
 
@@ -1222,10 +2118,6 @@ impl Book {
         where F: FnMut(&str) -> Result<String>
     {
         let mut mapbuilder = MapBuilder::new();
-        mapbuilder = mapbuilder.insert_str("crowbook_version", env!("CARGO_PKG_VERSION"));
-        mapbuilder =
-            mapbuilder.insert_bool(&format!("lang_{}", self.options.get_str("lang").unwrap()),
-                                   true);
 
         // Add metadata to mapbuilder
         for key in self.options.get_metadata() {
@@ -1237,10 +2129,10 @@ impl Book {
                     "lang" => Ok(s.to_string()),
                     _ => f(s),
                 };
-                let raw = view_as_text(&Parser::from(&self).parse(s)?);
                 match content {
                     Ok(content) => {
                         if !content.is_empty() {
+                            let raw = view_as_text(&self.parse_markdown(s)?);
                             mapbuilder = mapbuilder.insert_str(&format!("{}_raw", key), raw);
                             mapbuilder = mapbuilder.insert_str(&key, content);
 
@@ -1262,6 +2154,46 @@ impl Book {
             }
         }
 
+        // Add the list of other books by the author
+        let also_by = self.options.get_str_vec("rendering.also_by").unwrap_or(&[]);
+        let mut rendered = vec![];
+        for title in also_by {
+            rendered.push(f(title)?);
+        }
+        mapbuilder = mapbuilder.insert_bool("has_also_by", !rendered.is_empty())
+            .insert_vec("also_by", |mut builder| {
+                for title in &rendered {
+                    builder = builder.push_str(title.as_str());
+                }
+                builder
+            });
+
+        Ok(self.add_common_metadata(mapbuilder))
+    }
+
+    /// Adds the fields of `get_metadata` that don't need to be rendered: version,
+    /// language, git information and localization strings
+    fn add_common_metadata(&self, mut mapbuilder: MapBuilder) -> MapBuilder {
+        mapbuilder = mapbuilder.insert_str("crowbook_version", env!("CARGO_PKG_VERSION"));
+        mapbuilder =
+            mapbuilder.insert_bool(&format!("lang_{}", self.options.get_str("lang").unwrap()),
+                                   true);
+
+        // Add information about the git repository
+        if let Some(ref git) = self.git {
+            mapbuilder = mapbuilder.insert_bool("has_git", true)
+                .insert_str("git_commit", git.commit.as_str())
+                .insert_str("git_short_commit", git.short_commit.as_str())
+                .insert_str("git_revision", git.revision())
+                .insert_bool("git_dirty", git.dirty)
+                .insert_bool("has_git_tag", git.tag.is_some());
+            if let Some(ref tag) = git.tag {
+                mapbuilder = mapbuilder.insert_str("git_tag", tag.as_str());
+            }
+        } else {
+            mapbuilder = mapbuilder.insert_bool("has_git", false);
+        }
+
         // Add localization strings
         let hash = lang::get_hash(self.options.get_str("lang").unwrap());
         for (key, value) in hash {
@@ -1269,7 +2201,7 @@ impl Book {
             let value = value.as_str().unwrap();
             mapbuilder = mapbuilder.insert_str(&key, value);
         }
-        Ok(mapbuilder)
+        mapbuilder
     }
 
     /// Remove YAML blocks from a string and try to parse them to set options
@@ -1280,32 +2212,78 @@ impl Book {
     /// ---
     /// or
     /// ...
-    fn parse_yaml(&mut self, content: &mut String) {
-        if !(content.starts_with("---\n") || content.contains("\n---\n") ||
-             content.starts_with("---\r\n") || content.contains("\n---\r\n")) {
+    ///
+    /// Returns the chapter-specific metadata (e.g. `epigraph`) found in these
+    /// blocks, which are not book options and are thus read even if
+    /// `input.yaml_blocks` is not set.
+    ///
+    /// If `front_matter_only` is set (for formats where `---` lines can mean
+    /// something else, such as reStructuredText titles), only a block at the
+    /// start of the content is read.
+    fn parse_yaml(&mut self, content: &mut String, front_matter_only: bool) -> HashMap<String, String> {
+        let mut metadata = HashMap::new();
+        let starts_with_yaml = content.starts_with("---\n") || content.starts_with("---\r\n");
+        if !(starts_with_yaml ||
+             (!front_matter_only &&
+              (content.contains("\n---\n") || content.contains("\n---\r\n")))) {
             // Content can't contain YAML, so aborting early
-            return;
+            return metadata;
         }
         let mut new_content = String::new();
         let mut previous_empty = true;
         {
             let mut lines = content.lines();
             while let Some(line) = lines.next() {
-                if line == "---" && previous_empty {
+                if line == "---" && previous_empty &&
+                   (!front_matter_only || new_content.is_empty()) {
                     previous_empty = false;
                     let mut yaml_block = String::new();
                     let mut valid_block = false;
+                    let mut closing = None;
                     while let Some(new_line) = lines.next() {
                         if new_line == "---" || new_line == "..." {
+                            closing = Some(new_line);
                             // Checks that this is valid YAML
                             match YamlLoader::load_from_str(&yaml_block) {
                                 Ok(docs) => {
+                                    // Read chapter-specific metadata
+                                    if docs.len() == 1 && docs[0].as_hash().is_some() {
+                                        let hash = docs[0].as_hash().unwrap();
+                                        for &key in CHAPTER_METADATA {
+                                            match hash.get(&Yaml::String(key.to_owned())) {
+                                                Some(&Yaml::String(ref value)) => {
+                                                    metadata.insert(key.to_owned(), value.clone());
+                                                }
+                                                Some(&Yaml::Boolean(value)) => {
+                                                    metadata.insert(key.to_owned(), value.to_string());
+                                                }
+                                                Some(&Yaml::Integer(value)) => {
+                                                    metadata.insert(key.to_owned(), value.to_string());
+                                                }
+                                                Some(value) => {
+                                                    self.logger
+                                                        .error(lformat!("Inline YAML block: \
+                                                                         {} must be a \
+                                                                         string, a number or \
+                                                                         a boolean, found {:?}",
+                                                                        key,
+                                                                        value))
+                                                }
+                                                None => (),
+                                            }
+                                        }
+                                    }
                                     // Use this yaml block to set options only if 1) it is valid
                                     // 2) the option is activated
                                     if docs.len() == 1 && docs[0].as_hash().is_some() &&
                                        self.options.get_bool("input.yaml_blocks") == Ok(true) {
                                         let hash = docs[0].as_hash().unwrap();
                                         for (key, value) in hash {
+                                            if let Yaml::String(ref key) = *key {
+                                                if CHAPTER_METADATA.contains(&key.as_str()) {
+                                                    continue;
+                                                }
+                                            }
                                             match self.options
                                                 //todo: remove clone
                                                 .set_yaml(key.clone(), value.clone()) {
@@ -1342,7 +2320,9 @@ impl Book {
                                                                     block:\n---\n{block}---",
                                                                    block = &yaml_block));
                                     }
-                                    valid_block = true;
+                                    // Anything else than a hash (e.g. the text
+                                    // between two rules) is not a YAML block
+                                    valid_block = docs.len() == 1 && docs[0].as_hash().is_some();
                                 }
                                 Err(err) => {
                                     self.logger
@@ -1363,9 +2343,14 @@ impl Book {
                         }
                     }
                     if !valid_block {
-                        // Block was invalid, so add it to markdown content
+                        // Block was invalid, so add it to markdown content,
+                        // with its delimiters (which can be rules)
+                        new_content.push_str("---\n");
                         new_content.push_str(&yaml_block);
-                        new_content.push_str("\n");
+                        if let Some(closing) = closing {
+                            new_content.push_str(closing);
+                            new_content.push_str("\n");
+                        }
                     }
                 } else if line.is_empty() {
                     previous_empty = true;
@@ -1380,27 +2365,61 @@ impl Book {
         *content = new_content;
         self.update_cleaner();
         self.init_checker();
+        metadata
     }
 
 
-    // Update the cleaner according to autoclean and lang options
+    // Update the cleaner according to lang and input.clean options
     fn update_cleaner(&mut self) {
+        let mut dash_spacing = self.options.get_str("input.clean.dashes.spacing").unwrap().to_owned();
+        if !["keep", "none", "spaces", "thin"].contains(&dash_spacing.as_str()) {
+            self.logger.warning(lformat!("input.clean.dashes.spacing set to '{value}', expected \
+                                          'keep', 'none', 'spaces' or 'thin'; ignoring it",
+                                         value = dash_spacing));
+            dash_spacing = String::from("keep");
+        }
+        let writing_mode = self.options.get_str("writing_mode").unwrap();
+        if !["horizontal-tb", "vertical-rl"].contains(&writing_mode) {
+            self.logger.warning(lformat!("writing_mode set to '{value}', expected \
+                                          'horizontal-tb' or 'vertical-rl'; ignoring it",
+                                         value = writing_mode));
+        }
+        let lang = self.options.get_str("lang").unwrap().to_lowercase();
+        let mut apostrophes: Vec<String> = cleaner::default_apostrophes(&lang)
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        if let Ok(words) = self.options.get_str_vec("input.clean.apostrophes") {
+            apostrophes.extend(words.iter().cloned());
+        }
         let params = CleanerParams {
             smart_quotes: self.options.get_bool("input.clean.smart_quotes").unwrap(),
             ligature_dashes: self.options.get_bool("input.clean.ligature.dashes").unwrap(),
             ligature_guillemets: self.options.get_bool("input.clean.ligature.guillemets").unwrap(),
-        };
-        if self.options.get_bool("input.clean").unwrap() {
-            let lang = self.options.get_str("lang").unwrap().to_lowercase();
-            let cleaner: Box<Cleaner> = if lang.starts_with("fr") {
-                Box::new(French::new(params))
+            ellipsis: self.options.get_bool("input.clean.ellipsis").unwrap(),
+            dash_spacing: dash_spacing,
+            typewriter: self.options.get_bool("input.clean.typewriter").unwrap(),
+            apostrophes: apostrophes,
+            numbers: if self.options.get_bool("input.clean.numbers").unwrap() {
+                let mut format = NumberFormat::new(&lang);
+                if let Ok(separator) = self.options.get_str("input.clean.numbers.separator") {
+                    format.thousands = separator.to_owned();
+                }
+                Some(format)
             } else {
-                Box::new(Default::new(params))
-            };
-            self.cleaner = cleaner;
+                None
+            },
+            vertical: writing_mode == "vertical-rl",
+        };
+        // The cleaner is set even if input.clean is false, since it can be
+        // enabled for some formats with {format}.clean
+        let cleaner: Box<Cleaner> = if lang.starts_with("fr") {
+            Box::new(French::new(params))
         } else {
-            self.cleaner = Box::new(Off);
-        }
+            Box::new(Default::new(params))
+        };
+        self.cleaner = cleaner;
+        self.autoclean = self.options.get_bool("input.clean").unwrap();
     }
 }
 
@@ -1418,3 +2437,21 @@ pub fn compile_str<O>(template: &str, source: O, template_name: &str) -> Result<
                                                          error = err))),
     }
 }
+
+/// Wrapper around a `Write` object counting the bytes written to it
+struct CountingWriter<'a, T: Write + 'a> {
+    inner: &'a mut T,
+    count: usize,
+}
+
+impl<'a, T: Write> Write for CountingWriter<'a, T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count += n;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
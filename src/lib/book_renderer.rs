@@ -17,9 +17,10 @@
 
 use error::{Error, Result, Source};
 use book::Book;
-use std::io::Write;
-use std::fs::File;
-use std::path::Path;
+use std::io::{Write, BufWriter};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 /// Thait that must be implemented by the various renderers to render a whole book.
 
@@ -35,18 +36,36 @@ pub trait BookRenderer: Sync {
 
     /// Render the book to a given file.
     ///
-    /// The default implementation creates a file and calls `render` to write to it,
-    /// but in some cases it might be useful to override it.
+    /// The default implementation calls `render` to write to a temporary file
+    /// next to `path`, which is renamed to `path` once rendering succeeds (so
+    /// no partial file is left if it fails). In some cases it might be useful
+    /// to override it.
     fn render_to_file(&self, book: &Book, path: &Path) -> Result<()> {
-        // Not optimal but avoid creating an empty file if it fails
-        let mut content = vec![];
-        self.render(book, &mut content)?;
-        let mut file = File::create(path)
+        let file_name = path.file_name()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let tmp_path = path.with_file_name(format!(".{}.crowbook-tmp", file_name));
+        let file = File::create(&tmp_path)
             .map_err(|err| Error::default(Source::empty(),
                                     lformat!("could not create file '{file}': {err}",
-                                             file = path.display(),
+                                             file = tmp_path.display(),
                                              err = err)))?;
-        file.write_all(&content)
+        let result = {
+            let mut writer = BufWriter::new(file);
+            self.render(book, &mut writer)
+                .and_then(|_| {
+                    writer.flush()
+                        .map_err(|err| Error::default(Source::empty(),
+                                                      lformat!("could not write book content to file '{file}': {err}",
+                                                               file = path.display(),
+                                                               err = err)))
+                })
+        };
+        if let Err(err) = result {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(err);
+        }
+        fs::rename(&tmp_path, path)
             .map_err(|err| Error::default(Source::empty(),
                                           lformat!("could not write book content to file '{file}': {err}",
                                                    file = path.display(),
@@ -54,3 +73,28 @@ pub trait BookRenderer: Sync {
         Ok(())
     }
 }
+
+/// Result of the rendering of a book to a format
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderResult {
+    /// Name of the format (e.g. "epub")
+    pub format: String,
+    /// Where the result was written
+    pub output: RenderOutput,
+    /// Time spent rendering
+    pub duration: Duration,
+    /// Warnings logged during rendering
+    pub warnings: Vec<String>,
+    /// Features used in the book that the format can't express, with the way
+    /// they were rendered instead
+    pub degraded: Vec<String>,
+}
+
+/// Where the result of a rendering was written
+#[derive(Debug, Clone, PartialEq)]
+pub enum RenderOutput {
+    /// A file (or a directory, for formats producing multiple files)
+    File(PathBuf),
+    /// A `Write` object, with the number of bytes written
+    Bytes(usize),
+}
@@ -1,12 +1,13 @@
 use error::{Error, Result, Source};
 use bookoption::BookOption;
 use book::Book;
-use logger::{Logger};
+use misc;
 
 use yaml_rust::{Yaml, YamlLoader};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::{PathBuf, Path};
 use std::env;
+use std::mem;
 
 lazy_static! {
     static ref OPTIONS: String = format!("\
@@ -23,6 +24,10 @@ subtitle:meta                       # {subtitle}
 license:meta                        # {license}
 version:meta                        # {version}
 date:meta                           # {date}
+identifier:str                      # {identifier}
+author.bio:meta                     # {author_bio}
+author.photo:str                    # {author_photo}
+writing_mode:str:horizontal-tb      # {writing_mode}
 
 # {output_opt}
 output:strvec                       # {output}
@@ -31,8 +36,18 @@ output.html:path                    # {output_html}
 output.html.dir:path                # {output_html_dir}
 output.tex:path                     # {output_tex}
 output.pdf:path                     # {output_pdf}
+output.pdf.cover:path               # {output_pdf_cover}
 output.odt:path                     # {output_odt}
+output.mobi:path                    # {output_mobi}
+output.docbook:path                 # {output_docbook}
+output.txt:path                     # {output_txt}
+output.md:path                      # {output_md}
+output.ast:path                     # {output_ast}
+output.slides:path                  # {output_slides}
 output.html.if:path                 # {output_if}
+output.manifest:path                # {output_manifest}
+output.calibre:bool:false           # {output_calibre}
+output.versioned:bool:false         # {output_versioned}
 output.base_path:path:\"\"            # {output_base_path}
 
 # {render_opt}
@@ -41,7 +56,10 @@ rendering.highlight.theme:str:InspiredGitHub                         # {renderin
 rendering.initials:bool:false                                        # {rendering_initials}
 rendering.inline_toc:bool:false                                      # {inline_toc}
 rendering.inline_toc.name:str:\"{{{{{{loc_toc}}}}}}\"                        # {toc_name}
+rendering.inline_toc.location:str:start                              # {toc_location}
 rendering.num_depth:int:1                                            # {num_depth}
+rendering.toc.depth:int                                              # {toc_depth}
+rendering.headings.case:str                                          # {headings_case}
 rendering.chapter:str                                                # {chapter}
 rendering.part:str                                                   # {part}
 rendering.chapter.roman_numerals:bool:false                                  # {roman_numerals_chapters}
@@ -50,12 +68,51 @@ rendering.part.reset_counter:bool:true                                      # {r
 rendering.chapter.template:str:\"{{{{{{number}}}}}}. {{{{{{chapter_title}}}}}}\" # {chapter_template}
 
 rendering.part.template:str:\"{{{{{{number}}}}}}. {{{{{{part_title}}}}}}\" # {part_template}
+rendering.scene_break:str:asterisks                                  # {scene_break}
+rendering.scene_break.ornament:path                                  # {scene_break_ornament}
+rendering.colophon:bool:false                                        # {colophon}
+rendering.colophon.template:tpl                                      # {colophon_tmpl}
+rendering.colophon.fonts:str                                         # {colophon_fonts}
+rendering.revision_history:bool:false                                # {revision_history}
+rendering.revision_history.template:tpl                              # {revision_history_tmpl}
+rendering.revision_history.max:int:20                                # {revision_history_max}
+rendering.front_matter.half_title:bool:true                          # {front_half_title}
+rendering.front_matter.title_page:bool:true                          # {front_title_page}
+rendering.front_matter.copyright:bool:true                           # {front_copyright}
+rendering.also_by:strvec                                             # {also_by}
+rendering.also_by.page:bool:false                                    # {also_by_page}
+rendering.also_by.template:tpl                                       # {also_by_tmpl}
+rendering.about_author:bool:false                                    # {about_author}
+rendering.about_author.template:tpl                                  # {about_author_tmpl}
+rendering.exercises.answers:str:end                                  # {exercises_answers}
+editions:strvec                                                      # {editions}
+sample:str                                                           # {sample}
+sample.url:str                                                       # {sample_url}
+sample.template:tpl                                                  # {sample_tmpl}
+watermark:str                                                        # {watermark}
+watermark.pdf:str:diagonal                                           # {watermark_pdf}
+rendering.footnotes.style:str:numbers                                # {footnotes_style}
+rendering.footnotes.reset:bool                                       # {footnotes_reset}
+rendering.notes:str:footnotes                                        # {notes}
+rendering.list_of_listings:bool:false                                # {list_of_listings}
+rendering.figures:bool:false                                         # {figures}
+rendering.list_of_figures:bool:false                                 # {list_of_figures}
+rendering.list_of_tables:bool:false                                  # {list_of_tables}
+rendering.acronyms.expand:bool:true                                  # {acronyms_expand}
+rendering.list_of_acronyms:bool:false                                # {list_of_acronyms}
+rendering.links.appendix:bool:false                                  # {links_appendix}
+rendering.diagrams:bool:false                                        # {diagrams}
+rendering.diagrams.format:str:png                                    # {diagrams_format}
+rendering.diagrams.dot:str:\"dot -T{{format}} -o {{output}} {{input}}\"       # {diagrams_dot}
+rendering.diagrams.mermaid:str:\"mmdc -i {{input}} -o {{output}}\"           # {diagrams_mermaid}
 
 
 
 
 # {special_ops}
 import:path                  # {import_config}
+inherits:strvec              # {inherits}
+strict:bool:false            # {strict}
 
 # {html_opt}
 html.icon:path                      # {html_icon}
@@ -70,18 +127,31 @@ html.css.print:tpl                  # {css_print}
 html.highlight.js:tpl               # {highlight_js}
 html.highlight.css:tpl              # {highlight_css}
 html.side_notes:bool:false          # {side_notes}
+html.footnotes.style:str            # {html_footnotes_style}
+html.clean:bool                     # {html_clean}
 html.escape_nb_spaces:bool:true     # {nb_spaces}
+html.minify:bool:false              # {html_minify}
 html.chapter.template:str:\"<h1 id = 'link-{{{{{{link}}}}}}'>{{{{#has_number}}}}<span class = 'chapter-header'>{{{{{{header}}}}}} {{{{{{number}}}}}}</span>{{{{#has_title}}}}<br />{{{{/has_title}}}}{{{{/has_number}}}}{{{{{{title}}}}}}</h1>\" # {html_chapter_template}
 html.part.template:str:\"<h2 class = 'part'>{{{{{{header}}}}}} {{{{{{number}}}}}}</h2> <h1 id = 'link-{{{{{{link}}}}}}' class = 'part'>{{{{{{title}}}}}}</h1>\" # {html_part_template}
 
 # {html_single_opt}
 html.standalone.template:tpl                # {single_html}
 html.standalone.one_chapter:bool:false  # {one_chapter}
+html.standalone.reader_mode:bool:false  # {reader_mode}
+html.standalone.self_contained:bool:false # {self_contained}
 html.standalone.js:tpl                  # {single_js}
 
 
 # {html_dir_opt}
 html.dir.template:tpl               # {html_dir_template}
+html.dir.images.srcset:str          # {html_dir_srcset}
+html.dir.images.sizes:str:100vw     # {html_dir_sizes}
+html.dir.images.resize:str:\"convert {{input}} -resize {{width}}x> {{output}}\" # {html_dir_resize}
+html.dir.images.inline:int:0        # {html_dir_inline}
+html.dir.serial:bool:false          # {html_dir_serial}
+html.dir.feed:str                   # {html_dir_feed}
+html.dir.summaries:bool:false       # {html_dir_summaries}
+html.dir.fingerprint:bool:false     # {html_dir_fingerprint}
 
 # {html_if_opt}
 html.if.js:tpl                      # {if_js}
@@ -89,19 +159,45 @@ html.if.new_turn:str               # {if_new_turn}
 html.if.end_turn:str                # {if_end_turn}
 html.if.new_game:tpl                # {if_new_game}
 
+# {slides_opt}
+slides.template:tpl                 # {slides_template}
+slides.theme:str:white              # {slides_theme}
+slides.reveal_url:str:\"https://cdn.jsdelivr.net/npm/reveal.js@4\" # {slides_reveal_url}
+
 # {epub_opt}
 epub.version:int:2                  # {epub_ver}
+epub.footnotes.style:str            # {epub_footnotes_style}
+epub.clean:bool                     # {epub_clean}
 epub.highlight.theme:str            # {epub_theme}
 epub.css:tpl                        # {epub_css}
 epub.css.add:str                    # {epub_css_add}
 epub.chapter.xhtml:tpl              # {chapter_xhtml}
 epub.toc.extras:bool:true           # {epub_toc}
+epub.page_list:bool:true            # {epub_page_list}
+epub.flavor:str:standard            # {epub_flavor}
 epub.escape_nb_spaces:bool:true     # {nb_spaces}
+epub.newsletter.url:str             # {epub_newsletter_url}
+epub.newsletter.text:str            # {epub_newsletter_text}
+epub.size.warning:float:10.0        # {epub_size_warning}
+mobi.command:str:\"ebook-convert {{input}} {{output}}\" # {mobi_command}
+
+# {odt_opt}
+odt.styles.emphasis:str             # {odt_styles_emphasis}
+odt.styles.strong:str               # {odt_styles_strong}
+odt.styles.code:str                 # {odt_styles_code}
+odt.styles.superscript:str          # {odt_styles_superscript}
+odt.styles.subscript:str            # {odt_styles_subscript}
+odt.styles.abbreviation:str         # {odt_styles_abbreviation}
+odt.clean:bool                      # {odt_clean}
 
 # {tex_opt}
 tex.highlight.theme:str             # {tex_theme}
 tex.links_as_footnotes:bool:true    # {tex_links}
+tex.links.qrcode:bool:false         # {tex_qrcode}
 tex.command:str:xelatex             # {tex_command}
+pdf.engine:str:latex                # {pdf_engine}
+pdf.libreoffice.command:str:soffice # {pdf_libreoffice_command}
+pdf.clean:bool                      # {pdf_clean}
 tex.template:tpl                    # {tex_tmpl}
 tex.template.add:str                # {tex_tmpl_add}
 tex.class:str:book                  # {tex_class}
@@ -111,9 +207,26 @@ tex.margin.right:str                # {tex_margin_right}
 tex.margin.top:str:\"2cm\"          # {tex_margin_top}
 tex.margin.bottom:str:\"1.5cm\"     # {tex_margin_bottom}
 tex.title:bool:true                 # {tex_title}
+tex.front_matter.template:tpl       # {tex_front_matter_tmpl}
 tex.font.size:int                   # {tex_font_size}
 tex.hyperref:bool:true              # {tex_hyperref}
 tex.stdpage:bool:false              # {tex_stdpage}
+tex.chapter.open_recto:bool         # {tex_open_recto}
+tex.blank_pages.empty:bool:true     # {tex_blank_pages}
+tex.bleed:str                       # {tex_bleed}
+tex.crop_marks:bool:false           # {tex_crop_marks}
+tex.grayscale:bool:false            # {tex_grayscale}
+tex.footnotes.style:str             # {tex_footnotes_style}
+tex.clean:bool                      # {tex_clean}
+tex.cover.template:tpl              # {tex_cover_tmpl}
+tex.cover.pages:int                 # {tex_cover_pages}
+tex.cover.paper:str:white           # {tex_cover_paper}
+tex.cover.paper.thickness:float     # {tex_cover_thickness}
+
+# {txt_opt}
+txt.width:int:72                    # {txt_width}
+md.clean:bool                       # {md_clean}
+docbook.clean:bool                  # {docbook_clean}
 
 
 # {rs_opt}
@@ -125,12 +238,40 @@ resources.base_path.images:path:.    # {rs_img}
 resources.base_path.files:path:.     # {rs_base_files}
 resources.base_path.templates:path:. # {rs_tmpl}
 
+# {publish_opt}
+publish.base_url:str                # {publish_base_url}
+publish.s3:str                      # {publish_s3}
+publish.s3.command:str:\"aws s3 sync {{dir}} {{target}}\"   # {publish_s3_command}
+publish.sftp:str                    # {publish_sftp}
+publish.sftp.command:str:\"scp -r {{dir}}/. {{target}}\"    # {publish_sftp_command}
+publish.ftp:str                     # {publish_ftp}
+publish.ftp.command:str:\"curl --silent --show-error --netrc-optional --ftp-create-dirs -T {{file}} {{url}}\" # {publish_ftp_command}
+publish.git:str                     # {publish_git}
+publish.git.remote:str:origin       # {publish_git_remote}
+
+# {retail_opt}
+retail.isbn:str                     # {retail_isbn}
+retail.publisher:str                # {retail_publisher}
+retail.keywords:strvec              # {retail_keywords}
+retail.categories:strvec            # {retail_categories}
+
 # {input_opt}
 input.clean:bool:true               # {autoclean}
 input.clean.smart_quotes:bool:true  # {smart_quotes}
 input.clean.ligature.dashes:bool:false # {ligature_dashes}
 input.clean.ligature.guillemets:bool:false # {ligature_guillemets}
+input.clean.dialogue:str            # {clean_dialogue}
+input.clean.ellipsis:bool:false     # {clean_ellipsis}
+input.clean.dashes.spacing:str:keep # {clean_dashes_spacing}
+input.clean.typewriter:bool:false   # {clean_typewriter}
+input.clean.apostrophes:strvec      # {clean_apostrophes}
+input.clean.numbers:bool:false      # {clean_numbers}
+input.clean.numbers.separator:str   # {clean_numbers_separator}
 input.yaml_blocks:bool:false        # {yaml}
+input.chapters:str:file             # {input_chapters}
+input.page_breaks.pdf:path          # {page_breaks_pdf}
+input.page_breaks.first:int:1       # {page_breaks_first}
+input.page_breaks.command:str:\"pdftotext -enc UTF-8 {{input}} {{output}}\" # {page_breaks_command}
 
 
 # {crowbook_opt}
@@ -210,9 +351,16 @@ crowbook.verbose:alias                              # {removed}
                                          html_single_opt = lformat!("Standalone HTML options"),
                                          html_dir_opt = lformat!("Multifile HTML options"),
                                          html_if_opt = lformat!("Interactive fiction HTML options"),
+                                         slides_opt = lformat!("Slides options"),
+                                         slides_template = lformat!("Path of an HTML template for slides"),
+                                         slides_theme = lformat!("Theme of the reveal.js slides (e.g. white, black, serif, simple)"),
+                                         slides_reveal_url = lformat!("URL of reveal.js, from which its scripts, stylesheets and themes are loaded"),
                                          epub_opt = lformat!("EPUB options"),
                                          tex_opt = lformat!("LaTeX options"),
+                                         txt_opt = lformat!("Plain text, Markdown and DocBook options"),
                                          rs_opt = lformat!("Resources option"),
+                                         publish_opt = lformat!("Publishing options"),
+                                         retail_opt = lformat!("Retail metadata options"),
                                          input_opt = lformat!("Input options"),
                                          crowbook_opt = lformat!("Crowbook options"),
                                          prf_opt = lformat!("Output options (for proofreading)"),
@@ -224,28 +372,45 @@ crowbook.verbose:alias                              # {removed}
                                          lang = lformat!("Language of the book"),
                                          subject = lformat!("Subject of the book (used for EPUB metadata)"),
                                          description = lformat!("Description of the book (used for EPUB metadata)"),
+                                         identifier = lformat!("Unique identifier of the book, e.g. a UUID, used in OPDS catalogs, feeds, ONIX records and Calibre libraries (by default, the ISBN or a hash of the title and author)"),
                                          cover = lformat!("Path to the cover of the book"),
 
                                          subtitle = lformat!("Subtitle of the book"),
                                          license = lformat!("License of the book. This information will be displayed on PDF documents"),
                                          version = lformat!("Version of the book"),
                                          date = lformat!("Date the book was revised"),
+                                         author_bio = lformat!("Biography of the author, displayed on the 'About the author' page"),
+                                         author_photo = lformat!("Photo of the author, displayed on the 'About the author' page (relative to resources.base_path.images)"),
+                                         writing_mode = lformat!("Writing mode of the book: 'horizontal-tb' or 'vertical-rl' (vertical text read from right to left, e.g. for Japanese fiction in EPUB)"),
                                          
                                          output_epub = lformat!("Output file name for EPUB rendering"),
                                          output_html = lformat!("Output file name for HTML rendering"),
                                          output_tex = lformat!("Output file name for LaTeX rendering"),
                                          output_pdf = lformat!("Output file name for PDF rendering"),
+                                         output_pdf_cover = lformat!("Output file name for the print cover (PDF)"),
                                          output_odt = lformat!("Output file name for ODT rendering"),
+                                         output_mobi = lformat!("Output file name for Mobi (Kindle) rendering"),
+                                         output_docbook = lformat!("Output file name for DocBook rendering"),
+                                         output_txt = lformat!("Output file name for plain text rendering"),
+                                         output_md = lformat!("Output file name for Markdown rendering (a single, cleaned CommonMark file)"),
+                                         output_slides = lformat!("Output file name for a reveal.js slide deck (chapters and their sections as slides)"),
+                                         output_ast = lformat!("Output file name for the JSON dump of the parsed book (metadata and tokens of its chapters)"),
                                          output_if = lformat!("Output file name for HTML (interactive fiction) rendering"),
+                                         output_versioned = lformat!("If set to true, the version of the book is appended to output file names, e.g. 'my_book-1.3.0.epub'"),
+                                         output_manifest = lformat!("Output file name for a JSON manifest listing the generated files, with their size and SHA-256 checksum"),
+                                         output_calibre = lformat!("If set to true, writes a metadata.opf file and the cover of the book in output.base_path, for Calibre and other library managers"),
                                          output_html_dir = lformat!("Output directory name for HTML rendering"),
                                          output_base_path = lformat!("Directory where those output files will we written"),
 
                                          rendering_highlight = lformat!("If/how highligh code blocks. Possible values: \"syntect\" (default, performed at runtime), \"highlight.js\" (HTML-only, uses Javascript), \"none\""),
                                          rendering_highlight_theme = lformat!("Theme for syntax highlighting (if rendering.highlight is set to 'syntect')"),
-                                         rendering_initials = lformat!("Use initials ('lettrines') for first letter of a chapter (experimental)"),
+                                         rendering_initials = lformat!("Use initials ('lettrines', or drop caps) for first letter of a chapter (experimental)"),
                                          inline_toc = lformat!("Display a table of content in the document"),
                                          toc_name = lformat!("Name of the table of contents if it is displayed in document"),
+                                         toc_location = lformat!("Where the table of contents is displayed in the document: 'start', 'end' or 'none'"),
                                          num_depth = lformat!("The  maximum heading levels that should be numbered (0: no numbering, 1: only chapters, ..., 6: all)"),
+                                         headings_case = lformat!("Normalizes the capitalization of headings: 'title' (title case) or 'sentence' (sentence case)"),
+                                         toc_depth = lformat!("The maximum heading levels that are listed in tables of contents (default: same as rendering.num_depth)"),
                                          part = lformat!("How to call parts (or 'books', 'episodes', ..."),
                                          chapter = lformat!("How to call chapters"),
                                          chapter_template = lformat!("Naming scheme of chapters, for TOC"),
@@ -253,8 +418,47 @@ crowbook.verbose:alias                              # {removed}
                                          roman_numerals_parts = lformat!("If set to true, display part number with roman numerals"),
                                          roman_numerals_chapters = lformat!("If set to true, display chapter number with roman numerals"),
                                          reset_counter = lformat!("If set to true, reset chapter number at each part"),
+                                         scene_break = lformat!("How to render scene breaks (horizontal rules in Markdown): 'asterisks' ('***'), 'asterism' ('⁂'), 'blank' (an empty line) or 'ornament' (an image)"),
+                                         scene_break_ornament = lformat!("Path to the image used for scene breaks if rendering.scene_break is set to 'ornament'"),
+                                         colophon = lformat!("Add a colophon (with Crowbook version, build date, ...) at the end of the book"),
+                                         colophon_tmpl = lformat!("Path of a Markdown template for the colophon"),
+                                         colophon_fonts = lformat!("Fonts used in the book, listed in the colophon"),
+                                         revision_history = lformat!("Add a chapter listing the git commits that modified the book's chapters at the end of the book"),
+                                         revision_history_tmpl = lformat!("Path of a Markdown template for the revision history"),
+                                         revision_history_max = lformat!("Maximum number of commits listed in the revision history"),
+                                         front_half_title = lformat!("Add a half title page (with only the title) at the beginning of LaTeX/PDF and ODT documents"),
+                                         front_title_page = lformat!("Add a title page (with title, subtitle, author and date) at the beginning of LaTeX/PDF and ODT documents"),
+                                         front_copyright = lformat!("Add a copyright page (with title, version, author and license) at the beginning of LaTeX/PDF and ODT documents"),
+                                         also_by = lformat!("List of other books by the author (in Markdown, so they can contain links and cover images), displayed on 'Also by' pages"),
+                                         also_by_page = lformat!("Add an 'Also by this author' chapter, listing rendering.also_by, at the end of the book"),
+                                         also_by_tmpl = lformat!("Path of a Markdown template for the 'Also by this author' chapter"),
+                                         about_author = lformat!("Add an 'About the author' chapter, with author.bio and author.photo, at the end of the book"),
+                                         about_author_tmpl = lformat!("Path of a Markdown template for the 'About the author' chapter"),
+                                         exercises_answers = lformat!("Where to display the answers of exercises: 'end' (in an 'Answers' chapter at the end of the book), 'inline' (after each exercise, hidden by default) or 'none'"),
+                                         editions = lformat!("If set, only include the chapters and blocks that are untagged or tagged with one of these editions (e.g. [standard])"),
+                                         sample = lformat!("If set, only render a sample of the book: its first chapters (e.g. '3') or a percentage of its words (e.g. '10%'), followed by a 'Buy the full book' page. '-sample' is appended to output file names"),
+                                         sample_url = lformat!("URL where the full book can be bought, linked to at the end of a sample"),
+                                         sample_tmpl = lformat!("Path of a Markdown template for the page at the end of a sample"),
+                                         watermark = lformat!("If set, a notice (e.g. 'Review copy for Jane Doe') stamped at the end of each chapter in EPUB and on each page in PDF, to personalize copies"),
+                                         watermark_pdf = lformat!("How the watermark is displayed in PDF: 'diagonal' (across the page) or 'footer'"),
+                                         footnotes_style = lformat!("Numbering style of footnotes: 'numbers' or 'symbols' (*, †, ‡, ...)"),
+                                         notes = lformat!("Where to display notes: 'footnotes' (default behaviour of each format), 'end_of_chapter' or 'end_of_book'"),
+                                         list_of_listings = lformat!("Add a list of the numbered code listings at the beginning of the book"),
+                                         figures = lformat!("If set to true, standalone images with a title are displayed as numbered figures, with a caption"),
+                                         list_of_figures = lformat!("Add a list of the numbered figures at the beginning of the book"),
+                                         list_of_tables = lformat!("Add a list of the numbered tables at the beginning of the book"),
+                                         acronyms_expand = lformat!("If set to true, the first use of each acronym (defined with acronyms.* keys) in a chapter is expanded"),
+                                         list_of_acronyms = lformat!("Add a list of the acronyms at the end of the book"),
+                                         links_appendix = lformat!("If set to true, external links are numbered and listed at the end of the book in PDF and ODT output, since they can't be clicked on paper"),
+                                         diagrams = lformat!("If set to true, code blocks whose language is 'dot' or 'mermaid' are rendered as images at build time"),
+                                         diagrams_format = lformat!("Image format of rendered diagrams"),
+                                         diagrams_dot = lformat!("Command used to render Graphviz diagrams"),
+                                         diagrams_mermaid = lformat!("Command used to render Mermaid diagrams"),
+                                         footnotes_reset = lformat!("If set to true, footnote numbering starts again at each chapter; if set to false, it is continuous. Default depends on the output format"),
                                          
                                          import_config = lformat!("Import another book configuration file"),
+                                         inherits = lformat!("Base configuration file(s) whose options are inherited, the options set by this book overriding them"),
+                                         strict = lformat!("If set to true, warnings (broken links, non-local images, unknown options in YAML blocks, ...) fail the build"),
 
                                          html_icon = lformat!("Path to an icon to be used for the HTML files(s)"),
                                          html_header = lformat!("Custom header to display at the beginning of html file(s)"),
@@ -267,9 +471,26 @@ crowbook.verbose:alias                              # {removed}
                                          highlight_js = lformat!("Set another highlight.js version than the bundled one"),
                                          highlight_css = lformat!("Set another highlight.js CSS theme than the default one"),
                                          side_notes = lformat!("Display footnotes as side notes in HTML/Epub (experimental)"),
+                                         html_footnotes_style = lformat!("Numbering style of footnotes for HTML, overriding rendering.footnotes.style"),
+                                         html_clean = lformat!("Toggle typographic cleaning for HTML, overriding input.clean"),
                                          nb_spaces = lformat!("Replace unicode non breaking spaces with HTML entities and CSS"),
+                                         html_minify = lformat!("If set to true, minifies the generated HTML and CSS"),
+                                         epub_newsletter_url = lformat!("URL of a newsletter sign-up form: if set, adds a page inviting readers to subscribe at the end of EPUB files"),
+                                         epub_newsletter_text = lformat!("Text (in Markdown) of the newsletter page of EPUB files"),
+                                         epub_size_warning = lformat!("Size, in megabytes, above which a warning is displayed when rendering an EPUB file, as some stores charge delivery fees or have lower royalties for big files (0 to disable)"),
+                                         mobi_command = lformat!("Command used to convert the EPUB file to Mobi"),
+                                         odt_opt = lformat!("ODT options"),
+                                         odt_styles_emphasis = lformat!("Name of the character style used for emphasis in ODT output, instead of direct formatting"),
+                                         odt_styles_strong = lformat!("Name of the character style used for strong emphasis in ODT output, instead of direct formatting"),
+                                         odt_styles_code = lformat!("Name of the character style used for inline code in ODT output"),
+                                         odt_styles_superscript = lformat!("Name of the character style used for superscript in ODT output"),
+                                         odt_styles_subscript = lformat!("Name of the character style used for subscript in ODT output"),
+                                         odt_styles_abbreviation = lformat!("Name of the character style used for acronyms in ODT output (e.g. a small caps style)"),
+                                         odt_clean = lformat!("Toggle typographic cleaning for ODT (and PDF with pdf.engine set to 'libreoffice'), overriding input.clean"),
                                          
                                          one_chapter = lformat!("Display only one chapter at a time (with a button to display all)"),
+                                         reader_mode = lformat!("Add a toolbar allowing readers to choose the font, its size, the line width and the justification of the text (remembered by their browser)"),
+                                         self_contained = lformat!("Also display the cover and embed the files referenced by the stylesheet (fonts, background images, ...) as data URIs, so the HTML file can be shared on its own"),
                                          single_html = lformat!("Path of an HTML template for standalone HTML"),
                                          single_js = lformat!("Path of a javascript file"),
                                          if_js = lformat!("Path of a javascript file"),
@@ -280,19 +501,36 @@ crowbook.verbose:alias                              # {removed}
                                          html_chapter_template = lformat!("Inline template for HTML chapter formatting"),
                                          html_part_template = lformat!("Inline template for HTML part formatting"),
                                          html_dir_template = lformat!("Path of a HTML template for multifile HTML"),
+                                         html_dir_srcset = lformat!("Widths in pixels (e.g. '480 800 1200') of resized variants of images to generate, so browsers can pick the most appropriate one with srcset"),
+                                         html_dir_sizes = lformat!("Value of the sizes attribute of images with resized variants"),
+                                         html_dir_serial = lformat!("If set to true, renders each chapter as an article that can be published on its own (with its publication date and a canonical link) and generates an Atom feed of the chapters"),
+                                         html_dir_feed = lformat!("If set to 'atom' or 'rss', generates a feed of the chapters, using their publication dates, so readers can follow a book in progress"),
+                                         html_dir_summaries = lformat!("If set to true, lists the summaries of chapters in the index, and uses them in feeds and Open Graph descriptions"),
+                                         html_dir_fingerprint = lformat!("If set to true, adds a hash of their content to the names of CSS, Javascript and image files, so they can be cached indefinitely"),
+                                         html_dir_inline = lformat!("Maximal size, in bytes, of images that are included in HTML pages as data URIs instead of separate files (0 to disable)"),
+                                         html_dir_resize = lformat!("Command used to resize images"),
                                          
                                          epub_ver = lformat!("EPUB version to generate (2 or 3)"),
+                                         epub_footnotes_style = lformat!("Numbering style of footnotes for EPUB, overriding rendering.footnotes.style"),
+                                         epub_clean = lformat!("Toggle typographic cleaning for EPUB, overriding input.clean"),
                                          epub_css = lformat!("Path of a stylesheet for EPUB"),
                                          epub_css_add = lformat!("Inline CSS added to the EPUB stylesheet template"),
                                          chapter_xhtml = lformat!("Path of an xhtml template for each chapter"),
                                          epub_toc = lformat!("Add 'Title' and (if set) 'Cover' in the EPUB table of contents"),
+                                         epub_flavor = lformat!("Reading system the EPUB file is tweaked for: 'standard', 'kobo' (kepub) or 'apple' (Apple Books)"),
+                                         epub_page_list = lformat!("If set to true and the book contains page break markers of the print edition, add a page list to EPUB 3 files"),
                                          
+                                         tex_qrcode = lformat!("If set to true, a QR code of external links is displayed in a footnote"),
                                          tex_links = lformat!("Add foontotes to URL of links so they are readable when printed"),
+                                         pdf_engine = lformat!("How to generate PDF: 'latex' (using tex.command), 'libreoffice' (converting the ODT output) or 'builtin' (experimental, without any external tool)"),
+                                         pdf_libreoffice_command = lformat!("LibreOffice command to use for generating PDF when pdf.engine is set to 'libreoffice'"),
+                                         pdf_clean = lformat!("Toggle typographic cleaning for PDF when pdf.engine is set to 'builtin', overriding input.clean"),
                                          tex_command = lformat!("LaTeX command to use for generating PDF"),
                                          tex_tmpl = lformat!("Path of a LaTeX template file"),
                                          tex_tmpl_add = lformat!("Inline code added in the LaTeX template"),
-                                         tex_class = lformat!("LaTeX class to use"),
+                                         tex_class = lformat!("LaTeX class to use (book, article, or beamer to render slides)"),
                                          tex_title = lformat!("If true, generate a title with \\maketitle"),
+                                         tex_front_matter_tmpl = lformat!("Path of a LaTeX template for the front matter pages generated by \\maketitle"),
                                          tex_paper_size = lformat!("Specifies the size of the page."),
                                          tex_margin_left = lformat!("Specifies left margin (note that with book class left and right margins are reversed for odd pages, thus the default value is 1.5cm for book class and 2cm else)"),
                                          tex_margin_right = lformat!("Specifies right margin(note that with book class left and right margins are reversed for odd pages, thus the default value is 2.5cm for book class and 2cm else)"),
@@ -301,6 +539,20 @@ crowbook.verbose:alias                              # {removed}
                                          tex_font_size = lformat!("Specify latex font size (in pt, 10 (default), 11, or 12 are accepted)"),
                                          tex_hyperref = lformat!("If disabled, don't try to find references inside the document"),
                                          tex_stdpage = lformat!("If set to true, use 'stdpage' package to format a manuscript according to standards"),
+                                         tex_open_recto = lformat!("If set to true, chapters start on odd (recto) pages, inserting blank pages if needed; if set to false, they start on the next page. Default depends on tex.class"),
+                                         tex_blank_pages = lformat!("Don't display headers and footers on blank pages inserted before chapters"),
+                                         tex_bleed = lformat!("Size of the bleed added on each side of the page (e.g. \"3mm\" or \"0.125in\"), tex.paper.size then being the trim size"),
+                                         tex_crop_marks = lformat!("If set to true, display crop marks at the corners of the trim size (requires tex.bleed)"),
+                                         tex_cover_tmpl = lformat!("Path of a LaTeX template file for the print cover"),
+                                         tex_cover_pages = lformat!("Number of pages of the book, used to compute the spine width of the print cover. If not set, the PDF is rendered to count them"),
+                                         tex_cover_paper = lformat!("Paper used for the book, to compute the spine width of the print cover ('white' or 'cream')"),
+                                         tex_cover_thickness = lformat!("Thickness of a page in mm, overriding tex.cover.paper"),
+                                         tex_footnotes_style = lformat!("Numbering style of footnotes for LaTeX/PDF, overriding rendering.footnotes.style"),
+                                         tex_clean = lformat!("Toggle typographic cleaning for LaTeX/PDF, overriding input.clean"),
+                                         tex_grayscale = lformat!("If set to true, convert all colours (e.g. links or syntax highlighting) to grayscale. Images are not converted"),
+                                         txt_width = lformat!("Maximal number of characters of a line in plain text output (0 to disable wrapping)"),
+                                         md_clean = lformat!("Toggle typographic cleaning for Markdown, overriding input.clean"),
+                                         docbook_clean = lformat!("Toggle typographic cleaning for DocBook, overriding input.clean"),
                                          
                                          rs_files = lformat!("Whitespace-separated list of files to embed in e.g. EPUB file; useful for including e.g. fonts"),
                                          rs_out = lformat!("Paths where additional resources should be copied in the EPUB file or HTML directory"),
@@ -309,13 +561,38 @@ crowbook.verbose:alias                              # {removed}
                                          rs_img = lformat!("Set base path but only for images. Useless if resources.base_path is set"),
                                          rs_base_files = lformat!("Set base path but only for additional files. Useless if resources.base_path is set."),
                                          rs_tmpl = lformat!("Set base path but only for templates files. Useless if resources.base_path is set"),
+
+                                         retail_isbn = lformat!("ISBN of the EPUB edition of the book"),
+                                         retail_publisher = lformat!("Name of the publisher of the book (default: the author)"),
+                                         retail_keywords = lformat!("Keywords used by stores to find the book"),
+                                         retail_categories = lformat!("BISAC codes of the categories of the book (e.g. FIC009000), the first one being the main one"),
+                                         publish_base_url = lformat!("Base URL of the published output files, used for the links of the OPDS catalog"),
+                                         publish_s3 = lformat!("S3 bucket (and path) where output files are published with --publish, e.g. 's3://bucket/books'"),
+                                         publish_s3_command = lformat!("Command used to publish files to S3"),
+                                         publish_sftp = lformat!("Server (and path) where output files are published with --publish, e.g. 'user@example.org:books'"),
+                                         publish_sftp_command = lformat!("Command used to publish files to a server"),
+                                         publish_ftp = lformat!("FTP URL (and path) where output files are published with --publish, e.g. 'ftp://example.org/books'"),
+                                         publish_ftp_command = lformat!("Command used to upload each file to an FTP server"),
+                                         publish_git = lformat!("Branch of the book's git repository (e.g. 'gh-pages') where output files are published with --publish"),
+                                         publish_git_remote = lformat!("Git remote used to publish files to a branch"),
                                          
                                          autoclean = lformat!("Toggle typographic cleaning of input markdown according to lang"),
                                          smart_quotes = lformat!("If enabled, tries to replace vertical quotations marks to curly ones"),
+                                         clean_ellipsis = lformat!("If enabled, replaces '...' with an ellipsis ('…')"),
+                                         clean_dashes_spacing = lformat!("Spaces around em dashes in the middle of a sentence: 'keep' (as in the source), 'none', 'spaces' or 'thin' (thin non-breaking spaces)"),
+                                         clean_numbers = lformat!("If enabled, groups the thousands of long numbers and puts the right (non-breaking) space before units and percent signs, according to the language"),
+                                         clean_numbers_separator = lformat!("Separator between groups of thousands, instead of the one of the language"),
+                                         clean_apostrophes = lformat!("Words starting with an apostrophe (e.g. 'tis), added to the ones known for the language, that smart quotes must not convert to an opening quote"),
+                                         clean_typewriter = lformat!("If enabled, replaces curly quotes, guillemets, dashes, ellipses and non-breaking spaces with plain characters (e.g. for plain text output)"),
+                                         clean_dialogue = lformat!("If set to 'dashes', converts dialogue between quotation marks to dialogue introduced by em dashes; if set to 'quotes', does the opposite"),
                                          ligature_dashes = lformat!("If enabled, replaces '--' to en dash ('–') and '---' to em dash ('—')"),
                                          ligature_guillemets = lformat!("If enabled, replaces '<<' and '>>' to french \"guillemets\" ('«' and '»')"),
                                          superscript = lformat!("If enabled, allow support for superscript and subscript using respectively foo^up^  and bar~down~ syntax."),
                                          yaml = lformat!("Enable inline YAML blocks to override options set in config file"),
+                                         input_chapters = lformat!("How chapter files are mapped to chapters: 'file' (one chapter per file) or 'heading' (one chapter per level-1 heading)"),
+                                         page_breaks_pdf = lformat!("PDF file of the print edition, whose page breaks are added to the book"),
+                                         page_breaks_first = lformat!("Page number of the first page of input.page_breaks.pdf in the print edition"),
+                                         page_breaks_command = lformat!("Command used to extract the text of input.page_breaks.pdf, with pages separated by form feeds"),
                                          html_as_text = lformat!("Consider HTML blocks as text. This avoids having <foo> being considered as HTML and thus ignored."),
                                          tmp_dir = lformat!("Path where to create a temporary directory (default: uses result from Rust's std::env::temp_dir())"),
                                          zip = lformat!("Command to use to zip files (for EPUB/ODT)"),
@@ -373,15 +650,19 @@ pub struct BookOptions {
     options: HashMap<String, BookOption>,
     defaults: HashMap<String, BookOption>,
     deprecated: HashMap<String, Option<String>>,
-    valid_tpls: Vec<&'static str>,
-    valid_bools: Vec<&'static str>,
-    valid_chars: Vec<&'static str>,
-    valid_strings: Vec<&'static str>,
-    valid_paths: Vec<&'static str>,
-    valid_ints: Vec<&'static str>,
-    valid_floats: Vec<&'static str>,
-    valid_str_vecs: Vec<&'static str>,
+    valid_tpls: HashSet<&'static str>,
+    valid_bools: HashSet<&'static str>,
+    valid_chars: HashSet<&'static str>,
+    valid_strings: HashSet<&'static str>,
+    valid_paths: HashSet<&'static str>,
+    valid_ints: HashSet<&'static str>,
+    valid_floats: HashSet<&'static str>,
+    valid_str_vecs: HashSet<&'static str>,
     metadata: Vec<String>,
+    /// `output.{format}` options of formats added with `Book::add_format`
+    outputs: HashSet<String>,
+    /// Warnings emitted while setting options, to be logged by the book
+    warnings: Vec<String>,
 
     /// Source for errors (unnecessary copy :/)
     #[doc(hidden)]
@@ -399,15 +680,17 @@ impl BookOptions {
             options: HashMap::new(),
             deprecated: HashMap::new(),
             defaults: HashMap::new(),
-            valid_bools: vec![],
-            valid_chars: vec![],
-            valid_ints: vec![],
-            valid_floats: vec![],
-            valid_strings: vec![],
-            valid_paths: vec![],
-            valid_tpls: vec![],
-            valid_str_vecs: vec![],
+            valid_bools: HashSet::new(),
+            valid_chars: HashSet::new(),
+            valid_ints: HashSet::new(),
+            valid_floats: HashSet::new(),
+            valid_strings: HashSet::new(),
+            valid_paths: HashSet::new(),
+            valid_tpls: HashSet::new(),
+            valid_str_vecs: HashSet::new(),
             metadata: vec![],
+            outputs: HashSet::new(),
+            warnings: vec![],
             root: PathBuf::new(),
             source: Source::empty(),
         };
@@ -421,18 +704,32 @@ impl BookOptions {
             match option_type.unwrap() {
                 "meta" => {
                     options.metadata.push(key.to_owned());
-                    options.valid_strings.push(key);
+                    options.valid_strings.insert(key);
                 },
-                "str" => options.valid_strings.push(key),
-                "strvec" => options.valid_str_vecs.push(key),
-                "bool" => options.valid_bools.push(key),
-                "int" => options.valid_ints.push(key),
-                "float" => options.valid_floats.push(key),
-                "char" => options.valid_chars.push(key),
-                "path" => options.valid_paths.push(key),
+                "str" => {
+                    options.valid_strings.insert(key);
+                }
+                "strvec" => {
+                    options.valid_str_vecs.insert(key);
+                }
+                "bool" => {
+                    options.valid_bools.insert(key);
+                }
+                "int" => {
+                    options.valid_ints.insert(key);
+                }
+                "float" => {
+                    options.valid_floats.insert(key);
+                }
+                "char" => {
+                    options.valid_chars.insert(key);
+                }
+                "path" => {
+                    options.valid_paths.insert(key);
+                }
                 "tpl" => {
-                    options.valid_tpls.push(key);
-                    options.valid_paths.push(key);
+                    options.valid_tpls.insert(key);
+                    options.valid_paths.insert(key);
                 }
                 "alias" => {
                     options.deprecated.insert(key.to_owned(), default_value.map(|s| s.to_owned()));
@@ -480,7 +777,7 @@ impl BookOptions {
                                           lformat!("Expected a String as a key, found {:?}", key)));
         };
 
-        if self.valid_str_vecs.contains(&key.as_ref()) {
+        if self.valid_str_vecs.contains(key.as_str()) {
             // Value is a list of string
             if let Yaml::Array(array) = value {
                 let mut inner:Vec<String> = vec!();
@@ -512,7 +809,7 @@ impl BookOptions {
                                                 &key,
                                                 &value)))
             }
-        } else if self.valid_strings.contains(&key.as_ref()) {
+        } else if self.valid_strings.contains(key.as_str()) {
             // value is a string
             if let Yaml::String(value) = value {
                 Ok(self.options.insert(key, BookOption::String(value)))
@@ -523,7 +820,7 @@ impl BookOptions {
                                                 &key,
                                                 &value)))
             }
-        } else if self.valid_paths.contains(&key.as_ref()) {
+        } else if self.valid_paths.contains(key.as_str()) || self.outputs.contains(&key) {
             // value is a path
             if let Yaml::String(value) = value {
                 if &key == "import" {
@@ -550,7 +847,7 @@ impl BookOptions {
                                                 &key,
                                                 &value)))
             }
-        } else if self.valid_chars.contains(&key.as_ref()) {
+        } else if self.valid_chars.contains(key.as_str()) {
             // value is a char
             if let Yaml::String(value) = value {
                 let chars: Vec<_> = value.chars().collect();
@@ -569,7 +866,7 @@ impl BookOptions {
                                                 &key,
                                                 &value)))
             }
-        } else if self.valid_bools.contains(&key.as_ref()) {
+        } else if self.valid_bools.contains(key.as_str()) {
             // value is a bool
             if let Yaml::Boolean(value) = value {
                 Ok(self.options.insert(key, BookOption::Bool(value)))
@@ -580,7 +877,7 @@ impl BookOptions {
                                                 &key,
                                                 &value)))
             }
-        } else if self.valid_ints.contains(&key.as_ref()) {
+        } else if self.valid_ints.contains(key.as_str()) {
             // value is an int
             if let Yaml::Integer(value) = value {
                 Ok(self.options.insert(key, BookOption::Int(value as i32)))
@@ -591,7 +888,7 @@ impl BookOptions {
                                                 &key,
                                                 &value)))
             }
-        } else if self.valid_floats.contains(&key.as_ref()) {
+        } else if self.valid_floats.contains(key.as_str()) {
             // value is a float
             if let Yaml::Real(value) = value {
                 match value.parse::<f32>() {
@@ -614,14 +911,51 @@ impl BookOptions {
         } else if self.deprecated.contains_key(&key) {
             let opt = self.deprecated[&key].clone();
             if let Some(new_key) = opt {
-                Logger::display_warning(lformat!("'{old_key}' has been deprecated, you should \
-                                                  now use '{new_key}'",
-                                                 old_key = &key,
-                                                 new_key = &new_key));
+                self.warnings.push(lformat!("'{old_key}' has been deprecated, you should \
+                                             now use '{new_key}'",
+                                            old_key = &key,
+                                            new_key = &new_key));
                 self.set_yaml(Yaml::String(new_key), value)
+            } else if self.get_bool("strict").unwrap() {
+                Err(Error::book_option(&self.source,
+                                       lformat!("'{key}' has been removed", key = &key)))
+            } else {
+                self.warnings.push(lformat!("'{key}' has been removed and will be ignored",
+                                            key = &key));
+                Ok(None)
+            }
+        } else if key.starts_with("acronyms.") {
+            // key is an acronym, value is its expansion
+            if let Yaml::String(value) = value {
+                Ok(self.options.insert(key, BookOption::String(value)))
+            } else {
+                Err(Error::book_option(&self.source,
+                                       lformat!("expected a string as value for key '{}', found \
+                                                 {:?}",
+                                                &key,
+                                                &value)))
+            }
+        } else if key.starts_with("names.") {
+            // key is a canonical name, value is the list of its forbidden variants
+            if let Yaml::Array(array) = value {
+                let mut variants = vec![];
+                for value in array {
+                    if let Yaml::String(value) = value {
+                        variants.push(value);
+                    } else {
+                        return Err(Error::book_option(&self.source,
+                                                      lformat!("Expected only string in the list \
+                                                                for key {}, found {:?}",
+                                                               &key,
+                                                               &value)));
+                    }
+                }
+                Ok(self.options.insert(key, BookOption::StringVec(variants)))
             } else {
-                Err(Error::book_option(self.source.clone(),
-                                       lformat!("key '{key}' has been deprecated.", key = &key)))
+                Err(Error::book_option(&self.source,
+                                       lformat!("Expected a list as value for key {}, found {:?}",
+                                                &key,
+                                                &value)))
             }
         } else if key.starts_with("metadata.") {
             // key is a custom metadata
@@ -638,11 +972,41 @@ impl BookOptions {
             }
         } else {
             // key not recognized
-            Err(Error::book_option(self.source.clone(),
-                                   lformat!("unrecognized key '{key}'", key = &key)))
+            match self.suggest(&key) {
+                Some(suggestion) => {
+                    Err(Error::book_option(self.source.clone(),
+                                           lformat!("unrecognized key '{key}', did you mean \
+                                                     '{suggestion}'?",
+                                                    key = &key,
+                                                    suggestion = suggestion)))
+                }
+                None => {
+                    Err(Error::book_option(self.source.clone(),
+                                           lformat!("unrecognized key '{key}'", key = &key)))
+                }
+            }
         }
     }
 
+    /// Returns the valid key closest to an unrecognized one, if it is close
+    /// enough to be a probable typo (the first by name among equally close ones)
+    fn suggest(&self, key: &str) -> Option<&'static str> {
+        let max_distance = ::std::cmp::max(1, key.chars().count() / 4);
+        self.valid_bools
+            .iter()
+            .chain(&self.valid_chars)
+            .chain(&self.valid_ints)
+            .chain(&self.valid_floats)
+            .chain(&self.valid_strings)
+            .chain(&self.valid_paths)
+            .chain(&self.valid_tpls)
+            .chain(&self.valid_str_vecs)
+            .map(|valid| (misc::edit_distance(key, valid), *valid))
+            .filter(|&(distance, _)| distance <= max_distance)
+            .min()
+            .map(|(_, valid)| valid)
+    }
+
     /// Sets an option
     ///
     /// # Arguments
@@ -690,12 +1054,52 @@ impl BookOptions {
         }
     }
 
+    /// Returns the warnings emitted while setting options (e.g. for deprecated
+    /// keys) since the last call, so the book can log them
+    #[doc(hidden)]
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        mem::replace(&mut self.warnings, vec![])
+    }
+
     /// Return the list of keys that are metadata
     #[doc(hidden)]
     pub fn get_metadata(&self) -> &[String] {
         &self.metadata
     }
 
+    /// Returns the acronyms defined with `acronyms.*` keys and their expansions,
+    /// sorted alphabetically
+    #[doc(hidden)]
+    pub fn get_acronyms(&self) -> Vec<(&str, &str)> {
+        let mut acronyms: Vec<_> = self.options
+            .iter()
+            .filter_map(|(key, value)| match *value {
+                BookOption::String(ref expansion) if key.starts_with("acronyms.") => {
+                    Some((&key["acronyms.".len()..], expansion.as_str()))
+                }
+                _ => None,
+            })
+            .collect();
+        acronyms.sort();
+        acronyms
+    }
+
+    /// Returns the canonical names of characters and places defined with
+    /// `names.*` keys, and their forbidden variants
+    pub fn get_names(&self) -> Vec<(&str, &[String])> {
+        let mut names: Vec<_> = self.options
+            .iter()
+            .filter_map(|(key, value)| match *value {
+                BookOption::StringVec(ref variants) if key.starts_with("names.") => {
+                    Some((&key["names.".len()..], variants.as_slice()))
+                }
+                _ => None,
+            })
+            .collect();
+        names.sort();
+        names
+    }
+
     /// Gets an option
     #[doc(hidden)]
     pub fn get(&self, key: &str) -> Result<&BookOption> {
@@ -724,6 +1128,16 @@ impl BookOptions {
         self.get(key)?.as_str()
     }
 
+    /// Registers the `output.{format}` option of a format, so it can be set
+    /// like the ones of built-in formats
+    #[doc(hidden)]
+    pub fn add_output(&mut self, format: &str) {
+        let key = format!("output.{}", format);
+        if !self.valid_paths.contains(key.as_str()) {
+            self.outputs.insert(key);
+        }
+    }
+
     /// Get a stringvec option
     pub fn get_str_vec(&self, key: &str) -> Result<&[String]> {
         self.get(key)?.as_str_vec()
@@ -753,7 +1167,7 @@ impl BookOptions {
                 self.root.join(path)
             }
 
-            "cover" | "html.icon" => {
+            "cover" | "html.icon" | "rendering.scene_break.ornament" => {
                 // Translate according to resources.base_path.images
                 let base = self.get_path("resources.base_path.images").unwrap();
                 Path::new(&base).join(path)
@@ -763,8 +1177,15 @@ impl BookOptions {
             "output.html" |
             "output.html.dir" |
             "output.pdf" |
+            "output.pdf.cover" |
             "output.tex" |
             "output.odt" |
+            "output.mobi" |
+            "output.docbook" |
+            "output.txt" |
+            "output.md" |
+            "output.ast" |
+            "output.slides" |
             "output.proofread.html" |
             "output.proofread.html.dir" |
             "output.proofread.pdf" |
@@ -774,7 +1195,13 @@ impl BookOptions {
                 Path::new(&base).join(path)
             }
 
-            key if self.valid_tpls.contains(&key) => {
+            key if self.outputs.contains(key) => {
+                // Output of a format added by a library user
+                let base = self.get_path("output.base_path").unwrap();
+                Path::new(&base).join(path)
+            }
+
+            key if self.valid_tpls.contains(key) => {
                 // Translate according to resources.base_path.template
                 let base = self.get_path("resources.base_path.templates").unwrap();
                 Path::new(&base).join(path)
@@ -0,0 +1,127 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Calibre metadata (`metadata.opf` and cover) written next to the output
+//! files, so library managers pick up the metadata of the book.
+
+use book::Book;
+use error::{Error, Result};
+use misc;
+use opds;
+
+use crowbook_text_processing::escape;
+
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Writes `metadata.opf` and the cover of the book to `output.base_path`,
+/// if `output.calibre` is set
+pub fn write_metadata(book: &Book) -> Result<()> {
+    if !book.options.get_bool("output.calibre").unwrap() {
+        return Ok(());
+    }
+    let dir = book.options.get_path("output.base_path").unwrap();
+    let dir = Path::new(&dir);
+
+    let cover = match book.options.get_path("cover") {
+        Ok(cover) => {
+            let ext = Path::new(&cover)
+                .extension()
+                .map(|e| e.to_string_lossy().to_lowercase())
+                .unwrap_or_else(|| String::from("jpg"));
+            let file = format!("cover.{}", ext);
+            fs::copy(&cover, dir.join(&file))
+                .map_err(|e| {
+                    Error::default(&book.source,
+                                   lformat!("could not copy cover {file} for Calibre: {error}",
+                                            file = misc::normalize(&cover),
+                                            error = e))
+                })?;
+            Some(file)
+        }
+        Err(_) => None,
+    };
+
+    let content = opf(book, cover.as_ref().map(|s| s.as_str()))?;
+    let path = dir.join("metadata.opf");
+    fs::File::create(&path)
+        .and_then(|mut f| io::Write::write_all(&mut f, content.as_bytes()))
+        .map_err(|e| {
+            Error::default(&book.source,
+                           lformat!("could not write {file}: {error}",
+                                    file = path.display(),
+                                    error = e))
+        })?;
+    book.logger.info(lformat!("Succesfully generated Calibre metadata: {path}",
+                              path = misc::normalize(&path)));
+    Ok(())
+}
+
+/// Returns the content of a `metadata.opf` file describing the book, in the
+/// format used by Calibre
+pub fn opf(book: &Book, cover: Option<&str>) -> Result<String> {
+    let options = &book.options;
+    let mut metadata = String::new();
+    write!(metadata,
+           "    <dc:identifier opf:scheme=\"crowbook\" id=\"crowbook_id\">{id}</dc:identifier>
+    <dc:title>{title}</dc:title>
+    <dc:creator opf:role=\"aut\">{author}</dc:creator>
+    <dc:language>{lang}</dc:language>
+    <dc:contributor opf:role=\"bkp\">crowbook</dc:contributor>\n",
+           id = opds::book_id(book),
+           title = escape::html(options.get_str("title").unwrap()),
+           author = escape::html(options.get_str("author").unwrap()),
+           lang = escape::html(options.get_str("lang").unwrap()))?;
+    let elements = [("dc:identifier opf:scheme=\"ISBN\"", "dc:identifier", "retail.isbn"),
+                    ("dc:publisher", "dc:publisher", "retail.publisher"),
+                    ("dc:date", "dc:date", "date"),
+                    ("dc:description", "dc:description", "description"),
+                    ("dc:subject", "dc:subject", "subject"),
+                    ("dc:rights", "dc:rights", "license")];
+    for &(open, close, key) in &elements {
+        if let Ok(value) = options.get_str(key) {
+            write!(metadata, "    <{}>{}</{}>\n", open, escape::html(value), close)?;
+        }
+    }
+    // Calibre displays subjects as tags
+    if let Ok(keywords) = options.get_str_vec("retail.keywords") {
+        for keyword in keywords {
+            write!(metadata, "    <dc:subject>{}</dc:subject>\n", escape::html(keyword.as_str()))?;
+        }
+    }
+    let guide = match cover {
+        Some(file) => {
+            format!("  <guide>
+    <reference type=\"cover\" title=\"Cover\" href=\"{}\"/>
+  </guide>
+",
+                    escape::html(file))
+        }
+        None => String::new(),
+    };
+
+    Ok(format!("<?xml version=\"1.0\" encoding=\"utf-8\"?>
+<package xmlns=\"http://www.idpf.org/2007/opf\" unique-identifier=\"crowbook_id\" version=\"2.0\">
+  <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\" xmlns:opf=\"http://www.idpf.org/2007/opf\">
+{metadata}  </metadata>
+{guide}</package>
+",
+               metadata = metadata,
+               guide = guide))
+}
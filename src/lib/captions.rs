@@ -0,0 +1,281 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Numbering of captioned elements (e.g. code listings) and exercises, so
+//! they can be referenced and listed.
+
+use book::Book;
+use sections;
+use token::Token;
+use syntax::CodeOptions;
+use images::{self, ImageOptions};
+use text_view::view_as_text;
+use lang;
+
+use std::collections::{HashMap, HashSet};
+
+/// Kind of numbered elements
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CaptionKind {
+    /// Code block with a caption or an id
+    Listing,
+    /// Standalone image with a title
+    Figure,
+    /// Table with a caption
+    Table,
+    /// Exercise block
+    Exercise,
+}
+
+impl CaptionKind {
+    /// Returns the localisation key of the name of this kind of elements
+    pub fn loc_key(&self) -> &'static str {
+        match *self {
+            CaptionKind::Listing => "listing",
+            CaptionKind::Figure => "figure",
+            CaptionKind::Table => "table",
+            CaptionKind::Exercise => "exercise",
+        }
+    }
+
+    fn index(&self) -> usize {
+        match *self {
+            CaptionKind::Listing => 0,
+            CaptionKind::Figure => 1,
+            CaptionKind::Table => 2,
+            CaptionKind::Exercise => 3,
+        }
+    }
+}
+
+/// A numbered element of the book
+#[derive(Debug, Clone, PartialEq)]
+pub struct Caption {
+    /// Kind of the element
+    pub kind: CaptionKind,
+    /// Index of the chapter containing it
+    pub chapter: usize,
+    /// Id of the element, used as an anchor
+    pub id: String,
+    /// Number of the element, e.g. "3.2" for the second listing of chapter 3
+    pub number: String,
+    /// Caption of the element (inline Markdown, possibly empty)
+    pub caption: String,
+}
+
+impl Caption {
+    /// Returns the localised label of this element, e.g. "Listing 3.2"
+    pub fn label(&self, lang: &str) -> String {
+        format!("{} {}", lang::get_str(lang, self.kind.loc_key()), self.number)
+    }
+}
+
+/// The numbered elements of a book, used by renderers to get the number of
+/// the elements they render
+#[derive(Debug)]
+pub struct Captions {
+    captions: Vec<Caption>,
+    /// Indices of the elements of each kind
+    by_kind: [Vec<usize>; 4],
+    /// Indices of the elements by id
+    by_id: HashMap<String, usize>,
+    /// Number of elements of each kind that were already rendered
+    rendered: [usize; 4],
+    /// Whether standalone images with a title are figures
+    figures: bool,
+}
+
+impl Captions {
+    /// Finds all the numbered elements of the book
+    pub fn new(book: &Book) -> Captions {
+        let captions = collect(book, None);
+        let mut by_kind = [vec![], vec![], vec![], vec![]];
+        let mut by_id = HashMap::new();
+        for (i, caption) in captions.iter().enumerate() {
+            by_kind[caption.kind.index()].push(i);
+            by_id.entry(caption.id.clone()).or_insert(i);
+        }
+        Captions {
+            captions: captions,
+            by_kind: by_kind,
+            by_id: by_id,
+            rendered: [0; 4],
+            figures: book.options.get_bool("rendering.figures").unwrap(),
+        }
+    }
+
+    /// Returns the next element of this kind, in the order they appear in the book
+    pub fn next(&mut self, kind: CaptionKind) -> Option<Caption> {
+        let n = self.rendered[kind.index()];
+        self.rendered[kind.index()] += 1;
+        self.by_kind[kind.index()]
+            .get(n)
+            .map(|&i| self.captions[i].clone())
+    }
+
+    /// Returns the next figure if a standalone image with this title and
+    /// these options is a numbered figure, or `None` if it isn't
+    pub fn next_figure(&mut self, title: &str, options: &ImageOptions) -> Option<Caption> {
+        if is_figure(self.figures, title, options) {
+            self.next(CaptionKind::Figure)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the element with the given id, if any
+    pub fn find(&self, id: &str) -> Option<&Caption> {
+        self.by_id.get(id).map(|&i| &self.captions[i])
+    }
+
+    /// Returns all the numbered elements
+    pub fn all(&self) -> &[Caption] {
+        &self.captions
+    }
+}
+
+/// Returns all the elements of a kind (or of all kinds, if `kind` is `None`),
+/// in the order they appear in the book
+///
+/// Elements of numbered chapters are numbered with the displayed number of
+/// their chapter (see `sections::chapter_numbers`).
+pub fn collect(book: &Book, kind: Option<CaptionKind>) -> Vec<Caption> {
+    let mut collector = Collector {
+        kind: kind,
+        result: vec![],
+        chapter: 0,
+        prefix: None,
+        count: [0; 4],
+        unnumbered: [0; 4],
+        ids: HashSet::new(),
+        figures: book.options.get_bool("rendering.figures").unwrap(),
+    };
+    let numbers = sections::chapter_numbers(book);
+    for (i, (chapter, number)) in book.chapters.iter().zip(numbers).enumerate() {
+        collector.chapter = i;
+        collector.count = [0; 4];
+        collector.prefix = number;
+        collector.visit(&chapter.content);
+    }
+    collector.result
+}
+
+/// Walks through the tokens to find numbered elements
+struct Collector {
+    kind: Option<CaptionKind>,
+    result: Vec<Caption>,
+    chapter: usize,
+    /// Number of the current chapter, if it is numbered
+    prefix: Option<String>,
+    /// Number of elements of each kind found in current chapter
+    count: [i32; 4],
+    /// Number of elements of each kind found in unnumbered chapters
+    unnumbered: [i32; 4],
+    /// Ids that are already used
+    ids: HashSet<String>,
+    /// Whether standalone images with a title are figures
+    figures: bool,
+}
+
+impl Collector {
+    fn visit(&mut self, tokens: &[Token]) {
+        for token in tokens {
+            if let Some((kind, id, caption)) = is_numbered(token, self.figures) {
+                if self.kind.is_none() || self.kind == Some(kind) {
+                    let number = match self.prefix {
+                        Some(ref prefix) => {
+                            self.count[kind.index()] += 1;
+                            format!("{}.{}", prefix, self.count[kind.index()])
+                        }
+                        None => {
+                            self.unnumbered[kind.index()] += 1;
+                            format!("{}", self.unnumbered[kind.index()])
+                        }
+                    };
+                    let id = match id {
+                        Some(id) => id,
+                        None => {
+                            // Numbers are not unique if chapter numbers
+                            // restart at each part
+                            let base = format!("{}-{}", kind.loc_key(), number);
+                            let mut id = base.clone();
+                            let mut n = 1;
+                            while self.ids.contains(&id) {
+                                n += 1;
+                                id = format!("{}-{}", base, n);
+                            }
+                            id
+                        }
+                    };
+                    self.ids.insert(id.clone());
+                    self.result.push(Caption {
+                        kind: kind,
+                        chapter: self.chapter,
+                        id: id,
+                        number: number,
+                        caption: caption,
+                    });
+                }
+            }
+            if let Some(inner) = token.inner() {
+                self.visit(inner);
+            }
+        }
+    }
+}
+
+/// Returns the kind, the (optional) id and the caption of a token if it is
+/// numbered
+fn is_numbered(token: &Token, figures: bool) -> Option<(CaptionKind, Option<String>, String)> {
+    match *token {
+        Token::CodeBlock(ref info, _) => {
+            CodeOptions::parse(info)
+                .and_then(|options| if options.is_numbered() {
+                    Some((CaptionKind::Listing, options.id, options.caption.unwrap_or_default()))
+                } else {
+                    None
+                })
+        }
+        Token::StandaloneImage(ref url, ref title, ref attributes, _) => {
+            let options = ImageOptions::parse(attributes).unwrap_or_default();
+            if is_figure(figures, title, &options) && images::media_kind(url).is_none() {
+                Some((CaptionKind::Figure, options.id, title.clone()))
+            } else {
+                None
+            }
+        }
+        Token::Table(_, ref content) => {
+            match content.first() {
+                Some(&Token::TableCaption(ref caption)) => {
+                    Some((CaptionKind::Table, None, view_as_text(caption)))
+                }
+                _ => None,
+            }
+        }
+        Token::Exercise(ref id, _) => {
+            let id = if id.is_empty() { None } else { Some(id.clone()) };
+            Some((CaptionKind::Exercise, id, String::new()))
+        }
+        _ => None,
+    }
+}
+
+/// Returns true if a standalone image is a numbered figure, that is if
+/// `rendering.figures` is set and it has a title and no `nocaption` attribute
+fn is_figure(figures: bool, title: &str, options: &ImageOptions) -> bool {
+    figures && !title.is_empty() && !options.nocaption
+}
@@ -17,9 +17,10 @@
 
 use number::Number;
 use token::Token;
+use captions::CaptionKind;
 
 /// Represents the content of a chapter.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Chapter {
     /// The numbering scheme of this chapter.
     pub number: Number,
@@ -27,6 +28,26 @@ pub struct Chapter {
     pub filename: String,
     /// The (already parsed) content of this chapter
     pub content: Vec<Token>,
+    /// Whether this chapter is part of the front matter (preface, dedication, ...)
+    pub front_matter: bool,
+    /// Whether this chapter is listed in tables of contents
+    pub toc: bool,
+    /// Whether the title of this chapter is hidden (it can still be listed in
+    /// tables of contents)
+    pub hide_title: bool,
+    /// Set if this chapter is a generated list of figures, tables or listings
+    pub list_of: Option<CaptionKind>,
+    /// Point of view character of this chapter, if set
+    pub pov: Option<String>,
+    /// In-story date of this chapter, if set
+    pub story_date: Option<String>,
+    /// In-story location of this chapter, if set
+    pub location: Option<String>,
+    /// Publication date of this chapter, if set (for books published serially)
+    pub published: Option<String>,
+    /// Summary of this chapter: the blocks before its `<!-- more -->` marker,
+    /// or its first paragraph
+    pub summary: Vec<Token>,
 }
 
 impl Chapter {
@@ -41,6 +62,15 @@ impl Chapter {
             number: number,
             filename: filename.into(),
             content: content,
+            front_matter: false,
+            toc: true,
+            hide_title: number.is_hidden(),
+            list_of: None,
+            pov: None,
+            story_date: None,
+            location: None,
+            published: None,
+            summary: vec![],
         }
     }
 }
@@ -18,6 +18,7 @@
 //! This module contains the `Cleaner` traits and various implementations of it.
 
 use std::borrow::Cow;
+use std::cmp;
 use crowbook_text_processing::clean;
 use crowbook_text_processing::FrenchFormatter;
 
@@ -27,6 +28,290 @@ pub struct CleanerParams {
     pub smart_quotes: bool,
     pub ligature_guillemets: bool,
     pub ligature_dashes: bool,
+    /// Replace `...` with an ellipsis character
+    pub ellipsis: bool,
+    /// Spaces around em dashes: `keep`, `none`, `spaces` or `thin`
+    pub dash_spacing: String,
+    /// Reverse all typographic conversions, for plain text output
+    pub typewriter: bool,
+    /// Words starting with an apostrophe, that must not be mistaken for an
+    /// opening single quote (e.g. `'tis`)
+    pub apostrophes: Vec<String>,
+    /// Format numbers and units, if set
+    pub numbers: Option<NumberFormat>,
+    /// Use the punctuation of vertical text (`writing_mode: vertical-rl`)
+    pub vertical: bool,
+}
+
+/// Separators used to format numbers and units
+pub struct NumberFormat {
+    /// Separator between groups of thousands
+    pub thousands: String,
+    /// Space before `%` and `‰` (can be empty)
+    pub percent: String,
+    /// Space between a number and a unit
+    pub unit: String,
+}
+
+impl NumberFormat {
+    /// Returns the usual format of numbers for this language
+    pub fn new(lang: &str) -> NumberFormat {
+        let (thousands, percent, unit) = if lang.starts_with("en") {
+            (",", "", "\u{a0}")
+        } else if lang.starts_with("de") {
+            (".", "\u{202f}", "\u{202f}")
+        } else {
+            ("\u{202f}", "\u{202f}", "\u{202f}")
+        };
+        NumberFormat {
+            thousands: thousands.to_owned(),
+            percent: percent.to_owned(),
+            unit: unit.to_owned(),
+        }
+    }
+}
+
+/// Units that are separated from the number preceding them
+static UNITS: &'static [&'static str] = &["km/h", "km", "cm", "mm", "m", "kg", "mg", "g", "ml",
+                                           "l", "L", "ms", "min", "h", "s", "°C", "°F", "K",
+                                           "€", "kB", "MB", "GB", "Ko", "Mo", "Go"];
+
+/// Returns the words starting with an apostrophe that are known for this language
+pub fn default_apostrophes(lang: &str) -> &'static [&'static str] {
+    if lang.starts_with("en") {
+        &["'tis", "'twas", "'twill", "'cause", "'em", "'til", "'bout", "'round", "'cept",
+          "'n'", "'kay"]
+    } else {
+        &[]
+    }
+}
+
+/// Replaces the straight quotes that are apostrophes at the start of a word
+/// with `’`, so smart quotes don't turn them into opening quotes
+///
+/// These are the words listed in `words` (ignoring case), and abbreviated
+/// numbers such as `'90s`.
+pub fn apostrophes<'a>(s: Cow<'a, str>, words: &[String]) -> Cow<'a, str> {
+    if !s.contains('\'') {
+        return s;
+    }
+    let mut res = String::with_capacity(s.len());
+    let mut previous: Option<char> = None;
+    // Apostrophes before this position are part of a matched word
+    let mut end = 0;
+    for (i, c) in s.char_indices() {
+        if c == '\'' && i >= end && previous.map(|c| !c.is_alphanumeric()).unwrap_or(true) {
+            if let Some(len) = elision_len(&s[i..], words) {
+                end = i + len;
+            }
+        }
+        if c == '\'' && i < end {
+            res.push('’');
+        } else {
+            res.push(c);
+        }
+        previous = Some(c);
+    }
+    Cow::Owned(res)
+}
+
+/// If `s` starts with a known elided word, returns its length
+fn elision_len(s: &str, words: &[String]) -> Option<usize> {
+    if s[1..].chars().next().map(|c| c.is_digit(10)).unwrap_or(false) {
+        return Some(1);
+    }
+    for word in words {
+        let len = word.len();
+        if s.len() >= len && s.is_char_boundary(len) && s[..len].to_lowercase() == word.to_lowercase() &&
+           s[len..].chars().next().map(|c| !c.is_alphanumeric()).unwrap_or(true) {
+            return Some(len);
+        }
+    }
+    None
+}
+
+impl CleanerParams {
+    /// Applies the options that are common to all languages, after a
+    /// cleaner's own cleaning
+    fn apply<'a>(&self, mut s: Cow<'a, str>, latex: bool) -> Cow<'a, str> {
+        if self.ellipsis && s.contains("...") {
+            s = Cow::Owned(s.replace("...", "…"));
+        }
+        if self.dash_spacing != "keep" && s.contains('—') {
+            let sep = match self.dash_spacing.as_ref() {
+                "none" => "",
+                "thin" if latex => "\\,",
+                "thin" => "\u{202f}",
+                _ => " ",
+            };
+            s = Cow::Owned(dash_spacing(&s, sep));
+        }
+        if let Some(ref format) = self.numbers {
+            if s.contains(|c: char| c.is_digit(10)) {
+                s = Cow::Owned(numbers(&s, format, latex));
+            }
+        }
+        // LaTeX would turn `--` back into a dash
+        if self.typewriter && !latex {
+            s = Cow::Owned(typewriter(&s));
+        } else if self.vertical {
+            s = Cow::Owned(vertical(&s));
+        }
+        s
+    }
+}
+
+/// Groups the thousands of numbers of five digits or more, and puts the
+/// right space between a number and a unit or a percent sign
+///
+/// Numbers that are part of a word (e.g. `mp3`), and decimal parts, are left
+/// untouched; a space between a number and a unit is replaced, but never
+/// inserted (`1990s` is not a duration).
+pub fn numbers(s: &str, format: &NumberFormat, latex: bool) -> String {
+    let tex = |sep: &str| if latex {
+        sep.replace('\u{202f}', "\\,").replace('\u{a0}', "~")
+    } else {
+        sep.to_owned()
+    };
+    let (thousands, percent, unit) = (tex(&format.thousands), tex(&format.percent), tex(&format.unit));
+    let chars: Vec<char> = s.chars().collect();
+    let mut res = String::with_capacity(s.len());
+    let mut i = 0;
+    while i < chars.len() {
+        if !chars[i].is_digit(10) {
+            res.push(chars[i]);
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < chars.len() && chars[i].is_digit(10) {
+            i += 1;
+        }
+        let digits: String = chars[start..i].iter().cloned().collect();
+        let part_of_word = start > 0 &&
+                           (chars[start - 1].is_alphanumeric() || "_/#@.,\\".contains(chars[start - 1]));
+        if part_of_word {
+            res.push_str(&digits);
+            continue;
+        }
+        if digits.len() >= 5 {
+            let len = digits.len();
+            for (j, c) in digits.chars().enumerate() {
+                if j > 0 && (len - j) % 3 == 0 {
+                    res.push_str(&thousands);
+                }
+                res.push(c);
+            }
+        } else {
+            res.push_str(&digits);
+        }
+        // Decimal part (or number that is already formatted)
+        if i + 1 < chars.len() && (chars[i] == '.' || chars[i] == ',') && chars[i + 1].is_digit(10) {
+            res.push(chars[i]);
+            i += 1;
+            while i < chars.len() && chars[i].is_digit(10) {
+                res.push(chars[i]);
+                i += 1;
+            }
+        }
+
+        let after = if i < chars.len() && is_space(chars[i]) { i + 1 } else { i };
+        let next: String = chars[after..cmp::min(after + 6, chars.len())].iter().cloned().collect();
+        if next.starts_with('%') || next.starts_with('‰') || next.starts_with("\\%") {
+            res.push_str(&percent);
+            i = after;
+        } else if after > i {
+            let is_unit = UNITS.iter().any(|&u| {
+                next.starts_with(u) &&
+                next[u.len()..].chars().next().map(|c| !c.is_alphanumeric()).unwrap_or(true)
+            });
+            if is_unit {
+                res.push_str(&unit);
+                i = after;
+            }
+        }
+    }
+    res
+}
+
+/// Sets the spaces around em dashes in the middle of a text
+///
+/// Dashes starting a string (e.g. introducing dialogue) are left untouched.
+pub fn dash_spacing(s: &str, sep: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '—' && !res.trim().is_empty() {
+            let end = res.trim_right_matches(is_space).len();
+            res.truncate(end);
+            res.push_str(sep);
+            res.push(c);
+            while chars.peek().map(|&c| is_space(c)).unwrap_or(false) {
+                chars.next();
+            }
+            if chars.peek().is_some() {
+                res.push_str(sep);
+            }
+        } else {
+            res.push(c);
+        }
+    }
+    res
+}
+
+/// Replaces typographic characters (curly quotes, guillemets, dashes,
+/// ellipsis, non-breaking spaces) with the ones of a typewriter
+pub fn typewriter(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '“' | '”' | '„' => res.push('"'),
+            '‘' | '’' => res.push('\''),
+            '«' => {
+                res.push('"');
+                while chars.peek().map(|&c| is_space(c)).unwrap_or(false) {
+                    chars.next();
+                }
+            }
+            '»' => {
+                let end = res.trim_right_matches(is_space).len();
+                res.truncate(end);
+                res.push('"');
+            }
+            '—' => res.push_str("--"),
+            '–' => res.push('-'),
+            '…' => res.push_str("..."),
+            '\u{a0}' | '\u{202f}' => res.push(' '),
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+/// Replaces the punctuation that stays horizontal in vertical text (ASCII
+/// punctuation, curly double quotes, em dashes) with its full-width or
+/// vertical form
+pub fn vertical(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            '!' => '！',
+            '?' => '？',
+            ':' => '：',
+            ';' => '；',
+            '(' => '（',
+            ')' => '）',
+            '“' => '〝',
+            '”' => '〟',
+            '—' => '―',
+            c => c,
+        })
+        .collect()
+}
+
+/// Returns true if this character is a (possibly non-breaking) space
+fn is_space(c: char) -> bool {
+    c.is_whitespace() || c == '\u{a0}' || c == '\u{202f}'
 }
 
 /// Trait for cleaning a string.
@@ -70,7 +355,7 @@ impl Cleaner for Default {
     fn clean<'a>(&self, input: Cow<'a, str>, _: bool) -> Cow<'a, str> {
         let mut s = clean::whitespaces(input);
         if self.params.smart_quotes {
-            s = clean::quotes(s);
+            s = clean::quotes(apostrophes(s, &self.params.apostrophes));
         }
         if self.params.ligature_dashes {
             s = clean::dashes(s);
@@ -78,7 +363,7 @@ impl Cleaner for Default {
         if self.params.ligature_guillemets {
             s = clean::guillemets(s);
         }
-        s
+        self.params.apply(s, false)
     }
 }
 
@@ -108,11 +393,15 @@ impl French {
 
 impl Cleaner for French {
     /// Puts non breaking spaces before/after `:`, `;`, `?`, `!`, `«`, `»`, `—`
-    fn clean<'a>(&self, s: Cow<'a, str>, latex: bool) -> Cow<'a, str> {
-        if latex {
+    fn clean<'a>(&self, mut s: Cow<'a, str>, latex: bool) -> Cow<'a, str> {
+        if self.params.smart_quotes {
+            s = apostrophes(s, &self.params.apostrophes);
+        }
+        let s = if latex {
             self.formatter.format_tex(s)
         } else {
             self.formatter.format(s)
-        }
+        };
+        self.params.apply(s, latex)
     }
 }
@@ -0,0 +1,129 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Generation of a wraparound cover (back, spine and front) for printed books.
+
+use book::{Book, compile_str};
+use error::{Error, Result};
+use latex::LatexRenderer;
+use renderer::Renderer;
+use parser::Parser;
+use zipper::Zipper;
+use book_renderer::BookRenderer;
+
+use std::io;
+use std::io::Read;
+use std::fs::File;
+use std::path::Path;
+
+/// Renderer for the print cover
+pub struct PdfCover;
+
+impl PdfCover {
+    /// Returns the thickness of a sheet of paper, in mm
+    fn paper_thickness(book: &Book) -> Result<f32> {
+        if let Ok(thickness) = book.options.get_f32("tex.cover.paper.thickness") {
+            return Ok(thickness);
+        }
+        match book.options.get_str("tex.cover.paper").unwrap() {
+            "white" => Ok(0.0572),
+            "cream" => Ok(0.0635),
+            value => {
+                Err(Error::render(&book.source,
+                                  lformat!("tex.cover.paper must be either 'white' or 'cream', \
+                                            found '{value}'",
+                                           value = value)))
+            }
+        }
+    }
+
+    /// Returns the number of pages of the book, rendering the PDF if needed
+    fn page_count(book: &Book) -> Result<i32> {
+        if let Ok(pages) = book.options.get_i32("tex.cover.pages") {
+            return Ok(pages);
+        }
+        book.logger.debug(lformat!("Rendering PDF to compute the number of pages..."));
+        let mut interior = vec![];
+        let output = LatexRenderer::new(book).render_pdf(&mut interior)?;
+        parse_page_count(&output)
+            .ok_or_else(|| Error::render(&book.source,
+                                         lformat!("could not find the number of pages of the \
+                                                   PDF file; you can set it manually with \
+                                                   tex.cover.pages")))
+    }
+}
+
+impl BookRenderer for PdfCover {
+    fn auto_path(&self, book_name: &str) -> Result<String> {
+        Ok(format!("{}.cover.pdf", book_name))
+    }
+
+    fn render(&self, book: &Book, to: &mut io::Write) -> Result<()> {
+        let pages = PdfCover::page_count(book)?;
+        let spine = pages as f32 * PdfCover::paper_thickness(book)?;
+
+        let template = compile_str(book.get_template("tex.cover.template")?.as_ref(),
+                                   &book.source,
+                                   "tex.cover.template")?;
+        let mut latex = LatexRenderer::new(book);
+        let mut data = book.get_metadata(|s| latex.render_vec(&Parser::new().parse_inline(s)?))?
+            .insert_str("papersize", book.options.get_str("tex.paper.size").unwrap())
+            .insert_str("bleed", book.options.get_str("tex.bleed").unwrap_or("0pt"))
+            .insert_str("spine", format!("{:.2}mm", spine))
+            .insert_str("pages", format!("{}", pages));
+        if book.options.get_str("tex.command") == Ok("xelatex") {
+            data = data.insert_bool("xelatex", true);
+        }
+
+        let mut zipper = Zipper::new(&book.options.get_path("crowbook.temp_dir").unwrap(),
+                                     &book.logger)?;
+        if let Ok(cover) = book.options.get_path("cover") {
+            let mut content = vec![];
+            File::open(&cover)
+                .and_then(|mut f| f.read_to_end(&mut content))
+                .map_err(|_| {
+                    Error::file_not_found(&book.source, lformat!("cover"), cover.clone())
+                })?;
+            let dest = match Path::new(&cover).extension().and_then(|s| s.to_str()) {
+                Some(ext) => format!("cover.{}", ext),
+                None => String::from("cover"),
+            };
+            zipper.write(&dest, &content, false)?;
+            data = data
+                .insert_bool("has_cover", true)
+                .insert_str("cover", dest);
+        }
+
+        let data = data.build();
+        let mut res: Vec<u8> = vec![];
+        template.render_data(&mut res, &data)?;
+        zipper.write("result.tex", &res, false)?;
+        zipper.generate_pdf(book.options.get_str("tex.command").unwrap(),
+                            "result.tex",
+                            to)?;
+        Ok(())
+    }
+}
+
+/// Finds the number of pages in the output of a LaTeX command
+/// (e.g. "Output written on result.pdf (42 pages).")
+fn parse_page_count(output: &str) -> Option<i32> {
+    output.find("Output written on")
+        .and_then(|i| output[i..].find('(').map(|j| i + j + 1))
+        .and_then(|i| output[i..].split_whitespace().next())
+        .and_then(|n| n.parse().ok())
+}
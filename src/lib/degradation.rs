@@ -0,0 +1,176 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Report of the features used in a book that an output format can't
+//! express, and of the way they are rendered instead.
+
+use book::Book;
+use token::Token;
+use images;
+
+/// A feature of a manuscript that some formats can't express
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Feature {
+    MarginNotes,
+    Footnotes,
+    Media,
+    Images,
+    /// Images that aren't JPEG files
+    OtherImages,
+    Tables,
+    CodeBlocks,
+    OrderedLists,
+    Scripts,
+    Spoilers,
+    Columns,
+}
+
+/// Returns the features used by a book that a format can't express, with
+/// the way they were rendered instead, e.g. "margin notes (displayed as
+/// footnotes)"
+pub fn report(book: &Book, format: &str) -> Vec<String> {
+    let degradations = degradations(book, format);
+    if degradations.is_empty() {
+        return vec![];
+    }
+    let mut used = vec![];
+    for chapter in &book.chapters {
+        visit(&chapter.content, &mut used);
+    }
+    degradations.into_iter()
+        .filter(|&(feature, _)| used.contains(&feature))
+        .map(|(_, description)| description)
+        .collect()
+}
+
+/// Lists the features used by some tokens
+fn visit(tokens: &[Token], used: &mut Vec<Feature>) {
+    for token in tokens {
+        let feature = match *token {
+            Token::MarginNote(_) => Some(Feature::MarginNotes),
+            Token::Footnote(_) => Some(Feature::Footnotes),
+            Token::Image(ref url, ..) |
+            Token::StandaloneImage(ref url, ..) => {
+                let lower = url.to_lowercase();
+                if images::media_kind(url).is_some() {
+                    Some(Feature::Media)
+                } else if lower.ends_with(".jpg") || lower.ends_with(".jpeg") {
+                    Some(Feature::Images)
+                } else {
+                    if !used.contains(&Feature::Images) {
+                        used.push(Feature::Images);
+                    }
+                    Some(Feature::OtherImages)
+                }
+            }
+            Token::Table(..) => Some(Feature::Tables),
+            Token::CodeBlock(..) => Some(Feature::CodeBlocks),
+            Token::OrderedList(..) => Some(Feature::OrderedLists),
+            Token::Superscript(_) | Token::Subscript(_) => Some(Feature::Scripts),
+            Token::Spoiler(..) => Some(Feature::Spoilers),
+            Token::Columns(..) => Some(Feature::Columns),
+            _ => None,
+        };
+        if let Some(feature) = feature {
+            if !used.contains(&feature) {
+                used.push(feature);
+            }
+        }
+        if let Some(inner) = token.inner() {
+            visit(inner, used);
+        }
+    }
+}
+
+/// Returns the features that a format can't express, and how they are
+/// rendered instead
+fn degradations(book: &Book, format: &str) -> Vec<(Feature, String)> {
+    let format = if format.starts_with("proofread.") {
+        &format["proofread.".len()..]
+    } else {
+        format
+    };
+    let engine = match format {
+        "epub" | "mobi" => {
+            if book.options.get_i32("epub.version").unwrap() == 3 {
+                "epub3"
+            } else {
+                "epub2"
+            }
+        }
+        "pdf" => book.options.get_str("pdf.engine").unwrap(),
+        "tex" => "latex",
+        other => other,
+    };
+    match engine {
+        "epub2" => {
+            vec![(Feature::MarginNotes, lformat!("margin notes (displayed as footnotes)")),
+                 (Feature::Media, lformat!("audio and video (replaced by their description)"))]
+        }
+        "epub3" => {
+            vec![(Feature::MarginNotes, lformat!("margin notes (displayed as footnotes)"))]
+        }
+        "docbook" => {
+            vec![(Feature::MarginNotes, lformat!("margin notes (displayed as footnotes)")),
+                 (Feature::Columns, lformat!("multi-column regions (displayed in a single \
+                                               column)"))]
+        }
+        "latex" => {
+            vec![(Feature::Media, lformat!("audio and video (replaced by their description)")),
+                 (Feature::Spoilers, lformat!("spoilers (moved to the solutions at the end of \
+                                                the book)"))]
+        }
+        "builtin" => {
+            vec![(Feature::MarginNotes, lformat!("margin notes (inserted in the text)")),
+                 (Feature::Media, lformat!("audio and video (replaced by their description)")),
+                 (Feature::OtherImages, lformat!("images that aren't JPEG files (replaced by \
+                                                   their description)")),
+                 (Feature::Tables, lformat!("tables (displayed as lines of text)")),
+                 (Feature::Scripts, lformat!("superscript and subscript (displayed as normal \
+                                               text)")),
+                 (Feature::Spoilers, lformat!("spoilers (displayed after their summary)")),
+                 (Feature::Columns, lformat!("multi-column regions (displayed in a single \
+                                               column)"))]
+        }
+        "txt" => {
+            vec![(Feature::MarginNotes, lformat!("margin notes (displayed as footnotes)")),
+                 (Feature::Media, lformat!("audio and video (replaced by their description)")),
+                 (Feature::Images, lformat!("images (replaced by their description)")),
+                 (Feature::Tables, lformat!("tables (displayed as lines of text)")),
+                 (Feature::Scripts, lformat!("superscript and subscript (displayed as normal \
+                                               text)")),
+                 (Feature::Spoilers, lformat!("spoilers (displayed after their summary)")),
+                 (Feature::Columns, lformat!("multi-column regions (displayed in a single \
+                                               column)"))]
+        }
+        "odt" | "libreoffice" => {
+            vec![(Feature::MarginNotes, lformat!("margin notes (ignored)")),
+                 (Feature::Footnotes, lformat!("footnotes (ignored)")),
+                 (Feature::Media, lformat!("audio and video (replaced by their description)")),
+                 (Feature::Images, lformat!("images (ignored)")),
+                 (Feature::Tables, lformat!("tables (ignored)")),
+                 (Feature::CodeBlocks, lformat!("code blocks (displayed as normal paragraphs)")),
+                 (Feature::OrderedLists, lformat!("ordered lists (displayed as bullet lists)")),
+                 (Feature::Scripts, lformat!("superscript and subscript (displayed as normal \
+                                               text)")),
+                 (Feature::Spoilers, lformat!("spoilers (displayed after their summary)")),
+                 (Feature::Columns, lformat!("multi-column regions (displayed in a single \
+                                               column)"))]
+        }
+        _ => vec![],
+    }
+}
@@ -0,0 +1,157 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Rendering of diagrams (Graphviz, Mermaid) described in code blocks
+//! to images, at build time.
+
+use book::Book;
+use error::{Error, Result, Source};
+use token::Token;
+use syntax::{CodeOptions, strip_language};
+use text_view::view_as_text;
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::fs::{self, File, DirBuilder};
+use std::io::Write;
+use std::path::Path;
+use std::process::{self, Command};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Languages of code blocks that are rendered as diagrams
+const LANGUAGES: &'static [&'static str] = &["dot", "mermaid"];
+
+/// Number of diagrams rendered by this process, to name their temporary files
+static RENDERED: AtomicUsize = AtomicUsize::new(0);
+
+/// Replaces code blocks containing diagrams by the corresponding images
+///
+/// Does nothing if `rendering.diagrams` is not set to true.
+pub fn render_diagrams(book: &Book, tokens: &mut [Token], source: &Source) -> Result<()> {
+    if !book.options.get_bool("rendering.diagrams").unwrap() {
+        return Ok(());
+    }
+    replace_diagrams(book, tokens, source)
+}
+
+fn replace_diagrams(book: &Book, tokens: &mut [Token], source: &Source) -> Result<()> {
+    for token in tokens {
+        let image = if let Token::CodeBlock(ref info, ref content) = *token {
+            let language = strip_language(info);
+            if LANGUAGES.iter().any(|l| *l == language) {
+                let alt = CodeOptions::parse(info)
+                    .and_then(|options| options.caption)
+                    .unwrap_or_default();
+                let path = render_diagram(book, language, &view_as_text(content), source)?;
+                Some((path, alt))
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+        if let Some((path, alt)) = image {
+            *token = Token::StandaloneImage(path, String::new(), String::new(), vec![Token::Str(alt)]);
+            continue;
+        }
+        if let Some(inner) = token.inner_mut() {
+            replace_diagrams(book, inner, source)?;
+        }
+    }
+    Ok(())
+}
+
+/// Renders a diagram with the command set for this language, and returns the
+/// path of the resulting image
+///
+/// Images are kept in a cache directory, so diagrams that didn't change
+/// are not rendered again. They are rendered to a temporary file which is
+/// renamed once complete, so books rendered in parallel (which share this
+/// cache) never use a partial image.
+fn render_diagram(book: &Book, language: &str, code: &str, source: &Source) -> Result<String> {
+    let format = book.options.get_str("rendering.diagrams.format").unwrap();
+    let command = book.options.get_str(&format!("rendering.diagrams.{}", language)).unwrap();
+    let dir = Path::new(&book.options.get_path("crowbook.temp_dir").unwrap())
+        .join("crowbook-diagrams");
+    DirBuilder::new()
+        .recursive(true)
+        .create(&dir)
+        .map_err(|e| {
+            Error::render(source,
+                          lformat!("could not create directory for diagrams: {error}",
+                                   error = e))
+        })?;
+
+    let mut hasher = DefaultHasher::new();
+    (command, format, code).hash(&mut hasher);
+    let name = format!("{:x}", hasher.finish());
+    let output = dir.join(format!("{}.{}", name, format));
+    if output.exists() {
+        book.logger.debug(lformat!("using cached diagram {file}", file = output.display()));
+        return Ok(format!("{}", output.display()));
+    }
+    let tmp = format!("{}.{}-{}",
+                      name,
+                      process::id(),
+                      RENDERED.fetch_add(1, Ordering::SeqCst));
+    let input = dir.join(format!("{}.{}", tmp, language));
+    let tmp_output = dir.join(format!("{}.{}", tmp, format));
+
+    let input_str = format!("{}", input.display());
+    let output_str = format!("{}", tmp_output.display());
+    let mut args = command.split_whitespace()
+        .map(|arg| {
+            arg.replace("{input}", &input_str)
+                .replace("{output}", &output_str)
+                .replace("{format}", format)
+        });
+    let program = match args.next() {
+        Some(program) => program,
+        None => {
+            return Err(Error::render(source,
+                                     lformat!("rendering.diagrams.{language} is empty",
+                                              language = language)))
+        }
+    };
+    File::create(&input)
+        .and_then(|mut f| f.write_all(code.as_bytes()))
+        .map_err(|e| {
+            Error::render(source,
+                          lformat!("could not write diagram source: {error}", error = e))
+        })?;
+    let result = Command::new(&program).args(args).output();
+    let _ = fs::remove_file(&input);
+    let result = result.map_err(|e| {
+        Error::render(source,
+                      lformat!("failed to run command '{command}' to render diagram: {error}",
+                               command = program,
+                               error = e))
+    })?;
+    if !result.status.success() || !tmp_output.exists() {
+        let _ = fs::remove_file(&tmp_output);
+        return Err(Error::render(source,
+                                 lformat!("command '{command}' failed to render diagram:\n{error}",
+                                          command = command,
+                                          error = String::from_utf8_lossy(&result.stderr))));
+    }
+    fs::rename(&tmp_output, &output)
+        .map_err(|e| {
+            Error::render(source,
+                          lformat!("could not write diagram to cache: {error}", error = e))
+        })?;
+    Ok(format!("{}", output.display()))
+}
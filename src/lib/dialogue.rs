@@ -0,0 +1,156 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Conversion of dialogue between quotation marks (“Hello,” she said.) and
+//! dialogue introduced by em dashes (— Hello, she said.), according to the
+//! `input.clean.dialogue` option.
+
+use book::Book;
+use error::{Error, Result, Source};
+use token::Token;
+use text_view::view_as_text;
+
+/// Opening quotation marks, and the matching closing ones
+static QUOTES: &'static [(char, char)] = &[('“', '”'), ('«', '»'), ('„', '“'), ('"', '"')];
+
+/// Converts the dialogue of a chapter, if `input.clean.dialogue` is set to
+/// `dashes` or `quotes`
+///
+/// Only paragraphs starting with a quotation mark and containing a single
+/// quote (or starting with an em dash, for the opposite conversion) are
+/// converted; narration following the quote is kept.
+pub fn convert(book: &Book, tokens: &mut Vec<Token>, source: &Source) -> Result<()> {
+    let style = match book.options.get_str("input.clean.dialogue") {
+        Ok(style) => style,
+        Err(_) => return Ok(()),
+    };
+    let lang = book.options.get_str("lang").unwrap().to_lowercase();
+    let (open, close) = if lang.starts_with("fr") {
+        ("«\u{a0}", "\u{a0}»")
+    } else if lang.starts_with("de") {
+        ("„", "“")
+    } else {
+        ("“", "”")
+    };
+    match style {
+        "dashes" => visit(tokens, &to_dashes),
+        "quotes" => visit(tokens, &|p| to_quotes(p, open, close)),
+        _ => {
+            return Err(Error::config_parser(source,
+                                            lformat!("input.clean.dialogue set to '{value}', \
+                                                      expected 'dashes' or 'quotes'",
+                                                     value = style)))
+        }
+    }
+    Ok(())
+}
+
+fn visit(tokens: &mut Vec<Token>, f: &Fn(&mut Vec<Token>)) {
+    for token in tokens {
+        match *token {
+            Token::Paragraph(ref mut content) => f(content),
+            Token::Code(..) | Token::CodeBlock(..) | Token::Header(..) => {}
+            ref mut token => {
+                if let Some(inner) = token.inner_mut() {
+                    visit(inner, f);
+                }
+            }
+        }
+    }
+}
+
+/// Returns true if this character is a space that can surround a quotation mark
+fn is_space(c: char) -> bool {
+    c.is_whitespace() || c == '\u{a0}' || c == '\u{202f}'
+}
+
+/// Converts a paragraph such as `“Hello,” she said.` to `— Hello, she said.`
+fn to_dashes(paragraph: &mut Vec<Token>) {
+    let text = view_as_text(paragraph);
+    let text = text.trim_left();
+    let (open, close) = match QUOTES.iter().find(|&&(open, _)| text.starts_with(open)) {
+        Some(&(open, close)) => (open, close),
+        None => return,
+    };
+    let expected = if open == close { 2 } else { 1 };
+    if text.chars().filter(|&c| c == close).count() != expected {
+        return;
+    }
+    // The closing quote must be in a string of this paragraph, and not in
+    // some emphasis
+    let closing = paragraph.iter().any(|token| match *token {
+        Token::Str(ref s) => s.contains(close),
+        _ => false,
+    });
+    if !closing {
+        return;
+    }
+
+    if let Some(&mut Token::Str(ref mut s)) = paragraph.first_mut() {
+        let rest = s.trim_left()[open.len_utf8()..].trim_left_matches(is_space).to_owned();
+        *s = format!("— {}", rest);
+    } else {
+        return;
+    }
+    for token in paragraph.iter_mut() {
+        if let Token::Str(ref mut s) = *token {
+            if let Some(pos) = s.find(close) {
+                let before = s[..pos].trim_right_matches(is_space).to_owned();
+                let after = s[pos + close.len_utf8()..].to_owned();
+                *s = format!("{}{}", before, after);
+                break;
+            }
+        }
+    }
+}
+
+/// Converts a paragraph such as `— Hello, she said.` to `“Hello, she said.”`
+fn to_quotes(paragraph: &mut Vec<Token>, open: &str, close: &str) {
+    // A paragraph containing several lines of dialogue can't be converted
+    if paragraph.iter().any(|token| *token == Token::HardBreak) {
+        return;
+    }
+    if let Some(&mut Token::Str(ref mut s)) = paragraph.first_mut() {
+        let rest = {
+            let trimmed = s.trim_left();
+            if !(trimmed.starts_with('—') || trimmed.starts_with('–')) {
+                return;
+            }
+            let rest = trimmed['—'.len_utf8()..].trim_left_matches(is_space);
+            if rest.len() == trimmed.len() - '—'.len_utf8() {
+                // Not followed by a space, so not dialogue
+                return;
+            }
+            rest.to_owned()
+        };
+        *s = format!("{}{}", open, rest);
+    } else {
+        return;
+    }
+    let needs_token = match paragraph.last_mut() {
+        Some(&mut Token::Str(ref mut s)) => {
+            let end = s.trim_right_matches(is_space).len();
+            s.truncate(end);
+            s.push_str(close);
+            false
+        }
+        _ => true,
+    };
+    if needs_token {
+        paragraph.push(Token::Str(close.to_owned()));
+    }
+}
@@ -0,0 +1,503 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Rendering of a book to DocBook 5 XML, to use it with publishing
+//! toolchains (e.g. DocBook XSL stylesheets) that consume DocBook.
+
+use token::Token;
+use book::Book;
+use number::Number;
+use error::{Error, Result, Source};
+use parser::Parser;
+use renderer::Renderer;
+use book_renderer::BookRenderer;
+use resource_handler::ResourceHandler;
+use sections::Sections;
+use captions::{Captions, CaptionKind};
+use text_view::view_as_text;
+use lang;
+
+use crowbook_text_processing::escape;
+
+use std::io::Write;
+
+/// Renderer for DocBook 5
+///
+/// Numbering is left to the toolchain processing the document: chapter and
+/// section titles don't contain their numbers.
+pub struct DocBookRenderer<'a> {
+    book: &'a Book,
+    handler: ResourceHandler<'a>,
+    sections: Sections,
+    captions: Captions,
+    /// Whether the chapter being rendered is a part
+    current_part: bool,
+    /// Levels of the sections that are open in the current chapter
+    open_sections: Vec<i32>,
+    /// Whether a verse block is being rendered
+    verse: bool,
+    /// Whether text is typographically cleaned
+    clean: bool,
+}
+
+impl<'a> DocBookRenderer<'a> {
+    /// Creates a new DocBookRenderer
+    pub fn new(book: &'a Book) -> DocBookRenderer<'a> {
+        DocBookRenderer {
+            book: book,
+            handler: ResourceHandler::new(&book.logger),
+            sections: Sections::new(book),
+            captions: Captions::new(book),
+            current_part: false,
+            open_sections: vec![],
+            verse: false,
+            clean: book.cleans("docbook"),
+        }
+    }
+
+    /// Renders a full book
+    pub fn render_book(&mut self) -> Result<String> {
+        for (i, chapter) in self.book.chapters.iter().enumerate() {
+            self.handler.add_chapter_link(chapter.filename.as_ref(), format!("#chapter-{}", i));
+        }
+        for caption in self.captions.all() {
+            self.handler.add_link(format!("#{}", caption.id), format!("#{}", caption.id));
+        }
+        for section in self.sections.all() {
+            self.handler.add_link(format!("#{}", section.id), format!("#{}", section.id));
+        }
+
+        let lang = self.book.options.get_str("lang").unwrap();
+        let mut content = format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<book xmlns=\"http://docbook.org/ns/docbook\" \
+                                   xmlns:xlink=\"http://www.w3.org/1999/xlink\" \
+                                   version=\"5.0\" xml:lang=\"{}\">
+{}",
+                                  attribute(lang),
+                                  self.render_info()?);
+
+        let mut in_part = false;
+        for (i, chapter) in self.book.chapters.iter().enumerate() {
+            let chapter = self.book.clean_chapter(chapter, self.clean)?;
+            self.current_part = chapter.number.is_part();
+            let (element, label) = if self.current_part {
+                if in_part {
+                    content.push_str("</part>\n");
+                }
+                in_part = true;
+                match chapter.number {
+                    Number::SpecifiedPart(n) => ("part", format!(" label=\"{}\"", n)),
+                    Number::UnnumberedPart => ("part", String::from(" role=\"unnumbered\"")),
+                    _ => ("part", String::new()),
+                }
+            } else if chapter.front_matter {
+                ("preface", String::new())
+            } else {
+                match chapter.number {
+                    Number::Specified(n) => ("chapter", format!(" label=\"{}\"", n)),
+                    Number::Hidden | Number::Unnumbered => {
+                        ("chapter", String::from(" role=\"unnumbered\""))
+                    }
+                    _ => ("chapter", String::new()),
+                }
+            };
+            let hidden = if chapter.hide_title {
+                " condition=\"hidden-title\""
+            } else {
+                ""
+            };
+            content.push_str(&format!("<{} xml:id=\"chapter-{}\"{}{}>\n", element, i, label, hidden));
+
+            // DocBook elements must start with their title
+            let title = chapter.content
+                .iter()
+                .position(|t| if let Token::Header(1, _) = *t { true } else { false });
+            match title {
+                Some(pos) => {
+                    let title = self.render_vec(chapter.content[pos].inner().unwrap())?;
+                    content.push_str(&format!("<title>{}</title>\n", title));
+                }
+                None => content.push_str("<title/>\n"),
+            }
+            let mut body = String::new();
+            for (j, token) in chapter.content.iter().enumerate() {
+                if Some(j) != title {
+                    body.push_str(&self.render_token(token)?);
+                }
+            }
+            body.push_str(&self.close_sections(1));
+            if self.current_part {
+                if !body.trim().is_empty() {
+                    content.push_str(&format!("<partintro>\n{}</partintro>\n", body));
+                }
+            } else {
+                content.push_str(&body);
+                content.push_str(&format!("</{}>\n", element));
+            }
+        }
+        if in_part {
+            content.push_str("</part>\n");
+        }
+        content.push_str("</book>\n");
+        Ok(content)
+    }
+
+    /// Renders the metadata of the book
+    fn render_info(&mut self) -> Result<String> {
+        let mut info = String::from("<info>\n");
+        info.push_str(&format!("<title>{}</title>\n", self.render_metadata("title")?));
+        if self.book.options.get_str("subtitle").is_ok() {
+            info.push_str(&format!("<subtitle>{}</subtitle>\n",
+                                   self.render_metadata("subtitle")?));
+        }
+        info.push_str(&format!("<author><personname>{}</personname></author>\n",
+                               self.render_metadata("author")?));
+        if self.book.options.get_str("date").is_ok() {
+            info.push_str(&format!("<pubdate>{}</pubdate>\n", self.render_metadata("date")?));
+        }
+        if self.book.options.get_str("version").is_ok() {
+            info.push_str(&format!("<releaseinfo>{}</releaseinfo>\n",
+                                   self.render_metadata("version")?));
+        }
+        if self.book.options.get_str("license").is_ok() {
+            info.push_str(&format!("<legalnotice><para>{}</para></legalnotice>\n",
+                                   self.render_metadata("license")?));
+        }
+        info.push_str("</info>\n");
+        Ok(info)
+    }
+
+    /// Renders a metadata field, which can contain inline Markdown
+    fn render_metadata(&mut self, key: &str) -> Result<String> {
+        let value = self.book.options.get_str(key).unwrap_or("");
+        let tokens = Parser::new().parse_inline(value)?;
+        self.render_vec(&tokens)
+    }
+
+    /// Closes the open sections whose level is at least `level`
+    fn close_sections(&mut self, level: i32) -> String {
+        let mut res = String::new();
+        while self.open_sections.last().map_or(false, |&l| l >= level) {
+            self.open_sections.pop();
+            res.push_str("</section>\n");
+        }
+        res
+    }
+
+    /// Renders block content, wrapping it in a paragraph if it is inline
+    /// (e.g. the content of items of a tight list)
+    fn render_blocks(&mut self, tokens: &[Token]) -> Result<String> {
+        let content = self.render_vec(tokens)?;
+        if tokens.first().map_or(false, |t| is_block(t)) {
+            Ok(content)
+        } else {
+            Ok(format!("<para>{}</para>", content))
+        }
+    }
+
+    /// Renders a table: its caption, if any, and its rows
+    fn render_table(&mut self, cols: i32, tokens: &[Token]) -> Result<String> {
+        let mut title = None;
+        let mut head = String::new();
+        let mut body = String::new();
+        for token in tokens {
+            match *token {
+                Token::TableCaption(ref vec) => title = Some(self.render_vec(vec)?),
+                Token::TableHead(ref vec) => {
+                    head.push_str(&format!("<row>\n{}</row>\n", self.render_vec(vec)?))
+                }
+                _ => body.push_str(&self.render_token(token)?),
+            }
+        }
+        let mut res = match title {
+            Some(title) => format!("<table>\n<title>{}</title>\n", title),
+            None => String::from("<informaltable>\n"),
+        };
+        res.push_str(&format!("<tgroup cols=\"{}\">\n", cols));
+        if !head.is_empty() {
+            res.push_str(&format!("<thead>\n{}</thead>\n", head));
+        }
+        res.push_str(&format!("<tbody>\n{}</tbody>\n</tgroup>\n", body));
+        res.push_str(if res.starts_with("<table>") {
+            "</table>\n"
+        } else {
+            "</informaltable>\n"
+        });
+        Ok(res)
+    }
+}
+
+impl<'a> Renderer for DocBookRenderer<'a> {
+    fn render_token(&mut self, token: &Token) -> Result<String> {
+        match *token {
+            Token::Str(ref text) => {
+                Ok(escape::html(self.book.clean_if(self.clean, text.as_str(), false)).into_owned())
+            }
+            Token::Paragraph(ref vec) => {
+                if self.verse {
+                    Ok(format!("{}\n\n", self.render_vec(vec)?))
+                } else {
+                    Ok(format!("<para>{}</para>\n", self.render_vec(vec)?))
+                }
+            }
+            Token::Header(n, ref vec) => {
+                let level = if self.current_part { n - 1 } else { n };
+                let section = if level >= 2 {
+                    self.sections.next()
+                } else {
+                    None
+                };
+                let mut res = self.close_sections(n);
+                self.open_sections.push(n);
+                let id = match section {
+                    Some(ref section) => format!(" xml:id=\"{}\"", attribute(&section.id)),
+                    None => String::new(),
+                };
+                res.push_str(&format!("<section{}>\n<title>{}</title>\n",
+                                      id,
+                                      self.render_vec(vec)?));
+                Ok(res)
+            }
+            Token::Emphasis(ref vec) => Ok(format!("<emphasis>{}</emphasis>", self.render_vec(vec)?)),
+            Token::Strong(ref vec) => {
+                Ok(format!("<emphasis role=\"strong\">{}</emphasis>", self.render_vec(vec)?))
+            }
+            Token::Code(ref vec) => Ok(format!("<literal>{}</literal>", self.render_vec(vec)?)),
+            Token::Superscript(ref vec) => {
+                Ok(format!("<superscript>{}</superscript>", self.render_vec(vec)?))
+            }
+            Token::Subscript(ref vec) => {
+                Ok(format!("<subscript>{}</subscript>", self.render_vec(vec)?))
+            }
+            Token::Abbreviation(_, ref vec) => {
+                Ok(format!("<abbrev>{}</abbrev>", self.render_vec(vec)?))
+            }
+            Token::BlockQuote(ref vec) => {
+                // The attribution of a quote comes before its content in DocBook
+                let (attribution, content) = match vec.split_last() {
+                    Some((&Token::Attribution(ref attribution), content)) => {
+                        (format!("<attribution>{}</attribution>\n",
+                                 self.render_vec(attribution)?),
+                         content)
+                    }
+                    _ => (String::new(), &vec[..]),
+                };
+                Ok(format!("<blockquote>\n{}{}</blockquote>\n",
+                           attribution,
+                           self.render_blocks(content)?))
+            }
+            Token::Attribution(ref vec) => {
+                Ok(format!("<attribution>{}</attribution>\n", self.render_vec(vec)?))
+            }
+            Token::CodeBlock(ref language, ref vec) => {
+                let language = language.split(|c: char| c.is_whitespace() || c == '{')
+                    .next()
+                    .unwrap_or("");
+                let language = if language.is_empty() {
+                    String::new()
+                } else {
+                    format!(" language=\"{}\"", attribute(language))
+                };
+                // Code isn't cleaned
+                Ok(format!("<programlisting{}>{}</programlisting>\n",
+                           language,
+                           escape::html(view_as_text(vec))))
+            }
+            Token::Verse(ref vec) => {
+                self.verse = true;
+                let content = self.render_vec(vec);
+                self.verse = false;
+                Ok(format!("<literallayout class=\"normal\">{}</literallayout>\n",
+                           content?.trim_right()))
+            }
+            Token::Epigraph(ref author, ref vec) => {
+                let author = if author.is_empty() {
+                    String::new()
+                } else {
+                    let tokens = Parser::new().parse_inline(author)?;
+                    format!("<attribution>{}</attribution>\n", self.render_vec(&tokens)?)
+                };
+                Ok(format!("<epigraph>\n{}{}</epigraph>\n", author, self.render_blocks(vec)?))
+            }
+            Token::Spoiler(ref summary, ref vec) => {
+                let summary = if summary.is_empty() {
+                    escape::html(lang::get_str(self.book.options.get_str("lang").unwrap(),
+                                               "solution"))
+                        .into_owned()
+                } else {
+                    let tokens = Parser::new().parse_inline(summary)?;
+                    self.render_vec(&tokens)?
+                };
+                Ok(format!("<sidebar role=\"spoiler\">\n<title>{}</title>\n{}</sidebar>\n",
+                           summary,
+                           self.render_blocks(vec)?))
+            }
+            Token::Admonition(ref kind, ref title, ref vec) => {
+                // DocBook has an element for each kind of admonition
+                let title = if title.is_empty() {
+                    String::new()
+                } else {
+                    let tokens = Parser::new().parse_inline(title)?;
+                    format!("<title>{}</title>\n", self.render_vec(&tokens)?)
+                };
+                Ok(format!("<{kind}>\n{title}{content}</{kind}>\n",
+                           kind = kind,
+                           title = title,
+                           content = self.render_blocks(vec)?))
+            }
+            // DocBook has no element for columns, so their content is rendered as is
+            Token::Columns(_, ref vec) => self.render_blocks(vec),
+            Token::Exercise(_, ref vec) => {
+                let lang = self.book.options.get_str("lang").unwrap();
+                let (id, label) = match self.captions.next(CaptionKind::Exercise) {
+                    Some(exercise) => {
+                        (format!(" xml:id=\"{}\"", attribute(&exercise.id)),
+                         exercise.label(lang))
+                    }
+                    None => (String::new(), lang::get_str(lang, "exercise")),
+                };
+                Ok(format!("<sidebar role=\"exercise\"{}>\n<title>{}</title>\n{}</sidebar>\n",
+                           id,
+                           escape::html(label),
+                           self.render_blocks(vec)?))
+            }
+            Token::Answer(ref vec) => self.render_vec(vec),
+            Token::List(ref vec) => {
+                Ok(format!("<itemizedlist>\n{}</itemizedlist>\n", self.render_vec(vec)?))
+            }
+            Token::OrderedList(n, ref vec) => {
+                let start = if n == 1 {
+                    String::new()
+                } else {
+                    format!(" startingnumber=\"{}\"", n)
+                };
+                Ok(format!("<orderedlist{}>\n{}</orderedlist>\n", start, self.render_vec(vec)?))
+            }
+            Token::Item(ref vec) => {
+                Ok(format!("<listitem>{}</listitem>\n", self.render_blocks(vec)?))
+            }
+            Token::Table(n, ref vec) => self.render_table(n, vec),
+            Token::TableHead(ref vec) |
+            Token::TableRow(ref vec) => Ok(format!("<row>\n{}</row>\n", self.render_vec(vec)?)),
+            Token::TableCell(ref vec) => Ok(format!("<entry>{}</entry>\n", self.render_vec(vec)?)),
+            Token::TableCaption(_) => Ok(String::new()),
+            Token::Footnote(ref vec) | Token::MarginNote(ref vec) => {
+                Ok(format!("<footnote>{}</footnote>", self.render_blocks(vec)?))
+            }
+            Token::Rule => {
+                let text = match self.book.options.get_str("rendering.scene_break").unwrap() {
+                    "asterism" => "⁂",
+                    "blank" => "",
+                    _ => "***",
+                };
+                Ok(format!("<para role=\"scene-break\">{}</para>\n", text))
+            }
+            Token::SoftBreak => Ok(String::from(" ")),
+            Token::HardBreak if self.verse => Ok(String::from("\n")),
+            Token::HardBreak => Ok(String::from("<?linebreak?>")),
+            Token::PageBreak(_) => Ok(String::new()),
+            // Processing instruction understood by the DocBook XSL stylesheets
+            Token::NewPage => Ok(String::from("<?hard-pagebreak?>\n")),
+            Token::KeepTogether(ref vec) => self.render_blocks(vec),
+            Token::Link(ref url, ref title, ref vec) => {
+                let content = if vec.is_empty() && url.starts_with('#') {
+                    // Link to a numbered element without text: the
+                    // toolchain generates its label
+                    String::new()
+                } else {
+                    self.render_vec(vec)?
+                };
+                let title = if title.is_empty() {
+                    String::new()
+                } else {
+                    format!(" xlink:title=\"{}\"", attribute(title))
+                };
+                if ResourceHandler::is_local(url) && self.handler.contains_link(url) {
+                    let link = self.handler.get_link(url).into_owned();
+                    if link.starts_with('#') {
+                        return if content.is_empty() {
+                            Ok(format!("<xref linkend=\"{}\"{}/>", attribute(&link[1..]), title))
+                        } else {
+                            Ok(format!("<link linkend=\"{}\"{}>{}</link>",
+                                       attribute(&link[1..]),
+                                       title,
+                                       content))
+                        };
+                    }
+                }
+                Ok(format!("<link xlink:href=\"{}\"{}>{}</link>", attribute(url), title, content))
+            }
+            Token::Image(ref url, _, _, ref alt) => {
+                Ok(format!("<inlinemediaobject>{}</inlinemediaobject>",
+                           self.render_media(url, alt)?))
+            }
+            Token::StandaloneImage(ref url, _, _, ref alt) => {
+                Ok(format!("<mediaobject>{}</mediaobject>\n", self.render_media(url, alt)?))
+            }
+            Token::Annotation(_, ref vec) => self.render_vec(vec),
+            Token::__NonExhaustive => unreachable!(),
+        }
+    }
+}
+
+impl<'a> DocBookRenderer<'a> {
+    /// Renders the content of a media object: an image and its description
+    fn render_media(&mut self, url: &str, alt: &[Token]) -> Result<String> {
+        let alt = self.render_vec(alt)?;
+        let mut res = format!("<imageobject><imagedata fileref=\"{}\"/></imageobject>",
+                              attribute(url));
+        if !alt.is_empty() {
+            res.push_str(&format!("<textobject><phrase>{}</phrase></textobject>", alt));
+        }
+        Ok(res)
+    }
+}
+
+/// Returns true if a token is rendered as a block element
+fn is_block(token: &Token) -> bool {
+    match *token {
+        Token::Paragraph(..) | Token::Header(..) | Token::BlockQuote(..) |
+        Token::CodeBlock(..) | Token::Verse(..) | Token::Epigraph(..) | Token::Spoiler(..) |
+        Token::Admonition(..) | Token::Columns(..) | Token::KeepTogether(..) |
+        Token::Exercise(..) | Token::List(..) | Token::OrderedList(..) | Token::Table(..) |
+        Token::Rule | Token::StandaloneImage(..) => true,
+        _ => false,
+    }
+}
+
+/// Escapes a string to use it as the value of an attribute
+fn attribute(s: &str) -> String {
+    escape::html(s).replace('"', "&quot;")
+}
+
+pub struct DocBook {}
+
+impl BookRenderer for DocBook {
+    fn auto_path(&self, book_name: &str) -> Result<String> {
+        Ok(format!("{}.xml", book_name))
+    }
+
+    fn render(&self, book: &Book, to: &mut Write) -> Result<()> {
+        let content = DocBookRenderer::new(book).render_book()?;
+        to.write_all(content.as_bytes())
+            .map_err(|e| {
+                Error::render(Source::empty(),
+                              lformat!("problem when writing DocBook: {error}", error = e))
+            })?;
+        Ok(())
+    }
+}
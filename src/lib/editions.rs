@@ -0,0 +1,112 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Filtering of the chapters and blocks of a book according to the editions
+//! (e.g. `standard` or `extended`) they are tagged with.
+
+use book::Book;
+use error::{Error, Result, Source};
+
+/// Returns true if content tagged with `tags` (a comma-separated list of
+/// editions, e.g. `extended, deluxe`) is part of the editions set by the
+/// `editions` option
+///
+/// If `editions` isn't set, all content is included.
+pub fn is_included(book: &Book, tags: &str) -> bool {
+    match book.options.get_str_vec("editions") {
+        Ok(editions) => {
+            tags.split(',')
+                .map(|tag| tag.trim())
+                .any(|tag| editions.iter().any(|edition| edition == tag))
+        }
+        Err(_) => true,
+    }
+}
+
+/// Removes the blocks of a chapter's source that are tagged with editions
+/// that aren't built
+///
+/// Blocks start with a `<!-- edition: extended -->` line and end with a
+/// `<!-- end edition -->` line. Removed lines (including these markers) are
+/// replaced by empty lines, so line numbers of error messages are kept.
+pub fn filter_blocks(book: &Book, content: &str, source: &Source) -> Result<String> {
+    if !content.contains("<!--") {
+        return Ok(content.to_owned());
+    }
+    let mut res = String::with_capacity(content.len());
+    // Whether the current block is included, if there is one
+    let mut block: Option<bool> = None;
+    let at = |n: usize| {
+        let mut source = source.clone();
+        source.set_line(n as u32);
+        source
+    };
+    for (i, line) in content.lines().enumerate() {
+        match marker(line) {
+            Some(Marker::Start(tags)) => {
+                if block.is_some() {
+                    return Err(Error::parser(at(i + 1),
+                                             lformat!("edition blocks can not be nested")));
+                }
+                block = Some(is_included(book, tags));
+            }
+            Some(Marker::End) => {
+                if block.is_none() {
+                    return Err(Error::parser(at(i + 1),
+                                             lformat!("'<!-- end edition -->' without a \
+                                                       matching '<!-- edition: ... -->'")));
+                }
+                block = None;
+            }
+            None => {
+                if block != Some(false) {
+                    res.push_str(line);
+                }
+            }
+        }
+        res.push('\n');
+    }
+    if block.is_some() {
+        return Err(Error::parser(source,
+                                 lformat!("edition block is not closed by \
+                                           '<!-- end edition -->'")));
+    }
+    Ok(res)
+}
+
+/// Marker of the start or of the end of a block
+enum Marker<'a> {
+    /// Start of a block, with its tags
+    Start(&'a str),
+    End,
+}
+
+/// Returns the marker of this line, if it is one
+fn marker(line: &str) -> Option<Marker> {
+    let line = line.trim();
+    if !line.starts_with("<!--") || !line.ends_with("-->") || line.len() < 7 {
+        return None;
+    }
+    let inner = line[4..line.len() - 3].trim();
+    if inner.starts_with("edition:") {
+        Some(Marker::Start(inner["edition:".len()..].trim()))
+    } else if inner.split_whitespace().collect::<Vec<_>>() == ["end", "edition"] {
+        Some(Marker::End)
+    } else {
+        None
+    }
+}
@@ -20,32 +20,50 @@ use token::Token;
 use html::HtmlRenderer;
 use book::{Book, compile_str};
 use book::Header;
+use chapter::Chapter;
 use templates::epub::*;
 use templates::epub3;
 use resource_handler;
 use renderer::Renderer;
 use parser::Parser;
 use lang;
+use images;
+use kepub;
+use epub_size::{EpubSize, CountingWriter};
 use book_renderer::BookRenderer;
+use zipper::Zipper;
 use text_view::view_as_text;
 
 use mustache::Template;
+use mustache::MapBuilder;
 use crowbook_text_processing::escape;
+use epub_builder;
 use epub_builder::EpubBuilder;
 use epub_builder::EpubVersion;
 use epub_builder::EpubContent;
 use epub_builder::ZipCommand;
+use epub_builder::Zip;
 use epub_builder::ReferenceType;
 
-use std::io::Write;
+use std::io::{Read, Write};
 use std::convert::{AsRef, AsMut};
 use std::fs;
 use std::fs::File;
+use std::process::Command;
 use std::path::Path;
 use std::borrow::Cow;
 use std::mem;
 use mime_guess::guess_mime_type_opt;
 
+/// Display options for Apple Books, allowing to use the fonts of the book
+const APPLE_DISPLAY_OPTIONS: &'static str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<display_options>
+  <platform name="*">
+    <option name="specified-fonts">true</option>
+  </platform>
+</display_options>
+"#;
+
 /// Renderer for Epub
 ///
 /// Uses part of the HTML renderer
@@ -65,6 +83,8 @@ impl<'a> EpubRenderer<'a> {
                                          .unwrap_or_else(|_| book.options.get_str("rendering.highlight.theme").unwrap()))?;
         html.handler.set_images_mapping(true);
         html.handler.set_base64(false);
+        html.footnote_style = book.footnote_style("epub")?;
+        html.clean = book.cleans("epub");
         Ok(EpubRenderer {
             html: html,
             toc: vec![],
@@ -79,7 +99,35 @@ impl<'a> EpubRenderer<'a> {
         let mut zip = ZipCommand::new_in(self.html.book.options.get_path("crowbook.temp_dir")?)?;
         zip.command(self.html.book.options.get_str("crowbook.zip.command")
                     .unwrap());
-        let mut maker = EpubBuilder::new(zip)?;
+        let flavor = self.html.book.options.get_str("epub.flavor").unwrap();
+        match flavor {
+            "standard" | "kobo" => (),
+            "apple" => {
+                // Allow readers to display the fonts of the book
+                zip.write_file("META-INF/com.apple.ibooks.display-options.xml",
+                               APPLE_DISPLAY_OPTIONS.as_bytes())?;
+            }
+            value => {
+                return Err(Error::render(&self.html.book.source,
+                                         lformat!("epub.flavor set to '{value}', not a valid value",
+                                                  value = value)));
+            }
+        }
+        let vertical = self.html.book.options.get_str("writing_mode") == Ok("vertical-rl");
+        // The page list is a part of the navigation document, not a page of the spine
+        let mut page_list = None;
+        if self.html.book.options.get_i32("epub.version").unwrap() == 3 &&
+            self.html.book.options.get_bool("epub.page_list").unwrap() {
+            let pages = page_breaks(&self.html.book.chapters);
+            if !pages.is_empty() {
+                page_list = Some(self.render_page_list(&pages)?);
+            }
+        }
+        let mut maker = EpubBuilder::new(PackageZip {
+            inner: zip,
+            vertical: vertical,
+            page_list: page_list,
+        })?;
         if self.html.book.options.get_i32("epub.version").unwrap() == 3 {
             maker.epub_version(EpubVersion::V30);
         }
@@ -125,7 +173,15 @@ impl<'a> EpubRenderer<'a> {
 
         
         for (i, chapter) in self.html.book.chapters.iter().enumerate() {
-            self.html.handler.add_link(chapter.filename.as_str(), filenamer(i));
+            self.html.handler.add_chapter_link(chapter.filename.as_str(), filenamer(i));
+        }
+        for caption in self.html.captions.all() {
+            self.html.handler.add_link(format!("#{}", caption.id),
+                                       format!("{}#{}", filenamer(caption.chapter), caption.id));
+        }
+        for section in self.html.sections.all() {
+            self.html.handler.add_link(format!("#{}", section.id),
+                                       format!("{}#{}", filenamer(section.chapter), section.id));
         }
 
         // Write cover.xhtml (if needs be)
@@ -150,7 +206,8 @@ impl<'a> EpubRenderer<'a> {
             maker.add_content(content)?;
         }
 
-        if self.html.book.options.get_bool("rendering.inline_toc").unwrap() {
+        let toc_location = self.html.book.inline_toc_location()?;
+        if toc_location == Some("start") {
             maker.inline_toc();
         }
 
@@ -160,32 +217,78 @@ impl<'a> EpubRenderer<'a> {
             compile_str(self.html.book.get_template("epub.chapter.xhtml")?.as_ref(),
                         &self.html.book.source,
                         "epub.chapter.xhtml")?;
-        let mut rendered = vec![];
+        // The main text starts at the first chapter that is not front matter
+        let text_start = self.html
+            .book
+            .chapters
+            .iter()
+            .position(|c| !c.front_matter)
+            .unwrap_or(0);
+        // If notes are displayed at the end of the book, they are in the last chapter
+        let last = self.html.book.chapters.len().saturating_sub(1);
+        let end_of_book = self.html.notes == "end_of_book";
+        if end_of_book {
+            self.html.notes_filename = filenamer(last);
+        }
+        // Each chapter is written to the EPUB as soon as it is rendered, so
+        // only one rendered chapter is kept in memory at a time
+        //
+        // TOC elements are added in chapter order, so there's no need to look
+        // again at the ones of previous chapters
+        let mut toc_start = 0;
+        let mut size = EpubSize::new();
         for (i, chapter) in self.html.book.chapters.iter().enumerate() {
+            let chapter = self.html.book.clean_chapter(chapter, self.html.clean)?;
             let n = chapter.number;
             let v = &chapter.content;
             self.html.chapter_config(i, n, filenamer(i));
-            let this_chapter = self.render_chapter(v, &template_chapter)?;
-            rendered.push(this_chapter);
-        }
-
-        for (i, (rendered_chapter, raw_title)) in rendered.into_iter().enumerate() {
+            let (rendered_chapter, raw_title) =
+                self.render_chapter(v, &template_chapter, !end_of_book || i == last)?;
+            let rendered_chapter = if flavor == "kobo" {
+                kepub::add_spans(&rendered_chapter)
+            } else {
+                rendered_chapter
+            };
+            size.text += rendered_chapter.len() as u64;
             let mut content = EpubContent::new(filenamer(i), rendered_chapter.as_bytes());
-            if i == 0 {
+            if i == text_start {
                 content = content.reftype(ReferenceType::Text);
             }
 
             // horrible hack to add subtoc of this chapter to epub's toc
             // todo: find cleaner way
-            for element in &self.html.toc.elements {
-                if element.url.contains(&filenamer(i)) {
+            let filename = filenamer(i);
+            for (j, element) in self.html.toc.elements.iter().enumerate().skip(toc_start) {
+                if element.url.contains(&filename) {
                     content = content.title(escape::html(raw_title));
                     content.toc.children = element.children.clone();
+                    toc_start = j + 1;
                     break;
                 }
             }
             maker.add_content(content)?;
         }
+
+        // Write the newsletter page, inviting readers to subscribe
+        if let Ok(url) = self.html.book.options.get_str("epub.newsletter.url") {
+            let (page, title) = self.render_newsletter_page(&template_chapter, url)?;
+            let mut content = EpubContent::new("newsletter.xhtml", page.as_bytes());
+            if toc_extras {
+                content = content.title(escape::html(title));
+            }
+            maker.add_content(content)?;
+        }
+
+        // Write the inline toc after the chapters, if it must be displayed at the end
+        if toc_location == Some("end") {
+            let (toc_page, toc_title) = self.render_toc_page(&template_chapter)?;
+            let mut content = EpubContent::new("toc_end.xhtml", toc_page.as_bytes());
+            if toc_extras {
+                content = content.title(escape::html(toc_title));
+            }
+            maker.add_content(content)?;
+        }
+
         self.html.source = Source::empty();
 
         // Render the CSS file and write it
@@ -193,17 +296,16 @@ impl<'a> EpubRenderer<'a> {
             compile_str(self.html.book.get_template("epub.css").unwrap().as_ref(),
                         &self.html.book.source,
                         "epub.css")?;
-        let mut data = self.html
+        let data = self.html
             .book
             .get_metadata(|s| self.render_vec(&Parser::new().parse_inline(s)?))?
-            .insert_bool(self.html.book.options.get_str("lang").unwrap(), true);
-        if let Ok(epub_css_add) = self.html.book.options.get_str("epub.css.add") {
-            data = data.insert_str("additional_code", epub_css_add);
-        }
-        let data = data.build();
+            .insert_bool(self.html.book.options.get_str("lang").unwrap(), true)
+            .insert_bool("vertical_rl", vertical)
+            .build();
         let mut res: Vec<u8> = vec![];
         template_css.render_data(&mut res, &data)?;
-        let css = String::from_utf8_lossy(&res);
+        let css = self.html.append_css(String::from_utf8_lossy(&res), "epub.css.add");
+        size.text += css.len() as u64;
         maker.stylesheet(css.as_bytes())?;
 
         // Write all images (including cover)
@@ -216,6 +318,7 @@ impl<'a> EpubRenderer<'a> {
                                       lformat!("image or cover"),
                                       source.to_owned())
             })?;
+            size.add_resource(dest, f.metadata().map(|m| m.len()).unwrap_or(0));
             if cover.as_ref() == Ok(source) {
                 // Treat cover specially so it is properly tagged
                 maker.add_cover_image(dest, &f, self.get_format(dest))?;
@@ -239,12 +342,15 @@ impl<'a> EpubRenderer<'a> {
                                           lformat!("additional resource from resources.files"),
                                           abs_path.to_string_lossy().into_owned())
                 })?;
+                size.add_resource(&path, f.metadata().map(|m| m.len()).unwrap_or(0));
                 maker.add_resource(data_path.join(&path), &f, self.get_format(path.as_ref()))?;
             }
         }
 
-        maker.generate(to)?;
-    
+        let mut counter = CountingWriter::new(to);
+        maker.generate(&mut counter)?;
+        size.report(self.html.book, counter.count);
+
         Ok(String::new())
     }
 
@@ -266,6 +372,91 @@ impl<'a> EpubRenderer<'a> {
         }
     }
 
+    /// Render the page list navigation element, given the chapter and number of each page
+    fn render_page_list(&self, pages: &[(usize, String)]) -> Result<String> {
+        let lang = self.html.book.options.get_str("lang").unwrap();
+        let template = compile_str(epub3::PAGE_LIST,
+                                   &self.html.book.source,
+                                   "page list")?;
+        let mut list = String::new();
+        for &(i, ref page) in pages {
+            list.push_str(&format!("      <li><a href = \"{file}#page-{page}\">{page}</a></li>\n",
+                                   file = filenamer(i),
+                                   page = escape::html(page.as_str())));
+        }
+        let data = MapBuilder::new()
+            .insert_str("page_list_title", lang::get_str(lang, "page_list"))
+            .insert_str("pages", list)
+            .build();
+        let mut res: Vec<u8> = vec![];
+        template.render_data(&mut res, &data)?;
+        match String::from_utf8(res) {
+            Err(_) => panic!("generated HTML in page list was not utf-8 valid"),
+            Ok(res) => Ok(res),
+        }
+    }
+
+    /// Renders the table of contents as a page of its own, using the chapter
+    /// template, and returns it along with its title
+    fn render_toc_page(&mut self, template: &Template) -> Result<(String, String)> {
+        let title = self.html.get_toc_name()?;
+        let content = format!("<h1>{}</h1>
+<div id = \"toc\">
+{}
+</div>
+",
+                              title,
+                              self.html.toc.render(false));
+        let data = self.html
+            .book
+            .get_metadata(|s| self.render_vec(&Parser::new().parse_inline(s)?))?
+            .insert_str("content", content)
+            .insert_str("chapter_title_raw", title.clone())
+            .insert_str("chapter_title", title.clone())
+            .build();
+        let mut res: Vec<u8> = vec![];
+        template.render_data(&mut res, &data)?;
+        match String::from_utf8(res) {
+            Err(_) => panic!(lformat!("generated HTML was not utf-8 valid")),
+            Ok(res) => Ok((res, title)),
+        }
+    }
+
+    /// Renders the newsletter page, with `epub.newsletter.text` and a link to
+    /// `url`, using the chapter template
+    fn render_newsletter_page(&mut self, template: &Template, url: &str) -> Result<(String, String)> {
+        let lang = self.html.book.options.get_str("lang").unwrap();
+        let title = lang::get_str(lang, "newsletter");
+        let text = match self.html.book.options.get_str("epub.newsletter.text") {
+            Ok(text) => {
+                let tokens = self.html.book.parse_markdown(text)?;
+                self.render_vec(&tokens)?
+            }
+            Err(_) => String::new(),
+        };
+        let content = format!("<h1>{title}</h1>
+{text}
+<p class = \"newsletter\"><a href = \"{url}\">{signup}</a></p>
+",
+                              title = escape::html(title.as_str()),
+                              text = text,
+                              url = escape::html(url),
+                              signup = escape::html(lang::get_str(lang, "newsletter_signup")));
+        let data = self.html
+            .book
+            .get_metadata(|s| self.render_vec(&Parser::new().parse_inline(s)?))?
+            .insert_str("content", content)
+            .insert_str("chapter_title_raw", title.clone())
+            .insert_str("chapter_title", escape::html(title.as_str()).into_owned())
+            .build();
+        let mut res: Vec<u8> = vec![];
+        template.render_data(&mut res, &data)?;
+        match String::from_utf8(res) {
+            Err(_) => panic!(lformat!("generated HTML was not utf-8 valid")),
+            Ok(res) => Ok((res, title)),
+        }
+    }
+
     /// Render cover.xhtml
     fn render_cover(&mut self) -> Result<String> {
         if let Ok(cover) = self.html.book.options.get_path("cover") {
@@ -302,14 +493,24 @@ impl<'a> EpubRenderer<'a> {
     /// Render a chapter
     ///
     /// Return chapter content and raw title
-    pub fn render_chapter(&mut self, v: &[Token], template: &Template) -> Result<(String, String)> {
+    pub fn render_chapter(&mut self,
+                          v: &[Token],
+                          template: &Template,
+                          render_end_notes: bool)
+                          -> Result<(String, String)> {
         let mut content = String::new();
 
         for token in v {
             content.push_str(&self.render_token(token)?);
             self.html.render_side_notes(&mut content);
         }
-        self.html.render_end_notes(&mut content);
+        if render_end_notes {
+            self.html.render_end_notes(&mut content);
+        }
+        if let Ok(watermark) = self.html.book.options.get_str("watermark") {
+            content.push_str(&format!("<p class = \"watermark\">{}</p>\n",
+                                      escape::html(watermark)));
+        }
 
         if self.chapter_title.is_empty() && self.html.current_numbering >= 1 {
             let number;
@@ -453,7 +654,7 @@ impl<'a> EpubRenderer<'a> {
                 let content = if html.verbatim {
                     Cow::Borrowed(text.as_ref())
                 } else {
-                    escape::html(html.book.clean(text.as_ref(), false))
+                    escape::html(html.book.clean_if(html.clean, text.as_ref(), false))
                 };
                 let mut content = if html.first_letter {
                     html.first_letter = false;
@@ -500,13 +701,13 @@ impl<'a> EpubRenderer<'a> {
                     .unwrap() == 3;
                 let inner_content = this.render_vec(vec)?;
                 let html: &mut HtmlRenderer = this.as_mut();
-                html.footnote_number += 1;
-                let number = html.footnote_number;
+                let (number, label) = html.next_footnote();
+                let (dest_link, source_link) = html.footnote_links(number);
                 let note_number = format!("<p class = \"note-number\">
-  <a href = \"#note-source-{}\">[{}]</a>
+  <a href = \"{}\">[{}]</a>
 </p>\n",
-                                          number,
-                                          number);
+                                          source_link,
+                                          label);
                 let inner = if epub3 {
                     format!("<aside epub:type = \"footnote\" id = \"note-dest-{}\">{}</aside>",
                             number,
@@ -516,12 +717,66 @@ impl<'a> EpubRenderer<'a> {
                 };
                 html.add_footnote(note_number, inner);
 
-                Ok(format!("<a {} href = \"#note-dest-{}\"><sup id = \
+                Ok(format!("<a {} href = \"{}\"><sup id = \
                             \"note-source-{}\">[{}]</sup></a>",
                            if epub3 { "epub:type = \"noteref\"" } else { "" },
+                           dest_link,
                            number,
-                           number,
-                           number))
+                           label))
+            }
+            Token::PageBreak(ref page) => {
+                let epub3 = (this.as_ref() as &HtmlRenderer)
+                    .book
+                    .options
+                    .get_i32("epub.version")
+                    .unwrap() == 3;
+                if epub3 {
+                    Ok(format!("<span epub:type = \"pagebreak\" role = \"doc-pagebreak\" \
+                                id = \"page-{page}\" title = \"{page}\"></span>",
+                               page = escape::html(page.as_str())))
+                } else {
+                    HtmlRenderer::static_render_token(this, token)
+                }
+            }
+            Token::Attribution(ref vec) => {
+                let epub3 = (this.as_ref() as &HtmlRenderer)
+                    .book
+                    .options
+                    .get_i32("epub.version")
+                    .unwrap() == 3;
+                if epub3 {
+                    HtmlRenderer::static_render_token(this, token)
+                } else {
+                    // No footer element in EPUB 2
+                    Ok(format!("<p class = \"attribution\">— {}</p>\n", this.render_vec(vec)?))
+                }
+            }
+            Token::MarginNote(ref vec) => {
+                // No margin in EPUB, so margin notes are displayed as footnotes
+                EpubRenderer::static_render_token(this, &Token::Footnote(vec.clone()))
+            }
+            Token::Image(ref url, _, _, ref alt) |
+            Token::StandaloneImage(ref url, _, _, ref alt) => {
+                let epub3 = (this.as_ref() as &HtmlRenderer)
+                    .book
+                    .options
+                    .get_i32("epub.version")
+                    .unwrap() == 3;
+                match images::media_kind(url) {
+                    Some(kind) if !epub3 => {
+                        // No audio or video in EPUB 2, so only display their description
+                        let content = this.render_vec(alt)?;
+                        let html: &HtmlRenderer = this.as_ref();
+                        let lang = html.book.options.get_str("lang").unwrap();
+                        let text = format!("[{}: {}]", lang::get_str(lang, kind), content);
+                        if token.is_image() {
+                            Ok(text)
+                        } else {
+                            Ok(format!("<p class = \"media\">{}</p>\n", text))
+                        }
+                    }
+                    _ => HtmlRenderer::static_render_token(this, token),
+                }
             }
             _ => HtmlRenderer::static_render_token(this, token),
         }
@@ -529,6 +784,55 @@ impl<'a> EpubRenderer<'a> {
 }
 
 
+/// Zip wrapper adjusting the package and navigation documents generated by
+/// epub-builder, which can't set the page progression of the book nor add
+/// a page list
+struct PackageZip<Z: Zip> {
+    inner: Z,
+    vertical: bool,
+    page_list: Option<String>,
+}
+
+impl<Z: Zip> Zip for PackageZip<Z> {
+    fn write_file<P: AsRef<Path>, R: Read>(&mut self, path: P, mut content: R) -> epub_builder::Result<()> {
+        if self.vertical && path.as_ref().ends_with("content.opf") {
+            let mut opf = String::new();
+            content.read_to_string(&mut opf)
+                .map_err(|e| format!("could not read content.opf: {}", e))?;
+            self.inner.write_file(path, vertical_opf(&opf).as_bytes())
+        } else if self.page_list.is_some() && path.as_ref().ends_with("nav.xhtml") {
+            let mut nav = String::new();
+            content.read_to_string(&mut nav)
+                .map_err(|e| format!("could not read nav.xhtml: {}", e))?;
+            let nav = nav_page_list(&nav, self.page_list.as_ref().unwrap());
+            self.inner.write_file(path, nav.as_bytes())
+        } else {
+            self.inner.write_file(path, content)
+        }
+    }
+
+    fn generate<W: Write>(&mut self, to: W) -> epub_builder::Result<()> {
+        self.inner.generate(to)
+    }
+}
+
+/// Sets a right-to-left page progression and a vertical primary writing
+/// mode in a package document, for `writing_mode: vertical-rl`
+pub fn vertical_opf(opf: &str) -> String {
+    opf.replacen("<spine", "<spine page-progression-direction=\"rtl\"", 1)
+        .replacen("</metadata>",
+                  "  <meta name=\"primary-writing-mode\" content=\"vertical-rl\"/>\n  </metadata>",
+                  1)
+}
+
+/// Adds a page list `nav` element at the end of a navigation document
+pub fn nav_page_list(nav: &str, page_list: &str) -> String {
+    match nav.rfind("</body>") {
+        Some(end) => format!("{}{}{}", &nav[..end], page_list, &nav[end..]),
+        None => nav.to_owned(),
+    }
+}
+
 /// Generate a file name given an int
 fn filenamer(i: usize) -> String {
     format!("chapter_{:03}.xhtml", i)
@@ -550,3 +854,59 @@ impl BookRenderer for Epub {
         Ok(())
     }
 }
+
+/// Renders a book to Mobi, by converting its EPUB output with the command
+/// set by `mobi.command` (e.g. Calibre's `ebook-convert` or `kindlegen`)
+pub struct Mobi {}
+
+impl BookRenderer for Mobi {
+    fn auto_path(&self, book_name: &str) -> Result<String> {
+        Ok(format!("{}.mobi", book_name))
+    }
+
+    fn render(&self, book: &Book, to: &mut Write) -> Result<()> {
+        let mut epub = vec![];
+        EpubRenderer::new(book)?
+            .render_book(&mut epub)?;
+
+        let mut zipper = Zipper::new(&book.options.get_path("crowbook.temp_dir").unwrap(),
+                                     &book.logger)?;
+        zipper.write("book.epub", &epub, false)?;
+
+        let command = book.options.get_str("mobi.command").unwrap();
+        let mut args = command.split_whitespace()
+            .map(|arg| {
+                arg.replace("{input}", "book.epub")
+                    .replace("{output}", "book.mobi")
+            });
+        let program = match args.next() {
+            Some(program) => program,
+            None => {
+                return Err(Error::render(&book.source, lformat!("mobi.command is empty")))
+            }
+        };
+        let mut command = Command::new(&program);
+        command.args(args);
+        zipper.run_command(command, &program, "book.mobi", to)?;
+        Ok(())
+    }
+}
+
+/// Returns the page breaks of the print edition in the book, with the index
+/// of the chapter they are in
+fn page_breaks(chapters: &[Chapter]) -> Vec<(usize, String)> {
+    fn visit(i: usize, tokens: &[Token], pages: &mut Vec<(usize, String)>) {
+        for token in tokens {
+            if let Token::PageBreak(ref page) = *token {
+                pages.push((i, page.clone()));
+            } else if let Some(inner) = token.inner() {
+                visit(i, inner, pages);
+            }
+        }
+    }
+    let mut pages = vec![];
+    for (i, chapter) in chapters.iter().enumerate() {
+        visit(i, &chapter.content, &mut pages);
+    }
+    pages
+}
@@ -0,0 +1,149 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Size breakdown of generated EPUB files, and warnings when they are too
+//! big for some stores.
+
+use book::Book;
+
+use std::io;
+use std::io::Write;
+
+/// Number of the biggest images listed when an EPUB file is too big
+const BIGGEST_IMAGES: usize = 3;
+
+/// Size of the parts of an EPUB file, before compression
+pub struct EpubSize {
+    /// Rendered chapters and stylesheets
+    pub text: u64,
+    /// Images, with their size
+    pub images: Vec<(String, u64)>,
+    /// Embedded fonts
+    pub fonts: u64,
+    /// Other additional resources
+    pub other: u64,
+}
+
+impl EpubSize {
+    /// Creates an empty breakdown
+    pub fn new() -> EpubSize {
+        EpubSize {
+            text: 0,
+            images: vec![],
+            fonts: 0,
+            other: 0,
+        }
+    }
+
+    /// Adds a resource to the breakdown, according to its extension
+    pub fn add_resource(&mut self, file: &str, size: u64) {
+        let lower = file.to_lowercase();
+        let ext = lower.rsplit('.').next().unwrap_or("");
+        match ext {
+            "ttf" | "otf" | "woff" | "woff2" => self.fonts += size,
+            "png" | "jpg" | "jpeg" | "gif" | "svg" | "webp" => {
+                self.images.push((file.to_owned(), size))
+            }
+            _ => self.other += size,
+        }
+    }
+
+    /// Returns the total size of images
+    pub fn images_size(&self) -> u64 {
+        self.images.iter().map(|&(_, size)| size).sum()
+    }
+
+    /// Displays the breakdown, and a warning if the size of the (compressed)
+    /// file exceeds `epub.size.warning`
+    pub fn report(&self, book: &Book, total: u64) {
+        book.logger.info(lformat!("EPUB file size: {total} (text: {text}, images: {images}, \
+                                   fonts: {fonts}, other: {other}, before compression)",
+                                  total = human_size(total),
+                                  text = human_size(self.text),
+                                  images = human_size(self.images_size()),
+                                  fonts = human_size(self.fonts),
+                                  other = human_size(self.other)));
+        let limit = book.options.get_f32("epub.size.warning").unwrap();
+        if limit <= 0.0 || (total as f64) <= limit as f64 * 1_000_000.0 {
+            return;
+        }
+        book.logger.warning(lformat!("EPUB file is {size}, more than the {limit} MB set by \
+                                      epub.size.warning: some stores charge delivery fees or \
+                                      have lower royalties for big files",
+                                     size = human_size(total),
+                                     limit = limit));
+        let content = self.text + self.images_size() + self.fonts + self.other;
+        if content > 0 && self.images_size() * 2 > content {
+            let mut images: Vec<&(String, u64)> = self.images.iter().collect();
+            images.sort_by(|a, b| b.1.cmp(&a.1));
+            let biggest: Vec<String> = images.iter()
+                .take(BIGGEST_IMAGES)
+                .map(|&&(ref file, size)| format!("{} ({})", file, human_size(size)))
+                .collect();
+            book.logger.warning(lformat!("Images make up most of this size (biggest: {list}): \
+                                          reducing their resolution, or saving photos as JPEG, \
+                                          would make it smaller",
+                                         list = biggest.join(", ")));
+        }
+        if content > 0 && self.fonts * 4 > content {
+            book.logger.warning(lformat!("Embedded fonts take {size}: subsetting them, or \
+                                          removing them from resources.files, would make the \
+                                          file smaller",
+                                         size = human_size(self.fonts)));
+        }
+    }
+}
+
+/// Formats a size in bytes, e.g. `1.4 MB`
+pub fn human_size(size: u64) -> String {
+    if size >= 1_000_000 {
+        format!("{:.1} MB", size as f64 / 1_000_000.0)
+    } else if size >= 1000 {
+        format!("{:.0} kB", size as f64 / 1000.0)
+    } else {
+        format!("{} B", size)
+    }
+}
+
+/// A writer that counts the bytes written to another one
+pub struct CountingWriter<'a> {
+    inner: &'a mut Write,
+    /// Number of bytes written so far
+    pub count: u64,
+}
+
+impl<'a> CountingWriter<'a> {
+    /// Creates a writer counting the bytes written to `inner`
+    pub fn new(inner: &'a mut Write) -> CountingWriter<'a> {
+        CountingWriter {
+            inner: inner,
+            count: 0,
+        }
+    }
+}
+
+impl<'a> Write for CountingWriter<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
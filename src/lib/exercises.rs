@@ -0,0 +1,103 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Answers of exercises, which can be displayed after them, collected at the
+//! end of the book or removed.
+
+use chapter::Chapter;
+use captions::Caption;
+use token::Token;
+
+use std::mem;
+
+/// Removes the answers from the exercises of these chapters
+///
+/// Returns, for each exercise (in the order they appear in the book, as
+/// listed by `captions::collect`), its answer, if it has one.
+pub fn take_answers(chapters: &mut [Chapter]) -> Vec<Option<Vec<Token>>> {
+    let mut answers = vec![];
+    for chapter in chapters {
+        take(&mut chapter.content, &mut answers);
+    }
+    answers
+}
+
+/// Replaces the answers of the exercises of these chapters by spoiler
+/// blocks with this summary, so they are hidden by default
+pub fn answers_as_spoilers(chapters: &mut [Chapter], summary: &str) {
+    for chapter in chapters {
+        replace(&mut chapter.content, summary);
+    }
+}
+
+/// Returns the content of the "Answers" chapter, listing these answers after
+/// a link to their exercise
+pub fn answers_chapter(title: &str,
+                       lang: &str,
+                       exercises: &[Caption],
+                       answers: Vec<Option<Vec<Token>>>)
+                       -> Vec<Token> {
+    let mut content = vec![Token::Header(1, vec![Token::Str(title.to_owned())])];
+    for (exercise, answer) in exercises.iter().zip(answers) {
+        if let Some(mut answer) = answer {
+            let link = Token::Link(format!("#{}", exercise.id),
+                                   String::new(),
+                                   vec![Token::Str(exercise.label(lang))]);
+            content.push(Token::Paragraph(vec![Token::Strong(vec![link])]));
+            content.append(&mut answer);
+        }
+    }
+    content
+}
+
+fn take(tokens: &mut Vec<Token>, answers: &mut Vec<Option<Vec<Token>>>) {
+    for token in tokens {
+        if let Token::Exercise(_, ref mut content) = *token {
+            let has_answer = match content.last() {
+                Some(&Token::Answer(_)) => true,
+                _ => false,
+            };
+            if has_answer {
+                match content.pop() {
+                    Some(Token::Answer(answer)) => answers.push(Some(answer)),
+                    _ => unreachable!(),
+                }
+            } else {
+                answers.push(None);
+            }
+        }
+        if let Some(inner) = token.inner_mut() {
+            take(inner, answers);
+        }
+    }
+}
+
+fn replace(tokens: &mut Vec<Token>, summary: &str) {
+    for token in tokens {
+        let answer = if let Token::Answer(ref mut content) = *token {
+            Some(mem::replace(content, vec![]))
+        } else {
+            None
+        };
+        if let Some(answer) = answer {
+            *token = Token::Spoiler(summary.to_owned(), answer);
+        }
+        if let Some(inner) = token.inner_mut() {
+            replace(inner, summary);
+        }
+    }
+}
@@ -0,0 +1,191 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Atom and RSS feeds of the chapters of a book rendered as multi-file
+//! HTML, so readers can follow a book that is published serially.
+
+use book::Book;
+use error::Result;
+use misc;
+use opds;
+
+use crowbook_text_processing::escape;
+
+use std::fmt::Write;
+use std::cmp::Ordering;
+
+/// A chapter listed in the feed
+pub struct FeedEntry {
+    /// Title of the chapter, as text
+    pub title: String,
+    /// Name of the HTML file of the chapter
+    pub file: String,
+    /// Publication date of the chapter, if set
+    pub published: Option<String>,
+    /// HTML summary of the chapter, if there is one
+    pub summary: Option<String>,
+    /// HTML content of the chapter
+    pub content: String,
+}
+
+/// Returns an Atom feed listing these chapters, most recent first
+///
+/// Links are relative to `publish.base_url`, if it is set.
+pub fn atom(book: &Book, entries: &[FeedEntry]) -> Result<String> {
+    let title = escape::html(book.options.get_str("title").unwrap());
+    let author = escape::html(book.options.get_str("author").unwrap());
+    let base_url = book.options.get_str("publish.base_url").unwrap_or("");
+    let id = opds::book_id(book);
+    let updated = entries.iter()
+        .filter_map(|e| e.published.as_ref())
+        .max()
+        .map(|date| to_rfc3339(date))
+        .unwrap_or_else(|| to_rfc3339(&misc::today()));
+
+    let mut content = String::new();
+    for entry in sorted(entries) {
+        let updated = entry.published
+            .as_ref()
+            .map(|date| to_rfc3339(date))
+            .unwrap_or_else(|| updated.clone());
+        let summary = match entry.summary {
+            Some(ref summary) => {
+                format!("\n    <summary type = \"html\">{}</summary>",
+                        escape::html(summary.as_str()))
+            }
+            None => String::new(),
+        };
+        write!(content,
+               "  <entry>
+    <title>{title}</title>
+    <id>{id}:{file}</id>
+    <link href = \"{url}\" />
+    <updated>{updated}</updated>{summary}
+    <content type = \"html\">{content}</content>
+  </entry>\n",
+               title = escape::html(entry.title.as_str()),
+               id = id,
+               file = entry.file,
+               url = opds::url(base_url, &entry.file),
+               updated = updated,
+               summary = summary,
+               content = escape::html(entry.content.as_str()))?;
+    }
+
+    Ok(format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<feed xmlns=\"http://www.w3.org/2005/Atom\">
+  <id>{id}:feed</id>
+  <title>{title}</title>
+  <link href = \"{url}\" />
+  <updated>{updated}</updated>
+  <author><name>{author}</name></author>
+{content}</feed>
+",
+            id = id,
+            title = title,
+            url = opds::url(base_url, "index.html"),
+            updated = updated,
+            author = author,
+            content = content))
+}
+
+/// Returns a RSS 2.0 feed listing these chapters, most recent first
+///
+/// Links are relative to `publish.base_url`, if it is set. Chapters are
+/// described by their summaries, if they have one.
+pub fn rss(book: &Book, entries: &[FeedEntry]) -> Result<String> {
+    let base_url = book.options.get_str("publish.base_url").unwrap_or("");
+    let id = opds::book_id(book);
+
+    let mut content = String::new();
+    for entry in sorted(entries) {
+        let date = match entry.published {
+            Some(ref date) => format!("\n      <pubDate>{}</pubDate>", to_rfc822(date)),
+            None => String::new(),
+        };
+        write!(content,
+               "    <item>
+      <title>{title}</title>
+      <link>{url}</link>
+      <guid isPermaLink = \"false\">{id}:{file}</guid>{date}
+      <description>{content}</description>
+    </item>\n",
+               title = escape::html(entry.title.as_str()),
+               url = opds::url(base_url, &entry.file),
+               id = id,
+               file = entry.file,
+               date = date,
+               content = escape::html(entry.summary.as_ref().unwrap_or(&entry.content).as_str()))?;
+    }
+
+    Ok(format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<rss version=\"2.0\">
+  <channel>
+    <title>{title}</title>
+    <link>{url}</link>
+    <description>{description}</description>
+    <language>{lang}</language>
+{content}  </channel>
+</rss>
+",
+            title = escape::html(book.options.get_str("title").unwrap()),
+            url = opds::url(base_url, "index.html"),
+            description = escape::html(book.options
+                                           .get_str("description")
+                                           .or_else(|_| book.options.get_str("subtitle"))
+                                           .unwrap_or("")),
+            lang = escape::html(book.options.get_str("lang").unwrap()),
+            content = content))
+}
+
+/// Returns the entries sorted by publication date, most recent first
+///
+/// Entries without a date keep their order in the book, after the others.
+fn sorted(entries: &[FeedEntry]) -> Vec<&FeedEntry> {
+    let mut entries: Vec<&FeedEntry> = entries.iter().collect();
+    entries.sort_by(|a, b| match (a.published.as_ref(), b.published.as_ref()) {
+        (Some(a), Some(b)) => b.cmp(a),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    });
+    entries
+}
+
+/// Converts a date such as `2017-05-12` to the format used by Atom
+fn to_rfc3339(date: &str) -> String {
+    if date.len() == 10 {
+        format!("{}T00:00:00Z", date)
+    } else {
+        date.to_owned()
+    }
+}
+
+/// Converts a date such as `2017-05-12` to the format used by RSS
+fn to_rfc822(date: &str) -> String {
+    static MONTHS: &'static [&'static str] = &["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul",
+                                               "Aug", "Sep", "Oct", "Nov", "Dec"];
+    let parts: Vec<&str> = date.split('-').collect();
+    if parts.len() == 3 {
+        if let (Ok(month), Ok(day)) = (parts[1].parse::<usize>(), parts[2].parse::<u32>()) {
+            if month >= 1 && month <= 12 {
+                return format!("{:02} {} {} 00:00:00 +0000", day, MONTHS[month - 1], parts[0]);
+            }
+        }
+    }
+    date.to_owned()
+}
@@ -0,0 +1,130 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Information about the git repository containing a book, so builds can be
+//! tied to an exact revision of its sources.
+
+use std::path::Path;
+use std::process::Command;
+
+/// State of the git repository containing a book
+#[derive(Debug, Clone, PartialEq)]
+pub struct GitInfo {
+    /// Hash of the current commit
+    pub commit: String,
+    /// Abbreviated hash of the current commit
+    pub short_commit: String,
+    /// Tag pointing to the current commit, if any
+    pub tag: Option<String>,
+    /// Whether there are uncommitted changes to tracked files
+    pub dirty: bool,
+}
+
+impl GitInfo {
+    /// Returns information about the git repository containing `root`, or
+    /// `None` if it isn't in a git repository (or if git isn't installed)
+    pub fn new(root: &Path) -> Option<GitInfo> {
+        let commit = match git(root, &["rev-parse", "HEAD"]) {
+            Some(commit) => commit,
+            None => return None,
+        };
+        let short_commit = git(root, &["rev-parse", "--short", "HEAD"]).unwrap_or_else(|| {
+            commit.chars().take(7).collect()
+        });
+        let tag = git(root, &["describe", "--tags", "--exact-match", "HEAD"]);
+        let dirty = git(root, &["status", "--porcelain", "--untracked-files=no"])
+            .map(|s| !s.is_empty())
+            .unwrap_or(false);
+        Some(GitInfo {
+            commit: commit,
+            short_commit: short_commit,
+            tag: tag,
+            dirty: dirty,
+        })
+    }
+
+    /// Returns a short description of the revision, e.g. `v1.2` or
+    /// `3f2a1bc-dirty`
+    pub fn revision(&self) -> String {
+        let mut res = self.tag.clone().unwrap_or_else(|| self.short_commit.clone());
+        if self.dirty {
+            res.push_str("-dirty");
+        }
+        res
+    }
+}
+
+/// A commit of the git history
+#[derive(Debug, Clone, PartialEq)]
+pub struct Commit {
+    /// Abbreviated hash of the commit
+    pub short_commit: String,
+    /// Date of the commit (YYYY-MM-DD)
+    pub date: String,
+    /// Name of the author of the commit
+    pub author: String,
+    /// First line of the commit message
+    pub subject: String,
+}
+
+/// Returns the (at most `max`) last commits that modified one of `files`,
+/// most recent first, or `None` if `root` isn't in a git repository
+pub fn log(root: &Path, files: &[&str], max: i32) -> Option<Vec<Commit>> {
+    let max = format!("--max-count={}", max);
+    let mut args = vec!["log", "--date=short", "--format=%h%x1f%ad%x1f%an%x1f%s", &max, "--"];
+    args.extend_from_slice(files);
+    let output = match git(root, &args) {
+        Some(output) => output,
+        None => return None,
+    };
+    Some(parse_log(&output))
+}
+
+/// Parses the output of `git log --format=%h%x1f%ad%x1f%an%x1f%s`, ignoring
+/// malformed lines
+pub fn parse_log(output: &str) -> Vec<Commit> {
+    output.lines()
+        .filter_map(|line| {
+            let fields: Vec<_> = line.split('\x1f').collect();
+            if fields.len() == 4 {
+                Some(Commit {
+                    short_commit: fields[0].to_owned(),
+                    date: fields[1].to_owned(),
+                    author: fields[2].to_owned(),
+                    subject: fields[3].to_owned(),
+                })
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Runs a git command in `root`, and returns its (trimmed) output if it succeeds
+pub fn git(root: &Path, args: &[&str]) -> Option<String> {
+    let root = if root.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        root
+    };
+    match Command::new("git").current_dir(root).args(args).output() {
+        Ok(ref output) if output.status.success() => {
+            Some(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+        }
+        _ => None,
+    }
+}
@@ -132,6 +132,7 @@ impl GrammarChecker {
                     Token::Paragraph(ref mut v) |
                     Token::Header(_, ref mut v) |
                     Token::BlockQuote(ref mut v) |
+                    Token::Verse(ref mut v) |
                     Token::List(ref mut v) |
                     Token::OrderedList(_, ref mut v) => {
                         let check = self.check(&view_as_text(v))?;
@@ -0,0 +1,162 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Normalization of the capitalization of headings, according to the
+//! `rendering.headings.case` option.
+
+use book::Book;
+use error::{Error, Result, Source};
+use token::Token;
+use text_view::view_as_text;
+
+use std::collections::HashSet;
+
+/// Words that are not capitalized in English title case, unless they are
+/// the first or last word of a title
+static SMALL_WORDS: &'static [&'static str] = &["a", "an", "the", "and", "but", "or", "nor",
+                                                 "for", "so", "yet", "as", "at", "by", "in",
+                                                 "of", "off", "on", "per", "to", "up", "via",
+                                                 "vs", "from", "into", "with"];
+
+struct State<'a> {
+    /// Index of the current word in the heading
+    index: usize,
+    /// Number of words in the heading
+    total: usize,
+    /// Whether the previous word ended with a colon
+    after_colon: bool,
+    /// Use title case, instead of sentence case
+    title: bool,
+    /// Words of `names.*` keys, that must keep their capitalization
+    names: &'a HashSet<&'a str>,
+}
+
+/// Normalizes the capitalization of the headings of a chapter, if
+/// `rendering.headings.case` is set to `title` or `sentence`
+///
+/// Title case follows English rules, so other languages, which capitalize
+/// titles like sentences, get sentence case. Words containing capitals
+/// after their first letter (e.g. acronyms) and names set with `names.*`
+/// keys are left as they are.
+pub fn normalize(book: &Book, tokens: &mut Vec<Token>, source: &Source) -> Result<()> {
+    let case = match book.options.get_str("rendering.headings.case") {
+        Ok(case) => case,
+        Err(_) => return Ok(()),
+    };
+    let lang = book.options.get_str("lang").unwrap().to_lowercase();
+    let title = match case {
+        "title" => lang.starts_with("en"),
+        "sentence" => false,
+        _ => {
+            return Err(Error::config_parser(source,
+                                            lformat!("rendering.headings.case set to '{value}', \
+                                                      expected 'title' or 'sentence'",
+                                                     value = case)))
+        }
+    };
+    let names = book.options.get_names();
+    let names: HashSet<&str> = names.iter()
+        .flat_map(|&(name, _)| name.split_whitespace())
+        .collect();
+    for token in tokens {
+        if let Token::Header(_, ref mut inner) = *token {
+            let mut state = State {
+                index: 0,
+                total: view_as_text(inner).split_whitespace().count(),
+                after_colon: false,
+                title: title,
+                names: &names,
+            };
+            visit(inner, &mut state);
+        }
+    }
+    Ok(())
+}
+
+fn visit(tokens: &mut Vec<Token>, state: &mut State) {
+    for token in tokens {
+        match *token {
+            Token::Str(ref mut s) => *s = convert(s, state),
+            Token::Code(..) => {}
+            ref mut token => {
+                if let Some(inner) = token.inner_mut() {
+                    visit(inner, state);
+                }
+            }
+        }
+    }
+}
+
+fn convert(s: &str, state: &mut State) -> String {
+    let mut res = String::with_capacity(s.len());
+    let mut word = String::new();
+    for c in s.chars().chain(Some(' ')) {
+        if !c.is_whitespace() {
+            word.push(c);
+            continue;
+        }
+        if !word.is_empty() {
+            res.push_str(&convert_word(&word, state));
+            state.after_colon = word.ends_with(':');
+            state.index += 1;
+            word.clear();
+        }
+        res.push(c);
+    }
+    // Remove the space that was added to handle the last word
+    res.pop();
+    res
+}
+
+fn convert_word(word: &str, state: &State) -> String {
+    let first = state.index == 0 || state.after_colon;
+    let last = state.index + 1 == state.total;
+    let bare = word.trim_matches(|c: char| !c.is_alphanumeric());
+    if first {
+        capitalize(word)
+    } else if state.names.contains(bare) || has_inner_capitals(word) {
+        word.to_owned()
+    } else if state.title {
+        if !last && SMALL_WORDS.contains(&bare.to_lowercase().as_str()) {
+            word.to_lowercase()
+        } else {
+            capitalize(word)
+        }
+    } else {
+        word.to_lowercase()
+    }
+}
+
+/// Returns true if the word contains capitals after its first letter
+fn has_inner_capitals(word: &str) -> bool {
+    word.chars().filter(|c| c.is_alphabetic()).skip(1).any(|c| c.is_uppercase())
+}
+
+/// Puts the first letter of the word in upper case
+fn capitalize(word: &str) -> String {
+    let mut res = String::with_capacity(word.len());
+    let mut done = false;
+    for c in word.chars() {
+        if !done && c.is_alphabetic() {
+            res.extend(c.to_uppercase());
+            done = true;
+        } else {
+            res.push(c);
+        }
+    }
+    res
+}
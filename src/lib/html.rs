@@ -25,16 +25,19 @@ use number::Number;
 use resource_handler::ResourceHandler;
 use renderer::Renderer;
 use parser::Parser;
-use syntax::Syntax;
+use syntax::{Syntax, CodeOptions, strip_language};
+use captions::{Captions, CaptionKind};
+use sections::{Sections, Section};
+use images::{self, ImageOptions};
 use logger::Logger;
 use lang;
+use misc;
 
 use std::borrow::Cow;
 use std::convert::{AsMut, AsRef};
 use std::fmt::Write;
 
 use crowbook_text_processing::escape;
-use numerals::roman::Roman;
 use epub_builder::Toc;
 use epub_builder::TocElement;
 use mustache::Template;
@@ -60,7 +63,8 @@ pub struct HtmlRenderer<'a> {
     #[doc(hidden)]
     pub first_letter: bool,
     first_paragraph: bool,
-    footnotes: Vec<(String, String)>,
+    /// Footnotes to render, with a flag set to true for margin notes
+    footnotes: Vec<(String, String, bool)>,
     filename: String,
 
     /// Book that must be rendered
@@ -85,6 +89,10 @@ pub struct HtmlRenderer<'a> {
     #[doc(hidden)]
     pub current_part: bool,
 
+    /// Whether current chapter is listed in the table of contents
+    #[doc(hidden)]
+    pub current_toc: bool,
+
     /// Resource handler
     #[doc(hidden)]
     pub handler: ResourceHandler<'a>,
@@ -93,6 +101,32 @@ pub struct HtmlRenderer<'a> {
     #[doc(hidden)]
     pub footnote_number: u32,
 
+    /// Displayed footnote number (differs from `footnote_number` if it
+    /// is reset at each chapter)
+    #[doc(hidden)]
+    pub footnote_counter: u32,
+
+    /// Footnote numbering style (`numbers` or `symbols`)
+    #[doc(hidden)]
+    pub footnote_style: &'a str,
+
+    /// Current margin note number, and displayed one (margin notes have
+    /// their own sequence, so they don't shift footnote numbers)
+    margin_note_number: u32,
+    margin_note_counter: u32,
+
+    /// Whether text is typographically cleaned
+    #[doc(hidden)]
+    pub clean: bool,
+
+    /// Where notes are displayed (`footnotes`, `end_of_chapter` or `end_of_book`)
+    #[doc(hidden)]
+    pub notes: &'a str,
+
+    /// File where notes are displayed, if it is not the current one
+    #[doc(hidden)]
+    pub notes_filename: String,
+
     /// Source for error messages
     #[doc(hidden)]
     pub source: Source,
@@ -108,6 +142,27 @@ pub struct HtmlRenderer<'a> {
     #[doc(hidden)]
     pub link_number: u32,
 
+    /// Numbered elements (listings, figures, tables) of the book
+    #[doc(hidden)]
+    pub captions: Captions,
+
+    /// Sections (`##`, `###`, ...) of the book, with their numbers and ids
+    #[doc(hidden)]
+    pub sections: Sections,
+
+    /// Section currently rendered, if the current title is a section's one
+    #[doc(hidden)]
+    pub current_section: Option<Section>,
+
+    /// Widths (in pixels) of the resized variants of images, used to set
+    /// their `srcset` attribute. Empty if images are not resized.
+    #[doc(hidden)]
+    pub srcset: Vec<u32>,
+
+    /// Value of the `sizes` attribute of resized images
+    #[doc(hidden)]
+    pub sizes: String,
+
     syntax: Option<Syntax>,
 
     part_template_html: Template,
@@ -143,13 +198,26 @@ impl<'a> HtmlRenderer<'a> {
             book: book,
             toc: Toc::new(),
             link_number: 0,
+            captions: Captions::new(book),
+            sections: Sections::new(book),
+            current_section: None,
+            srcset: vec![],
+            sizes: String::new(),
             current_chapter: [0, 0, 0, 0, 0, 0, 0],
             current_numbering: book.options.get_i32("rendering.num_depth").unwrap(),
             current_part: false,
             current_par: 0,
             current_hide: false,
+            current_toc: true,
             table_head: false,
             footnote_number: 0,
+            footnote_counter: 0,
+            footnote_style: book.footnote_style("html")?,
+            margin_note_number: 0,
+            margin_note_counter: 0,
+            clean: book.cleans("html"),
+            notes: book.notes_mode()?,
+            notes_filename: String::new(),
             footnotes: vec![],
             verbatim: false,
             filename: String::new(),
@@ -180,7 +248,53 @@ impl<'a> HtmlRenderer<'a> {
     /// Add a footnote which will be renderer later on
     #[doc(hidden)]
     pub fn add_footnote(&mut self, number: String, content: String) {
-        self.footnotes.push((number, content));
+        self.footnotes.push((number, content, false));
+    }
+
+    /// Increases footnote numbers, and returns the number (used for links)
+    /// and the label of the new footnote
+    #[doc(hidden)]
+    pub fn next_footnote(&mut self) -> (u32, String) {
+        self.footnote_number += 1;
+        self.footnote_counter += 1;
+        let label = if self.footnote_style == "symbols" {
+            misc::footnote_symbol(self.footnote_counter)
+        } else {
+            format!("{}", self.footnote_counter)
+        };
+        (self.footnote_number, label)
+    }
+
+    /// Increases margin note numbers, and returns the number (used for
+    /// links) and the label (`a`, `b`...) of the new margin note
+    fn next_margin_note(&mut self) -> (u32, String) {
+        self.margin_note_number += 1;
+        self.margin_note_counter += 1;
+        let mut n = self.margin_note_counter;
+        let mut label = vec![];
+        while n > 0 {
+            n -= 1;
+            label.push((b'a' + (n % 26) as u8) as char);
+            n /= 26;
+        }
+        (self.margin_note_number, label.into_iter().rev().collect())
+    }
+
+    /// Returns the links to a footnote and back to its reference
+    #[doc(hidden)]
+    pub fn footnote_links(&self, number: u32) -> (String, String) {
+        self.note_links("note", number)
+    }
+
+    /// Returns the links to a note (whose ids start with `kind`) and back to
+    /// its reference
+    fn note_links(&self, kind: &str, number: u32) -> (String, String) {
+        if self.notes_filename.is_empty() {
+            (format!("#{}-dest-{}", kind, number), format!("#{}-source-{}", kind, number))
+        } else {
+            (format!("{}#{}-dest-{}", self.notes_filename, kind, number),
+             format!("{}#{}-source-{}", self.filename, kind, number))
+        }
     }
 
     /// Configure the Renderer for this chapter
@@ -188,10 +302,16 @@ impl<'a> HtmlRenderer<'a> {
     pub fn chapter_config(&mut self, i: usize, n: Number, filename: String) {
         self.source = Source::new(self.book.chapters[i].filename.as_str());
         self.first_paragraph = true;
-        self.current_hide = false;
+        if self.book.options.get_bool("rendering.footnotes.reset") == Ok(true) {
+            self.footnote_counter = 0;
+            self.margin_note_counter = 0;
+        }
+        self.current_hide = self.book.chapters[i].hide_title;
         let book_numbering = self.book.options.get_i32("rendering.num_depth").unwrap();
         match n {
-            Number::Unnumbered | Number::UnnumberedPart => self.current_numbering = 0,
+            Number::Hidden | Number::Unnumbered | Number::UnnumberedPart => {
+                self.current_numbering = 0
+            }
             Number::Default | Number::DefaultPart => self.current_numbering = book_numbering,
             Number::Specified(n) => {
                 self.current_numbering = book_numbering;
@@ -201,13 +321,10 @@ impl<'a> HtmlRenderer<'a> {
                 self.current_numbering = book_numbering;
                 self.current_chapter[0] = n - 1;
             }
-            Number::Hidden => {
-                self.current_numbering = 0;
-                self.current_hide = true;
-            },
         } //          _ => panic!("Parts are not supported yet"),
         self.current_part = n.is_part();
-        
+        self.current_toc = self.book.chapters[i].toc;
+
         self.filename = filename;
     }
 
@@ -235,7 +352,12 @@ impl<'a> HtmlRenderer<'a> {
             n
         };
         self.inc_header(n);
-        
+        self.current_section = if n >= 2 {
+            self.sections.next()
+        } else {
+            None
+        };
+
         let number = self.current_chapter[n as usize];
         let c_title = self.render_vec(vec)?;
         
@@ -250,8 +372,8 @@ impl<'a> HtmlRenderer<'a> {
                     let mut parser = Parser::from(&self.book);
                     self.render_vec(&parser.parse_inline(s)?)
                 })
-        } else if self.current_numbering >= n {
-            let numbers = self.get_numbers();
+        } else if self.current_numbering >= n && self.current_section.is_some() {
+            let numbers = format!("{}.", self.current_section.as_ref().unwrap().number);
             Ok(HeaderData {
                 text: format!("{} {}", numbers, c_title),
                 number: numbers,
@@ -295,11 +417,21 @@ impl<'a> HtmlRenderer<'a> {
                 Ok(String::from_utf8(res)?)
             }
         } else {
-            Ok(format!("<h{} id = \"link-{}\">{}</h{}>\n",
+            Ok(format!("<h{} id = \"{}\">{}</h{}>\n",
                        n,
-                       self.link_number,
+                       self.anchor(),
                        data.text,
-                       n))                
+                       n))
+        }
+    }
+
+    /// Returns the id of the current title: the one of the section if it is
+    /// a section's title, `link-N` else
+    #[doc(hidden)]
+    pub fn anchor(&self) -> String {
+        match self.current_section {
+            Some(ref section) => section.id.clone(),
+            None => format!("link-{}", self.link_number),
         }
     }
 
@@ -327,43 +459,18 @@ impl<'a> HtmlRenderer<'a> {
         self.link_number += 1;
     }
 
-    /// Returns a "x.y.z" corresponding to current chapter/section/...
-    fn get_numbers(&self) -> String {
-        let mut output = String::new();
-        for i in 1..self.current_chapter.len() {
-            if self.current_chapter[i] == 0 {
-                if i == self.current_chapter.len() - 1 {
-                    break;
-                }
-                let bools: Vec<_> = self.current_chapter[i + 1..].iter().map(|x| *x != 0).collect();
-                if !bools.contains(&true) {
-                    break;
-                }
-            }
-            if i != 1 || !self.book.options.get_bool("rendering.chapter.roman_numerals").unwrap() {
-                write!(output, "{}.", self.current_chapter[i]).unwrap(); //todo
-            } else if self.current_chapter[i] >= 1 {
-                write!(output,
-                       "{:X}.",
-                       Roman::from(self.current_chapter[i] as i16)).unwrap();
-            } else {
-                self.book.logger.error(lformat!("can not use roman numerals with zero or negative chapter numbers ({n})",
-                                                    n = self.current_chapter[i]));
-            }
-        }
-        output
-    }
-
-
     /// Display side notes if option is to true
     #[doc(hidden)]
     pub fn render_side_notes(&mut self, res: &mut String) {
-        if self.book.options.get_bool("html.side_notes").unwrap() {
-            for (note_number, footnote) in self.footnotes.drain(..) {
-                write!(res,
-                       "<div class = \"sidenote\">\n{} {}\n</div>\n",
-                       note_number,
-                       footnote).unwrap();
+        if self.book.options.get_bool("html.side_notes").unwrap() && self.notes == "footnotes" {
+            for (note_number, footnote, margin) in self.footnotes.drain(..) {
+                // Margin notes are already displayed in the margin
+                if !margin {
+                    write!(res,
+                           "<div class = \"sidenote\">\n{} {}\n</div>\n",
+                           note_number,
+                           footnote).unwrap();
+                }
             }
         }
     }
@@ -383,15 +490,18 @@ impl<'a> HtmlRenderer<'a> {
             //             }
 
 
+            // Margin notes are only displayed here when they can't be displayed in the margin
+            let only_margin = self.footnotes.iter().all(|note| note.2);
             write!(res,
-                   "<div class = \"notes\">
+                   "<div class = \"notes{}\">
  <h2 class = \"notes\">{}</h2>\n",
+                   if only_margin { " marginnote-fallback" } else { "" },
                    lang::get_str(self.book.options.get_str("lang").unwrap(),
                                  "notes")).unwrap();
             res.push_str("<table class = \"notes\">\n");
-            for (note_number, footnote) in self.footnotes.drain(..) {
+            for (note_number, footnote, margin) in self.footnotes.drain(..) {
                 write!(res,
-                       "<tr class = \"notes\">
+                       "<tr class = \"notes{}\">
  <td class = \"note-number\">
   {}
  </td>
@@ -399,6 +509,7 @@ impl<'a> HtmlRenderer<'a> {
   {}
   </td>
 </tr>\n",
+                                      if margin { " marginnote-fallback" } else { "" },
                                       note_number,
                                       footnote).unwrap();
             }
@@ -449,7 +560,7 @@ impl<'a> HtmlRenderer<'a> {
                 let mut content = if this.as_ref().verbatim {
                     Cow::Borrowed(text.as_ref())
                 } else {
-                    escape::html(this.as_ref().book.clean(text.as_ref(), false))
+                    escape::html(this.as_ref().book.clean_if(this.as_ref().clean, text.as_ref(), false))
                 };
                 if this.as_ref().first_letter {
                     this.as_mut().first_letter = false;
@@ -481,10 +592,10 @@ impl<'a> HtmlRenderer<'a> {
             }
             Token::Header(n, ref vec) => {
                 let data = this.as_mut().render_title(n, vec)?;
-                if n <= this.as_ref().book.options.get_i32("rendering.num_depth").unwrap() {
-                    let url = format!("{}#link-{}",
+                if this.as_ref().current_toc && n <= this.as_ref().book.toc_depth() {
+                    let url = format!("{}#{}",
                                       this.as_ref().filename,
-                                      this.as_ref().link_number);
+                                      this.as_ref().anchor());
                     if !this.as_ref().current_part {
                         this.as_mut().toc.add(TocElement::new(url, data.text.clone())
                                               .level(n));
@@ -501,27 +612,198 @@ impl<'a> HtmlRenderer<'a> {
             Token::Code(ref vec) => Ok(format!("<code>{}</code>", this.render_vec(vec)?)),
             Token::Subscript(ref vec) => Ok(format!("<sub>{}</sub>", this.render_vec(vec)?)),
             Token::Superscript(ref vec) => Ok(format!("<sup>{}</sup>", this.render_vec(vec)?)),
+            Token::Abbreviation(ref title, ref vec) => {
+                Ok(format!("<abbr title = \"{}\">{}</abbr>",
+                           escape::html(title.as_str()),
+                           this.render_vec(vec)?))
+            }
             Token::BlockQuote(ref vec) => {
                 Ok(format!("<blockquote>{}</blockquote>\n", this.render_vec(vec)?))
             }
+            Token::Attribution(ref vec) => {
+                Ok(format!("<footer>— {}</footer>\n", this.render_vec(vec)?))
+            }
             Token::CodeBlock(ref language, ref vec) => {
                 this.as_mut().verbatim = true;
                 let s = this.render_vec(vec)?;
-                let output = if let Some(ref syntax) = this.as_ref().syntax {
+                let options = CodeOptions::parse(language).unwrap_or_default();
+                let output = if options.is_set() {
+                    let lines = if let Some(ref syntax) = this.as_ref().syntax {
+                        syntax.to_html_lines(&s, language)?
+                    } else {
+                        s.lines().map(|line| escape::html(line).into_owned()).collect()
+                    };
+                    let mut content = String::new();
+                    for (i, line) in lines.iter().enumerate() {
+                        let n = i + 1;
+                        let class = if options.is_highlighted(n) {
+                            "code-line highlighted"
+                        } else {
+                            "code-line"
+                        };
+                        let number = if options.line_numbers {
+                            format!("<span class = \"line-number\">{}</span>", n)
+                        } else {
+                            String::new()
+                        };
+                        content.push_str(&format!("<span class = \"{class}\">{number}{line}\n</span>",
+                                                  class = class,
+                                                  number = number,
+                                                  line = line));
+                    }
+                    // "nohighlight" prevents highlight.js from messing with line numbers
+                    format!("<pre class = \"code-lines\"><code class = \"nohighlight\">{}</code></pre>\n",
+                            content)
+                } else if let Some(ref syntax) = this.as_ref().syntax {
                     syntax.to_html(&s, language)?
                 } else if language.is_empty() {
                     format!("<pre><code>{}</code></pre>\n", s)
                 } else {
                     format!("<pre><code class = \"language-{}\">{}</code></pre>\n",
-                            language,
+                            strip_language(language),
                             escape::html(s))
                 };
                 this.as_mut().verbatim = false;
-                Ok(output)
+                if !options.is_numbered() {
+                    return Ok(output);
+                }
+
+                // Numbered listing: add a caption and an anchor
+                let listing = this.as_mut().captions.next(CaptionKind::Listing);
+                if let Some(listing) = listing {
+                    let lang = this.as_ref().book.options.get_str("lang").unwrap();
+                    let caption = if listing.caption.is_empty() {
+                        String::new()
+                    } else {
+                        let tokens = Parser::new().parse_inline(&listing.caption)?;
+                        format!(": {}", this.render_vec(&tokens)?)
+                    };
+                    Ok(format!("<div class = \"listing\" id = \"{id}\">
+<p class = \"listing-caption\">{label}{caption}</p>
+{code}</div>\n",
+                               id = escape::html(listing.id.as_str()),
+                               label = listing.label(lang),
+                               caption = caption,
+                               code = output))
+                } else {
+                    Ok(output)
+                }
+            }
+            Token::Verse(ref vec) => {
+                // Stanzas are not "real" paragraphs, so they must not get initials
+                let first_paragraph = this.as_ref().first_paragraph;
+                this.as_mut().first_paragraph = false;
+                let content = this.render_vec(vec)?;
+                this.as_mut().first_paragraph = first_paragraph;
+                Ok(format!("<div class = \"verse\">\n{}</div>\n", content))
+            }
+            Token::Spoiler(ref summary, ref vec) => {
+                let first_paragraph = this.as_ref().first_paragraph;
+                this.as_mut().first_paragraph = false;
+                let content = this.render_vec(vec)?;
+                this.as_mut().first_paragraph = first_paragraph;
+                let summary = if summary.is_empty() {
+                    let book = this.as_ref().book;
+                    lang::get_str(book.options.get_str("lang").unwrap(), "solution")
+                } else {
+                    this.render_vec(&Parser::new().parse_inline(summary)?)?
+                };
+                Ok(format!("<details class = \"spoiler\">\n<summary>{}</summary>\n{}</details>\n",
+                           summary,
+                           content))
+            }
+            Token::Admonition(ref kind, ref title, ref vec) => {
+                let first_paragraph = this.as_ref().first_paragraph;
+                this.as_mut().first_paragraph = false;
+                let content = this.render_vec(vec)?;
+                this.as_mut().first_paragraph = first_paragraph;
+                let title = if title.is_empty() {
+                    let book = this.as_ref().book;
+                    escape::html(lang::get_str(book.options.get_str("lang").unwrap(), kind))
+                        .into_owned()
+                } else {
+                    this.render_vec(&Parser::new().parse_inline(title)?)?
+                };
+                Ok(format!("<div class = \"admonition {}\">\n<p class = \"admonition-title\">{}</p>\n{}</div>\n",
+                           kind,
+                           title,
+                           content))
+            }
+            Token::Columns(count, ref vec) => {
+                let first_paragraph = this.as_ref().first_paragraph;
+                this.as_mut().first_paragraph = false;
+                let content = this.render_vec(vec)?;
+                this.as_mut().first_paragraph = first_paragraph;
+                Ok(format!("<div class = \"columns\" style = \"-webkit-column-count: {count}; \
+                            column-count: {count}\">\n{content}</div>\n",
+                           count = count,
+                           content = content))
+            }
+            Token::Exercise(_, ref vec) => {
+                let exercise = this.as_mut().captions.next(CaptionKind::Exercise);
+                let content = this.render_vec(vec)?;
+                match exercise {
+                    Some(exercise) => {
+                        let lang = this.as_ref().book.options.get_str("lang").unwrap();
+                        Ok(format!("<div class = \"exercise\" id = \"{id}\">
+<p class = \"exercise-label\">{label}</p>
+{content}</div>\n",
+                                   id = escape::html(exercise.id.as_str()),
+                                   label = exercise.label(lang),
+                                   content = content))
+                    }
+                    None => Ok(format!("<div class = \"exercise\">\n{}</div>\n", content)),
+                }
+            }
+            Token::Answer(ref vec) => this.render_vec(vec),
+            Token::Epigraph(ref author, ref vec) => {
+                let first_paragraph = this.as_ref().first_paragraph;
+                this.as_mut().first_paragraph = false;
+                let content = this.render_vec(vec)?;
+                this.as_mut().first_paragraph = first_paragraph;
+                let author = if author.is_empty() {
+                    String::new()
+                } else {
+                    format!("<p class = \"epigraph-author\">— {}</p>\n",
+                            this.render_vec(&Parser::new().parse_inline(author)?)?)
+                };
+                Ok(format!("<div class = \"epigraph\">\n{}{}</div>\n", content, author))
+            }
+            Token::Rule => {
+                let book = this.as_ref().book;
+                match book.options.get_str("rendering.scene_break").unwrap() {
+                    "asterisks" => Ok(String::from("<p class = \"rule\">***</p>\n")),
+                    "asterism" => Ok(String::from("<p class = \"rule\">⁂</p>\n")),
+                    "blank" => Ok(String::from("<p class = \"rule blank\">&#160;</p>\n")),
+                    "ornament" => {
+                        let html: &mut HtmlRenderer = this.as_mut();
+                        let ornament = book.options
+                            .get_path("rendering.scene_break.ornament")
+                            .map_err(|_| {
+                                Error::render(&html.source,
+                                              lformat!("rendering.scene_break is set to 'ornament' \
+                                                        but rendering.scene_break.ornament is not set"))
+                            })?;
+                        let url = html.handler.map_image(&html.source, ornament)?;
+                        Ok(format!("<p class = \"rule\"><img src = \"{}\" alt = \"***\" /></p>\n",
+                                   url))
+                    }
+                    value => Err(Error::render(&this.as_ref().source,
+                                               lformat!("rendering.scene_break set to '{value}', \
+                                                         not a valid value",
+                                                        value = value))),
+                }
             }
-            Token::Rule => Ok(String::from("<p class = \"rule\">***</p>\n")),
             Token::SoftBreak => Ok(String::from(" ")),
             Token::HardBreak => Ok(String::from("<br />\n")),
+            Token::PageBreak(ref page) => {
+                Ok(format!("<span class = \"pagebreak\" id = \"page-{}\"></span>",
+                           escape::html(page.as_str())))
+            }
+            Token::NewPage => Ok(String::from("<div class = \"page-break\"></div>\n")),
+            Token::KeepTogether(ref vec) => {
+                Ok(format!("<div class = \"keep-together\">\n{}</div>\n", this.render_vec(vec)?))
+            }
             Token::List(ref vec) => Ok(format!("<ul>\n{}</ul>\n", this.render_vec(vec)?)),
             Token::OrderedList(n, ref vec) => {
                 Ok(format!("<ol{}>\n{}</ol>\n",
@@ -534,9 +816,28 @@ impl<'a> HtmlRenderer<'a> {
             }
             Token::Item(ref vec) => Ok(format!("<li>{}</li>\n", this.render_vec(vec)?)),
             Token::Link(ref url, ref title, ref vec) => {
+                let content = if vec.is_empty() {
+                    // Link to a numbered element without text: use its label
+                    let lang = this.as_ref().book.options.get_str("lang").unwrap();
+                    if url.starts_with('#') {
+                        let id = &url[1..];
+                        match this.as_ref().captions.find(id) {
+                            Some(caption) => caption.label(lang),
+                            None => {
+                                this.as_ref().sections.find(id)
+                                    .map(|section| section.label(lang))
+                                    .unwrap_or_default()
+                            }
+                        }
+                    } else {
+                        String::new()
+                    }
+                } else {
+                    this.render_vec(vec)?
+                };
                 let url = escape::html(url.as_ref());
                 let url = if ResourceHandler::is_local(&url) {
-                    Cow::Owned(this.as_ref().handler.get_link(&url).to_owned())
+                    Cow::Owned(this.as_ref().handler.get_link(&url).into_owned())
                 } else {
                     url
                 };
@@ -548,36 +849,137 @@ impl<'a> HtmlRenderer<'a> {
                            } else {
                                format!(" title = \"{}\"", title)
                            },
-                           this.render_vec(vec)?))
+                           content))
             }
-            Token::Image(ref url, ref title, ref alt) |
-            Token::StandaloneImage(ref url, ref title, ref alt) => {
+            Token::Image(ref url, ref title, ref attributes, ref alt) |
+            Token::StandaloneImage(ref url, ref title, ref attributes, ref alt) => {
                 let content = this.render_vec(alt)?;
                 let html: &mut HtmlRenderer = this.as_mut();
+                let options = ImageOptions::parse(attributes).unwrap_or_default();
+
+                if let Some(kind) = images::media_kind(url) {
+                    // Audio or video, with a link to the file as a fallback
+                    let url = html.handler.map_image(&html.source, url.as_ref())?;
+                    let media = format!("<{kind} src = \"{url}\" controls = \"controls\"{title}{style}>
+  <a href = \"{url}\">{alt}</a>
+</{kind}>",
+                                        kind = kind,
+                                        url = url,
+                                        title = if title.is_empty() {
+                                            String::new()
+                                        } else {
+                                            format!(" title = \"{}\"", title)
+                                        },
+                                        style = match options.width {
+                                            Some(ref width) => format!(" style = \"width: {}\"", width),
+                                            None => String::new(),
+                                        },
+                                        alt = content);
+                    return if token.is_image() {
+                        Ok(media)
+                    } else {
+                        Ok(format!("<div class = \"media\">\n{}\n</div>\n", media))
+                    };
+                }
+
                 let url = html.handler.map_image(&html.source, url.as_ref())?;
 
+                // Width and alignment set by attributes
+                let mut style = vec![];
+                if let Some(ref width) = options.width {
+                    style.push(format!("width: {}", width));
+                }
+                let mut div_style = String::new();
+                if let Some(ref align) = options.align {
+                    if !token.is_image() {
+                        div_style = format!(" style = \"text-align: {}\"", align);
+                    } else if align != "center" {
+                        style.push(format!("float: {}", align));
+                    }
+                }
+                let mut style = if style.is_empty() {
+                    String::new()
+                } else {
+                    format!(" style = \"{}\"", style.join("; "))
+                };
+
+                // Resized variants of the image, if any
+                if !html.srcset.is_empty() && images::is_resizable(&url) {
+                    let variants: Vec<_> = html.srcset
+                        .iter()
+                        .map(|width| format!("{} {}w", images::variant_name(&url, *width), width))
+                        .collect();
+                    write!(style, " srcset = \"{}\" sizes = \"{}\"",
+                           variants.join(", "),
+                           html.sizes)?;
+                }
+
                 if token.is_image() {
-                    Ok(format!("<img src = \"{}\" title = \"{}\" alt = \"{}\" />",
+                    Ok(format!("<img src = \"{}\" title = \"{}\" alt = \"{}\"{} />",
                                url,
                                title,
-                               content))
+                               content,
+                               style))
+                } else if let Some(figure) = html.captions.next_figure(title, &options) {
+                    // Numbered figure: add a caption and an anchor
+                    let lang = html.book.options.get_str("lang").unwrap();
+                    Ok(format!("<div class = \"image\" id = \"{id}\"{div_style}>
+  <img src = \"{url}\" title = \"{title}\" alt = \"{alt}\"{style} />
+  <p class = \"caption\">{label}{caption}</p>
+</div>",
+                               id = escape::html(figure.id.as_str()),
+                               div_style = div_style,
+                               url = url,
+                               title = title,
+                               alt = content,
+                               style = style,
+                               label = format!("{}: ", figure.label(lang)),
+                               caption = escape::html(title.as_str())))
                 } else {
-                    Ok(format!("<div class = \"image\">
+                    Ok(format!("<div class = \"image\"{}>
   <img src = \"{}\" title = \"{}\" alt = \
-                                \"{}\" />
+                                \"{}\"{} />
 </div>",
+                               div_style,
                                url,
                                title,
-                               content))
+                               content,
+                               style))
                 }
             }
             Token::Table(_, ref vec) => {
-                Ok(format!("<div class = \"table\">
-    <table>\n{}
+                // Numbered table: add a caption and an anchor
+                let (caption, vec) = match vec.first() {
+                    Some(&Token::TableCaption(ref caption)) => (Some(caption), &vec[1..]),
+                    _ => (None, &vec[..]),
+                };
+                let mut id = String::new();
+                let mut caption_html = String::new();
+                if let Some(caption) = caption {
+                    let table = this.as_mut().captions.next(CaptionKind::Table);
+                    if let Some(table) = table {
+                        let lang = this.as_ref().book.options.get_str("lang").unwrap();
+                        id = format!(" id = \"{}\"", escape::html(table.id.as_str()));
+                        caption_html = if caption.is_empty() {
+                            format!("<caption>{}</caption>\n", table.label(lang))
+                        } else {
+                            format!("<caption>{}: {}</caption>\n",
+                                    table.label(lang),
+                                    this.render_vec(caption)?)
+                        };
+                    }
+                }
+                Ok(format!("<div class = \"table\"{}>
+    <table>\n{}{}
     </table>
 </div>\n",
+                           id,
+                           caption_html,
                            this.render_vec(vec)?))
             }
+            Token::TableCaption(ref vec) => {
+                Ok(format!("<caption>{}</caption>\n", this.render_vec(vec)?))
+            }
             Token::TableRow(ref vec) => Ok(format!("<tr>\n{}</tr>\n", this.render_vec(vec)?)),
             Token::TableCell(ref vec) => {
                 let tag = if this.as_ref().table_head { "th" } else { "td" };
@@ -590,26 +992,59 @@ impl<'a> HtmlRenderer<'a> {
                 Ok(format!("<tr>\n{}</tr>\n", s))
             }
             Token::Footnote(ref vec) => {
-                this.as_mut().footnote_number += 1;
-                let number = this.as_ref().footnote_number;
+                let (number, label) = this.as_mut().next_footnote();
+                let (dest_link, source_link) = this.as_ref().footnote_links(number);
                 assert!(!vec.is_empty());
 
                 let note_number = format!("<p class = \"note-number\">
-  <a href = \"#note-source-{}\">[{}]</a>
+  <a href = \"{}\">[{}]</a>
 </p>\n",
-                                          number,
-                                          number);
+                                          source_link,
+                                          label);
 
                 let inner = format!("<aside id = \"note-dest-{}\">{}</aside>",
                                     number,
                                     this.render_vec(vec)?);
-                this.as_mut().footnotes.push((note_number, inner));
+                this.as_mut().footnotes.push((note_number, inner, false));
 
-                Ok(format!("<a href = \"#note-dest-{}\"><sup id = \
+                Ok(format!("<a href = \"{}\"><sup id = \
                             \"note-source-{}\">[{}]</sup></a>",
+                           dest_link,
                            number,
+                           label))
+            }
+            Token::MarginNote(ref vec) => {
+                let mut lines = vec![];
+                for token in vec {
+                    if let Token::Paragraph(ref inner) = *token {
+                        lines.push(this.render_vec(inner)?);
+                    } else {
+                        lines.push(this.render_token(token)?);
+                    }
+                }
+                let content = lines.join("<br />\n");
+
+                // The same content is also added to the notes, which are only
+                // displayed if the screen is too narrow for the margin
+                let (number, label) = this.as_mut().next_margin_note();
+                let (dest_link, source_link) = this.as_ref().note_links("marginnote", number);
+                let note_number = format!("<p class = \"note-number\">
+  <a href = \"{}\">[{}]</a>
+</p>\n",
+                                          source_link,
+                                          label);
+                let inner = format!("<aside id = \"marginnote-dest-{}\">{}</aside>",
+                                    number,
+                                    content);
+                this.as_mut().footnotes.push((note_number, inner, true));
+
+                Ok(format!("<span class = \"marginnote-ref\"><a href = \"{}\"><sup id = \
+                            \"marginnote-source-{}\">[{}]</sup></a></span><span class = \
+                            \"marginnote\">{}</span>",
+                           dest_link,
                            number,
-                           number))
+                           label,
+                           content))
             }
             Token::__NonExhaustive => unreachable!(),
         }
@@ -641,6 +1076,16 @@ impl<'a> HtmlRenderer<'a> {
         Ok(String::from_utf8_lossy(&res).into_owned())
     }
 
+    /// Appends the inline CSS set by `option` (`html.css.add` or
+    /// `epub.css.add`), if any, after a rendered stylesheet, so its rules
+    /// override the ones of the template
+    pub fn append_css<'c>(&self, css: Cow<'c, str>, option: &str) -> Cow<'c, str> {
+        match self.book.options.get_str(option) {
+            Ok(add) => Cow::Owned(format!("{}\n{}\n", css, add)),
+            Err(_) => css,
+        }
+    }
+
     /// Render a section containing schema.org JSON-LD code
     pub fn get_json_ld(&mut self) -> Result<String> {
         let json = r#"
@@ -686,12 +1131,26 @@ impl<'a> HtmlRenderer<'a> {
         } else {
             String::new()
         };
-        if content.is_empty() {
+        let revision = HtmlRenderer::get_draft_revision(this.as_ref());
+        if content.is_empty() && revision.is_empty() {
             Ok(content)
         } else {
-            let tokens = Parser::from(&this.as_ref().book).parse(&content)?;
+            let tokens = this.as_ref().book.parse_markdown(&content)?;
             let content = this.render_vec(&tokens)?;
-            Ok(format!("<footer id = \"footer\">{}</footer>", content))
+            Ok(format!("<footer id = \"footer\">{}{}</footer>", content, revision))
+        }
+    }
+
+    /// Renders the revision of the book's sources, if this is a proofreading
+    /// (draft) build and the book is in a git repository
+    fn get_draft_revision(this: &HtmlRenderer) -> String {
+        match this.book.git {
+            Some(ref git) if this.proofread => {
+                format!("<p class = \"revision\">{} {}</p>",
+                        lang::get_str(this.book.options.get_str("lang").unwrap(), "draft"),
+                        escape::html(git.revision()))
+            }
+            _ => String::new(),
         }
     }
 
@@ -703,7 +1162,7 @@ impl<'a> HtmlRenderer<'a> {
         if let Ok(top) = this.as_ref().book.options.get_str("html.header") {
             match this.as_mut().templatize(top) {
                 Ok(content) => {
-                    let tokens = Parser::from(&this.as_ref().book).parse(&content)?;
+                    let tokens = this.as_ref().book.parse_markdown(&content)?;
                     Ok(format!("<div id = \"top\">{}</div>",
                                this.render_vec(&tokens)?))
                 }
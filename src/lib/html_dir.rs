@@ -26,6 +26,13 @@ use renderer::Renderer;
 use parser::Parser;
 use book_renderer::BookRenderer;
 use text_view::view_as_text;
+use images;
+use misc;
+use minify;
+use opds;
+use feed::{self, FeedEntry};
+
+use crowbook_text_processing::escape;
 
 use std::io;
 use std::io::Read;
@@ -36,6 +43,7 @@ use std::path::Path;
 use std::path::PathBuf;
 use std::borrow::Cow;
 use std::convert::{AsRef, AsMut};
+use std::process::Command;
 
 
 /// Multiple files HTML renderer
@@ -54,6 +62,19 @@ impl<'a> HtmlDirRenderer<'a> {
                                          .unwrap_or_else(|_| book.options.get_str("rendering.highlight.theme").unwrap()))?;
         html.handler.set_images_mapping(true);
         html.handler.set_base64(false);
+        if let Ok(widths) = book.options.get_str("html.dir.images.srcset") {
+            html.srcset = images::parse_widths(widths).ok_or_else(|| {
+                Error::render(&book.source,
+                              lformat!("html.dir.images.srcset must be a list of widths in pixels, \
+                                        found '{widths}'",
+                                       widths = widths))
+            })?;
+            html.sizes = book.options.get_str("html.dir.images.sizes").unwrap().to_owned();
+        }
+        let inline_limit = book.options.get_i32("html.dir.images.inline").unwrap();
+        if inline_limit > 0 {
+            html.handler.set_inline_limit(Some(inline_limit as u64));
+        }
         Ok(HtmlDirRenderer { html: html })
     }
 
@@ -67,7 +88,15 @@ impl<'a> HtmlDirRenderer<'a> {
     pub fn render_book(&mut self, dest_path: &Path) -> Result<()> {
         // Add internal files to resource handler
         for (i, chapter) in self.html.book.chapters.iter().enumerate() {
-            self.html.handler.add_link(chapter.filename.as_ref(), filenamer(i));
+            self.html.handler.add_chapter_link(chapter.filename.as_ref(), filenamer(i));
+        }
+        for caption in self.html.captions.all() {
+            self.html.handler.add_link(format!("#{}", caption.id),
+                                       format!("{}#{}", filenamer(caption.chapter), caption.id));
+        }
+        for section in self.html.sections.all() {
+            self.html.handler.add_link(format!("#{}", section.id),
+                                       format!("{}#{}", filenamer(section.chapter), section.id));
         }
 
         if let Ok(metadata) = fs::metadata(&dest_path) {
@@ -105,10 +134,13 @@ impl<'a> HtmlDirRenderer<'a> {
         // Write CSS
         self.write_css()?;
         // Write print.css
-        self.write_file("print.css",
-                        self.html.book.get_template("html.css.print")
-                        .unwrap()
-                        .as_bytes())?;
+        let print_css = self.html.book.get_template("html.css.print").unwrap();
+        let print_css = if self.html.book.options.get_bool("html.minify").unwrap() {
+            Cow::Owned(minify::css(&print_css))
+        } else {
+            print_css
+        };
+        self.write_file("print.css", print_css.as_bytes())?;
         // Write index.html and chapter_xxx.html
         self.write_html()?;
         // Write menu.svg
@@ -130,8 +162,11 @@ impl<'a> HtmlDirRenderer<'a> {
                             .as_bytes())?;
         }
 
-        // Write all images (including cover)
+        // Write all images (including cover), except the ones that are inlined
         for (source, dest) in self.html.handler.images_mapping() {
+            if dest.starts_with("data:") {
+                continue;
+            }
             let mut f = fs::canonicalize(source)
                 .and_then(|f| File::open(f))
                 .map_err(|_| {
@@ -148,6 +183,15 @@ impl<'a> HtmlDirRenderer<'a> {
                                            error = e))
                 })?;
             self.write_file(dest, &content)?;
+
+            // Write resized variants, for srcset
+            if images::is_resizable(dest) {
+                for width in &self.html.srcset {
+                    self.resize_image(source,
+                                      &dest_path.join(images::variant_name(dest, *width)),
+                                      *width)?;
+                }
+            }
         }
 
         // Write additional files
@@ -175,16 +219,113 @@ impl<'a> HtmlDirRenderer<'a> {
             }
         }
 
+        if self.html.book.options.get_bool("html.dir.fingerprint").unwrap() {
+            self.fingerprint(dest_path)?;
+        }
+
         Ok(())
     }
 
+    /// Renames CSS, Javascript and image files to include a hash of their
+    /// content, and updates the references to them
+    ///
+    /// Images and scripts are renamed first, so stylesheets can refer to
+    /// them before being hashed too.
+    fn fingerprint(&self, dest_path: &Path) -> Result<()> {
+        let mut assets = vec![String::from("menu.svg")];
+        if self.html.highlight == Highlight::Js {
+            assets.push(String::from("highlight.js"));
+        }
+        for dest in self.html.handler.images_mapping().values() {
+            if dest.starts_with("data:") {
+                continue;
+            }
+            assets.push(dest.clone());
+            if images::is_resizable(dest) {
+                for width in &self.html.srcset {
+                    assets.push(images::variant_name(dest, *width));
+                }
+            }
+        }
+        let mut stylesheets = vec![String::from("stylesheet.css"), String::from("print.css")];
+        if self.html.highlight == Highlight::Js {
+            stylesheets.push(String::from("highlight.css"));
+        }
+
+        let mut renamed = vec![];
+        for file in assets {
+            let content = self.read_file(dest_path, &file)?;
+            let new_name = misc::fingerprint(&file, &content);
+            self.rename_file(dest_path, &file, &new_name)?;
+            renamed.push((file, new_name));
+        }
+        for file in stylesheets {
+            let content = update_references(&self.read_file(dest_path, &file)?, &renamed);
+            let new_name = misc::fingerprint(&file, content.as_bytes());
+            self.write_file(&new_name, content.as_bytes())?;
+            fs::remove_file(dest_path.join(&file))
+                .map_err(|e| {
+                    Error::render(&self.html.book.source,
+                                  lformat!("could not remove file {file}: {error}",
+                                           file = file,
+                                           error = e))
+                })?;
+            renamed.push((file, new_name));
+        }
+
+        let mut pages = vec![String::from("index.html")];
+        pages.extend((0..self.html.book.chapters.len()).map(filenamer));
+        if fs::metadata(dest_path.join("feed.xml")).is_ok() {
+            pages.push(String::from("feed.xml"));
+        }
+        for file in pages {
+            let content = update_references(&self.read_file(dest_path, &file)?, &renamed);
+            self.write_file(&file, content.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Reads a file that has been written to the output directory
+    fn read_file(&self, dest_path: &Path, file: &str) -> Result<Vec<u8>> {
+        let mut content = vec![];
+        File::open(dest_path.join(file))
+            .and_then(|mut f| f.read_to_end(&mut content))
+            .map_err(|e| {
+                Error::render(&self.html.book.source,
+                              lformat!("could not read file {file}: {error}",
+                                       file = file,
+                                       error = e))
+            })?;
+        Ok(content)
+    }
+
+    /// Renames a file of the output directory
+    fn rename_file(&self, dest_path: &Path, from: &str, to: &str) -> Result<()> {
+        fs::rename(dest_path.join(from), dest_path.join(to))
+            .map_err(|e| {
+                Error::render(&self.html.book.source,
+                              lformat!("could not rename file {file}: {error}",
+                                       file = from,
+                                       error = e))
+            })
+    }
+
     // Render each chapter and write them, and index.html too
     fn write_html(&mut self) -> Result<()> {
         let mut chapters = vec![];
         
         let mut titles = vec![];
         let mut titles_raw = vec![];
+        let use_summaries = self.html.book.options.get_bool("html.dir.summaries").unwrap();
+        let mut summaries = vec![];
+        // If notes are displayed at the end of the book, they are in the last chapter
+        let last = self.html.book.chapters.len().saturating_sub(1);
+        let end_of_book = self.html.notes == "end_of_book";
+        if end_of_book {
+            self.html.notes_filename = filenamer(last);
+        }
         for (i, chapter) in self.html.book.chapters.iter().enumerate() {
+            let chapter = self.html.book.clean_chapter(chapter, self.html.clean)?;
             let n = chapter.number;
             let v = &chapter.content;
             self.html.chapter_config(i, n, filenamer(i));
@@ -225,18 +366,74 @@ impl<'a> HtmlDirRenderer<'a> {
             }
             titles.push(title);
             titles_raw.push(title_raw);
+            if use_summaries {
+                summaries.push((self.html.render_vec(&chapter.summary)?,
+                                view_as_text(&chapter.summary)));
+            }
 
-            let chapter = HtmlRenderer::render_html(self, v, true);
+            let chapter = HtmlRenderer::render_html(self, v, !end_of_book || i == last);
             chapters.push(chapter);
         }
         self.html.source = Source::empty();
         let toc = self.html.toc.render(false);
+        // If the inline toc must be displayed at the end, it is appended to
+        // the last chapter instead of the index
+        let end_toc = if self.html.book.inline_toc_location()? == Some("end") {
+            Some(format!("<h1>{}</h1>
+<div id = \"toc\">
+{}
+</div>
+",
+                         self.html.get_toc_name()?,
+                         &toc))
+        } else {
+            None
+        };
 
         // render all chapters
         let template =
             compile_str(self.html.book.get_template("html.dir.template")?.as_ref(),
                         &self.html.book.source,
                         "html.dir.template")?;
+        // These parts are the same for all chapters, so only render them once
+        let json_data = self.html.get_json_ld()?;
+        let footer = HtmlRenderer::get_footer(self)?;
+        let header = HtmlRenderer::get_header(self)?;
+        let script = self.html.book.get_template("html.js").unwrap();
+        let favicon = match self.html.book.options.get_path("html.icon") {
+            Ok(favicon) => {
+                let favicon = self.html.handler.map_image(&self.html.book.source, favicon)?;
+                Some(format!("<link rel = \"icon\" href = \"{}\">", favicon))
+            }
+            Err(_) => None,
+        };
+        let book = self.html.book;
+        let serial = book.options.get_bool("html.dir.serial").unwrap();
+        let base_url = book.options.get_str("publish.base_url").ok();
+        if serial && base_url.is_none() {
+            book.logger.warning(lformat!("html.dir.serial is set but publish.base_url is not, \
+                                          so chapters won't have canonical links"));
+        }
+        // Serial books get an Atom feed, unless another format is set
+        let feed_type = match book.options.get_str("html.dir.feed") {
+            Ok("atom") => Some("atom"),
+            Ok("rss") => Some("rss"),
+            Ok(value) => {
+                return Err(Error::render(&book.source,
+                                         lformat!("html.dir.feed set to '{value}', expected 'atom' or 'rss'",
+                                                  value = value)))
+            }
+            Err(_) if serial => Some("atom"),
+            Err(_) => None,
+        };
+        let feed_link = feed_type.map(|feed_type| {
+            format!("<link rel = \"alternate\" type = \"application/{}+xml\" \
+                     title = \"{}\" href = \"feed.xml\">",
+                    feed_type,
+                    escape::html(book.options.get_str("title").unwrap()))
+        });
+        let minify = book.options.get_bool("html.minify").unwrap();
+        let mut entries = vec![];
         for (i, content) in chapters.into_iter().enumerate() {
             let prev_chapter = if i > 0 {
                 format!("<p class = \"prev_chapter\">
@@ -262,36 +459,84 @@ impl<'a> HtmlDirRenderer<'a> {
                 String::new()
             };
 
+            let mut content = content?;
+            if i == last {
+                if let Some(ref end_toc) = end_toc {
+                    content.push_str(end_toc);
+                }
+            }
+            let chapter = &book.chapters[i];
+            // Generated chapters, such as lists of figures, are not listed in the feed
+            if feed_type.is_some() && !chapter.filename.is_empty() {
+                entries.push(FeedEntry {
+                    title: titles_raw[i].clone(),
+                    file: filenamer(i),
+                    published: chapter.published.clone(),
+                    summary: summaries.get(i)
+                        .map(|s| s.0.clone())
+                        .and_then(|s| if s.is_empty() { None } else { Some(s) }),
+                    content: content.clone(),
+                });
+            }
+            if serial {
+                content = format!("{}{}", serial_header(book, chapter.published.as_ref()), content);
+            }
+
             // Render each HTML document
             let mut mapbuilder = self.html
                 .book
                 .get_metadata(|s| self.render_vec(&Parser::new().parse_inline(s)?))?
-                .insert_str("content", content?)
+                .insert_str("content", content)
                 .insert_str("chapter_title",
                             titles[i].clone())
-                .insert_str("json_data", self.html.get_json_ld()?)
+                .insert_str("json_data", json_data.as_str())
                 .insert_str("chapter_title_raw",
                             titles_raw[i].clone())
-                .insert_str("toc", toc.clone())
+                .insert_str("toc", toc.as_str())
                 .insert_str("prev_chapter", prev_chapter)
                 .insert_str("next_chapter", next_chapter)
-                .insert_str("footer", HtmlRenderer::get_footer(self)?)
-                .insert_str("header", HtmlRenderer::get_header(self)?)
-                .insert_str("script", self.html.book.get_template("html.js").unwrap())
+                .insert_str("footer", footer.as_str())
+                .insert_str("header", header.as_str())
+                .insert_str("script", script.clone())
                 .insert_bool(self.html.book.options.get_str("lang").unwrap(), true);
 
-            if let Ok(favicon) = self.html.book.options.get_path("html.icon") {
-                let favicon = self.html.handler.map_image(&self.html.book.source, favicon)?;
-                mapbuilder = mapbuilder.insert_str("favicon", format!("<link rel = \"icon\" href = \"{}\">", favicon));
+            if let Some(ref favicon) = favicon {
+                mapbuilder = mapbuilder.insert_str("favicon", favicon.as_str());
             }
             if self.html.highlight == Highlight::Js {
                 mapbuilder = mapbuilder.insert_bool("highlight_code", true);
             }
+            if let Some(ref feed_link) = feed_link {
+                mapbuilder = mapbuilder.insert_str("feed", feed_link.as_str());
+            }
+            if let Some(&(_, ref description)) = summaries.get(i) {
+                mapbuilder = mapbuilder.insert_str("open_graph",
+                                                   open_graph(book,
+                                                              "article",
+                                                              &titles_raw[i],
+                                                              description,
+                                                              base_url.map(|url| opds::url(url, filenamer(i)))));
+            }
+            if serial {
+                if let Some(base_url) = base_url {
+                    mapbuilder = mapbuilder.insert_str("canonical",
+                                                       format!("<link rel = \"canonical\" href = \"{}\">",
+                                                               opds::url(base_url, filenamer(i))));
+                }
+            }
             let data = mapbuilder.build();
             let mut res = vec![];
             template.render_data(&mut res, &data)?;
+            if minify {
+                res = minify::html(&String::from_utf8_lossy(&res)).into_bytes();
+            }
             self.write_file(&filenamer(i), &res)?;
         }
+        match feed_type {
+            Some("atom") => self.write_file("feed.xml", feed::atom(book, &entries)?.as_bytes())?,
+            Some(_) => self.write_file("feed.xml", feed::rss(book, &entries)?.as_bytes())?,
+            None => {}
+        }
 
         let mut content = if let Ok(cover) = self.html.book.options.get_path("cover") {
             // checks first that cover exists
@@ -320,7 +565,7 @@ impl<'a> HtmlDirRenderer<'a> {
                              .unwrap_or_else(|_| ""));
 
         // Insert toc inline if option is set
-        if self.html.book.options.get_bool("rendering.inline_toc").unwrap() {
+        if self.html.book.inline_toc_location()? == Some("start") {
 
             write!(content,
                    "<h1>{}</h1>
@@ -342,6 +587,26 @@ impl<'a> HtmlDirRenderer<'a> {
                    filenamer(0),
                    titles[0])?;
         }
+
+        // List chapters with their summaries
+        if use_summaries {
+            content.push_str("<div class = \"summaries\">\n");
+            for (i, &(ref summary, _)) in summaries.iter().enumerate() {
+                if summary.is_empty() || book.chapters[i].filename.is_empty() {
+                    continue;
+                }
+                write!(content,
+                       "<div class = \"summary\">
+  <h2><a href = \"{file}\">{title}</a></h2>
+{summary}
+</div>
+",
+                       file = filenamer(i),
+                       title = titles[i],
+                       summary = summary)?;
+            }
+            content.push_str("</div>\n");
+        }
         // Render index.html and write it too
         let mut mapbuilder = self.html
             .book
@@ -359,6 +624,25 @@ impl<'a> HtmlDirRenderer<'a> {
         if self.html.highlight == Highlight::Js {
             mapbuilder = mapbuilder.insert_bool("highlight_code", true);
         }
+        if let Some(feed_link) = feed_link {
+            mapbuilder = mapbuilder.insert_str("feed", feed_link);
+        }
+        if use_summaries {
+            let description = book.options.get_str("description").unwrap_or("");
+            mapbuilder = mapbuilder.insert_str("open_graph",
+                                               open_graph(book,
+                                                          "book",
+                                                          book.options.get_str("title").unwrap(),
+                                                          description,
+                                                          base_url.map(|url| opds::url(url, "index.html"))));
+        }
+        if serial {
+            if let Some(base_url) = base_url {
+                mapbuilder = mapbuilder.insert_str("canonical",
+                                                   format!("<link rel = \"canonical\" href = \"{}\">",
+                                                           opds::url(base_url, "index.html")));
+            }
+        }
         let data = mapbuilder.build();
         let template =
             compile_str(self.html.book.get_template("html.dir.template")?.as_ref(),
@@ -366,6 +650,9 @@ impl<'a> HtmlDirRenderer<'a> {
                         "html.dir.template")?;
         let mut res = vec![];
         template.render_data(&mut res, &data)?;
+        if minify {
+            res = minify::html(&String::from_utf8_lossy(&res)).into_bytes();
+        }
         self.write_file("index.html", &res)?;
 
         Ok(())
@@ -383,19 +670,59 @@ impl<'a> HtmlDirRenderer<'a> {
         let mut data = self.html.book.get_metadata(|s| Ok(s.to_owned()))?;
         data = data.insert_str("colours",
                                self.html.book.get_template("html.css.colours")?);
-        if let Ok(html_css_add) = self.html.book.options.get_str("html.css.add") {
-            data = data.insert_str("additional_code", html_css_add);
-        }
         let data = data.build();
         let mut res: Vec<u8> = vec![];
         template_css.render_data(&mut res, &data)?;
-        let css = String::from_utf8_lossy(&res);
+        let css = self.html.append_css(String::from_utf8_lossy(&res), "html.css.add");
+        let css = if self.html.book.options.get_bool("html.minify").unwrap() {
+            Cow::Owned(minify::css(&css))
+        } else {
+            css
+        };
 
         // Write it
         self.write_file("stylesheet.css", css.as_bytes())
     }
 
     // Write content to a file
+    /// Writes a variant of an image resized to `width` pixels, using the
+    /// command set by `html.dir.images.resize`
+    fn resize_image(&self, input: &str, output: &Path, width: u32) -> Result<()> {
+        let command = self.html.book.options.get_str("html.dir.images.resize").unwrap();
+        let output_str = format!("{}", output.display());
+        let width_str = format!("{}", width);
+        let mut args = command.split_whitespace()
+            .map(|arg| {
+                arg.replace("{input}", input)
+                    .replace("{output}", &output_str)
+                    .replace("{width}", &width_str)
+            });
+        let program = match args.next() {
+            Some(program) => program,
+            None => {
+                return Err(Error::render(&self.html.book.source,
+                                         lformat!("html.dir.images.resize is empty")))
+            }
+        };
+        let result = Command::new(&program)
+            .args(args)
+            .output()
+            .map_err(|e| {
+                Error::render(&self.html.book.source,
+                              lformat!("failed to run command '{command}' to resize image: {error}",
+                                       command = program,
+                                       error = e))
+            })?;
+        if !result.status.success() {
+            return Err(Error::render(&self.html.book.source,
+                                     lformat!("command '{command}' failed to resize image {file}:\n{error}",
+                                              command = command,
+                                              file = input,
+                                              error = String::from_utf8_lossy(&result.stderr))));
+        }
+        Ok(())
+    }
+
     fn write_file(&self, file: &str, content: &[u8]) -> Result<()> {
         let dir_name = if self.html.proofread {
             self.html.book.options.get_path("output.proofread.html.dir").unwrap()
@@ -436,6 +763,80 @@ impl<'a> HtmlDirRenderer<'a> {
     }
 }
 
+/// Replaces the references to renamed files in a HTML, CSS or XML file
+///
+/// Only whole file names are replaced, e.g. renaming `image_1.png` doesn't
+/// change `image_11.png`.
+fn update_references(content: &[u8], renamed: &[(String, String)]) -> String {
+    let mut content = String::from_utf8_lossy(content).into_owned();
+    for &(ref from, ref to) in renamed {
+        let mut res = String::with_capacity(content.len());
+        let mut rest = content.as_str();
+        while let Some(i) = rest.find(from.as_str()) {
+            let end = i + from.len();
+            let before = rest[..i].chars().next_back();
+            let after = rest[end..].chars().next();
+            res.push_str(&rest[..i]);
+            if is_name_boundary(before) && is_name_boundary(after) {
+                res.push_str(to);
+            } else {
+                res.push_str(from);
+            }
+            rest = &rest[end..];
+        }
+        res.push_str(rest);
+        content = res;
+    }
+    content
+}
+
+fn is_name_boundary(c: Option<char>) -> bool {
+    match c {
+        None => true,
+        Some(c) => !(c.is_alphanumeric() || c == '_' || c == '-' || c == '.' || c == '/'),
+    }
+}
+
+/// Returns the Open Graph metadata of a page, used by social networks to
+/// display links to it
+fn open_graph(book: &Book, kind: &str, title: &str, description: &str, url: Option<String>) -> String {
+    let mut res = format!("<meta property = \"og:type\" content = \"{}\">
+    <meta property = \"og:site_name\" content = \"{}\">
+    <meta property = \"og:title\" content = \"{}\">
+",
+                          kind,
+                          escape::html(book.options.get_str("title").unwrap()),
+                          escape::html(title));
+    if !description.is_empty() {
+        res.push_str(&format!("    <meta property = \"og:description\" content = \"{}\">\n",
+                              escape::html(description.trim())));
+    }
+    if let Some(url) = url {
+        res.push_str(&format!("    <meta property = \"og:url\" content = \"{}\">\n", url));
+    }
+    res
+}
+
+/// Returns the header of a chapter published as an article, with the title
+/// of the book, its author and the publication date of the chapter
+fn serial_header(book: &Book, published: Option<&String>) -> String {
+    let date = match published {
+        Some(date) => {
+            format!(" — <time datetime = \"{date}\">{date}</time>",
+                    date = escape::html(date.as_str()))
+        }
+        None => String::new(),
+    };
+    format!("<header class = \"serial\">
+  <p class = \"serial-book\"><a href = \"index.html\">{title}</a></p>
+  <p class = \"serial-meta\">{author}{date}</p>
+</header>
+",
+            title = escape::html(book.options.get_str("title").unwrap()),
+            author = escape::html(book.options.get_str("author").unwrap()),
+            date = date)
+}
+
 /// Generate a file name given an int
 fn filenamer(i: usize) -> String {
     format!("chapter_{:03}.html", i)
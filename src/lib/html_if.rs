@@ -179,11 +179,19 @@ return crowbook_return_variable.replace(/<\\/ul><ul>/g, '');\n",
 
         let mut titles = vec![];
         let mut chapters = vec![];
-        let render_notes_chapter = true;
+        let render_notes_chapter = self.html.notes != "end_of_book";
 
         for (i, chapter) in self.html.book.chapters.iter().enumerate() {
-            self.html.handler.add_link(chapter.filename.as_ref(),
-                                       format!("#chapter-{}", i));
+            self.html.handler.add_chapter_link(chapter.filename.as_ref(),
+                                               format!("#chapter-{}", i));
+        }
+        for caption in self.html.captions.all() {
+            self.html.handler.add_link(format!("#{}", caption.id),
+                                       format!("#{}", caption.id));
+        }
+        for section in self.html.sections.all() {
+            self.html.handler.add_link(format!("#{}", section.id),
+                                       format!("#{}", section.id));
         }
 
         let pre_code = self.html.book.options.get_str("html.if.new_turn")
@@ -192,6 +200,7 @@ return crowbook_return_variable.replace(/<\\/ul><ul>/g, '');\n",
             .unwrap_or("");
 
         for (i, chapter) in self.html.book.chapters.iter().enumerate() {
+            let chapter = self.html.book.clean_chapter(chapter, self.html.clean)?;
             let n = chapter.number;
             let v = &chapter.content;
             self.html.chapter_config(i, n, String::new());
@@ -258,18 +267,15 @@ return crowbook_return_variable.replace(/<\\/ul><ul>/g, '');\n",
                                        .as_ref(),
                                        &self.html.book.source,
                                        "html.css")?;
-        let mut data = self.html
+        let data = self.html
             .book
             .get_metadata(|s| self.render_vec(&Parser::new().parse_inline(s)?))?
             .insert_str("colours",
-                        self.html.book.get_template("html.css.colours")?);
-        if let Ok(html_css_add) = self.html.book.options.get_str("html.css.add") {
-            data = data.insert_str("additional_code", html_css_add);
-        }
-        let data = data.build();
+                        self.html.book.get_template("html.css.colours")?)
+            .build();
         let mut res: Vec<u8> = vec![];
         template_css.render_data(&mut res, &data)?;
-        let css = String::from_utf8_lossy(&res);
+        let css = self.html.append_css(String::from_utf8_lossy(&res), "html.css.add");
 
         // Render the JS
         let template_js =
@@ -0,0 +1,984 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Importing of HTML chapters (`.html` or `.htm` files), e.g. exported from
+//! a word processor or saved from a blog.
+
+use error::{Result, Source};
+use token::Token;
+use parser::Features;
+use images::ImageOptions;
+
+use std::char;
+use std::mem;
+
+/// Elements that have no content nor closing tag
+static VOID_ELEMENTS: &'static [&'static str] = &["area", "base", "br", "col", "embed", "hr",
+                                                  "img", "input", "link", "meta", "param",
+                                                  "source", "track", "wbr"];
+
+/// Elements whose content is raw text, which can contain `<`
+static RAW_TEXT_ELEMENTS: &'static [&'static str] = &["script", "style"];
+
+/// Elements whose content is not part of the text of the chapter
+static SKIPPED_ELEMENTS: &'static [&'static str] = &["head", "title", "script", "style",
+                                                     "template", "noscript", "nav", "form",
+                                                     "button", "iframe", "object", "svg",
+                                                     "canvas", "audio", "video"];
+
+/// Elements that are displayed as blocks, and can't be part of a paragraph
+static BLOCK_ELEMENTS: &'static [&'static str] = &["address", "article", "aside",
+                                                   "blockquote", "body", "center", "dd",
+                                                   "details", "div", "dl", "dt", "fieldset",
+                                                   "figcaption", "figure", "footer", "h1",
+                                                   "h2", "h3", "h4", "h5", "h6", "header",
+                                                   "hr", "html", "li", "main", "ol", "p",
+                                                   "pre", "section", "summary", "table",
+                                                   "ul"];
+
+/// Named character references that are decoded, other characters can be
+/// written with numeric references
+static ENTITIES: &'static [(&'static str, char)] = &[("amp", '&'),
+                                                     ("lt", '<'),
+                                                     ("gt", '>'),
+                                                     ("quot", '"'),
+                                                     ("apos", '\''),
+                                                     ("nbsp", '\u{a0}'),
+                                                     ("shy", '\u{ad}'),
+                                                     ("ndash", '–'),
+                                                     ("mdash", '—'),
+                                                     ("hellip", '…'),
+                                                     ("laquo", '«'),
+                                                     ("raquo", '»'),
+                                                     ("lsquo", '‘'),
+                                                     ("rsquo", '’'),
+                                                     ("sbquo", '‚'),
+                                                     ("ldquo", '“'),
+                                                     ("rdquo", '”'),
+                                                     ("bdquo", '„'),
+                                                     ("bull", '•'),
+                                                     ("middot", '·'),
+                                                     ("copy", '©'),
+                                                     ("reg", '®'),
+                                                     ("trade", '™'),
+                                                     ("deg", '°'),
+                                                     ("times", '×'),
+                                                     ("euro", '€')];
+
+/// A node of an HTML document
+enum Node {
+    Element(Element),
+    Text(String),
+}
+
+/// An HTML element, with its lowercased name
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Node>,
+}
+
+impl Element {
+    /// Returns the value of an attribute, if it is set
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|&&(ref key, _)| key == name)
+            .map(|&(_, ref value)| value.as_str())
+    }
+}
+
+/// An importer that reads (simple, semantic) HTML and converts it to the
+/// same AST as the Markdown `Parser`
+///
+/// Headings, paragraphs, inline markup, links, images and figures, lists,
+/// definition lists, block quotes, preformatted text and tables are
+/// converted; other elements only keep their content. The content of the
+/// `head` element, scripts, styles and navigation menus is ignored.
+///
+/// # Examples
+///
+/// ```
+/// use crowbook::HtmlImporter;
+/// let mut importer = HtmlImporter::new();
+/// let result = importer.parse("<h1>Title</h1><p>Some <em>valid</em> HTML</p>");
+/// assert!(result.is_ok());
+/// ```
+pub struct HtmlImporter {
+    source: Source,
+    features: Features,
+    /// Warnings emitted while importing
+    warnings: Vec<String>,
+}
+
+impl HtmlImporter {
+    /// Creates an importer
+    pub fn new() -> HtmlImporter {
+        HtmlImporter {
+            source: Source::empty(),
+            features: Features::new(),
+            warnings: vec![],
+        }
+    }
+
+    /// Sets an importer's source file
+    pub fn set_source_file(&mut self, s: &str) {
+        self.source = Source::new(s);
+    }
+
+    /// Returns the list of features used by this importer
+    pub fn features(&self) -> Features {
+        self.features
+    }
+
+    /// Returns the warnings emitted while importing since the last call
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        mem::replace(&mut self.warnings, vec![])
+    }
+
+    /// Parse a string and returns an AST or an Error.
+    pub fn parse(&mut self, s: &str) -> Result<Vec<Token>> {
+        let nodes = parse_tree(s);
+        Ok(self.blocks(&nodes))
+    }
+
+    /// Converts nodes that are the content of a block element, where
+    /// consecutive text and inline elements are grouped into paragraphs
+    fn blocks(&mut self, nodes: &[Node]) -> Vec<Token> {
+        let mut res = vec![];
+        let mut start = 0;
+        for (i, node) in nodes.iter().enumerate() {
+            if let Node::Element(ref element) = *node {
+                if BLOCK_ELEMENTS.contains(&element.name.as_str()) ||
+                   SKIPPED_ELEMENTS.contains(&element.name.as_str()) {
+                    self.paragraph(&nodes[start..i], &mut res);
+                    self.block(element, &mut res);
+                    start = i + 1;
+                }
+            }
+        }
+        self.paragraph(&nodes[start..], &mut res);
+        res
+    }
+
+    /// Converts inline nodes to a paragraph, unless they are blank
+    fn paragraph(&mut self, nodes: &[Node], res: &mut Vec<Token>) {
+        let mut content = self.inline(nodes);
+        trim(&mut content);
+        if is_blank(&content) {
+            return;
+        }
+        // Like in Markdown, an image alone in its paragraph is a standalone
+        // image
+        let is_image = content.len() == 1 &&
+                       match content[0] {
+            Token::Image(..) => true,
+            _ => false,
+        };
+        if is_image {
+            if let Some(Token::Image(source, title, attributes, alt)) = content.pop() {
+                res.push(Token::StandaloneImage(source, title, attributes, alt));
+            }
+            return;
+        }
+        res.push(Token::Paragraph(content));
+    }
+
+    /// Converts a block element
+    fn block(&mut self, element: &Element, res: &mut Vec<Token>) {
+        match element.name.as_str() {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let level = element.name[1..].parse::<i32>().unwrap();
+                let mut content = self.inline(&element.children);
+                trim(&mut content);
+                if !is_blank(&content) {
+                    res.push(Token::Header(level, content));
+                }
+            }
+            "p" => self.paragraph(&element.children, res),
+            "blockquote" => {
+                let content = self.blocks(&element.children);
+                if !content.is_empty() {
+                    self.features.blockquote = true;
+                    res.push(Token::BlockQuote(content));
+                }
+            }
+            "pre" => {
+                self.features.codeblock = true;
+                let language = language(element).unwrap_or_default();
+                let mut code = raw_text(&element.children).replace("\r\n", "\n");
+                // Like browsers, ignore a newline following the opening tag
+                if code.starts_with('\n') {
+                    code.remove(0);
+                }
+                if !code.ends_with('\n') {
+                    code.push('\n');
+                }
+                res.push(Token::CodeBlock(language, vec![Token::Str(code)]));
+            }
+            "ul" => {
+                let items = self.items(element);
+                res.push(Token::List(items));
+            }
+            "ol" => {
+                self.features.ordered_list = true;
+                let start = element.attribute("start")
+                    .and_then(|s| s.trim().parse::<usize>().ok())
+                    .unwrap_or(1);
+                let items = self.items(element);
+                res.push(Token::OrderedList(start, items));
+            }
+            "dl" => {
+                // Terms are displayed in bold, followed by their definitions
+                for node in &element.children {
+                    if let Node::Element(ref child) = *node {
+                        match child.name.as_str() {
+                            "dt" => {
+                                let mut term = self.inline(&child.children);
+                                trim(&mut term);
+                                if !is_blank(&term) {
+                                    res.push(Token::Paragraph(vec![Token::Strong(term)]));
+                                }
+                            }
+                            _ => {
+                                let definition = self.blocks(&child.children);
+                                res.extend(definition);
+                            }
+                        }
+                    }
+                }
+            }
+            "hr" => res.push(Token::Rule),
+            "figure" => {
+                let image = find(element, "img").and_then(|img| self.image(img));
+                match image {
+                    Some(Token::Image(source, title, attributes, alt)) => {
+                        // The caption of the figure is displayed as the
+                        // title of the image
+                        let caption = find(element, "figcaption")
+                            .map(|caption| collapse(&raw_text(&caption.children)).trim().to_owned())
+                            .unwrap_or_default();
+                        let title = if caption.is_empty() { title } else { caption };
+                        res.push(Token::StandaloneImage(source, title, attributes, alt));
+                    }
+                    _ => {
+                        let content = self.blocks(&element.children);
+                        res.extend(content);
+                    }
+                }
+            }
+            "table" => self.table(element, res),
+            name if SKIPPED_ELEMENTS.contains(&name) => {}
+            _ => {
+                // Containers such as `div` or `section` only keep their
+                // content
+                let content = self.blocks(&element.children);
+                res.extend(content);
+            }
+        }
+    }
+
+    /// Converts the items of a `ul` or `ol` element
+    fn items(&mut self, list: &Element) -> Vec<Token> {
+        let mut items = vec![];
+        for node in &list.children {
+            let element = match *node {
+                Node::Element(ref element) => element,
+                Node::Text(_) => continue,
+            };
+            match element.name.as_str() {
+                "li" => {
+                    let mut content = self.blocks(&element.children);
+                    // Like tight lists in Markdown, an item containing a
+                    // single paragraph only contains its text
+                    let paragraph = if content.len() == 1 {
+                        match content[0] {
+                            Token::Paragraph(ref mut inline) => Some(mem::replace(inline, vec![])),
+                            _ => None,
+                        }
+                    } else {
+                        None
+                    };
+                    items.push(Token::Item(paragraph.unwrap_or(content)));
+                }
+                "ul" | "ol" => {
+                    // Some editors put nested lists directly in their parent
+                    // list, instead of in the previous item
+                    let mut nested = vec![];
+                    self.block(element, &mut nested);
+                    let has_item = match items.last() {
+                        Some(&Token::Item(_)) => true,
+                        _ => false,
+                    };
+                    if !has_item {
+                        items.push(Token::Item(vec![]));
+                    }
+                    if let Some(&mut Token::Item(ref mut content)) = items.last_mut() {
+                        content.extend(nested);
+                    }
+                }
+                _ => {}
+            }
+        }
+        items
+    }
+
+    /// Converts a table
+    ///
+    /// Rows of the `thead` element, or the first row if it only contains
+    /// `th` cells, are the head of the table. Cells spanning several columns
+    /// or rows are not supported.
+    fn table(&mut self, table: &Element, res: &mut Vec<Token>) {
+        let mut caption = None;
+        let mut rows = vec![];
+        self.table_rows(table, false, &mut caption, &mut rows);
+        if rows.is_empty() {
+            return;
+        }
+        self.features.table = true;
+
+        let columns = rows.iter().map(|&(_, _, ref cells)| cells.len()).max().unwrap_or(0);
+        let has_head = rows.iter().any(|&(head, _, _)| head);
+        let mut content = vec![];
+        if let Some(caption) = caption {
+            content.push(Token::TableCaption(caption));
+        }
+        for (i, (head, only_th, mut cells)) in rows.into_iter().enumerate() {
+            while cells.len() < columns {
+                cells.push(Token::TableCell(vec![]));
+            }
+            if head || (!has_head && i == 0 && only_th) {
+                content.push(Token::TableHead(cells));
+            } else {
+                content.push(Token::TableRow(cells));
+            }
+        }
+        res.push(Token::Table(columns as i32, content));
+    }
+
+    /// Collects the caption and the rows of a table (or of a `thead`,
+    /// `tbody` or `tfoot` element), with whether they are part of its head
+    /// and whether they only contain `th` cells
+    fn table_rows(&mut self,
+                  element: &Element,
+                  head: bool,
+                  caption: &mut Option<Vec<Token>>,
+                  rows: &mut Vec<(bool, bool, Vec<Token>)>) {
+        for node in &element.children {
+            let child = match *node {
+                Node::Element(ref child) => child,
+                Node::Text(_) => continue,
+            };
+            match child.name.as_str() {
+                "caption" => {
+                    let mut content = self.inline(&child.children);
+                    trim(&mut content);
+                    if !is_blank(&content) {
+                        *caption = Some(content);
+                    }
+                }
+                "thead" => self.table_rows(child, true, caption, rows),
+                "tbody" | "tfoot" => self.table_rows(child, head, caption, rows),
+                "tr" => {
+                    let mut cells = vec![];
+                    let mut only_th = true;
+                    for node in &child.children {
+                        if let Node::Element(ref cell) = *node {
+                            if cell.name == "td" || cell.name == "th" {
+                                only_th = only_th && cell.name == "th";
+                                let content = self.blocks(&cell.children);
+                                let content = self.cell(content);
+                                cells.push(Token::TableCell(content));
+                            }
+                        }
+                    }
+                    if !cells.is_empty() {
+                        rows.push((head, only_th, cells));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Flattens the blocks of a table cell, since cells can only contain
+    /// inline content
+    fn cell(&mut self, blocks: Vec<Token>) -> Vec<Token> {
+        let mut res = vec![];
+        for block in blocks {
+            match block {
+                Token::Paragraph(inline) | Token::Header(_, inline) => {
+                    if !res.is_empty() {
+                        res.push(Token::HardBreak);
+                    }
+                    res.extend(inline);
+                }
+                _ => {
+                    self.warnings.push(lformat!("in {file}, ignoring a block that is \
+                                                 not a paragraph in a table cell",
+                                                file = self.source));
+                }
+            }
+        }
+        res
+    }
+
+    /// Converts an `img` element, unless it has no source
+    fn image(&mut self, element: &Element) -> Option<Token> {
+        let source = match element.attribute("src") {
+            Some(source) if !source.trim().is_empty() => source.trim(),
+            _ => return None,
+        };
+        if source.starts_with("data:") {
+            self.warnings.push(lformat!("in {file}, ignoring an image embedded in the \
+                                         HTML file, which should be saved in its own \
+                                         file",
+                                        file = self.source));
+            return None;
+        }
+        self.features.image = true;
+        let title = element.attribute("title").unwrap_or("").to_owned();
+        let alt = match element.attribute("alt") {
+            Some(alt) if !alt.is_empty() => vec![Token::Str(alt.to_owned())],
+            _ => vec![],
+        };
+        // Widths in pixels can't be used for all formats, so only relative
+        // widths and lengths are kept
+        let attributes = match element.attribute("width") {
+            Some(width) => {
+                let attributes = format!("width={}", width.trim());
+                if ImageOptions::parse(&attributes).is_some() {
+                    attributes
+                } else {
+                    String::new()
+                }
+            }
+            None => String::new(),
+        };
+        Some(Token::Image(source.to_owned(), title, attributes, alt))
+    }
+
+    /// Converts nodes that are the content of a paragraph or of an inline
+    /// element
+    fn inline(&mut self, nodes: &[Node]) -> Vec<Token> {
+        let mut res = vec![];
+        for node in nodes {
+            match *node {
+                Node::Text(ref text) => push_text(&mut res, &collapse(text)),
+                Node::Element(ref element) => self.inline_element(element, &mut res),
+            }
+        }
+        res
+    }
+
+    /// Converts an inline element
+    fn inline_element(&mut self, element: &Element, res: &mut Vec<Token>) {
+        match element.name.as_str() {
+            "em" | "i" | "cite" | "dfn" | "var" => {
+                res.push(Token::Emphasis(self.inline(&element.children)))
+            }
+            "strong" | "b" => res.push(Token::Strong(self.inline(&element.children))),
+            "code" | "kbd" | "samp" | "tt" => {
+                let code = collapse(&raw_text(&element.children));
+                res.push(Token::Code(vec![Token::Str(code)]));
+            }
+            "sup" => {
+                self.features.superscript = true;
+                res.push(Token::Superscript(self.inline(&element.children)));
+            }
+            "sub" => {
+                self.features.subscript = true;
+                res.push(Token::Subscript(self.inline(&element.children)));
+            }
+            "abbr" | "acronym" => {
+                let content = self.inline(&element.children);
+                match element.attribute("title") {
+                    Some(title) if !title.is_empty() => {
+                        res.push(Token::Abbreviation(title.to_owned(), content))
+                    }
+                    _ => res.extend(content),
+                }
+            }
+            "a" => {
+                let content = self.inline(&element.children);
+                match element.attribute("href") {
+                    // Internal links (e.g. anchors of footnotes added by word
+                    // processors) don't point to anything once imported
+                    Some(href) if !href.is_empty() && !href.starts_with('#') => {
+                        self.features.url = true;
+                        let title = element.attribute("title").unwrap_or("").to_owned();
+                        res.push(Token::Link(href.to_owned(), title, content));
+                    }
+                    _ => res.extend(content),
+                }
+            }
+            "img" => {
+                if let Some(image) = self.image(element) {
+                    res.push(image);
+                }
+            }
+            "br" => {
+                trim_end(res);
+                res.push(Token::HardBreak);
+            }
+            name if SKIPPED_ELEMENTS.contains(&name) => {}
+            _ => {
+                // Word processors often use styles instead of semantic
+                // elements for bold and italic text
+                let style = element.attribute("style")
+                    .unwrap_or("")
+                    .to_lowercase()
+                    .replace(' ', "");
+                let mut content = self.inline(&element.children);
+                if style.contains("font-style:italic") {
+                    content = vec![Token::Emphasis(content)];
+                }
+                if style.contains("font-weight:bold") || style.contains("font-weight:700") ||
+                   style.contains("font-weight:800") || style.contains("font-weight:900") {
+                    content = vec![Token::Strong(content)];
+                }
+                res.extend(content);
+            }
+        }
+    }
+}
+
+/// Parses an HTML document into a list of nodes
+///
+/// The parser is lenient and does not validate the document: closing tags
+/// that don't match an open element are ignored, paragraphs, list items
+/// and table cells can be left open, and elements that are still open at
+/// the end of the document are closed.
+fn parse_tree(s: &str) -> Vec<Node> {
+    // The root of the document is an element without a name, which is
+    // never closed
+    let mut stack = vec![Element {
+                             name: String::new(),
+                             attributes: vec![],
+                             children: vec![],
+                         }];
+    let mut rest = s;
+    while let Some(pos) = rest.find('<') {
+        add_text(&mut stack, &rest[..pos]);
+        rest = &rest[pos..];
+        if rest.starts_with("<!--") {
+            rest = match rest.find("-->") {
+                Some(end) => &rest[end + 3..],
+                None => "",
+            };
+        } else if rest.starts_with("<!") || rest.starts_with("<?") {
+            // Doctype, CDATA, processing instructions and conditional
+            // comments of word processors
+            rest = match rest.find('>') {
+                Some(end) => &rest[end + 1..],
+                None => "",
+            };
+        } else if rest.starts_with("</") {
+            let end = rest.find('>').map_or(rest.len(), |end| end + 1);
+            let name = tag_name(&rest[2..end]);
+            close(&mut stack, &name);
+            rest = &rest[end..];
+        } else if rest.as_bytes().get(1).map_or(false, |&c| is_letter(c)) {
+            let end = tag_end(rest);
+            let tag = rest[1..end].trim_right_matches('>').trim_right();
+            let name = tag_name(tag);
+            let self_closing = tag.ends_with('/');
+            let attributes = parse_attributes(&tag[name.len()..]);
+            rest = &rest[end..];
+            if RAW_TEXT_ELEMENTS.contains(&name.as_str()) {
+                // Skip the content of scripts and styles, their closing tag
+                // is then ignored
+                rest = match find_closing_tag(rest, &name) {
+                    Some(end) => &rest[end..],
+                    None => "",
+                };
+                continue;
+            }
+            open(&mut stack, name, attributes, self_closing);
+        } else {
+            // A `<` that doesn't start a tag
+            add_text(&mut stack, "<");
+            rest = &rest[1..];
+        }
+    }
+    add_text(&mut stack, rest);
+    close_to(&mut stack, 1);
+    stack.pop().unwrap().children
+}
+
+/// Adds a text node to the current element
+fn add_text(stack: &mut Vec<Element>, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    stack.last_mut()
+        .unwrap()
+        .children
+        .push(Node::Text(decode_entities(text)));
+}
+
+/// Opens an element, closing the elements that can't contain it first
+fn open(stack: &mut Vec<Element>,
+        name: String,
+        attributes: Vec<(String, String)>,
+        self_closing: bool) {
+    // Elements that are closed when this one opens, unless one of the
+    // elements of the second list is found first
+    let (closed, scope): (&[&str], &[&str]) = match name.as_str() {
+        "li" => (&["li"][..], &["ul", "ol"][..]),
+        "dt" | "dd" => (&["dt", "dd"][..], &["dl"][..]),
+        "tr" => (&["tr"][..], &["table"][..]),
+        "td" | "th" => (&["td", "th"][..], &["tr", "table"][..]),
+        "thead" | "tbody" | "tfoot" => (&["thead", "tbody", "tfoot"][..], &["table"][..]),
+        name if BLOCK_ELEMENTS.contains(&name) => (&["p"][..], BLOCK_ELEMENTS),
+        _ => (&[][..], &[][..]),
+    };
+    let mut found = None;
+    for (i, element) in stack.iter().enumerate().skip(1).rev() {
+        if closed.contains(&element.name.as_str()) {
+            found = Some(i);
+            break;
+        }
+        if scope.contains(&element.name.as_str()) {
+            break;
+        }
+    }
+    if let Some(i) = found {
+        close_to(stack, i);
+    }
+
+    let element = Element {
+        name: name,
+        attributes: attributes,
+        children: vec![],
+    };
+    if self_closing || VOID_ELEMENTS.contains(&element.name.as_str()) {
+        stack.last_mut().unwrap().children.push(Node::Element(element));
+    } else {
+        stack.push(element);
+    }
+}
+
+/// Closes the last open element with this name, and the elements it
+/// contains, if there is one
+fn close(stack: &mut Vec<Element>, name: &str) {
+    if let Some(i) = stack.iter().rposition(|element| element.name == name) {
+        if i > 0 {
+            close_to(stack, i);
+        }
+    }
+}
+
+/// Closes the open elements, starting from the `n`th one
+fn close_to(stack: &mut Vec<Element>, n: usize) {
+    while stack.len() > n {
+        let element = stack.pop().unwrap();
+        stack.last_mut().unwrap().children.push(Node::Element(element));
+    }
+}
+
+/// Returns the (lowercased) name at the start of a tag
+fn tag_name(s: &str) -> String {
+    s.chars()
+        .take_while(|&c| c <= '\x7f' && (c.is_alphanumeric() || c == '-' || c == ':'))
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// Returns the position following the `>` that ends the tag at the start
+/// of `s`, ignoring the ones in quoted attribute values
+fn tag_end(s: &str) -> usize {
+    let mut quote = None;
+    let mut previous = ' ';
+    for (i, c) in s.char_indices() {
+        match quote {
+            Some(q) => {
+                if c == q {
+                    quote = None;
+                }
+            }
+            None => {
+                match c {
+                    '"' | '\'' if previous == '=' => quote = Some(c),
+                    '>' => return i + 1,
+                    _ => {}
+                }
+            }
+        }
+        if !is_space(c) {
+            previous = c;
+        }
+    }
+    s.len()
+}
+
+/// Parses the attributes of a tag, following its name
+fn parse_attributes(s: &str) -> Vec<(String, String)> {
+    let mut attributes = vec![];
+    let mut rest = s;
+    loop {
+        rest = rest.trim_left_matches(|c: char| is_space(c) || c == '/');
+        if rest.is_empty() {
+            break;
+        }
+        let len = rest.find(|c: char| is_space(c) || c == '=' || c == '/')
+            .unwrap_or(rest.len());
+        let name = rest[..len].to_lowercase();
+        rest = rest[len..].trim_left_matches(is_space);
+        let mut value = String::new();
+        if rest.starts_with('=') {
+            rest = rest[1..].trim_left_matches(is_space);
+            if rest.starts_with('"') || rest.starts_with('\'') {
+                let quote = if rest.starts_with('"') { '"' } else { '\'' };
+                let end = rest[1..].find(quote).map_or(rest.len(), |end| end + 1);
+                value = decode_entities(&rest[1..end]);
+                rest = if end < rest.len() { &rest[end + 1..] } else { "" };
+            } else {
+                let end = rest.find(is_space).unwrap_or(rest.len());
+                value = decode_entities(&rest[..end]);
+                rest = &rest[end..];
+            }
+        }
+        if !name.is_empty() {
+            attributes.push((name, value));
+        }
+    }
+    attributes
+}
+
+/// Returns the position of the closing tag of a raw text element
+fn find_closing_tag(s: &str, name: &str) -> Option<usize> {
+    let pattern = format!("</{}", name);
+    let pattern = pattern.as_bytes();
+    let bytes = s.as_bytes();
+    if bytes.len() < pattern.len() {
+        return None;
+    }
+    (0..bytes.len() - pattern.len() + 1).find(|&i| {
+        bytes[i..i + pattern.len()]
+            .iter()
+            .zip(pattern)
+            .all(|(&c, &p)| to_lower(c) == p)
+    })
+}
+
+/// Decodes the character references of a text
+///
+/// Unknown or invalid references are kept as they are.
+fn decode_entities(s: &str) -> String {
+    if !s.contains('&') {
+        return s.to_owned();
+    }
+    let mut res = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(pos) = rest.find('&') {
+        res.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        let decoded = match rest.find(';') {
+            Some(end) if end <= 10 => decode_entity(&rest[1..end]).map(|c| (c, end)),
+            _ => None,
+        };
+        match decoded {
+            Some((c, end)) => {
+                res.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                res.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    res.push_str(rest);
+    res
+}
+
+/// Decodes a character reference, without the leading `&` and the trailing
+/// `;`
+fn decode_entity(s: &str) -> Option<char> {
+    if s.starts_with("#x") || s.starts_with("#X") {
+        u32::from_str_radix(&s[2..], 16).ok().and_then(char::from_u32)
+    } else if s.starts_with('#') {
+        s[1..].parse::<u32>().ok().and_then(char::from_u32)
+    } else {
+        ENTITIES.iter().find(|&&(name, _)| name == s).map(|&(_, c)| c)
+    }
+}
+
+/// Returns the first element with this name among the descendants of an
+/// element
+fn find<'a>(element: &'a Element, name: &str) -> Option<&'a Element> {
+    for node in &element.children {
+        if let Node::Element(ref child) = *node {
+            if child.name == name {
+                return Some(child);
+            }
+            if let Some(found) = find(child, name) {
+                return Some(found);
+            }
+        }
+    }
+    None
+}
+
+/// Returns the language of a `pre` element, set by a `language-xxx` or
+/// `lang-xxx` class on it or on the `code` element it contains
+fn language(pre: &Element) -> Option<String> {
+    let mut elements = vec![pre];
+    if let Some(code) = find(pre, "code") {
+        elements.push(code);
+    }
+    for element in elements {
+        for class in element.attribute("class").unwrap_or("").split_whitespace() {
+            for prefix in &["language-", "lang-"] {
+                if class.starts_with(*prefix) && class.len() > prefix.len() {
+                    return Some(class[prefix.len()..].to_owned());
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Returns the text of nodes and of their descendants, as it is written
+fn raw_text(nodes: &[Node]) -> String {
+    let mut res = String::new();
+    for node in nodes {
+        match *node {
+            Node::Text(ref text) => res.push_str(text),
+            Node::Element(ref element) => {
+                if element.name == "br" {
+                    res.push('\n');
+                } else if !SKIPPED_ELEMENTS.contains(&element.name.as_str()) {
+                    res.push_str(&raw_text(&element.children));
+                }
+            }
+        }
+    }
+    res
+}
+
+/// Replaces sequences of whitespace by a single space, like browsers do
+///
+/// Non-breaking spaces are kept.
+fn collapse(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    let mut space = false;
+    for c in s.chars() {
+        if is_space(c) {
+            if !space {
+                res.push(' ');
+            }
+            space = true;
+        } else {
+            res.push(c);
+            space = false;
+        }
+    }
+    res
+}
+
+/// Adds (collapsed) text to inline tokens, merging it with the previous
+/// text if there is some
+fn push_text(res: &mut Vec<Token>, text: &str) {
+    // Whitespace at the start of a line, or following whitespace, is not
+    // displayed
+    let skip_space = match res.last() {
+        Some(&Token::Str(ref s)) => s.ends_with(' '),
+        Some(&Token::HardBreak) => true,
+        _ => false,
+    };
+    let text = if skip_space { text.trim_left_matches(' ') } else { text };
+    if text.is_empty() {
+        return;
+    }
+    if let Some(&mut Token::Str(ref mut s)) = res.last_mut() {
+        s.push_str(text);
+        return;
+    }
+    res.push(Token::Str(text.to_owned()));
+}
+
+/// Removes the whitespace and line breaks at the start and at the end of
+/// inline tokens
+fn trim(tokens: &mut Vec<Token>) {
+    loop {
+        let remove = match tokens.first_mut() {
+            Some(&mut Token::Str(ref mut s)) => {
+                *s = s.trim_left_matches(' ').to_owned();
+                s.is_empty()
+            }
+            Some(&mut Token::HardBreak) => true,
+            _ => false,
+        };
+        if !remove {
+            break;
+        }
+        tokens.remove(0);
+    }
+    loop {
+        trim_end(tokens);
+        let is_break = match tokens.last() {
+            Some(&Token::HardBreak) => true,
+            _ => false,
+        };
+        if !is_break {
+            break;
+        }
+        tokens.pop();
+    }
+}
+
+/// Removes the whitespace at the end of inline tokens
+fn trim_end(tokens: &mut Vec<Token>) {
+    let remove = match tokens.last_mut() {
+        Some(&mut Token::Str(ref mut s)) => {
+            *s = s.trim_right_matches(' ').to_owned();
+            s.is_empty()
+        }
+        _ => false,
+    };
+    if remove {
+        tokens.pop();
+    }
+}
+
+/// Returns true if inline tokens only contain whitespace, e.g. the empty
+/// paragraphs (`<p>&nbsp;</p>`) of word processors
+fn is_blank(tokens: &[Token]) -> bool {
+    tokens.iter().all(|token| match *token {
+        Token::Str(ref s) => s.trim().is_empty(),
+        Token::HardBreak => true,
+        _ => false,
+    })
+}
+
+/// Returns true if a character is whitespace for HTML, which doesn't
+/// include non-breaking spaces
+fn is_space(c: char) -> bool {
+    c == ' ' || c == '\t' || c == '\n' || c == '\r' || c == '\x0c'
+}
+
+/// Returns true if a byte is an ASCII letter
+fn is_letter(c: u8) -> bool {
+    (c >= b'a' && c <= b'z') || (c >= b'A' && c <= b'Z')
+}
+
+/// Lowercases an ASCII letter
+fn to_lower(c: u8) -> u8 {
+    if c >= b'A' && c <= b'Z' { c + 32 } else { c }
+}
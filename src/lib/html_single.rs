@@ -24,12 +24,16 @@ use templates::img;
 use renderer::Renderer;
 use book_renderer::BookRenderer;
 use parser::Parser;
+use resource_handler::ResourceHandler;
+use minify;
 
 use rustc_serialize::base64::{self, ToBase64};
 
 use std::convert::{AsMut, AsRef};
 use std::io;
+use std::fs;
 use std::fmt::Write;
+use std::borrow::Cow;
 
 /// Single file HTML renderer
 ///
@@ -85,15 +89,27 @@ impl<'a> HtmlSingleRenderer<'a> {
 
         let mut titles = vec![];
         let mut chapters = vec![];
-        let render_notes_chapter =
-            self.html.book.options.get_bool("html.standalone.one_chapter").unwrap();
+        let render_notes_chapter = match self.html.notes {
+            "end_of_chapter" => true,
+            "end_of_book" => false,
+            _ => self.html.book.options.get_bool("html.standalone.one_chapter").unwrap(),
+        };
 
         for (i, chapter) in self.html.book.chapters.iter().enumerate() {
-            self.html.handler.add_link(chapter.filename.as_ref(),
-                                       format!("#chapter-{}", i));
+            self.html.handler.add_chapter_link(chapter.filename.as_ref(),
+                                               format!("#chapter-{}", i));
         }
-        
+        for caption in self.html.captions.all() {
+            self.html.handler.add_link(format!("#{}", caption.id),
+                                       format!("#{}", caption.id));
+        }
+        for section in self.html.sections.all() {
+            self.html.handler.add_link(format!("#{}", section.id),
+                                       format!("#{}", section.id));
+        }
+
         for (i, chapter) in self.html.book.chapters.iter().enumerate() {
+            let chapter = self.html.book.clean_chapter(chapter, self.html.clean)?;
             let n = chapter.number;
             let v = &chapter.content;
             self.html.chapter_config(i, n, String::new());
@@ -164,19 +180,44 @@ impl<'a> HtmlSingleRenderer<'a> {
         }
         self.html.render_end_notes(&mut content);
 
+        let self_contained = self.html.book.options.get_bool("html.standalone.self_contained")
+            .unwrap();
+        if self_contained {
+            if let Ok(cover) = self.html.book.options.get_path("cover") {
+                if fs::metadata(&cover).is_err() {
+                    return Err(Error::file_not_found(&self.html.book.source,
+                                                     lformat!("cover"),
+                                                     cover));
+                }
+                content = format!("<div id = \"cover\">
+  <img class = \"cover\" alt = \"{}\" src = \"{}\" />
+</div>
+{}",
+                                  self.html.book.options.get_str("title").unwrap(),
+                                  self.html.handler.map_image(&self.html.book.source,
+                                                              Cow::Owned(cover))?
+                                  .as_ref(),
+                                  content);
+            }
+        }
 
         let toc = self.html.toc.render(false);
         // If display_toc, display the toc inline
-        if self.html.book.options.get_bool("rendering.inline_toc").unwrap() {
-
-            content = format!("<div id = \"toc\">
+        if let Some(location) = self.html.book.inline_toc_location()? {
+            let class = if location == "end" { " class = \"toc-end\"" } else { "" };
+            let inline_toc = format!("<div id = \"toc\"{class}>
   <h1>{title}</h1>
   {toc}
 </div>
-{content}",
-                              title = self.html.get_toc_name()?,
-                              toc = &toc,
-                              content = content);
+",
+                                     class = class,
+                                     title = self.html.get_toc_name()?,
+                                     toc = &toc);
+            if location == "start" {
+                content = inline_toc + &content;
+            } else {
+                content.push_str(&inline_toc);
+            }
         }
 
         // Render the CSS
@@ -184,18 +225,18 @@ impl<'a> HtmlSingleRenderer<'a> {
                                        .as_ref(),
                                        &self.html.book.source,
                                        "html.css")?;
-        let mut data = self.html
+        let data = self.html
             .book
             .get_metadata(|s| self.render_vec(&Parser::new().parse_inline(s)?))?
             .insert_str("colours",
-                        self.html.book.get_template("html.css.colours")?);
-        if let Ok(html_css_add) = self.html.book.options.get_str("html.css.add") {
-            data = data.insert_str("additional_code", html_css_add);
-        }
-        let data = data.build();
+                        self.html.book.get_template("html.css.colours")?)
+            .build();
         let mut res: Vec<u8> = vec![];
         template_css.render_data(&mut res, &data)?;
-        let css = String::from_utf8_lossy(&res);
+        let mut css = self.html.append_css(String::from_utf8_lossy(&res), "html.css.add");
+        if self_contained {
+            css = Cow::Owned(self.embed_css_urls(&css)?);
+        }
 
         // Render the JS
         let template_js =
@@ -207,6 +248,8 @@ impl<'a> HtmlSingleRenderer<'a> {
             .insert_str("pages_svg", &pages_svg)
             .insert_bool("one_chapter",
                          self.html.book.options.get_bool("html.standalone.one_chapter").unwrap())
+            .insert_bool("reader_mode",
+                         self.html.book.options.get_bool("html.standalone.reader_mode").unwrap())
             .insert_str("common_script",
                         self.html.book.get_template("html.js").unwrap().as_ref())
             .build();
@@ -223,6 +266,8 @@ impl<'a> HtmlSingleRenderer<'a> {
             .insert_bool(self.html.book.options.get_str("lang").unwrap(), true)
             .insert_bool("one_chapter",
                          self.html.book.options.get_bool("html.standalone.one_chapter").unwrap())
+            .insert_bool("reader_mode",
+                         self.html.book.options.get_bool("html.standalone.reader_mode").unwrap())
             .insert_str("style", css.as_ref())
             .insert_str("print_style",
                         self.html.book.get_template("html.css.print").unwrap())
@@ -257,7 +302,56 @@ impl<'a> HtmlSingleRenderer<'a> {
                                    "html.standalone.template")?;
         let mut res = vec![];
         template.render_data(&mut res, &data)?;
-        Ok(String::from_utf8_lossy(&res).into_owned())
+        let res = String::from_utf8_lossy(&res);
+        if self.html.book.options.get_bool("html.minify").unwrap() {
+            Ok(minify::html(&res))
+        } else {
+            Ok(res.into_owned())
+        }
+    }
+
+    /// Replaces the local files referenced by `url(...)` in a stylesheet
+    /// (fonts, background images, ...) with data URIs
+    ///
+    /// Paths are relative to the book's root. Remote or missing files are
+    /// left as they are, with a warning.
+    fn embed_css_urls(&mut self, css: &str) -> Result<String> {
+        let mut res = String::with_capacity(css.len());
+        let mut rest = css;
+        while let Some(begin) = rest.find("url(") {
+            let after = &rest[begin + "url(".len()..];
+            let end = match after.find(')') {
+                Some(end) => end,
+                None => break,
+            };
+            res.push_str(&rest[..begin]);
+            let url = after[..end].trim().trim_matches(|c: char| c == '"' || c == '\'');
+            if url.is_empty() || url.starts_with("data:") || url.starts_with('#') {
+                res.push_str(&rest[begin..begin + "url(".len() + end + 1]);
+            } else if !ResourceHandler::is_local(url) {
+                self.html.book.logger
+                    .warning(lformat!("HTML: stylesheet references remote file {file}, which \
+                                       can't be embedded in the document",
+                                      file = url));
+                res.push_str(&rest[begin..begin + "url(".len() + end + 1]);
+            } else {
+                let path = self.html.book.root.join(url);
+                let path = path.to_string_lossy().into_owned();
+                if fs::metadata(&path).is_err() {
+                    self.html.book.logger
+                        .warning(lformat!("HTML: could not find file {file} referenced by the \
+                                           stylesheet",
+                                          file = url));
+                    res.push_str(&rest[begin..begin + "url(".len() + end + 1]);
+                } else {
+                    let data = self.html.handler.map_image(&self.html.book.source, path)?;
+                    write!(res, "url(\"{}\")", data)?;
+                }
+            }
+            rest = &after[end + 1..];
+        }
+        res.push_str(rest);
+        Ok(res)
     }
 }
 
@@ -0,0 +1,214 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Attributes of images, e.g. `![Logo](logo.png){width=60% align=center}`,
+//! and audio or video files included with the image syntax.
+
+use std::fs::File;
+use std::io::Read;
+
+/// Width of the text in ODT pages, in centimeters (A4 paper with 2cm margins)
+const ODT_TEXT_WIDTH: f32 = 17.0;
+
+/// Resolution used to compute the natural size of images
+const DPI: f32 = 96.0;
+
+/// Options of an image, set by the attributes following it
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ImageOptions {
+    /// Width of the image, as a percentage of the line width or as a length (e.g. `5cm`)
+    pub width: Option<String>,
+    /// Alignment of the image: `left`, `center` or `right`
+    pub align: Option<String>,
+    /// Id of the image, if it is a numbered figure
+    pub id: Option<String>,
+    /// Set if the title of a standalone image must not be displayed as a caption
+    pub nocaption: bool,
+}
+
+impl ImageOptions {
+    /// Parses the attributes of an image (without the braces), separated by
+    /// spaces or commas
+    ///
+    /// Returns `None` if the attributes are not valid.
+    pub fn parse(attributes: &str) -> Option<ImageOptions> {
+        let mut options = ImageOptions::default();
+        for attribute in attributes.split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|s| !s.is_empty()) {
+            let (key, value) = match attribute.find('=') {
+                Some(i) => (&attribute[..i], Some(&attribute[i + 1..])),
+                None => (attribute, None),
+            };
+            match (key, value) {
+                ("width", Some(width)) if is_width(width) => options.width = Some(width.to_owned()),
+                ("align", Some(align)) if align == "left" || align == "center" || align == "right" => {
+                    options.align = Some(align.to_owned())
+                }
+                ("id", Some(id)) if !id.is_empty() => options.id = Some(id.to_owned()),
+                ("nocaption", None) => options.nocaption = true,
+                _ => return None,
+            }
+        }
+        Some(options)
+    }
+
+    /// Returns the width as a LaTeX length, e.g. `0.6\linewidth` for `60%`
+    pub fn tex_width(&self) -> Option<String> {
+        self.width.as_ref().map(|width| if width.ends_with('%') {
+            let percent: f32 = width[..width.len() - 1].parse().unwrap();
+            format!("{}\\linewidth", percent / 100.0)
+        } else {
+            width.clone()
+        })
+    }
+
+    /// Returns the width and height of the frame of an image in ODT, in
+    /// centimeters, and its width relative to the text if it is a percentage
+    ///
+    /// `size` is the size of the image in pixels, if it is known: it sets the
+    /// height and, if no width is set, the width (at 96 DPI, but no wider
+    /// than the text).
+    pub fn odt_size(&self, size: Option<(u32, u32)>) -> (f32, f32, Option<String>) {
+        let (width, relative) = match self.width {
+            Some(ref width) if width.ends_with('%') => {
+                let percent: f32 = width[..width.len() - 1].parse().unwrap();
+                (ODT_TEXT_WIDTH * percent / 100.0, Some(width.clone()))
+            }
+            Some(ref width) => (length_cm(width), None),
+            None => {
+                match size {
+                    Some((w, _)) => ((w as f32 / DPI * 2.54).min(ODT_TEXT_WIDTH), None),
+                    None => (ODT_TEXT_WIDTH * 0.8, Some(String::from("80%"))),
+                }
+            }
+        };
+        let height = match size {
+            Some((w, h)) if w > 0 => width * h as f32 / w as f32,
+            _ => width * 0.75,
+        };
+        (width, height, relative)
+    }
+
+    /// Returns the LaTeX environment used to align the image
+    pub fn tex_align(&self) -> &'static str {
+        match self.align.as_ref().map(|s| s.as_str()) {
+            Some("left") => "flushleft",
+            Some("right") => "flushright",
+            _ => "center",
+        }
+    }
+}
+
+/// Checks that a width is a percentage or a length in a unit supported by both
+/// CSS and LaTeX
+fn is_width(width: &str) -> bool {
+    ["%", "cm", "mm", "in", "pt", "em"].iter().any(|unit| {
+        width.ends_with(unit) &&
+            width[..width.len() - unit.len()].parse::<f32>().map(|n| n > 0.0).unwrap_or(false)
+    })
+}
+
+/// Converts a length accepted by `is_width` (other than a percentage) to centimeters
+fn length_cm(width: &str) -> f32 {
+    let (unit, factor) = [("cm", 1.0), ("mm", 0.1), ("in", 2.54), ("pt", 2.54 / 72.0), ("em", 2.54 / 6.0)]
+        .iter()
+        .cloned()
+        .find(|&(unit, _)| width.ends_with(unit))
+        .unwrap();
+    width[..width.len() - unit.len()].parse::<f32>().unwrap() * factor
+}
+
+/// Returns the size in pixels of a PNG, GIF or JPEG image, read from its header
+pub fn image_size(file: &str) -> Option<(u32, u32)> {
+    let mut data = vec![];
+    match File::open(file).and_then(|f| f.take(1 << 16).read_to_end(&mut data)) {
+        Ok(_) => size_from_header(&data),
+        Err(_) => None,
+    }
+}
+
+/// Returns the size in pixels of a PNG, GIF or JPEG image, given (at least)
+/// its first bytes
+pub fn size_from_header(data: &[u8]) -> Option<(u32, u32)> {
+    let be = |i: usize| ((data[i] as u32) << 8) | data[i + 1] as u32;
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") && data.len() >= 24 {
+        Some(((be(16) << 16) | be(18), (be(20) << 16) | be(22)))
+    } else if data.starts_with(b"GIF8") && data.len() >= 10 {
+        Some((data[6] as u32 | (data[7] as u32) << 8, data[8] as u32 | (data[9] as u32) << 8))
+    } else if data.starts_with(&[0xff, 0xd8]) {
+        // Look for the start of frame segment, which contains the size
+        let mut i = 2;
+        while i + 9 <= data.len() && data[i] == 0xff {
+            let marker = data[i + 1];
+            if marker >= 0xc0 && marker <= 0xcf && marker != 0xc4 && marker != 0xc8 && marker != 0xcc {
+                return Some((be(i + 7), be(i + 5)));
+            }
+            i += 2 + be(i + 2) as usize;
+        }
+        None
+    } else {
+        None
+    }
+}
+
+/// Parses a list of image widths in pixels, separated by spaces or commas
+/// (e.g. `480 800 1200`)
+///
+/// Returns `None` if one of the widths is not a positive integer.
+pub fn parse_widths(widths: &str) -> Option<Vec<u32>> {
+    let mut res = vec![];
+    for width in widths.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|s| !s.is_empty()) {
+        match width.parse::<u32>() {
+            Ok(n) if n > 0 => res.push(n),
+            _ => return None,
+        }
+    }
+    res.sort();
+    res.dedup();
+    Some(res)
+}
+
+/// Returns true if the image is in a bitmap format that can be resized
+/// (i.e., not a vector image)
+pub fn is_resizable(file: &str) -> bool {
+    let file = file.to_lowercase();
+    [".png", ".jpg", ".jpeg", ".gif", ".webp"].iter().any(|ext| file.ends_with(ext))
+}
+
+/// Returns the file name of the variant of an image resized to `width` pixels,
+/// e.g. `images/image_0-480w.jpg` for `images/image_0.jpg`
+pub fn variant_name(file: &str, width: u32) -> String {
+    let (base, ext) = match file.rfind('.') {
+        Some(i) if !file[i..].contains('/') => (&file[..i], &file[i..]),
+        _ => (file, ""),
+    };
+    format!("{}-{}w{}", base, width, ext)
+}
+
+/// Returns the kind of media (`audio` or `video`) of a file included with
+/// the image syntax, based on its extension, or `None` if it is an image
+pub fn media_kind(file: &str) -> Option<&'static str> {
+    let file = file.to_lowercase();
+    if [".mp3", ".m4a", ".ogg", ".oga", ".wav"].iter().any(|ext| file.ends_with(ext)) {
+        Some("audio")
+    } else if [".mp4", ".m4v", ".webm", ".ogv"].iter().any(|ext| file.ends_with(ext)) {
+        Some("video")
+    } else {
+        None
+    }
+}
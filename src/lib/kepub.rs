@@ -0,0 +1,134 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Kobo-specific tweaks of EPUB chapters (a.k.a. kepub), allowing Kobo
+//! readers to display reading statistics and highlights.
+
+/// Elements whose content is counted as a paragraph by Kobo readers
+const BLOCKS: &'static [&'static str] = &["p", "h1", "h2", "h3", "h4", "h5", "h6", "li", "td",
+                                         "th", "dt", "dd", "caption"];
+
+/// Elements whose content must be left untouched
+const VERBATIM: &'static [&'static str] = &["pre", "script", "style"];
+
+/// Wraps each sentence of an XHTML chapter in a `koboSpan`, and the content of
+/// its body in the `div`s expected by Kobo readers
+pub fn add_spans(xhtml: &str) -> String {
+    let (start, end) = match (xhtml.find("<body"), xhtml.rfind("</body>")) {
+        (Some(start), Some(end)) => {
+            match xhtml[start..].find('>') {
+                Some(i) if start + i < end => (start + i + 1, end),
+                _ => return xhtml.to_owned(),
+            }
+        }
+        _ => return xhtml.to_owned(),
+    };
+
+    let mut res = String::with_capacity(xhtml.len() * 2);
+    res.push_str(&xhtml[..start]);
+    res.push_str("<div id = \"book-columns\"><div id = \"book-inner\">");
+
+    let body = &xhtml[start..end];
+    let mut paragraph = 0;
+    let mut sentence = 0;
+    let mut verbatim = 0;
+    let mut rest = body;
+    while !rest.is_empty() {
+        if rest.starts_with('<') {
+            let len = tag_len(rest);
+            let tag = &rest[..len];
+            let closing = tag.starts_with("</");
+            let name: String = tag.trim_left_matches(|c: char| c == '<' || c == '/')
+                .chars()
+                .take_while(|c| c.is_alphanumeric())
+                .collect();
+            let name = name.to_lowercase();
+            if VERBATIM.contains(&name.as_str()) && !tag.ends_with("/>") {
+                if closing {
+                    verbatim -= 1;
+                } else {
+                    verbatim += 1;
+                }
+            } else if !closing && BLOCKS.contains(&name.as_str()) {
+                paragraph += 1;
+                sentence = 0;
+            }
+            res.push_str(tag);
+            rest = &rest[len..];
+        } else {
+            let len = rest.find('<').unwrap_or(rest.len());
+            let text = &rest[..len];
+            if verbatim > 0 || paragraph == 0 || text.trim().is_empty() {
+                res.push_str(text);
+            } else {
+                for part in sentences(text) {
+                    if part.trim().is_empty() {
+                        res.push_str(part);
+                    } else {
+                        sentence += 1;
+                        res.push_str(&format!("<span class = \"koboSpan\" id = \"kobo.{}.{}\">{}</span>",
+                                              paragraph,
+                                              sentence,
+                                              part));
+                    }
+                }
+            }
+            rest = &rest[len..];
+        }
+    }
+
+    res.push_str("</div></div>");
+    res.push_str(&xhtml[end..]);
+    res
+}
+
+/// Returns the length of the tag at the start of `s`, ignoring `>` in quoted
+/// attribute values
+fn tag_len(s: &str) -> usize {
+    let mut quote = None;
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (None, '>') => return i + 1,
+            _ => (),
+        }
+    }
+    s.len()
+}
+
+/// Splits a text after each end of sentence (a punctuation mark followed by
+/// whitespace)
+pub fn sentences(text: &str) -> Vec<&str> {
+    let mut res = vec![];
+    let mut start = 0;
+    let mut after_punctuation = false;
+    for (i, c) in text.char_indices() {
+        if after_punctuation && c.is_whitespace() {
+            let end = i + c.len_utf8();
+            res.push(&text[start..end]);
+            start = end;
+            after_punctuation = false;
+        } else {
+            after_punctuation = c == '.' || c == '!' || c == '?' || c == '…';
+        }
+    }
+    if start < text.len() {
+        res.push(&text[start..]);
+    }
+    res
+}
@@ -21,11 +21,18 @@ use error::{Error, Result, Source};
 use token::Token;
 use token::Data;
 use zipper::Zipper;
+use odt::OdtRenderer;
+use pdf::PdfRenderer;
 use resource_handler::ResourceHandler;
 use renderer::Renderer;
 use parser::Parser;
 use book_renderer::BookRenderer;
-use syntax::Syntax;
+use syntax::{Syntax, CodeOptions, code_to_tex};
+use captions::{Captions, CaptionKind};
+use sections::Sections;
+use images::{self, ImageOptions};
+use slides;
+use lang;
 
 use crowbook_text_processing::escape;
 
@@ -42,16 +49,29 @@ use std::borrow::Cow;
 pub struct LatexRenderer<'a> {
     book: &'a Book,
     current_chapter: Number,
+    current_hide: bool,
     handler: ResourceHandler<'a>,
     source: Source,
     escape: bool,
+    /// Whether text is typographically cleaned
+    clean: bool,
     first_letter: bool,
     first_paragraph: bool,
     is_short: bool,
+    /// Whether chapters are rendered as frames of beamer slides (`tex.class: beamer`)
+    beamer: bool,
     proofread: bool,
     syntax: Option<Syntax>,
     hyperref: bool,
     enum_level: usize,
+    notes: &'a str,
+    pending_endnotes: bool,
+    captions: Captions,
+    sections: Sections,
+    /// External links listed at the end of the book, if `rendering.links.appendix` is set
+    links: Vec<String>,
+    /// Summaries and contents of spoiler blocks, listed at the end of the book
+    solutions: Vec<(String, String)>,
 }
 
 impl<'a> LatexRenderer<'a> {
@@ -59,6 +79,8 @@ impl<'a> LatexRenderer<'a> {
     pub fn new(book: &'a Book) -> LatexRenderer<'a> {
         let mut handler = ResourceHandler::new(&book.logger);
         handler.set_images_mapping(true);
+        // Labels are only defined for chapters, sections and numbered elements
+        handler.set_fragments(false);
         let syntax = if book.options.get_str("rendering.highlight").unwrap() == "syntect"
             && book.features.codeblock {
             Some(Syntax::new(book,
@@ -71,16 +93,26 @@ impl<'a> LatexRenderer<'a> {
         LatexRenderer {
             book: book,
             current_chapter: Number::Default,
+            current_hide: false,
             handler: handler,
             source: Source::empty(),
             escape: true,
+            clean: book.cleans("tex"),
             first_letter: false,
             first_paragraph: true,
-            is_short: book.options.get_str("tex.class").unwrap() == "article",
+            is_short: book.options.get_str("tex.class").unwrap() == "article" ||
+                book.options.get_str("tex.class").unwrap() == "beamer",
+            beamer: book.options.get_str("tex.class").unwrap() == "beamer",
             proofread: false,
             syntax: syntax,
             hyperref: book.options.get_bool("tex.hyperref").unwrap(),
             enum_level: 0,
+            notes: book.options.get_str("rendering.notes").unwrap(),
+            pending_endnotes: false,
+            captions: Captions::new(book),
+            sections: Sections::new(book),
+            links: vec![],
+            solutions: vec![],
         }
     }
 
@@ -121,40 +153,189 @@ impl<'a> LatexRenderer<'a> {
                             to)
     }
 
+    /// Wraps content that is not part of a chapter (e.g. the table of
+    /// contents) in a frame, in beamer mode
+    fn in_frame(&self, content: &str) -> String {
+        if self.beamer {
+            format!("\\begin{{frame}}[allowframebreaks]\n{}\\end{{frame}}\n", content)
+        } else {
+            content.to_owned()
+        }
+    }
+
+    /// Renders the content of a chapter as beamer frames
+    ///
+    /// Frames start at each level-2 header, which is used as their title,
+    /// and at each rule; other frames are titled with the chapter's title.
+    /// `label` is inserted in the first frame.
+    fn render_frames(&mut self, tokens: &[Token], title: &str, label: &str) -> Result<String> {
+        let mut res = String::new();
+        let mut label = label.to_owned();
+        for slide in slides::split_slides(tokens) {
+            let (frame_title, slide) = match slide[0] {
+                Token::Header(2, ref vec) => {
+                    if let Some(section) = self.sections.next() {
+                        write!(label, "\\label{{{}}}\n", section.id)?;
+                    }
+                    (self.render_vec(vec)?, &slide[1..])
+                }
+                _ => (title.to_owned(), slide),
+            };
+            write!(res,
+                   "\\begin{{frame}}[fragile]{{{}}}\n{}{}\\end{{frame}}\n",
+                   frame_title,
+                   label,
+                   self.render_vec(slide)?)?;
+            label.clear();
+        }
+        Ok(res)
+    }
+
     /// Render latex in a string
     pub fn render_book(&mut self) -> Result<String> {
         let mut content = String::new();
+        self.notes = self.book.notes_mode()?;
+        if self.beamer {
+            // Content outside of frames is not displayed
+            self.notes = "footnotes";
+        }
+        let endnotes = self.notes != "footnotes";
+        let note_counter = if endnotes { "endnote" } else { "footnote" };
 
         // set tex numbering and toc display to book's parameters
         let numbering = self.book.options.get_i32("rendering.num_depth").unwrap() - 1;
+        let toc_depth = self.book.toc_depth() - 1;
         write!(content,
                "\\setcounter{{tocdepth}}{{{}}}
 \\setcounter{{secnumdepth}}{{{}}}\n",
-               numbering,
+               toc_depth,
                numbering)?;
 
-        if self.book.options.get_bool("rendering.inline_toc").unwrap() {
-            content.push_str("\\tableofcontents\n");
+        // Front matter (and inline table of contents) use roman page numbers
+        let has_front_matter = self.book.chapters.iter().any(|c| c.front_matter);
+        if has_front_matter {
+            content.push_str("\\mdfrontmatter\n");
+        }
+
+        let toc_location = self.book.inline_toc_location()?;
+        if toc_location == Some("start") {
+            content.push_str(&self.in_frame("\\tableofcontents\n"));
         }
 
         for (i, chapter) in self.book.chapters.iter().enumerate() {
-            self.handler.add_link(chapter.filename.as_ref(), format!("chapter-{}", i));
+            self.handler.add_chapter_link(chapter.filename.as_ref(), format!("chapter-{}", i));
+        }
+        for caption in self.captions.all() {
+            self.handler.add_link(format!("#{}", caption.id), caption.id.as_str());
+        }
+        for section in self.sections.all() {
+            self.handler.add_link(format!("#{}", section.id), section.id.as_str());
         }
         
+        let mut main_matter = false;
         for (i, chapter) in self.book.chapters.iter().enumerate() {
+            let chapter = self.book.clean_chapter(chapter, self.clean)?;
             let n = chapter.number;
             self.current_chapter = n;
+            self.current_hide = chapter.hide_title;
             let v = &chapter.content;
             self.source = Source::new(chapter.filename.as_str());
+            if has_front_matter && !chapter.front_matter && !main_matter {
+                main_matter = true;
+                content.push_str("\\mdmainmatter\n");
+            }
+            // Lists of figures and tables are generated by LaTeX itself (but
+            // not by beamer)
+            match chapter.list_of {
+                Some(CaptionKind::Figure) => {
+                    if !self.beamer {
+                        content.push_str("\\listoffigures\n");
+                    }
+                    continue;
+                }
+                Some(CaptionKind::Table) => {
+                    if !self.beamer {
+                        content.push_str("\\listoftables\n");
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            // If this chapter must not be listed in the table of contents, set
+            // its depth so that nothing is added to it until the chapter ends
+            if !chapter.toc {
+                content.push_str("\\addtocontents{toc}{\\protect\\setcounter{tocdepth}{-2}}\n");
+            }
             let mut offset = 0;
-            if !v.is_empty() && v[0].is_header() {
+            // In beamer mode, other headers start a frame
+            let has_title = match v.first() {
+                Some(&Token::Header(n, _)) => !self.beamer || n == 1,
+                _ => false,
+            };
+            if has_title {
                 content.push_str(&self.render_token(&v[0])?);
                 offset = 1;
             }
+            if self.book.options.get_bool("rendering.footnotes.reset") == Ok(true) {
+                write!(content, "\\setcounter{{{}}}{{0}}\n", note_counter)?;
+            }
+            if self.beamer {
+                let title = match v.first() {
+                    Some(&Token::Header(1, ref vec)) => self.render_vec(vec)?,
+                    _ => String::new(),
+                };
+                let frames = self.render_frames(&v[offset..],
+                                                &title,
+                                                &format!("\\label{{chapter-{}}}\n", i))?;
+                content.push_str(&frames);
+            } else {
+                write!(content,
+                       "\\label{{chapter-{}}}\n",
+                       i)?;
+                content.push_str(&self.render_vec(&v[offset..])?);
+            }
+            // Only display end notes if there are some, else endnotes would
+            // display the ones of the previous chapter again
+            if self.notes == "end_of_chapter" && self.pending_endnotes {
+                self.pending_endnotes = false;
+                content.push_str("\\theendnotes\n");
+            }
+            if !chapter.toc {
+                write!(content,
+                       "\\addtocontents{{toc}}{{\\protect\\setcounter{{tocdepth}}{{{}}}}}\n",
+                       toc_depth)?;
+            }
+        }
+        if self.pending_endnotes {
+            content.push_str("\\theendnotes\n");
+        }
+        if !self.solutions.is_empty() {
+            let lang = self.book.options.get_str("lang").unwrap();
+            write!(content,
+                   "\\begin{{mdsolutions}}{{{}}}\n",
+                   escape::tex(lang::get_str(lang, "solutions")))?;
+            for (i, &(ref summary, ref solution)) in self.solutions.iter().enumerate() {
+                write!(content,
+                       "\\begin{{mdsolution}}{{{}}}{{{}}}{{{}}}\n{}\\end{{mdsolution}}\n",
+                       i + 1,
+                       summary,
+                       escape::tex(lang::get_str(lang, "back_to_page")),
+                       solution)?;
+            }
+            content.push_str("\\end{mdsolutions}\n");
+        }
+        if !self.links.is_empty() {
+            let lang = self.book.options.get_str("lang").unwrap();
             write!(content,
-                   "\\label{{chapter-{}}}\n",
-                   i)?;
-            content.push_str(&self.render_vec(&v[offset..])?);
+                   "\\begin{{mdlinks}}{{{}}}\n",
+                   escape::tex(lang::get_str(lang, "links")))?;
+            for (i, url) in self.links.iter().enumerate() {
+                write!(content, "\\item[{{[{}]}}] \\url{{{}}}\n", i + 1, url)?;
+            }
+            content.push_str("\\end{mdlinks}\n");
+        }
+        if toc_location == Some("end") {
+            content.push_str(&self.in_frame("\\tableofcontents\n"));
         }
         self.source = Source::empty();
 
@@ -209,28 +390,88 @@ impl<'a> LatexRenderer<'a> {
             }
         });
 
+        let front_matter = self.render_front_matter()?;
         let template = compile_str(self.book.get_template("tex.template")?.as_ref(),
                                    &self.book.source,
                                    "tex.template")?;
         let mut data = self.book.get_metadata(|s| self.render_vec(&Parser::new().parse_inline(s)?))?
             .insert_str("content", content)
+            .insert_str("front_matter", front_matter)
             .insert_str("class", self.book.options.get_str("tex.class").unwrap())
             .insert_bool("tex_title", self.book.options.get_bool("tex.title").unwrap())
             .insert_str("papersize", self.book.options.get_str("tex.paper.size").unwrap())
             .insert_bool("stdpage", self.book.options.get_bool("tex.stdpage").unwrap())
             .insert_bool("use_url", self.book.features.url)
+            .insert_bool("qrcodes", self.book.features.url &&
+                         self.book.options.get_bool("tex.links.qrcode").unwrap())
             .insert_bool("use_tables", self.book.features.table)
             .insert_bool("use_codeblocks", self.book.features.codeblock)
-            .insert_bool("use_images", self.book.features.image)
+            .insert_bool("use_admonitions", self.book.features.admonition)
+            .insert_bool("use_columns", self.book.features.columns)
+            .insert_bool("use_images", self.book.features.image ||
+                         self.book.options.get_str("rendering.scene_break") == Ok("ornament"))
             .insert_str("tex_lang", tex_lang);
         if let Ok(tex_tmpl_add) = self.book.options.get_str("tex.template.add") {
             data = data.insert_str("additional_code", tex_tmpl_add);
         }
+        let mut class_options = vec![];
         if let Ok(tex_font_size) = self.book.options.get_i32("tex.font.size") {
+            class_options.push(format!("{}pt", tex_font_size));
             data = data
                 .insert_bool("has_tex_size", true)
                 .insert_str("tex_size", format!("{}", tex_font_size));
         }
+        // Article class doesn't have chapters, so this option is meaningless for it
+        if !self.is_short {
+            if let Ok(open_recto) = self.book.options.get_bool("tex.chapter.open_recto") {
+                if open_recto {
+                    class_options.push(String::from("twoside"));
+                    class_options.push(String::from("openright"));
+                } else {
+                    class_options.push(String::from("openany"));
+                }
+            }
+        }
+        if !class_options.is_empty() {
+            data = data
+                .insert_bool("has_class_options", true)
+                .insert_str("class_options", class_options.join(","));
+        }
+        if self.book.options.get_bool("tex.blank_pages.empty").unwrap() {
+            data = data.insert_bool("empty_blank_pages", true);
+        }
+
+        // Options for print-ready PDF
+        if let Ok(bleed) = self.book.options.get_str("tex.bleed") {
+            data = data
+                .insert_bool("has_bleed", true)
+                .insert_str("bleed", bleed);
+        }
+        if self.book.options.get_bool("tex.crop_marks").unwrap() {
+            if self.book.options.get_str("tex.bleed").is_err() {
+                self.book.logger.warning(lformat!("LaTeX: tex.crop_marks is set to true but \
+                                                   tex.bleed is not set, so crop marks will \
+                                                   not be visible"));
+            }
+            data = data.insert_bool("crop_marks", true);
+        }
+        if self.book.options.get_bool("tex.grayscale").unwrap() {
+            data = data.insert_bool("grayscale", true);
+        }
+
+        // Footnote numbering
+        if self.book.options.get_bool("rendering.footnotes.reset") == Ok(false) {
+            data = data.insert_bool("footnotes_continuous", true);
+        }
+        if self.book.footnote_style("tex")? == "symbols" {
+            data = data.insert_bool("footnotes_symbols", true);
+        }
+        if endnotes {
+            data = data.insert_bool("endnotes", true);
+            if self.notes == "end_of_book" {
+                data = data.insert_bool("endnotes_book", true);
+            }
+        }
 
         // If class isn't book, set open_any to true, so margins are symetric.
         let mut book = false;
@@ -252,6 +493,28 @@ impl<'a> LatexRenderer<'a> {
         if self.book.options.get_str("tex.command") == Ok("xelatex") {
             data = data.insert_bool("xelatex", true);
         }
+        // Display the git revision in the footer of proofreading (draft) builds
+        if let (true, Some(git)) = (self.proofread, self.book.git.as_ref()) {
+            let lang = self.book.options.get_str("lang").unwrap();
+            data = data.insert_str("draft_revision",
+                                   format!("{} {}",
+                                           escape::tex(lang::get_str(lang, "draft")),
+                                           escape::tex(git.revision())));
+        }
+        // Personalized copies: stamp the watermark across or at the bottom of pages
+        if let Ok(watermark) = self.book.options.get_str("watermark") {
+            let key = match self.book.options.get_str("watermark.pdf").unwrap() {
+                "diagonal" => "watermark_diagonal",
+                "footer" => "watermark_footer",
+                value => {
+                    return Err(Error::render(&self.book.source,
+                                             lformat!("watermark.pdf set to '{value}', \
+                                                       expected 'diagonal' or 'footer'",
+                                                      value = value)))
+                }
+            };
+            data = data.insert_str(key, escape::tex(watermark));
+        }
         let data = data.build();
         let mut res: Vec<u8> = vec![];
         template.render_data(&mut res, &data)?;
@@ -260,6 +523,25 @@ impl<'a> LatexRenderer<'a> {
             Ok(res) => Ok(res),
         }
     }
+
+    /// Renders the front matter pages (half title, "also by", title and
+    /// copyright pages) displayed by `\maketitle`
+    fn render_front_matter(&mut self) -> Result<String> {
+        let template = compile_str(self.book.get_template("tex.front_matter.template")?.as_ref(),
+                                   &self.book.source,
+                                   "tex.front_matter.template")?;
+        let data = self.book.get_metadata(|s| self.render_vec(&Parser::new().parse_inline(s)?))?
+            .insert_bool("half_title",
+                         self.book.options.get_bool("rendering.front_matter.half_title").unwrap())
+            .insert_bool("title_page",
+                         self.book.options.get_bool("rendering.front_matter.title_page").unwrap())
+            .insert_bool("copyright",
+                         self.book.options.get_bool("rendering.front_matter.copyright").unwrap())
+            .build();
+        let mut res: Vec<u8> = vec![];
+        template.render_data(&mut res, &data)?;
+        Ok(String::from_utf8_lossy(&res).into_owned())
+    }
 }
 
 impl<'a> Renderer for LatexRenderer<'a> {
@@ -267,7 +549,7 @@ impl<'a> Renderer for LatexRenderer<'a> {
         match *token {
             Token::Str(ref text) => {
                 let content = if self.escape {
-                    self.book.clean(escape::tex(text.as_ref()), true)
+                    self.book.clean_if(self.clean, escape::tex(text.as_ref()), true)
                 } else {
                     Cow::Borrowed(text.as_ref())
                 };
@@ -318,49 +600,89 @@ impl<'a> Renderer for LatexRenderer<'a> {
                 }
                 Ok(format!("{}\n\n", self.render_vec(vec)?))
             }
+            Token::Header(n, ref vec) if self.beamer && n >= 2 => {
+                // Sectioning commands can't be used inside frames
+                let label = self.sections.next()
+                    .map(|section| format!("\\label{{{}}}\n", section.id))
+                    .unwrap_or_default();
+                Ok(format!("\\par\\textbf{{{}}}\\par\n{}", self.render_vec(vec)?, label))
+            }
             Token::Header(n, ref vec) => {
                 let mut content = String::new();
                 if n == 1 {
                     self.first_paragraph = true;
-                    if self.current_chapter == Number::Hidden {
-                        if !self.is_short {
-                            return Ok(r#"\chapter*{}"#.to_owned());
+                    if let Number::Specified(n) = self.current_chapter {
+                        // Short classes (article, beamer) don't have chapters
+                        if self.is_short {
+                            content.push_str(r"\setcounter{section}{");
                         } else {
-                            return Ok(r#"\section*{}"#.to_owned());
+                            content.push_str(r"\setcounter{chapter}{");
                         }
-                    } else if let Number::Specified(n) = self.current_chapter {
-                        content.push_str(r"\setcounter{chapter}{");
                         write!(content, "{}", n - 1)?;
                         content.push_str("}\n");
                     }
                 }
-                match n {
+                let level = match n {
                     1 => {
                         if !self.is_short {
                             if self.current_chapter.is_part() {
                                 if self.book.options.get_bool("rendering.part.reset_counter").unwrap() {
                                     content.push_str(r"\setcounter{chapter}{0}");
                                 }
-                                content.push_str(r"\part");
+                                "part"
                             } else {
-                                content.push_str(r"\chapter");
+                                "chapter"
                             }
                         } else {
                             // Chapters or parts aren't handlled for class article
-                            content.push_str(r"\section");
+                            "section"
                         }
                     }
-                    2 => content.push_str(r"\section"),
-                    3 => content.push_str(r"\subsection"),
-                    4 => content.push_str(r"\subsubsection"),
-                    _ => content.push_str(r"\paragraph"),
+                    2 => "section",
+                    3 => "subsection",
+                    4 => "subsubsection",
+                    _ => "paragraph",
+                };
+                let title = self.render_vec(vec)?;
+                let numbered = self.current_chapter.is_numbered();
+                let section_level = if self.current_chapter.is_part() { n - 1 } else { n };
+                let label = if section_level >= 2 {
+                    self.sections.next()
+                        .map(|section| format!("\\label{{{}}}\n", section.id))
+                        .unwrap_or_default()
+                } else {
+                    String::new()
+                };
+                if n == 1 && self.current_hide {
+                    // The title is not displayed, but the chapter is still
+                    // counted and listed in the table of contents
+                    write!(content, "\\{}*{{}}\n", level)?;
+                    if numbered {
+                        write!(content, "\\refstepcounter{{{}}}\n", level)?;
+                    }
+                } else if numbered {
+                    write!(content, "\\{}{{{}}}\n", level, title)?;
+                    content.push_str(&label);
+                    return Ok(content);
+                } else {
+                    write!(content, "\\{}*{{{}}}\n", level, title)?;
                 }
-                if !self.current_chapter.is_numbered() {
-                    content.push_str("*");
+                // Starred headings are not added to the table of contents
+                // by LaTeX, so do it manually
+                if !title.is_empty() {
+                    if numbered {
+                        write!(content,
+                               "\\addcontentsline{{toc}}{{{level}}}{{\\protect\\numberline{{\\the{level}}}{title}}}\n",
+                               level = level,
+                               title = title)?;
+                    } else {
+                        write!(content,
+                               "\\addcontentsline{{toc}}{{{level}}}{{{title}}}\n",
+                               level = level,
+                               title = title)?;
+                    }
                 }
-                content.push_str(r"{");
-                content.push_str(&self.render_vec(vec)?);
-                content.push_str("}\n");
+                content.push_str(&label);
                 Ok(content)
             }
             Token::Emphasis(ref vec) => Ok(format!("\\emph{{{}}}", self.render_vec(vec)?)),
@@ -368,11 +690,15 @@ impl<'a> Renderer for LatexRenderer<'a> {
             Token::Code(ref vec) => Ok(format!("\\mdcode{{{}}}",
                                                insert_breaks(&self.render_vec(vec)?))),
             Token::Superscript(ref vec) => Ok(format!("\\textsuperscript{{{}}}", self.render_vec(vec)?)),
+            Token::Abbreviation(_, ref vec) => self.render_vec(vec),
             Token::Subscript(ref vec) => Ok(format!("\\textsubscript{{{}}}", self.render_vec(vec)?)),
             Token::BlockQuote(ref vec) => {
                 Ok(format!("\\begin{{mdblockquote}}\n{}\n\\end{{mdblockquote}}\n",
                            self.render_vec(vec)?))
             }
+            Token::Attribution(ref vec) => {
+                Ok(format!("\\par\\hfill{{\\upshape --- {}}}\n", self.render_vec(vec)?))
+            }
             Token::CodeBlock(ref language, ref vec) => {
                 self.escape = false;
                 let mut res = self.render_vec(vec)?;
@@ -381,7 +707,30 @@ impl<'a> Renderer for LatexRenderer<'a> {
                     res.pop();
                 }
                 self.escape = true;
-                res = if let Some(ref syntax) = self.syntax {
+                let options = CodeOptions::parse(language).unwrap_or_default();
+                res = if options.is_set() {
+                    let lines = if let Some(ref syntax) = self.syntax {
+                        syntax.to_tex_lines(&res, language)?
+                    } else {
+                        res.lines().map(code_to_tex).collect()
+                    };
+                    let mut content = String::new();
+                    for (i, line) in lines.iter().enumerate() {
+                        let n = i + 1;
+                        let command = if options.is_highlighted(n) {
+                            "mdcodelinehl"
+                        } else {
+                            "mdcodeline"
+                        };
+                        let number = if options.line_numbers {
+                            format!("{}", n)
+                        } else {
+                            String::new()
+                        };
+                        content.push_str(&format!("\\{}{{{}}}{{{}}}\n", command, number, line));
+                    }
+                    format!("{{\\sloppy\n{}}}", content)
+                } else if let Some(ref syntax) = self.syntax {
                     syntax.to_tex(&res, language)?
                 } else {
                     format!("\\begin{{spverbatim}}
@@ -392,11 +741,139 @@ impl<'a> Renderer for LatexRenderer<'a> {
                 res = format!("\\begin{{mdcodeblock}}
 {}
 \\end{{mdcodeblock}}", res);
+                if options.is_numbered() {
+                    // Numbered listing: add a caption and a label
+                    let listing = self.captions.next(CaptionKind::Listing);
+                    if let Some(listing) = listing {
+                        let lang = self.book.options.get_str("lang").unwrap();
+                        let caption = if listing.caption.is_empty() {
+                            String::new()
+                        } else {
+                            self.render_vec(&Parser::new().parse_inline(&listing.caption)?)?
+                        };
+                        res = format!("\\mdlistingcaption{{{label}}}{{{number}}}{{{caption}}}{{{id}}}\n{code}",
+                                      label = escape::tex(listing.label(lang)),
+                                      number = listing.number,
+                                      caption = caption,
+                                      id = escape::tex(listing.id.as_str()),
+                                      code = res);
+                    }
+                }
                 Ok(res)
             }
-            Token::Rule => Ok(String::from("\\mdrule\n")),
+            Token::Verse(ref vec) => {
+                // Stanzas are not "real" paragraphs, so they must not get initials
+                let first_paragraph = self.first_paragraph;
+                self.first_paragraph = false;
+                let content = self.render_vec(vec)?;
+                self.first_paragraph = first_paragraph;
+                // Indentation is stored as em spaces, make sure LaTeX doesn't drop it
+                Ok(format!("\\begin{{mdverse}}\n{}\\end{{mdverse}}\n",
+                           content.replace('\u{2003}', "\\hspace*{1em}")))
+            }
+            Token::Spoiler(ref summary, ref vec) => {
+                // The content is moved to the solutions at the end of the book
+                let first_paragraph = self.first_paragraph;
+                self.first_paragraph = false;
+                let content = self.render_vec(vec)?;
+                self.first_paragraph = first_paragraph;
+                let lang = self.book.options.get_str("lang").unwrap();
+                let summary = if summary.is_empty() {
+                    escape::tex(lang::get_str(lang, "solution")).into_owned()
+                } else {
+                    self.render_vec(&Parser::new().parse_inline(summary)?)?
+                };
+                self.solutions.push((summary.clone(), content));
+                Ok(format!("\\mdspoiler{{{}}}{{{}}}{{{}}}\n",
+                           self.solutions.len(),
+                           summary,
+                           escape::tex(lang::get_str(lang, "see_page"))))
+            }
+            Token::Admonition(ref kind, ref title, ref vec) => {
+                let first_paragraph = self.first_paragraph;
+                self.first_paragraph = false;
+                let content = self.render_vec(vec)?;
+                self.first_paragraph = first_paragraph;
+                let title = if title.is_empty() {
+                    let lang = self.book.options.get_str("lang").unwrap();
+                    escape::tex(lang::get_str(lang, kind)).into_owned()
+                } else {
+                    self.render_vec(&Parser::new().parse_inline(title)?)?
+                };
+                Ok(format!("\\begin{{mdadmonition}}{{{}}}\n{}\\end{{mdadmonition}}\n",
+                           title,
+                           content))
+            }
+            Token::Columns(count, ref vec) => {
+                let first_paragraph = self.first_paragraph;
+                self.first_paragraph = false;
+                let content = self.render_vec(vec)?;
+                self.first_paragraph = first_paragraph;
+                Ok(format!("\\begin{{mdcolumns}}{{{}}}\n{}\\end{{mdcolumns}}\n",
+                           count,
+                           content))
+            }
+            Token::Exercise(_, ref vec) => {
+                let exercise = self.captions.next(CaptionKind::Exercise);
+                let content = self.render_vec(vec)?;
+                match exercise {
+                    Some(exercise) => {
+                        let lang = self.book.options.get_str("lang").unwrap();
+                        Ok(format!("\\begin{{mdexercise}}{{{label}}}{{{number}}}{{{id}}}\n{content}\\end{{mdexercise}}\n",
+                                   label = escape::tex(exercise.label(lang)),
+                                   number = exercise.number,
+                                   id = escape::tex(exercise.id.as_str()),
+                                   content = content))
+                    }
+                    None => Ok(content),
+                }
+            }
+            Token::Answer(ref vec) => self.render_vec(vec),
+            Token::Epigraph(ref author, ref vec) => {
+                let first_paragraph = self.first_paragraph;
+                self.first_paragraph = false;
+                let content = self.render_vec(vec)?;
+                self.first_paragraph = first_paragraph;
+                let author = self.render_vec(&Parser::new().parse_inline(author)?)?;
+                Ok(format!("\\mdepigraph{{{}}}{{{}}}\n", content, author))
+            }
+            Token::Rule => {
+                match self.book.options.get_str("rendering.scene_break").unwrap() {
+                    "asterisks" => Ok(String::from("\\mdrule\n")),
+                    "asterism" => Ok(String::from("\\mdasterism\n")),
+                    "blank" => Ok(String::from("\\mdblankrule\n")),
+                    "ornament" => {
+                        let ornament = self.book.options
+                            .get_path("rendering.scene_break.ornament")
+                            .map_err(|_| {
+                                Error::render(&self.source,
+                                              lformat!("rendering.scene_break is set to 'ornament' \
+                                                        but rendering.scene_break.ornament is not set"))
+                            })?;
+                        Ok(format!("\\mdornament{{{}}}\n",
+                                   self.handler.map_image(&self.source, ornament)?))
+                    }
+                    value => Err(Error::render(&self.source,
+                                               lformat!("rendering.scene_break set to '{value}', \
+                                                         not a valid value",
+                                                        value = value))),
+                }
+            }
             Token::SoftBreak => Ok(String::from(" ")),
             Token::HardBreak => Ok(String::from("\\mdhardbreak\n")),
+            // The print edition has its own page breaks
+            Token::PageBreak(_) => Ok(String::new()),
+            // In beamer mode, page breaks already split frames
+            Token::NewPage if self.beamer => Ok(String::new()),
+            Token::NewPage => Ok(String::from("\\clearpage\n")),
+            Token::KeepTogether(ref vec) => {
+                let mut blocks = vec![];
+                for token in vec {
+                    blocks.push(self.render_token(token)?);
+                }
+                Ok(format!("\\begin{{samepage}}\n{}\\end{{samepage}}\n",
+                           blocks.join("\\nopagebreak\n")))
+            }
             Token::List(ref vec) => {
                 Ok(format!("\\begin{{itemize}}\n{}\\end{{itemize}}",
                            self.render_vec(vec)?))
@@ -430,29 +907,105 @@ impl<'a> Renderer for LatexRenderer<'a> {
             },
             Token::Item(ref vec) => Ok(format!("\\item {}\n", self.render_vec(vec)?)),
             Token::Link(ref url, _, ref vec) => {
-                let content = self.render_vec(vec)?;
+                let content = if vec.is_empty() && url.starts_with('#') {
+                    // Link to a numbered element without text: use its label
+                    let lang = self.book.options.get_str("lang").unwrap();
+                    let id = &url[1..];
+                    match self.captions.find(id) {
+                        Some(caption) => escape::tex(caption.label(lang)).into_owned(),
+                        None => {
+                            self.sections.find(id)
+                                .map(|section| escape::tex(section.label(lang)).into_owned())
+                                .unwrap_or_default()
+                        }
+                    }
+                } else {
+                    self.render_vec(vec)?
+                };
 
                 if self.hyperref && self.handler.contains_link(url) {
                     Ok(format!("\\hyperref[{}]{{{}}}", escape::tex(self.handler.get_link(url)), content))
                 } else {
-                    let url = escape::tex(url.as_ref());
-                    if &content == &url {
-                        Ok(format!("\\url{{{}}}", content))
-                    } else if self.book.options.get_bool("tex.links_as_footnotes").unwrap() {
-                        Ok(format!("\\href{{{}}}{{{}}}\\protect\\footnote{{\\url{{{}}}}}",
-                                   url,
-                                   content,
-                                   url))
+                    let external = !ResourceHandler::is_local(url);
+                    let appendix = external &&
+                        self.book.options.get_bool("rendering.links.appendix").unwrap();
+                    let qrcode = external && self.book.options.get_bool("tex.links.qrcode").unwrap();
+                    // Autolinks are rendered with \url, other links with \href
+                    let autolink = content == escape::tex(url.as_ref());
+                    let url = escape_url(url);
+                    let footnote = !autolink && !appendix &&
+                        self.book.options.get_bool("tex.links_as_footnotes").unwrap();
+                    let mut link = if autolink {
+                        format!("\\url{{{}}}", url)
                     } else {
-                        Ok(format!("\\href{{{}}}{{{}}}", url, content))
+                        format!("\\href{{{}}}{{{}}}", url, content)
+                    };
+                    if appendix {
+                        // Only number each link once
+                        let n = match self.links.iter().position(|l| *l == url) {
+                            Some(i) => i + 1,
+                            None => {
+                                self.links.push(url.clone());
+                                self.links.len()
+                            }
+                        };
+                        write!(link, "~[{}]", n)?;
+                    }
+                    if footnote || qrcode {
+                        let mut note = String::new();
+                        if qrcode {
+                            write!(note, "\\mdqrcode{{{}}}", url)?;
+                        }
+                        if footnote {
+                            if qrcode {
+                                note.push(' ');
+                            }
+                            write!(note, "\\url{{{}}}", url)?;
+                        }
+                        write!(link, "\\protect\\footnote{{{}}}", note)?;
                     }
+                    Ok(link)
                 }
             }
-            Token::StandaloneImage(ref url, _, _) => {
+            Token::Image(ref url, _, _, ref alt) |
+            Token::StandaloneImage(ref url, _, _, ref alt) if images::media_kind(url).is_some() => {
+                // Audio or video can't be printed, so only display their description
+                let kind = images::media_kind(url).unwrap();
+                let lang = self.book.options.get_str("lang").unwrap();
+                let media = format!("\\mdmedia{{{}}}{{{}}}",
+                                    escape::tex(lang::get_str(lang, kind)),
+                                    self.render_vec(alt)?);
+                if token.is_image() {
+                    Ok(media)
+                } else {
+                    Ok(format!("{}\n\n", media))
+                }
+            }
+            Token::StandaloneImage(ref url, ref title, ref attributes, _) => {
+                let options = ImageOptions::parse(attributes).unwrap_or_default();
+                let figure = self.captions.next_figure(title, &options);
                 if ResourceHandler::is_local(url) {
                     let img = self.handler.map_image(&self.source, url.as_ref())?;
-                    Ok(format!("\\mdstandaloneimage{{{}}}\n",
-                               img))
+                    let width = options.tex_width()
+                        .unwrap_or_else(|| String::from("0.8\\linewidth"));
+                    if let Some(figure) = figure {
+                        // Numbered figure: add a caption and a label
+                        Ok(format!("\\mdfigure{{{img}}}{{{width}}}{{{align}}}{{{number}}}{{{caption}}}{{{id}}}\n",
+                                   img = img,
+                                   width = width,
+                                   align = options.tex_align(),
+                                   number = figure.number,
+                                   caption = escape::tex(title.as_str()),
+                                   id = escape::tex(figure.id.as_str())))
+                    } else if options.width.is_some() || options.align.is_some() {
+                        Ok(format!("\\mdstandaloneimageopt{{{}}}{{{}}}{{{}}}\n",
+                                   img,
+                                   width,
+                                   options.tex_align()))
+                    } else {
+                        Ok(format!("\\mdstandaloneimage{{{}}}\n",
+                                   img))
+                    }
 
                 } else {
                     self.book
@@ -464,10 +1017,13 @@ impl<'a> Renderer for LatexRenderer<'a> {
                     Ok(String::new())
                 }
             }
-            Token::Image(ref url, _, _) => {
+            Token::Image(ref url, _, ref attributes, _) => {
                 if ResourceHandler::is_local(url) {
-                    Ok(format!("\\mdimage{{{}}}",
-                               self.handler.map_image(&self.source, url.as_ref())?))
+                    let img = self.handler.map_image(&self.source, url.as_ref())?;
+                    match ImageOptions::parse(attributes).unwrap_or_default().tex_width() {
+                        Some(width) => Ok(format!("\\mdimagewidth{{{}}}{{{}}}", img, width)),
+                        None => Ok(format!("\\mdimage{{{}}}", img)),
+                    }
                 } else {
                     self.book
                         .logger
@@ -479,7 +1035,15 @@ impl<'a> Renderer for LatexRenderer<'a> {
                 }
             }
             Token::Footnote(ref vec) => {
-                Ok(format!("\\protect\\footnote{{{}}}", self.render_vec(vec)?))
+                if self.notes == "footnotes" {
+                    Ok(format!("\\protect\\footnote{{{}}}", self.render_vec(vec)?))
+                } else {
+                    self.pending_endnotes = true;
+                    Ok(format!("\\protect\\endnote{{{}}}", self.render_vec(vec)?))
+                }
+            }
+            Token::MarginNote(ref vec) => {
+                Ok(format!("\\protect\\mdmarginnote{{{}}}", self.render_vec(vec)?))
             }
             Token::Table(n, ref vec) => {
                 let mut cols = String::new();
@@ -487,14 +1051,31 @@ impl<'a> Renderer for LatexRenderer<'a> {
                     cols.push_str("|X");
                 }
                 cols.push_str("|");
-                Ok(format!("\\begin{{mdtable}}{{{}}}
+                // Numbered table: add a caption and a label
+                let (caption, vec) = match vec.first() {
+                    Some(&Token::TableCaption(ref caption)) => (Some(caption), &vec[1..]),
+                    _ => (None, &vec[..]),
+                };
+                let mut caption_tex = String::new();
+                if let Some(caption) = caption {
+                    let table = self.captions.next(CaptionKind::Table);
+                    if let Some(table) = table {
+                        caption_tex = format!("\\mdtablecaption{{{number}}}{{{caption}}}{{{id}}}\n",
+                                              number = table.number,
+                                              caption = self.render_vec(caption)?,
+                                              id = escape::tex(table.id.as_str()));
+                    }
+                }
+                Ok(format!("{}\\begin{{mdtable}}{{{}}}
 \\hline
 {}
 \\hline
 \\end{{mdtable}}\n\n",
+                           caption_tex,
                            cols,
                            self.render_vec(vec)?))
             }
+            Token::TableCaption(_) => Ok(String::new()),
             Token::TableRow(ref vec) |
             Token::TableHead(ref vec) => {
                 let mut res: String = vec.iter()
@@ -583,8 +1164,26 @@ impl BookRenderer for Pdf {
     }
     
     fn render(&self, book: &Book, to: &mut io::Write) -> Result<()> {
-        LatexRenderer::new(book)
-            .render_pdf(to)?;
+        match book.options.get_str("pdf.engine").unwrap() {
+            "latex" => {
+                LatexRenderer::new(book)
+                    .render_pdf(to)?;
+            }
+            "libreoffice" => {
+                OdtRenderer::new(book)
+                    .render_pdf(to)?;
+            }
+            "builtin" => {
+                PdfRenderer::new(book)
+                    .render_book(to)?;
+            }
+            engine => {
+                return Err(Error::render(&book.source,
+                                         lformat!("pdf.engine set to '{engine}', expected 'latex', \
+                                                   'libreoffice' or 'builtin'",
+                                                  engine = engine)));
+            }
+        }
         Ok(())
     }
 }
@@ -617,3 +1216,23 @@ pub fn insert_breaks(text: &str) -> String {
     }
     result
 }
+
+/// Escapes an URL for `\url`, `\href` or `\mdqrcode`
+///
+/// Unlike `escape::tex`, which would display backslashes in the URL, this
+/// only escapes `#` and `%` (which would break the build inside another
+/// command, e.g. a footnote), and percent-encodes braces and backslashes.
+pub fn escape_url(url: &str) -> String {
+    let mut res = String::with_capacity(url.len());
+    for c in url.chars() {
+        match c {
+            '#' => res.push_str("\\#"),
+            '%' => res.push_str("\\%"),
+            '{' => res.push_str("\\%7B"),
+            '}' => res.push_str("\\%7D"),
+            '\\' => res.push_str("\\%5C"),
+            _ => res.push(c),
+        }
+    }
+    res
+}
@@ -61,6 +61,39 @@
 //! for all chapters represented as an Abstact Syntax Tree (i.e., a vector of `Token`s).
 //! It is thus possible to create a new renderer (or manipulate this AST in other ways).
 //!
+//! # Adding output formats
+//!
+//! A new renderer implements the `BookRenderer` trait, and is registered with
+//! `Book::add_format`. Its output file is then set with an `output.{format}`
+//! option, in the book configuration file or with `set_options`, and
+//! `render_all` renders it along with built-in formats:
+//!
+//! ```
+//! use crowbook::{Result, Book, BookRenderer};
+//! use std::io::Write;
+//!
+//! /// Renders the number of chapters of a book
+//! struct Count {}
+//!
+//! impl BookRenderer for Count {
+//!     fn auto_path(&self, book_name: &str) -> Result<String> {
+//!         Ok(format!("{}.count", book_name))
+//!     }
+//!
+//!     fn render(&self, book: &Book, to: &mut Write) -> Result<()> {
+//!         write!(to, "{}", book.chapters.len()).unwrap();
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let mut book = Book::new();
+//! book.add_format("count", "Number of chapters", Box::new(Count {}))
+//!     .set_options(&[("output.count", "book.count")]);
+//! let mut result = vec![];
+//! book.render_format_to("count", &mut result).unwrap();
+//! assert_eq!(result, b"0");
+//! ```
+//!
 //! # Parser
 //!
 //! It is also possible to directly use `Parser` to transform some markdown string or file
@@ -131,6 +164,8 @@ extern crate syntect;
 
 
 pub use parser::Parser;
+pub use rst::RstParser;
+pub use html_import::HtmlImporter;
 pub use book::Book;
 pub use bookoption::BookOption;
 pub use bookoptions::BookOptions;
@@ -141,9 +176,15 @@ pub use number::Number;
 pub use resource_handler::ResourceHandler;
 pub use logger::{Logger, InfoLevel};
 pub use renderer::Renderer;
-pub use book_renderer::BookRenderer;
+pub use book_renderer::{BookRenderer, RenderResult, RenderOutput};
 pub use chapter::Chapter;
 pub use stats::Stats;
+pub use names::NameReport;
+pub use timeline::Timeline;
+pub use nbsp::NbspReport;
+pub use reorder::Reorder;
+pub use split::{Split, SplitChapter};
+pub use batch::render_books;
 
 #[macro_use]
 #[doc(hidden)]
@@ -155,8 +196,17 @@ mod error;
 mod book;
 mod epub;
 mod latex;
+mod cover;
 mod odt;
+mod docbook;
+mod text;
+mod markdown;
+mod ast;
+mod slides;
+mod pdf;
 mod parser;
+mod rst;
+mod html_import;
 mod token;
 mod cleaner;
 mod chapter;
@@ -170,7 +220,37 @@ mod book_renderer;
 mod html_single;
 mod html_if;
 mod syntax;
+mod captions;
+mod diagrams;
+mod page_breaks;
+mod acronyms;
+mod links;
+mod images;
+mod kepub;
+mod epub_size;
+mod opds;
+mod feed;
+mod retail;
+mod publish;
+mod manifest;
+mod calibre;
 mod stats;
+mod git;
+mod sections;
+mod sample;
+mod editions;
+mod exercises;
+mod names;
+mod timeline;
+mod reorder;
+mod split;
+mod batch;
+mod degradation;
+mod dialogue;
+mod nbsp;
+mod quotes;
+mod headings;
+mod minify;
 
 mod zipper;
 mod templates;
@@ -0,0 +1,97 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Validation of the links between chapters and to numbered elements.
+
+use book::Book;
+use captions;
+use sections;
+use error::{Error, Result, Source};
+use resource_handler::ResourceHandler;
+use token::Token;
+
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Checks that all intra-book links can be resolved
+///
+/// A link to another file must point to a chapter of the book (or to an
+/// existing file), and a fragment (e.g. `#hello`) must be the id of a section
+/// or of a numbered listing, figure or table. Broken links are reported as warnings, or make
+/// this function return an error if `strict` is set to true.
+pub fn check_links(book: &Book) -> Result<()> {
+    let ids: HashSet<String> = captions::collect(book, None)
+        .into_iter()
+        .map(|caption| caption.id)
+        .chain(sections::collect(book).into_iter().map(|section| section.id))
+        .collect();
+    let chapters: HashSet<&str> = book.chapters
+        .iter()
+        .map(|c| c.filename.as_str())
+        .filter(|f| !f.is_empty())
+        .collect();
+    let mut broken = 0;
+    for chapter in &book.chapters {
+        let mut links = vec![];
+        collect_links(&chapter.content, &mut links);
+        for url in links {
+            if !is_valid(book, url, &chapters, &ids) {
+                broken += 1;
+                book.logger.warning(lformat!("{source}: broken link to '{url}'",
+                                             source = Source::new(chapter.filename.as_str()),
+                                             url = url));
+            }
+        }
+    }
+    if broken > 0 && book.options.get_bool("strict").unwrap() {
+        Err(Error::default(&book.source,
+                           lformat!("found {n} broken link(s) in the book", n = broken)))
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns the urls of all links in these tokens
+fn collect_links<'a>(tokens: &'a [Token], links: &mut Vec<&'a str>) {
+    for token in tokens {
+        if let Token::Link(ref url, _, _) = *token {
+            links.push(url.as_str());
+        }
+        if let Some(inner) = token.inner() {
+            collect_links(inner, links);
+        }
+    }
+}
+
+/// Checks whether a link is external or points to something in the book
+fn is_valid(book: &Book, url: &str, chapters: &HashSet<&str>, ids: &HashSet<String>) -> bool {
+    if !ResourceHandler::is_local(url) || url.starts_with("mailto:") {
+        return true;
+    }
+    let (file, fragment) = match url.find('#') {
+        Some(pos) => (&url[..pos], &url[pos + 1..]),
+        None => (url, ""),
+    };
+    if !file.is_empty() {
+        let md = format!("{}", Path::new(file).with_extension("md").display());
+        let is_chapter = chapters.contains(file) || chapters.contains(md.as_str());
+        if !is_chapter && (file.ends_with(".md") || !book.root.join(file).exists()) {
+            return false;
+        }
+    }
+    fragment.is_empty() || ids.contains(fragment)
+}
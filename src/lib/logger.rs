@@ -4,6 +4,10 @@ use std::convert::AsRef;
 use std::io;
 use std::io::Write;
 use std::fmt::Display;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::{self, ThreadId};
 
 /// The level of information to display to a logger
 ///
@@ -104,13 +108,44 @@ impl Output {
 #[derive(Debug)]
 pub struct Logger {
     verbosity: InfoLevel,
+    /// Warnings recorded for each thread that called `start_recording`
+    recorded: Mutex<HashMap<ThreadId, Vec<String>>>,
+    /// Number of warnings logged (whatever the verbosity)
+    warnings: AtomicUsize,
 }
 
 
 impl Logger {
     /// Creates a new logger with default verbosity (`Info`).
     pub fn new() -> Logger {
-        Logger { verbosity: InfoLevel::Warning }
+        Logger {
+            verbosity: InfoLevel::Warning,
+            recorded: Mutex::new(HashMap::new()),
+            warnings: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the number of warnings logged so far (whatever the verbosity)
+    pub fn warnings(&self) -> usize {
+        self.warnings.load(Ordering::SeqCst)
+    }
+
+    /// Starts recording the warnings logged by the current thread
+    /// (whatever the verbosity)
+    #[doc(hidden)]
+    pub fn start_recording(&self) {
+        self.recorded.lock().unwrap().insert(thread::current().id(), vec![]);
+    }
+
+    /// Stops recording the warnings logged by the current thread, and
+    /// returns them
+    #[doc(hidden)]
+    pub fn stop_recording(&self) -> Vec<String> {
+        self.recorded
+            .lock()
+            .unwrap()
+            .remove(&thread::current().id())
+            .unwrap_or_default()
     }
 
     /// Get verbosity
@@ -153,6 +188,12 @@ impl Logger {
 
     /// Prints a message if logger's verbosity <= level
     pub fn log<S: AsRef<str>>(&self, level: InfoLevel, s: S) {
+        if level == Warning {
+            self.warnings.fetch_add(1, Ordering::SeqCst);
+            if let Some(warnings) = self.recorded.lock().unwrap().get_mut(&thread::current().id()) {
+                warnings.push(s.as_ref().to_owned());
+            }
+        }
         if level >= self.verbosity {
             match level {
                 Debug => Self::display_debug(s),
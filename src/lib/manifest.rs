@@ -0,0 +1,135 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Manifest of the files generated for a book, with their size and checksum.
+
+use book::Book;
+use book_renderer::{RenderResult, RenderOutput};
+use error::{Error, Result};
+use misc;
+
+use rustc_serialize::json;
+
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+
+#[derive(RustcEncodable, Debug)]
+struct Manifest {
+    title: String,
+    author: String,
+    version: Option<String>,
+    date: String,
+    generator: String,
+    files: Vec<FileEntry>,
+}
+
+#[derive(RustcEncodable, Debug)]
+struct FileEntry {
+    path: String,
+    format: String,
+    size: u64,
+    sha256: String,
+}
+
+/// Writes the manifest of the files written by `results` to `output.manifest`,
+/// if this option is set
+///
+/// Paths of the files are relative to the directory of the manifest. The
+/// manifest is not written if a format failed to render, since its output
+/// file might be missing or left from a previous build.
+pub fn write_manifest(book: &Book, results: &[(String, Result<RenderResult>)]) -> Result<()> {
+    let manifest_path = match book.options.get_path("output.manifest") {
+        Ok(path) => path,
+        Err(_) => return Ok(()),
+    };
+    if results.iter().any(|&(_, ref result)| result.is_err()) {
+        book.logger.warning(lformat!("not writing manifest {file}, since some formats failed \
+                                      to render",
+                                     file = misc::normalize(&manifest_path)));
+        return Ok(());
+    }
+    let base = Path::new(&manifest_path)
+        .parent()
+        .unwrap_or_else(|| Path::new(""))
+        .to_path_buf();
+
+    let mut files = vec![];
+    for &(ref format, ref result) in results {
+        if let Ok(RenderResult { output: RenderOutput::File(ref path), .. }) = *result {
+            add_files(book, format, path, &base, &mut files)?;
+        }
+    }
+
+    let manifest = Manifest {
+        title: book.options.get_str("title").unwrap().to_owned(),
+        author: book.options.get_str("author").unwrap().to_owned(),
+        version: book.options.get_str("version").ok().map(|s| s.to_owned()),
+        date: misc::today(),
+        generator: format!("crowbook {}", env!("CARGO_PKG_VERSION")),
+        files: files,
+    };
+    let content = format!("{}\n", json::as_pretty_json(&manifest));
+    fs::File::create(&manifest_path)
+        .and_then(|mut f| f.write_all(content.as_bytes()))
+        .map_err(|e| {
+            Error::default(&book.source,
+                           lformat!("could not write manifest {file}: {error}",
+                                    file = manifest_path,
+                                    error = e))
+        })?;
+    book.logger.info(lformat!("Succesfully generated manifest: {path}",
+                              path = misc::normalize(&manifest_path)));
+    Ok(())
+}
+
+/// Adds a file (or all the files of a directory) to the list
+fn add_files(book: &Book,
+             format: &str,
+             path: &Path,
+             base: &Path,
+             files: &mut Vec<FileEntry>)
+             -> Result<()> {
+    let io_error = |e: ::std::io::Error| {
+        Error::default(&book.source,
+                       lformat!("could not read {file} to add it to the manifest: {error}",
+                                file = path.display(),
+                                error = e))
+    };
+    if path.is_dir() {
+        let mut entries = fs::read_dir(path)
+            .and_then(|entries| entries.collect::<::std::io::Result<Vec<_>>>())
+            .map_err(&io_error)?;
+        entries.sort_by_key(|entry| entry.path());
+        for entry in entries {
+            add_files(book, format, &entry.path(), base, files)?;
+        }
+    } else if path.is_file() {
+        let mut content = vec![];
+        fs::File::open(path)
+            .and_then(|mut f| f.read_to_end(&mut content))
+            .map_err(&io_error)?;
+        let relative = path.strip_prefix(base).unwrap_or(path);
+        files.push(FileEntry {
+            path: format!("{}", relative.display()),
+            format: format.to_owned(),
+            size: content.len() as u64,
+            sha256: misc::sha256(&content),
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,497 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Rendering of a book to a single, normalized CommonMark file, after
+//! typographic cleaning, e.g. to use it with other Markdown tools.
+
+use token::Token;
+use book::Book;
+use error::{Error, Result, Source};
+use renderer::Renderer;
+use book_renderer::BookRenderer;
+
+use std::io::Write;
+use std::cmp;
+use std::iter;
+
+/// Renderer for Markdown
+///
+/// Chapters are merged in a single file, starting with a YAML block with the
+/// metadata of the book. Crowbook's own extensions (e.g. margin notes, verse
+/// or spoiler blocks) are kept, so the result can be read by Crowbook again.
+pub struct MarkdownRenderer<'a> {
+    book: &'a Book,
+    /// Footnotes and margin notes of the current chapter, with their label
+    notes: Vec<(String, Vec<Token>)>,
+    /// Number of notes in the book so far, since labels must be unique in
+    /// the file
+    note_number: u32,
+    /// Whether text is typographically cleaned
+    clean: bool,
+}
+
+impl<'a> MarkdownRenderer<'a> {
+    /// Creates a new MarkdownRenderer
+    pub fn new(book: &'a Book) -> MarkdownRenderer<'a> {
+        MarkdownRenderer {
+            book: book,
+            notes: vec![],
+            note_number: 0,
+            clean: book.cleans("md"),
+        }
+    }
+
+    /// Renders a full book
+    pub fn render_book(&mut self) -> Result<String> {
+        let mut content = String::from("---\n");
+        for key in &["title", "subtitle", "author", "lang", "date"] {
+            if let Ok(value) = self.book.options.get_str(key) {
+                content.push_str(&format!("{}: \"{}\"\n",
+                                          key,
+                                          value.replace('\\', "\\\\").replace('"', "\\\"")));
+            }
+        }
+        content.push_str("---\n");
+        let book = self.book;
+        for chapter in book.chapters.iter() {
+            let chapter = book.clean_chapter(chapter, self.clean)?;
+            let chapter = self.render_blocks(&chapter.content)?;
+            let notes = self.render_notes()?;
+            if !chapter.is_empty() {
+                content.push('\n');
+                content.push_str(&chapter);
+            }
+            if !notes.is_empty() {
+                content.push('\n');
+                content.push_str(&notes);
+            }
+        }
+        Ok(content)
+    }
+
+    /// Renders block content, separating blocks by an empty line
+    fn render_blocks(&mut self, tokens: &[Token]) -> Result<String> {
+        let mut blocks = vec![];
+        let mut inline = vec![];
+        for token in tokens {
+            if is_inline(token) {
+                inline.push(token.clone());
+                continue;
+            }
+            if !inline.is_empty() {
+                blocks.push(self.render_paragraph(&inline)?);
+                inline.clear();
+            }
+            let block = self.render_block(token)?;
+            if !block.is_empty() {
+                blocks.push(block);
+            }
+        }
+        if !inline.is_empty() {
+            blocks.push(self.render_paragraph(&inline)?);
+        }
+        Ok(blocks.join("\n"))
+    }
+
+    /// Renders a paragraph, escaping characters that would start another
+    /// block at its beginning
+    fn render_paragraph(&mut self, tokens: &[Token]) -> Result<String> {
+        let content = self.render_vec(tokens)?;
+        let content = content.trim();
+        if content.is_empty() {
+            return Ok(String::new());
+        }
+        let escape = content.starts_with(|c| match c {
+            '#' | '>' | '-' | '+' | '=' => true,
+            _ => false,
+        }) || is_list_number(content);
+        Ok(format!("{}{}\n", if escape { "\\" } else { "" }, content))
+    }
+
+    /// Renders a block, with each of its lines ending with a newline
+    fn render_block(&mut self, token: &Token) -> Result<String> {
+        match *token {
+            Token::Paragraph(ref vec) => self.render_paragraph(vec),
+            Token::Header(n, ref vec) => {
+                let title = self.render_vec(vec)?;
+                Ok(format!("{} {}\n", header_marks(n), title.trim()))
+            }
+            Token::CodeBlock(ref info, ref vec) => {
+                let mut code = String::new();
+                for token in vec {
+                    if let Token::Str(ref s) = *token {
+                        code.push_str(s);
+                    }
+                }
+                Ok(fenced(info, &code))
+            }
+            Token::Verse(ref vec) => {
+                // A poem is either a list of stanzas, or a single one
+                let stanzas: Vec<&[Token]> = if vec.iter().all(|t| !is_inline(t)) {
+                    vec.iter().filter_map(|t| t.inner()).collect()
+                } else {
+                    vec![vec.as_slice()]
+                };
+                let mut poem = vec![];
+                for stanza in stanzas {
+                    let mut lines = String::new();
+                    for line in stanza.split(|t| *t == Token::HardBreak || *t == Token::SoftBreak) {
+                        // Indentation is stored as em spaces
+                        let line = self.render_vec(line)?;
+                        let text = line.trim_left_matches('\u{2003}');
+                        let indent = line.chars().count() - text.chars().count();
+                        lines.push_str(&spaces(indent * 2));
+                        lines.push_str(text);
+                        lines.push('\n');
+                    }
+                    poem.push(lines);
+                }
+                Ok(fenced("verse", &poem.join("\n")))
+            }
+            Token::BlockQuote(ref vec) => {
+                let content = self.render_blocks(vec)?;
+                Ok(prefix_lines(&content, "> ", "> "))
+            }
+            Token::Attribution(ref vec) => Ok(format!("— {}\n", self.render_vec(vec)?.trim())),
+            Token::Epigraph(ref author, ref vec) => {
+                let mut content = self.render_blocks(vec)?;
+                if !author.is_empty() {
+                    content.push_str(&format!("\n— {}\n", author));
+                }
+                Ok(prefix_lines(&content, "> ", "> "))
+            }
+            Token::List(ref vec) => self.render_list(vec, None),
+            Token::OrderedList(start, ref vec) => self.render_list(vec, Some(start)),
+            Token::Rule => Ok(String::from("***\n")),
+            Token::PageBreak(ref page) => Ok(format!("<!-- page {} -->\n", page)),
+            Token::NewPage => Ok(String::from("<!-- pagebreak -->\n")),
+            Token::KeepTogether(ref vec) => {
+                let content = self.render_blocks(vec)?;
+                Ok(fenced("keep", &content))
+            }
+            Token::StandaloneImage(..) => {
+                let image = self.render_token(token)?;
+                Ok(format!("{}\n", image))
+            }
+            Token::Table(_, ref vec) => self.render_table(vec),
+            Token::Spoiler(ref summary, ref vec) => {
+                let content = self.render_blocks(vec)?;
+                let info = if summary.is_empty() {
+                    String::from("spoiler")
+                } else {
+                    format!("spoiler {}", summary)
+                };
+                Ok(fenced(&info, &content))
+            }
+            Token::Admonition(ref kind, ref title, ref vec) => {
+                let content = self.render_blocks(vec)?;
+                let info = if title.is_empty() {
+                    kind.clone()
+                } else {
+                    format!("{} {}", kind, title)
+                };
+                Ok(fenced(&info, &content))
+            }
+            Token::Columns(count, ref vec) => {
+                let content = self.render_blocks(vec)?;
+                Ok(fenced(&format!("columns {}", count), &content))
+            }
+            Token::Exercise(ref id, ref vec) => {
+                // The answer is in the exercise, but follows it in the source
+                let (answer, content) = match vec.last() {
+                    Some(&Token::Answer(ref answer)) => (Some(answer), &vec[..vec.len() - 1]),
+                    _ => (None, vec.as_slice()),
+                };
+                let info = if id.is_empty() {
+                    String::from("exercise")
+                } else {
+                    format!("exercise {{id={}}}", id)
+                };
+                let content = self.render_blocks(content)?;
+                let mut res = fenced(&info, &content);
+                if let Some(answer) = answer {
+                    let answer = self.render_blocks(answer)?;
+                    res.push('\n');
+                    res.push_str(&fenced("answer", &answer));
+                }
+                Ok(res)
+            }
+            ref token => {
+                match token.inner() {
+                    Some(inner) => self.render_blocks(inner),
+                    None => Ok(String::new()),
+                }
+            }
+        }
+    }
+
+    /// Renders a list, with the content of each item indented after its
+    /// marker
+    fn render_list(&mut self, items: &[Token], start: Option<usize>) -> Result<String> {
+        // Items whose content is made of paragraphs are separated by an empty line
+        let loose = items.iter()
+            .filter_map(|item| item.inner())
+            .any(|inner| {
+                inner.iter().any(|t| if let Token::Paragraph(_) = *t { true } else { false })
+            });
+        let mut res = vec![];
+        for (i, item) in items.iter().enumerate() {
+            let marker = match start {
+                Some(start) => format!("{}. ", start + i),
+                None => String::from("- "),
+            };
+            let content = match item.inner() {
+                Some(inner) => self.render_blocks(inner)?,
+                None => String::new(),
+            };
+            let content = if content.is_empty() {
+                String::from("\n")
+            } else {
+                content
+            };
+            res.push(prefix_lines(&content, &marker, &spaces(marker.len())));
+        }
+        Ok(res.join(if loose { "\n" } else { "" }))
+    }
+
+    /// Renders a table as a pipe table
+    fn render_table(&mut self, rows: &[Token]) -> Result<String> {
+        let mut res = String::new();
+        let mut caption = String::new();
+        for row in rows {
+            match *row {
+                Token::TableHead(ref cells) |
+                Token::TableRow(ref cells) => {
+                    let mut text = vec![];
+                    for cell in cells {
+                        let cell = match cell.inner() {
+                            Some(inner) => self.render_vec(inner)?,
+                            None => String::new(),
+                        };
+                        text.push(cell.replace('\n', " ").trim().to_owned());
+                    }
+                    res.push_str(&format!("| {} |\n", text.join(" | ")));
+                    if let Token::TableHead(_) = *row {
+                        let separator: Vec<&str> = text.iter().map(|_| "---").collect();
+                        res.push_str(&format!("| {} |\n", separator.join(" | ")));
+                    }
+                }
+                Token::TableCaption(ref vec) => caption = self.render_paragraph(vec)?,
+                _ => (),
+            }
+        }
+        if !caption.is_empty() {
+            res.push('\n');
+            res.push_str(&caption);
+        }
+        Ok(res)
+    }
+
+    /// Renders the definitions of the notes of the current chapter
+    fn render_notes(&mut self) -> Result<String> {
+        let mut res = vec![];
+        // Notes can contain notes, which are added at the end of the list
+        let mut i = 0;
+        while i < self.notes.len() {
+            let (label, note) = self.notes[i].clone();
+            let content = self.render_blocks(&note)?;
+            let content = if content.is_empty() {
+                String::from("\n")
+            } else {
+                content
+            };
+            res.push(prefix_lines(&content, &format!("[^{}]: ", label), "    "));
+            i += 1;
+        }
+        self.notes.clear();
+        Ok(res.join("\n"))
+    }
+}
+
+impl<'a> Renderer for MarkdownRenderer<'a> {
+    /// Renders inline content (blocks are rendered by `render_block`)
+    fn render_token(&mut self, token: &Token) -> Result<String> {
+        match *token {
+            Token::Str(ref text) => Ok(escape(&self.book.clean_if(self.clean, text.as_str(), false))),
+            Token::SoftBreak => Ok(String::from("\n")),
+            Token::HardBreak => Ok(String::from("\\\n")),
+            Token::Emphasis(ref vec) => Ok(format!("*{}*", self.render_vec(vec)?)),
+            Token::Strong(ref vec) => Ok(format!("**{}**", self.render_vec(vec)?)),
+            Token::Superscript(ref vec) => Ok(format!("^{}^", self.render_vec(vec)?)),
+            Token::Subscript(ref vec) => Ok(format!("~{}~", self.render_vec(vec)?)),
+            Token::Code(ref vec) => {
+                let mut code = String::new();
+                for token in vec {
+                    if let Token::Str(ref s) = *token {
+                        code.push_str(s);
+                    }
+                }
+                let ticks: String = iter::repeat('`').take(longest_run(&code, '`') + 1).collect();
+                let padding = if code.starts_with('`') || code.ends_with('`') { " " } else { "" };
+                Ok(format!("{}{}{}{}{}", ticks, padding, code, padding, ticks))
+            }
+            Token::Link(ref url, ref title, ref vec) => {
+                Ok(format!("[{}]({}{})", self.render_vec(vec)?, url, link_title(title)))
+            }
+            Token::Image(ref url, ref title, ref attributes, ref alt) |
+            Token::StandaloneImage(ref url, ref title, ref attributes, ref alt) => {
+                let attributes = if attributes.is_empty() {
+                    String::new()
+                } else {
+                    format!("{{{}}}", attributes)
+                };
+                Ok(format!("![{}]({}{}){}",
+                           self.render_vec(alt)?,
+                           url,
+                           link_title(title),
+                           attributes))
+            }
+            Token::Footnote(ref vec) => {
+                self.note_number += 1;
+                let label = format!("{}", self.note_number);
+                self.notes.push((label.clone(), vec.clone()));
+                Ok(format!("[^{}]", label))
+            }
+            Token::MarginNote(ref vec) => {
+                self.note_number += 1;
+                let label = format!(">{}", self.note_number);
+                self.notes.push((label.clone(), vec.clone()));
+                Ok(format!("[^{}]", label))
+            }
+            ref token => {
+                match token.inner() {
+                    Some(inner) => self.render_vec(inner),
+                    None => Ok(String::new()),
+                }
+            }
+        }
+    }
+}
+
+/// Returns true if a token is displayed inside a line of text
+fn is_inline(token: &Token) -> bool {
+    match *token {
+        Token::Str(_) | Token::Emphasis(_) | Token::Strong(_) | Token::Code(_) |
+        Token::Superscript(_) | Token::Subscript(_) | Token::Abbreviation(..) |
+        Token::Link(..) | Token::Image(..) | Token::Footnote(_) | Token::MarginNote(_) |
+        Token::SoftBreak | Token::HardBreak | Token::Annotation(..) => true,
+        _ => false,
+    }
+}
+
+/// Escapes the characters of a text that have a meaning in Markdown (or in
+/// Crowbook's extensions, such as `^` for superscript)
+fn escape(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '^' | '~' | '|' => res.push('\\'),
+            _ => (),
+        }
+        res.push(c);
+    }
+    res
+}
+
+/// Returns true if a text starts like an item of an ordered list, e.g. `1. `
+fn is_list_number(text: &str) -> bool {
+    let digits = text.chars().take_while(|c| c.is_digit(10)).count();
+    digits > 0 && (text[digits..].starts_with(". ") || text[digits..].starts_with(") "))
+}
+
+/// Returns the `#` marks of a header of level `n`
+fn header_marks(n: i32) -> String {
+    iter::repeat('#').take(if n > 0 { n as usize } else { 1 }).collect()
+}
+
+/// Returns the title part of a link or an image, e.g. ` "Title"`
+fn link_title(title: &str) -> String {
+    if title.is_empty() {
+        String::new()
+    } else {
+        format!(" \"{}\"", title.replace('"', "\\\""))
+    }
+}
+
+/// Returns a fenced code block, with a fence longer than the ones it
+/// contains
+fn fenced(info: &str, content: &str) -> String {
+    let fence: String = iter::repeat('`').take(cmp::max(longest_run(content, '`'), 2) + 1).collect();
+    let mut res = format!("{}{}\n", fence, info);
+    res.push_str(content);
+    if !content.is_empty() && !content.ends_with('\n') {
+        res.push('\n');
+    }
+    res.push_str(&fence);
+    res.push('\n');
+    res
+}
+
+/// Returns the length of the longest run of a character in a text
+fn longest_run(text: &str, c: char) -> usize {
+    let mut longest = 0;
+    let mut current = 0;
+    for d in text.chars() {
+        if d == c {
+            current += 1;
+            longest = cmp::max(longest, current);
+        } else {
+            current = 0;
+        }
+    }
+    longest
+}
+
+/// Starts each line of a text with a prefix (`first` for the first line)
+fn prefix_lines(text: &str, first: &str, next: &str) -> String {
+    let mut res = String::new();
+    for (i, line) in text.lines().enumerate() {
+        let prefix = if i == 0 { first } else { next };
+        if line.is_empty() {
+            res.push_str(prefix.trim_right());
+        } else {
+            res.push_str(prefix);
+            res.push_str(line);
+        }
+        res.push('\n');
+    }
+    res
+}
+
+/// Returns a string of `n` spaces
+fn spaces(n: usize) -> String {
+    iter::repeat(' ').take(n).collect()
+}
+
+/// Markdown
+pub struct Markdown {}
+
+impl BookRenderer for Markdown {
+    fn auto_path(&self, book_name: &str) -> Result<String> {
+        Ok(format!("{}.md", book_name))
+    }
+
+    fn render(&self, book: &Book, to: &mut Write) -> Result<()> {
+        let content = MarkdownRenderer::new(book).render_book()?;
+        to.write_all(content.as_bytes())
+            .map_err(|e| {
+                Error::render(Source::empty(),
+                              lformat!("problem when writing Markdown: {error}", error = e))
+            })?;
+        Ok(())
+    }
+}
@@ -0,0 +1,202 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Minification of the generated HTML and CSS, when `html.minify` is set.
+//!
+//! This is conservative: it only removes what can't change the rendering of
+//! a page (comments, indentation and repeated whitespace).
+
+/// Elements whose content is kept as it is
+static VERBATIM: &'static [&'static str] = &["pre", "textarea", "script"];
+
+/// Minifies a HTML document
+///
+/// Runs of whitespace are collapsed to a single space (or a single newline,
+/// if they contain one), comments are removed, and the content of `<style>`
+/// elements is minified as CSS. The content of `<pre>`, `<textarea>` and
+/// `<script>` elements is left untouched.
+pub fn html(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find('<') {
+        collapse_whitespace(&rest[..i], &mut res);
+        rest = &rest[i..];
+        if rest.starts_with("<!--") {
+            // Comments are removed, but not the ones that contain markup
+            // (e.g. conditional comments)
+            let end = rest.find("-->").map(|i| i + 3).unwrap_or(rest.len());
+            if rest[4..end].contains('<') {
+                res.push_str(&rest[..end]);
+            }
+            rest = &rest[end..];
+            continue;
+        }
+        let end = rest.find('>').map(|i| i + 1).unwrap_or(rest.len());
+        let tag = &rest[..end];
+        res.push_str(tag);
+        rest = &rest[end..];
+
+        let name = tag_name(tag);
+        let closing = format!("</{}", name);
+        if name == "style" {
+            let end = find_ignore_case(rest, &closing).unwrap_or(rest.len());
+            res.push_str(&css(&rest[..end]));
+            rest = &rest[end..];
+        } else if VERBATIM.contains(&name.as_str()) {
+            let end = find_ignore_case(rest, &closing).unwrap_or(rest.len());
+            res.push_str(&rest[..end]);
+            rest = &rest[end..];
+        }
+    }
+    collapse_whitespace(rest, &mut res);
+    res
+}
+
+/// Minifies a CSS stylesheet
+///
+/// Comments are removed, as well as whitespace around braces, semicolons,
+/// commas and after colons, and the last semicolon of a block. Strings
+/// are left untouched.
+pub fn css(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    let mut space = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut prev = ' ';
+                while let Some(c) = chars.next() {
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+                continue;
+            }
+            '"' | '\'' => {
+                if space && needs_space(&res) {
+                    res.push(' ');
+                }
+                space = false;
+                res.push(c);
+                while let Some(d) = chars.next() {
+                    res.push(d);
+                    if d == '\\' {
+                        if let Some(e) = chars.next() {
+                            res.push(e);
+                        }
+                    } else if d == c {
+                        break;
+                    }
+                }
+                continue;
+            }
+            c if is_whitespace(c) => {
+                space = true;
+                continue;
+            }
+            '{' | '}' | ';' | ',' | '>' => {
+                if c == '}' && res.ends_with(';') {
+                    res.pop();
+                }
+                res.push(c);
+            }
+            _ => {
+                if space && needs_space(&res) {
+                    res.push(' ');
+                }
+                res.push(c);
+            }
+        }
+        space = false;
+    }
+    res
+}
+
+/// Returns true if a space must be kept after this (minified) CSS
+fn needs_space(res: &str) -> bool {
+    match res.chars().next_back() {
+        None | Some('{') | Some('}') | Some(';') | Some(',') | Some('>') | Some(':') => false,
+        _ => true,
+    }
+}
+
+/// Returns true if the character is a whitespace that can be collapsed
+/// (unlike non-breaking spaces)
+fn is_whitespace(c: char) -> bool {
+    c == ' ' || c == '\t' || c == '\n' || c == '\r'
+}
+
+/// Appends text to `res`, collapsing whitespace
+fn collapse_whitespace(s: &str, res: &mut String) {
+    let mut whitespace: Option<char> = None;
+    for c in s.chars() {
+        if is_whitespace(c) {
+            if c == '\n' || whitespace.is_none() {
+                whitespace = Some(if c == '\n' { '\n' } else { ' ' });
+            }
+        } else {
+            if let Some(w) = whitespace.take() {
+                push_whitespace(res, w);
+            }
+            res.push(c);
+        }
+    }
+    if let Some(w) = whitespace {
+        push_whitespace(res, w);
+    }
+}
+
+/// Appends whitespace to `res`, merging it with the previous one (e.g. when
+/// a comment has been removed between them)
+fn push_whitespace(res: &mut String, w: char) {
+    match res.chars().next_back() {
+        Some('\n') => {}
+        Some(' ') => {
+            if w == '\n' {
+                res.pop();
+                res.push('\n');
+            }
+        }
+        _ => res.push(w),
+    }
+}
+
+/// Returns the (lowercase) name of an opening tag, e.g. `div` for `<div class = "foo">`
+fn tag_name(tag: &str) -> String {
+    tag[1..]
+        .chars()
+        .take_while(|c| c.is_alphanumeric())
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// Finds an (ASCII, lowercase) pattern in a string, ignoring case
+fn find_ignore_case(s: &str, pattern: &str) -> Option<usize> {
+    let bytes = s.as_bytes();
+    let pattern = pattern.as_bytes();
+    if bytes.len() < pattern.len() {
+        return None;
+    }
+    (0..bytes.len() - pattern.len() + 1).find(|&i| {
+        bytes[i..i + pattern.len()]
+            .iter()
+            .zip(pattern)
+            .all(|(&a, &b)| a == b || (a >= b'A' && a <= b'Z' && a + 32 == b))
+    })
+}
@@ -20,8 +20,10 @@
 use token::Token;
 
 use std;
+use std::iter;
 use std::path::{Path, PathBuf};
 use std::io::Result;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 /// Try to canonicalize a path using std::fs::canonicalize, and returns the
 /// unmodified path if it fails (e.g. if the path doesn't exist (yet))
@@ -54,6 +56,36 @@ fn try_normalize<P: AsRef<Path>>(path: P) -> Result<String> {
     }
 }
 
+/// Returns the current (UTC) date, in YYYY-MM-DD format
+pub fn today() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    // Convert the number of days since epoch to a civil date,
+    // see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = (secs / 86400) as i64 + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Returns the symbol used for the n-th footnote (`*`, `†`, `‡`, `§`, `‖`, `¶`,
+/// then the same symbols doubled, tripled, and so on)
+pub fn footnote_symbol(n: u32) -> String {
+    let symbols = ['*', '†', '‡', '§', '‖', '¶'];
+    let n = if n == 0 { 0 } else { n - 1 };
+    iter::repeat(symbols[(n % 6) as usize])
+        .take((n / 6 + 1) as usize)
+        .collect()
+}
+
 /// Insert a title (if there is none) to a vec of tokens
 pub fn insert_title(tokens: &mut Vec<Token>) {
     for token in tokens.iter() {
@@ -63,3 +95,139 @@ pub fn insert_title(tokens: &mut Vec<Token>) {
     }
     tokens.insert(0, Token::Header(1, vec!()));
 }
+
+/// Splits a vec of tokens into one vec per level-1 header
+///
+/// Tokens before the first header stay with it.
+pub fn split_at_headings(tokens: Vec<Token>) -> Vec<Vec<Token>> {
+    let mut res: Vec<Vec<Token>> = vec![vec![]];
+    let mut has_header = false;
+    for token in tokens {
+        if let Token::Header(1, _) = token {
+            if has_header {
+                res.push(vec![]);
+            }
+            has_header = true;
+        }
+        res.last_mut().unwrap().push(token);
+    }
+    res
+}
+
+/// Returns the summary of a chapter: the blocks before its `<!-- more -->`
+/// marker if it has one (`len` is then the number of these blocks, not
+/// counting headers), or its first paragraph otherwise
+///
+/// Headers, epigraphs and footnotes are not part of the summary.
+pub fn summary(tokens: &[Token], len: Option<usize>) -> Vec<Token> {
+    let blocks = tokens.iter().filter(|t| match **t {
+        Token::Header(..) | Token::Epigraph(..) => false,
+        _ => true,
+    });
+    let mut res: Vec<Token> = match len {
+        Some(len) => blocks.take(len).cloned().collect(),
+        None => {
+            blocks.filter(|t| if let Token::Paragraph(_) = **t { true } else { false })
+                .take(1)
+                .cloned()
+                .collect()
+        }
+    };
+    remove_footnotes(&mut res);
+    res
+}
+
+fn remove_footnotes(tokens: &mut Vec<Token>) {
+    tokens.retain(|t| if let Token::Footnote(_) = *t { false } else { true });
+    for token in tokens {
+        if let Some(inner) = token.inner_mut() {
+            remove_footnotes(inner);
+        }
+    }
+}
+
+/// Round constants of SHA-256
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// Returns the SHA-256 checksum of some data, as an hexadecimal string
+pub fn sha256(data: &[u8]) -> String {
+    let mut h: [u32; 8] = [0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a,
+                           0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19];
+
+    // Pad the message to a multiple of 64 bytes, ending with its length in bits
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    let bits = (data.len() as u64).wrapping_mul(8);
+    for i in 0..8 {
+        message.push((bits >> (56 - i * 8)) as u8);
+    }
+
+    for chunk in message.chunks(64) {
+        let mut w = [0u32; 64];
+        for i in 0..16 {
+            w[i] = (chunk[i * 4] as u32) << 24 | (chunk[i * 4 + 1] as u32) << 16 |
+                   (chunk[i * 4 + 2] as u32) << 8 | chunk[i * 4 + 3] as u32;
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+        }
+
+        let mut v = h;
+        for i in 0..64 {
+            let s1 = v[4].rotate_right(6) ^ v[4].rotate_right(11) ^ v[4].rotate_right(25);
+            let ch = (v[4] & v[5]) ^ (!v[4] & v[6]);
+            let t1 = v[7].wrapping_add(s1).wrapping_add(ch).wrapping_add(SHA256_K[i]).wrapping_add(w[i]);
+            let s0 = v[0].rotate_right(2) ^ v[0].rotate_right(13) ^ v[0].rotate_right(22);
+            let maj = (v[0] & v[1]) ^ (v[0] & v[2]) ^ (v[1] & v[2]);
+            let t2 = s0.wrapping_add(maj);
+            v = [t1.wrapping_add(t2), v[0], v[1], v[2], v[3].wrapping_add(t1), v[4], v[5], v[6]];
+        }
+        for i in 0..8 {
+            h[i] = h[i].wrapping_add(v[i]);
+        }
+    }
+
+    h.iter().map(|x| format!("{:08x}", x)).collect()
+}
+
+/// Returns the name of a file with a hash of its content inserted before
+/// its extension, e.g. `stylesheet.3a7bd3e2.css` for `stylesheet.css`
+pub fn fingerprint(file: &str, content: &[u8]) -> String {
+    let hash = &sha256(content)[..8];
+    match file.rfind('.') {
+        Some(i) if !file[i..].contains('/') => format!("{}.{}{}", &file[..i], hash, &file[i..]),
+        _ => format!("{}.{}", file, hash),
+    }
+}
+
+/// Returns the edit (Levenshtein) distance between two strings
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..b.len() + 1).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            let value = (previous[j] + cost)
+                .min(previous[j + 1] + 1)
+                .min(current[j] + 1);
+            current.push(value);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
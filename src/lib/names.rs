@@ -0,0 +1,179 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Consistency check of the names of characters and places, defined with
+//! `names.*` keys.
+
+use book::Book;
+use token::Token;
+use text_view::view_as_text;
+
+use std::cmp;
+use std::collections::HashSet;
+use std::fmt;
+
+/// A name that doesn't match its canonical form
+struct NameIssue {
+    /// Chapter and paragraph where it was found
+    location: String,
+    /// The name as it was found
+    found: String,
+    /// Its canonical form
+    canonical: String,
+    /// Whether it is a possible misspelling, and not a forbidden variant
+    misspelling: bool,
+}
+
+/// Report of the names of characters and places that are inconsistent with
+/// the canonical names set by `names.*` keys
+///
+/// Forbidden variants listed for each name are always reported, as well as
+/// capitalized words that are close to a canonical name (possible
+/// misspellings).
+pub struct NameReport {
+    issues: Vec<NameIssue>,
+}
+
+impl NameReport {
+    /// Checks the names used in the chapters of the book
+    pub fn new(book: &Book) -> NameReport {
+        let names = book.options.get_names();
+        // Words that are part of a canonical name must not be reported as misspellings
+        let known: HashSet<&str> = names.iter()
+            .flat_map(|&(name, _)| name.split_whitespace())
+            .collect();
+        let mut issues = vec![];
+        for (i, chapter) in book.chapters.iter().enumerate() {
+            let file = if chapter.filename.is_empty() {
+                lformat!("chapter {n}", n = i + 1)
+            } else {
+                chapter.filename.clone()
+            };
+            for (j, token) in chapter.content.iter().enumerate() {
+                if let Token::CodeBlock(..) = *token {
+                    continue;
+                }
+                let text = view_as_text(&chapter.content[j..j + 1]);
+                let location = lformat!("{file}, paragraph {n}", file = file, n = j + 1);
+                for &(name, variants) in &names {
+                    for variant in variants {
+                        for _ in find_word(&text, variant) {
+                            issues.push(NameIssue {
+                                location: location.clone(),
+                                found: variant.clone(),
+                                canonical: name.to_owned(),
+                                misspelling: false,
+                            });
+                        }
+                    }
+                }
+                for word in text.split(|c: char| !c.is_alphanumeric() && c != '-') {
+                    if !word.chars().next().map(|c| c.is_uppercase()).unwrap_or(false) ||
+                       known.contains(word) {
+                        continue;
+                    }
+                    for &(name, variants) in &names {
+                        if is_misspelling(word, name) &&
+                           !variants.iter().any(|variant| variant == word) {
+                            issues.push(NameIssue {
+                                location: location.clone(),
+                                found: word.to_owned(),
+                                canonical: name.to_owned(),
+                                misspelling: true,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        NameReport { issues: issues }
+    }
+
+    /// Returns true if no inconsistent name was found
+    pub fn is_empty(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl fmt::Display for NameReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.issues.is_empty() {
+            return write!(f, "{}\n", lformat!("No inconsistent name found"));
+        }
+        for issue in &self.issues {
+            let msg = if issue.misspelling {
+                lformat!("'{found}' might be a misspelling of '{canonical}'",
+                         found = issue.found,
+                         canonical = issue.canonical)
+            } else {
+                lformat!("'{found}' should be '{canonical}'",
+                         found = issue.found,
+                         canonical = issue.canonical)
+            };
+            write!(f, "{}: {}\n", issue.location, msg)?;
+        }
+        write!(f,
+               "---------\n{}\n",
+               lformat!("{n} inconsistent name(s) found", n = self.issues.len()))
+    }
+}
+
+/// Returns the positions of the occurrences of `word` in `text` that are
+/// not part of a longer word
+fn find_word(text: &str, word: &str) -> Vec<usize> {
+    let mut res = vec![];
+    if word.is_empty() {
+        return res;
+    }
+    for (pos, _) in text.match_indices(word) {
+        let before = text[..pos].chars().next_back();
+        let after = text[pos + word.len()..].chars().next();
+        let boundary = |c: Option<char>| c.map(|c| !c.is_alphanumeric()).unwrap_or(true);
+        if boundary(before) && boundary(after) {
+            res.push(pos);
+        }
+    }
+    res
+}
+
+/// Returns true if `word` is close enough to the (single-word) name to be a
+/// misspelling of it
+fn is_misspelling(word: &str, name: &str) -> bool {
+    let len = name.chars().count();
+    if len < 5 || name.contains(char::is_whitespace) || word == name {
+        return false;
+    }
+    let max = if len < 8 { 1 } else { 2 };
+    distance(word, name) <= max
+}
+
+/// Levenshtein distance between two strings
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..b.len() + 1).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, &cb) in b.iter().enumerate() {
+            let cost = if ca == cb { 0 } else { 1 };
+            let value = cmp::min(cmp::min(previous[j + 1] + 1, current[j] + 1),
+                                 previous[j] + cost);
+            current.push(value);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
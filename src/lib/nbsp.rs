@@ -0,0 +1,150 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Audit of the non-breaking spaces of the source, according to French
+//! typographic rules.
+
+use book::Book;
+use token::Token;
+use text_view::view_as_text;
+
+use std::fmt;
+
+/// Punctuation marks that must be preceded by a non-breaking space
+static BEFORE: &'static [char] = &[':', ';', '!', '?', '»'];
+
+/// Punctuation marks that must be followed by a non-breaking space
+static AFTER: &'static [char] = &['«'];
+
+/// Number of characters displayed before and after an issue
+const CONTEXT: usize = 12;
+
+/// A space that doesn't follow French typographic rules
+struct NbspIssue {
+    /// Chapter and paragraph where it was found
+    location: String,
+    /// Text around the space
+    context: String,
+    /// The punctuation mark next to the space
+    mark: char,
+    /// Whether the space is before the mark, or after it
+    before: bool,
+    /// Whether the space is a regular one where a non-breaking one is
+    /// required, and not the opposite
+    regular: bool,
+}
+
+/// Report of the places where French typographic rules require a
+/// non-breaking space but the source has a regular one, or the opposite
+///
+/// Cleaning (`input.clean`) fixes them when rendering a French book, but
+/// other tools using the source don't, so it might be better to fix it.
+pub struct NbspReport {
+    issues: Vec<NbspIssue>,
+}
+
+impl NbspReport {
+    /// Checks the spaces around punctuation marks in the chapters of the book
+    pub fn new(book: &Book) -> NbspReport {
+        let mut issues = vec![];
+        for (i, chapter) in book.chapters.iter().enumerate() {
+            let file = if chapter.filename.is_empty() {
+                lformat!("chapter {n}", n = i + 1)
+            } else {
+                chapter.filename.clone()
+            };
+            for (j, token) in chapter.content.iter().enumerate() {
+                if let Token::CodeBlock(..) = *token {
+                    continue;
+                }
+                let text: Vec<char> = view_as_text(&chapter.content[j..j + 1]).chars().collect();
+                for k in 0..text.len() {
+                    let c = text[k];
+                    let previous = if k > 0 { Some(text[k - 1]) } else { None };
+                    let next = text.get(k + 1).cloned();
+                    // (space, before the mark, regular space)
+                    let issue = if BEFORE.contains(&c) && previous == Some(' ') {
+                        Some((k - 1, true, true))
+                    } else if AFTER.contains(&c) && next == Some(' ') {
+                        Some((k + 1, false, true))
+                    } else if BEFORE.contains(&c) && next.map(is_nbsp).unwrap_or(false) &&
+                              text.get(k + 2).map(|c| c.is_alphanumeric()).unwrap_or(false) {
+                        Some((k + 1, false, false))
+                    } else if AFTER.contains(&c) && previous.map(is_nbsp).unwrap_or(false) {
+                        Some((k - 1, true, false))
+                    } else {
+                        None
+                    };
+                    if let Some((pos, before, regular)) = issue {
+                        let start = if pos > CONTEXT { pos - CONTEXT } else { 0 };
+                        let end = if pos + CONTEXT < text.len() { pos + CONTEXT } else { text.len() };
+                        issues.push(NbspIssue {
+                            location: lformat!("{file}, paragraph {n}", file = file, n = j + 1),
+                            context: text[start..end].iter().cloned().collect(),
+                            mark: c,
+                            before: before,
+                            regular: regular,
+                        });
+                    }
+                }
+            }
+        }
+        NbspReport { issues: issues }
+    }
+
+    /// Returns true if no misplaced space was found
+    pub fn is_empty(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl fmt::Display for NbspReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.issues.is_empty() {
+            return write!(f, "{}\n", lformat!("No misplaced space found"));
+        }
+        for issue in &self.issues {
+            let msg = match (issue.regular, issue.before) {
+                (true, true) => {
+                    lformat!("regular space before '{mark}', should be non-breaking",
+                             mark = issue.mark)
+                }
+                (true, false) => {
+                    lformat!("regular space after '{mark}', should be non-breaking",
+                             mark = issue.mark)
+                }
+                (false, true) => {
+                    lformat!("non-breaking space before '{mark}', should be regular",
+                             mark = issue.mark)
+                }
+                (false, false) => {
+                    lformat!("non-breaking space after '{mark}', should be regular",
+                             mark = issue.mark)
+                }
+            };
+            write!(f, "{}: {} (\"{}\")\n", issue.location, msg, issue.context.trim())?;
+        }
+        write!(f,
+               "---------\n{}\n",
+               lformat!("{n} misplaced space(s) found", n = self.issues.len()))
+    }
+}
+
+/// Returns true if this character is a non-breaking space (including narrow ones)
+fn is_nbsp(c: char) -> bool {
+    c == '\u{a0}' || c == '\u{202f}'
+}
@@ -6,7 +6,8 @@
 /// considered a breaking change
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Number {
-    /// Chapter's title is hidden
+    /// Chapter is not numbered and its title is hidden (this can be
+    /// changed independently with the chapter's `hide_title` field)
     Hidden,
 
     /// Chapter is not numbered
@@ -55,4 +56,21 @@ impl Number {
             _ => true,
         }
     }
+
+    /// Returns a number of the same kind (chapter or part), that is numbered
+    /// or not according to `numbered`
+    ///
+    /// Self is returned unchanged if it is already numbered (or not) as
+    /// required, so a specified number is kept.
+    pub fn with_numbering(self, numbered: bool) -> Number {
+        if self.is_numbered() == numbered {
+            self
+        } else if numbered {
+            if self.is_part() { Number::DefaultPart } else { Number::Default }
+        } else if self.is_part() {
+            Number::UnnumberedPart
+        } else {
+            Number::Unnumbered
+        }
+    }
 }
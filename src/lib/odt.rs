@@ -1,15 +1,23 @@
 use token::Token;
 use book::{Book, compile_str};
 use number::Number;
-use error::Result;
+use error::{Error, Result};
 use templates::odt;
 use zipper::Zipper;
 use parser::Parser;
 use book_renderer::BookRenderer;
+use resource_handler::ResourceHandler;
+use lang;
+use images::{self, ImageOptions};
+use text_view::view_as_text;
+use sections::Sections;
+use captions::{Captions, CaptionKind};
 
 use crowbook_text_processing::escape;
+use mime_guess::guess_mime_type_opt;
 
-use std::io::Write;
+use std::fs::File;
+use std::io::{Read, Write};
 
 /// Rendererer for ODT
 ///
@@ -19,17 +27,47 @@ pub struct OdtRenderer<'a> {
     current_numbering: i32,
     current_hide: bool,
     current_chapter: i32,
+    current_part: bool,
+    sections: Sections,
+    captions: Captions,
+    paragraph_style: &'static str,
     automatic_styles: String,
+    /// External links listed at the end of the book, if `rendering.links.appendix` is set
+    links: Vec<String>,
+    /// Whether text is typographically cleaned
+    clean: bool,
+    handler: ResourceHandler<'a>,
+    /// Number of image frames, which must have a unique name
+    frames: usize,
+    /// Whether the book has a table of contents
+    inline_toc: bool,
+    /// Whether the current chapter is listed in the table of contents
+    current_toc: bool,
+    /// Level and content of the headings listed in the table of contents
+    toc: Vec<(i32, String)>,
 }
 
 impl<'a> OdtRenderer<'a> {
     /// Creates a new OdtRenderer
     pub fn new(book: &'a Book) -> OdtRenderer {
+        let mut handler = ResourceHandler::new(&book.logger);
+        handler.set_images_mapping(true);
         OdtRenderer {
             book: book,
+            handler: handler,
+            frames: 0,
+            inline_toc: false,
+            current_toc: true,
+            toc: vec![],
             current_chapter: 1,
             current_numbering: book.options.get_i32("rendering.num_depth").unwrap(),
             current_hide: false,
+            current_part: false,
+            sections: Sections::new(book),
+            captions: Captions::new(book),
+            paragraph_style: "Text_20_body",
+            links: vec![],
+            clean: book.cleans("odt"),
             automatic_styles: String::from("
 <style:style style:name=\"T1\" \
                                             style:family=\"text\">
@@ -45,6 +83,145 @@ impl<'a> OdtRenderer<'a> {
                                             fo:font-weight=\"bold\" \
                                             style:font-weight-asian=\"bold\" \
                                             style:font-weight-complex=\"bold\"/>
+</style:style>
+\
+                                            <style:style style:name=\"Verse\" \
+                                            style:family=\"paragraph\" \
+                                            style:parent-style-name=\"Text_20_body\">
+  <style:paragraph-properties \
+                                            fo:margin-left=\"1cm\" \
+                                            fo:text-indent=\"0cm\" \
+                                            fo:text-align=\"start\"/>
+</style:style>
+\
+                                            <style:style style:name=\"Rule\" \
+                                            style:family=\"paragraph\" \
+                                            style:parent-style-name=\"Text_20_body\">
+  <style:paragraph-properties \
+                                            fo:text-indent=\"0cm\" \
+                                            fo:text-align=\"center\"/>
+</style:style>
+\
+                                            <style:style style:name=\"Epigraph\" \
+                                            style:family=\"paragraph\" \
+                                            style:parent-style-name=\"Text_20_body\">
+  <style:paragraph-properties \
+                                            fo:margin-left=\"6cm\" \
+                                            fo:text-indent=\"0cm\" \
+                                            fo:text-align=\"end\"/>
+  <style:text-properties \
+                                            fo:font-style=\"italic\" \
+                                            style:font-style-asian=\"italic\" \
+                                            style:font-style-complex=\"italic\"/>
+</style:style>
+\
+                                            <style:style style:name=\"Epigraph_20_author\" \
+                                            style:display-name=\"Epigraph author\" \
+                                            style:family=\"paragraph\" \
+                                            style:parent-style-name=\"Epigraph\">
+  <style:text-properties \
+                                            fo:font-style=\"normal\" \
+                                            style:font-style-asian=\"normal\" \
+                                            style:font-style-complex=\"normal\"/>
+</style:style>
+\
+                                            <style:style style:name=\"Block_20_quote\" \
+                                            style:display-name=\"Block quote\" \
+                                            style:family=\"paragraph\" \
+                                            style:parent-style-name=\"Text_20_body\">
+  <style:paragraph-properties \
+                                            fo:margin-left=\"1cm\" \
+                                            fo:margin-right=\"1cm\" \
+                                            fo:text-indent=\"0cm\"/>
+  <style:text-properties \
+                                            fo:font-style=\"italic\" \
+                                            style:font-style-asian=\"italic\" \
+                                            style:font-style-complex=\"italic\"/>
+</style:style>
+\
+                                            <style:style style:name=\"Block_20_quote_20_attribution\" \
+                                            style:display-name=\"Block quote attribution\" \
+                                            style:family=\"paragraph\" \
+                                            style:parent-style-name=\"Block_20_quote\">
+  <style:paragraph-properties \
+                                            fo:text-align=\"end\"/>
+  <style:text-properties \
+                                            fo:font-style=\"normal\" \
+                                            style:font-style-asian=\"normal\" \
+                                            style:font-style-complex=\"normal\"/>
+</style:style>
+\
+                                            <style:style style:name=\"Admonition\" \
+                                            style:family=\"paragraph\" \
+                                            style:parent-style-name=\"Text_20_body\">
+  <style:paragraph-properties \
+                                            fo:margin-left=\"0.5cm\" \
+                                            fo:margin-right=\"0.5cm\" \
+                                            fo:text-indent=\"0cm\" \
+                                            fo:padding=\"0.2cm\" \
+                                            fo:border-left=\"0.1cm solid #808080\" \
+                                            fo:background-color=\"#eeeeee\"/>
+</style:style>
+\
+                                            <style:style style:name=\"Admonition_20_title\" \
+                                            style:display-name=\"Admonition title\" \
+                                            style:family=\"paragraph\" \
+                                            style:parent-style-name=\"Admonition\">
+  <style:text-properties \
+                                            fo:font-weight=\"bold\" \
+                                            style:font-weight-asian=\"bold\" \
+                                            style:font-weight-complex=\"bold\"/>
+</style:style>
+\
+                                            <style:style style:name=\"Keep_20_together\" \
+                                            style:display-name=\"Keep together\" \
+                                            style:family=\"paragraph\" \
+                                            style:parent-style-name=\"Text_20_body\">
+  <style:paragraph-properties \
+                                            fo:keep-together=\"always\" \
+                                            fo:keep-with-next=\"always\"/>
+</style:style>
+\
+                                            <style:style style:name=\"Front_20_matter\" \
+                                            style:display-name=\"Front matter\" \
+                                            style:family=\"paragraph\" \
+                                            style:parent-style-name=\"Text_20_body\">
+  <style:paragraph-properties \
+                                            fo:text-indent=\"0cm\" \
+                                            fo:text-align=\"center\"/>
+</style:style>
+\
+                                            <style:style style:name=\"Page_20_break\" \
+                                            style:display-name=\"Page break\" \
+                                            style:family=\"paragraph\" \
+                                            style:parent-style-name=\"Text_20_body\">
+  <style:paragraph-properties \
+                                            fo:break-after=\"page\"/>
+</style:style>
+\
+                                            <style:style style:name=\"Image_20_center\" \
+                                            style:display-name=\"Image center\" \
+                                            style:family=\"paragraph\" \
+                                            style:parent-style-name=\"Text_20_body\">
+  <style:paragraph-properties \
+                                            fo:text-indent=\"0cm\" \
+                                            fo:text-align=\"center\"/>
+</style:style>
+\
+                                            <style:style style:name=\"Image_20_left\" \
+                                            style:display-name=\"Image left\" \
+                                            style:family=\"paragraph\" \
+                                            style:parent-style-name=\"Image_20_center\">
+  <style:paragraph-properties \
+                                            fo:text-align=\"start\"/>
+</style:style>
+\
+                                            <style:style style:name=\"Image_20_right\" \
+                                            style:display-name=\"Image right\" \
+                                            style:family=\"paragraph\" \
+                                            style:parent-style-name=\"Image_20_center\">
+  <style:paragraph-properties \
+                                            fo:text-align=\"end\"/>
 </style:style>"),
         }
     }
@@ -59,11 +236,36 @@ impl<'a> OdtRenderer<'a> {
     /// * An error if there was somel problem during either the rendering to
     ///   ODT format, or the generation of the ODT file itself.
     pub fn render_book(&mut self, to: &mut Write) -> Result<String> {
+        let mut zipper = self.prepare_zipper()?;
+        // Zip and copy
+        zipper.generate_odt(self.book.options.get_str("crowbook.zip.command").unwrap(),
+                            to)
+    }
+
+    /// Renders a full book to PDF, by converting the ODT file with LibreOffice
+    ///
+    /// # Returns
+    /// * `Ok(s)` where `s` contains the output of the LibreOffice command.
+    /// * An error if there was some problem during the rendering to ODT
+    ///   format, or the conversion of the ODT file to PDF.
+    pub fn render_pdf(&mut self, to: &mut Write) -> Result<String> {
+        let mut zipper = self.prepare_zipper()?;
+        let mut odt = vec![];
+        zipper.generate_odt(self.book.options.get_str("crowbook.zip.command").unwrap(),
+                            &mut odt)?;
+        zipper.convert_to_pdf(self.book.options.get_str("pdf.libreoffice.command").unwrap(),
+                              "result.odt",
+                              to)
+    }
+
+    /// Writes the files of the ODT document in a temporary directory
+    fn prepare_zipper(&mut self) -> Result<Zipper<'a>> {
         let content = self.render_content()?;
 
+        let book = self.book;
         let mut zipper =
-            Zipper::new(&self.book.options.get_path("crowbook.temp_dir").unwrap(),
-                        &self.book.logger)?;
+            Zipper::new(&book.options.get_path("crowbook.temp_dir").unwrap(),
+                        &book.logger)?;
 
         // Write template.odt there
         zipper.write("template.odt", odt::ODT, false)?;
@@ -71,23 +273,49 @@ impl<'a> OdtRenderer<'a> {
         zipper.unzip("template.odt")?;
         // Complete it with content.xml
         zipper.write("content.xml", content.as_bytes(), false)?;
-        // Zip and copy
-        zipper.generate_odt(self.book.options.get_str("crowbook.zip.command").unwrap(),
-                            to)
+
+        // Add the images, which must be listed in the manifest
+        let mut entries = String::new();
+        for (source, dest) in self.handler.images_mapping() {
+            let mut content = vec![];
+            File::open(source)
+                .and_then(|mut f| f.read_to_end(&mut content))
+                .map_err(|_| Error::file_not_found(&book.source, lformat!("image"), source.to_owned()))?;
+            zipper.write(dest, &content, false)?;
+            let mime = guess_mime_type_opt(source)
+                .map(|m| m.to_string())
+                .unwrap_or_else(|| String::from("application/octet-stream"));
+            entries.push_str(&format!(" <manifest:file-entry manifest:full-path=\"{}\" manifest:media-type=\"{}\"/>\n",
+                                      dest,
+                                      mime));
+        }
+        zipper.write("META-INF/manifest.xml",
+                     odt::MANIFEST.replace("</manifest:manifest>",
+                                           &format!("{}</manifest:manifest>", entries))
+                         .as_bytes(),
+                     false)?;
+        Ok(zipper)
     }
 
     /// Render content.xml
-    fn render_content(&mut self) -> Result<String> {
+    #[doc(hidden)]
+    pub fn render_content(&mut self) -> Result<String> {
         // Print a warning for the features that aren't supported in ODT.
         let mut missing = vec![];
-        if self.book.features.image { missing.push(lformat!("images")); }
         if self.book.features.blockquote { missing.push(lformat!("blockquotes")); }
         if self.book.features.codeblock { missing.push(lformat!("codeblocks")); }
         if self.book.features.ordered_list { missing.push(lformat!("ordered lists")); }
         if self.book.features.footnote { missing.push(lformat!("footnotes")); }
         if self.book.features.table { missing.push(lformat!("tables")); }
-        if self.book.features.superscript { missing.push(lformat!("superscript")); }
-        if self.book.features.subscript { missing.push(lformat!("subscript")); }
+        if self.book.features.superscript && self.book.options.get_str("odt.styles.superscript").is_err() {
+            missing.push(lformat!("superscript"));
+        }
+        if self.book.features.subscript && self.book.options.get_str("odt.styles.subscript").is_err() {
+            missing.push(lformat!("subscript"));
+        }
+        if self.book.options.get_str("rendering.scene_break") == Ok("ornament") {
+            missing.push(lformat!("ornament images for scene breaks"));
+        }
 
         if !missing.is_empty() {
             let missing = missing.join(", ");
@@ -98,13 +326,20 @@ impl<'a> OdtRenderer<'a> {
 
         
         let mut content = String::new();
+        let toc_location = self.book.inline_toc_location()?;
+        self.inline_toc = toc_location.is_some();
 
-        for chapter in &self.book.chapters {
+        for chapter in self.book.chapters.iter() {
+            let chapter = self.book.clean_chapter(chapter, self.clean)?;
             let n = chapter.number;
             let v = &chapter.content;
-            self.current_hide = false;
+            self.current_hide = chapter.hide_title;
+            self.current_toc = chapter.toc;
+            self.current_part = n.is_part();
             match n {
-                Number::Unnumbered | Number::UnnumberedPart => self.current_numbering = 0,
+                Number::Hidden | Number::Unnumbered | Number::UnnumberedPart => {
+                    self.current_numbering = 0
+                }
                 Number::Default | Number::DefaultPart => {
                     self.current_numbering =
                         self.book.options.get_i32("rendering.num_depth").unwrap()
@@ -113,10 +348,6 @@ impl<'a> OdtRenderer<'a> {
                     self.current_numbering = self.book.options.get_i32("numbering").unwrap();
                     self.current_chapter = n;
                 }
-                Number::Hidden => {
-                    self.current_numbering = 0;
-                    self.current_hide = true;
-                }
            } 
             if n.is_part() {
                 self.book.logger.error(lformat!("Parts are not supported yet in ODT"));
@@ -127,12 +358,35 @@ impl<'a> OdtRenderer<'a> {
             }
         }
 
+        if !self.links.is_empty() {
+            let lang = self.book.options.get_str("lang").unwrap();
+            content.push_str(&format!("<text:h text:style-name=\"Heading_20_1\">\n{}</text:h>\n",
+                                      escape::html(lang::get_str(lang, "links"))));
+            for (i, url) in self.links.iter().enumerate() {
+                content.push_str(&format!("<text:p text:style-name=\"Text_20_body\">[{}] {}</text:p>\n",
+                                          i + 1,
+                                          url));
+            }
+        }
+
+        match toc_location {
+            Some("start") => content = self.render_toc()? + &content,
+            Some(_) => content.push_str(&self.render_toc()?),
+            None => (),
+        }
+
         let template = compile_str(odt::CONTENT,
                                    &self.book.source,
                                    "could not compile template for content.xml")?;
         let data = self.book.get_metadata(|s| Ok(s.to_owned()))?
             .insert_str("content", content)
             .insert_str("automatic_styles", &self.automatic_styles)
+            .insert_bool("half_title",
+                         self.book.options.get_bool("rendering.front_matter.half_title").unwrap())
+            .insert_bool("title_page",
+                         self.book.options.get_bool("rendering.front_matter.title_page").unwrap())
+            .insert_bool("copyright",
+                         self.book.options.get_bool("rendering.front_matter.copyright").unwrap())
             .build();
 
         let mut res: Vec<u8> = vec![];
@@ -143,6 +397,57 @@ impl<'a> OdtRenderer<'a> {
         }
     }
 
+    /// Renders the table of contents
+    ///
+    /// Headings are listed according to their index marks, so chapters with
+    /// `toc: false` are still excluded when LibreOffice updates it, but its
+    /// body is filled so it is displayed even if it isn't updated.
+    fn render_toc(&self) -> Result<String> {
+        let data = self.book.get_metadata(|s| Ok(s.to_owned()))?.build();
+        let template = compile_str(self.book.options.get_str("rendering.inline_toc.name").unwrap(),
+                                   &self.book.source,
+                                   "rendering.inline_toc.name")?;
+        let mut name = vec![];
+        template.render_data(&mut name, &data)?;
+        let name = escape::html(String::from_utf8_lossy(&name).into_owned()).into_owned();
+
+        let depth = self.book.toc_depth();
+        let mut templates = String::new();
+        for level in 1..depth + 1 {
+            templates.push_str(&format!("<text:table-of-content-entry-template text:outline-level=\"{level}\" \
+                                         text:style-name=\"Contents_20_{level}\">
+  <text:index-entry-chapter/>
+  <text:index-entry-text/>
+  <text:index-entry-tab-stop style:type=\"right\" style:leader-char=\".\"/>
+  <text:index-entry-page-number/>
+</text:table-of-content-entry-template>
+",
+                                        level = level));
+        }
+        let mut entries = String::new();
+        for &(level, ref title) in &self.toc {
+            entries.push_str(&format!("<text:p text:style-name=\"Contents_20_{}\">{}</text:p>\n",
+                                      level,
+                                      title));
+        }
+        Ok(format!("<text:table-of-content text:protected=\"true\" text:name=\"{name}\">
+<text:table-of-content-source text:outline-level=\"{depth}\" text:use-outline-level=\"false\" \
+                    text:use-index-marks=\"true\">
+<text:index-title-template text:style-name=\"Contents_20_Heading\">{name}</text:index-title-template>
+{templates}</text:table-of-content-source>
+<text:index-body>
+<text:index-title text:name=\"{name}_Head\">
+<text:p text:style-name=\"Contents_20_Heading\">{name}</text:p>
+</text:index-title>
+{entries}</text:index-body>
+</text:table-of-content>
+",
+                   name = name,
+                   depth = depth,
+                   templates = templates,
+                   entries = entries))
+    }
+
     /// Transform a vector of `Token`s to Odt format
     fn render_vec(&mut self, tokens: &[Token]) -> String {
         let mut res = String::new();
@@ -153,15 +458,70 @@ impl<'a> OdtRenderer<'a> {
         res
     }
 
+    /// Renders an inline element in a span, using the character style set
+    /// by `odt.styles.{key}` if there is one, or `default` otherwise
+    fn render_span(&mut self, key: &str, default: Option<&str>, tokens: &[Token]) -> String {
+        let content = self.render_vec(tokens);
+        let style = match self.book.options.get_str(&format!("odt.styles.{}", key)) {
+            Ok(name) => style_name(name),
+            Err(_) => {
+                match default {
+                    Some(style) => style.to_owned(),
+                    None => return content,
+                }
+            }
+        };
+        format!("<text:span text:style-name=\"{}\">{}</text:span>", style, content)
+    }
+
+    /// Renders an image in a frame, sized according to its attributes, or its
+    /// alternative text if it can't be included
+    fn render_image(&mut self, url: &str, attributes: &str, alt: &[Token]) -> String {
+        let book = self.book;
+        if !ResourceHandler::is_local(url) {
+            book.logger.debug(lformat!("ODT: image '{url}' doesn't seem to be local; ignoring it.",
+                                       url = url));
+            return self.render_vec(alt);
+        }
+        let file = match self.handler.map_image(&book.source, url) {
+            Ok(file) => file.into_owned(),
+            Err(err) => {
+                book.logger.error(lformat!("ODT: {error}", error = err));
+                return self.render_vec(alt);
+            }
+        };
+        let options = ImageOptions::parse(attributes).unwrap_or_default();
+        let (width, height, relative) = options.odt_size(images::image_size(url));
+        self.frames += 1;
+        format!("<draw:frame draw:style-name=\"Graphics\" draw:name=\"Image{n}\" \
+                 text:anchor-type=\"as-char\" svg:width=\"{width:.3}cm\" svg:height=\"{height:.3}cm\"{relative}>\
+                 <draw:image xlink:href=\"{file}\" xlink:type=\"simple\" xlink:show=\"embed\" xlink:actuate=\"onLoad\"/>\
+                 <svg:desc>{alt}</svg:desc></draw:frame>",
+                n = self.frames,
+                width = width,
+                height = height,
+                relative = match relative {
+                    Some(relative) => format!(" style:rel-width=\"{}\" style:rel-height=\"scale\"", relative),
+                    None => String::new(),
+                },
+                file = escape::html(file),
+                alt = escape::html(view_as_text(alt)))
+    }
+
     fn parse_token(&mut self, token: &Token) -> String {
         match *token {
-            Token::Str(ref text) => escape::html(self.book.clean(text.as_str(), false)).into_owned(),
+            Token::Str(ref text) => escape::html(self.book.clean_if(self.clean, text.as_str(), false)).into_owned(),
             Token::Paragraph(ref vec) => {
-                format!("<text:p text:style-name=\"Text_20_body\">{}</text:p>\n",
+                format!("<text:p text:style-name=\"{}\">{}</text:p>\n",
+                        self.paragraph_style,
                         self.render_vec(vec))
             }
             Token::Header(n, ref vec) => {
                 if n == 1 && self.current_hide {
+                    // A hidden title still counts for chapter numbering
+                    if self.current_numbering >= 1 {
+                        self.current_chapter += 1;
+                    }
                     return String::new();
                 }
                 let s = if n == 1 && self.current_numbering >= 1 {
@@ -172,20 +532,38 @@ impl<'a> OdtRenderer<'a> {
                     });
                     res.unwrap().text
                 } else {
-                    self.render_vec(vec)
+                    let title = self.render_vec(vec);
+                    let level = if self.current_part { n - 1 } else { n };
+                    let section = if level >= 2 {
+                        self.sections.next()
+                    } else {
+                        None
+                    };
+                    match section {
+                        Some(ref section) if !section.number.is_empty() => {
+                            format!("{}. {}", section.number, title)
+                        }
+                        _ => title,
+                    }
+                };
+                // Index marks list this heading in the table of contents
+                let s = if self.inline_toc && self.current_toc && n <= self.book.toc_depth() {
+                    let id = self.toc.len();
+                    self.toc.push((n, s.clone()));
+                    format!("<text:toc-mark-start text:id=\"toc{id}\" text:outline-level=\"{n}\"/>{s}\
+                             <text:toc-mark-end text:id=\"toc{id}\"/>",
+                            id = id,
+                            n = n,
+                            s = s)
+                } else {
+                    s
                 };
                 format!("<text:h text:style-name=\"Heading_20_{}\">\n{}</text:h>\n",
                         n,
                         s)
             }
-            Token::Emphasis(ref vec) => {
-                format!("<text:span text:style-name=\"T1\">{}</text:span>",
-                        self.render_vec(vec))
-            }
-            Token::Strong(ref vec) => {
-                format!("<text:span text:style-name=\"T2\">{}</text:span>",
-                        self.render_vec(vec))
-            }
+            Token::Emphasis(ref vec) => self.render_span("emphasis", Some("T1"), vec),
+            Token::Strong(ref vec) => self.render_span("strong", Some("T2"), vec),
             Token::List(ref vec) => format!("<text:list>\n{}</text:list>\n", self.render_vec(vec)),
             Token::OrderedList(_, ref vec) => {
                 format!("<text:list>\n{}</text:list>\n", self.render_vec(vec))
@@ -195,33 +573,173 @@ impl<'a> OdtRenderer<'a> {
                         self.render_vec(vec))
             }
             Token::Link(ref url, _, ref vec) => {
-                format!("<text:a xlink:type=\"simple\"  xlink:href=\"{}\">{}</text:a>",
-                        url,
-                        self.render_vec(vec))
+                let mut link = format!("<text:a xlink:type=\"simple\"  xlink:href=\"{}\">{}</text:a>",
+                                       url,
+                                       self.render_vec(vec));
+                if !ResourceHandler::is_local(url) &&
+                    self.book.options.get_bool("rendering.links.appendix").unwrap() {
+                    // Only number each link once
+                    let url = escape::html(url.as_str()).into_owned();
+                    let n = match self.links.iter().position(|l| *l == url) {
+                        Some(i) => i + 1,
+                        None => {
+                            self.links.push(url);
+                            self.links.len()
+                        }
+                    };
+                    link.push_str(&format!(" [{}]", n));
+                }
+                link
+            }
+            Token::Code(ref vec) => self.render_span("code", Some("Preformatted_20_Text"), vec),
+            Token::Superscript(ref vec) => self.render_span("superscript", None, vec),
+            Token::Subscript(ref vec) => self.render_span("subscript", None, vec),
+            Token::Abbreviation(_, ref vec) => self.render_span("abbreviation", None, vec),
+            Token::BlockQuote(ref vec) => {
+                let style = self.paragraph_style;
+                self.paragraph_style = "Block_20_quote";
+                let content = self.render_vec(vec);
+                self.paragraph_style = style;
+                content
             }
-            Token::Code(ref vec) => {
-                format!("<text:span text:style-name=\"Preformatted_20_Text\">{}</text:span>",
+            Token::Attribution(ref vec) => {
+                format!("<text:p text:style-name=\"Block_20_quote_20_attribution\">— {}</text:p>\n",
                         self.render_vec(vec))
             }
-            Token::Subscript(ref vec) | Token::Superscript(ref vec) => self.render_vec(vec),
-            Token::BlockQuote(ref vec) |
             Token::CodeBlock(_, ref vec) => {
                 format!("<text:p text:style-name=\"Text_20_Body\">{}</text:p>\n",
                         self.render_vec(vec))
             }
+            Token::Verse(ref vec) => {
+                self.paragraph_style = "Verse";
+                let content = self.render_vec(vec);
+                self.paragraph_style = "Text_20_body";
+                content
+            }
+            Token::Spoiler(ref summary, ref vec) => {
+                // ODT files can't hide content, so the summary is displayed
+                // before it
+                let summary = if summary.is_empty() {
+                    escape::html(lang::get_str(self.book.options.get_str("lang").unwrap(),
+                                               "solution"))
+                        .into_owned()
+                } else {
+                    match Parser::new().parse_inline(summary) {
+                        Ok(tokens) => self.render_vec(&tokens),
+                        Err(_) => escape::html(summary.as_str()).into_owned(),
+                    }
+                };
+                format!("<text:p text:style-name=\"Text_20_body\"><text:span \
+                         text:style-name=\"T1\">{}</text:span></text:p>\n{}",
+                        summary,
+                        self.render_vec(vec))
+            }
+            Token::Admonition(ref kind, ref title, ref vec) => {
+                let title = if title.is_empty() {
+                    escape::html(lang::get_str(self.book.options.get_str("lang").unwrap(), kind))
+                        .into_owned()
+                } else {
+                    match Parser::new().parse_inline(title) {
+                        Ok(tokens) => self.render_vec(&tokens),
+                        Err(_) => escape::html(title.as_str()).into_owned(),
+                    }
+                };
+                let style = self.paragraph_style;
+                self.paragraph_style = "Admonition";
+                let content = self.render_vec(vec);
+                self.paragraph_style = style;
+                format!("<text:p text:style-name=\"Admonition_20_title\">{}</text:p>\n{}",
+                        title,
+                        content)
+            }
+            // Columns are ignored, their content is displayed in the text's column
+            Token::Columns(_, ref vec) => self.render_vec(vec),
+            Token::Exercise(_, ref vec) => {
+                let label = match self.captions.next(CaptionKind::Exercise) {
+                    Some(exercise) => {
+                        exercise.label(self.book.options.get_str("lang").unwrap())
+                    }
+                    None => lang::get_str(self.book.options.get_str("lang").unwrap(), "exercise"),
+                };
+                format!("<text:p text:style-name=\"Text_20_body\"><text:span \
+                         text:style-name=\"T2\">{}</text:span></text:p>\n{}",
+                        escape::html(label),
+                        self.render_vec(vec))
+            }
+            Token::Answer(ref vec) => self.render_vec(vec),
+            Token::Epigraph(ref author, ref vec) => {
+                self.paragraph_style = "Epigraph";
+                let mut content = self.render_vec(vec);
+                self.paragraph_style = "Text_20_body";
+                if !author.is_empty() {
+                    let author = match Parser::new().parse_inline(author) {
+                        Ok(tokens) => self.render_vec(&tokens),
+                        Err(_) => escape::html(author.as_str()).into_owned(),
+                    };
+                    content.push_str(&format!("<text:p text:style-name=\"Epigraph_20_author\">— {}</text:p>\n",
+                                              author));
+                }
+                content
+            }
+            Token::HardBreak if self.paragraph_style == "Verse" => String::from("<text:line-break/>"),
             Token::SoftBreak | Token::HardBreak => String::from(" "),
-            Token::Rule => String::from("<text:p /><text:p>***</text:p><text:p />"),
-            Token::Image(_, _, _) |
-            Token::StandaloneImage(_, _, _) => {
-                String::from(" ")
+            Token::PageBreak(_) => String::new(),
+            Token::NewPage => String::from("<text:p text:style-name=\"Page_20_break\"/>\n"),
+            Token::KeepTogether(ref vec) => {
+                let style = self.paragraph_style;
+                self.paragraph_style = "Keep_20_together";
+                let content = self.render_vec(vec);
+                self.paragraph_style = style;
+                content
+            }
+            Token::Rule => {
+                match self.book.options.get_str("rendering.scene_break").unwrap() {
+                    "asterism" => {
+                        String::from("<text:p /><text:p text:style-name=\"Rule\">⁂</text:p><text:p />")
+                    }
+                    "blank" => String::from("<text:p /><text:p />"),
+                    _ => String::from("<text:p /><text:p text:style-name=\"Rule\">***</text:p><text:p />"),
+                }
+            }
+            Token::Image(ref url, ref title, ref attributes, ref alt) |
+            Token::StandaloneImage(ref url, ref title, ref attributes, ref alt) => {
+                if let Some(kind) = images::media_kind(url) {
+                    // Audio or video: only display their description
+                    let lang = self.book.options.get_str("lang").unwrap();
+                    let text = format!("[{}: {}]",
+                                       escape::html(lang::get_str(lang, kind)),
+                                       self.render_vec(alt));
+                    if token.is_image() {
+                        text
+                    } else {
+                        format!("<text:p text:style-name=\"{}\">{}</text:p>\n",
+                                self.paragraph_style,
+                                text)
+                    }
+                } else if token.is_image() {
+                    self.render_image(url, attributes, alt)
+                } else {
+                    let options = ImageOptions::parse(attributes).unwrap_or_default();
+                    let mut content = format!("<text:p text:style-name=\"Image_20_{}\">{}</text:p>\n",
+                                              options.align.as_ref().map(|s| s.as_str()).unwrap_or("center"),
+                                              self.render_image(url, attributes, alt));
+                    let figure = self.captions.next_figure(title, &options);
+                    if let Some(figure) = figure {
+                        content.push_str(&format!("<text:p text:style-name=\"Caption\">{}: {}</text:p>\n",
+                                                  escape::html(figure.label(self.book.options.get_str("lang").unwrap())),
+                                                  escape::html(title.as_str())));
+                    }
+                    content
+                }
             }
             Token::Table(_, _) |
+            Token::TableCaption(_) |
             Token::TableHead(_) |
             Token::TableRow(_) |
             Token::TableCell(_) => {
                 String::from(" ")
             }
-            Token::Footnote(_) => {
+            Token::Footnote(_) | Token::MarginNote(_) => {
                 String::new()
             }
             Token::Annotation(_, ref vec) => self.render_vec(vec),
@@ -230,6 +748,11 @@ impl<'a> OdtRenderer<'a> {
     }
 }
 
+/// Converts the display name of a style (e.g. "Source Text") to the name
+/// used in ODT files (e.g. "Source_20_Text")
+fn style_name(name: &str) -> String {
+    escape::html(name.replace(' ', "_20_")).into_owned()
+}
 
 pub struct Odt {}
 
@@ -0,0 +1,173 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Generation of an OPDS catalog for the output files of a book, so authors
+//! hosting their books can serve them to reading apps.
+
+use book::Book;
+use error::Result;
+use misc;
+
+use crowbook_text_processing::escape;
+use mime_guess::guess_mime_type_opt;
+
+use std::fmt::Write;
+use std::path::Path;
+
+/// Mime types of ebook formats that mime_guess doesn't know
+const EBOOK_TYPES: &'static [(&'static str, &'static str)] =
+    &[("epub", "application/epub+zip"),
+      ("mobi", "application/x-mobipocket-ebook"),
+      ("azw3", "application/vnd.amazon.ebook")];
+
+/// Returns an OPDS acquisition feed containing the book, with links to the
+/// files it is rendered to
+///
+/// Links are relative to `publish.base_url`, if it is set.
+pub fn catalog(book: &Book) -> Result<String> {
+    let title = escape::html(book.options.get_str("title").unwrap());
+    let author = escape::html(book.options.get_str("author").unwrap());
+    let lang = book.options.get_str("lang").unwrap();
+    let base_url = book.options.get_str("publish.base_url").unwrap_or("");
+    let updated = format!("{}T00:00:00Z", misc::today());
+    let id = book_id(book);
+
+    let mut entry = String::new();
+    write!(entry, "    <title>{}</title>
+    <id>{}</id>
+    <updated>{}</updated>
+    <author><name>{}</name></author>
+    <dc:language>{}</dc:language>\n",
+           title,
+           id,
+           updated,
+           author,
+           lang)?;
+    if let Ok(description) = book.options.get_str("description") {
+        write!(entry, "    <summary>{}</summary>\n", escape::html(description))?;
+    }
+    if let Ok(subject) = book.options.get_str("subject") {
+        write!(entry, "    <category term = \"{}\" />\n", escape::html(subject))?;
+    }
+    if let Ok(cover) = book.options.get_path("cover") {
+        let mime = guess_mime_type_opt(&cover)
+            .map(|m| m.to_string())
+            .unwrap_or_else(|| String::from("image/png"));
+        write!(entry, "    <link rel = \"http://opds-spec.org/image\" href = \"{}\" type = \"{}\" />\n",
+               url(base_url, &cover),
+               mime)?;
+    }
+    for format in book.formats() {
+        // Proofreading outputs and the print cover aren't editions of the book
+        if format.starts_with("proofread.") || format == "pdf.cover" {
+            continue;
+        }
+        if let Some(path) = book.output_path(format)? {
+            if let Some(mime) = mime_type(&path) {
+                write!(entry, "    <link rel = \"http://opds-spec.org/acquisition/open-access\" href = \"{}\" type = \"{}\" />\n",
+                       url(base_url, &path),
+                       mime)?;
+            }
+        }
+    }
+
+    Ok(format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<feed xmlns=\"http://www.w3.org/2005/Atom\"
+      xmlns:dc=\"http://purl.org/dc/terms/\"
+      xmlns:opds=\"http://opds-spec.org/2010/catalog\">
+  <id>{id}:catalog</id>
+  <title>{title}</title>
+  <updated>{updated}</updated>
+  <author><name>{author}</name></author>
+  <entry>
+{entry}  </entry>
+</feed>
+",
+               id = id,
+               title = title,
+               updated = updated,
+               author = author,
+               entry = entry))
+}
+
+/// Returns an id of the book, escaped for XML
+///
+/// This is `identifier` if it is set (as a URN if it is a UUID), else the
+/// ISBN set by `retail.isbn`, else a hash of the title and author of the
+/// book, which doesn't change from a build or a version of Crowbook to
+/// another.
+pub fn book_id(book: &Book) -> String {
+    let id = if let Ok(id) = book.options.get_str("identifier") {
+        let id = id.trim();
+        if is_uuid(id) {
+            format!("urn:uuid:{}", id.to_lowercase())
+        } else if id.contains(':') {
+            id.to_owned()
+        } else {
+            format!("urn:crowbook:{}", id)
+        }
+    } else if let Ok(isbn) = book.options.get_str("retail.isbn") {
+        format!("urn:isbn:{}", isbn.replace('-', ""))
+    } else {
+        // FNV-1a, since the algorithm of std's DefaultHasher is unspecified
+        let title = book.options.get_str("title").unwrap();
+        let author = book.options.get_str("author").unwrap();
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for b in title.bytes().chain(Some(0)).chain(author.bytes()) {
+            hash ^= b as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        format!("urn:crowbook:{:016x}", hash)
+    };
+    escape::html(id).into_owned()
+}
+
+/// Returns true if `s` is a UUID, e.g. `f81d4fae-7dec-11d0-a765-00a0c91e6bf6`
+fn is_uuid(s: &str) -> bool {
+    s.len() == 36 &&
+        s.char_indices().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_digit(16),
+        })
+}
+
+/// Returns the mime type of an output file, or `None` for directories
+/// and unknown formats
+fn mime_type(path: &Path) -> Option<String> {
+    let extension = match path.extension() {
+        Some(extension) => extension.to_string_lossy().to_lowercase(),
+        None => return None,
+    };
+    EBOOK_TYPES.iter()
+        .find(|&&(ext, _)| ext == extension)
+        .map(|&(_, mime)| mime.to_owned())
+        .or_else(|| guess_mime_type_opt(path).map(|m| m.to_string()))
+}
+
+/// Returns the URL of a published file
+pub fn url<P: AsRef<Path>>(base_url: &str, path: P) -> String {
+    let file = path.as_ref()
+        .file_name()
+        .map(|f| f.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let url = if base_url.is_empty() || base_url.ends_with('/') {
+        format!("{}{}", base_url, file)
+    } else {
+        format!("{}/{}", base_url, file)
+    };
+    escape::html(url).into_owned()
+}
@@ -0,0 +1,239 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Import of the page breaks of the print edition from its PDF file, so
+//! they don't have to be marked by hand.
+
+use book::Book;
+use chapter::Chapter;
+use error::{Error, Result, Source};
+use token::Token;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::process::Command;
+
+/// Number of words that must match at the start of a page
+const KEY_LEN: usize = 3;
+
+/// Number of words that can be skipped at the start of a page, for running
+/// heads and page numbers
+const MAX_SKIP: usize = 6;
+
+/// A word of the book, with the position of the `Str` token it is in
+struct Word {
+    chapter: usize,
+    path: Vec<usize>,
+    offset: usize,
+    text: String,
+}
+
+/// Adds page breaks to the chapters of the book, using the text of the PDF
+/// file set by `input.page_breaks.pdf`
+///
+/// Does nothing if this option is not set.
+pub fn import_page_breaks(book: &mut Book) -> Result<()> {
+    let pdf = match book.options.get_path("input.page_breaks.pdf") {
+        Ok(pdf) => pdf,
+        Err(_) => return Ok(()),
+    };
+    let text = pdf_text(book, &pdf)?;
+    let first = book.options.get_i32("input.page_breaks.first").unwrap();
+    let missing = add_page_breaks(&mut book.chapters, &text, first);
+    if !missing.is_empty() {
+        book.logger.warning(lformat!("could not find the start of {n} page(s) of {file} in the book: {pages}",
+                                     n = missing.len(),
+                                     file = pdf,
+                                     pages = missing.join(", ")));
+    }
+    Ok(())
+}
+
+/// Adds a page break at the start of each page of `text`, as extracted by
+/// `pdftotext` (pages are separated by form feeds), and returns the
+/// numbers of the pages that couldn't be found in the chapters
+///
+/// `first` is the number of the first page in the print edition.
+pub fn add_page_breaks(chapters: &mut [Chapter], text: &str, first: i32) -> Vec<String> {
+    let mut words = vec![];
+    for (i, chapter) in chapters.iter().enumerate() {
+        collect_words(&chapter.content, i, &mut vec![], &mut words);
+    }
+    let mut index: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, word) in words.iter().enumerate() {
+        index.entry(word.text.as_str()).or_insert_with(Vec::new).push(i);
+    }
+
+    let mut breaks = vec![];
+    let mut missing = vec![];
+    let mut pos = 0;
+    for (i, page) in text.split('\x0c').enumerate() {
+        let page_words: Vec<_> = page.split_whitespace()
+            .map(normalize)
+            .filter(|w| !w.is_empty())
+            .collect();
+        if page_words.is_empty() {
+            // Blank page
+            continue;
+        }
+        let number = format!("{}", first + i as i32);
+        let found = (0..MAX_SKIP)
+            .filter(|&skip| skip + KEY_LEN <= page_words.len())
+            .filter_map(|skip| find(&words, &index, &page_words[skip..skip + KEY_LEN], pos))
+            .min();
+        match found {
+            Some(n) => {
+                breaks.push((n, number));
+                pos = n + 1;
+            }
+            None => missing.push(number),
+        }
+    }
+
+    // Insert page breaks from the end, so the positions of the other words
+    // stay valid
+    for (n, number) in breaks.into_iter().rev() {
+        let word = &words[n];
+        insert(&mut chapters[word.chapter].content, &word.path, word.offset, number);
+    }
+    missing
+}
+
+/// Runs the command set by `input.page_breaks.command` to extract the text of a PDF file
+fn pdf_text(book: &Book, pdf: &str) -> Result<String> {
+    let source = Source::new(pdf);
+    let output = Path::new(&book.options.get_path("crowbook.temp_dir").unwrap())
+        .join("crowbook-page-breaks.txt");
+    let output_str = format!("{}", output.display());
+    let command = book.options.get_str("input.page_breaks.command").unwrap();
+    let mut args = command.split_whitespace()
+        .map(|arg| {
+            arg.replace("{input}", pdf)
+                .replace("{output}", &output_str)
+        });
+    let program = match args.next() {
+        Some(program) => program,
+        None => return Err(Error::default(&source, lformat!("input.page_breaks.command is empty"))),
+    };
+    let result = Command::new(&program)
+        .args(args)
+        .output()
+        .map_err(|e| {
+            Error::default(&source,
+                           lformat!("failed to run command '{command}' to read page breaks: {error}",
+                                    command = program,
+                                    error = e))
+        })?;
+    if !result.status.success() {
+        return Err(Error::default(&source,
+                                  lformat!("command '{command}' failed to read page breaks:\n{error}",
+                                           command = command,
+                                           error = String::from_utf8_lossy(&result.stderr))));
+    }
+    let mut text = String::new();
+    File::open(&output)
+        .and_then(|mut f| f.read_to_string(&mut text))
+        .map_err(|e| {
+            Error::default(&source,
+                           lformat!("could not read text extracted from PDF: {error}",
+                                    error = e))
+        })?;
+    Ok(text)
+}
+
+/// Lowercases a word and keeps only its alphanumeric characters, so
+/// punctuation and typographic cleaning don't matter
+fn normalize(word: &str) -> String {
+    word.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+fn collect_words(tokens: &[Token], chapter: usize, path: &mut Vec<usize>, words: &mut Vec<Word>) {
+    for (i, token) in tokens.iter().enumerate() {
+        path.push(i);
+        if let Token::Str(ref s) = *token {
+            let mut start = None;
+            for (j, c) in s.char_indices().chain(Some((s.len(), ' '))) {
+                match (start, c.is_whitespace()) {
+                    (None, false) => start = Some(j),
+                    (Some(k), true) => {
+                        let text = normalize(&s[k..j]);
+                        if !text.is_empty() {
+                            words.push(Word {
+                                chapter: chapter,
+                                path: path.clone(),
+                                offset: k,
+                                text: text,
+                            });
+                        }
+                        start = None;
+                    }
+                    _ => (),
+                }
+            }
+        } else if let Some(inner) = token.inner() {
+            collect_words(inner, chapter, path, words);
+        }
+        path.pop();
+    }
+}
+
+/// Returns the position of the first occurrence of `key` in the words of
+/// the book, starting at `pos`
+fn find(words: &[Word], index: &HashMap<&str, Vec<usize>>, key: &[String], pos: usize) -> Option<usize> {
+    let positions = match index.get(key[0].as_str()) {
+        Some(positions) => positions,
+        None => return None,
+    };
+    let start = match positions.binary_search(&pos) {
+        Ok(i) | Err(i) => i,
+    };
+    positions[start..]
+        .iter()
+        .cloned()
+        .find(|&p| {
+            p + key.len() <= words.len() &&
+                key.iter().enumerate().all(|(k, w)| words[p + k].text == *w)
+        })
+}
+
+/// Inserts a page break before the byte `offset` of the `Str` token at `path`
+fn insert(tokens: &mut Vec<Token>, path: &[usize], offset: usize, number: String) {
+    let i = path[0];
+    if path.len() > 1 {
+        let inner = tokens[i].inner_mut().unwrap();
+        return insert(inner, &path[1..], offset, number);
+    }
+    if offset == 0 {
+        tokens.insert(i, Token::PageBreak(number));
+        return;
+    }
+    let rest = match tokens[i] {
+        Token::Str(ref mut s) => {
+            let rest = s[offset..].to_owned();
+            s.truncate(offset);
+            rest
+        }
+        _ => unreachable!(),
+    };
+    tokens.insert(i + 1, Token::Str(rest));
+    tokens.insert(i + 1, Token::PageBreak(number));
+}
@@ -19,8 +19,11 @@ use token::Token;
 use error::{Result, Error, Source};
 use logger::Logger;
 use book::Book;
+use syntax::{CodeOptions, strip_language};
+use images::ImageOptions;
 
 use std::mem;
+use std::iter;
 use std::fs::File;
 use std::path::Path;
 use std::convert::AsRef;
@@ -30,6 +33,9 @@ use std::ops::BitOr;
 
 use cmark::{Parser as CMParser, Event, Tag, Options, OPTION_ENABLE_FOOTNOTES, OPTION_ENABLE_TABLES};
 
+/// Kinds of admonitions (callout blocks)
+static ADMONITIONS: &'static [&'static str] = &["note", "tip", "important", "warning", "caution"];
+
 
 
 
@@ -45,6 +51,8 @@ pub struct Features {
     pub url: bool,
     pub subscript: bool,
     pub superscript: bool,
+    pub admonition: bool,
+    pub columns: bool,
 }
 
 impl Features {
@@ -60,6 +68,8 @@ impl Features {
             url: false,
             subscript: false,
             superscript: false,
+            admonition: false,
+            columns: false,
         }
     }
 }
@@ -79,6 +89,8 @@ impl BitOr for Features {
             url: self.url | rhs.url,
             subscript: self.subscript | rhs.subscript,
             superscript: self.superscript | rhs.superscript,
+            admonition: self.admonition | rhs.admonition,
+            columns: self.columns | rhs.columns,
         }
     }
 }
@@ -116,6 +128,12 @@ pub struct Parser {
 
     html_as_text: bool,
     superscript: bool,
+
+    /// Number of blocks before a `<!-- more -->` marker
+    more: Option<usize>,
+
+    /// Warnings emitted while parsing
+    warnings: Vec<String>,
 }
 
 impl Parser {
@@ -127,6 +145,8 @@ impl Parser {
             features: Features::new(),
             html_as_text: true,
             superscript: false,
+            more: None,
+            warnings: vec![],
         }
     }
 
@@ -177,14 +197,25 @@ impl Parser {
 
 
         let mut res = vec![];
+        self.more = None;
         self.parse_events(&mut p, &mut res, None)?;
 
         self.parse_footnotes(&mut res)?;
 
+        self.attach_answers(&mut res)?;
+
         collapse(&mut res);
 
+        self.find_image_attributes(&mut res)?;
+
         find_standalone(&mut res);
 
+        find_table_captions(&mut res);
+
+        self.find_admonitions(&mut res);
+
+        find_attributions(&mut res);
+
         // Transform superscript and subscript
         if self.superscript {
             self.parse_super_vec(&mut res);
@@ -220,11 +251,86 @@ impl Parser {
         self.features
     }
 
+    /// Returns the warnings emitted while parsing since the last call
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        mem::replace(&mut self.warnings, vec![])
+    }
+
+    /// Returns the number of blocks (not counting headers) before the
+    /// `<!-- more -->` marker of the last parsed document, if it has one
+    pub fn summary_len(&self) -> Option<usize> {
+        self.more
+    }
+
+
+    /// Moves the attributes directly following an image (e.g. `{width=60%}`)
+    /// to this image
+    fn find_image_attributes(&self, ast: &mut Vec<Token>) -> Result<()> {
+        let mut i = 0;
+        while i < ast.len() {
+            let attributes = if i > 0 && ast[i - 1].is_image() {
+                match ast[i] {
+                    Token::Str(ref s) if s.starts_with('{') => {
+                        s.find('}').map(|end| (s[1..end].to_owned(), s[end + 1..].to_owned()))
+                    }
+                    _ => None,
+                }
+            } else {
+                None
+            };
+            if let Some((attributes, rest)) = attributes {
+                if ImageOptions::parse(&attributes).is_none() {
+                    return Err(Error::parser(&self.source,
+                                             lformat!("invalid attributes for image: '{attributes}'",
+                                                      attributes = attributes)));
+                }
+                if let Token::Image(_, _, ref mut image_attributes, _) = ast[i - 1] {
+                    *image_attributes = attributes;
+                }
+                if rest.is_empty() {
+                    ast.remove(i);
+                    continue;
+                }
+                ast[i] = Token::Str(rest);
+            }
+
+            if let Some(inner) = ast[i].inner_mut() {
+                self.find_image_attributes(inner)?;
+            }
+            i += 1;
+        }
+        Ok(())
+    }
+
+    /// Converts the block quotes starting with the marker of an admonition
+    /// (e.g. `> [!NOTE]`, the syntax of GitHub) to admonitions
+    fn find_admonitions(&mut self, ast: &mut Vec<Token>) {
+        for token in ast {
+            let kind = match *token {
+                Token::BlockQuote(ref mut content) => admonition_marker(content),
+                _ => None,
+            };
+            if let Some(kind) = kind {
+                self.features.admonition = true;
+                let content = match mem::replace(token, Token::Rule) {
+                    Token::BlockQuote(content) => content,
+                    _ => unreachable!(),
+                };
+                *token = Token::Admonition(kind, String::new(), content);
+            }
+
+            if let Some(inner) = token.inner_mut() {
+                self.find_admonitions(inner);
+            }
+        }
+    }
 
     /// Replace footnote reference with their definition
+    ///
+    /// Footnotes whose reference starts with '>' are margin notes.
     fn parse_footnotes(&mut self, v: &mut Vec<Token>) -> Result<()> {
         for token in v {
-            match *token {
+            let margin_note = match *token {
                 Token::Footnote(ref mut content) => {
                     let reference = if let Token::Str(ref text) = content[0] {
                         text.clone()
@@ -239,6 +345,7 @@ impl Parser {
                                                            not have a matching definition",
                                                           reference = &reference)));
                     }
+                    reference.starts_with('>')
                 }
                 Token::Paragraph(ref mut vec) |
                 Token::Header(_, ref mut vec) |
@@ -247,6 +354,13 @@ impl Parser {
                 Token::Code(ref mut vec) |
                 Token::BlockQuote(ref mut vec) |
                 Token::CodeBlock(_, ref mut vec) |
+                Token::Verse(ref mut vec) |
+                Token::Spoiler(_, ref mut vec) |
+                Token::Admonition(_, _, ref mut vec) |
+                Token::Columns(_, ref mut vec) |
+                Token::KeepTogether(ref mut vec) |
+                Token::Exercise(_, ref mut vec) |
+                Token::Answer(ref mut vec) |
                 Token::List(ref mut vec) |
                 Token::OrderedList(_, ref mut vec) |
                 Token::Item(ref mut vec) |
@@ -255,13 +369,45 @@ impl Parser {
                 Token::TableRow(ref mut vec) |
                 Token::TableCell(ref mut vec) |
                 Token::Link(_, _, ref mut vec) |
-                Token::Image(_, _, ref mut vec) => self.parse_footnotes(vec)?,
-                _ => (),
+                Token::Image(_, _, _, ref mut vec) => {
+                    self.parse_footnotes(vec)?;
+                    false
+                }
+                _ => false,
+            };
+            if margin_note {
+                let content = mem::replace(token.inner_mut().unwrap(), vec![]);
+                *token = Token::MarginNote(content);
             }
         }
         Ok(())
     }
     
+    /// Moves answers into the exercise they directly follow
+    fn attach_answers(&self, ast: &mut Vec<Token>) -> Result<()> {
+        let mut i = 0;
+        while i < ast.len() {
+            if let Token::Answer(_) = ast[i] {
+                let follows_exercise = i > 0 && match ast[i - 1] {
+                    Token::Exercise(..) => true,
+                    _ => false,
+                };
+                if !follows_exercise {
+                    return Err(Error::parser(&self.source,
+                                             lformat!("answer block does not directly follow \
+                                                       an exercise block")));
+                }
+                let answer = ast.remove(i);
+                if let Token::Exercise(_, ref mut content) = ast[i - 1] {
+                    content.push(answer);
+                }
+                continue;
+            }
+            i += 1;
+        }
+        Ok(())
+    }
+
     /// Looks for super script in a vector of tokens
     fn parse_super_vec(&mut self, mut v: &mut Vec<Token>) {
         for i in 0..v.len() {
@@ -330,7 +476,15 @@ impl Parser {
         while let Some(event) = p.next() {
             match event {
                 Event::Html(text) | Event::InlineHtml(text) => {
-                    if self.html_as_text {
+                    if let Some(page) = page_break(&text) {
+                        v.push(Token::PageBreak(page));
+                    } else if current_tag.is_none() && is_new_page_marker(&text) {
+                        v.push(Token::NewPage);
+                    } else if current_tag.is_none() && is_more_marker(&text) {
+                        if self.more.is_none() {
+                            self.more = Some(v.iter().filter(|t| !t.is_header()).count());
+                        }
+                    } else if self.html_as_text {
                         v.push(Token::Str(text.into_owned()));
                     } else {
                         Logger::display_debug(lformat!("ignoring HTML block '{}'", text));
@@ -381,7 +535,7 @@ impl Parser {
             },
             Tag::Image(url, title) => {
                 self.features.image = true;
-                Token::Image(url.into_owned(), title.into_owned(), res)
+                Token::Image(url.into_owned(), title.into_owned(), String::new(), res)
             },
             Tag::Rule => Token::Rule,
             Tag::List(opt) => {
@@ -398,8 +552,47 @@ impl Parser {
                 Token::BlockQuote(res)
             },
             Tag::CodeBlock(language) => {
-                self.features.codeblock = true;
-                Token::CodeBlock(language.into_owned(), res)
+                if language.as_ref() == "verse" {
+                    Token::Verse(self.parse_verse(&res)?)
+                } else if let Some(summary) = spoiler_summary(language.as_ref()) {
+                    Token::Spoiler(summary.to_owned(), self.parse_nested(&res)?)
+                } else if let Some((kind, title)) = admonition(language.as_ref()) {
+                    self.features.admonition = true;
+                    Token::Admonition(kind.to_owned(), title.to_owned(), self.parse_nested(&res)?)
+                } else if let Some(count) = columns(language.as_ref()) {
+                    let count = count.ok_or_else(|| Error::parser(&self.source,
+                                                                  lformat!("invalid number of \
+                                                                            columns: '{info}'",
+                                                                           info = language)))?;
+                    self.features.columns = true;
+                    Token::Columns(count, self.parse_nested(&res)?)
+                } else if language.as_ref().trim() == "keep" {
+                    Token::KeepTogether(self.parse_nested(&res)?)
+                } else if strip_language(language.as_ref()) == "exercise" {
+                    let options = CodeOptions::parse(language.as_ref())
+                        .ok_or_else(|| Error::parser(&self.source,
+                                                     lformat!("invalid options for exercise: \
+                                                               '{info}'",
+                                                              info = language)))?;
+                    Token::Exercise(options.id.unwrap_or_default(), self.parse_nested(&res)?)
+                } else if language.as_ref().trim() == "answer" {
+                    Token::Answer(self.parse_nested(&res)?)
+                } else {
+                    self.features.codeblock = true;
+                    if CodeOptions::parse(language.as_ref()).is_some() {
+                        Token::CodeBlock(language.into_owned(), res)
+                    } else {
+                        // Attributes meant for other tools (e.g. pandoc's
+                        // `{.python}`) are dropped, and the code block kept
+                        self.warnings.push(lformat!("in {file}, ignoring invalid options \
+                                                     for code block: '{info}'",
+                                                    file = self.source,
+                                                    info = language));
+                        let info = language.as_ref();
+                        let end = info.find('{').unwrap_or(info.len());
+                        Token::CodeBlock(info[..end].trim().to_owned(), res)
+                    }
+                }
             },
             Tag::Table(v) => {
                 self.features.table = true;
@@ -411,11 +604,11 @@ impl Parser {
             Tag::TableCell => Token::TableCell(res),
             Tag::FootnoteDefinition(reference) => {
                 if self.footnotes.contains_key(reference.as_ref()) {
-                    Logger::display_warning(lformat!("in {file}, found footnote definition for \
-                                                      note '{reference}' but previous \
-                                                      definition already exist, overriding it",
-                                                     file = self.source,
-                                                     reference = reference));
+                    self.warnings.push(lformat!("in {file}, found footnote definition for \
+                                                 note '{reference}' but previous \
+                                                 definition already exist, overriding it",
+                                                file = self.source,
+                                                reference = reference));
                 }
                 self.footnotes.insert(reference.into_owned(), res);
                 Token::SoftBreak
@@ -424,6 +617,93 @@ impl Parser {
         v.push(token);
         Ok(())
     }
+
+    /// Parses the content of a `spoiler`, `exercise` or `answer` code block
+    /// as Markdown
+    ///
+    /// Footnotes and other elements are resolved with the ones of the
+    /// chapter containing it.
+    fn parse_nested(&mut self, content: &[Token]) -> Result<Vec<Token>> {
+        let mut text = String::new();
+        for token in content {
+            if let Token::Str(ref s) = *token {
+                text.push_str(s);
+            }
+        }
+
+        let mut opts = Options::empty();
+        opts.insert(OPTION_ENABLE_TABLES);
+        opts.insert(OPTION_ENABLE_FOOTNOTES);
+        let mut p = CMParser::new_ext(&text, opts);
+        let mut res = vec![];
+        // A marker in a nested block doesn't end the summary of the chapter
+        let more = self.more;
+        self.parse_events(&mut p, &mut res, None)?;
+        self.more = more;
+        Ok(res)
+    }
+
+    /// Transforms the content of a `verse` code block into a list of stanzas
+    ///
+    /// Each line is parsed as inline Markdown, leading indentation is kept (as
+    /// em spaces, one for every two spaces) and empty lines separate stanzas.
+    fn parse_verse(&mut self, content: &[Token]) -> Result<Vec<Token>> {
+        let mut text = String::new();
+        for token in content {
+            if let Token::Str(ref s) = *token {
+                text.push_str(s);
+            }
+        }
+
+        let mut stanzas = vec![];
+        let mut stanza = vec![];
+        for line in text.lines() {
+            if line.trim().is_empty() {
+                if !stanza.is_empty() {
+                    stanzas.push(Token::Paragraph(mem::replace(&mut stanza, vec![])));
+                }
+                continue;
+            }
+            if !stanza.is_empty() {
+                stanza.push(Token::HardBreak);
+            }
+
+            let trimmed = line.trim_left();
+            let indent = line[..line.len() - trimmed.len()]
+                .chars()
+                .map(|c| if c == '\t' { 4 } else { 1 })
+                .sum::<usize>();
+            let indent = (indent + 1) / 2;
+            if indent > 0 {
+                stanza.push(Token::Str(iter::repeat('\u{2003}').take(indent).collect()));
+            }
+
+            let mut parser = Parser::new();
+            parser.html_as_text = self.html_as_text;
+            parser.source = self.source.clone();
+            let mut tokens = parser.parse(trimmed)?;
+            self.features = self.features | parser.features();
+            self.warnings.extend(parser.take_warnings());
+            // A line such as "- foo" or "1. foo" would be parsed as a list: only keep
+            // the result if it is a simple paragraph.
+            let inline = if tokens.len() == 1 {
+                match tokens[0] {
+                    Token::Paragraph(ref mut v) => Some(mem::replace(v, vec![])),
+                    _ => None,
+                }
+            } else {
+                None
+            };
+            match inline {
+                Some(mut inline) => stanza.append(&mut inline),
+                None => stanza.push(Token::Str(trimmed.to_owned())),
+            }
+        }
+        if !stanza.is_empty() {
+            stanzas.push(Token::Paragraph(stanza));
+        }
+        Ok(stanzas)
+    }
 }
 
 
@@ -550,14 +830,134 @@ fn collapse(ast: &mut Vec<Token>) {
 }
 
 /// Replace images which are alone in a paragraph by standalone images
+/// Returns the page number of a page break marker of the print edition,
+/// e.g. `<!-- page 12 -->`, or `None` if this HTML is not one
+fn page_break(html: &str) -> Option<String> {
+    let html = html.trim();
+    if !html.starts_with("<!--") || !html.ends_with("-->") {
+        return None;
+    }
+    let mut words = html[4..html.len() - 3].split_whitespace();
+    match (words.next(), words.next(), words.next()) {
+        (Some("page"), Some(page), None) => Some(page.to_owned()),
+        _ => None,
+    }
+}
+
+/// Returns true if the HTML block is a `<!-- pagebreak -->` marker, forcing
+/// a page break
+fn is_new_page_marker(html: &str) -> bool {
+    let html = html.trim();
+    html.starts_with("<!--") && html.ends_with("-->") &&
+        html[4..html.len() - 3].trim() == "pagebreak"
+}
+
+/// Returns true if the HTML block is a `<!-- more -->` marker, ending the
+/// summary of a chapter
+fn is_more_marker(html: &str) -> bool {
+    let html = html.trim();
+    html.starts_with("<!--") && html.ends_with("-->") && html[4..html.len() - 3].trim() == "more"
+}
+
+/// Returns the summary of a code block if its info string is the one of a
+/// spoiler block, e.g. `spoiler Show the answer`
+fn spoiler_summary(info: &str) -> Option<&str> {
+    let info = info.trim();
+    if info == "spoiler" {
+        Some("")
+    } else if info.starts_with("spoiler ") {
+        Some(info["spoiler ".len()..].trim())
+    } else {
+        None
+    }
+}
+
+/// Returns the kind and title of a code block if its info string is the one
+/// of an admonition, e.g. `warning Mind the gap`
+fn admonition(info: &str) -> Option<(&str, &str)> {
+    let info = info.trim();
+    let (kind, title) = match info.find(' ') {
+        Some(i) => (&info[..i], info[i..].trim()),
+        None => (info, ""),
+    };
+    if ADMONITIONS.iter().any(|k| *k == kind) {
+        Some((kind, title))
+    } else {
+        None
+    }
+}
+
+/// Returns the number of columns of a code block if its info string is the
+/// one of a multi-column region, e.g. `columns 3` (two columns if it isn't
+/// specified), or `Some(None)` if this number isn't valid
+fn columns(info: &str) -> Option<Option<u32>> {
+    let info = info.trim();
+    if info == "columns" {
+        Some(Some(2))
+    } else if info.starts_with("columns ") {
+        match info["columns ".len()..].trim().parse::<u32>() {
+            Ok(n) if n >= 2 => Some(Some(n)),
+            _ => Some(None),
+        }
+    } else {
+        None
+    }
+}
+
+/// Removes the marker of an admonition with the syntax of GitHub (e.g.
+/// `[!NOTE]`) from the start of the content of a block quote, and returns
+/// its kind, or `None` if the block quote doesn't start with one
+fn admonition_marker(content: &mut Vec<Token>) -> Option<String> {
+    let (kind, rest) = {
+        let s = match content.first() {
+            Some(&Token::Paragraph(ref inner)) => {
+                match inner.first() {
+                    Some(&Token::Str(ref s)) => s,
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+        if !s.starts_with("[!") {
+            return None;
+        }
+        let end = match s.find(']') {
+            Some(end) => end,
+            None => return None,
+        };
+        let kind = s[2..end].to_lowercase();
+        if !ADMONITIONS.iter().any(|k| *k == kind) {
+            return None;
+        }
+        (kind, s[end + 1..].trim_left().to_owned())
+    };
+    let empty = if let Token::Paragraph(ref mut inner) = content[0] {
+        if rest.is_empty() {
+            inner.remove(0);
+        } else {
+            inner[0] = Token::Str(rest);
+        }
+        while inner.first().map_or(false, |t| *t == Token::SoftBreak || *t == Token::HardBreak) {
+            inner.remove(0);
+        }
+        inner.is_empty()
+    } else {
+        unreachable!();
+    };
+    if empty {
+        content.remove(0);
+    }
+    Some(kind)
+}
+
 fn find_standalone(ast: &mut Vec<Token>) {
     for token in ast {
         let res = if let &mut Token::Paragraph(ref mut inner) = token {
             if inner.len() == 1 {
                 if inner[0].is_image() {
-                    if let Token::Image(source, title, inner) = mem::replace(&mut inner[0],
-                                                                             Token::Rule) {
-                        Token::StandaloneImage(source, title, inner)
+                    if let Token::Image(source, title, attributes, inner) =
+                        mem::replace(&mut inner[0], Token::Rule) {
+                        Token::StandaloneImage(source, title, attributes, inner)
                     } else {
                         unreachable!();
                     }
@@ -566,9 +966,10 @@ fn find_standalone(ast: &mut Vec<Token>) {
                     // Fixme: messy code and unnecessary clone
                     if let Token::Link(ref url, ref alt, ref mut inner) = inner[0] {
                         if inner[0].is_image() {
-                            if let Token::Image(source, title, inner) = mem::replace(&mut inner[0],
-                                                                                     Token::Rule) {
-                                Token::Link(url.clone(), alt.clone(), vec![Token::StandaloneImage(source, title, inner)])
+                            if let Token::Image(source, title, attributes, inner) =
+                                mem::replace(&mut inner[0], Token::Rule) {
+                                Token::Link(url.clone(), alt.clone(),
+                                            vec![Token::StandaloneImage(source, title, attributes, inner)])
                             } else {
                                 unreachable!();
                             }
@@ -590,6 +991,96 @@ fn find_standalone(ast: &mut Vec<Token>) {
     }
 }
 
+/// Moves a paragraph starting with "Table:" that directly follows a table
+/// to a `TableCaption` at the beginning of this table
+fn find_table_captions(ast: &mut Vec<Token>) {
+    let mut i = 0;
+    while i < ast.len() {
+        let is_caption = i > 0 && match (&ast[i - 1], &ast[i]) {
+            (&Token::Table(..), &Token::Paragraph(ref inner)) => {
+                match inner.first() {
+                    Some(&Token::Str(ref s)) => s.starts_with("Table:"),
+                    _ => false,
+                }
+            }
+            _ => false,
+        };
+        if is_caption {
+            let mut caption = match ast.remove(i) {
+                Token::Paragraph(inner) => inner,
+                _ => unreachable!(),
+            };
+            let rest = if let Token::Str(ref s) = caption[0] {
+                s["Table:".len()..].trim_left().to_owned()
+            } else {
+                unreachable!();
+            };
+            if rest.is_empty() {
+                caption.remove(0);
+            } else {
+                caption[0] = Token::Str(rest);
+            }
+            if let Token::Table(_, ref mut content) = ast[i - 1] {
+                content.insert(0, Token::TableCaption(caption));
+            }
+            continue;
+        }
+
+        if let Some(ref mut inner) = ast[i].inner_mut() {
+            find_table_captions(inner);
+        }
+        i += 1;
+    }
+}
+
+/// Moves the last paragraph of a block quote to an `Attribution` if it
+/// starts with a dash (e.g. `— Someone` or `-- Someone`) and follows the
+/// quote itself
+fn find_attributions(ast: &mut Vec<Token>) {
+    for token in ast {
+        if let Token::BlockQuote(ref mut content) = *token {
+            let prefix_len = if content.len() < 2 {
+                None
+            } else {
+                match content.last() {
+                    Some(&Token::Paragraph(ref inner)) => {
+                        match inner.first() {
+                            Some(&Token::Str(ref s)) => {
+                                ["— ", "—\u{a0}", "--- ", "-- "]
+                                    .iter()
+                                    .find(|prefix| s.starts_with(**prefix))
+                                    .map(|prefix| prefix.len())
+                            }
+                            _ => None,
+                        }
+                    }
+                    _ => None,
+                }
+            };
+            if let Some(len) = prefix_len {
+                let mut attribution = match content.pop() {
+                    Some(Token::Paragraph(inner)) => inner,
+                    _ => unreachable!(),
+                };
+                let rest = if let Token::Str(ref s) = attribution[0] {
+                    s[len..].trim_left().to_owned()
+                } else {
+                    unreachable!();
+                };
+                if rest.is_empty() {
+                    attribution.remove(0);
+                } else {
+                    attribution[0] = Token::Str(rest);
+                }
+                content.push(Token::Attribution(attribution));
+            }
+        }
+
+        if let Some(inner) = token.inner_mut() {
+            find_attributions(inner);
+        }
+    }
+}
 
 #[test]
 fn test_parse_super_str() {
@@ -0,0 +1,889 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Experimental PDF renderer that doesn't need any external tool, for books
+//! that are mostly text (and JPEG images), such as fiction.
+//!
+//! It uses the standard Times and Courier fonts, which are not embedded,
+//! so only characters of the Windows-1252 encoding are displayed.
+
+use book::Book;
+use error::{Error, Result};
+use number::Number;
+use parser::Parser;
+use token::Token;
+use text_view::view_as_text;
+use images;
+use lang;
+
+use std::io::{self, Read, Write};
+use std::fs::File;
+use std::mem;
+
+/// Width of the page (A5), in points
+const PAGE_WIDTH: f32 = 420.0;
+/// Height of the page (A5), in points
+const PAGE_HEIGHT: f32 = 595.0;
+const MARGIN: f32 = 50.0;
+/// Size of the body text
+const FONT_SIZE: f32 = 11.0;
+
+/// Widths of the ASCII characters (from space to tilde) of Times-Roman, in
+/// thousandths of the font size
+static TIMES_WIDTHS: [u16; 95] = [250, 333, 408, 500, 500, 833, 778, 180, 333, 333, 500, 564,
+                                  250, 333, 250, 278, 500, 500, 500, 500, 500, 500, 500, 500,
+                                  500, 500, 278, 278, 564, 564, 564, 444, 921, 722, 667, 667,
+                                  722, 611, 556, 722, 722, 333, 389, 722, 611, 889, 722, 722,
+                                  556, 722, 667, 556, 611, 722, 722, 944, 722, 722, 611, 333,
+                                  278, 333, 469, 500, 333, 444, 500, 444, 500, 444, 333, 500,
+                                  500, 278, 278, 500, 278, 778, 500, 500, 500, 500, 333, 389,
+                                  278, 500, 500, 722, 500, 500, 444, 480, 200, 480, 541];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Font {
+    Regular,
+    Bold,
+    Italic,
+    BoldItalic,
+    Mono,
+}
+
+impl Font {
+    fn name(self) -> &'static str {
+        match self {
+            Font::Regular => "F1",
+            Font::Bold => "F2",
+            Font::Italic => "F3",
+            Font::BoldItalic => "F4",
+            Font::Mono => "F5",
+        }
+    }
+
+    fn base_font(self) -> &'static str {
+        match self {
+            Font::Regular => "Times-Roman",
+            Font::Bold => "Times-Bold",
+            Font::Italic => "Times-Italic",
+            Font::BoldItalic => "Times-BoldItalic",
+            Font::Mono => "Courier",
+        }
+    }
+
+    fn bold(self) -> Font {
+        match self {
+            Font::Regular | Font::Bold => Font::Bold,
+            Font::Italic | Font::BoldItalic => Font::BoldItalic,
+            Font::Mono => Font::Mono,
+        }
+    }
+
+    fn italic(self) -> Font {
+        match self {
+            Font::Regular | Font::Italic => Font::Italic,
+            Font::Bold | Font::BoldItalic => Font::BoldItalic,
+            Font::Mono => Font::Mono,
+        }
+    }
+
+    /// Approximate width of a character, in thousandths of the font size
+    ///
+    /// Bold fonts are slightly wider than Times-Roman, so lines are not
+    /// overfull.
+    fn char_width(self, c: char) -> f32 {
+        if self == Font::Mono {
+            return 600.0;
+        }
+        let width = match c as u32 {
+            n @ 32...126 => TIMES_WIDTHS[(n - 32) as usize] as f32,
+            _ if c == '—' || c == '…' => 1000.0,
+            _ if c.is_uppercase() => 722.0,
+            _ => 500.0,
+        };
+        match self {
+            Font::Bold | Font::BoldItalic => width * 1.05,
+            _ => width,
+        }
+    }
+
+    fn width(self, s: &str, size: f32) -> f32 {
+        s.chars().map(|c| self.char_width(c)).sum::<f32>() * size / 1000.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Align {
+    Justify,
+    Left,
+    Center,
+}
+
+/// A word, which can use different fonts (e.g. `*Hello*, world`)
+#[derive(Debug, Default)]
+struct Word {
+    fragments: Vec<(String, Font)>,
+}
+
+impl Word {
+    fn width(&self, size: f32) -> f32 {
+        self.fragments.iter().map(|&(ref s, font)| font.width(s, size)).sum()
+    }
+}
+
+/// A JPEG image included in the PDF file
+struct Jpeg {
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+    components: u8,
+}
+
+/// Renderer for PDF, that doesn't use LaTeX
+///
+/// Still very experimental.
+pub struct PdfRenderer<'a> {
+    book: &'a Book,
+    current_chapter: i32,
+    current_numbering: i32,
+    current_hide: bool,
+    /// Content streams of the pages that are finished
+    pages: Vec<String>,
+    /// Content stream of the current page
+    page: String,
+    /// Vertical position on the current page, from the bottom
+    y: f32,
+    /// Left indentation of the current blocks (lists, quotes)
+    indent: f32,
+    /// Footnotes of the current chapter, displayed at its end
+    notes: Vec<Vec<Token>>,
+    images: Vec<Jpeg>,
+    /// Watermark of personalized copies, and whether it is displayed
+    /// across the page (or in the footer)
+    watermark: Option<(String, bool)>,
+    /// Whether text is typographically cleaned
+    clean: bool,
+}
+
+impl<'a> PdfRenderer<'a> {
+    /// Creates a new PdfRenderer
+    pub fn new(book: &'a Book) -> PdfRenderer<'a> {
+        PdfRenderer {
+            book: book,
+            current_chapter: 1,
+            current_numbering: book.options.get_i32("rendering.num_depth").unwrap(),
+            current_hide: false,
+            pages: vec![],
+            page: String::new(),
+            y: PAGE_HEIGHT - MARGIN,
+            indent: 0.0,
+            notes: vec![],
+            images: vec![],
+            watermark: None,
+            clean: book.cleans("pdf"),
+        }
+    }
+
+    /// Renders the book to a PDF file
+    pub fn render_book(&mut self, to: &mut Write) -> Result<()> {
+        let book = self.book;
+        if let Ok(watermark) = book.options.get_str("watermark") {
+            let diagonal = match book.options.get_str("watermark.pdf").unwrap() {
+                "diagonal" => true,
+                "footer" => false,
+                value => {
+                    return Err(Error::render(&book.source,
+                                             lformat!("watermark.pdf set to '{value}', \
+                                                       expected 'diagonal' or 'footer'",
+                                                      value = value)))
+                }
+            };
+            self.watermark = Some((watermark.to_owned(), diagonal));
+        }
+        if book.options.get_bool("rendering.front_matter.title_page").unwrap() {
+            self.title_page()?;
+        }
+        for chapter in book.chapters.iter() {
+            let chapter = book.clean_chapter(chapter, self.clean)?;
+            self.current_hide = chapter.hide_title;
+            match chapter.number {
+                Number::Hidden | Number::Unnumbered | Number::UnnumberedPart => {
+                    self.current_numbering = 0
+                }
+                Number::Default | Number::DefaultPart => {
+                    self.current_numbering = book.options.get_i32("rendering.num_depth").unwrap()
+                }
+                Number::Specified(n) | Number::SpecifiedPart(n) => {
+                    self.current_numbering = book.options.get_i32("rendering.num_depth").unwrap();
+                    self.current_chapter = n;
+                }
+            }
+            self.new_page();
+            self.render_blocks(&chapter.content)?;
+            self.render_notes()?;
+        }
+        self.new_page();
+        self.write_pdf(to)
+            .map_err(|e| {
+                Error::render(&book.source,
+                              lformat!("problem when writing PDF: {error}", error = e))
+            })
+    }
+
+    fn title_page(&mut self) -> Result<()> {
+        let book = self.book;
+        let title = book.options.get_str("title").unwrap_or("");
+        let author = book.options.get_str("author").unwrap_or("");
+        self.y = PAGE_HEIGHT * 2.0 / 3.0;
+        let words = words_of(&[Token::Str(title.to_owned())], Font::Bold);
+        self.layout(words, 22.0, Align::Center, 0.0);
+        self.y -= 20.0;
+        let words = words_of(&[Token::Str(author.to_owned())], Font::Regular);
+        self.layout(words, 14.0, Align::Center, 0.0);
+        Ok(())
+    }
+
+    fn render_blocks(&mut self, tokens: &[Token]) -> Result<()> {
+        let mut inline = vec![];
+        for token in tokens {
+            if is_inline(token) {
+                inline.push(token.clone());
+                continue;
+            }
+            if !inline.is_empty() {
+                let content = mem::replace(&mut inline, vec![]);
+                self.paragraph(&content, Align::Justify)?;
+            }
+            self.render_block(token)?;
+        }
+        if !inline.is_empty() {
+            self.paragraph(&inline, Align::Justify)?;
+        }
+        Ok(())
+    }
+
+    fn render_block(&mut self, token: &Token) -> Result<()> {
+        match *token {
+            Token::Paragraph(ref vec) => self.paragraph(vec, Align::Justify)?,
+            Token::Header(n, ref vec) => self.header(n, vec)?,
+            Token::CodeBlock(_, ref vec) => {
+                let text = view_as_text(vec);
+                for line in text.lines() {
+                    let word = Word { fragments: vec![(line.to_owned(), Font::Mono)] };
+                    self.layout(vec![word], FONT_SIZE - 2.0, Align::Left, 0.0);
+                }
+                self.y -= FONT_SIZE / 2.0;
+            }
+            Token::Verse(ref vec) => {
+                // A poem is either a list of stanzas, or a single one
+                let stanzas: Vec<&[Token]> = if vec.iter().all(|t| !is_inline(t)) {
+                    vec.iter().filter_map(|t| t.inner()).collect()
+                } else {
+                    vec![vec.as_slice()]
+                };
+                self.indent += 20.0;
+                for stanza in stanzas {
+                    for line in stanza.split(|t| *t == Token::HardBreak || *t == Token::SoftBreak) {
+                        let words = self.words(line)?;
+                        self.layout(words, FONT_SIZE, Align::Left, 0.0);
+                    }
+                    self.y -= FONT_SIZE / 2.0;
+                }
+                self.indent -= 20.0;
+            }
+            Token::BlockQuote(ref vec) => {
+                self.indent += 20.0;
+                self.render_blocks(vec)?;
+                self.indent -= 20.0;
+            }
+            Token::Attribution(ref vec) => {
+                let mut words = words_of(&[Token::Str(String::from("—"))], Font::Regular);
+                words.append(&mut self.words(vec)?);
+                self.layout(words, FONT_SIZE, Align::Left, 0.0);
+            }
+            Token::List(ref vec) => self.list(vec, None)?,
+            Token::OrderedList(start, ref vec) => self.list(vec, Some(start))?,
+            Token::Rule => {
+                let words = words_of(&[Token::Str(String::from("* * *"))], Font::Regular);
+                self.y -= FONT_SIZE;
+                self.layout(words, FONT_SIZE, Align::Center, 0.0);
+                self.y -= FONT_SIZE;
+            }
+            Token::PageBreak(_) | Token::NewPage => self.new_page(),
+            Token::StandaloneImage(ref url, _, _, ref alt) => self.image(url, alt)?,
+            Token::Epigraph(ref author, ref vec) => {
+                self.indent += PAGE_WIDTH / 4.0;
+                self.render_blocks(vec)?;
+                if !author.is_empty() {
+                    let author = Parser::new().parse_inline(author)?;
+                    let mut words = words_of(&[Token::Str(String::from("—"))], Font::Regular);
+                    words.append(&mut self.words(&author)?);
+                    self.layout(words, FONT_SIZE, Align::Left, 0.0);
+                }
+                self.indent -= PAGE_WIDTH / 4.0;
+                self.y -= FONT_SIZE;
+            }
+            Token::TableRow(ref cells) => {
+                let mut row = vec![];
+                for (i, cell) in cells.iter().enumerate() {
+                    if i > 0 {
+                        row.push(Token::Str(String::from(" | ")));
+                    }
+                    if let Some(inner) = cell.inner() {
+                        row.extend(inner.iter().cloned());
+                    }
+                }
+                self.paragraph(&row, Align::Left)?;
+            }
+            Token::Spoiler(ref summary, ref vec) => {
+                let summary = if summary.is_empty() {
+                    vec![Token::Str(lang::get_str(self.book.options.get_str("lang").unwrap(),
+                                                  "solution"))]
+                } else {
+                    Parser::new().parse_inline(summary)?
+                };
+                self.paragraph(&[Token::Emphasis(summary)], Align::Left)?;
+                self.render_blocks(vec)?;
+            }
+            Token::Admonition(ref kind, ref title, ref vec) => {
+                let title = if title.is_empty() {
+                    vec![Token::Str(lang::get_str(self.book.options.get_str("lang").unwrap(),
+                                                  kind))]
+                } else {
+                    Parser::new().parse_inline(title)?
+                };
+                self.indent += 20.0;
+                self.paragraph(&[Token::Strong(title)], Align::Left)?;
+                self.render_blocks(vec)?;
+                self.indent -= 20.0;
+            }
+            ref token => {
+                if let Some(inner) = token.inner() {
+                    self.render_blocks(inner)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn header(&mut self, n: i32, vec: &[Token]) -> Result<()> {
+        if n == 1 && self.current_hide {
+            // A hidden title still counts for chapter numbering
+            if self.current_numbering >= 1 {
+                self.current_chapter += 1;
+            }
+            return Ok(());
+        }
+        let words = if n == 1 && self.current_numbering >= 1 {
+            let chapter = self.current_chapter;
+            self.current_chapter += 1;
+            let title = self.book
+                .get_chapter_header(chapter, view_as_text(vec), |s| {
+                    Ok(view_as_text(&Parser::new().parse_inline(s)?))
+                })?
+                .text;
+            words_of(&[Token::Str(title)], Font::Bold)
+        } else {
+            words_of(vec, Font::Bold)
+        };
+        if n == 1 {
+            self.y -= 60.0;
+            self.layout(words, 18.0, Align::Center, 0.0);
+            self.y -= 30.0;
+        } else {
+            // Don't leave a title alone at the bottom of a page
+            if self.y - 4.0 * FONT_SIZE < MARGIN {
+                self.new_page();
+            }
+            self.y -= FONT_SIZE;
+            self.layout(words, FONT_SIZE + 2.0, Align::Left, 0.0);
+            self.y -= FONT_SIZE / 2.0;
+        }
+        Ok(())
+    }
+
+    fn paragraph(&mut self, vec: &[Token], align: Align) -> Result<()> {
+        let words = self.words(vec)?;
+        let first_indent = if align == Align::Justify { 15.0 } else { 0.0 };
+        self.layout(words, FONT_SIZE, align, first_indent);
+        Ok(())
+    }
+
+    fn list(&mut self, items: &[Token], start: Option<usize>) -> Result<()> {
+        self.indent += 15.0;
+        for (i, item) in items.iter().enumerate() {
+            let bullet = match start {
+                Some(start) => format!("{}.", start + i),
+                None => String::from("•"),
+            };
+            let mut content = vec![Token::Str(format!("{} ", bullet))];
+            let mut blocks = vec![];
+            if let Some(inner) = item.inner() {
+                // Only the text of the first paragraph follows the bullet
+                for token in inner {
+                    match *token {
+                        Token::Paragraph(ref vec) if content.len() == 1 => {
+                            content.extend(vec.iter().cloned())
+                        }
+                        ref token if is_inline(token) => content.push(token.clone()),
+                        ref token => blocks.push(token.clone()),
+                    }
+                }
+            }
+            self.paragraph(&content, Align::Left)?;
+            self.render_blocks(&blocks)?;
+        }
+        self.indent -= 15.0;
+        self.y -= FONT_SIZE / 2.0;
+        Ok(())
+    }
+
+    fn render_notes(&mut self) -> Result<()> {
+        if self.notes.is_empty() {
+            return Ok(());
+        }
+        let notes = mem::replace(&mut self.notes, vec![]);
+        self.y -= FONT_SIZE;
+        for (i, note) in notes.iter().enumerate() {
+            let mut words = words_of(&[Token::Str(format!("{}.", i + 1))], Font::Regular);
+            words.append(&mut self.words(&flatten_blocks(note))?);
+            self.layout(words, FONT_SIZE - 2.0, Align::Left, 0.0);
+        }
+        Ok(())
+    }
+
+    fn image(&mut self, url: &str, alt: &[Token]) -> Result<()> {
+        let lower = url.to_lowercase();
+        let image = if images::media_kind(url).is_none() &&
+                       (lower.ends_with(".jpg") || lower.ends_with(".jpeg")) {
+            match read_jpeg(url) {
+                Ok(Some(image)) => Some(image),
+                Ok(None) => {
+                    self.book.logger.warning(lformat!("PDF: '{file}' is not a valid JPEG file, \
+                                                       it was replaced by its description",
+                                                      file = url));
+                    None
+                }
+                Err(err) => {
+                    return Err(Error::render(&self.book.source,
+                                             lformat!("PDF: could not read image '{file}': {error}",
+                                                      file = url,
+                                                      error = err)));
+                }
+            }
+        } else {
+            self.book.logger.warning(lformat!("PDF: only JPEG images are supported by \
+                                               this renderer, '{file}' was replaced by its \
+                                               description",
+                                              file = url));
+            None
+        };
+        match image {
+            Some(image) => {
+                let max_width = PAGE_WIDTH - 2.0 * MARGIN - self.indent;
+                let max_height = PAGE_HEIGHT - 2.0 * MARGIN;
+                let scale = (max_width / image.width as f32)
+                    .min(max_height / image.height as f32)
+                    .min(1.0);
+                let (width, height) = (image.width as f32 * scale, image.height as f32 * scale);
+                if self.y - height < MARGIN {
+                    self.new_page();
+                }
+                self.y -= height;
+                let x = MARGIN + self.indent + (max_width - width) / 2.0;
+                self.images.push(image);
+                self.page.push_str(&format!("q {:.2} 0 0 {:.2} {:.2} {:.2} cm /Im{} Do Q\n",
+                                            width,
+                                            height,
+                                            x,
+                                            self.y,
+                                            self.images.len()));
+                self.y -= FONT_SIZE;
+            }
+            None => {
+                let mut alt = alt.to_vec();
+                alt.insert(0, Token::Str(String::from("[")));
+                alt.push(Token::Str(String::from("]")));
+                self.paragraph(&[Token::Emphasis(alt)], Align::Center)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the words of inline tokens
+    fn words(&mut self, tokens: &[Token]) -> Result<Vec<Word>> {
+        let mut words = vec![Word::default()];
+        self.add_words(tokens, Font::Regular, &mut words)?;
+        Ok(words.into_iter().filter(|w| !w.fragments.is_empty()).collect())
+    }
+
+    fn add_words(&mut self, tokens: &[Token], font: Font, words: &mut Vec<Word>) -> Result<()> {
+        for token in tokens {
+            match *token {
+                Token::Str(ref text) => {
+                    let text = self.book.clean_if(self.clean, text.as_str(), false);
+                    push_text(&text, font, words);
+                }
+                Token::Emphasis(ref vec) => self.add_words(vec, font.italic(), words)?,
+                Token::Strong(ref vec) => self.add_words(vec, font.bold(), words)?,
+                Token::Code(ref vec) => push_text(&view_as_text(vec), Font::Mono, words),
+                Token::SoftBreak | Token::HardBreak => words.push(Word::default()),
+                Token::Footnote(ref vec) => {
+                    self.notes.push(vec.clone());
+                    let marker = format!("[{}]", self.notes.len());
+                    push_text(&marker, font, words);
+                }
+                Token::Image(_, _, _, ref alt) => {
+                    push_text("[", font, words);
+                    self.add_words(alt, font.italic(), words)?;
+                    push_text("]", font, words);
+                }
+                ref token => {
+                    if let Some(inner) = token.inner() {
+                        self.add_words(inner, font, words)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Lays out words in lines, starting new pages when needed
+    fn layout(&mut self, words: Vec<Word>, size: f32, align: Align, first_indent: f32) {
+        let leading = size * 1.3;
+        let space = Font::Regular.width(" ", size);
+        let width = PAGE_WIDTH - 2.0 * MARGIN - self.indent;
+        let mut lines: Vec<Vec<Word>> = vec![];
+        let mut line: Vec<Word> = vec![];
+        let mut line_width = first_indent;
+        for word in words {
+            let w = word.width(size);
+            if !line.is_empty() && line_width + space + w > width {
+                lines.push(mem::replace(&mut line, vec![]));
+                line_width = 0.0;
+            }
+            if !line.is_empty() {
+                line_width += space;
+            }
+            line_width += w;
+            line.push(word);
+        }
+        if !line.is_empty() {
+            lines.push(line);
+        }
+
+        let count = lines.len();
+        for (i, line) in lines.into_iter().enumerate() {
+            if self.y - leading < MARGIN {
+                self.new_page();
+            }
+            self.y -= leading;
+            let indent = if i == 0 { first_indent } else { 0.0 };
+            let natural = indent + line.iter().map(|w| w.width(size)).sum::<f32>() +
+                          space * (line.len() - 1) as f32;
+            let (x, word_spacing) = match align {
+                Align::Left => (0.0, 0.0),
+                Align::Center => ((width - natural) / 2.0, 0.0),
+                Align::Justify if i + 1 < count && line.len() > 1 => {
+                    (0.0, (width - natural) / (line.len() - 1) as f32)
+                }
+                Align::Justify => (0.0, 0.0),
+            };
+            let mut content = format!("BT {:.2} Tw {:.2} {:.2} Td",
+                                      word_spacing,
+                                      MARGIN + self.indent + indent + x,
+                                      self.y);
+            for (j, word) in line.iter().enumerate() {
+                for (k, &(ref text, font)) in word.fragments.iter().enumerate() {
+                    let text = if j > 0 && k == 0 {
+                        format!(" {}", text)
+                    } else {
+                        text.clone()
+                    };
+                    content.push_str(&format!(" /{} {:.1} Tf ({}) Tj", font.name(), size, encode(&text)));
+                }
+            }
+            content.push_str(" ET\n");
+            self.page.push_str(&content);
+        }
+        self.y -= size * 0.3;
+    }
+
+    /// Finishes the current page, if it isn't empty
+    fn new_page(&mut self) {
+        if self.page.is_empty() {
+            self.y = PAGE_HEIGHT - MARGIN;
+            return;
+        }
+        let mut page = mem::replace(&mut self.page, String::new());
+        let number = (self.pages.len() + 1).to_string();
+        if !self.pages.is_empty() {
+            let x = (PAGE_WIDTH - Font::Regular.width(&number, 9.0)) / 2.0;
+            page.push_str(&format!("BT 0 Tw {:.2} {:.2} Td /F1 9.0 Tf ({}) Tj ET\n",
+                                   x,
+                                   MARGIN / 2.0,
+                                   number));
+        }
+        if let Some((ref text, diagonal)) = self.watermark {
+            page.push_str(&watermark(text, diagonal));
+        }
+        self.pages.push(page);
+        self.y = PAGE_HEIGHT - MARGIN;
+    }
+
+    /// Writes the PDF file
+    fn write_pdf(&self, to: &mut Write) -> io::Result<()> {
+        let fonts = [Font::Regular, Font::Bold, Font::Italic, Font::BoldItalic, Font::Mono];
+        // Objects are numbered from 1: catalog, pages, info, fonts, images,
+        // then a page object and a content stream for each page
+        let first_font = 4;
+        let first_image = first_font + fonts.len();
+        let first_page = first_image + self.images.len();
+
+        let mut objects: Vec<Vec<u8>> = vec![];
+        objects.push(b"<< /Type /Catalog /Pages 2 0 R >>".to_vec());
+        let kids: Vec<String> = (0..self.pages.len())
+            .map(|i| format!("{} 0 R", first_page + 2 * i))
+            .collect();
+        objects.push(format!("<< /Type /Pages /Kids [{}] /Count {} >>",
+                             kids.join(" "),
+                             self.pages.len())
+            .into_bytes());
+        objects.push(format!("<< /Title ({}) /Author ({}) /Producer (Crowbook) >>",
+                             encode(self.book.options.get_str("title").unwrap_or("")),
+                             encode(self.book.options.get_str("author").unwrap_or("")))
+            .into_bytes());
+        for font in &fonts {
+            objects.push(format!("<< /Type /Font /Subtype /Type1 /BaseFont /{} /Encoding \
+                                  /WinAnsiEncoding >>",
+                                 font.base_font())
+                .into_bytes());
+        }
+        for image in &self.images {
+            let (color_space, decode) = match image.components {
+                1 => ("DeviceGray", ""),
+                4 => ("DeviceCMYK", " /Decode [1 0 1 0 1 0 1 0]"),
+                _ => ("DeviceRGB", ""),
+            };
+            let mut object = format!("<< /Type /XObject /Subtype /Image /Width {} /Height {} \
+                                      /ColorSpace /{} /BitsPerComponent 8 /Filter /DCTDecode{} \
+                                      /Length {} >>\nstream\n",
+                                     image.width,
+                                     image.height,
+                                     color_space,
+                                     decode,
+                                     image.data.len())
+                .into_bytes();
+            object.extend_from_slice(&image.data);
+            object.extend_from_slice(b"\nendstream");
+            objects.push(object);
+        }
+        let font_resources: Vec<String> = fonts.iter()
+            .enumerate()
+            .map(|(i, font)| format!("/{} {} 0 R", font.name(), first_font + i))
+            .collect();
+        let image_resources: Vec<String> = (0..self.images.len())
+            .map(|i| format!("/Im{} {} 0 R", i + 1, first_image + i))
+            .collect();
+        let resources = format!("<< /Font << {} >> /XObject << {} >> >>",
+                                font_resources.join(" "),
+                                image_resources.join(" "));
+        for (i, page) in self.pages.iter().enumerate() {
+            objects.push(format!("<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] \
+                                  /Resources {} /Contents {} 0 R >>",
+                                 PAGE_WIDTH,
+                                 PAGE_HEIGHT,
+                                 resources,
+                                 first_page + 2 * i + 1)
+                .into_bytes());
+            objects.push(format!("<< /Length {} >>\nstream\n{}\nendstream", page.len(), page)
+                .into_bytes());
+        }
+
+        let mut pdf: Vec<u8> = b"%PDF-1.4\n".to_vec();
+        let mut offsets = vec![];
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            pdf.extend_from_slice(format!("{} 0 obj\n", i + 1).as_bytes());
+            pdf.extend_from_slice(object);
+            pdf.extend_from_slice(b"\nendobj\n");
+        }
+        let xref = pdf.len();
+        pdf.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1)
+            .as_bytes());
+        for offset in offsets {
+            pdf.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+        }
+        pdf.extend_from_slice(format!("trailer\n<< /Size {} /Root 1 0 R /Info 3 0 R >>\n\
+                                       startxref\n{}\n%%EOF\n",
+                                      objects.len() + 1,
+                                      xref)
+            .as_bytes());
+        to.write_all(&pdf)
+    }
+}
+
+/// Returns true if a token is displayed inside a line of text
+fn is_inline(token: &Token) -> bool {
+    match *token {
+        Token::Str(_) | Token::Emphasis(_) | Token::Strong(_) | Token::Code(_) |
+        Token::Superscript(_) | Token::Subscript(_) | Token::Abbreviation(..) |
+        Token::Link(..) | Token::Image(..) | Token::Footnote(_) | Token::SoftBreak |
+        Token::HardBreak | Token::Annotation(..) => true,
+        _ => false,
+    }
+}
+
+/// Turns paragraphs into inline content, separated by spaces
+fn flatten_blocks(tokens: &[Token]) -> Vec<Token> {
+    let mut res = vec![];
+    for token in tokens {
+        if is_inline(token) {
+            res.push(token.clone());
+        } else if let Some(inner) = token.inner() {
+            res.append(&mut flatten_blocks(inner));
+            res.push(Token::SoftBreak);
+        }
+    }
+    res
+}
+
+/// Returns the words of tokens, using the same font for all of them
+fn words_of(tokens: &[Token], font: Font) -> Vec<Word> {
+    let mut words = vec![Word::default()];
+    push_text(&view_as_text(tokens), font, &mut words);
+    words.into_iter().filter(|w| !w.fragments.is_empty()).collect()
+}
+
+/// Adds text to a list of words, where the last one is the current word
+fn push_text(text: &str, font: Font, words: &mut Vec<Word>) {
+    for (i, part) in text.split(|c: char| c.is_whitespace()).enumerate() {
+        if i > 0 {
+            words.push(Word::default());
+        }
+        if !part.is_empty() {
+            words.last_mut().unwrap().fragments.push((part.to_owned(), font));
+        }
+    }
+}
+
+/// Returns the content stream drawing a watermark in light gray, either
+/// along the diagonal of the page or centered in its footer
+fn watermark(text: &str, diagonal: bool) -> String {
+    if diagonal {
+        let angle = PAGE_HEIGHT.atan2(PAGE_WIDTH);
+        let (sin, cos) = (angle.sin(), angle.cos());
+        // Long watermarks are made smaller so they fit on the page
+        let max = PAGE_HEIGHT / sin * 0.7;
+        let mut size = 36.0;
+        let width = Font::Regular.width(text, size);
+        if width > max {
+            size *= max / width;
+        }
+        let width = Font::Regular.width(text, size);
+        format!("q 0.85 g BT 0 Tw /F1 {:.2} Tf {:.4} {:.4} {:.4} {:.4} {:.2} {:.2} Tm ({}) Tj ET Q\n",
+                size,
+                cos,
+                sin,
+                -sin,
+                cos,
+                (PAGE_WIDTH - width * cos) / 2.0,
+                (PAGE_HEIGHT - width * sin) / 2.0,
+                encode(text))
+    } else {
+        let size = 8.0;
+        format!("q 0.5 g BT 0 Tw {:.2} {:.2} Td /F1 {:.1} Tf ({}) Tj ET Q\n",
+                (PAGE_WIDTH - Font::Regular.width(text, size)) / 2.0,
+                MARGIN / 4.0,
+                size,
+                encode(text))
+    }
+}
+
+/// Escapes a string as the content of a PDF literal string, in the
+/// Windows-1252 encoding
+fn encode(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    for c in s.chars() {
+        let code: u32 = match c {
+            '(' | ')' | '\\' => {
+                res.push('\\');
+                res.push(c);
+                continue;
+            }
+            ' '...'~' => {
+                res.push(c);
+                continue;
+            }
+            '\u{a0}' | '\u{202f}' => 0xA0,
+            '\u{a1}'...'\u{ff}' => c as u32,
+            '€' => 0x80,
+            '‚' => 0x82,
+            '„' => 0x84,
+            '…' => 0x85,
+            'Œ' => 0x8C,
+            '‘' => 0x91,
+            '’' => 0x92,
+            '“' => 0x93,
+            '”' => 0x94,
+            '•' => 0x95,
+            '–' => 0x96,
+            '—' => 0x97,
+            '™' => 0x99,
+            'œ' => 0x9C,
+            _ => '?' as u32,
+        };
+        if code < 0x80 {
+            res.push(code as u8 as char);
+        } else {
+            res.push_str(&format!("\\{:03o}", code));
+        }
+    }
+    res
+}
+
+/// Reads a JPEG file, and its size and number of color components
+///
+/// Returns `Ok(None)` if the file could be read but is not a valid JPEG file.
+fn read_jpeg(file: &str) -> io::Result<Option<Jpeg>> {
+    let mut data = vec![];
+    File::open(file).and_then(|mut f| f.read_to_end(&mut data))?;
+    if data.len() < 4 || data[0] != 0xFF || data[1] != 0xD8 {
+        return Ok(None);
+    }
+    let mut i = 2;
+    while i + 9 < data.len() {
+        if data[i] != 0xFF {
+            return Ok(None);
+        }
+        let marker = data[i + 1];
+        let len = ((data[i + 2] as usize) << 8) + data[i + 3] as usize;
+        // Start of frame markers, except DHT, JPG and DAC
+        if marker >= 0xC0 && marker <= 0xCF && marker != 0xC4 && marker != 0xC8 &&
+           marker != 0xCC {
+            let height = ((data[i + 5] as u32) << 8) + data[i + 6] as u32;
+            let width = ((data[i + 7] as u32) << 8) + data[i + 8] as u32;
+            let components = data[i + 9];
+            return Ok(Some(Jpeg {
+                data: data,
+                width: width,
+                height: height,
+                components: components,
+            }));
+        }
+        i += 2 + len;
+    }
+    Ok(None)
+}
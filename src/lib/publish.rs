@@ -0,0 +1,294 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Publication of the output files of a book to an S3 bucket, a server
+//! (with SFTP or FTP) or a git branch (e.g. for GitHub Pages).
+
+use book::Book;
+use error::{Error, Result};
+use misc;
+
+use uuid;
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// A place where output files can be published
+pub trait Target {
+    /// Key of the option setting where files are published for this target
+    fn key(&self) -> &'static str;
+
+    /// Publishes the content of `dir` to `target`
+    fn publish(&self, book: &Book, dir: &Path, target: &str) -> Result<()>;
+}
+
+/// Publishes files with a command (set by `publish.{key}.command`)
+struct CommandTarget {
+    key: &'static str,
+}
+
+impl Target for CommandTarget {
+    fn key(&self) -> &'static str {
+        self.key
+    }
+
+    fn publish(&self, book: &Book, dir: &Path, target: &str) -> Result<()> {
+        let key = format!("publish.{}.command", self.key);
+        let dir = format!("{}", dir.display());
+        run_command(book, &key, &[("{dir}", &dir), ("{target}", target)])
+    }
+}
+
+/// Publishes files to an FTP server by uploading them one by one with a
+/// command (set by `publish.ftp.command`)
+struct FtpTarget;
+
+impl Target for FtpTarget {
+    fn key(&self) -> &'static str {
+        "ftp"
+    }
+
+    fn publish(&self, book: &Book, dir: &Path, target: &str) -> Result<()> {
+        let mut files = vec![];
+        list_files(dir, &mut files)
+            .map_err(|e| {
+                Error::default(&book.source,
+                               lformat!("could not list files to publish: {error}", error = e))
+            })?;
+        for file in files {
+            let url = ftp_url(target, file.strip_prefix(dir).unwrap());
+            let file = format!("{}", file.display());
+            run_command(book, "publish.ftp.command", &[("{file}", &file), ("{url}", &url)])?;
+        }
+        Ok(())
+    }
+}
+
+/// Publishes files by force-pushing a commit containing them to a branch
+struct GitTarget;
+
+impl Target for GitTarget {
+    fn key(&self) -> &'static str {
+        "git"
+    }
+
+    fn publish(&self, book: &Book, dir: &Path, branch: &str) -> Result<()> {
+        let remote = book.options.get_str("publish.git.remote").unwrap();
+        let output = Command::new("git")
+            .current_dir(&book.root)
+            .args(&["remote", "get-url", remote])
+            .output()
+            .map_err(|e| {
+                Error::default(&book.source,
+                               lformat!("failed to run git: {error}", error = e))
+            })?;
+        if !output.status.success() {
+            return Err(Error::default(&book.source,
+                                      lformat!("could not find the URL of git remote {remote}",
+                                               remote = remote)));
+        }
+        let url = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+
+        let message = format!("Publish {} ({})",
+                              book.options.get_str("title").unwrap(),
+                              misc::today());
+        run(book, Command::new("git").current_dir(dir).arg("init"))?;
+        run(book, Command::new("git").current_dir(dir).args(&["add", "--all"]))?;
+        run(book, Command::new("git").current_dir(dir).args(&["commit", "-m", &message]))?;
+        run(book,
+            Command::new("git")
+                .current_dir(dir)
+                .args(&["push", "--force", &url, &format!("HEAD:{}", branch)]))
+    }
+}
+
+/// Publishes the output files of the book to all the targets set by
+/// `publish.*` options
+///
+/// The book must already have been rendered. If `publish.base_url` is set,
+/// an OPDS catalog (`catalog.xml`) and the cover are published too.
+pub fn publish(book: &Book) -> Result<()> {
+    let targets = targets(book);
+    if targets.is_empty() {
+        return Err(Error::default(&book.source,
+                                  lformat!("no publishing target: set publish.s3, publish.sftp, \
+                                            publish.ftp or publish.git")));
+    }
+
+    // Gather all files in a temporary directory
+    let dir = Path::new(&book.options.get_path("crowbook.temp_dir").unwrap())
+        .join(format!("crowbook-publish-{}", uuid::Uuid::new_v4().simple()));
+    let result = stage(book, &dir).and_then(|_| {
+        for &(ref target, value) in &targets {
+            book.logger.info(lformat!("Publishing to {target}...", target = value));
+            target.publish(book, &dir, value)?;
+        }
+        Ok(())
+    });
+    if let Err(e) = fs::remove_dir_all(&dir) {
+        book.logger.error(lformat!("could not remove temporary directory {dir}: {error}",
+                                   dir = dir.display(),
+                                   error = e));
+    }
+    result
+}
+
+/// Returns the targets set by `publish.*` options, with the place where
+/// files are published for each of them
+pub fn targets(book: &Book) -> Vec<(Box<Target>, &str)> {
+    let targets: Vec<Box<Target>> = vec![Box::new(CommandTarget { key: "s3" }),
+                                         Box::new(CommandTarget { key: "sftp" }),
+                                         Box::new(FtpTarget),
+                                         Box::new(GitTarget)];
+    targets.into_iter()
+        .filter_map(|t| {
+            let value = book.options.get_str(&format!("publish.{}", t.key())).ok();
+            value.map(|value| (t, value))
+        })
+        .collect()
+}
+
+/// Copies the files to publish in `dir`
+fn stage(book: &Book, dir: &Path) -> Result<()> {
+    let mut files = vec![];
+    // Output files of all formats are published, except proofreading ones
+    for format in book.formats().into_iter().filter(|f| !f.starts_with("proofread.")) {
+        if let Some(path) = book.output_path(format)? {
+            files.push(path);
+        }
+    }
+    if book.options.get_str("publish.base_url").is_ok() {
+        if let Ok(cover) = book.options.get_path("cover") {
+            files.push(PathBuf::from(cover));
+        }
+    }
+    if files.is_empty() {
+        return Err(Error::default(&book.source,
+                                  lformat!("no output file to publish")));
+    }
+
+    fs::create_dir_all(dir)
+        .map_err(|e| {
+            Error::default(&book.source,
+                           lformat!("could not create directory {dir}: {error}",
+                                    dir = dir.display(),
+                                    error = e))
+        })?;
+    for file in &files {
+        let dest = dir.join(file.file_name().unwrap_or_default());
+        copy(file, &dest)
+            .map_err(|e| {
+                Error::default(&book.source,
+                               lformat!("could not copy {file} to publish it: {error}",
+                                        file = file.display(),
+                                        error = e))
+            })?;
+    }
+    if book.options.get_str("publish.base_url").is_ok() {
+        let catalog = book.opds_catalog()?;
+        fs::File::create(dir.join("catalog.xml"))
+            .and_then(|mut f| f.write_all(catalog.as_bytes()))
+            .map_err(|e| {
+                Error::default(&book.source,
+                               lformat!("could not write OPDS catalog: {error}", error = e))
+            })?;
+    }
+    Ok(())
+}
+
+/// Copies a file, or a directory and all its content
+fn copy(from: &Path, to: &Path) -> ::std::io::Result<()> {
+    if from.is_dir() {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        Ok(())
+    } else {
+        fs::copy(from, to).map(|_| ())
+    }
+}
+
+/// Returns the arguments of a command set by an option, replacing the
+/// variables (e.g. `{dir}`) in each of them
+pub fn command_args(command: &str, variables: &[(&str, &str)]) -> Vec<String> {
+    command.split_whitespace()
+        .map(|arg| {
+            variables.iter()
+                .fold(arg.to_owned(), |arg, &(name, value)| arg.replace(name, value))
+        })
+        .collect()
+}
+
+/// Returns the URL where a file (given relatively to the published
+/// directory) is uploaded on an FTP target
+pub fn ftp_url(target: &str, file: &Path) -> String {
+    let components: Vec<_> = file.components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    format!("{}/{}", target.trim_right_matches('/'), components.join("/"))
+}
+
+/// Lists the files in `dir` and its subdirectories, sorted by path
+fn list_files(dir: &Path, files: &mut Vec<PathBuf>) -> ::std::io::Result<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<::std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.path());
+    for entry in entries {
+        let path = entry.path();
+        if path.is_dir() {
+            list_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Runs the command set by the option `key`, with `variables` replaced in
+/// its arguments
+fn run_command(book: &Book, key: &str, variables: &[(&str, &str)]) -> Result<()> {
+    let mut args = command_args(book.options.get_str(key).unwrap(), variables);
+    if args.is_empty() {
+        return Err(Error::default(&book.source,
+                                  lformat!("{key} is empty", key = key)));
+    }
+    let program = args.remove(0);
+    run(book, Command::new(&program).args(&args))
+}
+
+/// Runs a command, returning an error if it fails
+fn run(book: &Book, command: &mut Command) -> Result<()> {
+    let description = format!("{:?}", command);
+    let output = command.output()
+        .map_err(|e| {
+            Error::default(&book.source,
+                           lformat!("failed to run command {command}: {error}",
+                                    command = description,
+                                    error = e))
+        })?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(Error::default(&book.source,
+                           lformat!("command {command} failed:\n{error}",
+                                    command = description,
+                                    error = String::from_utf8_lossy(&output.stderr))))
+    }
+}
@@ -0,0 +1,182 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Conversion of straight double quotes according to their nesting level
+//! (e.g. outer guillemets and inner curly quotes in French), tracked across
+//! the paragraphs of a chapter.
+
+use book::Book;
+use token::Token;
+
+/// Quotation marks of a language, for outer and inner quotes
+struct Marks {
+    outer: (&'static str, &'static str),
+    inner: (&'static str, &'static str),
+    /// In German, `“` is a closing quotation mark
+    german: bool,
+}
+
+impl Marks {
+    fn new(lang: &str) -> Marks {
+        if lang.starts_with("fr") {
+            Marks {
+                outer: ("«\u{a0}", "\u{a0}»"),
+                inner: ("“", "”"),
+                german: false,
+            }
+        } else if lang.starts_with("de") {
+            Marks {
+                outer: ("„", "“"),
+                inner: ("‚", "‘"),
+                german: true,
+            }
+        } else {
+            Marks {
+                outer: ("“", "”"),
+                inner: ("‘", "’"),
+                german: false,
+            }
+        }
+    }
+
+    /// Returns the opening and closing marks for this nesting level
+    fn level(&self, depth: usize) -> (&'static str, &'static str) {
+        if depth % 2 == 0 { self.outer } else { self.inner }
+    }
+
+    fn is_open(&self, c: char) -> bool {
+        c == '«' || c == '„' || (c == '“' && !self.german)
+    }
+
+    fn is_close(&self, c: char) -> bool {
+        c == '»' || c == '”' || (c == '“' && self.german)
+    }
+}
+
+struct State {
+    /// Number of quotes currently open
+    depth: usize,
+    /// True if nothing but spaces has been seen in the current paragraph
+    start: bool,
+    /// Last character seen in the current paragraph
+    previous: Option<char>,
+}
+
+/// Replaces the straight double quotes of a chapter with the quotation marks
+/// matching their nesting level, if `input.clean.smart_quotes` is set
+///
+/// Quotation marks that are already typographic are taken into account for
+/// the nesting level. A quote can span several paragraphs, following the
+/// convention of repeating the opening mark at the start of each paragraph
+/// and closing it only at the end of the last one.
+///
+/// Single quotes are left to the cleaner, as they are mostly apostrophes.
+pub fn nest_quotes(book: &Book, tokens: &mut Vec<Token>) {
+    if !book.options.get_bool("input.clean.smart_quotes").unwrap() {
+        return;
+    }
+    let marks = Marks::new(&book.options.get_str("lang").unwrap().to_lowercase());
+    let mut state = State {
+        depth: 0,
+        start: true,
+        previous: None,
+    };
+    visit(tokens, &marks, &mut state);
+}
+
+fn visit(tokens: &mut Vec<Token>, marks: &Marks, state: &mut State) {
+    for token in tokens {
+        match *token {
+            Token::Str(ref mut s) => {
+                if s.contains('"') || s.contains(|c: char| marks.is_open(c) || marks.is_close(c)) {
+                    *s = convert(s, marks, state);
+                } else if let Some(c) = s.chars().next_back() {
+                    state.start = state.start && s.trim().is_empty();
+                    state.previous = Some(c);
+                }
+            }
+            Token::Code(..) | Token::CodeBlock(..) => {}
+            Token::Header(_, ref mut inner) => {
+                // Quotes can't span over a title
+                let mut header_state = State {
+                    depth: 0,
+                    start: true,
+                    previous: None,
+                };
+                visit(inner, marks, &mut header_state);
+                state.depth = 0;
+            }
+            Token::Paragraph(ref mut inner) => {
+                state.start = true;
+                state.previous = None;
+                visit(inner, marks, state);
+            }
+            ref mut token => {
+                if let Some(inner) = token.inner_mut() {
+                    visit(inner, marks, state);
+                }
+            }
+        }
+    }
+}
+
+fn convert(s: &str, marks: &Marks, state: &mut State) -> String {
+    let mut res = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '"' {
+            let opening = state.previous
+                .map(|p| p.is_whitespace() || "([{—–-/«“„‘\u{a0}\u{202f}".contains(p))
+                .unwrap_or(true);
+            if opening {
+                // Unless it is a quote continued from the previous paragraph
+                if !(state.start && state.depth > 0) {
+                    state.depth += 1;
+                }
+                let mark = marks.level(state.depth - 1).0;
+                res.push_str(mark);
+                if mark.ends_with('\u{a0}') {
+                    while chars.peek().map(|&c| c == ' ').unwrap_or(false) {
+                        chars.next();
+                    }
+                }
+            } else {
+                if state.depth > 0 {
+                    state.depth -= 1;
+                }
+                let mark = marks.level(state.depth).1;
+                if mark.starts_with('\u{a0}') {
+                    let end = res.trim_right_matches(' ').len();
+                    res.truncate(end);
+                }
+                res.push_str(mark);
+            }
+        } else {
+            if marks.is_open(c) && !(state.start && state.depth > 0) {
+                state.depth += 1;
+            } else if marks.is_close(c) && state.depth > 0 {
+                state.depth -= 1;
+            }
+            res.push(c);
+        }
+        if !c.is_whitespace() {
+            state.start = false;
+        }
+        state.previous = Some(c);
+    }
+    res
+}
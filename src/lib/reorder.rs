@@ -0,0 +1,300 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Reordering of the chapters of a book: rewrites the chapter list of its
+//! configuration file, renumbers chapter files and updates the links
+//! between them.
+
+use error::{Error, Result, Source};
+
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// A chapter listed in the configuration file
+struct Entry {
+    /// Index of its line in the configuration file
+    line: usize,
+    /// File name, as written in the configuration file
+    file: String,
+}
+
+/// Chapter list of a book configuration file, that can be reordered
+pub struct Reorder {
+    path: PathBuf,
+    lines: Vec<String>,
+    entries: Vec<Entry>,
+}
+
+impl Reorder {
+    /// Reads the chapter list of a book configuration file
+    ///
+    /// Only chapters (and subchapters) are listed: parts stay where they
+    /// are in the list.
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Reorder> {
+        let path = path.as_ref().to_owned();
+        let content = read_file(&path)?;
+        let lines: Vec<String> = content.lines().map(|s| s.to_owned()).collect();
+        let mut entries = vec![];
+        let mut in_list = false;
+        for (i, line) in lines.iter().enumerate() {
+            // The chapter list starts at the first line that isn't YAML, as
+            // in `Book::read_config`
+            if !in_list &&
+               !line.starts_with(|c| match c {
+                '-' | '+' | '!' | '@' => true,
+                _ => c.is_digit(10),
+            }) {
+                continue;
+            }
+            in_list = true;
+            if let Some(file) = chapter_file(line.trim()) {
+                entries.push(Entry {
+                    line: i,
+                    file: file.to_owned(),
+                });
+            }
+        }
+        Ok(Reorder {
+            path: path,
+            lines: lines,
+            entries: entries,
+        })
+    }
+
+    /// Returns the chapter files, in the order they are listed
+    pub fn files(&self) -> Vec<&str> {
+        self.entries.iter().map(|e| e.file.as_str()).collect()
+    }
+
+    /// Parses a new order, given as a comma-separated list of the positions
+    /// of chapters in the current list (starting at 1) or of their file
+    /// names, e.g. `2,1,3`
+    ///
+    /// Returns the new order as indices in the current list.
+    pub fn parse_order(&self, order: &str) -> Result<Vec<usize>> {
+        let mut res = vec![];
+        for item in order.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+            let index = match item.parse::<usize>() {
+                Ok(n) if n >= 1 && n <= self.entries.len() => n - 1,
+                _ => {
+                    self.entries
+                        .iter()
+                        .position(|e| e.file == item)
+                        .ok_or_else(|| {
+                            Error::default(Source::empty(),
+                                           lformat!("'{item}' is neither the position of a \
+                                                     chapter nor one of its files",
+                                                    item = item))
+                        })?
+                }
+            };
+            if res.contains(&index) {
+                return Err(Error::default(Source::empty(),
+                                          lformat!("chapter '{file}' is listed twice",
+                                                   file = self.entries[index].file)));
+            }
+            res.push(index);
+        }
+        if res.len() != self.entries.len() {
+            return Err(Error::default(Source::empty(),
+                                      lformat!("the new order lists {n} chapter(s), but the \
+                                                book has {total}",
+                                               n = res.len(),
+                                               total = self.entries.len())));
+        }
+        Ok(res)
+    }
+
+    /// Reorders the chapters, given their indices in the current list
+    ///
+    /// Chapter files whose name starts with a number (e.g. `03-setup.md`)
+    /// are renamed according to their new position, and links to them in
+    /// other chapters are updated. Returns the renamed files.
+    pub fn apply(&mut self, order: &[usize]) -> Result<Vec<(String, String)>> {
+        if self.entries.is_empty() {
+            return Ok(vec![]);
+        }
+        let root = self.path.parent().map(|p| p.to_owned()).unwrap_or_default();
+        let old_files: Vec<String> = self.entries.iter().map(|e| e.file.clone()).collect();
+        let old_lines: Vec<String> = self.entries
+            .iter()
+            .map(|e| self.lines[e.line].clone())
+            .collect();
+
+        // Numbering starts at the number of the first chapter (e.g. 0 or 1)
+        let prefixes: Vec<Option<(u32, usize, String)>> = old_files.iter()
+            .map(|f| number_prefix(f))
+            .collect();
+        let start = prefixes[0].as_ref().map(|&(n, _, _)| n).unwrap_or(1);
+        let width = prefixes.iter()
+            .filter_map(|p| p.as_ref().map(|&(_, w, _)| w))
+            .max()
+            .unwrap_or(0);
+
+        let mut renames = vec![];
+        for (position, &index) in order.iter().enumerate() {
+            let old = &old_files[index];
+            let new = match prefixes[index] {
+                Some((_, _, ref rest)) => {
+                    let name = format!("{:0width$}{}",
+                                       start as usize + position,
+                                       rest,
+                                       width = width);
+                    match old.rfind('/') {
+                        Some(i) => format!("{}{}", &old[..i + 1], name),
+                        None => name,
+                    }
+                }
+                None => old.clone(),
+            };
+            let line = &old_lines[index];
+            let slot = self.entries[position].line;
+            self.lines[slot] = match line.rfind(old.as_str()) {
+                Some(i) => format!("{}{}{}", &line[..i], new, &line[i + old.len()..]),
+                None => line.clone(),
+            };
+            self.entries[position].file = new.clone();
+            if &new != old {
+                renames.push((old.clone(), new));
+            }
+        }
+
+        // Rename files in two steps, since a new name can be the old name of
+        // another chapter
+        for &(ref old, _) in &renames {
+            rename(&root.join(old), &root.join(format!("{}.crowbook-reorder", old)))?;
+        }
+        for &(ref old, ref new) in &renames {
+            rename(&root.join(format!("{}.crowbook-reorder", old)), &root.join(new))?;
+        }
+
+        for entry in &self.entries {
+            let path = root.join(&entry.file);
+            let content = read_file(&path)?;
+            let updated = update_links(&content, &entry.file, &renames);
+            if updated != content {
+                write_file(&path, &updated)?;
+            }
+        }
+        let mut config = self.lines.join("\n");
+        config.push('\n');
+        write_file(&self.path, &config)?;
+        Ok(renames)
+    }
+}
+
+/// Returns the file of a chapter line of the configuration file, or `None`
+/// if it isn't a chapter (e.g. a part or a comment)
+fn chapter_file(line: &str) -> Option<&str> {
+    let rest = if line.starts_with('+') || line.starts_with('-') || line.starts_with('!') {
+        line.trim_left_matches('-').trim_left_matches(|c: char| c == '+' || c == '!')
+    } else if line.starts_with(|c: char| c.is_digit(10)) {
+        match line.find(|c: char| c == '.' || c == ':' || c == '+') {
+            Some(i) => &line[i + 1..],
+            None => return None,
+        }
+    } else {
+        return None;
+    };
+    let rest = rest.trim();
+    if rest.is_empty() || rest.contains(char::is_whitespace) {
+        None
+    } else {
+        Some(rest)
+    }
+}
+
+/// Returns the number a file name starts with, the number of its digits and
+/// the rest of the file name, e.g. `(3, 2, "-setup.md")` for
+/// `chapters/03-setup.md`
+fn number_prefix(file: &str) -> Option<(u32, usize, String)> {
+    let name = match file.rfind('/') {
+        Some(i) => &file[i + 1..],
+        None => file,
+    };
+    let digits = name.chars().take_while(|c| c.is_digit(10)).count();
+    if digits == 0 || digits == name.len() {
+        return None;
+    }
+    name[..digits].parse::<u32>().ok().map(|n| (n, digits, name[digits..].to_owned()))
+}
+
+/// Updates the links to renamed chapters in the content of a chapter
+///
+/// Links are written relatively to the directory of the chapter (e.g.
+/// `[see setup](03-setup.md#install)`).
+fn update_links(content: &str, file: &str, renames: &[(String, String)]) -> String {
+    let dir = match file.rfind('/') {
+        Some(i) => &file[..i + 1],
+        None => "",
+    };
+    // Links are first replaced with placeholders, so a link isn't updated
+    // twice when a new name is the old name of another chapter
+    let mut content = content.to_owned();
+    let mut replaced = vec![];
+    for (i, &(ref old, ref new)) in renames.iter().enumerate() {
+        if !old.starts_with(dir) || !new.starts_with(dir) {
+            continue;
+        }
+        let (old, new) = (&old[dir.len()..], &new[dir.len()..]);
+        let placeholder = format!("\u{0}crowbook-link-{}\u{0}", i);
+        for end in &[")", "#"] {
+            content = content.replace(&format!("]({}{}", old, end),
+                                      &format!("]({}{}", placeholder, end));
+        }
+        replaced.push((placeholder, new));
+    }
+    for (placeholder, new) in replaced {
+        content = content.replace(&placeholder, new);
+    }
+    content
+}
+
+fn read_file(path: &Path) -> Result<String> {
+    let mut content = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut content))
+        .map_err(|e| {
+            Error::default(Source::empty(),
+                           lformat!("could not read {file}: {error}",
+                                    file = path.display(),
+                                    error = e))
+        })?;
+    Ok(content)
+}
+
+fn write_file(path: &Path, content: &str) -> Result<()> {
+    File::create(path)
+        .and_then(|mut f| f.write_all(content.as_bytes()))
+        .map_err(|e| {
+            Error::default(Source::empty(),
+                           lformat!("could not write {file}: {error}",
+                                    file = path.display(),
+                                    error = e))
+        })
+}
+
+fn rename(from: &Path, to: &Path) -> Result<()> {
+    fs::rename(from, to).map_err(|e| {
+        Error::default(Source::empty(),
+                       lformat!("could not rename {from} to {to}: {error}",
+                                from = from.display(),
+                                to = to.display(),
+                                error = e))
+    })
+}
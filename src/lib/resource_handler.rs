@@ -24,6 +24,8 @@ pub struct ResourceHandler<'r> {
     map_images: bool,
     logger: &'r Logger,
     base64: bool,
+    inline_limit: Option<u64>,
+    fragments: bool,
 
     /// Maps an original (local) file name to a new file name. Allows to
     /// make sure all image files will be included in e.g. the Epub document.
@@ -39,6 +41,8 @@ impl<'r> ResourceHandler<'r> {
             images: HashMap::new(),
             map_images: false,
             base64: false,
+            inline_limit: None,
+            fragments: true,
             logger: logger,
         }
     }
@@ -59,6 +63,21 @@ impl<'r> ResourceHandler<'r> {
         self.base64 = b;
     }
 
+    /// Sets the maximal size, in bytes, of images that are included as
+    /// base64 even if base64 mode is not set
+    pub fn set_inline_limit(&mut self, limit: Option<u64>) {
+        self.inline_limit = limit;
+    }
+
+    /// Sets whether fragments of links to another chapter (e.g. `setup.md#install`)
+    /// are kept in the destination link (default: true)
+    ///
+    /// If set to false, such links point to the chapter itself, unless the fragment
+    /// is a known link (e.g. the id of a numbered listing).
+    pub fn set_fragments(&mut self, b: bool) {
+        self.fragments = b;
+    }
+
     /// Add a local image file and get the resulting transformed
     /// file name
     pub fn map_image<'a, S: Into<Cow<'a, str>>>(&'a mut self,
@@ -92,7 +111,11 @@ impl<'r> ResourceHandler<'r> {
 
         // Else, create a new file name that has same extension
         // (or a base64 version of the file)
-        let dest_file = if !(self.base64) {
+        let inline = self.base64 ||
+                     self.inline_limit.map_or(false, |limit| {
+            fs::metadata(file.as_ref()).map(|m| m.len() <= limit).unwrap_or(false)
+        });
+        let dest_file = if !inline {
             if let Some(extension) = Path::new(file.as_ref()).extension() {
                 format!("images/image_{}.{}",
                         self.images.len(),
@@ -146,41 +169,67 @@ impl<'r> ResourceHandler<'r> {
         self.links.insert(from.into(), to.into());
     }
 
+    /// Add a match between the source file of a chapter and a dest file
+    ///
+    /// If the file was split into several chapters, links to it point to
+    /// the first one.
+    pub fn add_chapter_link<S1: Into<String>, S2:Into<String>>(&mut self, from: S1, to: S2) {
+        self.links.entry(from.into()).or_insert(to.into());
+    }
+
     /// Get a destination link from an original link
-    pub fn get_link<'a>(&'a self, from: &'a str) -> &'a str {
-        if let Some(link) = self.links.get(from) {
-            link
-        } else {
-            // Try to get a link by changing the extension
-            let new_from = format!("{}", Path::new(from)
-                .with_extension("md")
-                .display());
-            if let Some(link) = self.links.get(&new_from) {
-                link
+    ///
+    /// Links to a part of another chapter (e.g. `setup.md#install`) point to
+    /// the destination of the fragment if it is a known link, else to the
+    /// destination of the chapter followed by the fragment.
+    pub fn get_link<'a>(&'a self, from: &'a str) -> Cow<'a, str> {
+        if let Some(link) = self.find_link(from) {
+            return Cow::Borrowed(link);
+        }
+        if let Some((chapter, fragment)) = self.split_fragment(from) {
+            return if let Some(link) = self.links.get(fragment) {
+                Cow::Borrowed(link.as_str())
+            } else if self.fragments {
+                // Replace the fragment of the chapter destination, if any
+                let base = chapter.split('#').next().unwrap();
+                Cow::Owned(format!("{}{}", base, fragment))
             } else {
-                self.logger.warning(lformat!("Resources: could not find an in-book match for link \
-                                        {file}",
-                                             file = from));
-                from
-            }
+                Cow::Borrowed(chapter)
+            };
         }
+        self.logger.warning(lformat!("Resources: could not find an in-book match for link \
+                                      {file}",
+                                     file = from));
+        Cow::Borrowed(from)
     }
 
 
     /// Tell whether a file name is a local resource or net
     pub fn contains_link(&self, from: &str) -> bool {
-        if self.links.contains_key(from) {
-            true
+        self.find_link(from).is_some() || self.split_fragment(from).is_some()
+    }
+
+    /// Get the destination of a link, exactly or by changing its extension
+    fn find_link<'a>(&'a self, from: &str) -> Option<&'a str> {
+        if let Some(link) = self.links.get(from) {
+            Some(link.as_str())
         } else {
             // Try to get a link by changing the extension
             let new_from = format!("{}", Path::new(from)
                 .with_extension("md")
                 .display());
-            if self.links.contains_key(&new_from) {
-                true
-            } else {
-                false
+            self.links.get(&new_from).map(|link| link.as_str())
+        }
+    }
+
+    /// Splits a link to a part of another chapter, returning the destination of
+    /// the chapter and the fragment (starting with '#')
+    fn split_fragment<'a>(&'a self, from: &'a str) -> Option<(&'a str, &'a str)> {
+        match from.find('#') {
+            Some(pos) if pos > 0 => {
+                self.find_link(&from[..pos]).map(|chapter| (chapter, &from[pos..]))
             }
+            _ => None,
         }
     }
     
@@ -197,14 +246,15 @@ impl<'r> ResourceHandler<'r> {
         for mut token in ast {
             match *token {
                 Token::Link(ref mut url, _, ref mut v) => {
-                    if ResourceHandler::is_local(url) {
+                    // Anchors in the same file must be left as is
+                    if ResourceHandler::is_local(url) && !url.starts_with('#') {
                         let new_url = format!("{}", link_offset.join(&url).display());
                         *url = new_url;
                     }
                     Self::add_offset(link_offset, image_offset, v);
                 }
-                Token::Image(ref mut url, _, ref mut v) |
-                Token::StandaloneImage(ref mut url, _, ref mut v) => {
+                Token::Image(ref mut url, _, _, ref mut v) |
+                Token::StandaloneImage(ref mut url, _, _, ref mut v) => {
                     if ResourceHandler::is_local(url) {
                         let new_url = format!("{}", image_offset.join(&url).display());
                         *url = new_url;
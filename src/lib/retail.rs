@@ -0,0 +1,238 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Export of the metadata of a book for retailers, as an ONIX 3.0 record or
+//! as a CSV file following the fields of Kindle Direct Publishing, so
+//! listings use the same metadata as the ebook files.
+
+use book::Book;
+use error::{Error, Result};
+use misc;
+use opds;
+
+use crowbook_text_processing::escape;
+
+use std::fmt::Write;
+
+/// ISO 639-2/B codes (used by ONIX) of common languages
+static LANGUAGES: &'static [(&'static str, &'static str)] =
+    &[("ar", "ara"), ("ca", "cat"), ("cs", "cze"), ("da", "dan"), ("de", "ger"), ("el", "gre"),
+      ("en", "eng"), ("eo", "epo"), ("es", "spa"), ("fi", "fin"), ("fr", "fre"), ("it", "ita"),
+      ("ja", "jpn"), ("nb", "nor"), ("nl", "dut"), ("no", "nor"), ("pl", "pol"), ("pt", "por"),
+      ("ru", "rus"), ("sv", "swe"), ("tr", "tur"), ("zh", "chi")];
+
+/// Returns the metadata of the book in one of the supported formats
+/// (`onix` or `kdp`)
+pub fn export(book: &Book, format: &str) -> Result<String> {
+    match format {
+        "onix" => onix(book),
+        "kdp" => kdp_csv(book),
+        _ => {
+            Err(Error::default(&book.source,
+                               lformat!("unknown metadata format '{format}', expected 'onix' \
+                                         or 'kdp'",
+                                        format = format)))
+        }
+    }
+}
+
+/// Returns an ONIX 3.0 message describing the EPUB edition of the book
+pub fn onix(book: &Book) -> Result<String> {
+    let options = &book.options;
+    let id = opds::book_id(book);
+    let publisher = options.get_str("retail.publisher")
+        .unwrap_or_else(|_| options.get_str("author").unwrap());
+
+    let mut product = String::new();
+    write!(product, "    <RecordReference>{}</RecordReference>
+    <NotificationType>03</NotificationType>\n", id)?;
+    match options.get_str("retail.isbn") {
+        Ok(isbn) => {
+            write!(product, "    <ProductIdentifier>
+      <ProductIDType>15</ProductIDType>
+      <IDValue>{}</IDValue>
+    </ProductIdentifier>\n",
+                   escape::html(isbn.replace('-', "")))?
+        }
+        Err(_) => {
+            write!(product, "    <ProductIdentifier>
+      <ProductIDType>01</ProductIDType>
+      <IDValue>{}</IDValue>
+    </ProductIdentifier>\n",
+                   id)?
+        }
+    }
+
+    // Descriptive detail: form, title, author, language and subjects
+    let subtitle = match options.get_str("subtitle") {
+        Ok(subtitle) => format!("\n          <Subtitle>{}</Subtitle>", escape::html(subtitle)),
+        Err(_) => String::new(),
+    };
+    let bio = match options.get_str("author.bio") {
+        Ok(bio) => format!("\n        <BiographicalNote>{}</BiographicalNote>", escape::html(bio)),
+        Err(_) => String::new(),
+    };
+    write!(product, "    <DescriptiveDetail>
+      <ProductComposition>00</ProductComposition>
+      <ProductForm>ED</ProductForm>
+      <ProductFormDetail>E101</ProductFormDetail>
+      <TitleDetail>
+        <TitleType>01</TitleType>
+        <TitleElement>
+          <TitleElementLevel>01</TitleElementLevel>
+          <TitleText>{title}</TitleText>{subtitle}
+        </TitleElement>
+      </TitleDetail>
+      <Contributor>
+        <SequenceNumber>1</SequenceNumber>
+        <ContributorRole>A01</ContributorRole>
+        <PersonName>{author}</PersonName>{bio}
+      </Contributor>\n",
+           title = escape::html(options.get_str("title").unwrap()),
+           subtitle = subtitle,
+           author = escape::html(options.get_str("author").unwrap()),
+           bio = bio)?;
+    let lang = options.get_str("lang").unwrap();
+    match language_code(lang) {
+        Some(code) => {
+            write!(product, "      <Language>
+        <LanguageRole>01</LanguageRole>
+        <LanguageCode>{}</LanguageCode>
+      </Language>\n",
+                   code)?
+        }
+        None => {
+            book.logger.warning(lformat!("ONIX: no ISO 639-2 code known for language '{lang}', \
+                                          it won't be listed",
+                                         lang = lang))
+        }
+    }
+    if let Ok(categories) = options.get_str_vec("retail.categories") {
+        for (i, category) in categories.iter().enumerate() {
+            write!(product, "      <Subject>{main}
+        <SubjectSchemeIdentifier>10</SubjectSchemeIdentifier>
+        <SubjectCode>{code}</SubjectCode>
+      </Subject>\n",
+                   main = if i == 0 { "\n        <MainSubject/>" } else { "" },
+                   code = escape::html(category.as_str()))?;
+        }
+    }
+    if let Ok(keywords) = options.get_str_vec("retail.keywords") {
+        write!(product, "      <Subject>
+        <SubjectSchemeIdentifier>20</SubjectSchemeIdentifier>
+        <SubjectHeadingText>{}</SubjectHeadingText>
+      </Subject>\n",
+               escape::html(keywords.join("; ")))?;
+    }
+    product.push_str("    </DescriptiveDetail>\n");
+
+    if let Ok(description) = options.get_str("description") {
+        write!(product, "    <CollateralDetail>
+      <TextContent>
+        <TextType>03</TextType>
+        <ContentAudience>00</ContentAudience>
+        <Text>{}</Text>
+      </TextContent>
+    </CollateralDetail>\n",
+               escape::html(description))?;
+    }
+
+    let date = match options.get_str("date").ok().and_then(onix_date) {
+        Some(date) => {
+            format!("
+      <PublishingDate>
+        <PublishingDateRole>01</PublishingDateRole>
+        <Date>{}</Date>
+      </PublishingDate>",
+                    date)
+        }
+        None => String::new(),
+    };
+    write!(product, "    <PublishingDetail>
+      <Publisher>
+        <PublishingRole>01</PublishingRole>
+        <PublisherName>{publisher}</PublisherName>
+      </Publisher>{date}
+    </PublishingDetail>\n",
+           publisher = escape::html(publisher),
+           date = date)?;
+
+    Ok(format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<ONIXMessage release=\"3.0\" xmlns=\"http://ns.editeur.org/onix/3.0/reference\">
+  <Header>
+    <Sender>
+      <SenderName>{sender}</SenderName>
+    </Sender>
+    <SentDateTime>{today}</SentDateTime>
+  </Header>
+  <Product>
+{product}  </Product>
+</ONIXMessage>
+",
+               sender = escape::html(publisher),
+               today = misc::today().replace('-', ""),
+               product = product))
+}
+
+/// Returns a CSV file with the metadata fields asked by Kindle Direct
+/// Publishing, so they can be copied (or imported by tools) from it
+pub fn kdp_csv(book: &Book) -> Result<String> {
+    let options = &book.options;
+    let keywords = options.get_str_vec("retail.keywords").map(|k| k.join("; ")).unwrap_or_default();
+    let categories = options.get_str_vec("retail.categories")
+        .map(|c| c.join("; "))
+        .unwrap_or_default();
+    let fields = [("Language", options.get_str("lang").unwrap().to_owned()),
+                  ("Title", options.get_str("title").unwrap().to_owned()),
+                  ("Subtitle", options.get_str("subtitle").unwrap_or("").to_owned()),
+                  ("Author", options.get_str("author").unwrap().to_owned()),
+                  ("Description", options.get_str("description").unwrap_or("").to_owned()),
+                  ("Keywords", keywords),
+                  ("Categories", categories),
+                  ("ISBN", options.get_str("retail.isbn").unwrap_or("").to_owned()),
+                  ("Publisher", options.get_str("retail.publisher").unwrap_or("").to_owned()),
+                  ("Publication date", options.get_str("date").unwrap_or("").to_owned())];
+    let header: Vec<String> = fields.iter().map(|&(name, _)| csv_field(name)).collect();
+    let values: Vec<String> = fields.iter().map(|&(_, ref value)| csv_field(value)).collect();
+    Ok(format!("{}\r\n{}\r\n", header.join(","), values.join(",")))
+}
+
+/// Returns the ISO 639-2/B code of a language, e.g. `fre` for `fr` or `fr_FR`
+fn language_code(lang: &str) -> Option<&'static str> {
+    let lang = lang.to_lowercase();
+    let lang = lang.split(|c| c == '_' || c == '-').next().unwrap_or("");
+    LANGUAGES.iter()
+        .find(|&&(code, _)| code == lang)
+        .map(|&(_, code)| code)
+}
+
+/// Converts a date such as `2017-05-12` to the format used by ONIX
+/// (`20170512`), or returns `None` if it isn't a date in this format
+fn onix_date(date: &str) -> Option<String> {
+    let date = date.trim();
+    let digits: String = date.chars().filter(|&c| c != '-').collect();
+    if date.len() == 10 && digits.len() == 8 && digits.chars().all(|c| c.is_digit(10)) {
+        Some(digits)
+    } else {
+        None
+    }
+}
+
+/// Quotes a field of a CSV file
+fn csv_field(s: &str) -> String {
+    format!("\"{}\"", s.replace('"', "\"\""))
+}
@@ -0,0 +1,903 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Parsing of reStructuredText chapters (`.rst` files), e.g. to migrate a
+//! book written with Sphinx.
+
+use error::{Error, Result, Source};
+use token::Token;
+use parser::Features;
+use images::ImageOptions;
+
+use std::cmp;
+use std::iter;
+use std::mem;
+use std::collections::HashMap;
+
+/// Directives that are converted to admonitions, with the kind of admonition
+static ADMONITIONS: &'static [(&'static str, &'static str)] = &[("note", "note"),
+                                                                ("seealso", "note"),
+                                                                ("tip", "tip"),
+                                                                ("hint", "tip"),
+                                                                ("important", "important"),
+                                                                ("warning", "warning"),
+                                                                ("attention", "warning"),
+                                                                ("caution", "caution"),
+                                                                ("danger", "caution"),
+                                                                ("error", "caution")];
+
+/// Roles whose content is displayed as code
+static CODE_ROLES: &'static [&'static str] = &["literal", "code", "file", "samp", "command",
+                                               "program", "kbd", "envvar", "option", "regexp",
+                                               "func", "meth", "class", "mod", "attr", "data",
+                                               "obj", "exc", "const"];
+
+/// A parser that reads reStructuredText and converts it to the same AST as
+/// the Markdown `Parser`
+///
+/// Only the most common elements are supported: section titles,
+/// paragraphs, inline markup and roles, bullet and enumerated lists,
+/// block quotes, literal and line blocks, transitions, footnotes,
+/// hyperlinks, and the `code-block`, `image`, `figure`, `epigraph` and
+/// admonition directives. Other directives are ignored with a warning.
+///
+/// # Examples
+///
+/// ```
+/// use crowbook::RstParser;
+/// let mut parser = RstParser::new();
+/// let result = parser.parse("Title\n=====\n\nSome *valid* reStructuredText [#]_.\n\n.. [#] A footnote");
+/// assert!(result.is_ok());
+/// ```
+pub struct RstParser {
+    source: Source,
+    features: Features,
+
+    /// Adornments of section titles (their character, and whether they have
+    /// an overline), in the order they appear, which gives their level
+    title_styles: Vec<(char, bool)>,
+    /// Content of footnotes, by label (e.g. `1` or `#name`)
+    footnotes: HashMap<String, Vec<String>>,
+    /// Content of auto-numbered footnotes (`[#]` or `[*]`), in the order of
+    /// their definitions
+    auto_footnotes: Vec<Vec<String>>,
+    /// Number of references to auto-numbered footnotes found so far
+    auto_count: usize,
+    /// URLs of hyperlink targets (`.. _name: url`), by normalized name
+    targets: HashMap<String, String>,
+    /// Warnings emitted while parsing
+    warnings: Vec<String>,
+}
+
+impl RstParser {
+    /// Creates a parser
+    pub fn new() -> RstParser {
+        RstParser {
+            source: Source::empty(),
+            features: Features::new(),
+            title_styles: vec![],
+            footnotes: HashMap::new(),
+            auto_footnotes: vec![],
+            auto_count: 0,
+            targets: HashMap::new(),
+            warnings: vec![],
+        }
+    }
+
+    /// Sets a parser's source file
+    pub fn set_source_file(&mut self, s: &str) {
+        self.source = Source::new(s);
+    }
+
+    /// Returns the list of features used by this parser
+    pub fn features(&self) -> Features {
+        self.features
+    }
+
+    /// Returns the warnings emitted while parsing since the last call
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        mem::replace(&mut self.warnings, vec![])
+    }
+
+    /// Parse a string and returns an AST or an Error.
+    pub fn parse(&mut self, s: &str) -> Result<Vec<Token>> {
+        let lines: Vec<String> = s.lines()
+            .map(|line| line.replace('\t', "        ").trim_right().to_owned())
+            .collect();
+        self.title_styles.clear();
+        self.footnotes.clear();
+        self.auto_footnotes.clear();
+        self.auto_count = 0;
+        self.targets.clear();
+
+        self.find_definitions(&lines);
+        self.parse_blocks(&lines)
+    }
+
+    /// Collects the footnotes and hyperlink targets of the document, which
+    /// can be referenced before they are defined
+    fn find_definitions(&mut self, lines: &[String]) {
+        let mut i = 0;
+        while i < lines.len() {
+            if !lines[i].starts_with(".. ") {
+                i += 1;
+                continue;
+            }
+            let end = block_end(lines, i + 1, 1);
+            let first = lines[i][3..].trim();
+            if first.starts_with('[') {
+                if let Some(close) = first.find(']') {
+                    let label = &first[1..close];
+                    if is_footnote_label(label) {
+                        let mut content = vec![first[close + 1..].trim().to_owned()];
+                        content.extend(dedent(&lines[i + 1..end]));
+                        if label == "#" || label == "*" {
+                            self.auto_footnotes.push(content);
+                        } else {
+                            self.footnotes.insert(label.to_owned(), content);
+                        }
+                    }
+                }
+            } else if first.starts_with('_') && !first.starts_with("__") {
+                // Names containing colons are quoted with backquotes
+                let name = &first[1..];
+                let colon = if name.starts_with('`') {
+                    name.find("`:").map(|n| n + 1)
+                } else {
+                    name.find(':')
+                };
+                if let Some(colon) = colon {
+                    let mut url = name[colon + 1..].trim().to_owned();
+                    for line in &lines[i + 1..end] {
+                        url.push_str(line.trim());
+                    }
+                    if !url.is_empty() {
+                        self.targets.insert(normalize_name(&name[..colon]), url);
+                    }
+                }
+            }
+            i = end;
+        }
+    }
+
+    /// Parses a list of lines, with no common indentation, as blocks
+    fn parse_blocks(&mut self, lines: &[String]) -> Result<Vec<Token>> {
+        let mut res = vec![];
+        let mut i = 0;
+        while i < lines.len() {
+            let line = lines[i].as_str();
+            if line.is_empty() {
+                i += 1;
+                continue;
+            }
+            let next = lines.get(i + 1).map(|s| s.as_str()).unwrap_or("");
+
+            if indent(line) > 0 {
+                // Indented block
+                let end = block_end(lines, i, 1);
+                let content = dedent(&lines[i..end]);
+                let (quote, attribution) = split_attribution(&content);
+                let mut tokens = self.parse_blocks(quote)?;
+                if let Some(attribution) = attribution {
+                    tokens.push(Token::Attribution(self.parse_inline(&attribution)?));
+                }
+                self.features.blockquote = true;
+                res.push(Token::BlockQuote(tokens));
+                i = end;
+            } else if line == ".." || line.starts_with(".. ") {
+                let end = block_end(lines, i + 1, 1);
+                self.parse_explicit(&lines[i..end], &mut res)?;
+                i = end;
+            } else if is_table_border(line) {
+                let mut end = i;
+                while end < lines.len() && !lines[end].is_empty() {
+                    end += 1;
+                }
+                self.warnings.push(lformat!("in {file}, tables are not supported in \
+                                             reStructuredText, keeping it as a literal block",
+                                            file = self.source));
+                self.features.codeblock = true;
+                res.push(Token::CodeBlock(String::new(),
+                                          vec![Token::Str(format!("{}\n",
+                                                                  lines[i..end].join("\n")))]));
+                i = end;
+            } else if is_adornment(line) && !next.is_empty() &&
+                      lines.get(i + 2).map_or(false, |l| l == line) {
+                // Section title with an overline
+                let level = self.title_level(line, true);
+                res.push(Token::Header(level, self.parse_inline(next.trim())?));
+                i += 3;
+            } else if is_adornment(line) && line.len() >= 4 && next.is_empty() {
+                // Transition
+                res.push(Token::Rule);
+                i += 1;
+            } else if is_adornment(next) {
+                // Section title with an underline
+                let level = self.title_level(next, false);
+                res.push(Token::Header(level, self.parse_inline(line)?));
+                i += 2;
+            } else if line == "|" || line.starts_with("| ") {
+                // Line block, rendered as verse
+                let mut stanzas = vec![];
+                let mut stanza = vec![];
+                while i < lines.len() && (lines[i] == "|" || lines[i].starts_with("| ")) {
+                    let text = if lines[i] == "|" { "" } else { &lines[i][2..] };
+                    if text.trim().is_empty() {
+                        if !stanza.is_empty() {
+                            stanzas.push(Token::Paragraph(mem::replace(&mut stanza, vec![])));
+                        }
+                    } else {
+                        if !stanza.is_empty() {
+                            stanza.push(Token::HardBreak);
+                        }
+                        let trimmed = text.trim_left();
+                        let indent = (text.len() - trimmed.len() + 1) / 2;
+                        if indent > 0 {
+                            stanza.push(Token::Str(iter::repeat('\u{2003}').take(indent).collect()));
+                        }
+                        stanza.extend(self.parse_inline(trimmed)?);
+                    }
+                    i += 1;
+                }
+                if !stanza.is_empty() {
+                    stanzas.push(Token::Paragraph(stanza));
+                }
+                res.push(Token::Verse(stanzas));
+            } else if let Some((number, _)) = list_item(line) {
+                let ordered = number.is_some();
+                let mut items = vec![];
+                while i < lines.len() {
+                    let col = match list_item(&lines[i]) {
+                        Some((n, col)) if n.is_some() == ordered => col,
+                        _ => break,
+                    };
+                    let end = block_end(lines, i + 1, 1);
+                    let mut item_lines = vec![lines[i][col..].to_owned()];
+                    item_lines.extend(unindent(&lines[i + 1..end], col));
+                    let mut content = self.parse_blocks(&item_lines)?;
+                    // Like tight lists in Markdown, an item containing a
+                    // single paragraph only contains its text
+                    let paragraph = if content.len() == 1 {
+                        match content[0] {
+                            Token::Paragraph(ref mut inline) => Some(mem::replace(inline, vec![])),
+                            _ => None,
+                        }
+                    } else {
+                        None
+                    };
+                    items.push(Token::Item(paragraph.unwrap_or(content)));
+                    i = end;
+                    while i < lines.len() && lines[i].is_empty() {
+                        i += 1;
+                    }
+                }
+                match number {
+                    Some(n) => {
+                        self.features.ordered_list = true;
+                        res.push(Token::OrderedList(n, items));
+                    }
+                    None => res.push(Token::List(items)),
+                }
+            } else {
+                // Paragraph
+                let mut end = i + 1;
+                while end < lines.len() && !lines[end].is_empty() && indent(&lines[end]) == 0 {
+                    end += 1;
+                }
+                let mut text = lines[i..end].join("\n");
+                // A paragraph ending with `::` is followed by a literal block
+                let literal = text.ends_with("::");
+                if literal {
+                    let len = if text == "::" || text.ends_with(" ::") {
+                        text.trim_right_matches(':').trim_right().len()
+                    } else {
+                        text.len() - 1
+                    };
+                    text.truncate(len);
+                } else if end == i + 1 && end < lines.len() && !lines[end].is_empty() {
+                    // Definition list: the term is followed by its indented definition
+                    let def_end = block_end(lines, end, 1);
+                    res.push(Token::Paragraph(vec![Token::Strong(self.parse_inline(&text)?)]));
+                    let definition = self.parse_blocks(&dedent(&lines[end..def_end]))?;
+                    res.extend(definition);
+                    i = def_end;
+                    continue;
+                }
+                if !text.is_empty() {
+                    res.push(Token::Paragraph(self.parse_inline(&text)?));
+                }
+                i = end;
+                if literal {
+                    while i < lines.len() && lines[i].is_empty() {
+                        i += 1;
+                    }
+                    if i < lines.len() && indent(&lines[i]) > 0 {
+                        let end = block_end(lines, i, 1);
+                        let code = format!("{}\n", dedent(&lines[i..end]).join("\n"));
+                        self.features.codeblock = true;
+                        res.push(Token::CodeBlock(String::new(), vec![Token::Str(code)]));
+                        i = end;
+                    }
+                }
+            }
+        }
+        Ok(res)
+    }
+
+    /// Returns the level of a section title, given its adornment
+    fn title_level(&mut self, adornment: &str, overline: bool) -> i32 {
+        let style = (adornment.chars().next().unwrap(), overline);
+        let pos = match self.title_styles.iter().position(|s| *s == style) {
+            Some(pos) => pos,
+            None => {
+                self.title_styles.push(style);
+                self.title_styles.len() - 1
+            }
+        };
+        cmp::min(pos as i32 + 1, 6)
+    }
+
+    /// Parses an explicit markup block (starting with `..`): a directive,
+    /// a comment, a footnote or a hyperlink target
+    fn parse_explicit(&mut self, lines: &[String], res: &mut Vec<Token>) -> Result<()> {
+        let first = lines[0][2..].trim();
+        // Footnotes and hyperlink targets are collected before parsing blocks
+        if first.starts_with('[') || first.starts_with('_') {
+            return Ok(());
+        }
+        let pos = match first.find("::") {
+            Some(pos) => pos,
+            None => return Ok(()), // comment
+        };
+        let name = first[..pos].trim();
+        if name.is_empty() || name.contains(' ') {
+            if name.starts_with('|') {
+                self.warnings.push(lformat!("in {file}, ignoring unsupported \
+                                             reStructuredText substitution '{substitution}'",
+                                            file = self.source,
+                                            substitution = name));
+            }
+            return Ok(());
+        }
+        let args = first[pos + 2..].trim();
+
+        // The options of a directive (e.g. `:alt: text`) come before its content
+        let body = dedent(&lines[1..]);
+        let mut options = HashMap::new();
+        let mut start = 0;
+        while start < body.len() && body[start].starts_with(':') {
+            let option = &body[start][1..];
+            match option.find(':') {
+                Some(end) => {
+                    options.insert(option[..end].to_owned(), option[end + 1..].trim().to_owned())
+                }
+                None => break,
+            };
+            start += 1;
+        }
+        let content = trim_blank(&body[start..]);
+        // The arguments of some directives are the start of their content
+        let mut with_args = vec![];
+        if !args.is_empty() {
+            with_args.push(args.to_owned());
+        }
+        with_args.extend(content.iter().cloned());
+
+        match name {
+            "code-block" | "code" | "sourcecode" => {
+                self.features.codeblock = true;
+                res.push(Token::CodeBlock(args.to_owned(),
+                                          vec![Token::Str(format!("{}\n", content.join("\n")))]));
+            }
+            "image" | "figure" => {
+                self.features.image = true;
+                let mut attributes = vec![];
+                if let Some(width) = options.get("width") {
+                    attributes.push(format!("width={}", width));
+                }
+                if let Some(align) = options.get("align") {
+                    attributes.push(format!("align={}", align));
+                }
+                let mut attributes = attributes.join(" ");
+                if ImageOptions::parse(&attributes).is_none() {
+                    self.warnings.push(lformat!("in {file}, ignoring unsupported options \
+                                                 of image '{image}'",
+                                                file = self.source,
+                                                image = args));
+                    attributes = String::new();
+                }
+                let alt = match options.get("alt") {
+                    Some(alt) => vec![Token::Str(alt.clone())],
+                    None => vec![],
+                };
+                // The caption of a figure is its first paragraph, and the
+                // following ones are its legend
+                let caption_end = content.iter().position(|l| l.is_empty()).unwrap_or(content.len());
+                let caption = if name == "figure" {
+                    content[..caption_end].iter().map(|l| l.trim()).collect::<Vec<_>>().join(" ")
+                } else {
+                    String::new()
+                };
+                res.push(Token::StandaloneImage(args.to_owned(), caption, attributes, alt));
+                if name == "figure" {
+                    let legend = self.parse_blocks(&content[caption_end..])?;
+                    res.extend(legend);
+                }
+            }
+            "epigraph" | "highlights" | "pull-quote" => {
+                let (quote, attribution) = split_attribution(&with_args);
+                let quote = self.parse_blocks(quote)?;
+                if name == "epigraph" {
+                    res.push(Token::Epigraph(attribution.unwrap_or_default(), quote));
+                } else {
+                    let mut quote = quote;
+                    if let Some(attribution) = attribution {
+                        quote.push(Token::Attribution(self.parse_inline(&attribution)?));
+                    }
+                    self.features.blockquote = true;
+                    res.push(Token::BlockQuote(quote));
+                }
+            }
+            "admonition" => {
+                self.features.admonition = true;
+                let content = self.parse_blocks(content)?;
+                res.push(Token::Admonition(String::from("note"), args.to_owned(), content));
+            }
+            _ => {
+                match ADMONITIONS.iter().find(|&&(directive, _)| directive == name) {
+                    Some(&(_, kind)) => {
+                        self.features.admonition = true;
+                        let content = self.parse_blocks(&with_args)?;
+                        res.push(Token::Admonition(kind.to_owned(), String::new(), content));
+                    }
+                    None => {
+                        self.warnings.push(lformat!("in {file}, ignoring unsupported \
+                                                     reStructuredText directive \
+                                                     '{directive}'",
+                                                    file = self.source,
+                                                    directive = name));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses inline markup
+    fn parse_inline(&mut self, text: &str) -> Result<Vec<Token>> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut res = vec![];
+        let mut buf = String::new();
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            if c == '\\' && i + 1 < chars.len() {
+                // Escaped whitespace is removed
+                if !chars[i + 1].is_whitespace() {
+                    buf.push(chars[i + 1]);
+                }
+                i += 2;
+                continue;
+            }
+            if c == '\n' {
+                flush(&mut buf, &mut res);
+                res.push(Token::SoftBreak);
+                i += 1;
+                continue;
+            }
+            if i == 0 || is_start_boundary(chars[i - 1]) {
+                if let Some((token, next)) = self.inline_markup(&chars, i)? {
+                    // Footnote references are attached to the previous word
+                    if let Token::Footnote(_) = token {
+                        let len = buf.trim_right().len();
+                        buf.truncate(len);
+                    }
+                    flush(&mut buf, &mut res);
+                    res.push(token);
+                    i = next;
+                    continue;
+                }
+            }
+            if c == '_' && chars.get(i + 1).map_or(true, |&c| c != '_' && is_end_boundary(c)) {
+                // Simple hyperlink reference (`name_`)
+                let start = buf.char_indices()
+                    .rev()
+                    .take_while(|&(_, c)| c.is_alphanumeric() || c == '-')
+                    .last()
+                    .map(|(j, _)| j);
+                if let Some(start) = start {
+                    let url = self.targets.get(&normalize_name(&buf[start..])).cloned();
+                    if let Some(url) = url {
+                        let word = buf[start..].to_owned();
+                        buf.truncate(start);
+                        flush(&mut buf, &mut res);
+                        self.features.url = true;
+                        res.push(Token::Link(url, String::new(), vec![Token::Str(word)]));
+                        i += 1;
+                        continue;
+                    }
+                }
+            }
+            buf.push(c);
+            i += 1;
+        }
+        flush(&mut buf, &mut res);
+        Ok(res)
+    }
+
+    /// Parses the inline markup starting at `i`, if any, and returns it with
+    /// the position following it
+    fn inline_markup(&mut self, chars: &[char], i: usize) -> Result<Option<(Token, usize)>> {
+        let c = chars[i];
+        if starts_with(chars, i, "``") {
+            if let Some(end) = find_end(chars, i + 2, "``") {
+                let code = collect(&chars[i + 2..end]);
+                return Ok(Some((Token::Code(vec![Token::Str(code)]), end + 2)));
+            }
+        } else if starts_with(chars, i, "**") {
+            if let Some(end) = find_end(chars, i + 2, "**") {
+                let inner = self.parse_inline(&collect(&chars[i + 2..end]))?;
+                return Ok(Some((Token::Strong(inner), end + 2)));
+            }
+        } else if c == '*' {
+            if let Some(end) = find_end(chars, i + 1, "*") {
+                let inner = self.parse_inline(&collect(&chars[i + 1..end]))?;
+                return Ok(Some((Token::Emphasis(inner), end + 1)));
+            }
+        } else if c == '`' {
+            if let Some(end) = find_end(chars, i + 1, "`") {
+                let inner = collect(&chars[i + 1..end]);
+                let mut next = end + 1;
+                // Hyperlink reference (`text <url>`_ or `name`_)
+                let underscores = chars[next..].iter().take(2).take_while(|&&c| c == '_').count();
+                if underscores > 0 {
+                    next += underscores;
+                    return Ok(Some((self.reference(&inner)?, next)));
+                }
+                // Role given after the text (`text`:role:)
+                if let Some((role, after)) = role_at(chars, next) {
+                    return Ok(Some((self.role(&role, &inner), after)));
+                }
+                // Interpreted text without a role is a title reference
+                return Ok(Some((Token::Emphasis(vec![Token::Str(inner)]), next)));
+            }
+        } else if c == ':' {
+            if let Some((role, after)) = role_at(chars, i) {
+                if chars.get(after) == Some(&'`') {
+                    if let Some(end) = find_end(chars, after + 1, "`") {
+                        let inner = collect(&chars[after + 1..end]);
+                        return Ok(Some((self.role(&role, &inner), end + 1)));
+                    }
+                }
+            }
+        } else if c == '[' {
+            // Footnote reference ([1]_, [#]_, [#name]_ or [*]_)
+            if let Some(close) = chars[i..].iter().position(|&c| c == ']').map(|n| n + i) {
+                if chars.get(close + 1) == Some(&'_') &&
+                   chars.get(close + 2).map_or(true, |&c| is_end_boundary(c)) {
+                    let label = collect(&chars[i + 1..close]);
+                    if is_footnote_label(&label) {
+                        let footnote = self.footnote(&label)?;
+                        return Ok(Some((footnote, close + 2)));
+                    }
+                }
+            }
+        } else if starts_with(chars, i, "http://") || starts_with(chars, i, "https://") {
+            // Standalone URL
+            let mut end = i;
+            while end < chars.len() && !chars[end].is_whitespace() && chars[end] != '>' {
+                end += 1;
+            }
+            while end > i && ".,;:!?)'\"".contains(chars[end - 1]) {
+                end -= 1;
+            }
+            let url = collect(&chars[i..end]);
+            self.features.url = true;
+            return Ok(Some((Token::Link(url.clone(), String::new(), vec![Token::Str(url)]), end)));
+        }
+        Ok(None)
+    }
+
+    /// Returns the token of a hyperlink reference
+    fn reference(&mut self, inner: &str) -> Result<Token> {
+        let (text, url) = match inner.rfind('<') {
+            Some(pos) if inner.ends_with('>') => {
+                (inner[..pos].trim().to_owned(), inner[pos + 1..inner.len() - 1].trim().to_owned())
+            }
+            _ => {
+                (inner.to_owned(),
+                 self.targets.get(&normalize_name(inner)).cloned().unwrap_or_default())
+            }
+        };
+        // An embedded reference to a target (`text <name_>`_)
+        let url = if url.ends_with('_') {
+            self.targets.get(&normalize_name(&url[..url.len() - 1])).cloned().unwrap_or_default()
+        } else {
+            url
+        };
+        let text = if text.is_empty() { url.clone() } else { text };
+        if url.is_empty() {
+            // Reference to a section, or to an unknown target: only keep the text
+            return Ok(Token::Str(text));
+        }
+        self.features.url = true;
+        Ok(Token::Link(url, String::new(), self.parse_inline(&text)?))
+    }
+
+    /// Returns the token of interpreted text with a role (e.g. `:sup:`)
+    fn role(&mut self, role: &str, inner: &str) -> Token {
+        let text = vec![Token::Str(inner.to_owned())];
+        match role {
+            "strong" => Token::Strong(text),
+            "emphasis" | "title-reference" | "title" | "t" | "dfn" | "term" => Token::Emphasis(text),
+            "sup" | "superscript" => {
+                self.features.superscript = true;
+                Token::Superscript(text)
+            }
+            "sub" | "subscript" => {
+                self.features.subscript = true;
+                Token::Subscript(text)
+            }
+            "abbr" => {
+                // :abbr:`LIFO (last-in, first-out)`
+                match inner.find(" (") {
+                    Some(pos) if inner.ends_with(')') => {
+                        Token::Abbreviation(inner[pos + 2..inner.len() - 1].to_owned(),
+                                            vec![Token::Str(inner[..pos].to_owned())])
+                    }
+                    _ => Token::Str(inner.to_owned()),
+                }
+            }
+            // Roles of Sphinx domains, e.g. `:py:func:`
+            _ if role.contains(':') || CODE_ROLES.contains(&role) => Token::Code(text),
+            _ => {
+                // Cross-references (e.g. :ref:`Title <label>`) only keep their title
+                match inner.rfind(" <") {
+                    Some(pos) if inner.ends_with('>') => Token::Str(inner[..pos].to_owned()),
+                    _ => Token::Str(inner.to_owned()),
+                }
+            }
+        }
+    }
+
+    /// Returns the footnote with this label
+    fn footnote(&mut self, label: &str) -> Result<Token> {
+        let content = if label == "#" || label == "*" {
+            self.auto_count += 1;
+            self.auto_footnotes.get(self.auto_count - 1).cloned()
+        } else {
+            self.footnotes.get(label).cloned()
+        };
+        match content {
+            Some(lines) => Ok(Token::Footnote(self.parse_blocks(&lines)?)),
+            None => {
+                Err(Error::parser(&self.source,
+                                  lformat!("footnote reference {reference} does \
+                                            not have a matching definition",
+                                           reference = label)))
+            }
+        }
+    }
+}
+
+/// Pushes the text of the buffer, if any, to a list of tokens
+fn flush(buf: &mut String, res: &mut Vec<Token>) {
+    if !buf.is_empty() {
+        res.push(Token::Str(mem::replace(buf, String::new())));
+    }
+}
+
+/// Returns the indentation of a line
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_left_matches(' ').len()
+}
+
+/// Returns the end of the block starting at `start`, i.e. the line
+/// following its last line indented by `min` spaces or more
+fn block_end(lines: &[String], start: usize, min: usize) -> usize {
+    let mut end = start;
+    for (i, line) in lines.iter().enumerate().skip(start) {
+        if line.is_empty() {
+            continue;
+        }
+        if indent(line) < min {
+            break;
+        }
+        end = i + 1;
+    }
+    end
+}
+
+/// Removes the common indentation of lines
+fn dedent(lines: &[String]) -> Vec<String> {
+    let min = lines.iter()
+        .filter(|l| !l.is_empty())
+        .map(|l| indent(l))
+        .min()
+        .unwrap_or(0);
+    unindent(lines, min)
+}
+
+/// Removes up to `n` spaces at the start of each line
+fn unindent(lines: &[String], n: usize) -> Vec<String> {
+    lines.iter()
+        .map(|l| l[cmp::min(indent(l), n)..].to_owned())
+        .collect()
+}
+
+/// Removes the blank lines at the start and at the end of a list of lines
+fn trim_blank(lines: &[String]) -> &[String] {
+    let start = lines.iter().position(|l| !l.is_empty()).unwrap_or(lines.len());
+    let end = lines.iter().rposition(|l| !l.is_empty()).map_or(start, |n| n + 1);
+    &lines[start..end]
+}
+
+/// Splits the attribution of a quote, i.e. its last paragraph if it starts
+/// with `--`, `---` or an em dash, from its lines
+fn split_attribution(lines: &[String]) -> (&[String], Option<String>) {
+    let lines = trim_blank(lines);
+    let mut start = lines.len();
+    while start > 0 && !lines[start - 1].is_empty() {
+        start -= 1;
+    }
+    if start == lines.len() {
+        return (lines, None);
+    }
+    let first = lines[start].trim_left();
+    for marker in &["---", "--", "—"] {
+        if first.starts_with(*marker) {
+            let mut text = first[marker.len()..].trim().to_owned();
+            for line in &lines[start + 1..] {
+                text.push(' ');
+                text.push_str(line.trim());
+            }
+            return (&lines[..start], Some(text));
+        }
+    }
+    (lines, None)
+}
+
+/// Returns true if a line is the adornment of a section title or a
+/// transition (a repeated punctuation character)
+fn is_adornment(line: &str) -> bool {
+    let mut chars = line.chars();
+    match chars.next() {
+        Some(c) if "=-`:'\"~^_*+#<>.".contains(c) => line.len() >= 2 && chars.all(|d| d == c),
+        _ => false,
+    }
+}
+
+/// Returns true if a line is the border of a grid table or a simple table
+fn is_table_border(line: &str) -> bool {
+    ((line.starts_with("+-") || line.starts_with("+=")) &&
+     line.chars().all(|c| c == '+' || c == '-' || c == '=')) ||
+    (line.starts_with('=') && line.contains(" =") && line.chars().all(|c| c == '=' || c == ' '))
+}
+
+/// Returns the number of the list item started by a line (`None` for a
+/// bullet list), and the column of its text
+fn list_item(line: &str) -> Option<(Option<usize>, usize)> {
+    let bytes = line.as_bytes();
+    let (number, marker) = match bytes.first() {
+        Some(&b'-') | Some(&b'*') | Some(&b'+') => (None, 1),
+        Some(&b'#') if bytes.get(1) == Some(&b'.') || bytes.get(1) == Some(&b')') => (Some(1), 2),
+        Some(&b'(') => {
+            let digits = line[1..].bytes().take_while(|&b| is_digit(b)).count();
+            if digits == 0 || bytes.get(digits + 1) != Some(&b')') {
+                return None;
+            }
+            (line[1..digits + 1].parse().ok(), digits + 2)
+        }
+        Some(&b) if is_digit(b) => {
+            let digits = line.bytes().take_while(|&b| is_digit(b)).count();
+            match bytes.get(digits) {
+                Some(&b'.') | Some(&b')') => (line[..digits].parse().ok(), digits + 1),
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+    if marker == bytes.len() {
+        return Some((number, marker));
+    }
+    if bytes[marker] != b' ' {
+        return None;
+    }
+    Some((number, marker + indent(&line[marker..])))
+}
+
+/// Returns true if a byte is an ASCII digit
+fn is_digit(b: u8) -> bool {
+    b >= b'0' && b <= b'9'
+}
+
+/// Returns true if a string is the label of a footnote (a number, `#`,
+/// `#name` or `*`), and not of a citation
+fn is_footnote_label(label: &str) -> bool {
+    !label.is_empty() &&
+    (label.chars().all(|c| c.is_digit(10)) || label == "*" ||
+     label.starts_with('#') &&
+     label[1..].chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_'))
+}
+
+/// Normalizes the name of a hyperlink target, which is case insensitive
+fn normalize_name(name: &str) -> String {
+    name.trim_matches('`').split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+/// Returns true if the inline markup can start after this character
+fn is_start_boundary(c: char) -> bool {
+    c.is_whitespace() || "-:/'\"<([{".contains(c)
+}
+
+/// Returns true if the inline markup can end before this character
+fn is_end_boundary(c: char) -> bool {
+    c.is_whitespace() || "-.,:;!?\\/'\")]}>_".contains(c)
+}
+
+/// Returns true if `s` is at position `i` of `chars`
+fn starts_with(chars: &[char], i: usize, s: &str) -> bool {
+    let mut n = i;
+    for c in s.chars() {
+        if chars.get(n) != Some(&c) {
+            return false;
+        }
+        n += 1;
+    }
+    true
+}
+
+/// Returns the position of the end of inline markup, whose content starts
+/// at `from`
+fn find_end(chars: &[char], from: usize, end: &str) -> Option<usize> {
+    if chars.get(from).map_or(true, |c| c.is_whitespace()) {
+        return None;
+    }
+    let len = end.chars().count();
+    // Inline markup can't be empty
+    let mut i = from + 1;
+    while i + len <= chars.len() {
+        if starts_with(chars, i, end) && !chars[i - 1].is_whitespace() && chars[i - 1] != '\\' &&
+           chars.get(i + len).map_or(true, |&c| is_end_boundary(c)) {
+            return Some(i);
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Returns the name of the role (e.g. `:sup:`) at position `i`, if any, and
+/// the position following it
+fn role_at(chars: &[char], i: usize) -> Option<(String, usize)> {
+    if chars.get(i) != Some(&':') {
+        return None;
+    }
+    let mut end = i + 1;
+    while end < chars.len() && (chars[end].is_alphanumeric() || "-_.+:".contains(chars[end])) {
+        end += 1;
+    }
+    if end > i + 2 && chars[end - 1] == ':' {
+        Some((collect(&chars[i + 1..end - 1]), end))
+    } else {
+        None
+    }
+}
+
+/// Collects characters into a string
+fn collect(chars: &[char]) -> String {
+    chars.iter().cloned().collect()
+}
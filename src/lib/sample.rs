@@ -0,0 +1,107 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Sample (preview) editions, containing only the beginning of a book.
+
+use chapter::Chapter;
+use token::Token;
+use text_view::view_as_text;
+
+/// Size of a sample edition
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleSize {
+    /// A number of chapters
+    Chapters(usize),
+    /// A percentage of the words of the book
+    Percent(f32),
+}
+
+impl SampleSize {
+    /// Parses the value of the `sample` option, e.g. `3` (chapters) or `10%`
+    pub fn parse(s: &str) -> Option<SampleSize> {
+        let s = s.trim();
+        if s.ends_with('%') {
+            match s[..s.len() - 1].trim().parse::<f32>() {
+                Ok(n) if n > 0.0 && n <= 100.0 => Some(SampleSize::Percent(n)),
+                _ => None,
+            }
+        } else {
+            match s.parse::<usize>() {
+                Ok(n) if n > 0 => Some(SampleSize::Chapters(n)),
+                _ => None,
+            }
+        }
+    }
+}
+
+/// Removes the end of the book, keeping the front matter chapters and the
+/// beginning of the others, according to `size`
+///
+/// With a percentage, the last chapter kept is cut after the paragraph (or
+/// other block) that reaches it.
+pub fn truncate(chapters: &mut Vec<Chapter>, size: SampleSize) {
+    match size {
+        SampleSize::Chapters(n) => {
+            let mut count = 0;
+            chapters.retain(|chapter| {
+                if chapter.front_matter {
+                    true
+                } else {
+                    count += 1;
+                    count <= n
+                }
+            });
+        }
+        SampleSize::Percent(percent) => {
+            let total: usize = chapters.iter()
+                .filter(|chapter| !chapter.front_matter)
+                .map(|chapter| words(&chapter.content))
+                .sum();
+            let max = (total as f32 * percent / 100.0).ceil() as usize;
+            let mut count = 0;
+            let mut end = chapters.len();
+            for (i, chapter) in chapters.iter_mut().enumerate() {
+                if chapter.front_matter {
+                    continue;
+                }
+                if count >= max {
+                    end = i;
+                    break;
+                }
+                let mut len = chapter.content.len();
+                for j in 0..chapter.content.len() {
+                    count += words(&chapter.content[j..j + 1]);
+                    if count >= max {
+                        len = j + 1;
+                        break;
+                    }
+                }
+                chapter.content.truncate(len);
+            }
+            let mut i = 0;
+            chapters.retain(|chapter| {
+                i += 1;
+                chapter.front_matter || i <= end
+            });
+        }
+    }
+}
+
+/// Returns the number of words of these tokens
+fn words(tokens: &[Token]) -> usize {
+    view_as_text(tokens).split_whitespace().count()
+}
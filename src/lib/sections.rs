@@ -0,0 +1,286 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Numbering of the sections of chapters (e.g. "2.3.1"), shared by all
+//! renderers so they can be referenced.
+
+use book::Book;
+use captions;
+use number::Number;
+use token::Token;
+use text_view::view_as_text;
+use lang;
+
+use numerals::roman::Roman;
+
+use std::collections::{HashMap, HashSet};
+
+/// A section of a chapter, that is, a heading below the chapter title
+/// (`##`, `###`, ...)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    /// Index of the chapter containing it
+    pub chapter: usize,
+    /// Level of the section (2 for `##`, 3 for `###`, ...)
+    pub level: i32,
+    /// Id of the section, used as an anchor
+    pub id: String,
+    /// Number of the section, e.g. "2.3.1", or an empty string if it isn't
+    /// numbered
+    pub number: String,
+    /// Title of the section, as raw text
+    pub title: String,
+}
+
+impl Section {
+    /// Returns the localised label of this section, e.g. "Section 2.3.1", or
+    /// its title if it isn't numbered
+    pub fn label(&self, lang: &str) -> String {
+        if self.number.is_empty() {
+            self.title.clone()
+        } else {
+            format!("{} {}", lang::get_str(lang, "section"), self.number)
+        }
+    }
+}
+
+/// The sections of a book, used by renderers to get the number and the id of
+/// the sections they render
+#[derive(Debug)]
+pub struct Sections {
+    sections: Vec<Section>,
+    /// Indices of the sections by id
+    by_id: HashMap<String, usize>,
+    /// Number of sections that were already rendered
+    rendered: usize,
+}
+
+impl Sections {
+    /// Finds all the sections of the book
+    pub fn new(book: &Book) -> Sections {
+        let sections = collect(book);
+        let by_id = sections.iter()
+            .enumerate()
+            .map(|(i, section)| (section.id.clone(), i))
+            .collect();
+        Sections {
+            sections: sections,
+            by_id: by_id,
+            rendered: 0,
+        }
+    }
+
+    /// Returns the next section, in the order they appear in the book
+    pub fn next(&mut self) -> Option<Section> {
+        let section = self.sections.get(self.rendered).cloned();
+        self.rendered += 1;
+        section
+    }
+
+    /// Returns the section with the given id, if any
+    pub fn find(&self, id: &str) -> Option<&Section> {
+        self.by_id.get(id).map(|&i| &self.sections[i])
+    }
+
+    /// Returns all the sections
+    pub fn all(&self) -> &[Section] {
+        &self.sections
+    }
+}
+
+/// Returns all the sections of the book, in the order they appear
+///
+/// Sections are numbered up to `rendering.num_depth`, in numbered chapters
+/// only. Their ids are generated from their titles (e.g. `#installing-crowbook`
+/// for "Installing Crowbook"), and are unique across the whole book.
+pub fn collect(book: &Book) -> Vec<Section> {
+    let ids = captions::collect(book, None)
+        .into_iter()
+        .map(|caption| caption.id)
+        .collect();
+    walk(book, ids).result
+}
+
+/// Returns the number of each chapter of the book, as it is displayed in
+/// its heading (e.g. `3`, or `III` with roman numerals), or `None` if it
+/// isn't numbered
+///
+/// The numbering is the one of sections, so e.g. chapter numbers restart
+/// at each part if `rendering.part.reset_counter` is set.
+pub fn chapter_numbers(book: &Book) -> Vec<Option<String>> {
+    walk(book, HashSet::new()).chapter_numbers
+}
+
+/// Walks through the chapters of the book to number their headings, with
+/// `ids` the ids that are already used
+fn walk(book: &Book, ids: HashSet<String>) -> Collector {
+    let mut collector = Collector {
+        result: vec![],
+        chapter_numbers: vec![],
+        chapter: 0,
+        part: false,
+        numbering: 0,
+        counters: [0; 7],
+        depth: book.options.get_i32("rendering.num_depth").unwrap(),
+        roman: book.options.get_bool("rendering.chapter.roman_numerals").unwrap(),
+        reset: book.options.get_bool("rendering.part.reset_counter").unwrap(),
+        ids: ids,
+    };
+    for (i, chapter) in book.chapters.iter().enumerate() {
+        collector.chapter = i;
+        collector.part = chapter.number.is_part();
+        // Same numbering as the one of chapter headings
+        collector.numbering = match chapter.number {
+            Number::Default | Number::DefaultPart => collector.depth,
+            Number::Specified(n) => {
+                collector.counters[1] = n - 1;
+                collector.depth
+            }
+            Number::SpecifiedPart(n) => {
+                collector.counters[0] = n - 1;
+                collector.depth
+            }
+            _ => 0,
+        };
+        collector.visit(&chapter.content);
+        let number = if collector.part || collector.numbering < 1 || collector.counters[1] == 0 {
+            None
+        } else {
+            Some(collector.counter(1))
+        };
+        collector.chapter_numbers.push(number);
+    }
+    collector
+}
+
+/// Walks through the tokens to find headings
+struct Collector {
+    result: Vec<Section>,
+    /// Displayed number of each chapter visited so far
+    chapter_numbers: Vec<Option<String>>,
+    chapter: usize,
+    /// Whether the current chapter is a part (its headings are one level higher)
+    part: bool,
+    /// Maximum level of numbered headings in the current chapter
+    numbering: i32,
+    /// Current number of parts, chapters, sections, ...
+    counters: [i32; 7],
+    depth: i32,
+    roman: bool,
+    reset: bool,
+    /// Ids that are already used
+    ids: HashSet<String>,
+}
+
+impl Collector {
+    fn visit(&mut self, tokens: &[Token]) {
+        for token in tokens {
+            match *token {
+                Token::Header(n, ref vec) => {
+                    let n = if self.part { n - 1 } else { n };
+                    self.increment(n);
+                    if n >= 2 {
+                        let number = if self.numbering >= n {
+                            self.number()
+                        } else {
+                            String::new()
+                        };
+                        let title = view_as_text(vec);
+                        let id = self.unique_id(&title);
+                        self.result.push(Section {
+                            chapter: self.chapter,
+                            level: n,
+                            id: id,
+                            number: number,
+                            title: title,
+                        });
+                    }
+                }
+                // Notes are rendered separately, so headings they could
+                // contain would not be rendered in order
+                Token::Footnote(_) | Token::MarginNote(_) => {}
+                _ => {
+                    if let Some(inner) = token.inner() {
+                        self.visit(inner);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Increments the counter of this level, and resets the ones below it
+    fn increment(&mut self, n: i32) {
+        if n < 0 || n as usize >= self.counters.len() || self.numbering < n {
+            return;
+        }
+        let n = n as usize;
+        self.counters[n] += 1;
+        let begin = if n == 0 && !self.reset { n + 2 } else { n + 1 };
+        for counter in &mut self.counters[begin..] {
+            *counter = 0;
+        }
+    }
+
+    /// Returns the number of the current section, e.g. "2.3.1"
+    fn number(&self) -> String {
+        let mut numbers = vec![];
+        for i in 1..self.counters.len() {
+            if self.counters[i..].iter().all(|&x| x == 0) {
+                break;
+            }
+            numbers.push(self.counter(i));
+        }
+        numbers.join(".")
+    }
+
+    /// Returns the counter of a level, in roman numerals for chapters if
+    /// `rendering.chapter.roman_numerals` is set
+    fn counter(&self, i: usize) -> String {
+        if i == 1 && self.roman && self.counters[i] >= 1 {
+            format!("{:X}", Roman::from(self.counters[i] as i16))
+        } else {
+            format!("{}", self.counters[i])
+        }
+    }
+
+    /// Returns an id generated from this title, that isn't used yet
+    fn unique_id(&mut self, title: &str) -> String {
+        let mut base = String::new();
+        for c in title.chars().flat_map(|c| c.to_lowercase()) {
+            if c.is_alphanumeric() {
+                base.push(c);
+            } else if (c.is_whitespace() || c == '-' || c == '_') && !base.is_empty() &&
+                      !base.ends_with('-') {
+                base.push('-');
+            }
+        }
+        while base.ends_with('-') {
+            base.pop();
+        }
+        if base.is_empty() {
+            base.push_str("section");
+        }
+        let mut id = base.clone();
+        let mut n = 1;
+        while self.ids.contains(&id) {
+            n += 1;
+            id = format!("{}-{}", base, n);
+        }
+        self.ids.insert(id.clone());
+        id
+    }
+}
@@ -0,0 +1,181 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Rendering of a book as a reveal.js slide deck, to teach or give a talk
+//! from its chapters.
+
+use error::{Error, Result, Source};
+use html::HtmlRenderer;
+use html::Highlight;
+use book::{Book, compile_str};
+use token::Token;
+use renderer::Renderer;
+use book_renderer::BookRenderer;
+use parser::Parser;
+
+use rustc_serialize::base64::{self, ToBase64};
+
+use std::convert::{AsMut, AsRef};
+use std::io;
+use std::fmt::Write;
+
+/// Slide deck renderer
+///
+/// Each chapter is a vertical stack of slides, which are separated by
+/// level-2 headers and rules (`---`).
+pub struct SlidesRenderer<'a> {
+    html: HtmlRenderer<'a>,
+}
+
+impl<'a> SlidesRenderer<'a> {
+    /// Creates a new SlidesRenderer
+    pub fn new(book: &'a Book) -> Result<SlidesRenderer<'a>> {
+        let html = HtmlRenderer::new(book,
+                                     book.options
+                                     .get_str("html.highlight.theme")
+                                     .unwrap_or_else(|_| book.options.get_str("rendering.highlight.theme").unwrap()))?;
+        Ok(SlidesRenderer { html: html })
+    }
+
+    /// Renders a token
+    ///
+    /// Used by render_token implementation of Renderer trait. Separate function
+    /// because we need to be able to call it from other renderers.
+    ///
+    /// See http://lise-henry.github.io/articles/rust_inheritance.html
+    #[doc(hidden)]
+    pub fn static_render_token<T>(this: &mut T, token: &Token) -> Result<String>
+    where T: AsMut<SlidesRenderer<'a>>+AsRef<SlidesRenderer<'a>> +
+        AsMut<HtmlRenderer<'a>>+AsRef<HtmlRenderer<'a>> + Renderer
+    {
+        HtmlRenderer::static_render_token(this, token)
+    }
+
+    /// Renders the book as a reveal.js HTML document
+    pub fn render_book(&mut self) -> Result<String> {
+        for (i, chapter) in self.html.book.chapters.iter().enumerate() {
+            self.html.handler.add_chapter_link(chapter.filename.as_ref(),
+                                               format!("#/chapter-{}", i));
+        }
+        for caption in self.html.captions.all() {
+            self.html.handler.add_link(format!("#{}", caption.id),
+                                       format!("#{}", caption.id));
+        }
+        for section in self.html.sections.all() {
+            self.html.handler.add_link(format!("#{}", section.id),
+                                       format!("#{}", section.id));
+        }
+
+        let mut content = String::new();
+        for (i, chapter) in self.html.book.chapters.iter().enumerate() {
+            let chapter = self.html.book.clean_chapter(chapter, self.html.clean)?;
+            self.html.chapter_config(i, chapter.number, String::new());
+
+            let slides = split_slides(&chapter.content);
+            if slides.is_empty() {
+                continue;
+            }
+            writeln!(content, "<section id = \"chapter-{}\">", i)?;
+            for slide in &slides {
+                // Footnotes are displayed at the bottom of the slide
+                // containing them
+                writeln!(content,
+                         "<section>\n{}</section>",
+                         HtmlRenderer::render_html(self, slide, true)?)?;
+            }
+            content.push_str("</section>\n");
+        }
+        self.html.source = Source::empty();
+
+        let mut mapbuilder = self.html
+            .book
+            .get_metadata(|s| self.render_vec(&Parser::new().parse_inline(s)?))?
+            .insert_str("content", content)
+            .insert_str("reveal_url",
+                        self.html.book.options.get_str("slides.reveal_url").unwrap()
+                        .trim_right_matches('/'))
+            .insert_str("theme", self.html.book.options.get_str("slides.theme").unwrap());
+        if self.html.highlight == Highlight::Js {
+            let highlight_js = self.html.book.get_template("html.highlight.js")?
+                .as_bytes()
+                .to_base64(base64::STANDARD);
+            let highlight_js = format!("data:text/javascript;base64,{}", highlight_js);
+            mapbuilder = mapbuilder.insert_bool("highlight_code", true)
+                .insert_str("highlight_css",
+                            self.html.book.get_template("html.highlight.css")?)
+                .insert_str("highlight_js", highlight_js);
+        }
+        let data = mapbuilder.build();
+        let template = compile_str(self.html.book.get_template("slides.template")?.as_ref(),
+                                   &self.html.book.source,
+                                   "slides.template")?;
+        let mut res = vec![];
+        template.render_data(&mut res, &data)?;
+        Ok(String::from_utf8_lossy(&res).into_owned())
+    }
+}
+
+/// Splits the content of a chapter into slides, before each level-2 header
+/// and at each rule or forced page break
+///
+/// Also used by the LaTeX renderer to split chapters into beamer frames.
+pub fn split_slides(tokens: &[Token]) -> Vec<&[Token]> {
+    let mut slides = vec![];
+    let mut start = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match *token {
+            Token::Header(2, _) => {
+                if i > start {
+                    slides.push(&tokens[start..i]);
+                }
+                start = i;
+            }
+            Token::Rule | Token::NewPage => {
+                if i > start {
+                    slides.push(&tokens[start..i]);
+                }
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if tokens.len() > start {
+        slides.push(&tokens[start..]);
+    }
+    slides
+}
+
+derive_html!{SlidesRenderer<'a>, SlidesRenderer::static_render_token}
+
+pub struct Slides {}
+
+impl BookRenderer for Slides {
+    fn auto_path(&self, book_name: &str) -> Result<String> {
+        Ok(format!("{}.slides.html", book_name))
+    }
+
+    fn render(&self, book: &Book, to: &mut io::Write) -> Result<()> {
+        let mut slides = SlidesRenderer::new(book)?;
+        let result = slides.render_book()?;
+        to.write_all(result.as_bytes())
+            .map_err(|e| {
+                Error::render(&book.source,
+                              lformat!("problem when writing slides: {error}", error = e))
+            })?;
+        Ok(())
+    }
+}
@@ -0,0 +1,133 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Splitting of a single Markdown file into chapters, to migrate a
+//! manuscript written as one file to a book configuration file.
+
+/// A chapter of a split Markdown file
+#[derive(Debug, Clone, PartialEq)]
+pub struct SplitChapter {
+    /// File name of the chapter, e.g. `02-the-journey.md`
+    pub file: String,
+    /// Markdown content of the chapter
+    pub content: String,
+    /// Whether the chapter is numbered (the text before the first heading
+    /// isn't)
+    pub numbered: bool,
+}
+
+/// A Markdown file split into chapters at its level-1 headings
+#[derive(Debug, Clone, PartialEq)]
+pub struct Split {
+    /// Content of the YAML block at the beginning of the file, if any, which
+    /// can be used as the options of the book
+    pub yaml: Option<String>,
+    /// Chapters, in order
+    pub chapters: Vec<SplitChapter>,
+}
+
+impl Split {
+    /// Splits a Markdown file at its level-1 headings (`# Title` or
+    /// `Title` underlined with `=`), ignoring the ones in code blocks
+    ///
+    /// Text before the first heading (except a YAML block) is kept as an
+    /// unnumbered chapter.
+    pub fn new(markdown: &str) -> Split {
+        let mut lines: Vec<&str> = markdown.lines().collect();
+        let mut yaml = None;
+        if lines.first().map(|l| l.trim_right()) == Some("---") {
+            if let Some(end) = lines.iter().skip(1).position(|l| {
+                let l = l.trim_right();
+                l == "---" || l == "..."
+            }) {
+                yaml = Some(lines[1..end + 1].join("\n"));
+                lines.drain(..end + 2);
+            }
+        }
+
+        // Lines starting each chapter, with their title
+        let mut starts: Vec<(usize, String)> = vec![];
+        let mut fence: Option<&str> = None;
+        for (i, line) in lines.iter().enumerate() {
+            let trimmed = line.trim_left();
+            if let Some(f) = fence {
+                if trimmed.starts_with(f) {
+                    fence = None;
+                }
+                continue;
+            }
+            if trimmed.starts_with("```") {
+                fence = Some("```");
+            } else if trimmed.starts_with("~~~") {
+                fence = Some("~~~");
+            } else if line.starts_with("# ") || line.trim_right() == "#" {
+                let title = line.trim_left_matches('#').trim().trim_right_matches('#').trim();
+                starts.push((i, title.to_owned()));
+            } else if i > 0 && !lines[i - 1].trim().is_empty() && !line.is_empty() &&
+                      line.trim_right().chars().all(|c| c == '=') &&
+                      !starts.iter().any(|&(j, _)| j == i - 1) {
+                starts.push((i - 1, lines[i - 1].trim().to_owned()));
+            }
+        }
+
+        let mut chapters = vec![];
+        let first = starts.first().map(|&(i, _)| i).unwrap_or(lines.len());
+        let preamble = lines[..first].join("\n");
+        if !preamble.trim().is_empty() {
+            chapters.push(SplitChapter {
+                file: String::from("00-preamble.md"),
+                content: format!("{}\n", preamble.trim()),
+                numbered: false,
+            });
+        }
+        for (n, &(start, ref title)) in starts.iter().enumerate() {
+            let end = starts.get(n + 1).map(|&(i, _)| i).unwrap_or(lines.len());
+            let content = lines[start..end].join("\n");
+            let slug = slug(title);
+            chapters.push(SplitChapter {
+                file: if slug.is_empty() {
+                    format!("{:02}.md", n + 1)
+                } else {
+                    format!("{:02}-{}.md", n + 1, slug)
+                },
+                content: format!("{}\n", content.trim_right()),
+                numbered: true,
+            });
+        }
+        Split {
+            yaml: yaml,
+            chapters: chapters,
+        }
+    }
+}
+
+/// Returns a file name (without extension) for a title, e.g. `the-journey`
+/// for `The *Journey*`
+fn slug(title: &str) -> String {
+    let mut res = String::new();
+    for c in title.chars() {
+        if c.is_alphanumeric() {
+            res.extend(c.to_lowercase());
+        } else if !res.is_empty() && !res.ends_with('-') {
+            res.push('-');
+        }
+        if res.chars().count() >= 40 {
+            break;
+        }
+    }
+    res.trim_right_matches('-').to_owned()
+}
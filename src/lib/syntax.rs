@@ -71,10 +71,23 @@ impl Syntax {
                                                           syntect::html::IncludeBackground::No)))
     }
 
+    /// Convert a string containing code to HTML, returning one string for each line
+    pub fn to_html_lines(&self, code: &str, language: &str) -> Result<Vec<String>> {
+        let language = strip_language(language);
+        let syntax = self.syntax_set.find_syntax_by_token(language)
+            .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text());
+        let mut h = syntect::easy::HighlightLines::new(syntax, &self.theme);
+        Ok(code.lines()
+           .map(|line| {
+               let regions = h.highlight(line);
+               syntect::html::styles_to_coloured_html(&regions[..],
+                                                      syntect::html::IncludeBackground::No)
+           })
+           .collect())
+    }
+
     pub fn to_tex(&self, code: &str, language: &str) -> Result<String> {
         let language = strip_language(language);
-        use latex::insert_breaks;
-        use syntect::highlighting::{BLACK, FONT_STYLE_BOLD, FONT_STYLE_ITALIC, FONT_STYLE_UNDERLINE};
         let syntax = self.syntax_set.find_syntax_by_token(language)
             .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text());
         let mut h = syntect::easy::HighlightLines::new(syntax, &self.theme);
@@ -82,48 +95,246 @@ impl Syntax {
         
         let mut result = String::with_capacity(code.len());
         for (style, text) in regions {
-            let mut content = escape::tex(text).into_owned();
-            content = insert_breaks(&content);
-            content = content.replace('\n', "\\\\{}\n")
-                .replace(' ', "\\hphantom{ }\\allowbreak{}");
-            content = format!("\\texttt{{{}}}", content);
-            if style.foreground != BLACK {
-                let r = style.foreground.r as f32 / 255.0;
-                let g = style.foreground.g as f32 / 255.0;
-                let b = style.foreground.b as f32 / 255.0;
-                content = format!("\\textcolor[rgb]{{{r}, {g}, {b}}}{{{text}}}",
-                                  r = r,
-                                  g = g,
-                                  b = b,
-                                  text = content);
-            }
-            if style.font_style.contains(FONT_STYLE_BOLD) {
-                content = format!("\\textbf{{{}}}", content);
-            }
-            if style.font_style.contains(FONT_STYLE_ITALIC) {
-                content = format!("\\emph{{{}}}", content);
-            }
-            if style.font_style.contains(FONT_STYLE_UNDERLINE) {
-                content = format!("\\underline{{{}}}", content);
-            }
-            result.push_str(&content);
+            result.push_str(&region_to_tex(style, text));
         }
         Ok(format!("{{\\sloppy {}}}", result))
     }
+
+    /// Convert a string containing code to LaTeX, returning one string for each line
+    pub fn to_tex_lines(&self, code: &str, language: &str) -> Result<Vec<String>> {
+        let language = strip_language(language);
+        let syntax = self.syntax_set.find_syntax_by_token(language)
+            .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text());
+        let mut h = syntect::easy::HighlightLines::new(syntax, &self.theme);
+        Ok(code.lines()
+           .map(|line| {
+               h.highlight(line)
+                   .into_iter()
+                   .map(|(style, text)| region_to_tex(style, text))
+                   .collect::<Vec<_>>()
+                   .join("")
+           })
+           .collect())
+    }
+}
+
+/// Converts a region of highlighted code to LaTeX
+#[cfg(feature="syntect")]
+fn region_to_tex(style: syntect::highlighting::Style, text: &str) -> String {
+    use syntect::highlighting::{BLACK, FONT_STYLE_BOLD, FONT_STYLE_ITALIC, FONT_STYLE_UNDERLINE};
+    let mut content = code_to_tex(text);
+    if style.foreground != BLACK {
+        let r = style.foreground.r as f32 / 255.0;
+        let g = style.foreground.g as f32 / 255.0;
+        let b = style.foreground.b as f32 / 255.0;
+        content = format!("\\textcolor[rgb]{{{r}, {g}, {b}}}{{{text}}}",
+                          r = r,
+                          g = g,
+                          b = b,
+                          text = content);
+    }
+    if style.font_style.contains(FONT_STYLE_BOLD) {
+        content = format!("\\textbf{{{}}}", content);
+    }
+    if style.font_style.contains(FONT_STYLE_ITALIC) {
+        content = format!("\\emph{{{}}}", content);
+    }
+    if style.font_style.contains(FONT_STYLE_UNDERLINE) {
+        content = format!("\\underline{{{}}}", content);
+    }
+    content
+}
+
+/// Converts some (not highlighted) code to LaTeX, using a monospace font
+/// and allowing line breaks
+pub fn code_to_tex(text: &str) -> String {
+    use latex::insert_breaks;
+    let mut content = escape::tex(text).into_owned();
+    content = insert_breaks(&content);
+    content = content.replace('\n', "\\\\{}\n")
+        .replace(' ', "\\hphantom{ }\\allowbreak{}");
+    format!("\\texttt{{{}}}", content)
 }
 
 /// Strip language name of possible other infos, e.g. "rust,ignore" -> "rust"
-/// Currently only ',' is done
-fn strip_language(language: &str) -> &str {
+/// or "rust {linenos}" -> "rust"
+pub fn strip_language(language: &str) -> &str {
     let splits: Vec<_> = language
         .split(|c: char| match c {
-            ',' => true,
+            ',' | '{' => true,
+            c if c.is_whitespace() => true,
             _ => false
         })
         .collect();
     splits[0].trim()
 }
 
+/// Options of a code block, set in braces after the language,
+/// e.g. "rust {linenos, hl=3-5, caption=\"Hello, world\", include=\"hello.rs\"}"
+#[derive(Debug, PartialEq, Default)]
+pub struct CodeOptions {
+    /// Display line numbers
+    pub line_numbers: bool,
+    /// Ranges (inclusive, starting at 1) of the lines to highlight
+    pub highlighted: Vec<(usize, usize)>,
+    /// Caption of the listing
+    pub caption: Option<String>,
+    /// Id of the listing, allowing to reference it
+    pub id: Option<String>,
+    /// File whose content must be included in the code block
+    pub include: Option<String>,
+    /// Region of the included file, delimited by "ANCHOR: name" and "ANCHOR_END: name"
+    pub region: Option<String>,
+    /// Range of lines (inclusive, starting at 1) of the included file
+    pub lines: Option<(usize, usize)>,
+}
+
+impl CodeOptions {
+    /// Parses the options of a code block from its info string
+    ///
+    /// Returns `None` if the options are not valid.
+    pub fn parse(info: &str) -> Option<CodeOptions> {
+        let mut options = CodeOptions::default();
+        let start = match info.find('{') {
+            Some(start) => start,
+            None => return Some(options),
+        };
+        let inner = info[start + 1..].trim();
+        if !inner.ends_with('}') {
+            return None;
+        }
+        let inner = &inner[..inner.len() - 1];
+
+        // Split options on commas, except inside quotes
+        let mut list = vec![];
+        let mut current = String::new();
+        let mut quoted = false;
+        for c in inner.chars() {
+            match c {
+                '"' => {
+                    quoted = !quoted;
+                    current.push(c);
+                }
+                ',' if !quoted => {
+                    list.push(current);
+                    current = String::new();
+                }
+                _ => current.push(c),
+            }
+        }
+        if quoted {
+            return None;
+        }
+        list.push(current);
+
+        for option in list.iter().map(|s| s.trim()).filter(|s| !s.is_empty()) {
+            let (key, value) = match option.find('=') {
+                Some(i) => (option[..i].trim(), Some(unquote(option[i + 1..].trim()))),
+                None => (option, None),
+            };
+            match (key, value) {
+                ("linenos", None) => options.line_numbers = true,
+                ("hl", Some(range)) => {
+                    match parse_range(range) {
+                        Some(range) => options.highlighted.push(range),
+                        None => return None,
+                    }
+                }
+                ("lines", Some(range)) => {
+                    match parse_range(range) {
+                        Some(range) => options.lines = Some(range),
+                        None => return None,
+                    }
+                }
+                ("include", Some(path)) if !path.is_empty() => {
+                    options.include = Some(path.to_owned())
+                }
+                ("region", Some(region)) if !region.is_empty() => {
+                    options.region = Some(region.to_owned())
+                }
+                ("caption", Some(caption)) => options.caption = Some(caption.to_owned()),
+                ("id", Some(id)) if !id.is_empty() && !id.contains(char::is_whitespace) => {
+                    options.id = Some(id.to_owned())
+                }
+                _ => return None,
+            }
+        }
+        Some(options)
+    }
+
+    /// Returns the part of an included file that must be displayed,
+    /// according to `region` and `lines`
+    ///
+    /// Returns `None` if the region or the lines can't be found.
+    pub fn extract(&self, code: &str) -> Option<String> {
+        let mut lines: Vec<&str> = code.lines().collect();
+        if let Some(ref region) = self.region {
+            let start = format!("ANCHOR: {}", region);
+            let end = format!("ANCHOR_END: {}", region);
+            let begin = match lines.iter().position(|l| l.trim_right().ends_with(&start)) {
+                Some(begin) => begin + 1,
+                None => return None,
+            };
+            let finish = match lines[begin..].iter().position(|l| l.trim_right().ends_with(&end)) {
+                Some(finish) => begin + finish,
+                None => return None,
+            };
+            lines = lines[begin..finish].to_vec();
+        }
+        // Anchors of other regions must not be displayed
+        lines.retain(|l| !l.contains("ANCHOR: ") && !l.contains("ANCHOR_END: "));
+        if let Some((begin, end)) = self.lines {
+            if begin > lines.len() {
+                return None;
+            }
+            let end = if end > lines.len() { lines.len() } else { end };
+            lines = lines[begin - 1..end].to_vec();
+        }
+        let mut result = lines.join("\n");
+        result.push('\n');
+        Some(result)
+    }
+
+    /// Returns true if the code block is a numbered listing
+    pub fn is_numbered(&self) -> bool {
+        self.caption.is_some() || self.id.is_some()
+    }
+
+    /// Returns true if some options require to render the code block line by line
+    pub fn is_set(&self) -> bool {
+        self.line_numbers || !self.highlighted.is_empty()
+    }
+
+    /// Returns true if line `n` (starting at 1) must be highlighted
+    pub fn is_highlighted(&self, n: usize) -> bool {
+        self.highlighted
+            .iter()
+            .any(|&(begin, end)| n >= begin && n <= end)
+    }
+}
+
+
+/// Parses a range of lines, e.g. "3-5" or "3"
+fn parse_range(range: &str) -> Option<(usize, usize)> {
+    let (begin, end) = match range.find('-') {
+        Some(i) => (&range[..i], &range[i + 1..]),
+        None => (range, range),
+    };
+    match (begin.trim().parse::<usize>(), end.trim().parse::<usize>()) {
+        (Ok(begin), Ok(end)) if begin > 0 && begin <= end => Some((begin, end)),
+        _ => None,
+    }
+}
+
+/// Removes the quotes around a string, if any
+fn unquote(s: &str) -> &str {
+    if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') {
+        &s[1..s.len() - 1]
+    } else {
+        s
+    }
+}
+
 
 #[cfg(not(feature="syntect"))]
 impl Syntax {
@@ -142,4 +353,16 @@ impl Syntax {
         Ok(format!("\\begin{{spverbatim}}{}\\end{{spverbatim}}\n",
                 code))
     }
+
+    pub fn to_html_lines(&self, code: &str, _: &str) -> Result<Vec<String>> {
+        Ok(code.lines()
+           .map(|line| escape::html(line).into_owned())
+           .collect())
+    }
+
+    pub fn to_tex_lines(&self, code: &str, _: &str) -> Result<Vec<String>> {
+        Ok(code.lines()
+           .map(code_to_tex)
+           .collect())
+    }
 }
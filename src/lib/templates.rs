@@ -15,6 +15,8 @@
 // You should have received ba copy of the GNU Lesser General Public License
 // along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
 
+use mustache::{MapBuilder, Template};
+
 pub mod highlight {
     pub static CSS: &str = include_str!("../../templates/highlight/default.css");
     pub static JS: &str = include_str!("../../templates/highlight/highlight.pack.js");
@@ -51,6 +53,27 @@ pub mod html_if {
 
 pub mod latex {
     pub static TEMPLATE: &str = include_str!("../../templates/latex/template.tex");
+    pub static BEAMER: &str = include_str!("../../templates/latex/beamer.tex");
+    pub static COVER: &str = include_str!("../../templates/latex/cover.tex");
+    pub static FRONT_MATTER: &str = include_str!("../../templates/latex/front_matter.tex");
+}
+
+pub mod colophon {
+    pub static TEMPLATE: &str = include_str!("../../templates/colophon/colophon.md");
+}
+
+pub mod back_matter {
+    pub static ALSO_BY: &str = include_str!("../../templates/back_matter/also_by.md");
+    pub static ABOUT_AUTHOR: &str = include_str!("../../templates/back_matter/about_author.md");
+    pub static SAMPLE: &str = include_str!("../../templates/back_matter/sample.md");
+}
+
+pub mod revisions {
+    pub static TEMPLATE: &str = include_str!("../../templates/revisions/revisions.md");
+}
+
+pub mod slides {
+    pub static TEMPLATE: &str = include_str!("../../templates/slides/template.html");
 }
 
 pub mod epub {
@@ -64,9 +87,135 @@ pub mod epub3 {
     pub static TEMPLATE: &str = include_str!("../../templates/epub3/template.xhtml");
     pub static COVER: &str = include_str!("../../templates/epub3/cover.xhtml");
     pub static TITLE: &str = include_str!("../../templates/epub3/titlepage.xhtml");
+    pub static PAGE_LIST: &str = include_str!("../../templates/epub3/pagelist.xhtml");
 }
 
 pub mod odt {
     pub static CONTENT: &str = include_str!("../../templates/odt/content.xml");
     pub static ODT: &[u8] = include_bytes!("../../templates/odt/template.odt");
+    pub static MANIFEST: &str = include_str!("../../templates/odt/manifest.xml");
+}
+
+/// Returns true if the output of a compiled template depends on some of
+/// `variables`, by rendering it with the data returned by `data`, with and
+/// without placeholders for them
+///
+/// Since mustache renders the template, sections (e.g. `{{#has_foo}}`) and
+/// custom delimiters (e.g. `<<foo>>` in LaTeX templates) are handled.
+pub fn uses_variables<F>(template: &Template, data: F, variables: &[&str]) -> bool
+    where F: Fn() -> MapBuilder
+{
+    renders_differently(template, data(), with_placeholders(data(), variables))
+}
+
+/// Returns true if a compiled template gives different outputs for two
+/// sets of data (a failed rendering counting as an output)
+pub fn renders_differently(template: &Template, a: MapBuilder, b: MapBuilder) -> bool {
+    let render = |data: MapBuilder| {
+        let mut res: Vec<u8> = vec![];
+        template.render_data(&mut res, &data.build()).map(|_| res).ok()
+    };
+    render(a) != render(b)
+}
+
+/// Adds placeholders for `variables` to a map, which can be used both as
+/// values (e.g. `{{{foo}}}`) and as sections (e.g. `{{#foo}}...{{/foo}}`)
+pub fn with_placeholders(mut data: MapBuilder, variables: &[&str]) -> MapBuilder {
+    for variable in variables {
+        data = data.insert_fn(*variable, |content| format!("\u{1}{}", content));
+    }
+    data
+}
+
+
+/// Versioning of the templates
+///
+/// When built-in templates start using a variable that user templates need
+/// to render some content, `VERSION` is incremented and the variable is
+/// listed in `REQUIRED`. Templates are stamped with the version they were
+/// written for by a `crowbook-template: N` comment, so users of an older
+/// template can be warned about the variables it doesn't use.
+pub mod version {
+    use super::{uses_variables, with_placeholders};
+
+    use mustache;
+    use mustache::MapBuilder;
+
+    /// Current version of the templates
+    pub static VERSION: u32 = 4;
+
+    /// Variables that templates are expected to use, with the version that
+    /// introduced them
+    pub static REQUIRED: &[(&str, &str, u32)] =
+        &[("html.standalone.template", "content", 1),
+          ("html.standalone.template", "toc", 1),
+          ("html.standalone.template", "style", 1),
+          ("html.standalone.template", "script", 1),
+          ("html.standalone.template", "header", 1),
+          ("html.standalone.template", "footer", 1),
+          ("html.dir.template", "content", 1),
+          ("html.dir.template", "toc", 1),
+          ("html.dir.template", "script", 1),
+          ("html.dir.template", "header", 1),
+          ("html.dir.template", "footer", 1),
+          ("html.dir.template", "prev_chapter", 1),
+          ("html.dir.template", "next_chapter", 1),
+          ("slides.template", "content", 1),
+          ("epub.chapter.xhtml", "content", 1),
+          ("tex.template", "content", 1),
+          ("tex.template", "additional_code", 1),
+          ("tex.template", "watermark_diagonal", 2),
+          ("tex.template", "watermark_footer", 2),
+          ("tex.template", "use_admonitions", 3),
+          ("tex.template", "use_columns", 4)];
+
+    /// Returns the version a template was written for, or 1 if it isn't
+    /// stamped, since stamps were introduced after the first version
+    pub fn of(template: &str) -> u32 {
+        let stamp = "crowbook-template:";
+        template.find(stamp)
+            .and_then(|i| {
+                template[i + stamp.len()..]
+                    .trim_left()
+                    .split(|c: char| !c.is_digit(10))
+                    .next()
+                    .and_then(|n| n.parse().ok())
+            })
+            .unwrap_or(1)
+    }
+
+    /// Returns the variables that a user template for `name` doesn't use,
+    /// among the ones that were introduced after the version it was written
+    /// for
+    ///
+    /// Returns an empty list if the template doesn't compile, since this
+    /// error is reported when it is used.
+    pub fn missing(name: &str, template: &str) -> Vec<&'static str> {
+        let version = of(template);
+        let template = match mustache::compile_str(template) {
+            Ok(template) => template,
+            Err(_) => return vec![],
+        };
+        let required: Vec<&str> = REQUIRED.iter()
+            .filter(|&&(t, _, _)| t == name)
+            .map(|&(_, variable, _)| variable)
+            .collect();
+        REQUIRED.iter()
+            .filter(|&&(t, variable, since)| {
+                if t != name || since <= version {
+                    return false;
+                }
+                // Other variables are set, in case the template uses this one
+                // in one of their sections
+                let others: Vec<&str> = required.iter()
+                    .cloned()
+                    .filter(|v| *v != variable)
+                    .collect();
+                !uses_variables(&template,
+                                || with_placeholders(MapBuilder::new(), &others),
+                                &[variable])
+            })
+            .map(|&(_, variable, _)| variable)
+            .collect()
+    }
 }
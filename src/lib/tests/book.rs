@@ -1,6 +1,16 @@
 use book::Book;
+use number::Number;
+use token::Token;
+use captions;
+use captions::CaptionKind;
+use logger::InfoLevel;
 use super::test_eq;
 
+use std::env;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::PathBuf;
+
 #[test]
 fn load_config() {
     let config = "
@@ -19,3 +29,1247 @@ epub.version: 3";
             "A long description");
     assert_eq!(book.options.get_i32("epub.version").unwrap(), 3);
 }
+
+#[test]
+fn chapter_epigraph() {
+    let chapter = "---
+epigraph: Some *quote*
+epigraph.author: Someone
+---
+
+# Title
+
+Content
+";
+    let mut book = Book::new();
+    book.add_chapter_from_source(Number::Default, chapter.as_bytes()).unwrap();
+    let content = &book.chapters[0].content;
+    assert!(content[0].is_header());
+    assert_eq!(content[1],
+               Token::Epigraph(String::from("Someone"),
+                               vec![Token::Paragraph(vec![Token::Str(String::from("Some ")),
+                                                          Token::Emphasis(vec![Token::Str(String::from("quote"))])])]));
+    // Epigraph keys are not book options
+    assert!(book.options.get_str("epigraph").is_err());
+}
+
+#[test]
+fn chapter_front_matter() {
+    let mut book = Book::new();
+    book.add_chapter_from_source(Number::Unnumbered, "---\nfront_matter: true\n---\n\n# Preface\n".as_bytes())
+        .unwrap();
+    book.add_chapter_from_source(Number::Default, "# Chapter".as_bytes())
+        .unwrap();
+    assert!(book.chapters[0].front_matter);
+    assert!(!book.chapters[1].front_matter);
+}
+
+#[test]
+fn chapter_toc() {
+    let mut book = Book::new();
+    book.add_chapter_from_source(Number::Default, "---\ntoc: false\n---\n\n# Interlude\n".as_bytes())
+        .unwrap();
+    book.add_chapter_from_source(Number::Default, "# Chapter".as_bytes())
+        .unwrap();
+    assert!(!book.chapters[0].toc);
+    assert!(book.chapters[1].toc);
+}
+
+#[test]
+fn chapter_numbering_and_title() {
+    let mut book = Book::new();
+    book.add_chapter_from_source(Number::Default, "---\nnumbered: false\n---\n\n# Interlude\n".as_bytes())
+        .unwrap();
+    book.add_chapter_from_source(Number::Hidden, "---\nhide_title: false\n---\n\n# Prologue\n".as_bytes())
+        .unwrap();
+    book.add_chapter_from_source(Number::Hidden, "---\nnumbered: true\n---\n\n# One\n".as_bytes())
+        .unwrap();
+    book.add_chapter_from_source(Number::Specified(3), "# Three".as_bytes())
+        .unwrap();
+    assert_eq!(book.chapters[0].number, Number::Unnumbered);
+    assert!(!book.chapters[0].hide_title);
+    assert_eq!(book.chapters[1].number, Number::Hidden);
+    assert!(!book.chapters[1].hide_title);
+    assert_eq!(book.chapters[2].number, Number::Default);
+    assert!(book.chapters[2].hide_title);
+    assert_eq!(book.chapters[3].number, Number::Specified(3));
+    assert_eq!(Number::Specified(3).with_numbering(true), Number::Specified(3));
+    assert_eq!(Number::DefaultPart.with_numbering(false), Number::UnnumberedPart);
+}
+
+#[test]
+fn chapter_header_templates() {
+    let mut book = Book::new();
+    book.set_options(&[("author", "Author"), ("lang", "en")]);
+    let header = book.get_chapter_header(2, String::from("Title"), |s| Ok(s.to_uppercase()))
+        .unwrap();
+    test_eq(&header.text, "2. Title");
+    book.set_options(&[("rendering.chapter.template",
+                        "{{{loc_chapter}}} {{{number}}} ({{{ author }}}\
+                         {{#has_subtitle}}!{{/has_subtitle}})")]);
+    let header = book.get_chapter_header(2, String::from("Title"), |s| Ok(s.to_uppercase()))
+        .unwrap();
+    test_eq(&header.text, "Chapter 2 (AUTHOR)");
+    // Metadata are still rendered with custom delimiters
+    book.set_options(&[("rendering.chapter.template", "{{=<% %>=}}<% number %> (<%& author %>)")]);
+    let header = book.get_chapter_header(2, String::from("Title"), |s| Ok(s.to_uppercase()))
+        .unwrap();
+    test_eq(&header.text, "2 (AUTHOR)");
+}
+
+#[test]
+fn toc_options() {
+    let mut book = Book::new();
+    book.set_options(&[("rendering.num_depth", "2")]);
+    assert_eq!(book.toc_depth(), 2);
+    assert_eq!(book.inline_toc_location().unwrap(), None);
+    book.set_options(&[("rendering.toc.depth", "1"),
+                       ("rendering.inline_toc", "true"),
+                       ("rendering.inline_toc.location", "end")]);
+    assert_eq!(book.toc_depth(), 1);
+    assert_eq!(book.inline_toc_location().unwrap(), Some("end"));
+    book.set_options(&[("rendering.inline_toc.location", "none")]);
+    assert_eq!(book.inline_toc_location().unwrap(), None);
+    book.set_options(&[("rendering.inline_toc.location", "middle")]);
+    assert!(book.inline_toc_location().is_err());
+}
+
+#[test]
+fn odt_toc() {
+    use odt::OdtRenderer;
+    let mut book = Book::new();
+    book.set_options(&[("rendering.inline_toc", "true"), ("rendering.toc.depth", "2")]);
+    book.add_chapter_from_source(Number::Default,
+                                 "# One\n\n## Section\n\n### Subsection\n".as_bytes())
+        .unwrap();
+    book.add_chapter_from_source(Number::Default, "---\ntoc: false\n---\n\n# Two\n".as_bytes())
+        .unwrap();
+    let content = OdtRenderer::new(&book).render_content().unwrap();
+    assert!(content.contains("<text:table-of-content-source text:outline-level=\"2\""));
+    assert!(content.contains("<text:p text:style-name=\"Contents_20_1\">1. One</text:p>"));
+    assert!(content.contains("<text:p text:style-name=\"Contents_20_2\">Section</text:p>"));
+    assert!(!content.contains("Subsection</text:p>"));
+    assert!(!content.contains("Two</text:p>"));
+    // Only listed headings have index marks, so LibreOffice doesn't add the
+    // other ones when it updates the table of contents
+    assert_eq!(content.matches("<text:toc-mark-start").count(), 2);
+
+    book.set_options(&[("rendering.inline_toc.location", "none")]);
+    let content = OdtRenderer::new(&book).render_content().unwrap();
+    assert!(!content.contains("text:table-of-content"));
+    assert!(!content.contains("text:toc-mark"));
+}
+
+#[test]
+fn colophon() {
+    let config = "
+title: Some title
+version: Second edition
+rendering.colophon: true
+rendering.colophon.fonts: Libertinus";
+    let mut book = Book::new();
+    book.read_config(config.as_bytes()).unwrap();
+    assert_eq!(book.chapters.len(), 1);
+    assert_eq!(book.chapters[0].number, Number::Unnumbered);
+    assert_eq!(book.chapters[0].content[0],
+               Token::Header(1, vec![Token::Str(String::from("Colophon"))]));
+}
+
+#[test]
+fn list_of_listings() {
+    let content = r#"---
+rendering.list_of_listings: true
+---
+
+```rust {caption="Hello, world"}
+fn main() {}
+```
+"#;
+    let mut book = Book::new();
+    book.read_markdown_config(content.as_bytes()).unwrap();
+    assert_eq!(book.chapters.len(), 2);
+    assert!(book.chapters[0].front_matter);
+    assert_eq!(book.chapters[0].content[0],
+               Token::Header(1, vec![Token::Str(String::from("List of Listings"))]));
+    let listings = captions::collect(&book, Some(CaptionKind::Listing));
+    assert_eq!(listings.len(), 1);
+    assert_eq!(listings[0].chapter, 1);
+    test_eq(&listings[0].number, "1");
+    test_eq(&listings[0].id, "listing-1");
+    test_eq(&listings[0].caption, "Hello, world");
+}
+
+#[test]
+fn figures() {
+    let chapter = "![Logo](img/crowbook-small.png \"The logo\")\n\n![Other](img/crowbook.png)\n";
+    let mut book = Book::new();
+    book.add_chapter_from_source(Number::Unnumbered, chapter.as_bytes()).unwrap();
+    // Images with a title are only figures if rendering.figures is set
+    assert!(captions::collect(&book, Some(CaptionKind::Figure)).is_empty());
+    let mut html = vec![];
+    book.render_format_to("html", &mut html).unwrap();
+    assert!(!String::from_utf8(html).unwrap().contains("class = \"caption\""));
+
+    book.set_options(&[("rendering.figures", "true")]);
+    let figures = captions::collect(&book, Some(CaptionKind::Figure));
+    assert_eq!(figures.len(), 1);
+    test_eq(&figures[0].id, "figure-1");
+    test_eq(&figures[0].caption, "The logo");
+    let mut html = vec![];
+    book.render_format_to("html", &mut html).unwrap();
+    assert!(String::from_utf8(html).unwrap().contains("<p class = \"caption\">Figure 1: The logo</p>"));
+}
+
+#[test]
+fn caption_numbers_with_parts() {
+    let chapter = "# Chapter\n\n```rust {caption=\"Hello\"}\nfn main() {}\n```\n";
+    let mut book = Book::new();
+    book.add_chapter_from_source(Number::DefaultPart, "# One\n".as_bytes()).unwrap();
+    book.add_chapter_from_source(Number::Default, chapter.as_bytes()).unwrap();
+    book.add_chapter_from_source(Number::DefaultPart, "# Two\n".as_bytes()).unwrap();
+    book.add_chapter_from_source(Number::Default, chapter.as_bytes()).unwrap();
+    // Chapter numbers restart at each part, and so do the numbers of listings
+    let listings = captions::collect(&book, Some(CaptionKind::Listing));
+    assert_eq!(listings.len(), 2);
+    test_eq(&listings[0].number, "1.1");
+    test_eq(&listings[1].number, "1.1");
+    assert_eq!(listings[1].chapter, 3);
+    test_eq(&listings[0].id, "listing-1.1");
+    test_eq(&listings[1].id, "listing-1.1-2");
+
+    book.set_options(&[("rendering.part.reset_counter", "false")]);
+    let listings = captions::collect(&book, Some(CaptionKind::Listing));
+    test_eq(&listings[1].number, "2.1");
+}
+
+#[test]
+fn acronyms() {
+    let content = "---
+acronyms.HTTP: Hypertext Transfer Protocol
+---
+
+HTTP and HTTPS, but HTTP again.
+";
+    let mut book = Book::new();
+    book.read_markdown_config(content.as_bytes()).unwrap();
+    assert_eq!(book.chapters[0].content[0],
+               Token::Paragraph(vec![
+                   Token::Str(String::from("HTTP (Hypertext Transfer Protocol)")),
+                   Token::Str(String::from(" and HTTPS, but ")),
+                   Token::Abbreviation(String::from("Hypertext Transfer Protocol"),
+                                       vec![Token::Str(String::from("HTTP"))]),
+                   Token::Str(String::from(" again.")),
+               ]));
+}
+
+#[test]
+fn broken_links() {
+    let content = r#"---
+strict: true
+---
+
+```rust {id=hello}
+fn main() {}
+```
+
+See [](#hello) and [the doc](https://example.com).
+"#;
+    let mut book = Book::new();
+    assert!(book.read_markdown_config(content.as_bytes()).is_ok());
+
+    let content = "---
+strict: true
+---
+
+See [the setup](02-setup.md) and [](#nowhere).
+";
+    let mut book = Book::new();
+    assert!(book.read_markdown_config(content.as_bytes()).is_err());
+}
+
+#[test]
+fn sha256() {
+    use misc;
+    test_eq(&misc::sha256(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+    test_eq(&misc::sha256(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+}
+
+#[test]
+fn option_suggestions() {
+    use misc;
+    assert_eq!(misc::edit_distance("kitten", "sitting"), 3);
+    assert_eq!(misc::edit_distance("", "abc"), 3);
+
+    let mut book = Book::new();
+    let err = book.options.set("tex.paper.sise", "a4paper").unwrap_err();
+    assert!(format!("{}", err).contains("'tex.paper.size'"));
+    // Ties are broken by name: 'html.css' and 'html.js' are as close
+    for _ in 0..10 {
+        let err = book.options.set("html.ss", "foo").unwrap_err();
+        assert!(format!("{}", err).contains("'html.css'"));
+    }
+    // Renamed options still work, removed ones are ignored
+    book.options.set("tex.font_size", "12").unwrap();
+    assert_eq!(book.options.get_i32("tex.font.size").unwrap(), 12);
+    assert!(book.options.set("nb_char", "'~'").is_ok());
+}
+
+#[test]
+fn sections() {
+    use sections;
+    let mut book = Book::new();
+    book.set_options(&[("rendering.num_depth", "2")]);
+    book.add_chapter_from_source(Number::Default, "# One\n\n## Setup\n\n## Setup\n\n### Details\n".as_bytes())
+        .unwrap();
+    book.add_chapter_from_source(Number::Unnumbered, "# Notes\n\n## Thanks!\n".as_bytes())
+        .unwrap();
+    book.add_chapter_from_source(Number::Specified(5), "# Five\n\n## Usage\n".as_bytes())
+        .unwrap();
+    let sections = sections::collect(&book);
+    assert_eq!(sections.len(), 5);
+    test_eq(&sections[0].id, "setup");
+    test_eq(&sections[0].number, "1.1");
+    test_eq(&sections[1].id, "setup-2");
+    test_eq(&sections[1].number, "1.2");
+    test_eq(&sections[2].number, "");
+    assert_eq!(sections[2].level, 3);
+    test_eq(&sections[3].id, "thanks");
+    test_eq(&sections[3].number, "");
+    assert_eq!(sections[4].chapter, 2);
+    test_eq(&sections[4].number, "5.1");
+    test_eq(&sections[4].label("en"), "Section 5.1");
+}
+
+#[test]
+fn back_matter() {
+    let config = "
+title: Some title
+author: Someone
+author.bio: Someone writes *books*.
+rendering.about_author: true
+rendering.also_by.page: true
+rendering.also_by: [\"[First book](https://example.com/first)\", Second book]";
+    let mut book = Book::new();
+    book.read_config(config.as_bytes()).unwrap();
+    assert_eq!(book.chapters.len(), 2);
+    assert_eq!(book.chapters[0].number, Number::Unnumbered);
+    assert_eq!(book.chapters[0].content[0],
+               Token::Header(1, vec![Token::Str(String::from("About the author"))]));
+    assert_eq!(book.chapters[1].content[0],
+               Token::Header(1, vec![Token::Str(String::from("Also by this author"))]));
+    match book.chapters[1].content[1] {
+        Token::List(ref items) => assert_eq!(items.len(), 2),
+        ref token => panic!("expected a list, found {:?}", token),
+    }
+}
+
+#[test]
+fn sample() {
+    use sample::{self, SampleSize};
+    use std::mem;
+    assert_eq!(SampleSize::parse("3"), Some(SampleSize::Chapters(3)));
+    assert_eq!(SampleSize::parse("10%"), Some(SampleSize::Percent(10.0)));
+    assert_eq!(SampleSize::parse("0"), None);
+    assert_eq!(SampleSize::parse("150%"), None);
+
+    let chapters = |front_matter: bool| {
+        let mut book = Book::new();
+        book.add_chapter_from_source(Number::Unnumbered, "# Preface\n\nHello.\n".as_bytes())
+            .unwrap();
+        book.add_chapter_from_source(Number::Default, "# One\n\nA b c d.\n\nE f g h.\n".as_bytes())
+            .unwrap();
+        book.add_chapter_from_source(Number::Default, "# Two\n\nI j k l.\n".as_bytes())
+            .unwrap();
+        book.chapters[0].front_matter = front_matter;
+        mem::replace(&mut book.chapters, vec![])
+    };
+
+    // Front matter chapters are kept but not counted
+    let mut v = chapters(true);
+    sample::truncate(&mut v, SampleSize::Chapters(1));
+    assert_eq!(v.len(), 2);
+    let mut v = chapters(false);
+    sample::truncate(&mut v, SampleSize::Chapters(1));
+    assert_eq!(v.len(), 1);
+
+    // 30% of 14 words: the title and the first paragraph of chapter one
+    let mut v = chapters(true);
+    sample::truncate(&mut v, SampleSize::Percent(30.0));
+    assert_eq!(v.len(), 2);
+    assert_eq!(v[1].content.len(), 2);
+}
+
+#[test]
+fn editions() {
+    let source = "---\nedition: extended\n---\n\n# Bonus\n";
+    let mut book = Book::new();
+    book.add_chapter_from_source(Number::Default, source.as_bytes()).unwrap();
+    assert_eq!(book.chapters.len(), 1);
+
+    let mut book = Book::new();
+    book.set_options(&[("editions", "[standard]")]);
+    book.add_chapter_from_source(Number::Default, source.as_bytes()).unwrap();
+    assert_eq!(book.chapters.len(), 0);
+
+    let source = "# Chapter\n\n<!-- edition: extended, deluxe -->\nBonus.\n<!-- end edition -->\n";
+    book.add_chapter_from_source(Number::Default, source.as_bytes()).unwrap();
+    assert_eq!(book.chapters[0].content.len(), 1);
+    book.set_options(&[("editions", "[deluxe]")]);
+    book.add_chapter_from_source(Number::Default, source.as_bytes()).unwrap();
+    assert_eq!(book.chapters[1].content.len(), 2);
+
+    let source = "<!-- edition: extended -->\nBonus.\n";
+    assert!(book.add_chapter_from_source(Number::Default, source.as_bytes()).is_err());
+}
+
+#[test]
+fn exercises() {
+    use exercises;
+    let mut book = Book::new();
+    book.add_chapter_from_source(Number::Default,
+                                 "# One\n\n```exercise\nFirst.\n```\n\n```answer\nA.\n```\n\n\
+                                  ```exercise {id=ex-second}\nSecond.\n```\n"
+                                     .as_bytes())
+        .unwrap();
+    let list = captions::collect(&book, Some(CaptionKind::Exercise));
+    assert_eq!(list.len(), 2);
+    assert_eq!(list[0].id, "exercise-1.1");
+    assert_eq!(list[1].id, "ex-second");
+    assert_eq!(list[1].label("en"), "Exercise 1.2");
+
+    let answers = exercises::take_answers(&mut book.chapters);
+    assert_eq!(answers.len(), 2);
+    assert!(answers[0].is_some() && answers[1].is_none());
+    let content = exercises::answers_chapter("Answers", "en", &list, answers);
+    assert_eq!(content.len(), 3);
+    if let Token::Exercise(_, ref v) = book.chapters[0].content[1] {
+        assert_eq!(v.len(), 1);
+    } else {
+        panic!("exercise not found");
+    }
+}
+
+#[test]
+fn names() {
+    use names::NameReport;
+    let mut book = Book::new();
+    book.set_options(&[("names.Aragorn", "[Aragon]"), ("names.Minas Tirith", "[Minas Tirit]")]);
+    book.add_chapter_from_source(Number::Default,
+                                 "# One\n\nAragorn went to Minas Tirith.\n".as_bytes())
+        .unwrap();
+    assert!(NameReport::new(&book).is_empty());
+    book.add_chapter_from_source(Number::Default,
+                                 "# Two\n\nAragon and Aragorm left Minas Tirit.\n".as_bytes())
+        .unwrap();
+    let report = format!("{}", NameReport::new(&book));
+    assert!(report.contains("'Aragon' should be 'Aragorn'"));
+    assert!(report.contains("'Aragorm' might be a misspelling of 'Aragorn'"));
+    assert!(report.contains("'Minas Tirit' should be 'Minas Tirith'"));
+    assert!(report.contains("3 inconsistent name(s) found"));
+}
+
+#[test]
+fn timeline() {
+    use timeline::Timeline;
+    let mut book = Book::new();
+    book.add_chapter_from_source(Number::Default,
+                                 "---\npov: Sam\nstory_date: 3019-03-25\n---\n\n# Two\n".as_bytes())
+        .unwrap();
+    book.add_chapter_from_source(Number::Default,
+                                 "---\npov: Frodo\nstory_date: 3018-09-23\nlocation: Bag End\n---\n\n# One\n"
+                                     .as_bytes())
+        .unwrap();
+    assert_eq!(book.chapters[1].pov, Some(String::from("Frodo")));
+    assert_eq!(book.chapters[1].location, Some(String::from("Bag End")));
+
+    let mut timeline = Timeline::new(&book);
+    timeline.sort_by("date").unwrap();
+    let table = format!("{}", timeline);
+    assert!(table.find("One").unwrap() < table.find("Two").unwrap());
+    assert!(timeline.sort_by("weather").is_err());
+}
+
+#[test]
+fn dialogue() {
+    let mut book = Book::new();
+    book.set_options(&[("input.clean.dialogue", "dashes")]);
+    book.add_chapter_from_source(Number::Default,
+                                 "“Hello,” she said.\n\n“Not *this* one,” “nor this.”\n"
+                                     .as_bytes())
+        .unwrap();
+    // Chapters are kept as they were parsed
+    assert_eq!(book.chapters[0].content[1],
+               Token::Paragraph(vec![Token::Str(String::from("“Hello,” she said."))]));
+    let chapters = book.clean_chapters(true).unwrap().into_owned();
+    assert_eq!(chapters[0].content[1],
+               Token::Paragraph(vec![Token::Str(String::from("— Hello, she said."))]));
+    assert_eq!(chapters[0].content[2],
+               Token::Paragraph(vec![Token::Str(String::from("“Not ")),
+                                     Token::Emphasis(vec![Token::Str(String::from("this"))]),
+                                     Token::Str(String::from(" one,” “nor this.”"))]));
+
+    book.set_options(&[("input.clean.dialogue", "quotes")]);
+    book.add_chapter_from_source(Number::Default, "— Hello, she said.\n".as_bytes()).unwrap();
+    assert_eq!(book.clean_chapters(true).unwrap()[1].content[1],
+               Token::Paragraph(vec![Token::Str(String::from("“Hello, she said.”"))]));
+    assert_eq!(book.clean_chapters(false).unwrap()[1].content[1],
+               Token::Paragraph(vec![Token::Str(String::from("— Hello, she said."))]));
+}
+
+#[test]
+fn clean_chapter_borrowed() {
+    use std::borrow::Cow;
+    let mut book = Book::new();
+    book.set_options(&[("input.clean.smart_quotes", "false")]);
+    book.add_chapter_from_source(Number::Default, "Text.\n".as_bytes()).unwrap();
+    // Nothing to convert, so chapters are not copied
+    match book.clean_chapter(&book.chapters[0], true).unwrap() {
+        Cow::Borrowed(_) => {}
+        Cow::Owned(_) => panic!("chapter was copied"),
+    }
+    book.set_options(&[("input.clean.smart_quotes", "true")]);
+    match book.clean_chapter(&book.chapters[0], true).unwrap() {
+        Cow::Owned(_) => {}
+        Cow::Borrowed(_) => panic!("chapter was not cleaned"),
+    }
+}
+
+#[test]
+fn clean_typography() {
+    use cleaner;
+    assert_eq!(cleaner::dash_spacing("a — b — c", ""), "a—b—c");
+    assert_eq!(cleaner::dash_spacing("— Yes — no", " "), "— Yes — no");
+    assert_eq!(cleaner::typewriter("«\u{a0}Oui\u{a0}» — dit-il…"), "\"Oui\" -- dit-il...");
+
+    let mut book = Book::new();
+    book.set_options(&[("input.clean.ellipsis", "true"), ("input.clean.dashes.spacing", "none")]);
+    assert_eq!(book.clean("Well... maybe — or not.", false), "Well… maybe—or not.");
+    book.set_options(&[("input.clean.typewriter", "true")]);
+    assert_eq!(book.clean("Well… maybe — or not.", false), "Well... maybe--or not.");
+}
+
+#[test]
+fn vertical_writing() {
+    use cleaner;
+    use epub;
+    assert_eq!(cleaner::vertical("“Why?” (she asked)"), "〝Why？〟 （she asked）");
+
+    let mut book = Book::new();
+    book.set_options(&[("lang", "ja"), ("writing_mode", "vertical-rl")]);
+    assert_eq!(book.clean("本当!?", false), "本当！？");
+
+    let opf = "<package>\n  <metadata>\n  </metadata>\n  <spine toc=\"ncx\">\n  </spine>\n</package>";
+    let opf = epub::vertical_opf(opf);
+    assert!(opf.contains("<spine page-progression-direction=\"rtl\" toc=\"ncx\">"));
+    assert!(opf.contains("<meta name=\"primary-writing-mode\" content=\"vertical-rl\"/>"));
+}
+
+#[test]
+fn page_list_nav() {
+    use epub;
+    let nav = "<body>\n  <nav epub:type = \"toc\" id = \"toc\">\n  </nav>\n</body>\n</html>";
+    let page_list = "<nav epub:type = \"page-list\"></nav>\n";
+    test_eq(&epub::nav_page_list(nav, page_list),
+            "<body>\n  <nav epub:type = \"toc\" id = \"toc\">\n  </nav>\n<nav epub:type = \"page-list\"></nav>\n</body>\n</html>");
+}
+
+#[test]
+fn import_page_breaks() {
+    use page_breaks;
+    fn text(tokens: &[Token], s: &mut String) {
+        for token in tokens {
+            match *token {
+                Token::Str(ref t) => s.push_str(t),
+                Token::PageBreak(ref page) => s.push_str(&format!("|{}|", page)),
+                _ => if let Some(inner) = token.inner() {
+                    text(inner, s);
+                },
+            }
+        }
+    }
+
+    let mut book = Book::new();
+    book.add_chapter_from_source(Number::Default,
+                                 "# Title\n\nFirst page starts here and goes on. \
+                                  Second page *begins* right here and ends.\n".as_bytes())
+        .unwrap();
+    // Running head on the second page, a page that isn't in the book and
+    // a trailing form feed
+    let pdf = "Title\nFirst page starts here and goes on. Second\x0c\
+               12 My Book\npage begins right here and ends.\x0c\
+               Nowhere to be found\x0c";
+    let missing = page_breaks::add_page_breaks(&mut book.chapters, pdf, 11);
+    assert_eq!(missing, vec!["13".to_owned()]);
+
+    let mut s = String::new();
+    text(&book.chapters[0].content, &mut s);
+    test_eq(&s, "|11|TitleFirst page starts here and goes on. Second |12|page begins right here and ends.");
+}
+
+#[test]
+fn clean_per_format() {
+    let mut book = Book::new();
+    book.set_options(&[("input.clean.ellipsis", "true"), ("tex.clean", "false")]);
+    assert!(book.cleans("html"));
+    assert!(!book.cleans("tex"));
+    book.add_chapter_from_source(Number::Default, "\"Well...\"\n".as_bytes()).unwrap();
+    // Chapters are kept as they were parsed, and cleaned when rendered
+    assert_eq!(book.chapters[0].content[0],
+               Token::Paragraph(vec![Token::Str(String::from("\"Well...\""))]));
+
+    let mut html = vec![];
+    book.render_format_to("html", &mut html).unwrap();
+    assert!(String::from_utf8(html).unwrap().contains("“Well…”"));
+    let mut tex = vec![];
+    book.render_format_to("tex", &mut tex).unwrap();
+    let tex = String::from_utf8(tex).unwrap();
+    assert!(!tex.contains("“Well"));
+    assert!(!tex.contains("Well…"));
+
+    // Other formats have their own option too
+    let mut md = vec![];
+    book.render_format_to("md", &mut md).unwrap();
+    assert!(String::from_utf8(md).unwrap().contains("“Well…”"));
+    book.set_options(&[("md.clean", "false"), ("odt.clean", "false")]);
+    assert!(!book.cleans("odt"));
+    let mut md = vec![];
+    book.render_format_to("md", &mut md).unwrap();
+    assert!(String::from_utf8(md).unwrap().contains("\"Well...\""));
+}
+
+#[test]
+fn nbsp() {
+    use nbsp::NbspReport;
+    let mut book = Book::new();
+    book.add_chapter_from_source(Number::Default,
+                                 "Il dit\u{a0}: « Quoi\u{a0}?\u{a0}» et partit ; puis\u{a0}!\u{a0}rien."
+                                     .as_bytes())
+        .unwrap();
+    let report = format!("{}", NbspReport::new(&book));
+    assert!(report.contains("regular space after '«'"));
+    assert!(report.contains("regular space before ';'"));
+    assert!(report.contains("non-breaking space after '!'"));
+    assert!(report.contains("3 misplaced space(s) found"));
+
+    let mut book = Book::new();
+    book.add_chapter_from_source(Number::Default, "Il dit\u{a0}: «\u{a0}Quoi\u{a0}?\u{a0}»".as_bytes())
+        .unwrap();
+    assert!(NbspReport::new(&book).is_empty());
+}
+
+#[test]
+fn nested_quotes() {
+    let mut book = Book::new();
+    book.add_chapter_from_source(Number::Default,
+                                 "\"He said \"hi\" to me.\n\n\"Then he left.\"\n".as_bytes())
+        .unwrap();
+    {
+        let chapters = book.clean_chapters(true).unwrap();
+        assert_eq!(chapters[0].content[1],
+                   Token::Paragraph(vec![Token::Str(String::from("“He said ‘hi’ to me."))]));
+        assert_eq!(chapters[0].content[2],
+                   Token::Paragraph(vec![Token::Str(String::from("“Then he left.”"))]));
+    }
+
+    book.set_options(&[("lang", "fr")]);
+    book.add_chapter_from_source(Number::Default,
+                                 "\"Il m'a dit \"viens\" hier.\"\n".as_bytes())
+        .unwrap();
+    assert_eq!(book.clean_chapters(true).unwrap()[1].content[1],
+               Token::Paragraph(vec![Token::Str(String::from("«\u{a0}Il m'a dit “viens” hier.\u{a0}»"))]));
+}
+
+#[test]
+fn apostrophes() {
+    use cleaner;
+    let words = vec![String::from("'tis"), String::from("'n'")];
+    assert_eq!(cleaner::apostrophes("'Tis rock 'n' roll from the '90s, 'twas 'quoted'".into(), &words),
+               "’Tis rock ’n’ roll from the ’90s, 'twas 'quoted'");
+
+    let mut book = Book::new();
+    book.set_options(&[("input.clean.apostrophes", "[\"'twas\"]")]);
+    assert_eq!(book.clean("'twas 'cause", false), "’twas ’cause");
+}
+
+#[test]
+fn clean_numbers() {
+    use cleaner::{self, NumberFormat};
+    let fr = NumberFormat::new("fr");
+    assert_eq!(cleaner::numbers("En 1990, 12345678 km et 50 %, soit 3,14159 mp3", &fr, false),
+               "En 1990, 12\u{202f}345\u{202f}678\u{202f}km et 50\u{202f}%, soit 3,14159 mp3");
+    assert_eq!(cleaner::numbers("50 \\%", &fr, true), "50\\,\\%");
+    let en = NumberFormat::new("en");
+    assert_eq!(cleaner::numbers("the 1990s, 25000 s, 1,000.5 and 40 %", &en, false),
+               "the 1990s, 25,000\u{a0}s, 1,000.5 and 40%");
+}
+
+#[test]
+fn headings_case() {
+    let mut book = Book::new();
+    book.set_options(&[("rendering.headings.case", "title")]);
+    book.add_chapter_from_source(Number::Default,
+                                 "# the lord of the rings: the return of *the king* #\n".as_bytes())
+        .unwrap();
+    assert_eq!(book.chapters[0].content[0],
+               Token::Header(1,
+                             vec![Token::Str(String::from("The Lord of the Rings: The Return of ")),
+                                  Token::Emphasis(vec![Token::Str(String::from("the King"))])]));
+
+    book.set_options(&[("rendering.headings.case", "sentence"), ("names.Frodo", "[]")]);
+    book.add_chapter_from_source(Number::Default, "# A Journey With Frodo And NASA #\n".as_bytes())
+        .unwrap();
+    assert_eq!(book.chapters[1].content[0],
+               Token::Header(1, vec![Token::Str(String::from("A journey with Frodo and NASA"))]));
+
+    book.set_options(&[("rendering.headings.case", "upper")]);
+    assert!(book.add_chapter_from_source(Number::Default, "# Title #\n".as_bytes()).is_err());
+}
+
+#[test]
+fn builtin_pdf() {
+    let mut book = Book::new();
+    book.set_options(&[("pdf.engine", "builtin"), ("title", "Test (draft)")]);
+    book.add_chapter_from_source(Number::Default,
+                                 "# Title #\n\nSome *text*, with a footnote[^1].\n\n[^1]: Here.\n"
+                                     .as_bytes())
+        .unwrap();
+    let mut pdf = vec![];
+    book.render_format_to("pdf", &mut pdf).unwrap();
+    let pdf = String::from_utf8_lossy(&pdf);
+    assert!(pdf.starts_with("%PDF-1.4"));
+    assert!(pdf.ends_with("%%EOF\n"));
+    assert!(pdf.contains("/Title (Test \\(draft\\))"));
+    assert!(pdf.contains("(Some) Tj"));
+}
+
+#[test]
+fn builtin_pdf_images() {
+    let dir = env::temp_dir().join("crowbook-builtin-pdf");
+    fs::create_dir_all(&dir).unwrap();
+    let not_jpeg = dir.join("not_jpeg.jpg");
+    File::create(&not_jpeg).unwrap().write_all(b"GIF89a").unwrap();
+    let render = |file: &str| {
+        let mut book = Book::new();
+        book.set_options(&[("pdf.engine", "builtin")]);
+        book.add_chapter_from_source(Number::Default, format!("![An image]({})\n", file).as_bytes())
+            .unwrap();
+        let mut pdf = vec![];
+        let result = book.render_format_to("pdf", &mut pdf);
+        result.map(|_| String::from_utf8_lossy(&pdf).into_owned())
+    };
+    // A file that isn't a JPEG is replaced by its description...
+    assert!(render(not_jpeg.to_str().unwrap()).unwrap().contains("image"));
+    // ...but a file that can't be read is an error
+    assert!(render(dir.join("missing.jpg").to_str().unwrap()).is_err());
+}
+
+#[test]
+fn serial_feed() {
+    use feed::{self, FeedEntry};
+    let mut book = Book::new();
+    book.set_options(&[("title", "Serial"), ("publish.base_url", "https://example.com/book")]);
+    book.add_chapter_from_source(Number::Default,
+                                 "---\npublished: 2017-05-12\n---\n\n# One\n".as_bytes())
+        .unwrap();
+    assert_eq!(book.chapters[0].published, Some(String::from("2017-05-12")));
+
+    let entries = vec![FeedEntry {
+                           title: String::from("One"),
+                           file: String::from("chapter_000.html"),
+                           published: Some(String::from("2017-05-12")),
+                           summary: None,
+                           content: String::from("<p>First</p>"),
+                       },
+                       FeedEntry {
+                           title: String::from("Two"),
+                           file: String::from("chapter_001.html"),
+                           published: Some(String::from("2017-05-19")),
+                           summary: None,
+                           content: String::from("<p>Second</p>"),
+                       }];
+    let atom = feed::atom(&book, &entries).unwrap();
+    assert!(atom.contains("<updated>2017-05-19T00:00:00Z</updated>"));
+    assert!(atom.contains("<link href = \"https://example.com/book/chapter_000.html\" />"));
+    assert!(atom.contains("&lt;p&gt;First&lt;/p&gt;"));
+    assert!(atom.find("Two").unwrap() < atom.find("One").unwrap());
+}
+
+#[test]
+fn rss_feed() {
+    use feed::{self, FeedEntry};
+    let mut book = Book::new();
+    book.set_options(&[("title", "Serial")]);
+    let entries = vec![FeedEntry {
+                           title: String::from("One"),
+                           file: String::from("chapter_000.html"),
+                           published: Some(String::from("2017-05-12")),
+                           summary: None,
+                           content: String::from("<p>First</p>"),
+                       }];
+    let rss = feed::rss(&book, &entries).unwrap();
+    assert!(rss.contains("<rss version=\"2.0\">"));
+    assert!(rss.contains("<pubDate>12 May 2017 00:00:00 +0000</pubDate>"));
+    assert!(rss.contains("<link>chapter_000.html</link>"));
+}
+
+#[test]
+fn summaries() {
+    use text_view::view_as_text;
+    let mut book = Book::new();
+    book.add_chapter_from_source(Number::Default,
+                                 "# One #\n\nFirst[^1].\n\nSecond.\n\n<!-- more -->\n\nThird.\n\n[^1]: Note.\n"
+                                     .as_bytes())
+        .unwrap();
+    book.add_chapter_from_source(Number::Default, "# Two #\n\nFirst.\n\nSecond.\n".as_bytes())
+        .unwrap();
+    let summary = &book.chapters[0].summary;
+    assert_eq!(summary.len(), 2);
+    assert_eq!(view_as_text(&summary[..1]), "First.");
+    assert_eq!(book.chapters[1].summary,
+               vec![Token::Paragraph(vec![Token::Str(String::from("First."))])]);
+}
+
+#[test]
+fn epub_size() {
+    use epub_size::{self, EpubSize};
+    let mut size = EpubSize::new();
+    size.add_resource("images/image_0.JPG", 2_500_000);
+    size.add_resource("fonts/serif.otf", 300_000);
+    size.add_resource("data/notes.txt", 1200);
+    assert_eq!(size.images_size(), 2_500_000);
+    assert_eq!(size.fonts, 300_000);
+    assert_eq!(size.other, 1200);
+    assert_eq!(epub_size::human_size(2_500_000), "2.5 MB");
+    assert_eq!(epub_size::human_size(1200), "1 kB");
+    assert_eq!(epub_size::human_size(12), "12 B");
+}
+
+#[test]
+fn retail_metadata() {
+    let mut book = Book::new();
+    book.set_options(&[("title", "Dragons & Co"),
+                       ("author", "Jane Doe"),
+                       ("lang", "fr"),
+                       ("date", "2017-05-12"),
+                       ("retail.isbn", "978-2-0000-0000-0"),
+                       ("retail.categories", "[FIC009000, FIC009020]")]);
+    let onix = book.retail_metadata("onix").unwrap();
+    assert!(onix.contains("<TitleText>Dragons &amp; Co</TitleText>"));
+    assert!(onix.contains("<IDValue>9782000000000</IDValue>"));
+    assert!(onix.contains("<LanguageCode>fre</LanguageCode>"));
+    assert!(onix.contains("<Date>20170512</Date>"));
+    assert!(onix.contains("<PublisherName>Jane Doe</PublisherName>"));
+    assert_eq!(onix.matches("<MainSubject/>").count(), 1);
+
+    let csv = book.retail_metadata("kdp").unwrap();
+    assert!(csv.starts_with("\"Language\",\"Title\","));
+    assert!(csv.contains("\"fr\",\"Dragons & Co\",\"\",\"Jane Doe\""));
+    assert!(book.retail_metadata("xml").is_err());
+}
+
+#[test]
+fn calibre_metadata() {
+    use calibre;
+    let mut book = Book::new();
+    book.set_options(&[("title", "Dragons & Co"),
+                       ("author", "Jane Doe"),
+                       ("lang", "fr"),
+                       ("retail.isbn", "978-2-0000-0000-0"),
+                       ("retail.keywords", "[dragons, fantasy]")]);
+    let opf = calibre::opf(&book, Some("cover.jpg")).unwrap();
+    assert!(opf.contains("<dc:title>Dragons &amp; Co</dc:title>"));
+    assert!(opf.contains("<dc:creator opf:role=\"aut\">Jane Doe</dc:creator>"));
+    assert!(opf.contains("<dc:identifier opf:scheme=\"ISBN\">978-2-0000-0000-0</dc:identifier>"));
+    assert!(opf.contains("<dc:subject>fantasy</dc:subject>"));
+    assert!(opf.contains("<reference type=\"cover\" title=\"Cover\" href=\"cover.jpg\"/>"));
+    assert!(!calibre::opf(&book, None).unwrap().contains("<guide>"));
+}
+
+#[test]
+fn watermark() {
+    let mut book = Book::new();
+    book.set_options(&[("pdf.engine", "builtin"), ("watermark", "For Jane (ARC)")]);
+    book.add_chapter_from_source(Number::Default, "# Title #\n\nSome text.\n".as_bytes())
+        .unwrap();
+    let mut pdf = vec![];
+    book.render_format_to("pdf", &mut pdf).unwrap();
+    let pdf = String::from_utf8_lossy(&pdf);
+    assert!(pdf.contains("(For Jane \\(ARC\\)) Tj"));
+    assert!(pdf.contains(" Tm (For Jane"));
+
+    book.set_options(&[("watermark.pdf", "footer")]);
+    let mut pdf = vec![];
+    book.render_format_to("pdf", &mut pdf).unwrap();
+    assert!(!String::from_utf8_lossy(&pdf).contains(" Tm (For Jane"));
+
+    book.set_options(&[("watermark.pdf", "header")]);
+    assert!(book.render_format_to("pdf", &mut vec![]).is_err());
+}
+
+#[test]
+fn split() {
+    use split::Split;
+    let split = Split::new("---
+title: Foo
+---
+
+Some preface.
+
+# The *Journey* #
+
+```
+# not a chapter
+```
+
+Text.
+
+Home
+====
+
+The end.
+");
+    assert_eq!(split.yaml, Some(String::from("title: Foo")));
+    let files: Vec<_> = split.chapters.iter().map(|c| c.file.as_str()).collect();
+    assert_eq!(files, vec!["00-preamble.md", "01-the-journey.md", "02-home.md"]);
+    assert!(!split.chapters[0].numbered);
+    assert_eq!(split.chapters[0].content, "Some preface.\n");
+    assert!(split.chapters[1].content.contains("# not a chapter"));
+    assert_eq!(split.chapters[2].content, "Home\n====\n\nThe end.\n");
+}
+
+#[test]
+fn chapter_title() {
+    let mut book = Book::new();
+    book.add_chapter_from_source(Number::Default,
+                                 "---\nchapter_title: The *Long* Road\n---\n\n# Draft #\n\nText.\n"
+                                     .as_bytes())
+        .unwrap();
+    assert_eq!(book.chapters[0].content[0],
+               Token::Header(1, vec![Token::Str(String::from("The ")),
+                                     Token::Emphasis(vec![Token::Str(String::from("Long"))]),
+                                     Token::Str(String::from(" Road"))]));
+    assert!(!book.chapters[0].content.contains(&Token::Header(1, vec![Token::Str(String::from("Draft"))])));
+
+    book.add_chapter_from_source(Number::Default,
+                                 "---\nchapter_title: Road\nchapter_title.keep_heading: true\n---\n\n# Draft #\n"
+                                     .as_bytes())
+        .unwrap();
+    assert_eq!(book.chapters[1].content[0],
+               Token::Header(1, vec![Token::Str(String::from("Road"))]));
+    assert_eq!(book.chapters[1].content[1],
+               Token::Header(2, vec![Token::Str(String::from("Draft"))]));
+}
+
+#[test]
+fn chapters_at_headings() {
+    let mut book = Book::new();
+    book.set_options(&[("input.chapters", "heading")]);
+    book.add_chapter_from_source(Number::Specified(3),
+                                 "Preface.\n\n# One #\n\nText.\n\n## Section ##\n\n# Two #\n\nEnd.\n"
+                                     .as_bytes())
+        .unwrap();
+    assert_eq!(book.chapters.len(), 2);
+    assert_eq!(book.chapters[0].number, Number::Specified(3));
+    assert_eq!(book.chapters[1].number, Number::Default);
+    assert_eq!(book.chapters[0].content[0],
+               Token::Paragraph(vec![Token::Str(String::from("Preface."))]));
+    assert!(book.chapters[0].content.contains(&Token::Header(2, vec![Token::Str(String::from("Section"))])));
+    assert_eq!(book.chapters[1].content[0],
+               Token::Header(1, vec![Token::Str(String::from("Two"))]));
+}
+
+#[test]
+fn docbook() {
+    let mut book = Book::new();
+    book.set_options(&[("title", "Foo"), ("author", "Joan Doe")]);
+    book.add_chapter_from_source(Number::Default,
+                                 "# One #\n\nSome *text*.\n\n## Section ##\n\n* item\n".as_bytes())
+        .unwrap();
+    book.add_chapter_from_source(Number::Unnumbered, "# Two #\n\n[back](#section)\n".as_bytes())
+        .unwrap();
+    let mut xml = vec![];
+    book.render_format_to("docbook", &mut xml).unwrap();
+    let xml = String::from_utf8(xml).unwrap();
+    assert!(xml.contains("<info>\n<title>Foo</title>\n"));
+    assert!(xml.contains("<personname>Joan Doe</personname>"));
+    assert!(xml.contains("<chapter xml:id=\"chapter-0\">\n<title>One</title>\n\
+                          <para>Some <emphasis>text</emphasis>.</para>\n\
+                          <section xml:id=\"section\">\n<title>Section</title>\n\
+                          <itemizedlist>\n<listitem><para>item</para></listitem>\n\
+                          </itemizedlist>\n</section>\n</chapter>\n"));
+    assert!(xml.contains("<chapter xml:id=\"chapter-1\" role=\"unnumbered\">"));
+    assert!(xml.contains("<link linkend=\"section\">back</link>"));
+    assert!(xml.ends_with("</book>\n"));
+}
+
+#[test]
+fn template_version() {
+    use templates::{html_single, html_dir, epub, epub3, latex, slides, version};
+    assert!(version::missing("html.standalone.template", html_single::HTML).is_empty());
+    assert!(version::missing("html.dir.template", html_dir::TEMPLATE).is_empty());
+    assert!(version::missing("epub.chapter.xhtml", epub::TEMPLATE).is_empty());
+    assert!(version::missing("epub.chapter.xhtml", epub3::TEMPLATE).is_empty());
+    assert!(version::missing("tex.template", latex::TEMPLATE).is_empty());
+    assert!(version::missing("tex.template", latex::BEAMER).is_empty());
+    assert!(version::missing("slides.template", slides::TEMPLATE).is_empty());
+    assert_eq!(version::of(latex::TEMPLATE), version::VERSION);
+
+    let old = "{{=<< >>=}}<<&content>> <<#foo>><<&additional_code>><</foo>>";
+    assert_eq!(version::missing("tex.template", old),
+               vec!["watermark_diagonal", "watermark_footer", "use_admonitions", "use_columns"]);
+    let stamped = "{{=<< >>=}}% crowbook-template: 2\n<<&content>> <<&additional_code>>";
+    assert_eq!(version::missing("tex.template", stamped),
+               vec!["use_admonitions", "use_columns"]);
+    let stamped = "{{=<< >>=}}% crowbook-template: 3\n<<&content>> <<#use_columns>>x<</use_columns>>";
+    assert!(version::missing("tex.template", stamped).is_empty());
+    let stamped = "{{=<< >>=}}% crowbook-template: 4\n<<&content>> <<&additional_code>>";
+    assert!(version::missing("tex.template", stamped).is_empty());
+    // Unstamped templates are written for the first version, so they are not
+    // expected to use its variables
+    assert_eq!(version::missing("tex.template", "\\begin{document}"),
+               vec!["watermark_diagonal", "watermark_footer", "use_admonitions", "use_columns"]);
+    assert!(version::missing("html.standalone.template", "<body>{{{content}}}</body>").is_empty());
+}
+
+#[test]
+fn degradation_report() {
+    use degradation;
+    let mut book = Book::new();
+    book.add_chapter_from_source(Number::Default,
+                                 "Some text[^>1].\n\n[^>1]: A margin note.\n".as_bytes())
+        .unwrap();
+    assert!(degradation::report(&book, "html").is_empty());
+    assert_eq!(degradation::report(&book, "epub"),
+               vec![lformat!("margin notes (displayed as footnotes)")]);
+    book.set_options(&[("epub.version", "3")]);
+    assert_eq!(degradation::report(&book, "epub").len(), 1);
+    let mut xml = vec![];
+    let result = book.render_format_to("docbook", &mut xml).unwrap();
+    assert_eq!(result.degraded, vec![lformat!("margin notes (displayed as footnotes)")]);
+}
+
+#[test]
+fn txt() {
+    let mut book = Book::new();
+    book.set_options(&[("title", "Foo"), ("author", "Joan Doe"), ("txt.width", "20")]);
+    book.add_chapter_from_source(Number::Unnumbered,
+                                 "# One #\n\nSome *text* that is long enough to wrap.\n"
+                                     .as_bytes())
+        .unwrap();
+    book.add_chapter_from_source(Number::Unnumbered, "A note[^1].\n\n[^1]: Here.\n".as_bytes())
+        .unwrap();
+    let mut txt = vec![];
+    book.render_format_to("txt", &mut txt).unwrap();
+    let txt = String::from_utf8(txt).unwrap();
+    assert!(txt.starts_with("Foo\n===\n\nJoan Doe\n\n\nOne\n===\n\n\
+                             Some _text_ that is\nlong enough to wrap.\n"));
+    assert!(txt.ends_with("A note[1].\n\n[1] Here.\n"));
+}
+
+#[test]
+fn markdown() {
+    let mut book = Book::new();
+    book.set_options(&[("title", "Foo \"Bar\""), ("crowbook.markdown.superscript", "true")]);
+    book.add_chapter_from_source(Number::Default,
+                                 "# One #\n\nSome *text*[^1] and 2^nd^ [x] a_b.\n\n\
+                                  [^1]: A note.\n"
+                                     .as_bytes())
+        .unwrap();
+    book.add_chapter_from_source(Number::Default, "# Two #\n\n- a\n- b\n".as_bytes()).unwrap();
+    let mut md = vec![];
+    book.render_format_to("md", &mut md).unwrap();
+    let md = String::from_utf8(md).unwrap();
+    assert!(md.starts_with("---\ntitle: \"Foo \\\"Bar\\\"\"\n"));
+    assert!(md.contains("---\n\n# One\n\nSome *text*[^1] and 2^nd^ \\[x\\] a\\_b.\n\n\
+                         [^1]: A note.\n\n# Two\n\n- a\n- b\n"));
+}
+
+#[test]
+fn ast() {
+    use rustc_serialize::json::Json;
+
+    let mut book = Book::new();
+    book.set_options(&[("title", "Foo"), ("lang", "en")]);
+    book.add_chapter_from_source(Number::Specified(3), "# One #\n\nSome *text*.\n".as_bytes())
+        .unwrap();
+    let mut json = vec![];
+    book.render_format_to("ast", &mut json).unwrap();
+    let json = Json::from_str(&String::from_utf8(json).unwrap()).unwrap();
+    assert_eq!(json.find_path(&["metadata", "title"]).unwrap().as_string(), Some("Foo"));
+    let chapter = &json.find("chapters").unwrap().as_array().unwrap()[0];
+    assert_eq!(chapter.find_path(&["number", "kind"]).unwrap().as_string(), Some("specified"));
+    assert_eq!(chapter.find_path(&["number", "value"]).unwrap().as_i64(), Some(3));
+    let content = chapter.find("content").unwrap().as_array().unwrap();
+    assert_eq!(content[0].find("type").unwrap().as_string(), Some("header"));
+    assert_eq!(content[0].find("level").unwrap().as_i64(), Some(1));
+    let paragraph = content[1].find("content").unwrap().as_array().unwrap();
+    assert_eq!(paragraph[1].find("type").unwrap().as_string(), Some("emphasis"));
+}
+
+#[test]
+fn html_self_contained() {
+    let mut book = Book::new();
+    book.set_options(&[("html.standalone.self_contained", "true"),
+                       ("html.css.add", ".a { background: url('templates/img/book.svg'); }
+.b { background: url(http://example.org/b.png); }")]);
+    book.add_chapter_from_source(Number::Default, "# Title\n\nText.\n".as_bytes()).unwrap();
+    let mut html = vec![];
+    book.render_format_to("html", &mut html).unwrap();
+    let html = String::from_utf8(html).unwrap();
+    assert!(html.contains(".a { background: url(\"data:image/svg+xml;base64,"));
+    assert!(html.contains(".b { background: url(http://example.org/b.png); }"));
+}
+
+#[test]
+fn slides() {
+    let mut book = Book::new();
+    book.set_options(&[("title", "Course")]);
+    // Text between two `---` rules must not be taken for a YAML block
+    book.add_chapter_from_source(Number::Default,
+                                 "# Intro\n\nHello.\n\n## First\n\nOne.\n\n---\n\nTwo.\n\n\
+                                  ---\n\nThree.\n"
+                                     .as_bytes())
+        .unwrap();
+    let mut html = vec![];
+    book.render_format_to("slides", &mut html).unwrap();
+    let html = String::from_utf8(html).unwrap();
+    assert!(html.contains("<h1>Course</h1>"));
+    assert!(html.contains("<section id = \"chapter-0\">"));
+    // Title and introduction, first section, and the slides after each rule
+    assert_eq!(html.matches("<section>").count(), 4);
+    assert!(html.contains("Two.") && html.contains("Three."));
+    assert!(html.contains("reveal.js@4/dist/reveal.js"));
+}
+
+#[test]
+fn latex_links() {
+    use latex::escape_url;
+    test_eq(&escape_url("http://example.com/a_b?x=50%#top"), "http://example.com/a_b?x=50\\%\\#top");
+    test_eq(&escape_url("a{b}\\c"), "a\\%7Bb\\%7D\\%5Cc");
+
+    let mut book = Book::new();
+    book.set_options(&[("tex.links.qrcode", "true")]);
+    book.add_chapter_from_source(Number::Default,
+                                 "See [this page](http://example.com/a_b?x=50%#top).\n"
+                                     .as_bytes())
+        .unwrap();
+    let mut tex = vec![];
+    book.render_format_to("tex", &mut tex).unwrap();
+    let tex = String::from_utf8(tex).unwrap();
+    // '#' and '%' are escaped in the footnote, but the URL isn't escaped as text
+    let url = "http://example.com/a_b?x=50\\%\\#top";
+    assert!(tex.contains(&format!("\\href{{{url}}}{{this page}}\\protect\\footnote{{\\mdqrcode{{{url}}} \\url{{{url}}}}}",
+                                  url = url)));
+}
+
+#[test]
+fn beamer() {
+    let mut book = Book::new();
+    book.set_options(&[("tex.class", "beamer")]);
+    book.add_chapter_from_source(Number::Default,
+                                 "# Intro\n\nHello.\n\n## First\n\nOne.\n\n***\n\nTwo.\n"
+                                     .as_bytes())
+        .unwrap();
+    let mut tex = vec![];
+    book.render_format_to("tex", &mut tex).unwrap();
+    let tex = String::from_utf8(tex).unwrap();
+    assert!(tex.contains("{beamer}"));
+    assert!(tex.contains("\\section{Intro}"));
+    assert!(tex.contains("\\begin{frame}[fragile]{Intro}\n\\label{chapter-0}\nHello."));
+    assert!(tex.contains("\\begin{frame}[fragile]{First}\n"));
+    assert_eq!(tex.matches("\\begin{frame}[fragile]").count(), 3);
+}
+
+/// Loads a markdown book in strict mode, returning whether it succeeded
+fn load_strict_markdown(content: &str) -> bool {
+    let mut book = Book::new();
+    book.set_verbosity(InfoLevel::Error);
+    let content = format!("---\nstrict: true\n{}", content);
+    book.read_markdown_config(content.as_bytes()).is_ok()
+}
+
+/// Writes a book containing `chapter` in a temporary directory and loads it
+/// in strict mode, returning whether it succeeded
+fn load_strict_chapter(name: &str, chapter: &str, content: &str) -> bool {
+    let dir = env::temp_dir().join(format!("crowbook-strict-{}", name));
+    fs::create_dir_all(&dir).unwrap();
+    File::create(dir.join(chapter)).unwrap().write_all(content.as_bytes()).unwrap();
+    File::create(dir.join("book.yml"))
+        .unwrap()
+        .write_all(format!("strict: true\n\n+ {}\n", chapter).as_bytes())
+        .unwrap();
+    let mut book = Book::new();
+    book.set_verbosity(InfoLevel::Error);
+    let res = book.load_file(dir.join("book.yml")).is_ok();
+    fs::remove_dir_all(&dir).unwrap();
+    res
+}
+
+#[test]
+fn strict_options() {
+    assert!(load_strict_markdown("---\n\n# Title\n"));
+    // Renamed option
+    assert!(!load_strict_markdown("use_initials: true\n---\n\n# Title\n"));
+    // Removed option
+    assert!(!load_strict_markdown("nb_char: '~'\n---\n\n# Title\n"));
+
+    let mut book = Book::new();
+    assert!(book.options.set("nb_char", "'~'").unwrap().is_none());
+    book.options.set("strict", "true").unwrap();
+    assert!(book.options.set("nb_char", "'~'").is_err());
+}
+
+#[test]
+fn strict_code_block() {
+    assert!(load_strict_markdown("---\n\n```python\nx = 1\n```\n"));
+    assert!(!load_strict_markdown("---\n\n```{.python}\nx = 1\n```\n"));
+}
+
+#[test]
+fn strict_rst() {
+    assert!(load_strict_chapter("rst-ok", "chapter.rst", "Title\n=====\n\nText.\n"));
+    assert!(!load_strict_chapter("rst-directive",
+                                 "chapter.rst",
+                                 "Title\n=====\n\n.. unknown:: foo\n"));
+    assert!(!load_strict_chapter("rst-substitution",
+                                 "chapter.rst",
+                                 "Title\n=====\n\n.. |name| replace:: foo\n"));
+    assert!(!load_strict_chapter("rst-table",
+                                 "chapter.rst",
+                                 "Title\n=====\n\n+---+\n| a |\n+---+\n"));
+}
+
+#[test]
+fn strict_html_import() {
+    assert!(load_strict_chapter("html-ok", "chapter.html", "<h1>Title</h1><p>Text</p>"));
+    assert!(!load_strict_chapter("html-image",
+                                 "chapter.html",
+                                 "<h1>Title</h1><p><img src=\"data:image/png;base64,AAAA\"></p>"));
+    assert!(!load_strict_chapter("html-cell",
+                                 "chapter.html",
+                                 "<h1>Title</h1><table><tr><td><ul><li>a</li></ul></td></tr>\
+                                  </table>"));
+}
+
+#[test]
+fn versioned_output_path() {
+    let mut book = Book::new();
+    book.set_verbosity(InfoLevel::Error);
+    assert_eq!(book.output_path("epub").unwrap(), None);
+    book.set_options(&[("output.epub", "/tmp/out/book.epub"),
+                       ("output.html.dir", "/tmp/out/book")]);
+    assert_eq!(book.output_path("epub").unwrap(), Some(PathBuf::from("/tmp/out/book.epub")));
+
+    // The version is required
+    book.set_options(&[("output.versioned", "true")]);
+    assert!(book.output_path("epub").is_err());
+
+    book.set_options(&[("version", "1.2.0")]);
+    assert_eq!(book.output_path("epub").unwrap(),
+               Some(PathBuf::from("/tmp/out/book-1.2.0.epub")));
+    assert_eq!(book.output_path("html.dir").unwrap(),
+               Some(PathBuf::from("/tmp/out/book-1.2.0")));
+    assert_eq!(book.output_path("html").unwrap(), None);
+}
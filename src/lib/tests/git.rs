@@ -0,0 +1,52 @@
+use git::{self, GitInfo, Commit};
+
+use std::path::Path;
+
+fn info(tag: Option<&str>, dirty: bool) -> GitInfo {
+    GitInfo {
+        commit: String::from("3f2a1bc9d0e4f5a6b7c8d9e0f1a2b3c4d5e6f7a8"),
+        short_commit: String::from("3f2a1bc"),
+        tag: tag.map(|s| s.to_owned()),
+        dirty: dirty,
+    }
+}
+
+#[test]
+fn git_revision() {
+    assert_eq!(info(None, false).revision(), "3f2a1bc");
+    assert_eq!(info(None, true).revision(), "3f2a1bc-dirty");
+    assert_eq!(info(Some("v1.2"), false).revision(), "v1.2");
+    assert_eq!(info(Some("v1.2"), true).revision(), "v1.2-dirty");
+}
+
+#[test]
+fn git_info_outside_repository() {
+    assert_eq!(GitInfo::new(Path::new("/this/directory/does/not/exist")), None);
+}
+
+#[test]
+fn git_parse_log() {
+    let output = "3f2a1bc\x1f2017-03-02\x1fJane Doe\x1fFix typo in chapter 2\n\
+                  not a commit line\n\
+                  9e0d4c7\x1f2017-02-28\x1fJohn Smith\x1fAdd chapter 2\n";
+    assert_eq!(git::parse_log(output),
+               vec![Commit {
+                        short_commit: String::from("3f2a1bc"),
+                        date: String::from("2017-03-02"),
+                        author: String::from("Jane Doe"),
+                        subject: String::from("Fix typo in chapter 2"),
+                    },
+                    Commit {
+                        short_commit: String::from("9e0d4c7"),
+                        date: String::from("2017-02-28"),
+                        author: String::from("John Smith"),
+                        subject: String::from("Add chapter 2"),
+                    }]);
+    assert_eq!(git::parse_log(""), vec![]);
+}
+
+#[test]
+fn git_log_outside_repository() {
+    assert_eq!(git::log(Path::new("/this/directory/does/not/exist"), &["README.md"], 10),
+               None);
+}
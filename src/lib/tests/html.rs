@@ -68,3 +68,186 @@ Some paragraph
     println!("actual:\n {}", actual);
     test_eq(&actual, &expected);
 }
+
+#[test]
+fn scene_break() {
+    let mut book = Book::new();
+    book.set_options(&[("rendering.scene_break", "asterism")]);
+    {
+        let mut html = HtmlRenderer::new(&book, "").unwrap();
+        test_eq(&html.render_token(&Token::Rule).unwrap(),
+                "<p class = \"rule\">⁂</p>\n");
+    }
+
+    book.set_options(&[("rendering.scene_break", "foo")]);
+    let mut html = HtmlRenderer::new(&book, "").unwrap();
+    assert!(html.render_token(&Token::Rule).is_err());
+}
+
+#[test]
+fn footnote_symbols() {
+    let mut book = Book::new();
+    book.set_options(&[("rendering.footnotes.style", "symbols")]);
+    let note = Token::Footnote(vec![Token::Str(String::from("note"))]);
+    let mut html = HtmlRenderer::new(&book, "").unwrap();
+    let mut last = String::new();
+    for _ in 0..8 {
+        last = html.render_token(&note).unwrap();
+    }
+    test_eq(&last,
+            "<a href = \"#note-dest-8\"><sup id = \"note-source-8\">[††]</sup></a>");
+}
+
+#[test]
+fn margin_notes() {
+    let book = Book::new();
+    let note = Token::Footnote(vec![Token::Str(String::from("note"))]);
+    let margin = Token::MarginNote(vec![Token::Paragraph(vec![Token::Str(String::from("aside"))])]);
+    let mut html = HtmlRenderer::new(&book, "").unwrap();
+    html.render_token(&note).unwrap();
+    test_eq(&html.render_token(&margin).unwrap(),
+            "<span class = \"marginnote-ref\"><a href = \"#marginnote-dest-1\"><sup id = \
+             \"marginnote-source-1\">[a]</sup></a></span><span class = \"marginnote\">aside</span>");
+    // Margin notes don't take a footnote number
+    test_eq(&html.render_token(&note).unwrap(),
+            "<a href = \"#note-dest-2\"><sup id = \"note-source-2\">[2]</sup></a>");
+}
+
+#[test]
+fn media() {
+    use images;
+    assert_eq!(images::media_kind("music/song.MP3"), Some("audio"));
+    assert_eq!(images::media_kind("clip.webm"), Some("video"));
+    assert_eq!(images::media_kind("clip.ogv"), Some("video"));
+    assert_eq!(images::media_kind("image.png"), None);
+    assert_eq!(images::media_kind("mp3"), None);
+
+    let book = Book::new();
+    let mut html = HtmlRenderer::new(&book, "").unwrap();
+    let song = Token::StandaloneImage(String::from("https://example.org/song.mp3"),
+                                      String::new(),
+                                      String::new(),
+                                      vec![Token::Str(String::from("Song"))]);
+    test_eq(&html.render_token(&song).unwrap(),
+            "<div class = \"media\">\n<audio src = \"https://example.org/song.mp3\" \
+             controls = \"controls\">\n  <a href = \"https://example.org/song.mp3\">Song</a>\n\
+             </audio>\n</div>\n");
+    let clip = Token::Image(String::from("https://example.org/clip.mp4"),
+                            String::from("A clip"),
+                            String::from("width=50%"),
+                            vec![Token::Str(String::from("Clip"))]);
+    test_eq(&html.render_token(&clip).unwrap(),
+            "<video src = \"https://example.org/clip.mp4\" controls = \"controls\" \
+             title = \"A clip\" style = \"width: 50%\">\n  <a href = \
+             \"https://example.org/clip.mp4\">Clip</a>\n</video>");
+}
+
+#[test]
+fn notes_mode() {
+    let mut book = Book::new();
+    book.set_options(&[("rendering.notes", "end_of_book")]);
+    assert!(HtmlRenderer::new(&book, "").is_ok());
+    book.set_options(&[("rendering.notes", "foo")]);
+    assert!(HtmlRenderer::new(&book, "").is_err());
+}
+
+#[test]
+fn code_line_numbers() {
+    let doc = "
+```rust {linenos, hl=2}
+let x = 1;
+let y = &x;
+```
+";
+    let expected = "<pre class = \"code-lines\"><code class = \"nohighlight\">\
+<span class = \"code-line\"><span class = \"line-number\">1</span>let x = 1;\n</span>\
+<span class = \"code-line highlighted\"><span class = \"line-number\">2</span>let y = &amp;x;\n</span>\
+</code></pre>\n";
+    let actual = ast_to_html(&Parser::new().parse(doc).unwrap());
+    test_eq(&actual, expected);
+}
+
+#[test]
+fn chapter_links() {
+    use resource_handler::ResourceHandler;
+    use logger::Logger;
+    let logger = Logger::new();
+    let mut handler = ResourceHandler::new(&logger);
+    handler.add_link("02-setup.md", "#chapter-2");
+    handler.add_link("#hello", "#hello");
+    test_eq(&handler.get_link("02-setup.md"), "#chapter-2");
+    test_eq(&handler.get_link("02-setup.md#install"), "#install");
+    test_eq(&handler.get_link("02-setup.html#hello"), "#hello");
+    assert!(handler.contains_link("02-setup.md#install"));
+
+    handler.add_link("02-setup.md", "chapter_2.html");
+    test_eq(&handler.get_link("02-setup.md#install"), "chapter_2.html#install");
+
+    handler.set_fragments(false);
+    handler.add_link("02-setup.md", "chapter-2");
+    test_eq(&handler.get_link("02-setup.md#install"), "chapter-2");
+}
+
+#[test]
+fn fingerprint() {
+    use misc;
+    let name = misc::fingerprint("images/image_0.png", b"content");
+    assert!(name.starts_with("images/image_0."));
+    assert!(name.ends_with(".png"));
+    assert_eq!(name.len(), "images/image_0.png".len() + 9);
+    assert!(name != misc::fingerprint("images/image_0.png", b"other content"));
+}
+
+#[test]
+fn minify() {
+    use minify;
+    assert_eq!(minify::html("<div>\n  <!-- comment -->\n  <p>Some   <em>text</em></p>\n</div>\n<pre>a\n  b</pre>"),
+               "<div>\n<p>Some <em>text</em></p>\n</div>\n<pre>a\n  b</pre>");
+    assert_eq!(minify::css("/* comment */\np, li {\n    margin: 0 auto;\n    content: \"a  b\";\n}\na :hover { color: red; }\n"),
+               "p,li{margin:0 auto;content:\"a  b\"}a :hover{color:red}");
+}
+
+#[test]
+fn append_css() {
+    use std::borrow::Cow;
+    let mut book = Book::new();
+    book.set_options(&[("html.css.add", "h1 { color: red; }")]);
+    let html = HtmlRenderer::new(&book, "").unwrap();
+    assert_eq!(html.append_css(Cow::Borrowed("p { margin: 0; }"), "html.css.add"),
+               "p { margin: 0; }\nh1 { color: red; }\n");
+    assert_eq!(html.append_css(Cow::Borrowed("p { margin: 0; }"), "epub.css.add"),
+               "p { margin: 0; }");
+}
+
+#[test]
+fn kepub_sentences() {
+    use kepub::sentences;
+    assert_eq!(sentences("One. Two! Three"), vec!["One. ", "Two! ", "Three"]);
+    // Entities are not split, and a punctuation mark without whitespace
+    // after it doesn't end a sentence
+    assert_eq!(sentences("Dr.&#160;Who? Yes&hellip; 3.5 m."),
+               vec!["Dr.&#160;Who? ", "Yes&hellip; 3.5 m."]);
+}
+
+#[test]
+fn kepub_spans() {
+    use kepub::add_spans;
+    let xhtml = "<html><body><p title = \"a > b\">One. Two &amp; three.</p></body></html>";
+    test_eq(&add_spans(xhtml),
+            "<html><body><div id = \"book-columns\"><div id = \"book-inner\">\
+             <p title = \"a > b\">\
+             <span class = \"koboSpan\" id = \"kobo.1.1\">One. </span>\
+             <span class = \"koboSpan\" id = \"kobo.1.2\">Two &amp; three.</span>\
+             </p></div></div></body></html>");
+
+    // Code blocks are left untouched, but not inline code
+    let xhtml = "<body><pre><code>let x = 1. + 2.;\nx</code></pre>\
+                 <p>Use <code>x. y</code>.</p></body>";
+    test_eq(&add_spans(xhtml),
+            "<body><div id = \"book-columns\"><div id = \"book-inner\">\
+             <pre><code>let x = 1. + 2.;\nx</code></pre>\
+             <p><span class = \"koboSpan\" id = \"kobo.1.1\">Use </span>\
+             <code><span class = \"koboSpan\" id = \"kobo.1.2\">x. </span>\
+             <span class = \"koboSpan\" id = \"kobo.1.3\">y</span></code>\
+             <span class = \"koboSpan\" id = \"kobo.1.4\">.</span></p></div></div></body>");
+}
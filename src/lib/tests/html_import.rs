@@ -0,0 +1,99 @@
+use html_import::HtmlImporter;
+use token::Token;
+use book::Book;
+use number::Number;
+
+fn text(s: &str) -> Token {
+    Token::Str(String::from(s))
+}
+
+#[test]
+fn html_import_inline() {
+    let doc = "<!DOCTYPE html>
+<html><head><title>Ignored</title><style>p { color: red; }</style></head>
+<body>
+<h1>Title</h1>
+<p>Some <em>emphasis</em>, <b>strong</b> and
+   <code>code</code>.</p>
+<p>&nbsp;</p>
+<p>A <span style=\"font-weight: bold\">bold</span> &amp; <a href=\"http://example.com\">link</a><br>
+next line</p>
+</body></html>";
+    let res = HtmlImporter::new().parse(doc).unwrap();
+    assert_eq!(res,
+               vec![Token::Header(1, vec![text("Title")]),
+                    Token::Paragraph(vec![text("Some "),
+                                          Token::Emphasis(vec![text("emphasis")]),
+                                          text(", "),
+                                          Token::Strong(vec![text("strong")]),
+                                          text(" and "),
+                                          Token::Code(vec![text("code")]),
+                                          text(".")]),
+                    Token::Paragraph(vec![text("A "),
+                                          Token::Strong(vec![text("bold")]),
+                                          text(" & "),
+                                          Token::Link(String::from("http://example.com"),
+                                                      String::new(),
+                                                      vec![text("link")]),
+                                          Token::HardBreak,
+                                          text("next line")])]);
+}
+
+#[test]
+fn html_import_entities() {
+    let doc = "<p title='a > b'>x &#233;&#x41; &unknown; 1 < 2</p>";
+    let res = HtmlImporter::new().parse(doc).unwrap();
+    assert_eq!(res, vec![Token::Paragraph(vec![text("x éA &unknown; 1 < 2")])]);
+}
+
+#[test]
+fn html_import_blocks() {
+    let doc = "<ul>
+  <li>one
+  <li>two <ul><li>nested</li></ul></li>
+</ul>
+<ol start=\"3\"><li><p>three</p></li></ol>
+<pre><code class=\"language-rust\">
+let x = 1 &lt; 2;
+</code></pre>
+<blockquote><p>Quote</p></blockquote>
+<p><img src=\"image.png\" alt=\"Alt\" width=\"50%\"></p>
+<table>
+  <tr><th>A</th><th>B</th></tr>
+  <tr><td>1</td><td><p>2</p><p>3</p></td>
+</table>";
+    let mut importer = HtmlImporter::new();
+    let res = importer.parse(doc).unwrap();
+    assert_eq!(res,
+               vec![Token::List(vec![Token::Item(vec![text("one")]),
+                                     Token::Item(vec![Token::Paragraph(vec![text("two")]),
+                                                      Token::List(vec![Token::Item(vec![text("nested")])])])]),
+                    Token::OrderedList(3, vec![Token::Item(vec![text("three")])]),
+                    Token::CodeBlock(String::from("rust"), vec![text("let x = 1 < 2;\n")]),
+                    Token::BlockQuote(vec![Token::Paragraph(vec![text("Quote")])]),
+                    Token::StandaloneImage(String::from("image.png"),
+                                           String::new(),
+                                           String::from("width=50%"),
+                                           vec![text("Alt")]),
+                    Token::Table(2,
+                                 vec![Token::TableHead(vec![Token::TableCell(vec![text("A")]),
+                                                            Token::TableCell(vec![text("B")])]),
+                                      Token::TableRow(vec![Token::TableCell(vec![text("1")]),
+                                                           Token::TableCell(vec![text("2"),
+                                                                                 Token::HardBreak,
+                                                                                 text("3")])])])]);
+    let features = importer.features();
+    assert!(features.ordered_list && features.codeblock && features.blockquote &&
+            features.image && features.table);
+}
+
+#[test]
+fn html_import_chapter() {
+    let mut book = Book::new();
+    book.add_chapter_from_named_source(Number::Default,
+                                       "chapter.html",
+                                       "<h1>Title</h1>\n<div><p>Text</p></div>\n".as_bytes())
+        .unwrap();
+    assert_eq!(book.chapters[0].content[1],
+               Token::Paragraph(vec![text("Text")]));
+}
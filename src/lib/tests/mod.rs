@@ -6,5 +6,10 @@ pub fn test_eq(actual: &str, expected: &str) {
 }
 
 mod parser;
+mod rst;
+mod html_import;
 mod html;
 mod book;
+mod render;
+mod publish;
+mod git;
@@ -137,7 +137,7 @@ Test: ![alt text][logo]
 
 [logo]: http://foo.bar/baz.png \"Title\"
 ";
-    let expected = r#"[Paragraph([Str("Test: "), Image("http://foo.bar/baz.png", "Title", [Str("alt text")])])]"#;
+    let expected = r#"[Paragraph([Str("Test: "), Image("http://foo.bar/baz.png", "Title", "", [Str("alt text")])])]"#;
     let result = format!("{:?}", parse_from_str(doc));
     test_eq(&result, expected);
 }
@@ -149,7 +149,7 @@ fn image_reference_standalone() {
 
 [logo]: http://foo.bar/baz.png \"Title\"
 ";
-    let expected = r#"[StandaloneImage("http://foo.bar/baz.png", "Title", [Str("alt text")])]"#;
+    let expected = r#"[StandaloneImage("http://foo.bar/baz.png", "Title", "", [Str("alt text")])]"#;
     let result = format!("{:?}", parse_from_str(doc));
     test_eq(&result, expected);
 }
@@ -158,7 +158,7 @@ fn image_reference_standalone() {
 #[test]
 fn image_standalone() {
     let doc = "![alt text](http://foo.bar/baz.png \"Title\")";
-    let expected = r#"[StandaloneImage("http://foo.bar/baz.png", "Title", [Str("alt text")])]"#;
+    let expected = r#"[StandaloneImage("http://foo.bar/baz.png", "Title", "", [Str("alt text")])]"#;
     let result = format!("{:?}", parse_from_str(doc));
     test_eq(&result, expected);
 }
@@ -166,7 +166,7 @@ fn image_standalone() {
 #[test]
 fn image_link_standalone() {
     let doc = "[![alt text](http://foo.bar/baz.png \"Title\")](http://foo.bar)";
-    let expected = r#"[Link("http://foo.bar", "", [StandaloneImage("http://foo.bar/baz.png", "Title", [Str("alt text")])])]"#;
+    let expected = r#"[Link("http://foo.bar", "", [StandaloneImage("http://foo.bar/baz.png", "Title", "", [Str("alt text")])])]"#;
     let result = format!("{:?}", parse_from_str(doc));
     test_eq(&result, expected);
 }
@@ -271,3 +271,299 @@ fn footnote_incorrect() {
     let result = parser.parse(doc);
     assert!(result.is_err());
 }
+
+#[test]
+fn verse() {
+    let doc = "
+```verse
+Some *verse*
+  indented line
+
+- second stanza
+```
+";
+    let expected = vec![Token::Verse(vec![
+        Token::Paragraph(vec![Token::Str(String::from("Some ")),
+                              Token::Emphasis(vec![Token::Str(String::from("verse"))]),
+                              Token::HardBreak,
+                              Token::Str(String::from("\u{2003}indented line"))]),
+        Token::Paragraph(vec![Token::Str(String::from("- second stanza"))])])];
+    assert_eq!(parse_from_str(doc), expected);
+}
+
+#[test]
+fn margin_note() {
+    let doc = "Text[^>1]\n\n[^>1]: In the margin\n";
+    let mut parser = Parser::new();
+    let res = parser.parse(doc).unwrap();
+    assert_eq!(res,
+               vec![Token::Paragraph(vec![Token::Str(String::from("Text")),
+                                          Token::MarginNote(vec![Token::Paragraph(vec![Token::Str(String::from("In the margin"))])])])]);
+}
+
+#[test]
+fn code_block_options() {
+    use syntax::CodeOptions;
+    assert_eq!(CodeOptions::parse("rust {linenos, hl=3-5, hl=8}"),
+               Some(CodeOptions {
+                   line_numbers: true,
+                   highlighted: vec![(3, 5), (8, 8)],
+                   ..CodeOptions::default()
+               }));
+    let options = CodeOptions::parse(r#"rust {caption="Hello, world", id=hello}"#).unwrap();
+    assert_eq!(options.caption, Some(String::from("Hello, world")));
+    assert_eq!(options.id, Some(String::from("hello")));
+    assert!(options.is_numbered());
+    assert!(CodeOptions::parse("rust,ignore").is_some());
+    assert!(CodeOptions::parse("rust {hl=5-3}").is_none());
+    assert!(CodeOptions::parse("rust {foo}").is_none());
+    assert_eq!(Parser::new().parse("```rust {hl=x}\nfoo\n```\n").unwrap(),
+               vec![Token::CodeBlock(String::from("rust"),
+                                     vec![Token::Str(String::from("foo\n"))])]);
+}
+
+#[test]
+fn code_block_pandoc_attributes() {
+    // Unknown attributes are ignored instead of failing the whole build
+    assert_eq!(Parser::new().parse("```{.python}\nprint(1)\n```\n").unwrap(),
+               vec![Token::CodeBlock(String::new(),
+                                     vec![Token::Str(String::from("print(1)\n"))])]);
+    assert_eq!(Parser::new().parse("```rust {foo}\nfoo\n```\n").unwrap(),
+               vec![Token::CodeBlock(String::from("rust"),
+                                     vec![Token::Str(String::from("foo\n"))])]);
+}
+
+#[test]
+fn code_include_extract() {
+    use syntax::CodeOptions;
+    let code = "fn main() {
+    // ANCHOR: body
+    let x = 42;
+    println!(\"{}\", x);
+    // ANCHOR_END: body
+}
+";
+    let options = CodeOptions::parse("rust {include=main.rs, region=body}").unwrap();
+    assert_eq!(options.extract(code),
+               Some(String::from("    let x = 42;\n    println!(\"{}\", x);\n")));
+    let options = CodeOptions::parse("rust {include=main.rs, lines=1-2}").unwrap();
+    assert_eq!(options.extract(code),
+               Some(String::from("fn main() {\n    let x = 42;\n")));
+    let options = CodeOptions::parse("rust {include=main.rs, region=foo}").unwrap();
+    assert_eq!(options.extract(code), None);
+}
+
+#[test]
+fn table_caption() {
+    let doc = "| a | b |
+|---|---|
+| 1 | 2 |
+
+Table: *Some* numbers
+";
+    let tokens = Parser::new().parse(doc).unwrap();
+    assert_eq!(tokens.len(), 1);
+    match tokens[0] {
+        Token::Table(_, ref content) => {
+            assert_eq!(content[0],
+                       Token::TableCaption(vec![Token::Emphasis(vec![Token::Str(String::from("Some"))]),
+                                                Token::Str(String::from(" numbers"))]));
+        }
+        _ => panic!("expected a table, found {:?}", tokens[0]),
+    }
+}
+
+#[test]
+fn image_attributes() {
+    use images::ImageOptions;
+    let tokens = Parser::new().parse("![Logo](logo.png){width=60% align=center}").unwrap();
+    assert_eq!(tokens,
+               vec![Token::StandaloneImage(String::from("logo.png"),
+                                           String::new(),
+                                           String::from("width=60% align=center"),
+                                           vec![Token::Str(String::from("Logo"))])]);
+    let options = ImageOptions::parse("width=60% align=center").unwrap();
+    assert_eq!(options.tex_width(), Some(String::from("0.6\\linewidth")));
+    assert_eq!(options.tex_align(), "center");
+    assert!(ImageOptions::parse("width=60px").is_none());
+    assert!(Parser::new().parse("Some ![Logo](logo.png){size=big} text").is_err());
+}
+
+#[test]
+fn image_odt_size() {
+    use images::{self, ImageOptions};
+    fn size(attributes: &str, pixels: Option<(u32, u32)>) -> (String, String, Option<String>) {
+        let (width, height, relative) = ImageOptions::parse(attributes).unwrap().odt_size(pixels);
+        (format!("{:.2}", width), format!("{:.2}", height), relative)
+    }
+    assert_eq!(size("width=60%", Some((640, 480))),
+               (String::from("10.20"), String::from("7.65"), Some(String::from("60%"))));
+    assert_eq!(size("width=5cm", Some((200, 100))),
+               (String::from("5.00"), String::from("2.50"), None));
+    assert_eq!(size("", Some((96, 48))),
+               (String::from("2.54"), String::from("1.27"), None));
+    assert_eq!(size("", Some((9600, 100))),
+               (String::from("17.00"), String::from("0.18"), None));
+    assert_eq!(size("", None),
+               (String::from("13.60"), String::from("10.20"), Some(String::from("80%"))));
+
+    let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+    png.extend_from_slice(&[0, 0, 2, 0x80, 0, 0, 1, 0xe0]);
+    assert_eq!(images::size_from_header(&png), Some((640, 480)));
+    assert_eq!(images::size_from_header(b"GIF89a\x10\x00\x20\x00"), Some((16, 32)));
+    let jpeg = [0xff, 0xd8, 0xff, 0xe0, 0, 4, 0, 0, 0xff, 0xc0, 0, 17, 8, 0, 100, 0, 200, 3];
+    assert_eq!(images::size_from_header(&jpeg), Some((200, 100)));
+    assert_eq!(images::size_from_header(b"<svg"), None);
+}
+
+#[test]
+fn image_variants() {
+    use images;
+    assert_eq!(images::parse_widths("1200 480, 800,480"), Some(vec![480, 800, 1200]));
+    assert_eq!(images::parse_widths(""), Some(vec![]));
+    assert_eq!(images::parse_widths("480 0"), None);
+    assert_eq!(images::parse_widths("480px"), None);
+    assert_eq!(images::variant_name("images/image_0.jpg", 480), "images/image_0-480w.jpg");
+    assert_eq!(images::variant_name("images/image_0", 480), "images/image_0-480w");
+    assert_eq!(images::variant_name("images.d/image_0", 800), "images.d/image_0-800w");
+    assert!(images::is_resizable("photo.JPG"));
+    assert!(!images::is_resizable("logo.svg"));
+}
+
+#[test]
+fn page_breaks() {
+    let tokens = Parser::new().parse("The end of a page<!-- page 12 -->and the beginning of another.
+
+<!-- page 13 -->
+
+<!-- a comment -->
+").unwrap();
+    assert_eq!(tokens,
+               vec![Token::Paragraph(vec![Token::Str(String::from("The end of a page")),
+                                          Token::PageBreak(String::from("12")),
+                                          Token::Str(String::from("and the beginning of another."))]),
+                    Token::PageBreak(String::from("13"))]);
+}
+
+#[test]
+fn spoiler() {
+    let doc = "
+```spoiler Show the *answer*
+It is **42**[^1].
+```
+
+[^1]: Obviously.
+";
+    let expected = vec![Token::Spoiler(String::from("Show the *answer*"), vec![
+        Token::Paragraph(vec![Token::Str(String::from("It is ")),
+                              Token::Strong(vec![Token::Str(String::from("42"))]),
+                              Token::Footnote(vec![Token::Paragraph(vec![Token::Str(String::from("Obviously."))])]),
+                              Token::Str(String::from("."))])])];
+    assert_eq!(parse_from_str(doc), expected);
+
+    let tokens = parse_from_str("```spoiler\nSecret.\n```\n");
+    assert_eq!(tokens,
+               vec![Token::Spoiler(String::new(),
+                                   vec![Token::Paragraph(vec![Token::Str(String::from("Secret."))])])]);
+}
+
+#[test]
+fn exercise() {
+    let doc = "
+```exercise {id=ex-sum}
+What is 1 + 1?
+```
+
+```answer
+2.
+```
+";
+    let expected = vec![Token::Exercise(String::from("ex-sum"), vec![
+        Token::Paragraph(vec![Token::Str(String::from("What is 1 + 1?"))]),
+        Token::Answer(vec![Token::Paragraph(vec![Token::Str(String::from("2."))])])])];
+    assert_eq!(parse_from_str(doc), expected);
+
+    assert!(Parser::new().parse("Text\n\n```answer\nNo exercise.\n```\n").is_err());
+}
+
+#[test]
+fn blockquote_attribution() {
+    let doc = "
+> Some quote.
+>
+> -- *Someone*, somewhere
+";
+    let expected = vec![Token::BlockQuote(vec![
+        Token::Paragraph(vec![Token::Str(String::from("Some quote."))]),
+        Token::Attribution(vec![Token::Emphasis(vec![Token::Str(String::from("Someone"))]),
+                                Token::Str(String::from(", somewhere"))])])];
+    assert_eq!(parse_from_str(doc), expected);
+
+    // A quote made of a single paragraph has no attribution
+    assert_eq!(parse_from_str("> — Hello, she said.\n"),
+               vec![Token::BlockQuote(vec![
+                   Token::Paragraph(vec![Token::Str(String::from("— Hello, she said."))])])]);
+}
+
+#[test]
+fn admonition() {
+    let doc = "
+```warning Mind the *gap*
+Careful.
+```
+";
+    assert_eq!(parse_from_str(doc),
+               vec![Token::Admonition(String::from("warning"),
+                                      String::from("Mind the *gap*"),
+                                      vec![Token::Paragraph(vec![Token::Str(String::from("Careful."))])])]);
+
+    assert_eq!(parse_from_str("> [!NOTE]\n> Some *text*.\n"),
+               vec![Token::Admonition(String::from("note"),
+                                      String::new(),
+                                      vec![Token::Paragraph(vec![Token::Str(String::from("Some ")),
+                                                                 Token::Emphasis(vec![Token::Str(String::from("text"))]),
+                                                                 Token::Str(String::from("."))])])]);
+    assert_eq!(parse_from_str("> [!FOO] Bar.\n"),
+               vec![Token::BlockQuote(vec![Token::Paragraph(vec![Token::Str(String::from("[!FOO] Bar."))])])]);
+}
+
+#[test]
+fn columns() {
+    let doc = "
+```columns
+Alice
+
+Bob
+```
+";
+    assert_eq!(parse_from_str(doc),
+               vec![Token::Columns(2, vec![Token::Paragraph(vec![Token::Str(String::from("Alice"))]),
+                                           Token::Paragraph(vec![Token::Str(String::from("Bob"))])])]);
+
+    let doc = "
+```columns 3
+Text.
+```
+";
+    assert_eq!(parse_from_str(doc),
+               vec![Token::Columns(3, vec![Token::Paragraph(vec![Token::Str(String::from("Text."))])])]);
+
+    assert!(Parser::new().parse("```columns many\nText.\n```\n").is_err());
+}
+
+#[test]
+fn new_page_keep_together() {
+    let doc = "
+Before.
+
+<!-- pagebreak -->
+
+```keep
+After.
+```
+";
+    assert_eq!(parse_from_str(doc),
+               vec![Token::Paragraph(vec![Token::Str(String::from("Before."))]),
+                    Token::NewPage,
+                    Token::KeepTogether(vec![Token::Paragraph(vec![Token::Str(String::from("After."))])])]);
+}
@@ -0,0 +1,68 @@
+use book::Book;
+use opds;
+use publish;
+
+use std::path::Path;
+
+#[test]
+fn publish_targets() {
+    let mut book = Book::new();
+    assert!(publish::targets(&book).is_empty());
+    book.set_options(&[("publish.git", "gh-pages"),
+                       ("publish.ftp", "ftp://example.org/books")]);
+    let targets: Vec<_> = publish::targets(&book)
+        .into_iter()
+        .map(|(target, value)| (target.key(), value.to_owned()))
+        .collect();
+    assert_eq!(targets,
+               vec![("ftp", String::from("ftp://example.org/books")),
+                    ("git", String::from("gh-pages"))]);
+}
+
+#[test]
+fn publish_command_args() {
+    assert_eq!(publish::command_args("scp -r {dir}/. {target}",
+                                     &[("{dir}", "/tmp/out"), ("{target}", "me@host:books")]),
+               vec!["scp", "-r", "/tmp/out/.", "me@host:books"]);
+    assert_eq!(publish::command_args("  ", &[]), Vec::<String>::new());
+}
+
+#[test]
+fn publish_ftp_url() {
+    assert_eq!(publish::ftp_url("ftp://example.org/books/", Path::new("html/index.html")),
+               "ftp://example.org/books/html/index.html");
+    assert_eq!(publish::ftp_url("ftp://example.org", Path::new("book.epub")),
+               "ftp://example.org/book.epub");
+}
+
+#[test]
+fn opds_book_id() {
+    let mut book = Book::new();
+    book.set_options(&[("title", "Moby Dick"), ("author", "Herman Melville")]);
+    assert_eq!(opds::book_id(&book), "urn:crowbook:ebf63beb36c9b730");
+    book.set_options(&[("retail.isbn", "978-3-16-148410-0")]);
+    assert_eq!(opds::book_id(&book), "urn:isbn:9783161484100");
+    book.set_options(&[("identifier", "F81D4FAE-7DEC-11D0-A765-00A0C91E6BF6")]);
+    assert_eq!(opds::book_id(&book), "urn:uuid:f81d4fae-7dec-11d0-a765-00a0c91e6bf6");
+    book.set_options(&[("identifier", "moby&dick")]);
+    assert_eq!(opds::book_id(&book), "urn:crowbook:moby&amp;dick");
+}
+
+#[test]
+fn opds_catalog() {
+    let mut book = Book::new();
+    book.set_options(&[("title", "Moby Dick"),
+                       ("author", "Herman Melville"),
+                       ("publish.base_url", "https://example.org/books"),
+                       ("output.epub", "moby.epub"),
+                       ("output.txt", "moby.txt"),
+                       ("output.html.dir", "html"),
+                       ("output.proofread.pdf", "proof.pdf")]);
+    let catalog = book.opds_catalog().unwrap();
+    assert!(catalog.contains("<id>urn:crowbook:ebf63beb36c9b730</id>"));
+    assert!(catalog.contains("<title>Moby Dick</title>"));
+    assert!(catalog.contains("href = \"https://example.org/books/moby.epub\" type = \"application/epub+zip\""));
+    assert!(catalog.contains("href = \"https://example.org/books/moby.txt\" type = \"text/plain\""));
+    assert!(!catalog.contains("proof.pdf"));
+    assert_eq!(catalog.matches("opds-spec.org/acquisition").count(), 2);
+}
@@ -0,0 +1,240 @@
+use book::Book;
+use book_renderer::{BookRenderer, RenderOutput};
+use error::{Error, Result, Source};
+use logger::InfoLevel;
+use misc;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Renders the number of chapters of a book
+struct Count {}
+
+impl BookRenderer for Count {
+    fn render(&self, book: &Book, to: &mut Write) -> Result<()> {
+        write!(to, "{}", book.chapters.len()).unwrap();
+        Ok(())
+    }
+}
+
+/// Always fails to render
+struct Fail {}
+
+impl BookRenderer for Fail {
+    fn render(&self, _: &Book, _: &mut Write) -> Result<()> {
+        Err(Error::render(Source::empty(), "failure"))
+    }
+}
+
+/// Logs a warning and renders nothing
+struct Warn {}
+
+impl BookRenderer for Warn {
+    fn render(&self, book: &Book, _: &mut Write) -> Result<()> {
+        book.logger.warning("something is wrong");
+        Ok(())
+    }
+}
+
+/// Panics while rendering
+struct Panic {}
+
+impl BookRenderer for Panic {
+    fn render(&self, _: &Book, _: &mut Write) -> Result<()> {
+        panic!("renderer bug");
+    }
+}
+
+/// Returns a new temporary directory for a test
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("crowbook-render-{}", name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn path(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+fn read(path: &Path) -> String {
+    let mut content = String::new();
+    File::open(path).unwrap().read_to_string(&mut content).unwrap();
+    content
+}
+
+/// Returns a book with the `count` and `fail` formats
+fn book() -> Book {
+    let mut book = Book::new();
+    book.set_verbosity(InfoLevel::Error)
+        .add_format("count", "Number of chapters", Box::new(Count {}))
+        .add_format("fail", "Failure", Box::new(Fail {}));
+    book
+}
+
+#[test]
+fn render_result() {
+    let mut book = book();
+    book.add_format("warn", "Warning", Box::new(Warn {}));
+
+    let mut content: Vec<u8> = vec![];
+    let result = book.render_format_to("count", &mut content).unwrap();
+    assert_eq!(result.format, "count");
+    assert_eq!(result.output, RenderOutput::Bytes(1));
+    assert!(result.warnings.is_empty());
+    assert_eq!(content, b"0");
+
+    let result = book.render_format_to("warn", &mut io::sink()).unwrap();
+    assert_eq!(result.output, RenderOutput::Bytes(0));
+    assert_eq!(result.warnings, vec![String::from("something is wrong")]);
+
+    // Warnings are only recorded for the format being rendered
+    let result = book.render_format_to("count", &mut io::sink()).unwrap();
+    assert!(result.warnings.is_empty());
+
+    let dir = temp_dir("result");
+    let file = dir.join("book.count");
+    let result = book.render_format_to_file("count", file.clone()).unwrap();
+    assert_eq!(result.output, RenderOutput::File(file.clone()));
+    assert_eq!(read(&file), "0");
+
+    assert!(book.render_format_to("fail", &mut io::sink()).is_err());
+    assert!(book.render_format_to("unknown", &mut io::sink()).is_err());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn render_all_failures() {
+    let dir = temp_dir("failures");
+    let mut book = book();
+    book.add_format("panic", "Panic", Box::new(Panic {}))
+        .set_options(&[("output.count", path(&dir.join("book.count")).as_str()),
+                       ("output.fail", path(&dir.join("book.fail")).as_str()),
+                       ("output.panic", path(&dir.join("book.panic")).as_str())]);
+    let results = book.render_all();
+    let formats: Vec<_> = results.iter().map(|&(ref format, _)| format.as_str()).collect();
+    assert_eq!(formats, vec!["count", "fail", "panic"]);
+    // Neither a failure nor a panic prevents other formats from being rendered
+    assert!(results[0].1.is_ok());
+    assert_eq!(read(&dir.join("book.count")), "0");
+    assert!(results[1].1.is_err());
+    assert!(results[2].1.is_err());
+    // and no partial file is left
+    assert!(!dir.join("book.fail").exists());
+    assert!(!dir.join("book.panic").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn manifest() {
+    let dir = temp_dir("manifest");
+    let count = dir.join("book.count");
+    let manifest = dir.join("manifest.json");
+    let mut book = book();
+    book.set_options(&[("output.count", path(&count).as_str()),
+                       ("output.manifest", path(&manifest).as_str())]);
+    let results = book.render_all();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].1.as_ref().unwrap().output, RenderOutput::File(count.clone()));
+    let content = read(&manifest);
+    assert!(content.contains("\"path\": \"book.count\""));
+    assert!(content.contains("\"format\": \"count\""));
+    assert!(content.contains(&misc::sha256(b"0")));
+
+    // Not written if a format fails
+    fs::remove_file(&manifest).unwrap();
+    book.set_options(&[("output.fail", path(&dir.join("book.fail")).as_str())]);
+    let results = book.render_all();
+    assert!(results.iter().any(|&(ref format, ref result)| format == "fail" && result.is_err()));
+    assert!(!manifest.exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn render_books() {
+    use batch;
+    let dir = temp_dir("books");
+    for name in &["a", "b"] {
+        let file = dir.join(format!("{}.book", name));
+        File::create(&file)
+            .unwrap()
+            .write_all(format!("output.count: {}.count\n", name).as_bytes())
+            .unwrap();
+    }
+    let files = [path(&dir.join("a.book")), path(&dir.join("missing.book")), path(&dir.join("b.book"))];
+    let files: Vec<&str> = files.iter().map(|s| s.as_str()).collect();
+    let failures = batch::render_books(&files,
+                                       |file| {
+                                           let mut book = book();
+                                           book.load_file(file)?;
+                                           Ok(book)
+                                       },
+                                       |book| book.render_all());
+    // A book that can't be loaded doesn't prevent the others from being rendered
+    assert_eq!(failures.len(), 1);
+    assert!(failures[0].starts_with(&format!("* {}: ", files[1])));
+    assert_eq!(read(&dir.join("a.count")), "0");
+    assert_eq!(read(&dir.join("b.count")), "0");
+
+    // Failures of each format are reported
+    let failures = batch::render_books(&files[..1],
+                                       |file| {
+                                           let mut book = book();
+                                           book.load_file(file)?
+                                               .set_options(&[("output.fail", "a.fail")]);
+                                           Ok(book)
+                                       },
+                                       |book| book.render_all());
+    assert_eq!(failures.len(), 1);
+    assert!(failures[0].starts_with(&format!("* {} (fail): ", files[0])));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn diagrams() {
+    use diagrams;
+    use token::Token;
+
+    let dir = temp_dir("diagrams");
+    let cache = dir.join("crowbook-diagrams");
+    let code = || vec![Token::CodeBlock(String::from("dot"),
+                                        vec![Token::Str(String::from("digraph { a -> b }"))])];
+    let mut book = book();
+    book.set_options(&[("crowbook.temp_dir", path(&dir).as_str())]);
+
+    // Nothing is done unless rendering.diagrams is set
+    let mut tokens = code();
+    diagrams::render_diagrams(&book, &mut tokens, &Source::empty()).unwrap();
+    assert_eq!(tokens, code());
+
+    // A missing command is an error, and leaves no file in the cache
+    book.set_options(&[("rendering.diagrams", "true"),
+                       ("rendering.diagrams.dot", "crowbook-no-such-command {input}")]);
+    assert!(diagrams::render_diagrams(&book, &mut code(), &Source::empty()).is_err());
+    assert_eq!(fs::read_dir(&cache).unwrap().count(), 0);
+    book.set_options(&[("rendering.diagrams.dot", " ")]);
+    assert!(diagrams::render_diagrams(&book, &mut code(), &Source::empty()).is_err());
+    assert_eq!(fs::read_dir(&cache).unwrap().count(), 0);
+
+    if cfg!(unix) {
+        book.set_options(&[("rendering.diagrams.dot", "cp {input} {output}"),
+                           ("rendering.diagrams.format", "svg")]);
+        let mut tokens = code();
+        diagrams::render_diagrams(&book, &mut tokens, &Source::empty()).unwrap();
+        let image = match tokens[0] {
+            Token::StandaloneImage(ref url, ..) => PathBuf::from(url),
+            ref token => panic!("expected an image, found {:?}", token),
+        };
+        assert_eq!(image.parent(), Some(cache.as_path()));
+        assert_eq!(read(&image), "digraph { a -> b }");
+        // Only the image is left in the cache, and it is used next time
+        assert_eq!(fs::read_dir(&cache).unwrap().count(), 1);
+        File::create(&image).unwrap().write_all(b"cached").unwrap();
+        let mut tokens = code();
+        diagrams::render_diagrams(&book, &mut tokens, &Source::empty()).unwrap();
+        assert_eq!(read(&image), "cached");
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
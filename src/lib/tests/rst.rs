@@ -0,0 +1,110 @@
+use rst::RstParser;
+use token::Token;
+use book::Book;
+use number::Number;
+
+fn text(s: &str) -> Token {
+    Token::Str(String::from(s))
+}
+
+#[test]
+fn rst_titles_inline() {
+    let doc = "
+=====
+Title
+=====
+
+Section
+-------
+
+Some *emphasis*, **strong** and ``code``.
+";
+    let res = RstParser::new().parse(doc).unwrap();
+    assert_eq!(res,
+               vec![Token::Header(1, vec![text("Title")]),
+                    Token::Header(2, vec![text("Section")]),
+                    Token::Paragraph(vec![text("Some "),
+                                          Token::Emphasis(vec![text("emphasis")]),
+                                          text(", "),
+                                          Token::Strong(vec![text("strong")]),
+                                          text(" and "),
+                                          Token::Code(vec![text("code")]),
+                                          text(".")])]);
+}
+
+#[test]
+fn rst_lists_literal() {
+    let doc = "- one
+- two
+
+#. first
+#. second
+
+Example::
+
+    let x = 1;
+";
+    let res = RstParser::new().parse(doc).unwrap();
+    assert_eq!(res,
+               vec![Token::List(vec![Token::Item(vec![text("one")]),
+                                     Token::Item(vec![text("two")])]),
+                    Token::OrderedList(1,
+                                       vec![Token::Item(vec![text("first")]),
+                                            Token::Item(vec![text("second")])]),
+                    Token::Paragraph(vec![text("Example:")]),
+                    Token::CodeBlock(String::new(), vec![text("let x = 1;\n")])]);
+}
+
+#[test]
+fn rst_links_footnotes_directives() {
+    let doc = "See Python_ and `the docs <https://docs.rs>`_ [#]_.
+
+.. _Python: https://www.python.org
+
+.. [#] A *note*.
+
+.. note:: Be careful.
+";
+    let res = RstParser::new().parse(doc).unwrap();
+    assert_eq!(res,
+               vec![Token::Paragraph(vec![text("See "),
+                                          Token::Link(String::from("https://www.python.org"),
+                                                      String::new(),
+                                                      vec![text("Python")]),
+                                          text(" and "),
+                                          Token::Link(String::from("https://docs.rs"),
+                                                      String::new(),
+                                                      vec![text("the docs")]),
+                                          Token::Footnote(vec![Token::Paragraph(vec![text("A "),
+                                                                                    Token::Emphasis(vec![text("note")]),
+                                                                                    text(".")])]),
+                                          text(".")]),
+                    Token::Admonition(String::from("note"),
+                                      String::new(),
+                                      vec![Token::Paragraph(vec![text("Be careful.")])])]);
+
+    assert!(RstParser::new().parse("A missing note [1]_.").is_err());
+}
+
+#[test]
+fn rst_chapter() {
+    let mut book = Book::new();
+    book.add_chapter_from_named_source(Number::Default,
+                                       "chapter.rst",
+                                       "Title\n=====\n\n.. warning:: Don't.\n".as_bytes())
+        .unwrap();
+    assert_eq!(book.chapters[0].content[1],
+               Token::Admonition(String::from("warning"),
+                                 String::new(),
+                                 vec![Token::Paragraph(vec![text("Don't.")])]));
+
+    // Titles adorned with `---` are not YAML blocks
+    let mut book = Book::new();
+    book.add_chapter_from_named_source(Number::Default,
+                                       "faq.rst",
+                                       "---\nFAQ\n---\n\nText.\n".as_bytes())
+        .unwrap();
+    assert_eq!(book.chapters[0].content,
+               vec![Token::Header(1, vec![text("FAQ")]),
+                    Token::Paragraph(vec![text("Text.")])]);
+}
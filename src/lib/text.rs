@@ -0,0 +1,494 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Rendering of a book to plain text, e.g. to submit a manuscript to
+//! venues that only accept `.txt` files or to compare drafts.
+
+use token::Token;
+use book::Book;
+use number::Number;
+use error::{Error, Result, Source};
+use parser::Parser;
+use renderer::Renderer;
+use book_renderer::BookRenderer;
+use text_view::view_as_text;
+use lang;
+
+use std::io::Write;
+use std::iter;
+
+/// Renderer for plain text
+///
+/// Paragraphs are wrapped at `txt.width` columns, chapter titles are
+/// underlined and notes are displayed at the end of their chapter.
+pub struct TextRenderer<'a> {
+    book: &'a Book,
+    /// Maximal number of characters of a line (0 to disable wrapping)
+    width: usize,
+    current_chapter: i32,
+    current_part: i32,
+    current_numbering: i32,
+    current_hide: bool,
+    /// Whether the chapter being rendered is a part
+    current_is_part: bool,
+    /// Footnotes and margin notes of the current chapter
+    notes: Vec<Vec<Token>>,
+}
+
+impl<'a> TextRenderer<'a> {
+    /// Creates a new TextRenderer
+    pub fn new(book: &'a Book) -> TextRenderer<'a> {
+        let width = book.options.get_i32("txt.width").unwrap();
+        TextRenderer {
+            book: book,
+            width: if width > 0 { width as usize } else { 0 },
+            current_chapter: 1,
+            current_part: 1,
+            current_numbering: book.options.get_i32("rendering.num_depth").unwrap(),
+            current_hide: false,
+            current_is_part: false,
+            notes: vec![],
+        }
+    }
+
+    /// Renders a full book
+    pub fn render_book(&mut self) -> Result<String> {
+        let book = self.book;
+        let mut chapters = vec![self.render_title()?];
+        for chapter in &book.chapters {
+            self.current_hide = chapter.hide_title;
+            self.current_is_part = chapter.number.is_part();
+            match chapter.number {
+                Number::Hidden | Number::Unnumbered | Number::UnnumberedPart => {
+                    self.current_numbering = 0
+                }
+                Number::Default | Number::DefaultPart => {
+                    self.current_numbering = book.options.get_i32("rendering.num_depth").unwrap()
+                }
+                Number::Specified(n) => {
+                    self.current_numbering = book.options.get_i32("rendering.num_depth").unwrap();
+                    self.current_chapter = n;
+                }
+                Number::SpecifiedPart(n) => {
+                    self.current_numbering = book.options.get_i32("rendering.num_depth").unwrap();
+                    self.current_part = n;
+                }
+            }
+            let mut content = self.render_blocks(&chapter.content, "")?;
+            content.push_str(&self.render_notes()?);
+            if !content.trim().is_empty() {
+                chapters.push(content);
+            }
+        }
+        Ok(chapters.join("\n\n"))
+    }
+
+    /// Renders the title and the author of the book
+    fn render_title(&mut self) -> Result<String> {
+        let title = self.render_metadata("title")?;
+        let mut res = self.underline(&title, '=');
+        let author = self.render_metadata("author")?;
+        if !author.is_empty() {
+            res.push('\n');
+            res.push_str(&self.wrap(&author, "", ""));
+        }
+        Ok(res)
+    }
+
+    /// Renders a metadata field, which can contain inline Markdown
+    fn render_metadata(&mut self, key: &str) -> Result<String> {
+        let value = self.book.options.get_str(key).unwrap_or("");
+        let tokens = Parser::new().parse_inline(value)?;
+        self.render_vec(&tokens)
+    }
+
+    /// Renders block content, separating blocks by an empty line
+    ///
+    /// Each line starts with `indent`.
+    fn render_blocks(&mut self, tokens: &[Token], indent: &str) -> Result<String> {
+        let mut blocks = vec![];
+        let mut inline = vec![];
+        for token in tokens {
+            if is_inline(token) {
+                inline.push(token.clone());
+                continue;
+            }
+            if !inline.is_empty() {
+                let text = self.render_vec(&inline)?;
+                blocks.push(self.wrap(&text, indent, indent));
+                inline.clear();
+            }
+            let block = self.render_block(token, indent)?;
+            if !block.is_empty() {
+                blocks.push(block);
+            }
+        }
+        if !inline.is_empty() {
+            let text = self.render_vec(&inline)?;
+            blocks.push(self.wrap(&text, indent, indent));
+        }
+        Ok(blocks.join("\n"))
+    }
+
+    /// Renders a block, with each of its lines ending with a newline
+    fn render_block(&mut self, token: &Token, indent: &str) -> Result<String> {
+        match *token {
+            Token::Paragraph(ref vec) => {
+                let text = self.render_vec(vec)?;
+                Ok(self.wrap(&text, indent, indent))
+            }
+            Token::Header(n, ref vec) => self.render_header(n, vec),
+            Token::CodeBlock(_, ref vec) => {
+                let mut res = String::new();
+                for line in view_as_text(vec).lines() {
+                    res.push_str(format!("{}    {}", indent, line).trim_right());
+                    res.push('\n');
+                }
+                Ok(res)
+            }
+            Token::Verse(ref vec) => {
+                // A poem is either a list of stanzas, or a single one
+                let stanzas: Vec<&[Token]> = if vec.iter().all(|t| !is_inline(t)) {
+                    vec.iter().filter_map(|t| t.inner()).collect()
+                } else {
+                    vec![vec.as_slice()]
+                };
+                let first = format!("{}    ", indent);
+                let next = format!("{}        ", indent);
+                let mut res = vec![];
+                for stanza in stanzas {
+                    let mut lines = String::new();
+                    for line in stanza.split(|t| *t == Token::HardBreak || *t == Token::SoftBreak) {
+                        let text = self.render_vec(line)?;
+                        lines.push_str(&self.wrap(&text, &first, &next));
+                    }
+                    res.push(lines);
+                }
+                Ok(res.join("\n"))
+            }
+            Token::BlockQuote(ref vec) => self.render_blocks(vec, &format!("{}    ", indent)),
+            Token::Attribution(ref vec) => {
+                let text = self.render_vec(vec)?;
+                Ok(self.wrap(&format!("— {}", text), indent, indent))
+            }
+            Token::List(ref vec) => self.render_list(vec, None, indent),
+            Token::OrderedList(start, ref vec) => self.render_list(vec, Some(start), indent),
+            Token::Rule => {
+                let padding = if self.width > 5 { (self.width - 5) / 2 } else { 0 };
+                Ok(format!("{}* * *\n", spaces(padding)))
+            }
+            Token::PageBreak(_) => Ok(String::new()),
+            Token::StandaloneImage(_, _, _, ref alt) => {
+                let alt = self.render_vec(alt)?;
+                if alt.is_empty() {
+                    Ok(String::new())
+                } else {
+                    Ok(self.wrap(&format!("[{}]", alt), indent, indent))
+                }
+            }
+            Token::Epigraph(ref author, ref vec) => {
+                let indent = format!("{}        ", indent);
+                let mut res = self.render_blocks(vec, &indent)?;
+                if !author.is_empty() {
+                    let author = self.render_vec(&Parser::new().parse_inline(author)?)?;
+                    res.push_str(&self.wrap(&format!("— {}", author), &indent, &indent));
+                }
+                Ok(res)
+            }
+            Token::Table(_, ref vec) => {
+                let mut res = String::new();
+                for row in vec {
+                    match *row {
+                        Token::TableHead(ref cells) |
+                        Token::TableRow(ref cells) => {
+                            let mut text = vec![];
+                            for cell in cells {
+                                text.push(match cell.inner() {
+                                    Some(inner) => self.render_vec(inner)?,
+                                    None => String::new(),
+                                });
+                            }
+                            res.push_str(&self.wrap(&text.join(" | "), indent, indent));
+                        }
+                        Token::TableCaption(ref vec) => {
+                            let text = self.render_vec(vec)?;
+                            res.push_str(&self.wrap(&text, indent, indent));
+                        }
+                        _ => (),
+                    }
+                }
+                Ok(res)
+            }
+            Token::Spoiler(ref summary, ref vec) => {
+                let summary = if summary.is_empty() {
+                    lang::get_str(self.book.options.get_str("lang").unwrap(), "solution")
+                } else {
+                    self.render_vec(&Parser::new().parse_inline(summary)?)?
+                };
+                let mut res = self.wrap(&format!("_{}_", summary), indent, indent);
+                res.push('\n');
+                res.push_str(&self.render_blocks(vec, indent)?);
+                Ok(res)
+            }
+            Token::Admonition(ref kind, ref title, ref vec) => {
+                let title = if title.is_empty() {
+                    lang::get_str(self.book.options.get_str("lang").unwrap(), kind)
+                } else {
+                    self.render_vec(&Parser::new().parse_inline(title)?)?
+                };
+                let indent = format!("{}    ", indent);
+                let mut res = self.wrap(&format!("*{}*", title), &indent, &indent);
+                res.push('\n');
+                res.push_str(&self.render_blocks(vec, &indent)?);
+                Ok(res)
+            }
+            ref token => {
+                match token.inner() {
+                    Some(inner) => self.render_blocks(inner, indent),
+                    None => Ok(String::new()),
+                }
+            }
+        }
+    }
+
+    /// Renders a header: chapter (and part) titles are numbered and
+    /// underlined with `=`, level-2 sections with `-`
+    fn render_header(&mut self, n: i32, vec: &[Token]) -> Result<String> {
+        if n == 1 && self.current_hide {
+            // A hidden title still counts for chapter numbering
+            if self.current_numbering >= 1 {
+                if self.current_is_part {
+                    self.current_part += 1;
+                } else {
+                    self.current_chapter += 1;
+                }
+            }
+            return Ok(String::new());
+        }
+        let title = if n == 1 && self.current_numbering >= 1 {
+            let book = self.book;
+            let title = view_as_text(vec);
+            let inline = |s: &str| -> Result<String> {
+                Ok(view_as_text(&Parser::new().parse_inline(s)?))
+            };
+            if self.current_is_part {
+                let part = self.current_part;
+                self.current_part += 1;
+                book.get_part_header(part, title, inline)?.text
+            } else {
+                let chapter = self.current_chapter;
+                self.current_chapter += 1;
+                book.get_chapter_header(chapter, title, inline)?.text
+            }
+        } else {
+            self.render_vec(vec)?
+        };
+        Ok(match n {
+            1 => self.underline(&title, '='),
+            2 => self.underline(&title, '-'),
+            _ => self.wrap(&title, "", ""),
+        })
+    }
+
+    /// Renders a list, with the content of each item indented after its
+    /// bullet
+    fn render_list(&mut self, items: &[Token], start: Option<usize>, indent: &str)
+                   -> Result<String> {
+        let mut res = String::new();
+        for (i, item) in items.iter().enumerate() {
+            let bullet = match start {
+                Some(start) => format!("{}{}. ", indent, start + i),
+                None => format!("{}* ", indent),
+            };
+            let next = format!("{}{}",
+                               indent,
+                               spaces(bullet.chars().count() - indent.chars().count()));
+            let mut first = vec![];
+            let mut blocks = vec![];
+            if let Some(inner) = item.inner() {
+                // Only the text of the first paragraph follows the bullet
+                for token in inner {
+                    match *token {
+                        Token::Paragraph(ref vec) if first.is_empty() && blocks.is_empty() => {
+                            first.extend(vec.iter().cloned())
+                        }
+                        ref token if is_inline(token) && blocks.is_empty() => {
+                            first.push(token.clone())
+                        }
+                        ref token => blocks.push(token.clone()),
+                    }
+                }
+            }
+            let text = self.render_vec(&first)?;
+            res.push_str(&self.wrap(&text, &bullet, &next));
+            if !blocks.is_empty() {
+                res.push_str(&self.render_blocks(&blocks, &next)?);
+            }
+        }
+        Ok(res)
+    }
+
+    /// Renders the notes of the current chapter
+    fn render_notes(&mut self) -> Result<String> {
+        if self.notes.is_empty() {
+            return Ok(String::new());
+        }
+        let mut res = String::from("\n");
+        // Notes can contain notes, which are added at the end of the list
+        let mut i = 0;
+        while i < self.notes.len() {
+            let note = self.notes[i].clone();
+            let mut parts = vec![];
+            for token in &note {
+                let part = match *token {
+                    Token::Paragraph(ref vec) => self.render_vec(vec)?,
+                    ref token => self.render_token(token)?,
+                };
+                parts.push(part);
+            }
+            let bullet = format!("[{}] ", i + 1);
+            let next = spaces(bullet.len());
+            res.push_str(&self.wrap(&parts.join(" "), &bullet, &next));
+            i += 1;
+        }
+        self.notes.clear();
+        Ok(res)
+    }
+
+    /// Returns a title underlined with a character
+    fn underline(&self, title: &str, c: char) -> String {
+        let title = self.wrap(title, "", "");
+        let len = title.lines().map(|l| l.chars().count()).max().unwrap_or(0);
+        let mut res = title;
+        for _ in 0..len {
+            res.push(c);
+        }
+        res.push('\n');
+        res
+    }
+
+    /// Wraps text at `txt.width` columns, starting its first line with
+    /// `first` and the next ones with `next`
+    ///
+    /// Line breaks in the text are kept, and words are only split at
+    /// spaces, so non-breaking spaces are respected.
+    fn wrap(&self, text: &str, first: &str, next: &str) -> String {
+        let mut res = String::new();
+        let mut line = first.to_owned();
+        let mut len = first.chars().count();
+        let mut empty = true;
+        for (i, text_line) in text.split('\n').enumerate() {
+            if i > 0 {
+                res.push_str(line.trim_right());
+                res.push('\n');
+                line = next.to_owned();
+                len = next.chars().count();
+                empty = true;
+            }
+            for word in text_line.split(|c| c == ' ' || c == '\t').filter(|w| !w.is_empty()) {
+                let n = word.chars().count();
+                if !empty && self.width > 0 && len + 1 + n > self.width {
+                    res.push_str(line.trim_right());
+                    res.push('\n');
+                    line = next.to_owned();
+                    len = next.chars().count();
+                    empty = true;
+                }
+                if !empty {
+                    line.push(' ');
+                    len += 1;
+                }
+                line.push_str(word);
+                len += n;
+                empty = false;
+            }
+        }
+        res.push_str(line.trim_right());
+        res.push('\n');
+        res
+    }
+}
+
+impl<'a> Renderer for TextRenderer<'a> {
+    /// Renders inline content (blocks are rendered by `render_block`)
+    fn render_token(&mut self, token: &Token) -> Result<String> {
+        match *token {
+            Token::Str(ref text) => Ok(text.clone()),
+            Token::SoftBreak => Ok(String::from(" ")),
+            Token::HardBreak => Ok(String::from("\n")),
+            Token::Emphasis(ref vec) => Ok(format!("_{}_", self.render_vec(vec)?)),
+            Token::Strong(ref vec) => Ok(format!("*{}*", self.render_vec(vec)?)),
+            Token::Link(ref url, _, ref vec) => {
+                let content = self.render_vec(vec)?;
+                let external = url.starts_with("http://") || url.starts_with("https://") ||
+                               url.starts_with("mailto:");
+                if external && content != *url {
+                    Ok(format!("{} <{}>", content, url))
+                } else {
+                    Ok(content)
+                }
+            }
+            Token::Image(_, _, _, ref alt) => Ok(format!("[{}]", self.render_vec(alt)?)),
+            Token::Footnote(ref vec) |
+            Token::MarginNote(ref vec) => {
+                self.notes.push(vec.clone());
+                Ok(format!("[{}]", self.notes.len()))
+            }
+            ref token => {
+                match token.inner() {
+                    Some(inner) => self.render_vec(inner),
+                    None => Ok(String::new()),
+                }
+            }
+        }
+    }
+}
+
+/// Returns true if a token is displayed inside a line of text
+fn is_inline(token: &Token) -> bool {
+    match *token {
+        Token::Str(_) | Token::Emphasis(_) | Token::Strong(_) | Token::Code(_) |
+        Token::Superscript(_) | Token::Subscript(_) | Token::Abbreviation(..) |
+        Token::Link(..) | Token::Image(..) | Token::Footnote(_) | Token::MarginNote(_) |
+        Token::SoftBreak | Token::HardBreak | Token::Annotation(..) => true,
+        _ => false,
+    }
+}
+
+/// Returns a string of `n` spaces
+fn spaces(n: usize) -> String {
+    iter::repeat(' ').take(n).collect()
+}
+
+/// Plain text
+pub struct Text {}
+
+impl BookRenderer for Text {
+    fn auto_path(&self, book_name: &str) -> Result<String> {
+        Ok(format!("{}.txt", book_name))
+    }
+
+    fn render(&self, book: &Book, to: &mut Write) -> Result<()> {
+        let content = TextRenderer::new(book).render_book()?;
+        to.write_all(content.as_bytes())
+            .map_err(|e| {
+                Error::render(Source::empty(),
+                              lformat!("problem when writing text: {error}", error = e))
+            })?;
+        Ok(())
+    }
+}
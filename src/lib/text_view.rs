@@ -35,9 +35,12 @@ pub fn traverse_token<F1, F2, R>(token: &Token, f: &F1, add: &F2) -> R
         Token::Rule |
         Token::HardBreak => f("\n"),
 
+        Token::PageBreak(..) |
+        Token::NewPage |
         Token::Image(..) |
         Token::StandaloneImage(..) |
         Token::Footnote(..) |
+        Token::MarginNote(..) |
         Token::Table(..) |
         Token::TableHead(..) |
         Token::TableRow(..) |
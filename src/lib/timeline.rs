@@ -0,0 +1,124 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Crowbook is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Timeline of the chapters of a book, according to their point of view
+//! character, in-story date and location.
+
+use book::Book;
+use error::{Error, Result, Source};
+use token::Token;
+use text_view::view_as_text;
+
+use std::cmp;
+use std::fmt;
+
+struct Entry {
+    /// Position of the chapter in the book, starting at 1
+    position: usize,
+    title: String,
+    pov: String,
+    date: String,
+    location: String,
+}
+
+/// Table of the chapters of a book with their point of view character,
+/// in-story date and location (set by the `pov`, `story_date` and `location`
+/// keys of inline YAML blocks)
+pub struct Timeline {
+    entries: Vec<Entry>,
+}
+
+impl Timeline {
+    /// Lists the chapters of the book, in the order they appear
+    ///
+    /// Chapters that weren't loaded from a file and don't set any of these
+    /// keys (e.g. generated lists of figures) are not listed.
+    pub fn new(book: &Book) -> Timeline {
+        let mut entries = vec![];
+        for (i, chapter) in book.chapters.iter().enumerate() {
+            if chapter.filename.is_empty() && chapter.pov.is_none() &&
+               chapter.story_date.is_none() && chapter.location.is_none() {
+                continue;
+            }
+            let title = chapter.content
+                .iter()
+                .filter_map(|token| match *token {
+                    Token::Header(1, ref title) => Some(view_as_text(title)),
+                    _ => None,
+                })
+                .next()
+                .unwrap_or_else(|| chapter.filename.clone());
+            entries.push(Entry {
+                position: i + 1,
+                title: title,
+                pov: chapter.pov.clone().unwrap_or_default(),
+                date: chapter.story_date.clone().unwrap_or_default(),
+                location: chapter.location.clone().unwrap_or_default(),
+            });
+        }
+        Timeline { entries: entries }
+    }
+
+    /// Sorts the chapters by `order` (their order in the book), `date`, `pov`
+    /// or `location`
+    ///
+    /// Chapters with the same value keep their order in the book. Dates are
+    /// compared as strings, so they should be written as e.g. `1943-05-12`.
+    pub fn sort_by(&mut self, key: &str) -> Result<()> {
+        match key {
+            "order" => self.entries.sort_by_key(|e| e.position),
+            "date" => self.entries.sort_by(|a, b| a.date.cmp(&b.date)),
+            "pov" => self.entries.sort_by(|a, b| a.pov.cmp(&b.pov)),
+            "location" => self.entries.sort_by(|a, b| a.location.cmp(&b.location)),
+            _ => {
+                return Err(Error::default(Source::empty(),
+                                          lformat!("can not sort timeline by '{key}', expected \
+                                                    'order', 'date', 'pov' or 'location'",
+                                                   key = key)))
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Timeline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let headers = [lformat!("Chapter"), lformat!("Date"), lformat!("POV"), lformat!("Location")];
+        let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+        for entry in &self.entries {
+            let columns = [&entry.title, &entry.date, &entry.pov, &entry.location];
+            for (width, column) in widths.iter_mut().zip(columns.iter()) {
+                *width = cmp::max(*width, column.chars().count());
+            }
+        }
+
+        let mut line = format!("{:>3}", "#");
+        for (header, width) in headers.iter().zip(&widths) {
+            line.push_str(&format!("  {:<width$}", header, width = *width));
+        }
+        write!(f, "{}\n---------\n", line.trim_right())?;
+        for entry in &self.entries {
+            let mut line = format!("{:>3}", entry.position);
+            let columns = [&entry.title, &entry.date, &entry.pov, &entry.location];
+            for (column, width) in columns.iter().zip(&widths) {
+                line.push_str(&format!("  {:<width$}", column, width = *width));
+            }
+            write!(f, "{}\n", line.trim_right())?;
+        }
+        Ok(())
+    }
+}
@@ -46,15 +46,37 @@ pub enum Token {
     Strong(Vec<Token>),
     /// `Code`, a.k.a. verbatim
     Code(Vec<Token>),
-    /// A quote
+    /// A quote, possibly ending with its `Attribution`
     BlockQuote(Vec<Token>),
+    /// Attribution of a block quote (e.g. its author or source)
+    Attribution(Vec<Token>),
     /// Code block with language and content
     CodeBlock(String, Vec<Token>),
+    /// Verse block, containing stanzas (as `Paragraph`s) whose lines are separated by `HardBreak`s
+    Verse(Vec<Token>),
+    /// Epigraph, with its attribution (as inline Markdown) and the quote
+    Epigraph(String, Vec<Token>),
+    /// Collapsible block (e.g. the solution of an exercise), with its summary (as inline
+    /// Markdown, possibly empty) and its content
+    Spoiler(String, Vec<Token>),
+    /// Callout block, with its kind (`note`, `tip`, `important`, `warning` or `caution`),
+    /// its title (as inline Markdown, possibly empty) and its content
+    Admonition(String, String, Vec<Token>),
+    /// Region of content laid out in several columns (e.g. a glossary), with its number of columns
+    Columns(u32, Vec<Token>),
+    /// Block that must not be split across pages
+    KeepTogether(Vec<Token>),
+    /// Exercise, with its id (possibly empty) and its content, possibly ending with its `Answer`
+    Exercise(String, Vec<Token>),
+    /// Answer of an exercise
+    Answer(Vec<Token>),
 
     /// Superscript, indicated with ^...^
     Superscript(Vec<Token>),
     /// Subscript, indicated with ~...~
     Subscript(Vec<Token>),
+    /// An abbreviation, with its expansion and the abbreviated text
+    Abbreviation(String, Vec<Token>),
 
     /// Unordered list, with a vector of `Item`s
     List(Vec<Token>),
@@ -64,7 +86,10 @@ pub enum Token {
     Item(Vec<Token>),
 
     /// Table with number of rows, and a list of `TableHead` and `TableRows`
+    /// (possibly preceded by a `TableCaption`)
     Table(i32, Vec<Token>),
+    /// Caption of a table
+    TableCaption(Vec<Token>),
     /// Table header, contains `TableCell`s
     TableHead(Vec<Token>),
     /// Row of a table, contains `TableCell`s
@@ -74,6 +99,8 @@ pub enum Token {
 
     /// A footnote, contains the content it is pointing to.
     Footnote(Vec<Token>),
+    /// A margin note, displayed as a footnote when it can't be displayed in the margin
+    MarginNote(Vec<Token>),
 
     /// Horizontal rule
     Rule,
@@ -81,13 +108,17 @@ pub enum Token {
     SoftBreak,
     /// Hardbreak
     HardBreak,
+    /// Page break of the print edition, with the number of the page starting here
+    PageBreak(String),
+    /// Forced page break, e.g. before a full-page illustration
+    NewPage,
 
     /// A link with an url, a title, and the linked text
     Link(String, String, Vec<Token>),
-    /// An image with a source url, a title and an alt tex
-    Image(String, String, Vec<Token>),
+    /// An image with a source url, a title, attributes (e.g. `width=60%`) and an alt tex
+    Image(String, String, String, Vec<Token>),
     /// Similar to previous, but when image is in a standalone paragraph
-    StandaloneImage(String, String, Vec<Token>),
+    StandaloneImage(String, String, String, Vec<Token>),
 
     /// An annotation inserted by crowbook for e.g. grammar checking
     Annotation(Data, Vec<Token>),
@@ -103,7 +134,7 @@ impl Token {
     /// Returns the inner list of tokens contained in this token (if any)
     pub fn inner(&self) -> Option<&[Token]> {
         match *self {
-            Rule | SoftBreak | HardBreak | Str(_) => None,
+            Rule | SoftBreak | HardBreak | PageBreak(_) | NewPage | Str(_) => None,
 
             Paragraph(ref v) |
             Header(_, ref v) |
@@ -111,20 +142,32 @@ impl Token {
             Strong(ref v) |
             Code(ref v) |
             BlockQuote(ref v) |
+            Attribution(ref v) |
             CodeBlock(_, ref v) |
+            Verse(ref v) |
+            Epigraph(_, ref v) |
+            Spoiler(_, ref v) |
+            Admonition(_, _, ref v) |
+            Columns(_, ref v) |
+            KeepTogether(ref v) |
+            Exercise(_, ref v) |
+            Answer(ref v) |
             Subscript(ref v) |
             Superscript(ref v) |
+            Abbreviation(_, ref v) |
             List(ref v) |
             OrderedList(_, ref v) |
             Item(ref v) |
             Table(_, ref v) |
+            TableCaption(ref v) |
             TableHead(ref v) |
             TableRow(ref v) |
             TableCell(ref v) |
             Footnote(ref v) |
+            MarginNote(ref v) |
             Link(_, _, ref v) |
-            Image(_, _, ref v) |
-            StandaloneImage(_, _, ref v) |
+            Image(_, _, _, ref v) |
+            StandaloneImage(_, _, _, ref v) |
             Annotation(_, ref v) => Some(v),
 
             __NonExhaustive => unreachable!(),
@@ -134,7 +177,7 @@ impl Token {
     /// Returns the inner list of tokens contained in this token (if any) (mutable version)
     pub fn inner_mut(&mut self) -> Option<&mut Vec<Token>> {
         match *self {
-            Rule | SoftBreak | HardBreak | Str(_) => None,
+            Rule | SoftBreak | HardBreak | PageBreak(_) | NewPage | Str(_) => None,
 
             Paragraph(ref mut v) |
             Annotation(_, ref mut v) |
@@ -143,20 +186,32 @@ impl Token {
             Strong(ref mut v) |
             Code(ref mut v) |
             BlockQuote(ref mut v) |
+            Attribution(ref mut v) |
             CodeBlock(_, ref mut v) |
+            Verse(ref mut v) |
+            Epigraph(_, ref mut v) |
+            Spoiler(_, ref mut v) |
+            Admonition(_, _, ref mut v) |
+            Columns(_, ref mut v) |
+            KeepTogether(ref mut v) |
+            Exercise(_, ref mut v) |
+            Answer(ref mut v) |
             Subscript(ref mut v) |
             Superscript(ref mut v) |
+            Abbreviation(_, ref mut v) |
             List(ref mut v) |
             OrderedList(_, ref mut v) |
             Item(ref mut v) |
             Table(_, ref mut v) |
+            TableCaption(ref mut v) |
             TableHead(ref mut v) |
             TableRow(ref mut v) |
             TableCell(ref mut v) |
             Footnote(ref mut v) |
+            MarginNote(ref mut v) |
             Link(_, _, ref mut v) |
-            Image(_, _, ref mut v) |
-            StandaloneImage(_, _, ref mut v) => Some(v),
+            Image(_, _, _, ref mut v) |
+            StandaloneImage(_, _, _, ref mut v) => Some(v),
 
             __NonExhaustive => unreachable!(),
         }
@@ -176,7 +231,7 @@ impl Token {
     /// **Returns** `true` if and only if token is Image variant
     /// (StandaloneImage returns *false*, like other variants)
     pub fn is_image(&self) -> bool {
-        if let Token::Image(_, _, _) = *self {
+        if let Token::Image(..) = *self {
             true
         } else {
             false
@@ -208,9 +263,14 @@ impl Token {
             Token::CodeBlock(..) | Token::Code(..) | Token::Paragraph(..)
                 | Token::Header(..) | Token::Emphasis(..) | Token::Strong(..)
                 | Token::List(..) | Token::OrderedList(..) | Token::Table(..)
+                | Token::TableCaption(..)
                 | Token::TableHead(..) | Token::TableRow(..) | Token::Footnote(..)
+                | Token::MarginNote(..)
                 | Token::TableCell(..) | Token::Annotation(..) | Token::Item(..)
-                | Token::BlockQuote(..) => true,
+                | Token::BlockQuote(..) | Token::Attribution(..) | Token::Verse(..)
+                | Token::Epigraph(..) | Token::Spoiler(..) | Token::Admonition(..)
+                | Token::Columns(..) | Token::KeepTogether(..)
+                | Token::Exercise(..) | Token::Answer(..) => true,
             _ => false,
         }
     }
@@ -174,6 +174,21 @@ This is forbidden because we are supposed \
     }
 
 
+    /// convert an ODT file to PDF with LibreOffice, and write it to given file
+    pub fn convert_to_pdf(&mut self,
+                          command_name: &str,
+                          odt_file: &str,
+                          pdf_file: &mut Write)
+                          -> Result<String> {
+        let mut command = Command::new(command_name);
+        command.arg("--headless")
+            .arg("--convert-to")
+            .arg("pdf")
+            .arg(odt_file);
+        let pdf = format!("{}.pdf", odt_file.trim_right_matches(".odt"));
+        self.run_command(command, command_name, &pdf, pdf_file)
+    }
+
     /// generate a pdf file into given file name
     pub fn generate_pdf(&mut self,
                         command_name: &str,
@@ -39,3 +39,55 @@ function toggle() {
         }*/
     }
 }
+
+/* Follows the link of the (displayed) element with this class, if any */
+function followLink(name) {
+    var elements = document.getElementsByClassName(name);
+    for (var i = 0; i < elements.length; i++) {
+        var links = elements[i].getElementsByTagName("a");
+        if (elements[i].offsetParent !== null && links.length > 0) {
+            links[0].click();
+            return true;
+        }
+    }
+    return false;
+}
+
+/* Moves the focus to the content of the page (used by the skip link) */
+function skipToContent() {
+    var page = document.getElementById("page");
+    page.setAttribute("tabindex", "-1");
+    page.focus();
+    return false;
+}
+
+/* Keyboard navigation: left/right arrows for previous/next chapter, t for the table of contents */
+document.addEventListener("keydown", function(event) {
+    if (event.altKey || event.ctrlKey || event.metaKey || event.shiftKey) {
+        return;
+    }
+    var target = event.target;
+    if (target.isContentEditable || target.tagName == "INPUT" ||
+        target.tagName == "SELECT" || target.tagName == "TEXTAREA") {
+        return;
+    }
+    var key = event.key || event.keyCode;
+    var handled = false;
+    if (key == "ArrowLeft" || key == "Left" || key == 37) {
+        handled = followLink("prev_chapter");
+    } else if (key == "ArrowRight" || key == "Right" || key == 39) {
+        handled = followLink("next_chapter");
+    } else if ((key == "t" || key == 84) && document.getElementById("nav")) {
+        toggle();
+        if (display_menu) {
+            var links = document.getElementById("nav").getElementsByTagName("a");
+            if (links.length > 0) {
+                links[0].focus();
+            }
+        }
+        handled = true;
+    }
+    if (handled) {
+        event.preventDefault();
+    }
+});
@@ -19,12 +19,13 @@ function showChapter(chap, noreset){
                 controls[i].style.display = "none";
             }
         }
-	// Hide toc unless we're at first chapter
+	// Hide toc unless we're at first chapter (or last, if it is displayed at the end)
 	var toc = document.getElementById("toc");
-	if (toc && chap == 0) {
+	var tocChap = (toc && toc.className == "toc-end") ? chapters.length - 1 : 0;
+	if (toc && chap == tocChap) {
 	    toc.style.display = "block";
 	}
-	if (toc && chap != 0) {
+	if (toc && chap != tocChap) {
 	    toc.style.display = "none";
 	}
         if (!noreset) {
@@ -100,3 +101,68 @@ window.onload = function(){
 };
 
 {{/one_chapter}}
+
+{{#reader_mode}}
+var readerDefaults = {font: "", size: 100, width: 33, justify: true};
+var readerSettings = loadReaderSettings();
+
+function loadReaderSettings() {
+    var settings = {};
+    for (var key in readerDefaults) {
+        settings[key] = readerDefaults[key];
+    }
+    try {
+        var saved = JSON.parse(localStorage.getItem("crowbook.reader"));
+        for (var key in saved) {
+            if (key in settings) {
+                settings[key] = saved[key];
+            }
+        }
+    } catch (e) {
+        // localStorage is not available (or contains garbage): use default settings
+    }
+    return settings;
+}
+
+function applyReaderSettings() {
+    var page = document.getElementById("page");
+    document.body.className = "reader-mode";
+    page.style.fontFamily = readerSettings.font;
+    page.style.fontSize = readerSettings.size + "%";
+    page.style.maxWidth = readerSettings.width + "em";
+    page.style.textAlign = readerSettings.justify ? "justify" : "left";
+    document.getElementById("reader-font").value = readerSettings.font;
+    document.getElementById("reader-justify").checked = readerSettings.justify;
+    try {
+        localStorage.setItem("crowbook.reader", JSON.stringify(readerSettings));
+    } catch (e) {
+        // Settings won't be remembered, but they still apply to this page
+    }
+}
+
+function setReaderSetting(key, value) {
+    readerSettings[key] = value;
+    applyReaderSettings();
+}
+
+function changeReaderSetting(key, delta) {
+    var limits = {size: [50, 300], width: [15, 90]};
+    var value = readerSettings[key] + delta;
+    value = Math.max(limits[key][0], Math.min(limits[key][1], value));
+    setReaderSetting(key, value);
+}
+
+function resetReaderSettings() {
+    for (var key in readerDefaults) {
+        readerSettings[key] = readerDefaults[key];
+    }
+    applyReaderSettings();
+}
+
+function toggleReaderSettings() {
+    var settings = document.getElementById("reader-settings");
+    settings.className = settings.className ? "" : "shown";
+}
+
+document.addEventListener("DOMContentLoaded", applyReaderSettings);
+{{/reader_mode}}
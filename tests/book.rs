@@ -21,3 +21,55 @@ fn book_example() {
     book.render_format_to("html", &mut io::sink()).unwrap();
     book.render_format_to("tex", &mut io::sink()).unwrap();
 }
+
+#[test]
+fn inherits() {
+    let mut book = Book::new();
+    book.set_verbosity(InfoLevel::Error)
+        .load_file(&format!("{}/{}", env!("CARGO_MANIFEST_DIR"), "tests/inherits.book"))
+        .unwrap();
+    assert_eq!(book.options.get_str("author").unwrap(), "Jane Doe");
+    assert_eq!(book.options.get_str("lang").unwrap(), "en");
+    assert_eq!(book.options.get_bool("rendering.initials").unwrap(), true);
+
+    let mut book = Book::new();
+    assert!(book.set_verbosity(InfoLevel::Error)
+        .load_file(&format!("{}/{}", env!("CARGO_MANIFEST_DIR"), "tests/loop.book"))
+        .is_err());
+}
+
+#[test]
+fn reorder() {
+    use crowbook::Reorder;
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{Read, Write};
+
+    let dir = env::temp_dir().join("crowbook-test-reorder");
+    fs::create_dir_all(&dir).unwrap();
+    let files = [("reorder.book", "title: Reorder\n\n+ 01-intro.md\n@ Part\n+ 02-setup.md\n+ 03-usage.md\n"),
+                 ("01-intro.md", "# Intro #\n\nSee [usage](03-usage.md#run).\n"),
+                 ("02-setup.md", "# Setup #\n"),
+                 ("03-usage.md", "# Usage #\n\nAfter [setup](02-setup.md).\n")];
+    for &(file, content) in &files {
+        File::create(dir.join(file)).unwrap().write_all(content.as_bytes()).unwrap();
+    }
+    let read = |file: &str| {
+        let mut s = String::new();
+        File::open(dir.join(file)).unwrap().read_to_string(&mut s).unwrap();
+        s
+    };
+
+    let mut reorder = Reorder::new(dir.join("reorder.book")).unwrap();
+    assert_eq!(reorder.files(), vec!["01-intro.md", "02-setup.md", "03-usage.md"]);
+    assert!(reorder.parse_order("1,2").is_err());
+    assert!(reorder.parse_order("1,1,2").is_err());
+    let order = reorder.parse_order("1, 03-usage.md, 2").unwrap();
+    let renames = reorder.apply(&order).unwrap();
+    assert_eq!(renames.len(), 2);
+    assert_eq!(read("reorder.book"),
+               "title: Reorder\n\n+ 01-intro.md\n@ Part\n+ 02-usage.md\n+ 03-setup.md\n");
+    assert_eq!(read("01-intro.md"), "# Intro #\n\nSee [usage](02-usage.md#run).\n");
+    assert_eq!(read("02-usage.md"), "# Usage #\n\nAfter [setup](03-setup.md).\n");
+    fs::remove_dir_all(&dir).unwrap();
+}